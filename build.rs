@@ -0,0 +1,19 @@
+use std::path::Path;
+use std::process::Command;
+
+/// Record the git commit of the build, for `fitsrotate_rs env`
+fn main() {
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=FITSROTATE_GIT_HASH={}", hash);
+    // Builds from a crates.io package have no git checkout to watch
+    if Path::new(".git/HEAD").exists() {
+        println!("cargo:rerun-if-changed=.git/HEAD");
+        println!("cargo:rerun-if-changed=.git/refs");
+    }
+}
@@ -0,0 +1,118 @@
+//! Rotate every FITS file in a directory with mode 321
+//!
+//! Each `.fits` file (but not the `.rot.fits` outputs of an earlier run) is
+//! rotated to a `.rot.fits` file next to it. Without an argument, a
+//! directory of synthetic cubes is made in the temporary directory first.
+//!
+//! ```bash
+//! cargo run --example batch_rotate
+//! cargo run --example batch_rotate -- /data/cubes
+//! ```
+
+use fitsio::images::{ImageDescription, ImageType};
+use fitsio::FitsFile;
+use ndarray::ArrayD;
+use std::path::{Path, PathBuf};
+
+/// The input axis of each output axis: reverse the axes
+const MODE: [usize; 3] = [3, 2, 1];
+
+/// Write a cube of the given shape (in array order) filled with a value
+fn write_synthetic_cube(filename: &Path, shape: &[usize], value: f32) -> Result<(), fitsio::errors::Error> {
+    let description = ImageDescription {
+        data_type: ImageType::Float,
+        dimensions: shape,
+    };
+    let mut fits_file = FitsFile::create(filename)
+        .with_custom_primary(&description)
+        .overwrite()
+        .open()?;
+    let hdu = fits_file.primary_hdu()?;
+    hdu.write_image(&mut fits_file, &vec![value; shape.iter().product()])?;
+    Ok(())
+}
+
+/// Rotate the primary image of a FITS file, writing the result to `out_filename`
+fn rotate(filename: &Path, out_filename: &Path, mode: &[usize]) -> Result<Vec<usize>, fitsio::errors::Error> {
+    let mut fits_file = FitsFile::open(filename)?;
+    let hdu = fits_file.primary_hdu()?;
+    let cube: ArrayD<f32> = hdu.read_image(&mut fits_file)?;
+    let naxis = cube.ndim();
+    if mode.len() != naxis {
+        return Err(format!(
+            "{} has {} axes, but the mode has {}",
+            filename.display(),
+            naxis,
+            mode.len()
+        )
+        .as_str()
+        .into());
+    }
+    // FITS axis `n` is array axis `naxis - n`
+    let new_axes: Vec<usize> = (0..naxis)
+        .map(|array_idx| naxis - mode[naxis - array_idx - 1])
+        .collect();
+    let rotated = cube.permuted_axes(new_axes).as_standard_layout().into_owned();
+    let shape = rotated.shape().to_vec();
+
+    let description = ImageDescription {
+        data_type: ImageType::Float,
+        dimensions: &shape,
+    };
+    let mut out_file = FitsFile::create(out_filename)
+        .with_custom_primary(&description)
+        .overwrite()
+        .open()?;
+    let out_hdu = out_file.primary_hdu()?;
+    out_hdu.write_image(&mut out_file, &rotated.into_raw_vec_and_offset().0)?;
+    Ok(shape)
+}
+
+/// The FITS files of a directory that are not outputs of a rotation, in name order
+fn fits_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            let name = path
+                .file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().to_string());
+            name.ends_with(".fits") && !name.ends_with(".rot.fits")
+        })
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = match std::env::args().nth(1) {
+        Some(dir) => PathBuf::from(dir),
+        None => {
+            let dir = std::env::temp_dir().join("fitsrotate_example_batch");
+            std::fs::create_dir_all(&dir)?;
+            for (idx, shape) in [[2, 3, 4], [5, 3, 4], [2, 6, 4]].iter().enumerate() {
+                write_synthetic_cube(&dir.join(format!("cube{}.fits", idx + 1)), shape, idx as f32)?;
+            }
+            dir
+        }
+    };
+
+    let files = fits_files(&dir)?;
+    let mut n_failed = 0;
+    for filename in &files {
+        let out_filename = filename.with_extension("rot.fits");
+        match rotate(filename, &out_filename, &MODE) {
+            Ok(shape) => println!("{} -> {} {:?}", filename.display(), out_filename.display(), shape),
+            Err(e) => {
+                eprintln!("Failed to rotate {}: {}", filename.display(), e);
+                n_failed += 1;
+            }
+        }
+    }
+    println!(
+        "Rotated {} of {} files in {}",
+        files.len() - n_failed,
+        files.len(),
+        dir.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,91 @@
+//! List the HDUs of a FITS file and their shapes
+//!
+//! Without an argument, a synthetic file with a primary image, an image
+//! extension and a binary table is written to the temporary directory and
+//! listed.
+//!
+//! ```bash
+//! cargo run --example list_hdus
+//! cargo run --example list_hdus -- cube.fits
+//! ```
+
+use fitsio::hdu::HduInfo;
+use fitsio::images::{ImageDescription, ImageType};
+use fitsio::tables::{ColumnDataType, ColumnDescription};
+use fitsio::FitsFile;
+use std::path::{Path, PathBuf};
+
+/// Write a file with an image in the primary HDU, an IMAGE extension and a BINTABLE extension
+fn write_synthetic_mef(filename: &Path) -> Result<(), fitsio::errors::Error> {
+    let description = ImageDescription {
+        data_type: ImageType::Float,
+        dimensions: &[2, 3, 4],
+    };
+    let mut fits_file = FitsFile::create(filename)
+        .with_custom_primary(&description)
+        .overwrite()
+        .open()?;
+    let hdu = fits_file.primary_hdu()?;
+    hdu.write_image(&mut fits_file, &[0.0f32; 24])?;
+
+    let weights = ImageDescription {
+        data_type: ImageType::Float,
+        dimensions: &[3, 4],
+    };
+    let hdu = fits_file.create_image("WEIGHTS", &weights)?;
+    hdu.write_image(&mut fits_file, &[1.0f32; 12])?;
+
+    let channel = ColumnDescription::new("CHANNEL")
+        .with_type(ColumnDataType::Int)
+        .create()?;
+    let frequency = ColumnDescription::new("FREQ")
+        .with_type(ColumnDataType::Double)
+        .create()?;
+    let hdu = fits_file.create_table("CHANNELS", &[channel, frequency])?;
+    hdu.write_col(&mut fits_file, "CHANNEL", &[1i32, 2])?;
+    hdu.write_col(&mut fits_file, "FREQ", &[1.4e9f64, 1.5e9])?;
+    Ok(())
+}
+
+/// Print one line per HDU: its index, EXTNAME, and image shape or table size
+fn list_hdus(filename: &Path) -> Result<(), fitsio::errors::Error> {
+    let mut fits_file = FitsFile::open(filename)?;
+    println!("{}", filename.display());
+    for idx in 0..fits_file.num_hdus()? {
+        let hdu = fits_file.hdu(idx)?;
+        let extname: String = hdu
+            .read_key(&mut fits_file, "EXTNAME")
+            .unwrap_or_else(|_| if idx == 0 { "PRIMARY" } else { "" }.to_string());
+        let description = match &hdu.info {
+            // The shape is in array order; FITS lists NAXIS1 (the last entry) first
+            HduInfo::ImageInfo { shape, image_type } if shape.is_empty() => format!("no data ({:?})", image_type),
+            HduInfo::ImageInfo { shape, image_type } => {
+                let naxes: Vec<String> = shape.iter().rev().map(|length| length.to_string()).collect();
+                format!("image {} ({:?})", naxes.join(" x "), image_type)
+            }
+            HduInfo::TableInfo {
+                column_descriptions,
+                num_rows,
+            } => {
+                let names: Vec<&str> = column_descriptions.iter().map(|column| column.name.as_str()).collect();
+                format!("table of {} rows ({})", num_rows, names.join(", "))
+            }
+            HduInfo::AnyInfo => "unknown".to_string(),
+        };
+        println!("  {:>3}  {:<10} {}", idx, extname.trim(), description);
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let filename = match std::env::args().nth(1) {
+        Some(filename) => PathBuf::from(filename),
+        None => {
+            let filename = std::env::temp_dir().join("fitsrotate_example_mef.fits");
+            write_synthetic_mef(&filename)?;
+            filename
+        }
+    };
+    list_hdus(&filename)?;
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//! Rotate a synthetic 3D cube with mode 312
+//!
+//! A small (RA, DEC, FREQ) cube is written to the temporary directory,
+//! read back, rotated so that FREQ becomes the first axis, and written
+//! next to it with its CTYPEs reordered to match. This is what
+//! `fitsrotate_rs cube.fits 312` does, without the rest of the WCS.
+//!
+//! ```bash
+//! cargo run --example rotate_3d
+//! ```
+
+use fitsio::images::{ImageDescription, ImageType};
+use fitsio::FitsFile;
+use ndarray::ArrayD;
+use std::path::Path;
+
+/// The CTYPE of each axis of the synthetic cube, in FITS order
+const CTYPES: [&str; 3] = ["RA---SIN", "DEC--SIN", "FREQ"];
+
+/// Write a cube whose value at each pixel is its flat (0-based) index
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file to create
+/// * `shape` - The length of each axis, in FITS order
+///
+fn write_synthetic_cube(filename: &Path, shape: &[usize]) -> Result<(), fitsio::errors::Error> {
+    // ndarray and fitsio put the slowest axis, the last FITS axis, first
+    let dimensions: Vec<usize> = shape.iter().rev().copied().collect();
+    let description = ImageDescription {
+        data_type: ImageType::Float,
+        dimensions: &dimensions,
+    };
+    let mut fits_file = FitsFile::create(filename)
+        .with_custom_primary(&description)
+        .overwrite()
+        .open()?;
+    let hdu = fits_file.primary_hdu()?;
+    for (idx, ctype) in CTYPES.iter().enumerate() {
+        hdu.write_key(&mut fits_file, &format!("CTYPE{}", idx + 1), *ctype)?;
+    }
+    let data: Vec<f32> = (0..shape.iter().product::<usize>()).map(|idx| idx as f32).collect();
+    hdu.write_image(&mut fits_file, &data)?;
+    Ok(())
+}
+
+/// Rotate the primary image of a FITS file
+///
+/// Output axis `n` is input axis `mode[n - 1]`, both in FITS (1-based,
+/// fastest first) order.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `out_filename` - The rotated FITS file to create
+/// * `mode` - The input axis of each output axis
+///
+fn rotate(filename: &Path, out_filename: &Path, mode: &[usize]) -> Result<(), fitsio::errors::Error> {
+    let mut fits_file = FitsFile::open(filename)?;
+    let hdu = fits_file.primary_hdu()?;
+    let cube: ArrayD<f32> = hdu.read_image(&mut fits_file)?;
+    let naxis = cube.ndim();
+
+    // FITS axis `n` is array axis `naxis - n`
+    let new_axes: Vec<usize> = (0..naxis)
+        .map(|array_idx| naxis - mode[naxis - array_idx - 1])
+        .collect();
+    let rotated = cube.permuted_axes(new_axes).as_standard_layout().into_owned();
+    println!(
+        "Rotated shape {:?} to {:?}",
+        hdu_shape(&mut fits_file)?,
+        rotated.shape()
+    );
+
+    let description = ImageDescription {
+        data_type: ImageType::Float,
+        dimensions: rotated.shape(),
+    };
+    let mut out_file = FitsFile::create(out_filename)
+        .with_custom_primary(&description)
+        .overwrite()
+        .open()?;
+    let out_hdu = out_file.primary_hdu()?;
+    for (out_idx, in_axis) in mode.iter().enumerate() {
+        let ctype: String = hdu.read_key(&mut fits_file, &format!("CTYPE{}", in_axis))?;
+        out_hdu.write_key(&mut out_file, &format!("CTYPE{}", out_idx + 1), ctype)?;
+    }
+    out_hdu.write_image(&mut out_file, &rotated.into_raw_vec_and_offset().0)?;
+    Ok(())
+}
+
+/// The shape of the primary image, in array order
+fn hdu_shape(fits_file: &mut FitsFile) -> Result<Vec<usize>, fitsio::errors::Error> {
+    match fits_file.primary_hdu()?.info {
+        fitsio::hdu::HduInfo::ImageInfo { shape, .. } => Ok(shape),
+        _ => Err("The primary HDU is not an image".into()),
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let dir = std::env::temp_dir();
+    let filename = dir.join("fitsrotate_example_cube.fits");
+    let out_filename = dir.join("fitsrotate_example_cube.rot.fits");
+    write_synthetic_cube(&filename, &[4, 3, 2])?;
+    rotate(&filename, &out_filename, &[3, 1, 2])?;
+
+    let mut out_file = FitsFile::open(&out_filename)?;
+    let hdu = out_file.primary_hdu()?;
+    for axis in 1..4 {
+        let ctype: String = hdu.read_key(&mut out_file, &format!("CTYPE{}", axis))?;
+        println!("CTYPE{} = {}", axis, ctype);
+    }
+    println!("Wrote {}", out_filename.display());
+    Ok(())
+}
@@ -0,0 +1,622 @@
+use crate::error::FitsRotateError;
+use crate::merge::glob_match;
+use clap::ValueEnum;
+use fitsio::errors::Error;
+use fitsio::FitsFile;
+use std::fmt;
+
+/// The physical role of a FITS axis, as derived from its CTYPE
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AxisRole {
+    Ra,
+    Dec,
+    Spectral,
+    Stokes,
+}
+
+/// The conventional (RA, DEC, FREQ, STOKES) ordering of the axis roles
+pub const STANDARD_ORDER: [AxisRole; 4] = [
+    AxisRole::Ra,
+    AxisRole::Dec,
+    AxisRole::Spectral,
+    AxisRole::Stokes,
+];
+
+impl fmt::Display for AxisRole {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            AxisRole::Ra => "RA",
+            AxisRole::Dec => "DEC",
+            AxisRole::Spectral => "FREQ",
+            AxisRole::Stokes => "STOKES",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// The conventions for numbering the axes of a cube
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "UPPER")]
+pub enum AxisOrder {
+    /// FITS order: 1-based, fastest (x) axis first
+    Fits,
+    /// ndarray order: 0-based, fastest axis last
+    Array,
+}
+
+/// Describe the axes of a cube in one of the axis numbering conventions
+///
+/// # Arguments
+///
+/// * `ctypes` - The CTYPEs, in FITS axis order
+/// * `shape` - The axis lengths, in array order
+/// * `order` - The convention to number the axes in
+///
+/// # Returns
+///
+/// * `Vec<String>` - One line per axis, e.g. `NAXIS1=FREQ length=256` or `axis[0]=DEC length=1024`
+///
+pub fn axis_order_lines(ctypes: &[String], shape: &[usize], order: AxisOrder) -> Vec<String> {
+    let naxis = shape.len();
+    let ctype = |fits_idx: usize| ctypes.get(fits_idx - 1).map_or("", |ctype| ctype.as_str());
+    match order {
+        AxisOrder::Fits => (1..naxis + 1)
+            .map(|fits_idx| format!("NAXIS{}={} length={}", fits_idx, ctype(fits_idx), shape[naxis - fits_idx]))
+            .collect(),
+        AxisOrder::Array => (0..naxis)
+            .map(|array_idx| format!("axis[{}]={} length={}", array_idx, ctype(naxis - array_idx), shape[array_idx]))
+            .collect(),
+    }
+}
+
+/// Determine the role of an axis from its CTYPE
+///
+/// Longitude-like axes (RA, GLON, ELON, ...) are treated as RA and
+/// latitude-like axes (DEC, GLAT, ELAT, ...) as DEC. Any of the spectral
+/// types from WCS paper III count as spectral.
+///
+/// # Arguments
+///
+/// * `ctype` - The CTYPE value
+///
+/// # Returns
+///
+/// * `Option<AxisRole>` - The role, if it could be determined
+///
+pub fn axis_role(ctype: &str) -> Option<AxisRole> {
+    let ctype = ctype.trim().to_uppercase();
+    // The coordinate type is the part before any projection code
+    let coord = ctype.split('-').next().unwrap_or("");
+    match coord {
+        "RA" | "GLON" | "ELON" | "SLON" | "HLON" => Some(AxisRole::Ra),
+        "DEC" | "GLAT" | "ELAT" | "SLAT" | "HLAT" => Some(AxisRole::Dec),
+        "FREQ" | "ENER" | "WAVN" | "VRAD" | "WAVE" | "VOPT" | "ZOPT" | "AWAV" | "VELO"
+        | "BETA" | "FELO" | "VELOCITY" => Some(AxisRole::Spectral),
+        "STOKES" => Some(AxisRole::Stokes),
+        _ => None,
+    }
+}
+
+/// Find an axis by name
+///
+/// The name is the start of a CTYPE (e.g. `ra` or `vrad`), or a role
+/// (`freq` for any spectral axis), case-insensitively.
+///
+/// # Arguments
+///
+/// * `name` - The name of the axis
+/// * `ctypes` - The CTYPEs, in FITS axis order
+///
+/// # Returns
+///
+/// * `Option<usize>` - The (0-based) index of the axis in `ctypes`
+///
+pub fn find_axis(name: &str, ctypes: &[String]) -> Option<usize> {
+    let name = name.trim().to_uppercase();
+    let coord = |ctype: &str| ctype.trim().to_uppercase().split('-').next().unwrap_or("").to_string();
+    ctypes.iter().position(|ctype| coord(ctype) == name).or_else(|| {
+        ctypes
+            .iter()
+            .position(|ctype| axis_role(ctype).is_some_and(|role| role.to_string() == name))
+    })
+}
+
+/// Read the CTYPE of each axis of the primary HDU
+///
+/// Missing CTYPE cards are returned as empty strings.
+///
+/// # Arguments
+///
+/// * `fits_file` - The FITS file
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, Error>` - The CTYPEs, in FITS axis order
+///
+pub fn read_ctypes(fits_file: &mut FitsFile) -> Result<Vec<String>, Error> {
+    let hdu = fits_file.primary_hdu()?;
+    let naxis: usize = hdu.read_key::<i64>(fits_file, "NAXIS")? as usize;
+    let mut ctypes: Vec<String> = Vec::with_capacity(naxis);
+    for fits_idx in 1..naxis + 1 {
+        let ctype: String = hdu
+            .read_key(fits_file, &format!("CTYPE{}", fits_idx))
+            .unwrap_or_default();
+        // Trailing spaces pad string values and are not significant
+        ctypes.push(ctype.trim().to_string());
+    }
+    Ok(ctypes)
+}
+
+/// Suggest the mode that puts the axes in (RA, DEC, FREQ, STOKES) order
+///
+/// The mode follows Miriad's `reorder` convention: the n-th digit is the
+/// input axis that becomes output axis n. Axes whose role cannot be
+/// determined keep their relative order after the recognised axes.
+///
+/// # Arguments
+///
+/// * `ctypes` - The CTYPEs, in FITS axis order
+///
+/// # Returns
+///
+/// * `(Vec<usize>, Vec<String>)` - The mode and a description of each step
+///
+pub fn suggest_mode(ctypes: &[String]) -> (Vec<usize>, Vec<String>) {
+    let mut reasons: Vec<String> = Vec::new();
+    let roles: Vec<Option<AxisRole>> = ctypes.iter().map(|c| axis_role(c)).collect();
+    for (idx, (ctype, role)) in ctypes.iter().zip(&roles).enumerate() {
+        match role {
+            Some(role) => reasons.push(format!("CTYPE{}={} is {}", idx + 1, ctype, role)),
+            None => reasons.push(format!("CTYPE{}={} has no standard role", idx + 1, ctype)),
+        }
+    }
+
+    let mut mode: Vec<usize> = Vec::with_capacity(ctypes.len());
+    for target in STANDARD_ORDER {
+        mode.extend(
+            roles
+                .iter()
+                .enumerate()
+                .filter(|(_, role)| **role == Some(target))
+                .map(|(idx, _)| idx + 1),
+        );
+    }
+    mode.extend(
+        roles
+            .iter()
+            .enumerate()
+            .filter(|(_, role)| role.is_none())
+            .map(|(idx, _)| idx + 1),
+    );
+
+    for (out_idx, in_axis) in mode.iter().enumerate() {
+        if *in_axis != out_idx + 1 {
+            reasons.push(format!("move axis {} to axis {}", in_axis, out_idx + 1));
+        }
+    }
+    if mode.iter().enumerate().all(|(idx, axis)| *axis == idx + 1) {
+        reasons.push("axes are already in standard order".to_string());
+    }
+    (mode, reasons)
+}
+
+/// Check if two CTYPEs describe the same axis: the same role, or the same CTYPE if neither has a role
+fn same_axis(a: &str, b: &str) -> bool {
+    match (axis_role(a), axis_role(b)) {
+        (Some(role_a), Some(role_b)) => role_a == role_b,
+        (None, None) => a.trim().eq_ignore_ascii_case(b.trim()),
+        _ => false,
+    }
+}
+
+/// Work out the mode that puts the axes of a file in the order of a reference
+///
+/// Each reference axis is matched to an input axis with the same role (see
+/// `axis_role`), or with the same CTYPE if the role cannot be determined.
+///
+/// # Arguments
+///
+/// * `ctypes` - The CTYPEs of the file to rotate, in FITS axis order
+/// * `reference` - The CTYPEs of the reference, in FITS axis order
+///
+/// # Returns
+///
+/// * `Result<Vec<usize>, FitsRotateError>` - The mode
+///
+pub fn mode_from_reference(ctypes: &[String], reference: &[String]) -> Result<Vec<usize>, FitsRotateError> {
+    if ctypes.len() != reference.len() {
+        return Err(FitsRotateError::Message(format!(
+            "The file has {} axes ({}), but the reference has {} ({})",
+            ctypes.len(),
+            ctypes.join(", "),
+            reference.len(),
+            reference.join(", ")
+        )));
+    }
+    let mut mode: Vec<usize> = Vec::with_capacity(reference.len());
+    for (ref_idx, ref_ctype) in reference.iter().enumerate() {
+        let matches: Vec<usize> = ctypes
+            .iter()
+            .enumerate()
+            .filter(|(_, ctype)| same_axis(ctype, ref_ctype))
+            .map(|(idx, _)| idx + 1)
+            .collect();
+        match matches.as_slice() {
+            [axis] => mode.push(*axis),
+            [] => {
+                return Err(FitsRotateError::UnknownAxis {
+                    name: ref_ctype.clone(),
+                    ctypes: ctypes.to_vec(),
+                })
+            }
+            _ => {
+                return Err(FitsRotateError::Message(format!(
+                    "Axis {} of the reference ({}) matches more than one axis of the file ({})",
+                    ref_idx + 1,
+                    ref_ctype,
+                    ctypes.join(", ")
+                )))
+            }
+        }
+    }
+    Ok(mode)
+}
+
+/// The Levenshtein edit distance between two strings
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..b_chars.len() + 1).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b_chars.len() + 1];
+        for (j, cb) in b_chars.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+    previous[b_chars.len()]
+}
+
+/// Find the candidate closest to a name by (case-insensitive) edit distance
+///
+/// # Arguments
+///
+/// * `name` - The name to match
+/// * `candidates` - The possible matches (e.g. the CTYPEs of a file)
+///
+/// # Returns
+///
+/// * `Option<&str>` - The closest candidate, if any is reasonably close
+///
+pub fn closest_match<'a>(name: &str, candidates: &'a [String]) -> Option<&'a str> {
+    let name = name.trim().to_uppercase();
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(&name, &candidate.trim().to_uppercase()), candidate))
+        .filter(|(distance, candidate)| *distance <= candidate.trim().len().max(name.len()) / 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+/// Work out the mode that puts the axes of a file in a target order
+///
+/// Each target axis (a role such as `RA` or `FREQ`, or a CTYPE) is matched
+/// to an input axis as in `mode_from_reference`. The target can name fewer
+/// axes than the file has, in which case the other axes keep their
+/// relative order after the targeted ones.
+///
+/// # Arguments
+///
+/// * `ctypes` - The CTYPEs of the file to rotate, in FITS axis order
+/// * `target` - The target order, e.g. `["RA", "DEC", "FREQ", "STOKES"]`
+///
+/// # Returns
+///
+/// * `Result<Vec<usize>, FitsRotateError>` - The mode
+///
+pub fn mode_from_target(ctypes: &[String], target: &[String]) -> Result<Vec<usize>, FitsRotateError> {
+    if target.len() > ctypes.len() {
+        return Err(FitsRotateError::Message(format!(
+            "The target order ({}) has more axes than the file ({})",
+            target.join(", "),
+            ctypes.join(", ")
+        )));
+    }
+    let mut mode: Vec<usize> = Vec::with_capacity(ctypes.len());
+    for name in target {
+        let matches: Vec<usize> = ctypes
+            .iter()
+            .enumerate()
+            .filter(|(idx, ctype)| same_axis(ctype, name) && !mode.contains(&(idx + 1)))
+            .map(|(idx, _)| idx + 1)
+            .collect();
+        match matches.first() {
+            Some(axis) => mode.push(*axis),
+            None => {
+                return Err(FitsRotateError::UnknownAxis {
+                    name: name.clone(),
+                    ctypes: ctypes.to_vec(),
+                })
+            }
+        }
+    }
+    mode.extend((1..ctypes.len() + 1).filter(|axis| !mode.contains(axis)).collect::<Vec<usize>>());
+    Ok(mode)
+}
+
+/// A CTYPE that an axis must have, from `--require-ctype`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CtypeRequirement {
+    /// The (FITS, 1-based) axis
+    pub axis: usize,
+    /// The CTYPE, which may contain `*` and `?` wildcards
+    pub pattern: String,
+}
+
+/// Parse a `--require-ctype` requirement, e.g. `1=RA---TAN` or `3=FREQ*`
+///
+/// # Arguments
+///
+/// * `requirement` - The requirement, as `AXIS=CTYPE`
+///
+/// # Returns
+///
+/// * `Result<CtypeRequirement, String>` - The requirement
+///
+pub fn parse_ctype_requirement(requirement: &str) -> Result<CtypeRequirement, String> {
+    let (axis, pattern) = requirement
+        .split_once('=')
+        .ok_or_else(|| format!("{} is not of the form AXIS=CTYPE (e.g. 1=RA---TAN)", requirement))?;
+    let axis: usize = match axis.trim().parse() {
+        Ok(axis) if axis >= 1 => axis,
+        _ => return Err(format!("{} is not a (1-based) axis number", axis.trim())),
+    };
+    Ok(CtypeRequirement {
+        axis,
+        pattern: pattern.trim().to_string(),
+    })
+}
+
+/// Check that the CTYPEs of a file meet every `--require-ctype` requirement
+///
+/// Matching is case-insensitive, and `*` and `?` in a requirement match any
+/// run of characters and any single character.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file, for the error message
+/// * `ctypes` - The CTYPEs of the file, in FITS axis order
+/// * `requirements` - The requirements
+///
+pub fn check_ctype_requirements(
+    filename: &str,
+    ctypes: &[String],
+    requirements: &[CtypeRequirement],
+) -> Result<(), FitsRotateError> {
+    for requirement in requirements {
+        let ctype = match ctypes.get(requirement.axis - 1) {
+            Some(ctype) => ctype.trim(),
+            None => {
+                return Err(FitsRotateError::Message(format!(
+                    "--require-ctype {}={}: {} has no axis {} (it has {} axes)",
+                    requirement.axis,
+                    requirement.pattern,
+                    filename,
+                    requirement.axis,
+                    ctypes.len()
+                )))
+            }
+        };
+        let pattern: Vec<char> = requirement.pattern.to_uppercase().chars().collect();
+        let name: Vec<char> = ctype.to_uppercase().chars().collect();
+        if !glob_match(&pattern, &name) {
+            return Err(FitsRotateError::Message(format!(
+                "--require-ctype {}={}: CTYPE{} of {} is {}",
+                requirement.axis,
+                requirement.pattern,
+                requirement.axis,
+                filename,
+                if ctype.is_empty() { "missing" } else { ctype }
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Parse an entry of `--expect-shape`, an axis length or `*`
+///
+/// # Arguments
+///
+/// * `length` - The entry
+///
+/// # Returns
+///
+/// * `Result<Option<usize>, String>` - The length, or `None` for any length
+///
+pub fn parse_expected_length(length: &str) -> Result<Option<usize>, String> {
+    match length.trim() {
+        "*" => Ok(None),
+        length => match length.parse() {
+            Ok(length) if length >= 1 => Ok(Some(length)),
+            _ => Err(format!("{} is not an axis length or *", length)),
+        },
+    }
+}
+
+/// Check the axes of a file against `--expect-order`
+///
+/// Each entry names the axis at that position, in FITS axis order, as
+/// [`find_axis`] does (`ra`, `freq`, ...). An entry with `*` or `?` is
+/// instead matched against the whole CTYPE, so `*` matches any axis.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file, for the error message
+/// * `ctypes` - The CTYPEs of the file, in FITS axis order
+/// * `expected` - The expected axes
+///
+pub fn check_expected_order(filename: &str, ctypes: &[String], expected: &[String]) -> Result<(), FitsRotateError> {
+    let matches = |name: &str, ctype: &String| {
+        if name.contains(['*', '?']) {
+            let pattern: Vec<char> = name.trim().to_uppercase().chars().collect();
+            let ctype: Vec<char> = ctype.trim().to_uppercase().chars().collect();
+            glob_match(&pattern, &ctype)
+        } else {
+            find_axis(name, std::slice::from_ref(ctype)).is_some()
+        }
+    };
+    if ctypes.len() != expected.len() || !expected.iter().zip(ctypes).all(|(name, ctype)| matches(name, ctype)) {
+        return Err(FitsRotateError::Expectation {
+            filename: filename.to_string(),
+            option: "--expect-order".to_string(),
+            expected: expected.iter().map(|name| name.trim().to_string()).collect(),
+            found: ctypes.iter().map(|ctype| ctype.trim().to_string()).collect(),
+        });
+    }
+    Ok(())
+}
+
+/// Check the axis lengths of a file against `--expect-shape`
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file, for the error message
+/// * `naxes` - The length of each axis, in FITS axis order
+/// * `expected` - The expected lengths, `None` for any length
+///
+pub fn check_expected_shape(filename: &str, naxes: &[usize], expected: &[Option<usize>]) -> Result<(), FitsRotateError> {
+    if naxes.len() != expected.len()
+        || !expected
+            .iter()
+            .zip(naxes)
+            .all(|(length, naxis)| length.is_none() || *length == Some(*naxis))
+    {
+        return Err(FitsRotateError::Expectation {
+            filename: filename.to_string(),
+            option: "--expect-shape".to_string(),
+            expected: expected
+                .iter()
+                .map(|length| length.map_or("*".to_string(), |length| length.to_string()))
+                .collect(),
+            found: naxes.iter().map(|naxis| naxis.to_string()).collect(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::EXIT_EXPECTATION;
+
+    fn strings(ctypes: &[&str]) -> Vec<String> {
+        ctypes.iter().map(|ctype| ctype.to_string()).collect()
+    }
+
+    #[test]
+    fn suggest_mode_swaps_stokes_and_freq() {
+        let (mode, reasons) = suggest_mode(&strings(&["RA---SIN", "DEC--SIN", "STOKES", "FREQ"]));
+        assert_eq!(mode, [1, 2, 4, 3]);
+        assert!(
+            reasons.contains(&"CTYPE3=STOKES is STOKES".to_string()),
+            "{:?}",
+            reasons
+        );
+        assert!(reasons.contains(&"move axis 4 to axis 3".to_string()), "{:?}", reasons);
+        assert!(reasons.contains(&"move axis 3 to axis 4".to_string()), "{:?}", reasons);
+    }
+
+    #[test]
+    fn suggest_mode_keeps_unknown_axes_last() {
+        let (mode, reasons) = suggest_mode(&strings(&["FREQ", "TIME", "RA---TAN", "DEC--TAN"]));
+        assert_eq!(mode, [3, 4, 1, 2]);
+        assert!(
+            reasons.contains(&"CTYPE2=TIME has no standard role".to_string()),
+            "{:?}",
+            reasons
+        );
+
+        let (mode, reasons) = suggest_mode(&strings(&["RA---TAN", "DEC--TAN", "FREQ"]));
+        assert_eq!(mode, [1, 2, 3]);
+        assert_eq!(
+            reasons.last().map(String::as_str),
+            Some("axes are already in standard order")
+        );
+    }
+
+    #[test]
+    fn mode_from_reference_matches_roles() {
+        let ctypes = strings(&["FREQ", "RA---SIN", "DEC--SIN", "STOKES"]);
+        let reference = strings(&["RA---TAN", "DEC--TAN", "STOKES", "VRAD"]);
+        assert_eq!(mode_from_reference(&ctypes, &reference).unwrap(), [2, 3, 4, 1]);
+        // Axes without a role match by CTYPE
+        let ctypes = strings(&["TIME", "RA---SIN", "DEC--SIN"]);
+        let reference = strings(&["RA---SIN", "DEC--SIN", "time"]);
+        assert_eq!(mode_from_reference(&ctypes, &reference).unwrap(), [2, 3, 1]);
+    }
+
+    #[test]
+    fn mode_from_reference_needs_a_one_to_one_match() {
+        let ctypes = strings(&["FREQ", "RA---SIN", "DEC--SIN"]);
+        let unmatched = mode_from_reference(&ctypes, &strings(&["RA---SIN", "DEC--SIN", "STOKES"]));
+        assert!(matches!(unmatched, Err(FitsRotateError::UnknownAxis { name, .. }) if name == "STOKES"));
+        let ambiguous = mode_from_reference(&strings(&["FREQ", "VRAD", "RA---SIN"]), &ctypes);
+        assert!(ambiguous
+            .unwrap_err()
+            .to_string()
+            .contains("matches more than one axis"));
+        assert!(mode_from_reference(&ctypes, &strings(&["RA---SIN", "DEC--SIN"])).is_err());
+    }
+
+    #[test]
+    fn expected_orders_match_by_name_role_or_wildcard() {
+        let ctypes = strings(&["FREQ", "STOKES", "RA---SIN", "DEC--SIN"]);
+        for expected in [
+            &["FREQ", "STOKES", "RA", "DEC"][..],
+            &["freq", "stokes", "ra", "dec"],
+            &["*", "STOKES", "RA---*", "*"],
+            &["FR??", "*", "*", "DEC--SI?"],
+        ] {
+            assert!(
+                check_expected_order("cube.fits", &ctypes, &strings(expected)).is_ok(),
+                "{:?}",
+                expected
+            );
+        }
+        for expected in [
+            &["STOKES", "FREQ", "RA", "DEC"][..],
+            &["FREQ", "STOKES", "RA"],
+            &["FREQ", "STOKES", "RA", "DEC", "*"],
+            &["FREQ", "STOKES", "*-TAN", "*"],
+        ] {
+            match check_expected_order("cube.fits", &ctypes, &strings(expected)) {
+                Err(FitsRotateError::Expectation { option, found, .. }) => {
+                    assert_eq!(option, "--expect-order");
+                    assert_eq!(found, ctypes);
+                }
+                other => panic!("{:?}: {:?}", expected, other),
+            }
+        }
+    }
+
+    #[test]
+    fn expected_shapes_match_lengths_and_wildcards() {
+        assert_eq!(parse_expected_length(" * "), Ok(None));
+        assert_eq!(parse_expected_length("4"), Ok(Some(4)));
+        for length in ["0", "-1", "4.0", "", "**"] {
+            assert!(parse_expected_length(length).is_err(), "{:?}", length);
+        }
+
+        let naxes = [10, 4, 256, 256];
+        assert!(check_expected_shape("cube.fits", &naxes, &[None, Some(4), None, None]).is_ok());
+        assert!(check_expected_shape("cube.fits", &naxes, &[Some(10), Some(4), Some(256), Some(256)]).is_ok());
+        assert!(check_expected_shape("cube.fits", &naxes, &[None; 4]).is_ok());
+        assert!(check_expected_shape("cube.fits", &naxes, &[None; 3]).is_err());
+        let error = check_expected_shape("cube.fits", &naxes, &[None, Some(1), None, None]).unwrap_err();
+        assert_eq!(error.exit_code(), EXIT_EXPECTATION);
+        assert_eq!(
+            error.to_string(),
+            "cube.fits does not meet --expect-shape\n  expected: *,1,*,*\n  found:    10,4,256,256"
+        );
+    }
+}
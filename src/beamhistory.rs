@@ -0,0 +1,121 @@
+//! Per-channel restoring beams recorded in AIPS HISTORY cards
+//!
+//! Older cubes without a BEAMS table record the beam of each channel as a
+//! HISTORY card written by an AIPS task, with the channel it applies to:
+//!
+//! ```text
+//! HISTORY AIPS   CLEAN BMAJ=  1.3889E-03 BMIN=  1.1111E-03 BPA=  12.50 CHAN=    3
+//! ```
+//!
+//! The channel may be given as `CHAN=`, `CHANNEL=` or `PLANE=`. Reversing
+//! or sorting the planes of the spectral axis renumbers the channels, so
+//! these cards have to be renumbered with it.
+
+use crate::header::{FitsHeader, HeaderCard, CARD_LENGTH};
+
+/// Fields giving the channel of an AIPS beam record
+const CHANNEL_FIELDS: [&str; 3] = ["CHAN=", "CHANNEL=", "PLANE="];
+
+/// Find the channel of an AIPS beam record
+///
+/// # Arguments
+///
+/// * `card` - The card
+///
+/// # Returns
+///
+/// * `Option<(usize, usize, usize)>` - The channel and the start and end of its value (including the spaces after the `=`) in the card image, or `None` if the card is not an AIPS beam record
+///
+fn beam_channel(card: &HeaderCard) -> Option<(usize, usize, usize)> {
+    if card.keyword() != "HISTORY" || card.record.len() <= 8 {
+        return None;
+    }
+    let text = card.record[8..].to_uppercase();
+    if !text.trim_start().starts_with("AIPS") || !text.contains("BMAJ=") {
+        return None;
+    }
+    CHANNEL_FIELDS.iter().find_map(|field| {
+        let idx = text
+            .match_indices(field)
+            .map(|(idx, _)| idx)
+            .find(|idx| *idx == 0 || text[..*idx].ends_with(' '))?;
+        let start = idx + field.len();
+        let rest = &text[start..];
+        let n_spaces = rest.len() - rest.trim_start().len();
+        let n_digits = rest[n_spaces..].chars().take_while(|c| c.is_ascii_digit()).count();
+        let channel = rest[n_spaces..n_spaces + n_digits].parse().ok()?;
+        Some((channel, 8 + start, 8 + start + n_spaces + n_digits))
+    })
+}
+
+/// The new channel of each channel after the planes of the spectral axis are sorted and then reversed
+///
+/// # Arguments
+///
+/// * `n_channels` - The length of the spectral axis
+/// * `sorted` - The (0-based) input plane of each sorted plane, if the planes are sorted
+/// * `reversed` - Whether the axis is reversed, after any sort
+///
+/// # Returns
+///
+/// * `Option<Vec<usize>>` - The new (1-based) channel of each channel, or `None` if no channel moves
+///
+pub fn channel_order(n_channels: usize, sorted: Option<&[usize]>, reversed: bool) -> Option<Vec<usize>> {
+    let mut channels: Vec<usize> = (1..n_channels + 1).collect();
+    if let Some(sorted) = sorted.filter(|sorted| sorted.len() == n_channels) {
+        for (new_plane, plane) in sorted.iter().enumerate() {
+            channels[*plane] = new_plane + 1;
+        }
+    }
+    if reversed {
+        for channel in channels.iter_mut() {
+            *channel = n_channels + 1 - *channel;
+        }
+    }
+    Some(channels).filter(|channels| channels.iter().enumerate().any(|(idx, channel)| *channel != idx + 1))
+}
+
+/// Renumber the AIPS beam records of a header after the planes of the spectral axis are reordered
+///
+/// Channel `c` becomes channel `channels[c - 1]`, keeping the width of the
+/// value so that the columns of the block still line up. The records are
+/// then put back in order of channel, in the places the block took up, and
+/// all other HISTORY cards are left alone.
+///
+/// # Arguments
+///
+/// * `header` - The header
+/// * `channels` - The new (1-based) channel of each channel, as from `channel_order`
+///
+/// # Returns
+///
+/// * `usize` - The number of records renumbered
+///
+pub fn renumber_beam_channels(header: &mut FitsHeader, channels: &[usize]) -> usize {
+    let mut places: Vec<usize> = Vec::new();
+    let mut records: Vec<(usize, HeaderCard)> = Vec::new();
+    for (idx, card) in header.cards.iter().enumerate() {
+        let (channel, start, end) = match beam_channel(card) {
+            Some((channel, start, end)) if channel >= 1 && channel <= channels.len() => (channel, start, end),
+            _ => continue,
+        };
+        let new_channel = channels[channel - 1];
+        let width = end - start;
+        let value = format!("{:>width$}", new_channel, width = width);
+        let value = if value.len() > width {
+            format!(" {}", new_channel)
+        } else {
+            value
+        };
+        let mut record = format!("{}{}{}", &card.record[..start], value, &card.record[end..]);
+        record = format!("{:<width$}", record.trim_end(), width = CARD_LENGTH);
+        record.truncate(CARD_LENGTH);
+        places.push(idx);
+        records.push((new_channel, HeaderCard::new(&record)));
+    }
+    records.sort_by_key(|(channel, _)| *channel);
+    for (idx, (_, card)) in places.iter().zip(&records) {
+        header.cards[*idx] = card.clone();
+    }
+    records.len()
+}
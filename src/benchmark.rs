@@ -0,0 +1,154 @@
+use crate::memory::format_bytes;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The phases of a rotation that are timed separately
+pub const PHASES: [&str; 3] = ["read", "rotate", "write"];
+
+/// Elapsed times of each phase over the runs of a benchmark
+#[derive(Clone, Debug, Default)]
+pub struct PhaseTimings {
+    /// The times of each run, one list per phase in `PHASES`
+    times: [Vec<Duration>; 3],
+    /// The most bytes allocated at once in each phase over all runs, with `--mem-profile`
+    peaks: [usize; 3],
+}
+
+impl PhaseTimings {
+    /// Record the times of one run
+    ///
+    /// # Arguments
+    ///
+    /// * `times` - The time taken by each phase in `PHASES`
+    ///
+    pub fn record(&mut self, times: [Duration; 3]) {
+        for (phase_times, time) in self.times.iter_mut().zip(times) {
+            phase_times.push(time);
+        }
+    }
+
+    /// Record the peak memory of each phase of one run
+    ///
+    /// # Arguments
+    ///
+    /// * `peaks` - The most bytes allocated at once in each phase in `PHASES`
+    ///
+    pub fn record_peaks(&mut self, peaks: [usize; 3]) {
+        for (phase_peak, peak) in self.peaks.iter_mut().zip(peaks) {
+            *phase_peak = (*phase_peak).max(peak);
+        }
+    }
+
+    /// The minimum, median and maximum time of a phase
+    ///
+    /// # Arguments
+    ///
+    /// * `phase` - The index of the phase in `PHASES`
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(Duration, Duration, Duration)>` - The (min, median, max), or `None` before the first run
+    ///
+    pub fn summary(&self, phase: usize) -> Option<(Duration, Duration, Duration)> {
+        let mut times = self.times[phase].clone();
+        if times.is_empty() {
+            return None;
+        }
+        times.sort();
+        let n = times.len();
+        let median = if n % 2 == 1 {
+            times[n / 2]
+        } else {
+            (times[n / 2 - 1] + times[n / 2]) / 2
+        };
+        Some((times[0], median, times[n - 1]))
+    }
+
+    /// Print a table of the min, median and max time of each phase
+    ///
+    /// The peak memory of each phase is added as a column if it was counted.
+    pub fn print(&self) {
+        let with_peaks = self.peaks.iter().any(|peak| *peak > 0);
+        let peak_column = |peak: String| if with_peaks { format!(" {:>12}", peak) } else { String::new() };
+        println!(
+            "{:<8} {:>12} {:>12} {:>12}{}",
+            "phase",
+            "min",
+            "median",
+            "max",
+            peak_column("peak".to_string())
+        );
+        for (phase, name) in PHASES.iter().enumerate() {
+            if let Some((min, median, max)) = self.summary(phase) {
+                println!(
+                    "{:<8} {:>12} {:>12} {:>12}{}",
+                    name,
+                    format!("{:.3?}", min),
+                    format!("{:.3?}", median),
+                    format!("{:.3?}", max),
+                    peak_column(format_bytes(self.peaks[phase]))
+                );
+            }
+        }
+    }
+}
+
+/// The scratch file that benchmark outputs are written to
+///
+/// Outputs go to the temporary directory (`TMPDIR`, or `/tmp`) rather than
+/// next to the input, so that slow or networked storage for the outputs
+/// does not skew the timings. Point `TMPDIR` at a RAM disk to take the
+/// storage out of the write timings altogether.
+///
+/// # Returns
+///
+/// * `PathBuf` - The scratch file
+///
+pub fn benchmark_filename() -> PathBuf {
+    std::env::temp_dir().join(format!("fitsrotate_rs.benchmark.{}.fits", std::process::id()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn summary_is_min_median_max_per_phase() {
+        let mut timings = PhaseTimings::default();
+        assert_eq!(timings.summary(0), None);
+        timings.record([ms(30), ms(5), ms(1)]);
+        timings.record([ms(10), ms(7), ms(2)]);
+        timings.record([ms(20), ms(6), ms(9)]);
+        assert_eq!(timings.summary(0), Some((ms(10), ms(20), ms(30))));
+        assert_eq!(timings.summary(1), Some((ms(5), ms(6), ms(7))));
+        assert_eq!(timings.summary(2), Some((ms(1), ms(2), ms(9))));
+    }
+
+    #[test]
+    fn even_runs_take_the_mean_of_the_middle_two() {
+        let mut timings = PhaseTimings::default();
+        for time in [ms(4), ms(1), ms(8), ms(2)] {
+            timings.record([time; 3]);
+        }
+        assert_eq!(timings.summary(1), Some((ms(1), ms(3), ms(8))));
+    }
+
+    #[test]
+    fn peaks_are_the_most_over_all_runs() {
+        let mut timings = PhaseTimings::default();
+        timings.record_peaks([10, 200, 30]);
+        timings.record_peaks([20, 100, 30]);
+        assert_eq!(timings.peaks, [20, 200, 30]);
+    }
+
+    #[test]
+    fn outputs_go_to_the_temporary_directory() {
+        let filename = benchmark_filename();
+        assert_eq!(filename.parent(), Some(std::env::temp_dir().as_path()));
+        assert!(filename.to_str().unwrap().ends_with(".fits"));
+    }
+}
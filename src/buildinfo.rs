@@ -0,0 +1,207 @@
+use fitsio::FitsFile;
+
+/// A minimal (header-only) FITS file, gzip compressed
+const GZIP_PROBE: [u8; 81] = [
+    31, 139, 8, 0, 0, 0, 0, 0, 2, 3, 11, 246, 244, 13, 240, 113, 85, 80, 176, 85, 192, 2, 66, 20, 72, 6, 78, 158, 33,
+    1, 158, 17, 56, 204, 179, 32, 221, 60, 63, 199, 8, 207, 96, 5, 28, 230, 25, 144, 110, 158, 171, 159, 139, 194, 40,
+    24, 5, 163, 96, 20, 140, 130, 81, 48, 10, 70, 193, 40, 24, 5, 228, 2, 0, 221, 125, 47, 107, 64, 11, 0, 0,
+];
+
+/// The same minimal FITS file, bzip2 compressed
+const BZIP2_PROBE: [u8; 87] = [
+    66, 90, 104, 57, 49, 65, 89, 38, 83, 89, 128, 21, 14, 238, 0, 0, 51, 62, 0, 192, 0, 64, 1, 64, 66, 54, 39, 76, 64,
+    0, 8, 32, 0, 72, 109, 73, 30, 136, 245, 26, 102, 80, 106, 26, 105, 163, 8, 208, 100, 135, 54, 247, 164, 40, 185,
+    44, 5, 102, 192, 16, 152, 159, 91, 118, 198, 53, 74, 94, 53, 92, 245, 227, 144, 241, 10, 15, 139, 185, 34, 156, 40,
+    72, 64, 10, 135, 119, 0,
+];
+
+/// Environment variables that change how fitsrotate_rs runs
+const ENV_VARS: [&str; 2] = ["TMPDIR", "RAYON_NUM_THREADS"];
+
+/// Everything about a build and its platform that is useful in a bug report
+#[derive(Clone, Debug)]
+pub struct EnvReport {
+    /// The crate version
+    pub version: String,
+    /// The git commit the binary was built from, if known
+    pub git_hash: Option<String>,
+    /// The version of the linked cfitsio
+    pub cfitsio_version: String,
+    /// Whether cfitsio can read gzip compressed files
+    pub gzip: bool,
+    /// Whether cfitsio can read bzip2 compressed files
+    pub bzip2: bool,
+    /// Whether cfitsio was built thread-safe
+    pub reentrant: bool,
+    /// The enabled cargo features
+    pub features: Vec<String>,
+    /// The operating system
+    pub os: String,
+    /// The CPU architecture
+    pub arch: String,
+    /// The number of CPUs available to the process
+    pub cpus: Option<usize>,
+    /// The total memory in bytes
+    pub memory: Option<u64>,
+    /// The relevant environment variables, if set
+    pub env_vars: Vec<(String, Option<String>)>,
+}
+
+impl EnvReport {
+    /// Gather the report, probing cfitsio at runtime
+    pub fn collect() -> Self {
+        let git_hash = env!("FITSROTATE_GIT_HASH");
+        let mut features: Vec<String> = Vec::new();
+        if cfg!(feature = "std") {
+            features.push("std".to_string());
+        }
+        if cfg!(feature = "interactive") {
+            features.push("interactive".to_string());
+        }
+        if cfg!(feature = "version-check") {
+            features.push("version-check".to_string());
+        }
+        if cfg!(feature = "http") {
+            features.push("http".to_string());
+        }
+        EnvReport {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            git_hash: if git_hash.is_empty() { None } else { Some(git_hash.to_string()) },
+            cfitsio_version: cfitsio_version(),
+            gzip: can_open(&GZIP_PROBE, "gz"),
+            bzip2: can_open(&BZIP2_PROBE, "bz2"),
+            reentrant: unsafe { fitsio::sys::fits_is_reentrant() } != 0,
+            features,
+            os: std::env::consts::OS.to_string(),
+            arch: std::env::consts::ARCH.to_string(),
+            cpus: std::thread::available_parallelism().ok().map(|n| n.get()),
+            memory: total_memory(),
+            env_vars: ENV_VARS
+                .iter()
+                .map(|name| (name.to_string(), std::env::var(name).ok()))
+                .collect(),
+        }
+    }
+
+    /// The lines of the report, for reading in a terminal
+    pub fn lines(&self) -> Vec<String> {
+        let or_unknown = |value: Option<String>| value.unwrap_or_else(|| "unknown".to_string());
+        let yes_no = |value: bool| if value { "yes" } else { "no" };
+        let mut lines = vec![
+            format!("fitsrotate_rs: {}", self.version),
+            format!("git commit: {}", or_unknown(self.git_hash.clone())),
+            format!("cfitsio: {}", self.cfitsio_version),
+            format!("cfitsio gzip: {}", yes_no(self.gzip)),
+            format!("cfitsio bzip2: {}", yes_no(self.bzip2)),
+            format!("cfitsio reentrant: {}", yes_no(self.reentrant)),
+            format!(
+                "features: {}",
+                if self.features.is_empty() { "none".to_string() } else { self.features.join(", ") }
+            ),
+            format!("platform: {} ({})", self.os, self.arch),
+            format!("cpus: {}", or_unknown(self.cpus.map(|n| n.to_string()))),
+            format!(
+                "memory: {}",
+                or_unknown(self.memory.map(|bytes| format!("{:.1} GiB", bytes as f64 / (1u64 << 30) as f64)))
+            ),
+        ];
+        for (name, value) in &self.env_vars {
+            lines.push(format!("{}: {}", name, value.as_deref().unwrap_or("(unset)")));
+        }
+        lines
+    }
+
+    /// The report as a JSON object, for attaching to issues
+    pub fn to_json(&self) -> String {
+        let string = |value: &str| json_string(value);
+        let optional = |value: Option<String>| value.unwrap_or_else(|| "null".to_string());
+        let features: Vec<String> = self.features.iter().map(|f| string(f)).collect();
+        let env_vars: Vec<String> = self
+            .env_vars
+            .iter()
+            .map(|(name, value)| format!("{}: {}", string(name), optional(value.as_deref().map(string))))
+            .collect();
+        let fields = [
+            ("version", string(&self.version)),
+            ("git_hash", optional(self.git_hash.as_deref().map(string))),
+            ("cfitsio_version", string(&self.cfitsio_version)),
+            ("gzip", self.gzip.to_string()),
+            ("bzip2", self.bzip2.to_string()),
+            ("reentrant", self.reentrant.to_string()),
+            ("features", format!("[{}]", features.join(", "))),
+            ("os", string(&self.os)),
+            ("arch", string(&self.arch)),
+            ("cpus", optional(self.cpus.map(|n| n.to_string()))),
+            ("memory_bytes", optional(self.memory.map(|bytes| bytes.to_string()))),
+            ("env", format!("{{{}}}", env_vars.join(", "))),
+        ];
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|(key, value)| format!("  {}: {}", string(key), value))
+            .collect();
+        format!("{{\n{}\n}}", fields.join(",\n"))
+    }
+}
+
+/// Quote and escape a string for JSON
+pub fn json_string(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// The version of the linked cfitsio, e.g. `4.2`
+fn cfitsio_version() -> String {
+    let mut version: f32 = 0.0;
+    unsafe {
+        fitsio::sys::ffvers(&mut version);
+    }
+    format!("{:.3}", version).trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Check if cfitsio can open a compressed file
+///
+/// cfitsio recognises compressed files by their contents, so a small file
+/// is written to the temporary directory and opened.
+///
+/// # Arguments
+///
+/// * `bytes` - The compressed FITS file
+/// * `extension` - The file extension of the compression
+///
+/// # Returns
+///
+/// * `bool` - True if the file could be opened
+///
+fn can_open(bytes: &[u8], extension: &str) -> bool {
+    let probe = std::env::temp_dir().join(format!("fitsrotate_rs.{}.probe.fits.{}", std::process::id(), extension));
+    if std::fs::write(&probe, bytes).is_err() {
+        return false;
+    }
+    let opened = FitsFile::open(&probe).is_ok();
+    let _ = std::fs::remove_file(&probe);
+    opened
+}
+
+/// The total memory of the machine in bytes, from `/proc/meminfo`
+#[cfg(target_os = "linux")]
+fn total_memory() -> Option<u64> {
+    let meminfo = std::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = meminfo.lines().find(|line| line.starts_with("MemTotal:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// The total memory of the machine in bytes (only known on Linux)
+#[cfg(not(target_os = "linux"))]
+fn total_memory() -> Option<u64> {
+    None
+}
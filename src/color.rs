@@ -0,0 +1,79 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Colour only when writing to a terminal and `NO_COLOR` is not set
+const AUTO: u8 = 0;
+/// Always colour (`--color`)
+const ALWAYS: u8 = 1;
+/// Never colour (`--no-color`)
+const NEVER: u8 = 2;
+
+/// The colour choice of the command line
+static CHOICE: AtomicU8 = AtomicU8::new(AUTO);
+
+/// ANSI colour codes
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+const RED: &str = "31";
+const CYAN: &str = "36";
+
+/// Set the colour choice from the command line flags
+///
+/// # Arguments
+///
+/// * `color` - Colour even if the output is not a terminal or `NO_COLOR` is set
+/// * `no_color` - Never colour
+///
+pub fn set_color_choice(color: bool, no_color: bool) {
+    let choice = if no_color {
+        NEVER
+    } else if color {
+        ALWAYS
+    } else {
+        AUTO
+    };
+    CHOICE.store(choice, Ordering::Relaxed);
+}
+
+/// Check if output should be coloured
+///
+/// # Arguments
+///
+/// * `is_terminal` - The output stream is a terminal
+///
+fn enabled(is_terminal: bool) -> bool {
+    match CHOICE.load(Ordering::Relaxed) {
+        ALWAYS => true,
+        NEVER => false,
+        _ => is_terminal && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty()),
+    }
+}
+
+/// Wrap text in an ANSI colour code, if enabled
+fn paint(text: &str, code: &str, is_terminal: bool) -> String {
+    if enabled(is_terminal) {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// Colour a success message printed to stdout
+pub fn success(text: &str) -> String {
+    paint(text, GREEN, std::io::stdout().is_terminal())
+}
+
+/// Colour a warning printed to stdout
+pub fn warning(text: &str) -> String {
+    paint(text, YELLOW, std::io::stdout().is_terminal())
+}
+
+/// Colour a field label printed to stdout
+pub fn label(text: &str) -> String {
+    paint(text, CYAN, std::io::stdout().is_terminal())
+}
+
+/// Colour an error message printed to stderr
+pub fn error(text: &str) -> String {
+    paint(text, RED, std::io::stderr().is_terminal())
+}
@@ -0,0 +1,154 @@
+use crate::error::FitsRotateError;
+use crate::header::FitsHeader;
+
+/// Valid values of BITPIX
+const VALID_BITPIX: [&str; 6] = ["8", "16", "32", "64", "-32", "-64"];
+
+/// Keywords of the SIP distortion convention
+const SIP_KEYWORDS: [&str; 4] = ["A_ORDER", "B_ORDER", "AP_ORDER", "BP_ORDER"];
+
+/// Find the value of the first card with a given keyword
+fn find_value(header: &FitsHeader, keyword: &str) -> Option<String> {
+    header
+        .cards
+        .iter()
+        .find(|card| card.keyword() == keyword)
+        .and_then(|card| card.value())
+}
+
+/// Work out which version of the FITS standard a header conforms to
+///
+/// An explicit `FITS_SD` keyword is reported if present. Otherwise the
+/// mandatory SIMPLE/BITPIX/EXTEND keywords are checked, and the version is
+/// inferred from the features in use: 64-bit integers (BITPIX = 64) need at
+/// least FITS 3.0, while long-string CONTINUE cards and tile-compressed
+/// images were only standardised in FITS 4.0. HIERARCH keywords and SIP
+/// distortions, which are conventions outside the standard, are reported
+/// as well.
+///
+/// # Arguments
+///
+/// * `header` - The primary header
+///
+/// # Returns
+///
+/// * `Vec<String>` - The lines of the report
+///
+pub fn fits_version_report(header: &FitsHeader) -> Vec<String> {
+    let mut report: Vec<String> = Vec::new();
+
+    let simple = find_value(header, "SIMPLE");
+    let bitpix = find_value(header, "BITPIX").unwrap_or_default();
+    let extend = find_value(header, "EXTEND");
+    match simple.as_deref() {
+        Some("T") => report.push("SIMPLE = T".to_string()),
+        Some(value) => report.push(format!("SIMPLE = {} (file does not conform to the standard)", value)),
+        None => report.push("SIMPLE is missing (file does not conform to the standard)".to_string()),
+    }
+    if VALID_BITPIX.contains(&bitpix.as_str()) {
+        report.push(format!("BITPIX = {}", bitpix));
+    } else {
+        report.push(format!("BITPIX = '{}' is not a valid value", bitpix));
+    }
+    match extend {
+        Some(extend) => report.push(format!("EXTEND = {}", extend)),
+        None => report.push("EXTEND is not set".to_string()),
+    }
+
+    let has_continue = header.cards.iter().any(|card| card.keyword() == "CONTINUE");
+    let is_compressed = find_value(header, "ZIMAGE").as_deref() == Some("T");
+    let version = if let Some(fits_sd) = find_value(header, "FITS_SD") {
+        format!("{} (from FITS_SD)", fits_sd)
+    } else if has_continue || is_compressed {
+        "4.0".to_string()
+    } else if bitpix == "64" {
+        "3.0 or later".to_string()
+    } else {
+        "3.0".to_string()
+    };
+    report.push(format!("File appears to conform to FITS {}", version));
+    if has_continue {
+        report.push("Uses long-string CONTINUE cards (FITS 4.0)".to_string());
+    }
+    if is_compressed {
+        report.push("Uses tile-compressed images (FITS 4.0)".to_string());
+    }
+    if bitpix == "64" {
+        report.push("Uses 64-bit integers (FITS 3.0 and later)".to_string());
+    }
+
+    let n_hierarch = header
+        .cards
+        .iter()
+        .filter(|card| card.keyword() == "HIERARCH")
+        .count();
+    if n_hierarch > 0 {
+        report.push(format!("Uses the HIERARCH convention ({} cards)", n_hierarch));
+    }
+    let has_sip = header.cards.iter().any(|card| {
+        SIP_KEYWORDS.contains(&card.keyword().as_str())
+            || (card.keyword().starts_with("CTYPE")
+                && card.value().is_some_and(|v| v.ends_with("-SIP")))
+    });
+    if has_sip {
+        report.push("Uses SIP distortion keywords".to_string());
+    }
+    report
+}
+
+/// The data type of a BITPIX value, for messages
+fn bitpix_type(bitpix: i64) -> &'static str {
+    match bitpix {
+        8 => "8-bit unsigned integers",
+        16 => "16-bit integers",
+        32 => "32-bit integers",
+        64 => "64-bit integers",
+        -32 => "32-bit floats",
+        -64 => "64-bit floats",
+        _ => "an invalid data type",
+    }
+}
+
+/// Parse a `--require-bitpix` value, which must be a valid BITPIX
+///
+/// # Arguments
+///
+/// * `value` - The BITPIX, e.g. `-32`
+///
+/// # Returns
+///
+/// * `Result<i64, String>` - The BITPIX
+///
+pub fn parse_bitpix(value: &str) -> Result<i64, String> {
+    let value = value.trim();
+    if !VALID_BITPIX.contains(&value) {
+        return Err(format!("{} is not a valid BITPIX (one of {})", value, VALID_BITPIX.join(", ")));
+    }
+    value.parse().map_err(|_| format!("{} is not a valid BITPIX", value))
+}
+
+/// Check that the BITPIX of a file is the one required by `--require-bitpix`
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file, for the error message
+/// * `bitpix` - The BITPIX of the file
+/// * `required` - The required BITPIX
+///
+/// # Returns
+///
+/// * `Result<(), FitsRotateError>` - An error naming both data types if they differ
+///
+pub fn check_bitpix(filename: &str, bitpix: i64, required: i64) -> Result<(), FitsRotateError> {
+    if bitpix == required {
+        return Ok(());
+    }
+    Err(FitsRotateError::Message(format!(
+        "{} has BITPIX = {} ({}), but --require-bitpix asks for {} ({})",
+        filename,
+        bitpix,
+        bitpix_type(bitpix),
+        required,
+        bitpix_type(required)
+    )))
+}
@@ -0,0 +1,586 @@
+use crate::axes::{axis_role, read_ctypes, AxisRole};
+use crate::error::FitsRotateError;
+use crate::header::{append_hdu_blocks, delete_key};
+use clap::ValueEnum;
+use fitsio::errors::{check_status, Error};
+use fitsio::{FileOpenMode, FitsFile};
+use std::ffi::CString;
+use std::path::Path;
+
+/// The seed CFITSIO takes from the checksum of the first tile, so that the same data is always dithered the same way
+const CHECKSUM_SEED: i32 = -1;
+
+/// The EXTNAME of the compressed image, as fpack names it
+pub const COMPRESSED_EXTNAME: &str = "COMPRESSED_IMAGE";
+
+/// The compression algorithm of a tile-compressed output
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Compression {
+    /// No compression, even of a tile-compressed input
+    None,
+    /// Rice, as fpack does by default
+    Rice,
+    /// Gzip
+    Gzip,
+    /// Gzip of the bytes shuffled by significance, which suits floating point pixels better
+    Gzip2,
+}
+
+impl Compression {
+    /// The CFITSIO code of the algorithm
+    fn code(self) -> i32 {
+        (match self {
+            Compression::None => fitsio::sys::NOCOMPRESS as u32,
+            Compression::Rice => fitsio::sys::RICE_1,
+            Compression::Gzip => fitsio::sys::GZIP_1,
+            Compression::Gzip2 => fitsio::sys::GZIP_2,
+        }) as i32
+    }
+
+    /// The algorithm of a ZCMPTYPE, if it is one that outputs can be compressed with
+    fn from_zcmptype(zcmptype: &str) -> Option<Compression> {
+        match zcmptype.trim() {
+            "RICE_1" | "RICE_ONE" => Some(Compression::Rice),
+            "GZIP_1" => Some(Compression::Gzip),
+            "GZIP_2" => Some(Compression::Gzip2),
+            _ => None,
+        }
+    }
+}
+
+/// How quantized pixel values are dithered
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Dither {
+    /// No dithering, which biases the quantized values
+    None,
+    /// Subtractive dithering (SUBTRACTIVE_DITHER_1)
+    Subtractive,
+    /// Subtractive dithering that keeps pixels of exactly zero (SUBTRACTIVE_DITHER_2)
+    Subtractive2,
+}
+
+impl Dither {
+    /// The method of a ZQUANTIZ, or `None` if the pixels are not quantized
+    fn from_zquantiz(zquantiz: &str) -> Option<Dither> {
+        match zquantiz.trim() {
+            "NO_DITHER" => Some(Dither::None),
+            "SUBTRACTIVE_DITHER_1" => Some(Dither::Subtractive),
+            "SUBTRACTIVE_DITHER_2" => Some(Dither::Subtractive2),
+            _ => None,
+        }
+    }
+
+    /// The CFITSIO code of the method
+    fn code(self) -> i32 {
+        match self {
+            Dither::None => fitsio::sys::NO_DITHER,
+            Dither::Subtractive => fitsio::sys::SUBTRACTIVE_DITHER_1 as i32,
+            Dither::Subtractive2 => fitsio::sys::SUBTRACTIVE_DITHER_2 as i32,
+        }
+    }
+}
+
+/// The tiles of a compressed image
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Tiles {
+    /// The default of CFITSIO, a row along the first axis at a time
+    Rows,
+    /// N by M pixels of the last two spatial axes, and 1 pixel along the others (see `tile_dims`)
+    Spatial([usize; 2]),
+    /// The tile length of each axis, in FITS order
+    Axes(Vec<usize>),
+}
+
+/// How to tile-compress an output
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompressionSettings {
+    /// The compression algorithm
+    pub compression: Compression,
+    /// The quantization level q of floating point pixels, or `None` to compress them losslessly
+    ///
+    /// The pixels of each tile are quantized in steps of the noise of the
+    /// tile divided by q, so a larger q keeps more of the noise.
+    pub quantize: Option<f32>,
+    /// How the quantized pixels are dithered
+    pub dither: Dither,
+    /// The seed of the dither (1 to 10000), or `None` for one from the clock
+    pub dither_seed: Option<i32>,
+    /// Take the seed of the dither from the data rather than the clock when no seed is given
+    pub reproducible: bool,
+    /// The tiles of the image
+    pub tiles: Tiles,
+}
+
+impl CompressionSettings {
+    /// Check that the settings can compress the floating point pixels of a rotated cube
+    ///
+    /// Rice only compresses integers, so floating point pixels have to be
+    /// quantized first; only gzip compresses them losslessly.
+    pub fn check(&self) -> Result<(), FitsRotateError> {
+        if self.compression == Compression::None && (self.quantize.is_some() || self.tiles != Tiles::Rows) {
+            return Err(FitsRotateError::Message(
+                "--quantize and --tile-size need a compression algorithm, not --compress none".to_string(),
+            ));
+        }
+        if self.quantize.is_none() && self.compression == Compression::Rice {
+            return Err(FitsRotateError::Message(
+                "Rice compression of floating point pixels needs a quantization level (e.g. --quantize q=4); use \
+                 --compress gzip or gzip2 to compress them losslessly"
+                    .to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The settings for a rotation of the image they were read from
+    ///
+    /// Tiles given for each axis are permuted with the axes, so that a tile
+    /// covers the same pixels before and after the rotation.
+    ///
+    /// # Arguments
+    ///
+    /// * `mode` - The (FITS, 1-based) input axis of each output axis
+    ///
+    pub fn permuted(&self, mode: &[usize]) -> CompressionSettings {
+        let tiles = match &self.tiles {
+            Tiles::Axes(tiles) => Tiles::Axes(
+                mode.iter()
+                    .map(|axis| tiles.get(axis - 1).copied().unwrap_or(1))
+                    .collect(),
+            ),
+            tiles => tiles.clone(),
+        };
+        CompressionSettings { tiles, ..self.clone() }
+    }
+
+    /// The settings read from a tile-compressed input, for its outputs
+    ///
+    /// The floating point pixels of the outputs are only quantized if a level
+    /// is given, as the level of the input is not recorded; without one, Rice
+    /// (which cannot compress them losslessly) becomes gzip2.
+    ///
+    /// # Arguments
+    ///
+    /// * `quantize` - The quantization level, or `None` to compress losslessly
+    /// * `dither_seed` - The seed of the dither, or `None` for that of the input
+    ///
+    pub fn carried(&self, quantize: Option<f32>, dither_seed: Option<i32>) -> CompressionSettings {
+        let compression = match (self.compression, quantize) {
+            (Compression::Rice, None) => Compression::Gzip2,
+            (compression, _) => compression,
+        };
+        CompressionSettings {
+            compression,
+            quantize,
+            dither_seed: dither_seed.or(self.dither_seed),
+            ..self.clone()
+        }
+    }
+
+    /// Read the compression of a tile-compressed image, to compress its rotated outputs the same way
+    ///
+    /// The algorithm, tiles and dither come from ZCMPTYPE, ZTILEn, ZQUANTIZ
+    /// and ZDITHER0. CFITSIO does not record the quantization level, so the
+    /// settings are lossless until `carried` is given one. Images compressed
+    /// with an algorithm that outputs cannot be written with (e.g.
+    /// HCOMPRESS_1) are compressed with gzip2.
+    ///
+    /// # Arguments
+    ///
+    /// * `fits_file` - The FITS file
+    /// * `hdu_idx` - The compressed image HDU
+    ///
+    pub fn read(fits_file: &mut FitsFile, hdu_idx: usize) -> Result<CompressionSettings, Error> {
+        let hdu = fits_file.hdu(hdu_idx)?;
+        let zcmptype: String = hdu.read_key(fits_file, "ZCMPTYPE")?;
+        let znaxis: i64 = hdu.read_key(fits_file, "ZNAXIS")?;
+        let mut tiles = Vec::with_capacity(znaxis as usize);
+        for axis in 1..=znaxis {
+            // A missing ZTILEn is a whole row along axis 1 and a single pixel along the others
+            let tile = match hdu.read_key::<i64>(fits_file, &format!("ZTILE{}", axis)) {
+                Ok(tile) => tile,
+                Err(_) if axis == 1 => hdu.read_key::<i64>(fits_file, "ZNAXIS1")?,
+                Err(_) => 1,
+            };
+            tiles.push(tile as usize);
+        }
+        let dither = hdu
+            .read_key::<String>(fits_file, "ZQUANTIZ")
+            .ok()
+            .and_then(|zquantiz| Dither::from_zquantiz(&zquantiz));
+        let dither_seed = hdu
+            .read_key::<i64>(fits_file, "ZDITHER0")
+            .ok()
+            .filter(|seed| (1..=10000).contains(seed))
+            .map(|seed| seed as i32);
+        Ok(CompressionSettings {
+            compression: Compression::from_zcmptype(&zcmptype).unwrap_or(Compression::Gzip2),
+            quantize: None,
+            dither: dither.unwrap_or(Dither::Subtractive),
+            dither_seed,
+            reproducible: false,
+            tiles: Tiles::Axes(tiles),
+        })
+    }
+}
+
+/// Parse the quantization level of `--quantize`, given as `q=N`
+///
+/// The level has to be given explicitly: CFITSIO would otherwise quantize
+/// floating point pixels with q = 4 without being asked to.
+///
+/// # Arguments
+///
+/// * `value` - The level, e.g. `q=4`
+///
+/// # Returns
+///
+/// * `Result<f32, String>` - The level q, which is positive
+///
+pub fn parse_quantize(value: &str) -> Result<f32, String> {
+    let level = value
+        .trim()
+        .strip_prefix("q=")
+        .ok_or_else(|| format!("Expected the quantization level as q=N (e.g. q=4), got '{}'", value))?;
+    match level.trim().parse::<f32>() {
+        Ok(q) if q.is_finite() && q > 0.0 => Ok(q),
+        _ => Err(format!("The quantization level must be a positive number, got '{}'", level)),
+    }
+}
+
+/// Parse the seed of `--dither-seed`, which CFITSIO takes from 1 to 10000
+pub fn parse_dither_seed(value: &str) -> Result<i32, String> {
+    match value.trim().parse::<i32>() {
+        Ok(seed) if (1..=10000).contains(&seed) => Ok(seed),
+        _ => Err(format!("The dither seed must be a whole number from 1 to 10000, got '{}'", value)),
+    }
+}
+
+/// Parse the tile size of `--tile-size`, given as `N,M`
+///
+/// # Arguments
+///
+/// * `value` - The tile size, e.g. `256,256`
+///
+/// # Returns
+///
+/// * `Result<[usize; 2], String>` - The tile size of the last two spatial axes
+///
+pub fn parse_tile_size(value: &str) -> Result<[usize; 2], String> {
+    let sizes: Vec<usize> = value
+        .split(',')
+        .map(|size| size.trim().parse::<usize>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("Expected the tile size as N,M (e.g. 256,256), got '{}'", value))?;
+    match sizes.as_slice() {
+        [n, m] if *n > 0 && *m > 0 => Ok([*n, *m]),
+        [_, _] => Err(format!("The tile size must be positive, got '{}'", value)),
+        _ => Err(format!("Expected the tile size as N,M (e.g. 256,256), got '{}'", value)),
+    }
+}
+
+/// The tile dimensions of an image, with a tile size for its last two spatial axes
+///
+/// Spectral and Stokes axes always have tiles of 1 pixel, so that each
+/// tile is a piece of a single plane. Any other spatial axes before the
+/// last two also have tiles of 1 pixel, and an image with a single
+/// spatial axis has tiles of N pixels along it.
+///
+/// # Arguments
+///
+/// * `ctypes` - The CTYPEs of the image, in FITS order
+/// * `tile_size` - The tile size of the last two spatial axes, in FITS order
+///
+/// # Returns
+///
+/// * `Vec<usize>` - The tile length of each axis, in FITS order
+///
+pub fn tile_dims(ctypes: &[String], tile_size: [usize; 2]) -> Vec<usize> {
+    let spatial: Vec<usize> = (0..ctypes.len())
+        .filter(|idx| !matches!(axis_role(&ctypes[*idx]), Some(AxisRole::Spectral | AxisRole::Stokes)))
+        .collect();
+    let mut dims = vec![1; ctypes.len()];
+    for (idx, size) in spatial[spatial.len().saturating_sub(2)..].iter().zip(tile_size) {
+        dims[*idx] = size;
+    }
+    dims
+}
+
+/// Set the compression of the images CFITSIO writes to a file from now on
+///
+/// Floating point pixels are compressed losslessly unless a quantization
+/// level is given, rather than with the default level of CFITSIO.
+///
+/// # Arguments
+///
+/// * `fits_file` - The FITS file
+/// * `settings` - The compression settings
+///
+fn set_compression(fits_file: &mut FitsFile, settings: &CompressionSettings) -> Result<(), Error> {
+    let seed = match settings.dither_seed {
+        Some(seed) => seed,
+        None if settings.reproducible => CHECKSUM_SEED,
+        None => 0,
+    };
+    let mut status = 0;
+    unsafe {
+        fitsio::sys::fits_set_compression_type(fits_file.as_raw(), settings.compression.code(), &mut status);
+        fitsio::sys::fits_set_quantize_level(fits_file.as_raw(), settings.quantize.unwrap_or(0.0), &mut status);
+        fitsio::sys::fits_set_quantize_method(fits_file.as_raw(), settings.dither.code(), &mut status);
+        fitsio::sys::fits_set_dither_seed(fits_file.as_raw(), seed, &mut status);
+    }
+    check_status(status)
+}
+
+/// Rewrite a FITS file with its primary image tile-compressed
+///
+/// The image, with its full header, becomes a compressed extension after
+/// an empty primary HDU, as fpack writes it. CFITSIO records the algorithm,
+/// tiles and quantization (ZCMPTYPE, ZTILEn, ZQUANTIZ and ZDITHER0) in its
+/// header. Larger tiles (`settings.tiles`) compress better, but each
+/// tile is held in memory as it is compressed, and read whole to read any
+/// pixel of it. Any extensions of the file follow it in their original order.
+/// The compressed file is written next to the file and then renamed over it.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file, with the image in its primary HDU
+/// * `settings` - The compression settings
+///
+pub fn compress_file(filename: &str, settings: &CompressionSettings) -> Result<(), Error> {
+    let mut plain = FitsFile::open(filename)?;
+    plain.primary_hdu()?;
+
+    let partial = format!("{}.{}.partial", filename, std::process::id());
+    let _ = std::fs::remove_file(&partial);
+    let result = (|| -> Result<(), Error> {
+        let mut compressed = FitsFile::create(&partial).open()?;
+        set_compression(&mut compressed, settings)?;
+        let mut status = 0;
+        let dims = match &settings.tiles {
+            Tiles::Rows => None,
+            Tiles::Spatial(tile_size) => Some(tile_dims(&read_ctypes(&mut plain)?, *tile_size)),
+            Tiles::Axes(tiles) => Some(tiles.clone()),
+        };
+        // Tiles for another number of axes (e.g. of a plane split from the cube) are left to CFITSIO
+        let naxis: i64 = plain.primary_hdu()?.read_key(&mut plain, "NAXIS")?;
+        if let Some(dims) = dims.filter(|dims| dims.len() == naxis as usize) {
+            let mut dims: Vec<std::os::raw::c_long> = dims.iter().map(|dim| *dim as std::os::raw::c_long).collect();
+            unsafe {
+                fitsio::sys::fits_set_tile_dim(compressed.as_raw(), dims.len() as i32, dims.as_mut_ptr(), &mut status);
+            }
+            check_status(status)?;
+        }
+        unsafe {
+            fitsio::sys::fits_img_compress(plain.as_raw(), compressed.as_raw(), &mut status);
+        }
+        check_status(status)?;
+        // fitsio names every primary HDU it creates _PRIMARY, which is no name for an extension
+        let extension = compressed.hdu(1)?;
+        if extension.read_key::<String>(&mut compressed, "EXTNAME").is_ok_and(|name| name == "_PRIMARY") {
+            delete_key(&mut compressed, "EXTNAME")?;
+            extension.write_key(&mut compressed, "EXTNAME", COMPRESSED_EXTNAME)?;
+        }
+
+        // The other HDUs are copied unchanged, as raw blocks after the file is closed
+        drop(compressed);
+        let hdus: Vec<usize> = (1..plain.num_hdus()?).collect();
+        append_hdu_blocks(&mut plain, Path::new(&partial), &hdus)?;
+        Ok(())
+    })();
+    drop(plain);
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e);
+    }
+    std::fs::rename(&partial, filename)?;
+    Ok(())
+}
+
+/// A tile-compressed input, unpacked to a plain FITS file that is removed when this is dropped
+pub struct UnpackedInput {
+    /// The plain FITS file, with the image in its primary HDU
+    pub path: String,
+    /// The compression of the input, in the axis order of the input
+    pub settings: CompressionSettings,
+}
+
+impl Drop for UnpackedInput {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Unpack a tile-compressed FITS file, as fpack writes it, to a plain FITS file
+///
+/// A file is tile-compressed if its primary HDU is empty and its first
+/// extension is a compressed image. The image is decompressed into the
+/// primary HDU of a file in the temporary directory (see
+/// `std::env::temp_dir`), and any other extensions follow it unchanged.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+///
+/// # Returns
+///
+/// * `Option<UnpackedInput>` - The unpacked file, or `None` if the file is not tile-compressed
+///
+pub fn unpack_input(filename: &str) -> Result<Option<UnpackedInput>, Error> {
+    let mut packed = FitsFile::open(filename)?;
+    let hdu = packed.primary_hdu()?;
+    let naxis: i64 = hdu.read_key(&mut packed, "NAXIS")?;
+    if naxis != 0 || packed.num_hdus()? < 2 {
+        return Ok(None);
+    }
+    packed.hdu(1)?;
+    let is_compressed = unsafe { fitsio::sys::fits_is_compressed_image(packed.as_raw(), &mut 0) };
+    if is_compressed == 0 {
+        return Ok(None);
+    }
+    let settings = CompressionSettings::read(&mut packed, 1)?;
+
+    let name = Path::new(filename).file_name().map_or_else(|| filename.into(), |name| name.to_string_lossy());
+    let path = std::env::temp_dir().join(format!("{}.{}.unpacked", name, std::process::id()));
+    let unpacked = UnpackedInput {
+        path: path.to_string_lossy().into_owned(),
+        settings,
+    };
+    let _ = std::fs::remove_file(&unpacked.path);
+    let mut status = 0;
+    let c_path = CString::new(unpacked.path.as_str())?;
+    let mut fptr = std::ptr::null_mut();
+    unsafe {
+        fitsio::sys::ffinit(&mut fptr, c_path.as_ptr(), &mut status);
+    }
+    check_status(status)?;
+    let mut plain = unsafe { FitsFile::from_raw(fptr, FileOpenMode::READWRITE)? };
+    unsafe {
+        fitsio::sys::fits_img_decompress(packed.as_raw(), plain.as_raw(), &mut status);
+    }
+    check_status(status)?;
+    // The other HDUs are copied unchanged, as raw blocks after the file is closed
+    drop(plain);
+    let hdus: Vec<usize> = (2..packed.num_hdus()?).collect();
+    append_hdu_blocks(&mut packed, Path::new(&unpacked.path), &hdus)?;
+    Ok(Some(unpacked))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quantization_levels_are_only_taken_as_q_equals_a_positive_number() {
+        assert_eq!(parse_quantize("q=4"), Ok(4.0));
+        assert_eq!(parse_quantize(" q=0.5 "), Ok(0.5));
+        for value in ["4", "q=", "q=0", "q=-4", "q=inf", "level=4"] {
+            assert!(parse_quantize(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn dither_seeds_are_taken_from_1_to_10000() {
+        assert_eq!(parse_dither_seed("1"), Ok(1));
+        assert_eq!(parse_dither_seed("10000"), Ok(10000));
+        for value in ["0", "-1", "10001", "seed"] {
+            assert!(parse_dither_seed(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn rice_needs_a_quantization_level_and_gzip_does_not() {
+        let settings = |compression, quantize| CompressionSettings {
+            compression,
+            quantize,
+            dither: Dither::Subtractive,
+            dither_seed: None,
+            reproducible: false,
+            tiles: Tiles::Rows,
+        };
+        assert!(settings(Compression::Rice, None).check().is_err());
+        assert!(settings(Compression::Rice, Some(4.0)).check().is_ok());
+        assert!(settings(Compression::Gzip, None).check().is_ok());
+        assert!(settings(Compression::Gzip2, Some(4.0)).check().is_ok());
+    }
+
+    #[test]
+    fn tile_sizes_are_taken_as_two_positive_lengths() {
+        assert_eq!(parse_tile_size("256,128"), Ok([256, 128]));
+        assert_eq!(parse_tile_size(" 1, 2 "), Ok([1, 2]));
+        for value in ["256", "256,256,1", "0,256", "256x256", "-1,2"] {
+            assert!(parse_tile_size(value).is_err(), "{}", value);
+        }
+    }
+
+    #[test]
+    fn only_the_last_two_spatial_axes_have_tiles_longer_than_a_pixel() {
+        let ctypes = |ctypes: &[&str]| ctypes.iter().map(|c| c.to_string()).collect::<Vec<String>>();
+        assert_eq!(tile_dims(&ctypes(&["RA---SIN", "DEC--SIN", "FREQ"]), [16, 8]), [16, 8, 1]);
+        assert_eq!(tile_dims(&ctypes(&["FREQ", "RA---SIN", "DEC--SIN"]), [16, 8]), [1, 16, 8]);
+        assert_eq!(
+            tile_dims(&ctypes(&["STOKES", "RA---SIN", "FREQ", "DEC--SIN"]), [16, 8]),
+            [1, 16, 1, 8]
+        );
+        // An axis of no known type counts as spatial
+        assert_eq!(tile_dims(&ctypes(&["X", "RA---SIN", "DEC--SIN"]), [16, 8]), [1, 16, 8]);
+        assert_eq!(tile_dims(&ctypes(&["RA---SIN", "FREQ"]), [16, 8]), [16, 1]);
+    }
+
+    #[test]
+    fn no_compression_takes_no_quantization_or_tiles() {
+        let settings = |quantize, tiles| CompressionSettings {
+            compression: Compression::None,
+            quantize,
+            dither: Dither::Subtractive,
+            dither_seed: None,
+            reproducible: false,
+            tiles,
+        };
+        assert!(settings(None, Tiles::Rows).check().is_ok());
+        assert!(settings(Some(4.0), Tiles::Rows).check().is_err());
+        assert!(settings(None, Tiles::Spatial([16, 16])).check().is_err());
+    }
+
+    #[test]
+    fn the_tiles_of_each_axis_follow_the_axis() {
+        let settings = CompressionSettings {
+            compression: Compression::Rice,
+            quantize: Some(4.0),
+            dither: Dither::Subtractive2,
+            dither_seed: Some(7),
+            reproducible: false,
+            tiles: Tiles::Axes(vec![16, 8, 1]),
+        };
+        let permuted = settings.permuted(&[3, 1, 2]);
+        assert_eq!(permuted.tiles, Tiles::Axes(vec![1, 16, 8]));
+        assert_eq!(permuted.compression, Compression::Rice);
+        assert_eq!(permuted.dither_seed, Some(7));
+        // A dropped Stokes axis takes its tile with it
+        assert_eq!(
+            CompressionSettings {
+                tiles: Tiles::Axes(vec![16, 8, 1, 1]),
+                ..settings.clone()
+            }
+            .permuted(&[3, 1, 2])
+            .tiles,
+            Tiles::Axes(vec![1, 16, 8])
+        );
+        let spatial = CompressionSettings {
+            tiles: Tiles::Spatial([16, 8]),
+            ..settings
+        };
+        assert_eq!(spatial.permuted(&[3, 1, 2]).tiles, Tiles::Spatial([16, 8]));
+    }
+
+    #[test]
+    fn zcmptype_and_zquantiz_are_read_as_fpack_writes_them() {
+        assert_eq!(Compression::from_zcmptype("RICE_1"), Some(Compression::Rice));
+        assert_eq!(Compression::from_zcmptype("GZIP_1"), Some(Compression::Gzip));
+        assert_eq!(Compression::from_zcmptype("GZIP_2 "), Some(Compression::Gzip2));
+        assert_eq!(Compression::from_zcmptype("HCOMPRESS_1"), None);
+        assert_eq!(Dither::from_zquantiz("NO_DITHER"), Some(Dither::None));
+        assert_eq!(Dither::from_zquantiz("SUBTRACTIVE_DITHER_2"), Some(Dither::Subtractive2));
+        assert_eq!(Dither::from_zquantiz("NONE"), None);
+    }
+}
@@ -0,0 +1,148 @@
+use crate::error::FitsRotateError;
+use crate::header::FitsHeader;
+use crate::merge::glob_match;
+use crate::triplet::within_tolerance;
+use fitsio::FitsFile;
+
+/// The keywords checked by `check-consistency` by default
+pub const DEFAULT_CONSISTENCY_KEYS: [&str; 5] = ["NAXIS*", "CTYPE*", "CRVAL*", "CDELT*", "CUNIT*"];
+
+/// The values of the checked keywords of one file
+#[derive(Clone, Debug)]
+struct KeyValues {
+    filename: String,
+    values: Vec<(String, String)>,
+}
+
+impl KeyValues {
+    /// The value of a keyword, if the file has it
+    fn get(&self, keyword: &str) -> Option<&str> {
+        self.values
+            .iter()
+            .find(|(key, _)| key == keyword)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Read the values of the keywords matching any of the patterns
+///
+/// Only the primary header is read.
+fn read_key_values(filename: &str, patterns: &[Vec<char>]) -> Result<KeyValues, FitsRotateError> {
+    let mut fits_file = FitsFile::open(filename)?;
+    fits_file
+        .primary_hdu()
+        .map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let header = FitsHeader::read(&mut fits_file)?;
+    let mut values: Vec<(String, String)> = Vec::new();
+    for card in &header.cards {
+        let keyword = card.keyword();
+        let name: Vec<char> = keyword.chars().collect();
+        if !patterns.iter().any(|pattern| glob_match(pattern, &name)) {
+            continue;
+        }
+        if let Some(value) = card.value() {
+            values.push((keyword, value));
+        }
+    }
+    Ok(KeyValues {
+        filename: filename.to_string(),
+        values,
+    })
+}
+
+/// Check if two values agree: within the tolerance if both are numbers, exactly otherwise
+fn values_agree(a: Option<&str>, b: Option<&str>, tolerance: f64) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => match (a.parse::<f64>(), b.parse::<f64>()) {
+            (Ok(a), Ok(b)) => within_tolerance(a, b, tolerance),
+            _ => a == b,
+        },
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+/// Check that the headers of many files agree
+///
+/// Only the headers are read. Files are grouped by the values of the
+/// keywords matching the patterns, comparing numbers within a relative
+/// tolerance and strings exactly. If there is more than one group, each
+/// group is compared with the largest one, listing the keywords that
+/// differ and their values.
+///
+/// # Arguments
+///
+/// * `filenames` - The FITS files
+/// * `keys` - Keyword patterns, with `*` and `?` wildcards (e.g. `CTYPE*`)
+/// * `tolerance` - The relative tolerance for numeric values
+///
+pub fn check_consistency(filenames: &[String], keys: &[String], tolerance: f64) -> Result<(), FitsRotateError> {
+    let patterns: Vec<Vec<char>> = keys.iter().map(|key| key.trim().to_uppercase().chars().collect()).collect();
+    let files: Vec<KeyValues> = filenames
+        .iter()
+        .map(|filename| read_key_values(filename, &patterns))
+        .collect::<Result<_, _>>()?;
+
+    // Every keyword found in any file, in order of first appearance
+    let mut keywords: Vec<String> = Vec::new();
+    for file in &files {
+        for (keyword, _) in &file.values {
+            if !keywords.contains(keyword) {
+                keywords.push(keyword.clone());
+            }
+        }
+    }
+
+    // Each group is the indices of its files, compared through the first
+    let mut groups: Vec<Vec<usize>> = Vec::new();
+    for (idx, file) in files.iter().enumerate() {
+        let group = groups.iter_mut().find(|group| {
+            let first = &files[group[0]];
+            keywords
+                .iter()
+                .all(|keyword| values_agree(first.get(keyword), file.get(keyword), tolerance))
+        });
+        match group {
+            Some(group) => group.push(idx),
+            None => groups.push(vec![idx]),
+        }
+    }
+
+    if groups.len() <= 1 {
+        println!("All {} files are consistent on {} keywords", files.len(), keywords.len());
+        return Ok(());
+    }
+
+    groups.sort_by_key(|group| std::cmp::Reverse(group.len()));
+    let reference = &files[groups[0][0]];
+    let names = |group: &[usize]| -> String {
+        group
+            .iter()
+            .map(|idx| files[*idx].filename.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    println!("The files fall into {} groups", groups.len());
+    println!("Group 1 ({} files): {}", groups[0].len(), names(&groups[0]));
+    for (group_idx, group) in groups.iter().enumerate().skip(1) {
+        println!("Group {} ({} files): {}", group_idx + 1, group.len(), names(group));
+        let file = &files[group[0]];
+        for keyword in &keywords {
+            let (value, reference_value) = (file.get(keyword), reference.get(keyword));
+            if !values_agree(value, reference_value, tolerance) {
+                println!(
+                    "  {}: {} (group 1: {})",
+                    keyword,
+                    value.unwrap_or("missing"),
+                    reference_value.unwrap_or("missing")
+                );
+            }
+        }
+    }
+    let n_deviating: usize = groups.iter().skip(1).map(|group| group.len()).sum();
+    Err(FitsRotateError::Message(format!(
+        "{} of {} files are not consistent with the rest",
+        n_deviating,
+        files.len()
+    )))
+}
@@ -0,0 +1,713 @@
+use crate::error::FitsRotateError;
+use crate::header::{FitsHeader, HeaderCard};
+use crate::layout::keyword_axes;
+use crate::repair::renumbered_card;
+use crate::FitsPixel;
+use ndarray::{ArrayD, Axis, IxDyn, Slice, Zip};
+
+/// The data of a FITS cube, in array (slowest axis first) order, and its header
+#[derive(Clone, Debug)]
+pub struct FitsCube<T> {
+    pub data: ArrayD<T>,
+    pub header: FitsHeader,
+}
+
+impl<T: Copy + From<f32>> FitsCube<T> {
+    /// Wrap the data of a FITS cube, with an empty header
+    pub fn new(data: ArrayD<T>) -> Self {
+        FitsCube::from_array(data, FitsHeader::default())
+    }
+
+    /// Wrap the data of a FITS cube and its header
+    ///
+    /// Nothing checks that the header describes the data: a header that
+    /// does not match the shape of the data, or that lacks the WCS cards
+    /// (CTYPE, CRVAL, CDELT, CRPIX, ...) of each axis, will not make a
+    /// valid FITS file.
+    ///
+    /// # Arguments
+    ///
+    /// * `data` - The data, in array order
+    /// * `header` - The header
+    ///
+    pub fn from_array(data: ArrayD<T>, header: FitsHeader) -> Self {
+        FitsCube { data, header }
+    }
+
+    /// Unwrap the data of the cube, dropping the header
+    pub fn into_array(self) -> ArrayD<T> {
+        self.data
+    }
+
+    /// Extract a single plane of the cube along an axis
+    ///
+    /// The plane has one fewer axis than the cube. Its header is the header
+    /// of the cube with `NAXIS` decremented, the `NAXISn` and WCS cards of
+    /// the removed axis dropped (including any `PCi_j` or `CDi_j` that
+    /// involve it), and those of the later axes renumbered down by one.
+    ///
+    /// # Arguments
+    ///
+    /// * `axis` - The (array) axis to slice along
+    /// * `index` - The (0-based) index of the plane along that axis
+    ///
+    /// # Returns
+    ///
+    /// * `Result<FitsCube<T>, FitsRotateError>` - The plane, or an error if the axis is not an axis of the cube
+    ///   or the index is past its end
+    ///
+    pub fn slice_at(self, axis: usize, index: usize) -> Result<FitsCube<T>, FitsRotateError> {
+        let naxis = self.data.ndim();
+        if axis >= naxis {
+            return Err(FitsRotateError::Message(format!(
+                "Axis {} is not an axis of a cube with {} axes",
+                axis, naxis
+            )));
+        }
+        let length = self.data.len_of(Axis(axis));
+        if index >= length {
+            return Err(FitsRotateError::Message(format!(
+                "Plane {} is past the end of axis {}, which has {} planes",
+                index, axis, length
+            )));
+        }
+        let fits_axis = naxis - axis;
+        let header = header_without_axis(&self.header, fits_axis, naxis);
+        Ok(FitsCube {
+            data: self.data.index_axis(Axis(axis), index).to_owned(),
+            header,
+        })
+    }
+
+    /// Blank the pixels excluded by a mask
+    ///
+    /// Pixels where the mask is `false` are set to NaN. The mask must have
+    /// the same number of axes as the cube, and each of its axes must either
+    /// match the cube or have length 1, in which case it is broadcast along
+    /// that axis (e.g. a single channel mask applied to every channel).
+    ///
+    /// # Arguments
+    ///
+    /// * `mask` - The mask, `true` for pixels to keep
+    ///
+    /// # Returns
+    ///
+    /// * `Result<usize, FitsRotateError>` - The number of masked pixels
+    ///
+    pub fn apply_mask(&mut self, mask: &ArrayD<bool>) -> Result<usize, FitsRotateError> {
+        let compatible = mask.ndim() == self.data.ndim()
+            && mask
+                .shape()
+                .iter()
+                .zip(self.data.shape())
+                .all(|(mask_len, len)| *mask_len == 1 || mask_len == len);
+        let broadcast = match mask.broadcast(self.data.shape()) {
+            Some(broadcast) if compatible => broadcast,
+            _ => {
+                return Err(FitsRotateError::Message(format!(
+                    "Mask shape {:?} cannot be broadcast to the cube shape {:?}",
+                    mask.shape(),
+                    self.data.shape()
+                )))
+            }
+        };
+        let blank = T::from(f32::NAN);
+        let mut n_masked = 0;
+        Zip::from(&mut self.data).and(&broadcast).for_each(|pixel, keep| {
+            if !keep {
+                *pixel = blank;
+                n_masked += 1;
+            }
+        });
+        Ok(n_masked)
+    }
+
+    /// Copy a cube into a tiled layout, so that each tile is contiguous
+    ///
+    /// The tiled array has twice as many axes as the cube: the first half
+    /// index the tile and the second half the pixel within the tile, so
+    /// pixel `idx` of the cube is at `[idx / tile, idx % tile]` (axis by
+    /// axis). Each tile is then a contiguous block of memory that fits in
+    /// the cache, which is the layout the chunk-based streaming path reads
+    /// and permutes. Tiles that run past the end of an axis are padded with
+    /// NaN.
+    ///
+    /// # Arguments
+    ///
+    /// * `cube` - The cube, in array order
+    /// * `target_tile_shape` - The shape of each tile, in array order
+    ///
+    /// # Returns
+    ///
+    /// * `ArrayD<T>` - The tiled cube
+    ///
+    /// # Panics
+    ///
+    /// If the tile shape does not have one non-zero length per axis of the cube.
+    ///
+    pub fn rechunk(cube: ArrayD<T>, target_tile_shape: &[usize]) -> ArrayD<T> {
+        let naxis = cube.ndim();
+        assert!(
+            target_tile_shape.len() == naxis && target_tile_shape.iter().all(|length| *length > 0),
+            "Tile shape {:?} does not match a cube with {} axes",
+            target_tile_shape,
+            naxis
+        );
+        let blank = T::from(f32::NAN);
+        // Pad each axis to a whole number of tiles
+        let padded_shape: Vec<usize> = cube
+            .shape()
+            .iter()
+            .zip(target_tile_shape)
+            .map(|(length, tile)| length.div_ceil(*tile) * tile)
+            .collect();
+        let padded = if padded_shape == cube.shape() {
+            cube.as_standard_layout().into_owned()
+        } else {
+            let mut padded = ArrayD::from_elem(IxDyn(&padded_shape), blank);
+            padded
+                .slice_each_axis_mut(|axis| Slice::from(0..cube.shape()[axis.axis.index()]))
+                .assign(&cube);
+            padded
+        };
+        // Split each axis into (tile, pixel within the tile), then move the tile axes first
+        let split_shape: Vec<usize> = padded_shape
+            .iter()
+            .zip(target_tile_shape)
+            .flat_map(|(length, tile)| [length / tile, *tile])
+            .collect();
+        let split = padded
+            .into_shape_with_order(IxDyn(&split_shape))
+            .expect("a standard layout array can be reshaped");
+        let order: Vec<usize> = (0..naxis).map(|axis| 2 * axis).chain((0..naxis).map(|axis| 2 * axis + 1)).collect();
+        Self::permute_inplace(split, &order)
+    }
+
+    /// Permute the axes of a cube, with the data copied into the new order
+    ///
+    /// `permuted_axes` on its own only swaps the strides, so the raw data
+    /// of the result (e.g. from `into_raw_vec`) is still in the old order.
+    /// Here the permuted cube is copied into standard (C-contiguous)
+    /// layout, unless the permutation leaves it in standard layout already.
+    ///
+    /// # Arguments
+    ///
+    /// * `cube` - The cube, in array order
+    /// * `mode` - The (array, 0-based) axis of `cube` that becomes each axis of the result
+    ///
+    /// # Returns
+    ///
+    /// * `ArrayD<T>` - The permuted cube, in standard layout
+    ///
+    /// # Panics
+    ///
+    /// If `mode` is not a permutation of the axes of the cube.
+    ///
+    pub fn permute_inplace(cube: ArrayD<T>, mode: &[usize]) -> ArrayD<T> {
+        let permuted = cube.permuted_axes(IxDyn(mode));
+        if permuted.is_standard_layout() {
+            permuted
+        } else {
+            permuted.as_standard_layout().into_owned()
+        }
+    }
+
+    /// Permute the axes of a cube through a tiled copy
+    ///
+    /// The cube is copied into tiles with `rechunk`, the tiles and the
+    /// pixels within them are permuted alike, and the tiles are put back
+    /// together. Each tile is read from and written to a contiguous block
+    /// of memory, rather than striding across the whole cube as
+    /// `permute_inplace` does, so large transposes stay in the cache.
+    ///
+    /// # Arguments
+    ///
+    /// * `cube` - The cube, in array order
+    /// * `mode` - The (array, 0-based) axis of `cube` that becomes each axis of the result
+    /// * `target_tile_shape` - The shape of each tile, in array order (of `cube`)
+    ///
+    /// # Returns
+    ///
+    /// * `ArrayD<T>` - The permuted cube, in standard layout, the same as from `permute_inplace`
+    ///
+    /// # Panics
+    ///
+    /// As `rechunk` and `permute_inplace`.
+    ///
+    pub fn permute_tiled(cube: ArrayD<T>, mode: &[usize], target_tile_shape: &[usize]) -> ArrayD<T> {
+        let naxis = cube.ndim();
+        let shape: Vec<usize> = mode.iter().map(|axis| cube.shape()[*axis]).collect();
+        let tiled = Self::rechunk(cube, target_tile_shape);
+        // Put the pixel axis of each tile axis next to it, in the new order
+        let interleaved: Vec<usize> = mode.iter().flat_map(|axis| [*axis, naxis + axis]).collect();
+        let interleaved = Self::permute_inplace(tiled, &interleaved);
+        let padded_shape: Vec<usize> = interleaved
+            .shape()
+            .chunks(2)
+            .map(|lengths| lengths[0] * lengths[1])
+            .collect();
+        let padded = interleaved
+            .into_shape_with_order(IxDyn(&padded_shape))
+            .expect("a standard layout array can be reshaped");
+        // Drop the padding of the tiles that ran past the end of an axis
+        let permuted = padded.slice_each_axis(|axis| Slice::from(0..shape[axis.axis.index()]));
+        if permuted.is_standard_layout() {
+            permuted.to_owned()
+        } else {
+            permuted.as_standard_layout().into_owned()
+        }
+    }
+
+    /// Blank the pixels outside a closed range of values
+    ///
+    /// Pixels below `min` or above `max` are set to NaN. Pixels that are
+    /// already NaN are left alone and not counted.
+    ///
+    /// # Arguments
+    ///
+    /// * `min` - The smallest value to keep, if any
+    /// * `max` - The largest value to keep, if any
+    /// * `plane_axis` - The (array) axis to count the blanked pixels along
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<usize>` - The number of pixels blanked in each plane along `plane_axis`
+    ///
+    pub fn apply_threshold(&mut self, min: Option<f64>, max: Option<f64>, plane_axis: usize) -> Vec<usize>
+    where
+        T: Into<f64>,
+    {
+        let blank = T::from(f32::NAN);
+        self.data
+            .axis_iter_mut(Axis(plane_axis))
+            .map(|mut plane| {
+                let mut n_clipped = 0;
+                plane.map_inplace(|pixel| {
+                    let value: f64 = (*pixel).into();
+                    if min.is_some_and(|min| value < min) || max.is_some_and(|max| value > max) {
+                        *pixel = blank;
+                        n_clipped += 1;
+                    }
+                });
+                n_clipped
+            })
+            .collect()
+    }
+}
+
+/// The header of a cube with one axis removed
+///
+/// # Arguments
+///
+/// * `header` - The header of the cube
+/// * `fits_axis` - The (FITS, 1-based) axis to remove
+/// * `naxis` - The number of axes of the cube
+///
+/// # Returns
+///
+/// * `FitsHeader` - The header, with the cards of the later axes renumbered
+///
+fn header_without_axis(header: &FitsHeader, fits_axis: usize, naxis: usize) -> FitsHeader {
+    let renumber = |axis: usize| if axis > fits_axis { axis - 1 } else { axis };
+    let cards = header
+        .cards
+        .iter()
+        .filter_map(|card| {
+            if card.is_commentary() {
+                return Some(card.clone());
+            }
+            let keyword = card.keyword();
+            if keyword == "NAXIS" {
+                let comment = card.record.get(30..).unwrap_or("");
+                return Some(HeaderCard::new(&format!("{:<8}= {:>20}{}", keyword, naxis - 1, comment)));
+            }
+            let axes = match keyword.strip_prefix("NAXIS").and_then(|axis| axis.parse::<usize>().ok()) {
+                Some(axis) => vec![axis],
+                None => match keyword_axes(&keyword) {
+                    Some(axes) => axes,
+                    None => return Some(card.clone()),
+                },
+            };
+            if axes.contains(&fits_axis) {
+                return None;
+            }
+            let stub = keyword.trim_end_matches(|c: char| c.is_ascii_digit() || c == '_');
+            let renumbered = match axes.as_slice() {
+                [axis] => format!("{}{}", stub, renumber(*axis)),
+                [i, j] => format!("{}{}_{}", stub, renumber(*i), renumber(*j)),
+                _ => return Some(card.clone()),
+            };
+            Some(renumbered_card(card, &renumbered))
+        })
+        .collect();
+    FitsHeader { cards }
+}
+
+impl<T: FitsPixel> FitsCube<T> {
+    /// Smooth the cube with a rolling mean along an axis
+    ///
+    /// Each pixel becomes the mean of the finite pixels in a window of
+    /// `window` pixels centred on it (with one more pixel after it than
+    /// before for an even window), so blank (NaN) pixels and the ends of
+    /// the axis do not pull the mean towards zero. A window with no finite
+    /// pixels is blank. Each lane along the axis is smoothed with a running
+    /// sum, so the cost does not depend on the window. A HISTORY card
+    /// recording the smoothing is appended to the header.
+    ///
+    /// # Arguments
+    ///
+    /// * `axis` - The (array) axis to smooth along
+    /// * `window` - The number of pixels to average
+    ///
+    /// # Returns
+    ///
+    /// * `FitsCube<T>` - The smoothed cube
+    ///
+    /// # Panics
+    ///
+    /// If the axis is not an axis of the cube, or the window is empty.
+    ///
+    pub(crate) fn rolling_mean(mut self, axis: usize, window: usize) -> FitsCube<T> {
+        let naxis = self.data.ndim();
+        assert!(axis < naxis, "Axis {} is not an axis of a cube with {} axes", axis, naxis);
+        assert!(window > 0, "The window of a rolling mean must have at least one pixel");
+        let before = (window - 1) / 2;
+        let after = window / 2;
+        let mut values: Vec<f64> = Vec::with_capacity(self.data.len_of(Axis(axis)));
+        for mut lane in self.data.lanes_mut(Axis(axis)) {
+            values.clear();
+            values.extend(lane.iter().map(|value| (*value).into()));
+            let (mut sum, mut count) = (0.0, 0usize);
+            for value in values.iter().take(after + 1).filter(|value| value.is_finite()) {
+                sum += value;
+                count += 1;
+            }
+            for (idx, out) in lane.iter_mut().enumerate() {
+                *out = T::from_f64(if count > 0 { sum / count as f64 } else { f64::NAN });
+                // Slide the window on by one pixel
+                if let Some(value) = values.get(idx + after + 1).filter(|value| value.is_finite()) {
+                    sum += value;
+                    count += 1;
+                }
+                if let Some(value) = idx.checked_sub(before).map(|first| values[first]).filter(|value| value.is_finite()) {
+                    sum -= value;
+                    count -= 1;
+                }
+            }
+        }
+        self.header.cards.push(HeaderCard::new(&format!(
+            "HISTORY {}",
+            rolling_mean_history(naxis - axis, window)
+        )));
+        self
+    }
+}
+
+/// The HISTORY record of a rolling mean
+///
+/// # Arguments
+///
+/// * `fits_axis` - The (FITS, 1-based) axis smoothed along
+/// * `window` - The number of pixels averaged
+///
+pub fn rolling_mean_history(fits_axis: usize, window: usize) -> String {
+    format!("fitsrotate_rs: rolling mean of {} pixels along axis {}", window, fits_axis)
+}
+
+impl<T: Copy + From<f32>> From<ArrayD<T>> for FitsCube<T> {
+    /// Wrap an array with an empty header
+    ///
+    /// The header has no cards at all, so the WCS of each axis has to be
+    /// added before the cube is a valid FITS file.
+    fn from(data: ArrayD<T>) -> Self {
+        FitsCube::new(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::arr2;
+
+    #[test]
+    fn mask_blanks_excluded_pixels() {
+        let mut cube = FitsCube::new(arr2(&[[1.0f32, 2.0, 3.0], [4.0, 5.0, 6.0]]).into_dyn());
+        let mask = arr2(&[[true, false, true], [false, true, true]]).into_dyn();
+        assert_eq!(cube.apply_mask(&mask).unwrap(), 2);
+        let blanked: Vec<bool> = cube.data.iter().map(|value| value.is_nan()).collect();
+        assert_eq!(blanked, [false, true, false, true, false, false]);
+        assert_eq!(cube.data[[1, 2]], 6.0);
+    }
+
+    #[test]
+    fn mask_is_broadcast_along_length_1_axes() {
+        let mut cube = FitsCube::new(ArrayD::<f32>::ones(IxDyn(&[4, 2, 3])));
+        // One channel mask, applied to every channel
+        let mask = arr2(&[[true, true, false], [true, true, true]])
+            .into_shape_with_order((1, 2, 3))
+            .unwrap();
+        assert_eq!(cube.apply_mask(&mask.into_dyn()).unwrap(), 4);
+        assert!(cube.data.index_axis(Axis(0), 3)[[0, 2]].is_nan());
+    }
+
+    #[test]
+    fn incompatible_masks_are_errors() {
+        let mut cube = FitsCube::new(ArrayD::<f32>::ones(IxDyn(&[4, 2, 3])));
+        // Wrong length, wrong number of axes, and a length-1 cube axis with a longer mask axis
+        for shape in [vec![4, 2, 2], vec![2, 3], vec![4, 2, 3, 1]] {
+            let mask = ArrayD::from_elem(IxDyn(&shape), false);
+            assert!(cube.apply_mask(&mask).is_err(), "{:?}", shape);
+        }
+        let mut plane = FitsCube::new(ArrayD::<f32>::ones(IxDyn(&[1, 3])));
+        assert!(plane.apply_mask(&ArrayD::from_elem(IxDyn(&[2, 3]), false)).is_err());
+        assert!(cube.data.iter().all(|value| *value == 1.0));
+    }
+
+    #[test]
+    fn rechunk_makes_each_tile_contiguous() {
+        let cube = ArrayD::from_shape_fn(IxDyn(&[3, 5]), |idx| (10 * idx[0] + idx[1]) as f32);
+        let tiled = FitsCube::rechunk(cube, &[2, 2]);
+        assert_eq!(tiled.shape(), [2, 3, 2, 2]);
+        // Tile (1, 2) holds row 2 and column 4, padded with NaN
+        assert_eq!(tiled[[1, 2, 0, 0]], 24.0);
+        assert!(tiled[[1, 2, 0, 1]].is_nan() && tiled[[1, 2, 1, 0]].is_nan());
+        let first: Vec<f32> = tiled.iter().take(4).copied().collect();
+        assert_eq!(first, [0.0, 1.0, 10.0, 11.0]);
+    }
+
+    #[test]
+    fn permute_inplace_moves_each_value_to_its_permuted_index() {
+        // Every axis has its own length, so a wrong permutation cannot give the right shape
+        let cube = ArrayD::from_shape_fn(IxDyn(&[2, 3, 4]), |idx| (100 * idx[0] + 10 * idx[1] + idx[2]) as f32);
+        // A mode that is not its own inverse, so the inverse permutation would fail
+        let mode = [1, 2, 0];
+        let permuted = FitsCube::permute_inplace(cube.clone(), &mode);
+        assert_eq!(permuted.shape(), [3, 4, 2]);
+        assert!(permuted.is_standard_layout());
+        for ((i, j, k), value) in permuted.clone().into_dimensionality::<ndarray::Ix3>().unwrap().indexed_iter() {
+            // Axis n of the result is axis mode[n] of the cube
+            assert_eq!(*value, cube[[k, i, j]], "{:?}", (i, j, k));
+        }
+        // The raw data is in the new order, as it is written
+        assert_eq!(&permuted.into_raw_vec_and_offset().0[..4], &[0.0, 100.0, 1.0, 101.0]);
+        // The identity leaves the cube as it is
+        assert_eq!(FitsCube::permute_inplace(cube.clone(), &[0, 1, 2]), cube);
+    }
+
+    #[test]
+    fn permute_tiled_matches_permute_inplace() {
+        let cube = ArrayD::from_shape_fn(IxDyn(&[2, 3, 4]), |idx| (100 * idx[0] + 10 * idx[1] + idx[2]) as f32);
+        for mode in [[2, 0, 1], [1, 2, 0], [0, 2, 1], [0, 1, 2]] {
+            // Tiles that divide the axes, that do not, and that are larger than them
+            for tile in [[1, 3, 2], [2, 2, 3], [4, 4, 4]] {
+                let tiled = FitsCube::permute_tiled(cube.clone(), &mode, &tile);
+                assert!(tiled.is_standard_layout());
+                assert_eq!(
+                    tiled,
+                    FitsCube::permute_inplace(cube.clone(), &mode),
+                    "{:?} {:?}",
+                    mode,
+                    tile
+                );
+            }
+        }
+    }
+
+    /// Time an 8192 x 8192 transpose with and without rechunking
+    ///
+    /// Run with `cargo test --release -- --ignored --nocapture transpose_8192`.
+    #[test]
+    #[ignore]
+    fn transpose_8192_with_and_without_rechunk() {
+        let n = 8192;
+        let cube = ArrayD::from_shape_fn(IxDyn(&[n, n]), |idx| (idx[0] * n + idx[1]) as f32);
+
+        let start = std::time::Instant::now();
+        let direct = FitsCube::permute_inplace(cube.clone(), &[1, 0]);
+        let direct_time = start.elapsed();
+        for tile in [32, 64, 256] {
+            let start = std::time::Instant::now();
+            let tiled = FitsCube::permute_tiled(cube.clone(), &[1, 0], &[tile, tile]);
+            let tiled_time = start.elapsed();
+            assert_eq!(tiled, direct);
+            println!(
+                "{0}x{0} transpose: {1:.3?} direct, {2:.3?} through {3}x{3} tiles",
+                n, direct_time, tiled_time, tile
+            );
+        }
+    }
+
+    #[test]
+    fn threshold_blanks_planted_outliers_and_counts_them_per_plane() {
+        let mut data = ArrayD::<f64>::zeros(IxDyn(&[3, 2, 2]));
+        data[[0, 0, 1]] = 1e4;
+        data[[2, 0, 0]] = -2e3;
+        data[[2, 1, 1]] = 5e3;
+        // On the edges of the closed range, so kept
+        data[[1, 0, 0]] = 1e3;
+        data[[1, 1, 1]] = -1e3;
+        // Already blank, so neither changed nor counted
+        data[[1, 0, 1]] = f64::NAN;
+        let mut cube = FitsCube::new(data);
+        assert_eq!(cube.apply_threshold(Some(-1e3), Some(1e3), 0), [1, 0, 2]);
+        let blanked: Vec<bool> = cube.data.iter().map(|value| value.is_nan()).collect();
+        assert_eq!(
+            blanked,
+            [false, true, false, false, false, true, false, false, true, false, false, true]
+        );
+        assert_eq!((cube.data[[1, 0, 0]], cube.data[[1, 1, 1]]), (1e3, -1e3));
+    }
+
+    #[test]
+    fn threshold_counts_along_any_axis_with_open_ends() {
+        let data = ArrayD::from_shape_fn(IxDyn(&[2, 3]), |idx| (10 * idx[0] + idx[1]) as f32);
+        // Values 0, 1, 2, 10, 11, 12: only a minimum, counted along the last axis
+        let mut cube = FitsCube::new(data.clone());
+        assert_eq!(cube.apply_threshold(Some(2.0), None, 1), [1, 1, 0]);
+        // Only a maximum, counted along the first axis
+        let mut cube = FitsCube::new(data);
+        assert_eq!(cube.apply_threshold(None, Some(10.5), 0), [0, 2]);
+    }
+
+    #[test]
+    fn arrays_convert_to_and_from_cubes() {
+        let data = ArrayD::from_shape_fn(IxDyn(&[2, 3]), |idx| (10 * idx[0] + idx[1]) as f32);
+        let header = FitsHeader {
+            cards: vec![HeaderCard::new("BUNIT   = 'Jy/beam '")],
+        };
+        let cube = FitsCube::from_array(data.clone(), header.clone());
+        assert_eq!(cube.header.cards, header.cards);
+        assert_eq!(cube.into_array(), data);
+
+        // From gives an empty header
+        let cube: FitsCube<f32> = data.clone().into();
+        assert!(cube.header.cards.is_empty());
+        assert_eq!(cube.into_array(), data);
+    }
+
+    /// A card with a value, as CFITSIO writes it
+    fn value_card(keyword: &str, value: &str) -> HeaderCard {
+        HeaderCard::new(&format!("{:<8}= {:>20} / {}", keyword, value, "comment"))
+    }
+
+    /// The header of a 3-D cube with a full PCi_j matrix whose entries name their axes (PCi_j = ij)
+    fn cube_header() -> FitsHeader {
+        let mut cards = vec![
+            value_card("NAXIS", "3"),
+            value_card("NAXIS1", "4"),
+            value_card("NAXIS2", "3"),
+            value_card("NAXIS3", "2"),
+            HeaderCard::new("COMMENT the world coordinates"),
+        ];
+        for axis in 1..=3 {
+            cards.push(value_card(&format!("CTYPE{}", axis), &format!("'AXIS{}'", axis)));
+            cards.push(value_card(&format!("CRVAL{}", axis), &format!("{}.0", axis * 10)));
+        }
+        for i in 1..=3 {
+            for j in 1..=3 {
+                cards.push(value_card(&format!("PC{}_{}", i, j), &format!("{}{}", i, j)));
+            }
+        }
+        cards.push(value_card("BUNIT", "'Jy/beam'"));
+        FitsHeader { cards }
+    }
+
+    #[test]
+    fn slice_at_drops_and_renumbers_the_cards_of_each_axis() {
+        let data = ArrayD::from_shape_fn(IxDyn(&[2, 3, 4]), |idx| (100 * idx[0] + 10 * idx[1] + idx[2]) as f32);
+        for array_axis in 0..3 {
+            let fits_axis = 3 - array_axis;
+            // The input axis of each axis of the plane
+            let kept: Vec<usize> = (1..=3).filter(|axis| *axis != fits_axis).collect();
+            let index = data.shape()[array_axis] - 1;
+            let cube = FitsCube::from_array(data.clone(), cube_header());
+            let plane = cube.slice_at(array_axis, index).unwrap();
+            assert_eq!(plane.data, data.index_axis(Axis(array_axis), index));
+
+            // Input axis n has length 5 - n
+            let mut expected = vec![
+                ("NAXIS".to_string(), Some("2".to_string())),
+                ("NAXIS1".to_string(), Some((5 - kept[0]).to_string())),
+                ("NAXIS2".to_string(), Some((5 - kept[1]).to_string())),
+                ("COMMENT".to_string(), None),
+            ];
+            for (axis, old) in kept.iter().enumerate() {
+                let axis = axis + 1;
+                expected.push((format!("CTYPE{}", axis), Some(format!("AXIS{}", old))));
+                expected.push((format!("CRVAL{}", axis), Some(format!("{}.0", old * 10))));
+            }
+            for i in 1..=3 {
+                for j in 1..=3 {
+                    if let (Some(new_i), Some(new_j)) = (
+                        kept.iter().position(|axis| *axis == i),
+                        kept.iter().position(|axis| *axis == j),
+                    ) {
+                        expected.push((format!("PC{}_{}", new_i + 1, new_j + 1), Some(format!("{}{}", i, j))));
+                    }
+                }
+            }
+            expected.push(("BUNIT".to_string(), Some("Jy/beam".to_string())));
+
+            let cards: Vec<(String, Option<String>)> = plane
+                .header
+                .cards
+                .iter()
+                .map(|card| (card.keyword(), card.value().filter(|_| !card.is_commentary())))
+                .collect();
+            assert_eq!(cards, expected, "axis {}", fits_axis);
+            // The comments of the cards are kept
+            assert!(plane.header.cards[0].record.ends_with("/ comment"));
+        }
+    }
+
+    #[test]
+    fn slice_at_refuses_axes_and_planes_outside_the_cube() {
+        let cube = FitsCube::new(ArrayD::<f32>::zeros(IxDyn(&[2, 3, 4])));
+        assert!(cube.clone().slice_at(3, 0).is_err());
+        assert!(cube.clone().slice_at(1, 3).is_err());
+        assert_eq!(cube.slice_at(1, 2).unwrap().data.shape(), [2, 4]);
+    }
+
+    #[test]
+    fn rolling_mean_spreads_a_delta_spectrum_over_the_window() {
+        // A delta in channel 4 of one spectrum, with the spectral axis first in array order
+        let mut data = ArrayD::<f64>::zeros(IxDyn(&[9, 2, 3]));
+        data[[4, 1, 2]] = 1.0;
+        // The channels of an odd window are centred, and an even window has one more after the pixel than before
+        for (window, spread) in [(1, 4..5), (3, 3..6), (4, 2..6), (5, 2..7)] {
+            let smoothed = FitsCube::new(data.clone()).rolling_mean(0, window);
+            let spectrum: Vec<f64> = smoothed.data.slice(ndarray::s![.., 1, 2]).to_vec();
+            let expected: Vec<f64> = (0..9)
+                .map(|channel| {
+                    if spread.contains(&channel) {
+                        1.0 / window as f64
+                    } else {
+                        0.0
+                    }
+                })
+                .collect();
+            assert_eq!(spectrum, expected, "window {}", window);
+            assert_eq!(smoothed.data.iter().filter(|value| **value != 0.0).count(), window);
+            assert_eq!(
+                smoothed.header.cards.last().unwrap().record,
+                format!("HISTORY {}", rolling_mean_history(3, window))
+            );
+        }
+    }
+
+    #[test]
+    fn rolling_mean_leaves_blank_pixels_out_of_the_mean() {
+        let spectrum = [f32::NAN, 3.0, f32::NAN, f32::NAN, f32::NAN, 6.0];
+        let data = ArrayD::from_shape_vec(IxDyn(&[1, 6]), spectrum.to_vec()).unwrap();
+        let smoothed = FitsCube::new(data).rolling_mean(1, 3).into_array();
+        // The ends of the axis and the blank pixels shrink the window rather than count as zero
+        let expected = [3.0, 3.0, 3.0, f32::NAN, 6.0, 6.0];
+        for (value, expected) in smoothed.iter().zip(expected) {
+            assert!(
+                value == &expected || (value.is_nan() && expected.is_nan()),
+                "{:?}",
+                smoothed
+            );
+        }
+    }
+}
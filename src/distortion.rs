@@ -0,0 +1,139 @@
+//! Distortion corrections of WCS Paper IV (Calabretta et al.)
+//!
+//! Prior and sequent distortions (`CPDISja`, `CQDISia`, `CPERRja`,
+//! `CQERRia`) and the detector to image corrections written by HST
+//! (`D2IMDISj`, `D2IMERRj`) are indexed by axis number, as are their
+//! record-valued parameters (`DPja`, `DQia`, `D2IMj`). The `AXIS.k` fields of
+//! those records hold axis numbers too, e.g. `DP1 = 'AXIS.1: 2'` says that
+//! the first argument of the distortion on axis 1 is axis 2, so both the
+//! keyword and the value have to be renumbered when the axes are reordered.
+//! Lookup tables live in `WCSDVARR` (and `D2IMARR`) image extensions.
+
+use crate::header::{FitsHeader, HeaderCard, CARD_LENGTH};
+use crate::permutation::output_axis;
+use fitsio::errors::Error;
+
+/// Stubs of the distortion keywords followed by an axis number, longest
+/// first so that `D2IMDIS1` is not taken for a `D2IM` card
+const DISTORTION_STUBS: [&str; 9] = [
+    "D2IMDIS", "D2IMERR", "D2IM", "CPDIS", "CQDIS", "CPERR", "CQERR", "DP", "DQ",
+];
+
+/// Stubs of the record-valued distortion keywords
+const RECORD_STUBS: [&str; 3] = ["D2IM", "DP", "DQ"];
+
+/// Names of the image extensions holding distortion lookup tables
+pub const DISTORTION_EXTNAMES: [&str; 2] = ["WCSDVARR", "D2IMARR"];
+
+/// Split a distortion keyword into its stub, axis and alternate WCS letter
+///
+/// # Arguments
+///
+/// * `keyword` - The keyword, e.g. `CPDIS1` or `DP2A`
+///
+/// # Returns
+///
+/// * `Option<(&str, usize, &str)>` - The stub, (FITS, 1-based) axis and alternate letter (possibly empty), or `None` if the keyword is not a distortion keyword
+///
+pub fn distortion_axis(keyword: &str) -> Option<(&'static str, usize, &str)> {
+    DISTORTION_STUBS.iter().find_map(|stub| {
+        let rest = keyword.strip_prefix(stub)?;
+        let n_digits = rest.chars().take_while(|c| c.is_ascii_digit()).count();
+        let (digits, alternate) = rest.split_at(n_digits);
+        let valid_alternate =
+            alternate.is_empty() || (alternate.len() == 1 && alternate.chars().all(|c| c.is_ascii_uppercase()));
+        if digits.is_empty() || digits.starts_with('0') || !valid_alternate {
+            return None;
+        }
+        Some((*stub, digits.parse().ok()?, alternate))
+    })
+}
+
+/// Check if a card is a record-valued distortion card (e.g. `DP1 = 'AXIS.1: 1'`)
+///
+/// Record-valued keywords are repeated, once per field, so these cards
+/// must not be treated as duplicates of each other.
+pub fn is_distortion_record(card: &HeaderCard) -> bool {
+    matches!(distortion_axis(&card.keyword()), Some((stub, _, _)) if RECORD_STUBS.contains(&stub))
+        && card.value().is_some_and(|value| value.contains(':'))
+}
+
+/// Renumber the axes of a distortion card
+///
+/// # Arguments
+///
+/// * `card` - The card
+/// * `mode` - The new ordering of the axes
+///
+/// # Returns
+///
+/// * `Result<Option<HeaderCard>, Error>` - The renumbered card, or `None` if the card is not a distortion card of an axis in the mode
+///
+pub fn permute_distortion_card(card: &HeaderCard, mode: &[usize]) -> Result<Option<HeaderCard>, Error> {
+    let keyword = card.keyword();
+    let (stub, axis, alternate) = match distortion_axis(&keyword) {
+        Some((stub, axis, alternate)) if mode.contains(&axis) => (stub, axis, alternate),
+        _ => return Ok(None),
+    };
+    let new_keyword = format!("{}{}{}", stub, output_axis(axis, mode), alternate);
+    if new_keyword.len() > 8 {
+        return Err(Error::Message(format!(
+            "Cannot renumber {}: {} is longer than 8 characters",
+            keyword, new_keyword
+        )));
+    }
+    let mut record = format!("{:<8}{}", new_keyword, &card.record[8.min(card.record.len())..]);
+
+    // The AXIS.k field of a record holds the axis that is the k-th argument
+    if is_distortion_record(card) {
+        let value = card.value().unwrap_or_default();
+        if let Some((field, argument)) = value.split_once(':') {
+            let argument = argument.trim();
+            let argument_axis = argument.parse::<usize>().ok().or_else(|| {
+                argument
+                    .parse::<f64>()
+                    .ok()
+                    .filter(|v| v.fract() == 0.0 && *v >= 1.0)
+                    .map(|v| v as usize)
+            });
+            if let Some(argument_axis) = argument_axis.filter(|_| field.trim().starts_with("AXIS.")) {
+                if mode.contains(&argument_axis) {
+                    let start = record[10..].find('\'').map(|idx| idx + 10);
+                    let end = start.and_then(|start| record[start + 1..].find('\'').map(|idx| idx + start + 1));
+                    if let (Some(start), Some(end)) = (start, end) {
+                        let new_value = if argument.contains('.') {
+                            format!("{}: {:.1}", field.trim(), output_axis(argument_axis, mode) as f64)
+                        } else {
+                            format!("{}: {}", field.trim(), output_axis(argument_axis, mode))
+                        };
+                        record = format!("{}{}{}", &record[..start + 1], new_value, &record[end..]);
+                    }
+                }
+            }
+        }
+    }
+    let mut record = format!("{:<width$}", record.trim_end(), width = CARD_LENGTH);
+    record.truncate(CARD_LENGTH);
+    Ok(Some(HeaderCard::new(&record)))
+}
+
+/// Renumber the axes of all distortion cards of a header
+///
+/// # Arguments
+///
+/// * `header` - The header of the input
+/// * `mode` - The new ordering of the axes
+///
+/// # Returns
+///
+/// * `Result<FitsHeader, Error>` - The renumbered distortion cards, in the order of the input
+///
+pub fn permute_distortion_cards(header: &FitsHeader, mode: &[usize]) -> Result<FitsHeader, Error> {
+    let mut cards: Vec<HeaderCard> = Vec::new();
+    for card in &header.cards {
+        if let Some(card) = permute_distortion_card(card, mode)? {
+            cards.push(card);
+        }
+    }
+    Ok(FitsHeader { cards })
+}
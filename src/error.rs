@@ -0,0 +1,333 @@
+use crate::axes::closest_match;
+use crate::format_mode;
+use fitsio::FitsFile;
+use std::fmt;
+
+/// Exit code when a file is locked by another process (EX_TEMPFAIL)
+pub const EXIT_LOCKED: i32 = 75;
+
+/// Exit code when the input does not meet an `--expect-*` assertion (EX_DATAERR)
+pub const EXIT_EXPECTATION: i32 = 65;
+
+/// Errors raised while rotating a FITS cube
+///
+/// The common failure classes carry enough context to render a suggestion
+/// of what to do next alongside the error message.
+#[derive(Debug)]
+pub enum FitsRotateError {
+    /// An error from fitsio/CFITSIO
+    Fits(fitsio::errors::Error),
+    /// The mode does not have one entry per axis
+    ModeLength {
+        mode: String,
+        length: usize,
+        naxis: usize,
+        ctypes: Vec<String>,
+    },
+    /// The output file exists and overwriting was not requested
+    OutputExists { filename: String },
+    /// An HDU could not be read
+    UnreadableHdu {
+        filename: String,
+        hdus: Vec<String>,
+        reason: String,
+    },
+    /// An axis name does not match any CTYPE of the file
+    UnknownAxis { name: String, ctypes: Vec<String> },
+    /// A file is locked by another process
+    Locked { filename: String, timeout: f64 },
+    /// A keyword needed for the rotated header is missing from the input
+    MissingKeyword { keyword: String, filename: String },
+    /// The input does not meet an `--expect-order` or `--expect-shape` assertion
+    Expectation {
+        filename: String,
+        option: String,
+        expected: Vec<String>,
+        found: Vec<String>,
+    },
+    /// Generic errors from simple strings
+    Message(String),
+}
+
+impl FitsRotateError {
+    /// Build an `UnreadableHdu` error, listing the HDUs that can be found
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The FITS file
+    /// * `error` - The error raised while reading the HDU
+    ///
+    /// # Returns
+    ///
+    /// * `FitsRotateError` - The error
+    ///
+    pub fn unreadable_hdu(filename: &str, error: fitsio::errors::Error) -> Self {
+        let mut hdus: Vec<String> = Vec::new();
+        if let Ok(mut fits_file) = FitsFile::open(filename) {
+            let n_hdus = fits_file.num_hdus().unwrap_or(0);
+            for idx in 0..n_hdus {
+                let name = fits_file
+                    .hdu(idx)
+                    .and_then(|hdu| hdu.name(&mut fits_file))
+                    .unwrap_or_default();
+                if name.is_empty() {
+                    hdus.push(format!("{} (unnamed)", idx));
+                } else {
+                    hdus.push(format!("{} ({})", idx, name));
+                }
+            }
+        }
+        FitsRotateError::UnreadableHdu {
+            filename: filename.to_string(),
+            hdus,
+            reason: error.to_string(),
+        }
+    }
+
+    /// Attach the CTYPEs of the file to errors that can make use of them
+    ///
+    /// # Arguments
+    ///
+    /// * `ctypes` - The CTYPEs, in FITS axis order
+    ///
+    /// # Returns
+    ///
+    /// * `FitsRotateError` - The error with the CTYPEs filled in
+    ///
+    pub fn with_ctypes(self, ctypes: Vec<String>) -> Self {
+        match self {
+            FitsRotateError::ModeLength { mode, length, naxis, .. } => {
+                FitsRotateError::ModeLength { mode, length, naxis, ctypes }
+            }
+            FitsRotateError::UnknownAxis { name, .. } => FitsRotateError::UnknownAxis { name, ctypes },
+            other => other,
+        }
+    }
+
+    /// The exit code to report the error with
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            FitsRotateError::Locked { .. } => EXIT_LOCKED,
+            FitsRotateError::Expectation { .. } => EXIT_EXPECTATION,
+            _ => 1,
+        }
+    }
+
+    /// A suggestion of how to fix the error, if there is one
+    pub fn suggestion(&self) -> Option<String> {
+        match self {
+            FitsRotateError::ModeLength { naxis, ctypes, .. } => {
+                let identity: Vec<usize> = (1..naxis + 1).collect();
+                let reversal: Vec<usize> = (1..naxis + 1).rev().collect();
+                let mut suggestion = format!(
+                    "the file has {} axes, so the mode needs {} {}, e.g. {} (identity) or {} (reversal)",
+                    naxis,
+                    naxis,
+                    if *naxis > 9 { "comma-separated axes" } else { "digits" },
+                    format_mode(&identity),
+                    format_mode(&reversal)
+                );
+                for (idx, ctype) in ctypes.iter().enumerate() {
+                    suggestion += &format!("\n        AXIS{}: {}", idx + 1, ctype);
+                }
+                Some(suggestion)
+            }
+            FitsRotateError::OutputExists { .. } => {
+                Some("pass --overwrite (or -o) to replace it".to_string())
+            }
+            FitsRotateError::UnreadableHdu { hdus, .. } => {
+                if hdus.is_empty() {
+                    Some("no HDUs could be found; is this a FITS file?".to_string())
+                } else {
+                    Some(format!("the file contains these HDUs: {}", hdus.join(", ")))
+                }
+            }
+            FitsRotateError::UnknownAxis { name, ctypes } => match closest_match(name, ctypes) {
+                Some(closest) => Some(format!("did you mean {}?", closest)),
+                None => Some(format!("the axes of the file are: {}", ctypes.join(", "))),
+            },
+            FitsRotateError::Locked { .. } => Some(
+                "wait for the other process to finish, raise --lock-timeout, or pass --no-lock \
+                 if locking is not supported on this filesystem"
+                    .to_string(),
+            ),
+            // There is no sensible default for a CTYPE
+            FitsRotateError::MissingKeyword { keyword, .. } if !keyword.starts_with("CTYPE") => {
+                Some("pass --fill-missing-wcs to write a default".to_string())
+            }
+            FitsRotateError::MissingKeyword { .. }
+            | FitsRotateError::Expectation { .. }
+            | FitsRotateError::Fits(_)
+            | FitsRotateError::Message(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for FitsRotateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FitsRotateError::Fits(e) => write!(f, "{}", e)?,
+            FitsRotateError::ModeLength { length, naxis, .. } => write!(
+                f,
+                "Mode length {} does not match number of axes in cube ({})",
+                length,
+                naxis
+            )?,
+            FitsRotateError::OutputExists { filename } => {
+                write!(f, "Output file {} already exists", filename)?
+            }
+            FitsRotateError::UnreadableHdu { filename, reason, .. } => {
+                write!(f, "Could not read HDU of {}: {}", filename, reason)?
+            }
+            FitsRotateError::UnknownAxis { name, .. } => write!(f, "Unknown axis {}", name)?,
+            FitsRotateError::Locked { filename, timeout } => write!(
+                f,
+                "{} is locked by another process (gave up after {} s)",
+                filename, timeout
+            )?,
+            FitsRotateError::MissingKeyword { keyword, filename } => {
+                write!(f, "{} is missing from the header of {}", keyword, filename)?
+            }
+            FitsRotateError::Expectation {
+                filename,
+                option,
+                expected,
+                found,
+            } => write!(
+                f,
+                "{} does not meet {}\n  expected: {}\n  found:    {}",
+                filename,
+                option,
+                expected.join(","),
+                found.join(",")
+            )?,
+            FitsRotateError::Message(message) => write!(f, "{}", message)?,
+        }
+        if let Some(suggestion) = self.suggestion() {
+            write!(f, "\n  help: {}", suggestion)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for FitsRotateError {}
+
+impl From<fitsio::errors::Error> for FitsRotateError {
+    fn from(error: fitsio::errors::Error) -> Self {
+        match error {
+            fitsio::errors::Error::ExistingFile(filename) => {
+                FitsRotateError::OutputExists { filename }
+            }
+            fitsio::errors::Error::Message(message) => FitsRotateError::Message(message),
+            other => FitsRotateError::Fits(other),
+        }
+    }
+}
+
+impl From<std::io::Error> for FitsRotateError {
+    fn from(error: std::io::Error) -> Self {
+        FitsRotateError::Fits(error.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    #[test]
+    fn mode_length_message() {
+        let error = FitsRotateError::ModeLength {
+            mode: "4123".to_string(),
+            length: 4,
+            naxis: 3,
+            ctypes: strings(&["RA---SIN", "DEC--SIN", "FREQ"]),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Mode length 4 does not match number of axes in cube (3)\n  \
+             help: the file has 3 axes, so the mode needs 3 digits, e.g. 123 (identity) or 321 (reversal)\n        \
+             AXIS1: RA---SIN\n        \
+             AXIS2: DEC--SIN\n        \
+             AXIS3: FREQ"
+        );
+    }
+
+    #[test]
+    fn mode_length_message_for_many_axes() {
+        let error = FitsRotateError::ModeLength {
+            mode: "123".to_string(),
+            length: 3,
+            naxis: 10,
+            ctypes: Vec::new(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Mode length 3 does not match number of axes in cube (10)\n  \
+             help: the file has 10 axes, so the mode needs 10 comma-separated axes, \
+             e.g. 1,2,3,4,5,6,7,8,9,10 (identity) or 10,9,8,7,6,5,4,3,2,1 (reversal)"
+        );
+    }
+
+    #[test]
+    fn output_exists_message() {
+        let error = FitsRotateError::from(fitsio::errors::Error::ExistingFile("cube.rot.fits".to_string()));
+        assert_eq!(
+            error.to_string(),
+            "Output file cube.rot.fits already exists\n  help: pass --overwrite (or -o) to replace it"
+        );
+    }
+
+    #[test]
+    fn unreadable_hdu_message() {
+        let error = FitsRotateError::UnreadableHdu {
+            filename: "cube.fits".to_string(),
+            hdus: strings(&["0 (unnamed)", "1 (BEAMS)"]),
+            reason: "not an image".to_string(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "Could not read HDU of cube.fits: not an image\n  \
+             help: the file contains these HDUs: 0 (unnamed), 1 (BEAMS)"
+        );
+        let reason = fitsio::errors::Error::Message("gone".to_string());
+        let error = FitsRotateError::unreadable_hdu("does-not-exist.fits", reason);
+        assert_eq!(
+            error.to_string(),
+            "Could not read HDU of does-not-exist.fits: Error: gone\n  \
+             help: no HDUs could be found; is this a FITS file?"
+        );
+    }
+
+    #[test]
+    fn unknown_axis_message() {
+        let ctypes = strings(&["RA---SIN", "DEC--SIN", "FREQ", "STOKES"]);
+        let error = FitsRotateError::UnknownAxis {
+            name: "FRQ".to_string(),
+            ctypes: Vec::new(),
+        }
+        .with_ctypes(ctypes.clone());
+        assert_eq!(error.to_string(), "Unknown axis FRQ\n  help: did you mean FREQ?");
+        let error = FitsRotateError::UnknownAxis {
+            name: "VELOCITY".to_string(),
+            ctypes,
+        };
+        assert_eq!(
+            error.to_string(),
+            "Unknown axis VELOCITY\n  help: the axes of the file are: RA---SIN, DEC--SIN, FREQ, STOKES"
+        );
+    }
+
+    #[test]
+    fn exit_codes() {
+        let locked = FitsRotateError::Locked {
+            filename: "cube.fits".to_string(),
+            timeout: 5.0,
+        };
+        assert_eq!(locked.exit_code(), EXIT_LOCKED);
+        assert_eq!(FitsRotateError::Message("failed".to_string()).exit_code(), 1);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::buildinfo::json_string;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Progress within a phase is logged at most this often, apart from its end
+const MIN_PROGRESS_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The event log, once `open` is called
+static LOG: Mutex<Option<EventLog>> = Mutex::new(None);
+
+/// The files started since the log was opened
+static FILES_STARTED: AtomicUsize = AtomicUsize::new(0);
+
+/// The files that failed since the log was opened
+static FILES_FAILED: AtomicUsize = AtomicUsize::new(0);
+
+/// A JSON-lines log of the events of a run, written with `--json-logs`
+struct EventLog {
+    /// Where the events are written
+    writer: Box<dyn Write + Send>,
+    /// When the log was opened
+    started: Instant,
+    /// When progress was last logged
+    last_progress: Option<Instant>,
+}
+
+/// Open the event log, which is written alongside the usual messages
+///
+/// # Arguments
+///
+/// * `path` - The file to write the events to, or `-` for stderr
+///
+/// # Returns
+///
+/// * `std::io::Result<()>` - An error if the file cannot be created
+///
+pub fn open(path: &str) -> std::io::Result<()> {
+    let writer: Box<dyn Write + Send> = if path == "-" {
+        Box::new(std::io::stderr())
+    } else {
+        Box::new(BufWriter::new(File::create(path)?))
+    };
+    *LOG.lock().unwrap_or_else(|e| e.into_inner()) = Some(EventLog {
+        writer,
+        started: Instant::now(),
+        last_progress: None,
+    });
+    Ok(())
+}
+
+/// Write an event as one line of JSON, if the log is open
+///
+/// Every event has the fields `event`, `time` (seconds since the Unix
+/// epoch) and `elapsed_s` (seconds since the log was opened), followed by
+/// `fields`, whose values are already JSON.
+///
+/// # Arguments
+///
+/// * `event` - The name of the event
+/// * `fields` - The other fields of the event
+///
+fn emit(event: &str, fields: &[(&str, String)]) {
+    let mut log = LOG.lock().unwrap_or_else(|e| e.into_inner());
+    let Some(log) = log.as_mut() else {
+        return;
+    };
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |time| time.as_secs_f64());
+    let mut line = format!(
+        "{{\"event\": {}, \"time\": {:.3}, \"elapsed_s\": {:.3}",
+        json_string(event),
+        time,
+        log.started.elapsed().as_secs_f64()
+    );
+    for (key, value) in fields {
+        line.push_str(&format!(", {}: {}", json_string(key), value));
+    }
+    line.push('}');
+    // The log must not stop a rotation, so a failed write is dropped
+    let _ = writeln!(log.writer, "{}", line);
+    let _ = log.writer.flush();
+}
+
+/// A list of strings as a JSON array
+fn json_strings(values: &[String]) -> String {
+    let values: Vec<String> = values.iter().map(|value| json_string(value)).collect();
+    format!("[{}]", values.join(", "))
+}
+
+/// Log the start of a run
+///
+/// # Arguments
+///
+/// * `arguments` - The command line
+///
+pub fn run_started(arguments: &[String]) {
+    emit(
+        "run_started",
+        &[
+            ("version", json_string(env!("CARGO_PKG_VERSION"))),
+            ("arguments", json_strings(arguments)),
+        ],
+    );
+}
+
+/// Log the start of the rotation of a file
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `modes` - The modes it is rotated with
+///
+pub fn file_started(filename: &str, modes: &[String]) {
+    FILES_STARTED.fetch_add(1, Ordering::Relaxed);
+    emit(
+        "file_started",
+        &[("file", json_string(filename)), ("modes", json_strings(modes))],
+    );
+}
+
+/// Log progress through a phase of the rotation of a file
+///
+/// Progress is logged at most once a second, but the end of a phase
+/// (`done == total`) is always logged.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `phase` - The phase, e.g. `read` or `rotate and write`
+/// * `done` - The steps of the phase done so far
+/// * `total` - The steps of the phase
+/// * `peak_bytes` - The peak memory of the phase, if counted
+///
+pub fn phase_progress(filename: &str, phase: &str, done: usize, total: usize, peak_bytes: Option<usize>) {
+    {
+        let mut log = LOG.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(log) = log.as_mut() else {
+            return;
+        };
+        let now = Instant::now();
+        if done < total && log.last_progress.is_some_and(|last| now - last < MIN_PROGRESS_INTERVAL) {
+            return;
+        }
+        log.last_progress = Some(now);
+    }
+    emit(
+        "phase_progress",
+        &[
+            ("file", json_string(filename)),
+            ("phase", json_string(phase)),
+            ("done", done.to_string()),
+            ("total", total.to_string()),
+            (
+                "peak_bytes",
+                peak_bytes.map_or("null".to_string(), |bytes| bytes.to_string()),
+            ),
+        ],
+    );
+}
+
+/// Log a warning, which is also printed as usual
+///
+/// # Arguments
+///
+/// * `message` - The warning, without colour
+///
+pub fn warning(message: &str) {
+    emit("warning", &[("message", json_string(message))]);
+}
+
+/// Log the end of the rotation of a file
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `result` - The outputs written, or the error the rotation failed with
+/// * `duration` - How long the rotation took
+///
+pub fn file_finished(filename: &str, result: Result<&[String], &str>, duration: Duration) {
+    let mut fields = vec![
+        ("file", json_string(filename)),
+        ("status", json_string(if result.is_ok() { "ok" } else { "failed" })),
+        ("duration_s", format!("{:.3}", duration.as_secs_f64())),
+    ];
+    match result {
+        Ok(outputs) => fields.push(("outputs", json_strings(outputs))),
+        Err(message) => {
+            FILES_FAILED.fetch_add(1, Ordering::Relaxed);
+            fields.push(("error", json_string(message)));
+        }
+    }
+    emit("file_finished", &fields);
+}
+
+/// Log the end of a run
+///
+/// # Arguments
+///
+/// * `error` - The error the run failed with, if any
+/// * `peak_bytes` - The peak memory of the run, if counted
+///
+pub fn run_finished(error: Option<&str>, peak_bytes: Option<usize>) {
+    let mut fields = vec![
+        ("status", json_string(if error.is_none() { "ok" } else { "failed" })),
+        ("files", FILES_STARTED.load(Ordering::Relaxed).to_string()),
+        ("failed", FILES_FAILED.load(Ordering::Relaxed).to_string()),
+        (
+            "peak_bytes",
+            peak_bytes.map_or("null".to_string(), |bytes| bytes.to_string()),
+        ),
+    ];
+    if let Some(error) = error {
+        fields.push(("error", json_string(error)));
+    }
+    emit("run_finished", &fields);
+}
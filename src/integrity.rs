@@ -0,0 +1,126 @@
+use crate::error::FitsRotateError;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+
+/// Size of a FITS block; headers and data units are padded to this
+const BLOCK_LENGTH: u64 = 2880;
+
+/// Length of a single FITS header card
+const CARD_LENGTH: usize = 80;
+
+/// Round a length up to a whole number of FITS blocks
+fn padded(length: u64) -> u64 {
+    length.div_ceil(BLOCK_LENGTH) * BLOCK_LENGTH
+}
+
+/// Parse the integer value of a header card
+fn card_int(card: &str) -> Option<i64> {
+    let value = card.get(10..)?;
+    value.split('/').next()?.trim().parse::<i64>().ok()
+}
+
+/// Read one header, returning its padded length and the length of its data unit
+///
+/// # Arguments
+///
+/// * `file` - The file, positioned at the start of the header
+/// * `filename` - The file name, for error messages
+/// * `hdu` - The (0-based) index of the HDU, for error messages
+///
+/// # Returns
+///
+/// * `Result<Option<(u64, u64)>, FitsRotateError>` - The header and data lengths in
+///   bytes, or `None` at the end of the file
+///
+fn read_hdu_lengths(file: &mut File, filename: &str, hdu: usize) -> Result<Option<(u64, u64)>, FitsRotateError> {
+    let mut block = vec![0u8; BLOCK_LENGTH as usize];
+    let (mut bitpix, mut naxis, mut pcount, mut gcount) = (0i64, 0i64, 0i64, 1i64);
+    let mut naxes: Vec<i64> = Vec::new();
+    let mut header_length = 0;
+    loop {
+        let n_read = read_block(file, &mut block)?;
+        // Anything after the last HDU that is not an extension is ignored
+        if header_length == 0 && (n_read == 0 || (hdu > 0 && !block.starts_with(b"XTENSION"))) {
+            return Ok(None);
+        }
+        if n_read < block.len() {
+            return Err(FitsRotateError::Message(format!(
+                "Header of HDU {} of {} has no END card (the file ends inside the header)",
+                hdu, filename
+            )));
+        }
+        header_length += BLOCK_LENGTH;
+        for card in block.chunks(CARD_LENGTH) {
+            let keyword = String::from_utf8_lossy(&card[..8]);
+            let keyword = keyword.trim_end();
+            let card = String::from_utf8_lossy(card);
+            match keyword {
+                "END" => {
+                    let n_values = naxes.iter().product::<i64>() * i64::from(naxis > 0);
+                    let data_length = (bitpix.abs() / 8) * gcount * (pcount + n_values);
+                    return Ok(Some((header_length, data_length.max(0) as u64)));
+                }
+                "BITPIX" => bitpix = card_int(&card).unwrap_or(0),
+                "NAXIS" => naxis = card_int(&card).unwrap_or(0),
+                "PCOUNT" => pcount = card_int(&card).unwrap_or(0),
+                "GCOUNT" => gcount = card_int(&card).unwrap_or(1),
+                _ if keyword.starts_with("NAXIS") => {
+                    let axis = keyword[5..].parse::<i64>().unwrap_or(0);
+                    if axis >= 1 && axis <= naxis {
+                        naxes.push(card_int(&card).unwrap_or(0));
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Fill a block from the file, returning the number of bytes read
+fn read_block(file: &mut File, block: &mut [u8]) -> Result<usize, FitsRotateError> {
+    let mut n_read = 0;
+    while n_read < block.len() {
+        match file.read(&mut block[n_read..])? {
+            0 => break,
+            n => n_read += n,
+        }
+    }
+    Ok(n_read)
+}
+
+/// Check that a FITS file is as long as its headers say it should be
+///
+/// The header of each HDU is read to work out the length of its data
+/// unit, and the sum of the (2880-byte padded) headers and data units is
+/// compared with the size of the file. This catches partially transferred
+/// files before any data is read.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+///
+pub fn check_file_size(filename: &str) -> Result<(), FitsRotateError> {
+    let mut file = File::open(filename)?;
+    let file_length = file.metadata()?.len();
+    let mut expected: u64 = 0;
+    let mut hdu = 0;
+    while expected < file_length {
+        file.seek(SeekFrom::Start(expected))?;
+        let (header_length, data_length) = match read_hdu_lengths(&mut file, filename, hdu)? {
+            Some(lengths) => lengths,
+            None => break,
+        };
+        expected += header_length + padded(data_length);
+        hdu += 1;
+    }
+    if expected > file_length {
+        return Err(FitsRotateError::Message(format!(
+            "{} is {} bytes short of the 2880-byte-padded size implied by its header ({} bytes); \
+             it may be truncated",
+            filename,
+            expected - file_length,
+            expected
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,253 @@
+/// One axis as shown in the interactive reordering screen
+///
+/// Only the screen reads the entries, so builds without it never do.
+#[derive(Clone, Debug)]
+#[cfg_attr(not(feature = "interactive"), allow(dead_code))]
+pub struct AxisEntry {
+    /// The (FITS, 1-based) input axis
+    pub axis: usize,
+    pub ctype: String,
+    pub length: usize,
+    /// Reverse the axis
+    pub flip: bool,
+}
+
+/// Convert an interactive selection into a mode string
+///
+/// The entries are in their chosen output order, so the n-th entry names
+/// the input axis that becomes output axis n. Flipped axes are prefixed
+/// with a minus sign, and the axes are separated by commas if there are
+/// more than 9 of them.
+///
+/// # Arguments
+///
+/// * `entries` - The axes, in output order
+///
+/// # Returns
+///
+/// * `String` - The mode string (e.g. `3-12`)
+///
+#[cfg(any(test, feature = "interactive"))]
+pub fn selection_to_mode(entries: &[AxisEntry]) -> String {
+    let axes: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            if entry.flip {
+                format!("-{}", entry.axis)
+            } else {
+                entry.axis.to_string()
+            }
+        })
+        .collect();
+    if entries.len() > 9 {
+        axes.join(",")
+    } else {
+        axes.concat()
+    }
+}
+
+#[cfg(feature = "interactive")]
+mod tui {
+    use super::{selection_to_mode, AxisEntry};
+    use crate::axes::axis_role;
+    use crate::error::FitsRotateError;
+    use crossterm::cursor::MoveTo;
+    use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+    use crossterm::style::Print;
+    use crossterm::terminal::{self, Clear, ClearType};
+    use crossterm::{execute, queue};
+    use std::io::{stdout, Write};
+
+    /// What the user decided on the reordering screen
+    enum Outcome {
+        Confirm,
+        Abort,
+    }
+
+    /// Draw one line at a given row of the screen
+    fn line(out: &mut impl Write, row: u16, text: &str) -> std::io::Result<()> {
+        queue!(out, MoveTo(0, row), Print(text))
+    }
+
+    /// Draw the axis list
+    fn draw_axes(
+        out: &mut impl Write,
+        entries: &[AxisEntry],
+        cursor: usize,
+        grabbed: bool,
+    ) -> std::io::Result<()> {
+        queue!(out, Clear(ClearType::All))?;
+        line(out, 0, "Reorder the axes of the output:")?;
+        line(out, 1, "  up/down: select   space: grab/drop   f: flip   enter: preview   q: quit")?;
+        for (idx, entry) in entries.iter().enumerate() {
+            let marker = match (idx == cursor, grabbed) {
+                (true, true) => "=>",
+                (true, false) => "->",
+                _ => "  ",
+            };
+            let role = axis_role(&entry.ctype)
+                .map(|role| role.to_string())
+                .unwrap_or_else(|| "?".to_string());
+            let text = format!(
+                "{} AXIS{}: input axis {} {:<10} length {:<6} role {:<6}{}",
+                marker,
+                idx + 1,
+                entry.axis,
+                entry.ctype,
+                entry.length,
+                role,
+                if entry.flip { " (flipped)" } else { "" }
+            );
+            line(out, idx as u16 + 3, &text)?;
+        }
+        out.flush()
+    }
+
+    /// Draw the header changes of the current selection
+    fn draw_preview(out: &mut impl Write, entries: &[AxisEntry]) -> std::io::Result<()> {
+        queue!(out, Clear(ClearType::All))?;
+        line(out, 0, &format!("Mode: {}", selection_to_mode(entries)))?;
+        line(out, 1, "Header changes:")?;
+        for (idx, entry) in entries.iter().enumerate() {
+            let text = format!(
+                "  CTYPE{} = {}  (was CTYPE{}){}",
+                idx + 1,
+                entry.ctype,
+                entry.axis,
+                if entry.flip { ", CDELT and CRPIX flipped" } else { "" }
+            );
+            line(out, idx as u16 + 2, &text)?;
+        }
+        line(out, entries.len() as u16 + 3, "Rotate with this mode? (y/n)")?;
+        out.flush()
+    }
+
+    /// Run the event loop until the user confirms or aborts
+    fn event_loop(entries: &mut [AxisEntry]) -> std::io::Result<Outcome> {
+        let mut out = stdout();
+        let mut cursor = 0;
+        let mut grabbed = false;
+        let mut previewing = false;
+        loop {
+            if previewing {
+                draw_preview(&mut out, entries)?;
+            } else {
+                draw_axes(&mut out, entries, cursor, grabbed)?;
+            }
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            if previewing {
+                match key.code {
+                    KeyCode::Char('y') | KeyCode::Enter => return Ok(Outcome::Confirm),
+                    KeyCode::Char('q') => return Ok(Outcome::Abort),
+                    _ => previewing = false,
+                }
+                continue;
+            }
+            match key.code {
+                KeyCode::Up if cursor > 0 => {
+                    if grabbed {
+                        entries.swap(cursor, cursor - 1);
+                    }
+                    cursor -= 1;
+                }
+                KeyCode::Down if cursor + 1 < entries.len() => {
+                    if grabbed {
+                        entries.swap(cursor, cursor + 1);
+                    }
+                    cursor += 1;
+                }
+                KeyCode::Char(' ') => grabbed = !grabbed,
+                KeyCode::Char('f') => entries[cursor].flip = !entries[cursor].flip,
+                KeyCode::Enter => previewing = true,
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(Outcome::Abort),
+                _ => {}
+            }
+        }
+    }
+
+    /// Let the user choose a mode interactively
+    ///
+    /// # Arguments
+    ///
+    /// * `entries` - The axes of the input, in input order
+    ///
+    /// # Returns
+    ///
+    /// * `Result<String, FitsRotateError>` - The chosen mode string
+    ///
+    pub fn choose_mode(mut entries: Vec<AxisEntry>) -> Result<String, FitsRotateError> {
+        let mut out = stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, terminal::EnterAlternateScreen)?;
+        let outcome = event_loop(&mut entries);
+        // Always restore the terminal, even if the event loop failed
+        execute!(out, terminal::LeaveAlternateScreen)?;
+        terminal::disable_raw_mode()?;
+        match outcome? {
+            Outcome::Confirm => Ok(selection_to_mode(&entries)),
+            Outcome::Abort => Err(FitsRotateError::Message("Aborted by user".to_string())),
+        }
+    }
+}
+
+#[cfg(feature = "interactive")]
+pub use tui::choose_mode;
+
+/// Let the user choose a mode interactively
+///
+/// This build does not include the interactive feature, so this always fails.
+#[cfg(not(feature = "interactive"))]
+pub fn choose_mode(_entries: Vec<AxisEntry>) -> Result<String, crate::error::FitsRotateError> {
+    Err(crate::error::FitsRotateError::Message(
+        "--interactive needs fitsrotate_rs to be built with the `interactive` feature".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_flips, parse_mode};
+
+    fn entries(naxis: usize) -> Vec<AxisEntry> {
+        (1..naxis + 1)
+            .map(|axis| AxisEntry {
+                axis,
+                ctype: format!("AXIS{}", axis),
+                length: axis + 1,
+                flip: false,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn untouched_selection_is_the_identity() {
+        assert_eq!(selection_to_mode(&entries(4)), "1234");
+    }
+
+    #[test]
+    fn moved_and_flipped_axes() {
+        // Move axis 3 to the top, then flip axis 1
+        let mut selection = entries(3);
+        selection.swap(2, 1);
+        selection.swap(1, 0);
+        selection[1].flip = true;
+        let mode = selection_to_mode(&selection);
+        assert_eq!(mode, "3-12");
+        assert_eq!(parse_mode(&mode, 3).unwrap(), [3, 1, 2]);
+        assert_eq!(parse_flips(&mode), [1]);
+    }
+
+    #[test]
+    fn many_axes_are_separated_by_commas() {
+        let mut selection = entries(10);
+        selection.reverse();
+        selection[0].flip = true;
+        let mode = selection_to_mode(&selection);
+        assert_eq!(mode, "-10,9,8,7,6,5,4,3,2,1");
+        assert_eq!(parse_mode(&mode, 10).unwrap(), (1..11).rev().collect::<Vec<usize>>());
+        assert_eq!(parse_flips(&mode), [10]);
+    }
+}
@@ -0,0 +1,142 @@
+use std::fs::File;
+
+/// Access pattern hints for the kernel page cache
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Advice {
+    /// The file is read from start to end
+    Sequential,
+    /// A range will be needed soon, so read it ahead
+    WillNeed,
+    /// A range is no longer needed, so its pages can be dropped
+    DontNeed,
+}
+
+/// Give the kernel a hint about how a range of a file will be accessed
+///
+/// This uses `posix_fadvise` on Linux and does nothing on other platforms.
+/// Hints are only advice, so failures are ignored.
+///
+/// # Arguments
+///
+/// * `file` - The file
+/// * `offset` - The start of the range, in bytes
+/// * `len` - The length of the range in bytes (0 for the rest of the file)
+/// * `advice` - The hint
+///
+#[cfg(target_os = "linux")]
+pub fn advise(file: &File, offset: u64, len: u64, advice: Advice) {
+    use std::os::unix::io::AsRawFd;
+    let advice = match advice {
+        Advice::Sequential => libc::POSIX_FADV_SEQUENTIAL,
+        Advice::WillNeed => libc::POSIX_FADV_WILLNEED,
+        Advice::DontNeed => libc::POSIX_FADV_DONTNEED,
+    };
+    unsafe {
+        libc::posix_fadvise(file.as_raw_fd(), offset as libc::off_t, len as libc::off_t, advice);
+    }
+}
+
+/// Give the kernel a hint about how a range of a file will be accessed
+///
+/// This uses `posix_fadvise` on Linux and does nothing on other platforms.
+#[cfg(not(target_os = "linux"))]
+pub fn advise(_file: &File, _offset: u64, _len: u64, _advice: Advice) {}
+
+/// A file that page cache hints can be given for
+///
+/// This is implemented for `File` with `advise`, and lets the hints of a
+/// `SequentialRead` be checked without a kernel that takes them.
+pub trait Advise {
+    /// Give a hint about how a range of the file will be accessed
+    fn advise(&self, offset: u64, len: u64, advice: Advice);
+}
+
+impl Advise for File {
+    fn advise(&self, offset: u64, len: u64, advice: Advice) {
+        advise(self, offset, len, advice);
+    }
+}
+
+/// Hints for reading a whole file once, from start to end
+///
+/// The file is marked for sequential read-ahead when created, and its
+/// pages are released from the cache when dropped, once the data is in
+/// memory.
+pub struct SequentialRead<F: Advise = File> {
+    file: F,
+}
+
+impl SequentialRead {
+    /// Start a sequential read of a file
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The file about to be read
+    ///
+    /// # Returns
+    ///
+    /// * `Option<SequentialRead>` - The hints, or `None` if the file cannot be opened
+    ///
+    pub fn start(filename: &str) -> Option<SequentialRead> {
+        let file = File::open(filename).ok()?;
+        Some(SequentialRead::with_file(file))
+    }
+}
+
+impl<F: Advise> SequentialRead<F> {
+    /// Start a sequential read of an open file
+    pub fn with_file(file: F) -> SequentialRead<F> {
+        file.advise(0, 0, Advice::Sequential);
+        file.advise(0, 0, Advice::WillNeed);
+        SequentialRead { file }
+    }
+}
+
+impl<F: Advise> Drop for SequentialRead<F> {
+    fn drop(&mut self) {
+        self.file.advise(0, 0, Advice::DontNeed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    /// Records the hints given for it rather than passing them on
+    #[derive(Default)]
+    struct Recorder {
+        hints: RefCell<Vec<(u64, u64, Advice)>>,
+    }
+
+    impl Advise for &Recorder {
+        fn advise(&self, offset: u64, len: u64, advice: Advice) {
+            self.hints.borrow_mut().push((offset, len, advice));
+        }
+    }
+
+    #[test]
+    fn a_sequential_read_asks_for_read_ahead_then_drops_the_pages() {
+        let recorder = Recorder::default();
+        let hints = SequentialRead::with_file(&recorder);
+        assert_eq!(
+            *recorder.hints.borrow(),
+            [(0, 0, Advice::Sequential), (0, 0, Advice::WillNeed)]
+        );
+        drop(hints);
+        assert_eq!(recorder.hints.borrow().last(), Some(&(0, 0, Advice::DontNeed)));
+        assert_eq!(recorder.hints.borrow().len(), 3);
+    }
+
+    #[test]
+    fn hints_on_a_real_file_are_harmless() {
+        let path = std::env::temp_dir().join(format!("fitsrotate_rs.iohints.{}", std::process::id()));
+        std::fs::write(&path, [0u8; 2880]).unwrap();
+        let hints = SequentialRead::start(path.to_str().unwrap());
+        assert!(hints.is_some());
+        drop(hints);
+        assert_eq!(std::fs::read(&path).unwrap(), [0u8; 2880]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(SequentialRead::start(path.to_str().unwrap()).is_none());
+    }
+}
@@ -0,0 +1,217 @@
+use crate::header::{FitsHeader, HeaderCard, HeaderCardValue};
+use crate::repair::renumbered_card;
+use crate::{OPTIONAL_WCS_CARD_STUBS, WCS_CARD_STUBS, WCS_MATRIX_STUBS};
+use fitsio::errors::{check_status, Error};
+use fitsio::FitsFile;
+use std::ffi::CString;
+
+/// The axes of an axis-indexed WCS keyword that a rotation renumbers, e.g. `[2]` for `CRVAL2` or `[1, 2]` for `PC1_2`
+pub fn keyword_axes(keyword: &str) -> Option<Vec<usize>> {
+    let number = |text: &str| {
+        Some(text)
+            .filter(|text| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|text| text.parse::<usize>().ok())
+    };
+    for stub in WCS_CARD_STUBS.iter().chain(OPTIONAL_WCS_CARD_STUBS.iter()) {
+        if let Some(axis) = keyword.strip_prefix(stub).and_then(number) {
+            return Some(vec![axis]);
+        }
+    }
+    for stub in WCS_MATRIX_STUBS {
+        if let Some((i, j)) = keyword.strip_prefix(stub).and_then(|pair| pair.split_once('_')) {
+            if let (Some(i), Some(j)) = (number(i), number(j)) {
+                return Some(vec![i, j]);
+            }
+        }
+    }
+    None
+}
+
+/// The input keyword whose card a rotation renumbers to an output keyword
+///
+/// Output axis `n` takes the cards of input axis `mode[n - 1]`.
+fn source_keyword(keyword: &str, mode: &[usize]) -> Option<String> {
+    let axes = keyword_axes(keyword)?;
+    let source: Vec<usize> = axes
+        .iter()
+        .map(|axis| mode.get(axis.checked_sub(1)?).copied())
+        .collect::<Option<Vec<usize>>>()?;
+    let stub = keyword.trim_end_matches(|c: char| c.is_ascii_digit() || c == '_');
+    match source.as_slice() {
+        [axis] => Some(format!("{}{}", stub, axis)),
+        [i, j] => Some(format!("{}{}_{}", stub, i, j)),
+        _ => None,
+    }
+}
+
+/// Check if two values are the same, counting an integer and a real number with the same value as the same
+///
+/// CFITSIO writes a copied `CRVAL3 = 1420000000` as `1420000000.`, which
+/// should not count as a change of value.
+fn same_value(a: &HeaderCardValue, b: &HeaderCardValue) -> bool {
+    match (a, b) {
+        (HeaderCardValue::Integer(integer), HeaderCardValue::Float(float))
+        | (HeaderCardValue::Float(float), HeaderCardValue::Integer(integer)) => *integer as f64 == *float,
+        _ => a == b,
+    }
+}
+
+/// A card laid out like `template`, with the value of `card`
+///
+/// The value is put in the fixed-format value field (columns 11 to 30) of
+/// the template, keeping its comment where it was. Strings, and values that
+/// do not fit, are taken with the whole of `card` instead.
+fn with_value(template: &HeaderCard, card: &HeaderCard) -> HeaderCard {
+    let value = match (card.typed_value(), card.value()) {
+        (HeaderCardValue::String(_), _) | (HeaderCardValue::Undefined, _) | (_, None) => return card.clone(),
+        (_, Some(value)) => value,
+    };
+    let fixed_format = template.record.len() >= 30
+        && template.value().is_some()
+        && &template.record[8..10] == "= "
+        && template.record[10..30].trim_start().len() == template.record[10..30].trim().len()
+        && template.record[30..].chars().next().is_none_or(|c| c == ' ');
+    if !fixed_format || value.len() > 20 {
+        return card.clone();
+    }
+    HeaderCard::new(&format!(
+        "{}{:>20}{}",
+        &template.record[..10],
+        value,
+        &template.record[30..]
+    ))
+}
+
+/// Lay out the header of a rotated file like the header of its input
+///
+/// Every card of the input is kept in its place, with the value the
+/// output has for the same keyword, so that a textual diff of the headers
+/// shows only the values that changed. A renumbered WCS card (e.g.
+/// `CRVAL1`, taken from `CRVAL3` of the input) keeps the layout and
+/// comment of the card it came from. The value field of a changed card is
+/// rewritten in place, keeping its comment and alignment. WCS cards the
+/// output no longer has are dropped, and cards only the output has (e.g. its
+/// HISTORY) are added after the cards of the input.
+///
+/// # Arguments
+///
+/// * `input` - The header of the input
+/// * `output` - The header of the rotated file
+/// * `mode` - The input axis of each output axis
+///
+/// # Returns
+///
+/// * `(Vec<HeaderCard>, Vec<HeaderCard>)` - The structural cards of the output laid out like those of the
+///   input, and the other cards, in order
+///
+pub fn preserved_cards(input: &FitsHeader, output: &FitsHeader, mode: &[usize]) -> (Vec<HeaderCard>, Vec<HeaderCard>) {
+    let find = |header: &FitsHeader, keyword: &str| {
+        header
+            .cards
+            .iter()
+            .find(|card| !card.is_commentary() && card.keyword() == keyword)
+            .cloned()
+    };
+    let structural: Vec<HeaderCard> = output
+        .cards
+        .iter()
+        .filter(|card| card.is_structural() && card.keyword() != "END")
+        .map(|card| match find(input, &card.keyword()) {
+            Some(template) if same_value(&template.typed_value(), &card.typed_value()) => template,
+            Some(template) => with_value(&template, card),
+            None => card.clone(),
+        })
+        .collect();
+
+    let mut placed = vec![false; output.cards.len()];
+    let mut cards: Vec<HeaderCard> = Vec::with_capacity(input.cards.len());
+    for card in input.cards.iter().filter(|card| !card.is_structural()) {
+        if card.is_commentary() {
+            // The output may have a copy of the commentary cards of the input
+            if let Some(idx) = output.cards.iter().enumerate().position(|(idx, other)| {
+                !placed[idx] && other.is_commentary() && other.record.trim_end() == card.record.trim_end()
+            }) {
+                placed[idx] = true;
+            }
+            cards.push(card.clone());
+            continue;
+        }
+        let keyword = card.keyword();
+        let idx = output
+            .cards
+            .iter()
+            .enumerate()
+            .position(|(idx, other)| !placed[idx] && !other.is_commentary() && other.keyword() == keyword);
+        match idx {
+            Some(idx) => {
+                placed[idx] = true;
+                let template = source_keyword(&keyword, mode)
+                    .and_then(|source| find(input, &source))
+                    .map_or(card.clone(), |source| renumbered_card(&source, &keyword));
+                let out_card = &output.cards[idx];
+                if same_value(&template.typed_value(), &out_card.typed_value()) {
+                    cards.push(template);
+                } else {
+                    cards.push(with_value(&template, out_card));
+                }
+            }
+            // The output always has the WCS cards of its axes, so a missing one was removed
+            None if keyword_axes(&keyword).is_some() => {}
+            None => cards.push(card.clone()),
+        }
+    }
+    cards.extend(
+        output
+            .cards
+            .iter()
+            .enumerate()
+            .filter(|(idx, card)| !placed[*idx] && !card.is_structural())
+            .map(|(_, card)| card.clone()),
+    );
+    (structural, cards)
+}
+
+/// Rewrite the primary header of a rotated file with the layout of the header of its input
+///
+/// The structural cards (SIMPLE, BITPIX, NAXISn, ...) are edited in
+/// place, and every other card is removed and written again in the order
+/// given by `preserved_cards`.
+///
+/// # Arguments
+///
+/// * `fits_file` - The rotated FITS file
+/// * `input` - The header of the input
+/// * `mode` - The input axis of each output axis
+///
+/// # Returns
+///
+/// * `Result<usize, Error>` - The number of cards written
+///
+pub fn preserve_layout(fits_file: &mut FitsFile, input: &FitsHeader, mode: &[usize]) -> Result<usize, Error> {
+    fits_file.primary_hdu()?;
+    let output = FitsHeader::read(fits_file)?;
+    let (structural, cards) = preserved_cards(input, &output, mode);
+
+    let mut status = 0;
+    // Delete from the end, so that the positions of the cards still to delete do not change
+    for (idx, card) in output.cards.iter().enumerate().rev() {
+        if card.is_structural() {
+            continue;
+        }
+        unsafe {
+            fitsio::sys::ffdrec(fits_file.as_raw(), idx as i32 + 1, &mut status);
+        }
+        check_status(status)?;
+    }
+    // The structural cards are now the first cards of the header, in the order CFITSIO wrote them
+    for (idx, card) in structural.iter().enumerate() {
+        let c_record = CString::new(card.record.as_str())?;
+        unsafe {
+            fitsio::sys::ffmrec(fits_file.as_raw(), idx as i32 + 1, c_record.as_ptr(), &mut status);
+        }
+        check_status(status)?;
+    }
+    let n_cards = cards.len();
+    FitsHeader { cards }.write(fits_file, &[])?;
+    Ok(n_cards)
+}
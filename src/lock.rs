@@ -0,0 +1,136 @@
+use crate::error::FitsRotateError;
+use fs4::{FileExt, TryLockError};
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How often a contended lock is retried while waiting for a timeout
+const LOCK_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// An advisory lock on a file, released when dropped
+pub struct FileLock {
+    file: File,
+    /// A lock file to remove before the lock is released
+    lock_path: Option<PathBuf>,
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Removing the lock file while it is still locked means that a
+        // process waiting on it finds it gone once it takes the lock, and
+        // starts again on a new one (see `lock_output`)
+        if let Some(lock_path) = &self.lock_path {
+            let _ = std::fs::remove_file(lock_path);
+        }
+        let _ = FileExt::unlock(&self.file);
+    }
+}
+
+/// Take a lock, waiting up to a timeout (or forever) if it is contended
+///
+/// # Arguments
+///
+/// * `file` - The file to lock
+/// * `filename` - The name of the locked file, for error messages
+/// * `exclusive` - Take an exclusive rather than a shared lock
+/// * `timeout` - How long to wait for the lock, or `None` to wait forever
+///
+fn acquire(file: &File, filename: &str, exclusive: bool, timeout: Option<Duration>) -> Result<(), FitsRotateError> {
+    // Newer versions of std have inherent locking methods on File, so the
+    // fs4 methods are called through the trait
+    let timeout = match timeout {
+        Some(timeout) => timeout,
+        None if exclusive => return Ok(FileExt::lock(file)?),
+        None => return Ok(FileExt::lock_shared(file)?),
+    };
+    let start = Instant::now();
+    loop {
+        let result = if exclusive {
+            FileExt::try_lock(file)
+        } else {
+            FileExt::try_lock_shared(file)
+        };
+        match result {
+            Ok(()) => return Ok(()),
+            Err(TryLockError::WouldBlock) => {
+                if start.elapsed() >= timeout {
+                    return Err(FitsRotateError::Locked {
+                        filename: filename.to_string(),
+                        timeout: timeout.as_secs_f64(),
+                    });
+                }
+                std::thread::sleep(LOCK_POLL_INTERVAL);
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+    }
+}
+
+/// Take a shared lock on an input file while it is read
+///
+/// # Arguments
+///
+/// * `filename` - The input file
+/// * `timeout` - How long to wait for the lock, or `None` to wait forever
+///
+/// # Returns
+///
+/// * `Result<FileLock, FitsRotateError>` - The lock
+///
+pub fn lock_input(filename: &str, timeout: Option<Duration>) -> Result<FileLock, FitsRotateError> {
+    let file = File::open(filename)?;
+    acquire(&file, filename, false, timeout)?;
+    Ok(FileLock { file, lock_path: None })
+}
+
+/// Take an exclusive lock on an output file while it is written
+///
+/// The output itself is removed and recreated when it is written, so the
+/// lock is taken on a `.lock` file next to it instead. The lock file is
+/// removed when the lock is released. A process that was waiting for the
+/// lock then holds a lock on a file that is no longer there, so once a
+/// lock is taken it is checked to still be on the file at the lock path,
+/// and taken again on a new lock file if not.
+///
+/// On platforms other than Unix the lock file is left in place.
+///
+/// # Arguments
+///
+/// * `filename` - The output file
+/// * `timeout` - How long to wait for the lock, or `None` to wait forever
+///
+/// # Returns
+///
+/// * `Result<FileLock, FitsRotateError>` - The lock
+///
+pub fn lock_output(filename: &str, timeout: Option<Duration>) -> Result<FileLock, FitsRotateError> {
+    let lock_path = PathBuf::from(format!("{}.lock", filename));
+    let start = Instant::now();
+    loop {
+        let file = OpenOptions::new().write(true).create(true).truncate(false).open(&lock_path)?;
+        let remaining = timeout.map(|timeout| timeout.saturating_sub(start.elapsed()));
+        acquire(&file, filename, true, remaining)?;
+        if is_lock_path(&file, &lock_path) {
+            let lock_path = cfg!(unix).then_some(lock_path);
+            return Ok(FileLock { file, lock_path });
+        }
+    }
+}
+
+/// Check that an open file is still the one at a path
+#[cfg(unix)]
+fn is_lock_path(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(open), Ok(current)) => open.dev() == current.dev() && open.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+/// Check that an open file is still the one at a path
+///
+/// Lock files are never removed on this platform, so it always is.
+#[cfg(not(unix))]
+fn is_lock_path(_file: &File, _path: &Path) -> bool {
+    true
+}
@@ -1,91 +1,313 @@
 #![crate_name = "fitsrotate_rs"]
-#![allow(unused)]
-use fitsio::images::{ImageDescription, ImageType};
+
+// Only src/permutation.rs can be built without std; the binary reads and writes files
+#[cfg(not(feature = "std"))]
+compile_error!("fitsrotate_rs needs the std feature");
+use fitsio::hdu::HduInfo;
+use fitsio::images::{ImageDescription, ImageType, ReadImage, WriteImage};
 #[doc(inline)]
 use fitsio::FitsFile;
 use fitsio::errors::Error;
-use ndarray::ArrayD;
-use std::path::Path;
-use clap::{builder::Str, Parser};
+use ndarray::{ArrayD, Axis, Slice};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{Duration, Instant};
+use tempfile::NamedTempFile;
+use clap::{Parser, Subcommand};
+
+mod axes;
+use axes::{
+    axis_order_lines, axis_role, check_ctype_requirements, check_expected_order, check_expected_shape, find_axis,
+    mode_from_reference, mode_from_target, parse_ctype_requirement, parse_expected_length, read_ctypes, suggest_mode, AxisOrder, AxisRole, CtypeRequirement,
+};
+
+mod beamhistory;
+use beamhistory::{channel_order, renumber_beam_channels};
+
+mod benchmark;
+use benchmark::{benchmark_filename, PhaseTimings};
+
+mod buildinfo;
+use buildinfo::EnvReport;
+
+mod color;
+use color::{label, set_color_choice, success, warning};
+
+mod compliance;
+use compliance::{check_bitpix, fits_version_report, parse_bitpix};
+
+mod compress;
+use compress::{
+    compress_file, parse_dither_seed, parse_quantize, parse_tile_size, unpack_input, Compression, CompressionSettings,
+    Dither, Tiles,
+};
+
+mod consistency;
+use consistency::{check_consistency, DEFAULT_CONSISTENCY_KEYS};
+
+mod cube;
+use cube::{rolling_mean_history, FitsCube};
+
+mod distortion;
+use distortion::{distortion_axis, permute_distortion_cards, DISTORTION_EXTNAMES};
+
+mod error;
+use error::FitsRotateError;
+
+mod events;
+
+mod header;
+use header::{
+    append_hdu_blocks, copy_hdu, data_extent, is_plain_fits, replace_key, update_i64_key, write_f64_key,
+    write_header_backup, FitsHeader, HeaderCard, BACKUP_EXTNAME, CARD_LENGTH,
+};
+
+mod iohints;
+use iohints::{advise, Advice, SequentialRead};
+
+mod integrity;
+use integrity::check_file_size;
+
+mod interactive;
+use interactive::{choose_mode, AxisEntry};
+
+mod lock;
+use lock::{lock_input, lock_output};
+
+mod mef;
+use mef::write_mef_layout;
+
+mod merge;
+use merge::{glob_files, merge_files, walk_files};
+
+mod modes;
+use modes::{list_modes, matrix_table, modes_json, modes_table};
+
+mod ops;
+use ops::{build_plan, parse_ops};
+
+mod pad;
+use pad::{pad_cube, parse_shape, PadMode};
+
+mod patch;
+use patch::HeaderPatch;
+
+mod partition;
+use partition::{resolve_task, task_files, Task};
+
+mod planesort;
+use planesort::PlaneSort;
+
+mod permutation;
+use permutation::{fits_index_to_array_index, output_axis, AxisPermutation};
+
+mod precision;
+use precision::{Narrowing, WorkingDtype};
+
+mod regrid;
+use regrid::SpectralRegrid;
+
+mod smooth;
+use smooth::SpectralSmoothing;
+
+mod layout;
+use layout::preserve_layout;
+
+mod memory;
+use memory::{format_bytes, peak_resident, phase_peak, total_peak, CountingAllocator};
+
+/// Counts allocations for `--mem-profile`; until then it only checks a flag
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+mod preview;
+use preview::{preview_plane, write_preview};
+
+mod probe;
+use probe::probe;
+
+mod provenance;
+use provenance::{print_provenance, version_history, write_date, write_history};
+
+mod rawio;
+use rawio::{raw_readable, raw_writable, read_data_unit, write_data_unit};
+
+mod region;
+use region::{parse_regions, region_mask};
+
+mod remote;
+use remote::{header_ctypes, is_url, read_fits_header_from_url};
+
+mod repair;
+use repair::{repair_header, rotated_keywords, rotated_wcs_cards};
+
+mod retry;
+use retry::{parse_duration, RetryPolicy, TRANSIENT_ERRNOS, TRANSIENT_FITS_STATUSES};
+
+mod sidecar;
+use sidecar::{write_sidecar, CubeInfo};
+
+mod spotcheck;
+#[cfg(debug_assertions)]
+use spotcheck::corrupt_for_test;
+use spotcheck::{default_seed, spot_check};
+
+mod stats;
+use stats::compare_stats;
+
+mod split;
+use split::{chunk_filename, shift_split_crpix, split_filename, write_chunk_keys};
+
+mod stokes;
+use stokes::{stokes_label, StokesPlane};
+
+mod triplet;
+use triplet::check_triplet;
+
+mod version;
+use version::{check_latest_version, CHANGELOG};
+
+mod wcs;
+use wcs::{check_output_wcs, flip_wcs_axis, modernize_wcs, read_wcs_cards, replace_wcs};
+
+/// Stubs of the axis-indexed WCS keywords that are swapped on rotation
+const WCS_CARD_STUBS: [&str; 5] = ["CTYPE", "CRVAL", "CDELT", "CRPIX", "CUNIT"];
+
+/// Stubs of the axis-indexed WCS keywords with string values; the others are numbers
+const STRING_WCS_CARD_STUBS: [&str; 2] = ["CTYPE", "CUNIT"];
+
+/// Stubs of legacy axis-indexed WCS keywords, swapped only if present
+const OPTIONAL_WCS_CARD_STUBS: [&str; 1] = ["CROTA"];
+
+/// Stubs of the WCS matrices indexed by a pair of axes (e.g. `PC1_2`), swapped only if present
+const WCS_MATRIX_STUBS: [&str; 2] = ["PC", "CD"];
+
+/// Equinox keywords, copied unchanged when the header is not repaired (`EPOCH` is the obsolete form of `EQUINOX`)
+const EQUINOX_CARDS: [&str; 2] = ["EQUINOX", "EPOCH"];
+
+/// Pixel types that can be rotated and written back out
+trait FitsPixel: WriteImage + Copy + Send + Sync + From<f32> + Into<f64> {
+    /// The image type of the output
+    const IMAGE_TYPE: ImageType;
+
+    /// Convert a value computed in double precision, e.g. by interpolation
+    fn from_f64(value: f64) -> Self;
+
+    /// Write the value as the big-endian bytes of a FITS data unit
+    fn write_be(self, bytes: &mut [u8]);
+}
+
+impl FitsPixel for f32 {
+    const IMAGE_TYPE: ImageType = ImageType::Float;
+
+    fn from_f64(value: f64) -> Self {
+        value as f32
+    }
+
+    fn write_be(self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.to_be_bytes());
+    }
+}
 
-/// Convert a FITS index to an array index
+impl FitsPixel for f64 {
+    const IMAGE_TYPE: ImageType = ImageType::Double;
+
+    fn from_f64(value: f64) -> Self {
+        value
+    }
+
+    fn write_be(self, bytes: &mut [u8]) {
+        bytes.copy_from_slice(&self.to_be_bytes());
+    }
+}
+
+/// Rotate the axes of a FITS cube array given some new ordering
 ///
-/// FITS indices are 1-based, while array indices are 0-based.
-/// FITS indices are in the order x, y, z, ..., while array indices are in the order z, y, x, ...
+/// The mode refers to FITS axes: the n-th entry is the (1-based) input axis
+/// that becomes output axis n. Since FITS axis 1 is the last (fastest) axis
+/// of the array, each entry is translated with `fits_index_to_array_index`
+/// before permuting the array axes.
 ///
 /// # Arguments
 ///
-/// * `fits_index` - The FITS index
-/// * `naxis` - The number of axes in the FITS cube
+/// * `fits_cube` - The FITS cube
+/// * `mode` - The new ordering of the (FITS) axes
 ///
 /// # Returns
 ///
-/// * `usize` - The array index
+/// * `ArrayD<T>` - The rotated FITS cube in standard layout, still to be written (e.g. with `write_fits_cube`)
 ///
 /// # Examples
 ///
 /// ```
-/// use fitsrotate_rs::fits_index_to_array_index;
-/// let fits_index = 3;
-/// let naxis = 3;
-/// let array_index = fits_index_to_array_index(fits_index, naxis);
-/// assert_eq!(array_index, 2);
+/// use fitsrotate_rs::rotate_fits_cube_axes;
+/// let fits_cube = ArrayD::zeros((3, 3, 3));
+/// let mode = [3, 2, 1];
+/// let rotated_fits_cube = rotate_fits_cube_axes(fits_cube, &mode);
 /// ```
+#[must_use = "the rotated cube is not written automatically; pass it to write_fits_cube"]
+fn rotate_fits_cube_axes<T: Copy + From<f32>>(fits_cube: ArrayD<T>, mode: &[usize]) -> ArrayD<T> {
+    // The data is copied into the new order, so that it is written in that order
+    FitsCube::permute_inplace(fits_cube, &array_mode(mode))
+}
+
+/// Translate a mode of FITS axes to the array axes of the input that become each array axis of the output
 ///
-/// ```
-/// use fitsrotate_rs::fits_index_to_array_index;
-/// let fits_index = 1;
-/// let naxis = 3;
-/// let array_index = fits_index_to_array_index(fits_index, naxis);
-/// assert_eq!(array_index, 0);
-/// ```
+/// # Arguments
 ///
-/// ```
-/// use fitsrotate_rs::fits_index_to_array_index;
-/// let fits_index = 2;
-/// let naxis = 3;
-/// let array_index = fits_index_to_array_index(fits_index, naxis);
-/// assert_eq!(array_index, 1);
-/// ```
-fn fits_index_to_array_index(fits_index: usize, naxis: usize) -> usize {
-    let range = (0..(naxis as i32)).rev();
-    let ret = Vec::from_iter(range)[fits_index - 1];
-    println!("fits_index_to_array_index: {} -> {}", fits_index, ret);
-    ret as usize
+/// * `mode` - The new ordering of the (FITS) axes
+///
+/// # Returns
+///
+/// * `Vec<usize>` - The (array, 0-based) input axis of each (array) output axis
+///
+fn array_mode(mode: &[usize]) -> Vec<usize> {
+    let naxis = mode.len();
+    // Array axis `array_idx` of the output is FITS axis `naxis - array_idx`
+    (0..naxis)
+        .map(|array_idx| fits_index_to_array_index(mode[naxis - array_idx - 1], naxis))
+        .collect()
 }
 
-/// Rotate the axes of a FITS cube array given some new ordering
+/// Reverse the given axes of a FITS cube array
 ///
 /// # Arguments
 ///
 /// * `fits_cube` - The FITS cube
-/// * `fits_file` - The FITS file
-/// * `mode` - The new ordering of the axes
+/// * `flips` - The (FITS, 1-based) axes to reverse
 ///
 /// # Returns
 ///
-/// * `ArrayD<f32>` - The rotated FITS cube
-///
-/// # Examples
+/// * `ArrayD<T>` - The flipped FITS cube, in standard layout
 ///
-/// ```
-/// use fitsrotate_rs::rotate_fits_cube_axes;
-/// use fitsrotate_rs::rotate_fits_cube_axes;
-/// let fits_cube = ArrayD::zeros((3, 3, 3));
-/// let mut fits_file = FitsFile::open(filename).unwrap();
-/// let mode = [3, 2, 1];
-/// let (rotated_fits_cube, freq_axis) = rotate_fits_cube_axes(fits_cube, &mut fits_file, &mode);
-/// ```
-fn rotate_fits_cube_axes(fits_cube: ArrayD<f32>, fits_file: &mut FitsFile, mode: &[usize]) -> ArrayD<f32> {
-    let shape = fits_cube.shape();
-    let old_axes: Vec<usize> = (0..shape.len()).collect();
-    let old_mode:Vec<usize> = (1..shape.len()+1).collect();
-    let new_axes: Vec<usize> = mode.iter().map(|x| x - 1).collect();
+fn flip_fits_cube_axes<T: Clone>(mut fits_cube: ArrayD<T>, flips: &[usize]) -> ArrayD<T> {
+    if flips.is_empty() {
+        return fits_cube;
+    }
+    let naxis = fits_cube.ndim();
+    for fits_idx in flips {
+        fits_cube.invert_axis(Axis(fits_index_to_array_index(*fits_idx, naxis)));
+    }
+    fits_cube.as_standard_layout().into_owned()
+}
 
-    // Just shift the data here
-    let rot_cube = fits_cube.permuted_axes(new_axes);
-    println!("New axes: {:?}", rot_cube.shape());
-    rot_cube
+/// The CTYPEs of the rotated cube, following the header rewrite in `write_fits_cube`
+///
+/// # Arguments
+///
+/// * `ctypes` - The CTYPEs of the original cube, in FITS axis order
+/// * `mode` - The new ordering of the axes
+///
+/// # Returns
+///
+/// * `Vec<String>` - The CTYPEs of the rotated cube, in FITS axis order
+///
+fn rotated_ctypes(ctypes: &[String], mode: &[usize]) -> Vec<String> {
+    let mut rotated = vec![String::new(); ctypes.len()];
+    for (fits_idx, ctype) in ctypes.iter().enumerate() {
+        rotated[output_axis(fits_idx + 1, mode) - 1] = ctype.clone();
+    }
+    rotated
 }
 
 /// Read a FITS cube
@@ -96,39 +318,199 @@ fn rotate_fits_cube_axes(fits_cube: ArrayD<f32>, fits_file: &mut FitsFile, mode:
 ///
 /// # Returns
 ///
-/// * `ArrayD<f32>` - The FITS cube
-/// * `FitsFile` - The FITS file
+/// * `Result<(ArrayD<T>, FitsFile), FitsRotateError>` - The FITS cube and the FITS file
 ///
 /// # Examples
 ///
 /// ```
 /// use fitsrotate_rs::read_fits_cube;
-/// let (fits_cube, fits_file) = read_fits_cube("test.fits");
+/// let (fits_cube, fits_file) = read_fits_cube::<f32>("test.fits")?;
 /// ```
-fn read_fits_cube(filename: &str) -> (ArrayD<f32>, FitsFile) {
-    let mut fits_file = FitsFile::open(filename).unwrap();
-    let hdu = fits_file.primary_hdu().unwrap();
-    let data = hdu.read_image(&mut fits_file).unwrap();
-    (data, fits_file)
+fn read_fits_cube<T>(filename: &str) -> Result<(ArrayD<T>, FitsFile), FitsRotateError>
+where
+    ArrayD<T>: ReadImage,
+{
+    let mut fits_file = FitsFile::open(filename)?;
+    let hdu = fits_file
+        .primary_hdu()
+        .map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let data = hdu.read_image(&mut fits_file)?;
+    Ok((data, fits_file))
+}
+
+/// Read a FITS cube through a memory map of its data unit, where the file allows it
+///
+/// Scaled (`BSCALE`/`BZERO`), integer and compressed images are read by
+/// CFITSIO with `read_fits_cube` instead.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+///
+/// # Returns
+///
+/// * `Result<(ArrayD<T>, FitsFile), FitsRotateError>` - The FITS cube and the FITS file
+///
+fn read_fits_cube_mapped<T: FitsPixel>(filename: &str) -> Result<(ArrayD<T>, FitsFile), FitsRotateError>
+where
+    ArrayD<T>: ReadImage,
+{
+    let mut fits_file = FitsFile::open(filename)?;
+    let hdu = fits_file
+        .primary_hdu()
+        .map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let (shape, image_type) = match &hdu.info {
+        HduInfo::ImageInfo { shape, image_type } => (shape.clone(), *image_type),
+        _ => {
+            return Err(FitsRotateError::Message(format!(
+                "The primary HDU of {} is not an image",
+                filename
+            )))
+        }
+    };
+    let bscale: f64 = hdu.read_key(&mut fits_file, "BSCALE").unwrap_or(1.0);
+    let bzero: f64 = hdu.read_key(&mut fits_file, "BZERO").unwrap_or(0.0);
+    if !raw_readable(filename, image_type, bscale != 1.0 || bzero != 0.0) {
+        drop(fits_file);
+        return read_fits_cube::<T>(filename);
+    }
+    let extent = data_extent(&mut fits_file, 0)?;
+    let data = read_data_unit(filename, extent.start, &shape, image_type)?;
+    Ok((data, fits_file))
 }
 
 
-/// Check if a file exists
-/// 
+/// Number of pixels converted at a time by `read_fits_cube_narrowed`
+const NARROWING_CHUNK: usize = 1 << 20;
+
+/// Read a double precision FITS cube, converting it to float32 as it is read
+///
+/// The data is read in chunks of `NARROWING_CHUNK` pixels, so the whole cube
+/// is never held in double precision.
+///
 /// # Arguments
-/// 
+///
+/// * `filename` - The FITS file
+///
+/// # Returns
+///
+/// * `Result<(ArrayD<T>, FitsFile, Narrowing), FitsRotateError>` - The FITS cube, the FITS file, and the precision lost
+///
+fn read_fits_cube_narrowed<T: FitsPixel>(filename: &str) -> Result<(ArrayD<T>, FitsFile, Narrowing), FitsRotateError> {
+    let mut fits_file = FitsFile::open(filename)?;
+    let hdu = fits_file
+        .primary_hdu()
+        .map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let shape = match &hdu.info {
+        HduInfo::ImageInfo { shape, .. } => shape.clone(),
+        _ => {
+            return Err(FitsRotateError::Message(format!(
+                "The primary HDU of {} is not an image",
+                filename
+            )))
+        }
+    };
+    let n_pixels: usize = shape.iter().product();
+    let mut narrowing = Narrowing::default();
+    let mut data: Vec<T> = Vec::with_capacity(n_pixels);
+    for start in (0..n_pixels).step_by(NARROWING_CHUNK) {
+        let end = (start + NARROWING_CHUNK).min(n_pixels);
+        let chunk: Vec<f64> = hdu.read_section(&mut fits_file, start, end)?;
+        data.extend(chunk.into_iter().map(|value| T::from(narrowing.narrow(value))));
+    }
+    let fits_cube = ArrayD::from_shape_vec(shape, data).map_err(|e| FitsRotateError::Message(e.to_string()))?;
+    Ok((fits_cube, fits_file, narrowing))
+}
+
+/// Check that an output file does not exist, unless it may be overwritten
+///
+/// # Arguments
+///
 /// * `filename` - The file to check
 /// * `overwrite` - Overwrite the file if it already exists
-/// 
-/// # Returns
-/// 
-/// * `Result<bool, Error>` - True if the file exists
-/// 
-fn check_file_exists(filename: &str, overwrite: bool) -> Result<bool, Error> {
-    if ! overwrite && Path::new(filename).exists() {
-        return Err(Error::ExistingFile(filename.to_string()));
+///
+fn check_file_exists(filename: &str, overwrite: bool) -> Result<(), FitsRotateError> {
+    if !overwrite && Path::new(filename).exists() {
+        return Err(FitsRotateError::OutputExists {
+            filename: filename.to_string(),
+        });
+    }
+    Ok(())
+}
+
+/// Check that an output file can be written
+///
+/// The parent directory of the output must exist (or is created, if
+/// requested), and a file must be creatable there.
+///
+/// # Arguments
+///
+/// * `filename` - The output file
+/// * `mkdirs` - Create the parent directory if it does not exist
+///
+fn check_output_writable(filename: &str, mkdirs: bool) -> Result<(), FitsRotateError> {
+    let dir = match Path::new(filename).parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.exists() {
+        if !mkdirs {
+            return Err(FitsRotateError::Message(format!(
+                "Cannot write {}: the directory {} does not exist (pass --mkdirs to create it)",
+                filename,
+                dir.display()
+            )));
+        }
+        std::fs::create_dir_all(dir)?;
+        println!("Created directory {}", dir.display());
+    }
+    if !dir.is_dir() {
+        return Err(FitsRotateError::Message(format!(
+            "Cannot write {}: {} is not a directory",
+            filename,
+            dir.display()
+        )));
     }
-    Ok(true)
+    // Permissions alone do not tell about read-only filesystems, so try it
+    let probe = dir.join(format!(".fitsrotate_rs.{}.probe", std::process::id()));
+    match std::fs::File::create(&probe) {
+        Ok(_) => {
+            std::fs::remove_file(&probe)?;
+            Ok(())
+        }
+        Err(e) => Err(FitsRotateError::Message(format!(
+            "Cannot write {}: cannot create files in {}: {}",
+            filename,
+            dir.display(),
+            e
+        ))),
+    }
+}
+
+/// Print a warning, and log it to the event log of --json-logs
+///
+/// # Arguments
+///
+/// * `message` - The warning, without colour
+///
+fn warn(message: &str) {
+    events::warning(message);
+    println!("{}", warning(message));
+}
+
+/// How `write_fits_cube` writes its output; the default writes a new file through CFITSIO
+#[derive(Clone, Copy, Debug, Default)]
+struct WriteOptions<'a> {
+    /// Overwrite the FITS file if it already exists
+    overwrite: bool,
+    /// A (repaired) header to write before the rotated WCS keywords
+    header: Option<&'a FitsHeader>,
+    /// Do not stamp the output with the current date
+    reproducible: bool,
+    /// Write defaults for missing WCS keywords rather than failing
+    fill_missing_wcs: bool,
+    /// Write the data through a memory map rather than CFITSIO, where the output allows it
+    mmap_write: bool,
 }
 
 /// Write a FITS cube
@@ -136,36 +518,46 @@ fn check_file_exists(filename: &str, overwrite: bool) -> Result<bool, Error> {
 /// # Arguments
 ///
 /// * `filename` - The FITS file
-/// * `fits_cube` - The FITS cube
+/// * `fits_cube` - The FITS cube, written as 32-bit (f32) or 64-bit (f64) floats
 /// * `mode` - The new ordering of the axes
 /// * `old_file` - The original FITS file
-/// * `overwrite` - Overwrite the FITS file if it already exists
+/// * `options` - How to write the file
 ///
 /// # Examples
 ///
 /// ```
 /// use fitsrotate_rs::write_fits_cube;
-/// write_fits_cube("test.fits", fits_cube, mode, old_file, true);
+/// let options = WriteOptions { overwrite: true, ..WriteOptions::default() };
+/// write_fits_cube("test.fits", fits_cube, mode, &mut old_file, options);
 /// ```
-fn write_fits_cube(
+fn write_fits_cube<T: FitsPixel>(
     filename: &str,
-    fits_cube: ArrayD<f32>,
+    fits_cube: ArrayD<T>,
     mode: &[usize],
-    mut old_file: FitsFile,
-    overwrite: bool,
-) -> Result<(), Error>{
+    old_file: &mut FitsFile,
+    options: WriteOptions,
+) -> Result<(), FitsRotateError>{
+    let WriteOptions {
+        overwrite,
+        header,
+        reproducible,
+        fill_missing_wcs,
+        mmap_write,
+    } = options;
     // Check if file exists
     if Path::new(filename).exists() {
         if overwrite {
             std::fs::remove_file(filename)?;
-            println!("File {} already exists, overwriting", filename);
+            warn(&format!("File {} already exists, overwriting", filename));
         } else {
-            return Err(Error::ExistingFile(filename.to_string()));
+            return Err(FitsRotateError::OutputExists {
+                filename: filename.to_string(),
+            });
         }
     };
 
     let description = ImageDescription {
-        data_type: ImageType::Double,
+        data_type: T::IMAGE_TYPE,
         dimensions: fits_cube.shape(),
     };
     let mut fits_file = FitsFile::create(filename)
@@ -173,99 +565,3067 @@ fn write_fits_cube(
         .open()?;
 
     let hdu = fits_file.hdu(0)?;
-    // hdu.copy_to(&mut old_file, &mut fits_file)?;
+    // hdu.copy_to(old_file, &mut fits_file)?;
 
     let shape = fits_cube.shape();
-    let old_axes: Vec<usize> = (0..shape.len()).collect();
-    let old_mode:Vec<usize> = (1..shape.len()+1).collect();
-    let new_axes: Vec<usize> = mode.iter().map(|x| x - 1).collect();
+    // The input has more axes than the output if an axis was dropped
+    let n_input_axes = mode.iter().copied().max().unwrap_or(0).max(shape.len());
+
+    // Write the repaired header, leaving the rotated keys to the loop below
+    if let Some(header) = header {
+        let mut skip: Vec<String> = WCS_CARD_STUBS
+            .iter()
+            .chain(OPTIONAL_WCS_CARD_STUBS.iter())
+            .flat_map(|stub| (1..n_input_axes + 1).map(move |idx| stub.to_string() + &idx.to_string()))
+            .collect();
+        skip.extend(WCS_MATRIX_STUBS.iter().flat_map(|stub| {
+            (1..n_input_axes + 1)
+                .flat_map(move |i| (1..n_input_axes + 1).map(move |j| format!("{}{}_{}", stub, i, j)))
+        }));
+        // The output gets its own DATE
+        skip.push("DATE".to_string());
+        // The distortion cards are renumbered below
+        skip.extend(
+            header
+                .cards
+                .iter()
+                .map(|card| card.keyword())
+                .filter(|keyword| matches!(distortion_axis(keyword), Some((_, axis, _)) if axis <= n_input_axes)),
+        );
+        // The cards CFITSIO has already written (e.g. EXTNAME) are not written twice
+        skip.extend(
+            FitsHeader::read(&mut fits_file)?
+                .cards
+                .iter()
+                .filter(|card| !card.is_commentary())
+                .map(|card| card.keyword()),
+        );
+        header.write(&mut fits_file, &skip)?;
+    } else {
+        // Without a repaired header both are kept as they are, so B1950 coordinates stay B1950
+        for keyword in EQUINOX_CARDS {
+            if let Ok(head_val) = hdu.read_key::<f64>(old_file, keyword) {
+                hdu.write_key(&mut fits_file, keyword, head_val)?;
+            }
+        }
+    }
     
-    // Swap the keys in the header
-    for card_stub in ["CTYPE", "CRVAL", "CDELT", "CRPIX", "CUNIT"] {
+    // Swap the keys in the header: output axis n takes the keys of input axis mode[n - 1]
+    for card_stub in WCS_CARD_STUBS.iter().chain(OPTIONAL_WCS_CARD_STUBS.iter()) {
         for fits_idx in 1..shape.len() + 1 {
-            let old_card = card_stub.to_owned() + &fits_idx.to_string();
-            let new_card = card_stub.to_owned() + &mode[fits_idx - 1].to_string();
-            let head_val: String = hdu.read_key(&mut old_file, &old_card).unwrap();
-            hdu.write_key(&mut fits_file, &new_card, head_val).unwrap();
-            }  
+            let old_card = card_stub.to_string() + &mode[fits_idx - 1].to_string();
+            let new_card = card_stub.to_string() + &fits_idx.to_string();
+            // Numeric values are read as numbers, so that they are written as numbers at full precision
+            let copied = if STRING_WCS_CARD_STUBS.contains(card_stub) {
+                hdu.read_key::<String>(old_file, &old_card)
+                    .map(|head_val| hdu.write_key(&mut fits_file, &new_card, head_val))
+            } else {
+                hdu.read_key::<f64>(old_file, &old_card)
+                    .map(|head_val| write_f64_key(&mut fits_file, &new_card, head_val))
+            };
+            match copied {
+                Ok(written) => written?,
+                Err(_) if OPTIONAL_WCS_CARD_STUBS.contains(card_stub) => continue,
+                Err(_) if fill_missing_wcs && *card_stub != "CTYPE" => {
+                    match missing_wcs_default(card_stub) {
+                        Some(default) => hdu.write_key(&mut fits_file, &new_card, default)?,
+                        None => hdu.write_key(&mut fits_file, &new_card, "")?,
+                    }
+                    warn(&format!("{} is missing, wrote a default {}", old_card, new_card));
+                    continue;
+                }
+                Err(_) => {
+                    return Err(FitsRotateError::MissingKeyword {
+                        keyword: old_card,
+                        filename: old_file.file_path().display().to_string(),
+                    })
+                }
+            }
+        }
+    }
+    // Matrix entry (i, j) of the output is entry (mode[i - 1], mode[j - 1]) of the input
+    for card_stub in WCS_MATRIX_STUBS.iter() {
+        for i in 1..shape.len() + 1 {
+            for j in 1..shape.len() + 1 {
+                let old_card = format!("{}{}_{}", card_stub, mode[i - 1], mode[j - 1]);
+                if let Ok(head_val) = hdu.read_key::<f64>(old_file, &old_card) {
+                    write_f64_key(&mut fits_file, &format!("{}{}_{}", card_stub, i, j), head_val)?;
+                }
+            }
+        }
+    }
+
+    // The Paper IV distortion cards index the axes in their names and values
+    let old_header = match header {
+        Some(header) => header.clone(),
+        None => {
+            old_file.primary_hdu()?;
+            FitsHeader::read(old_file)?
+        }
+    };
+    permute_distortion_cards(&old_header, mode)?.write(&mut fits_file, &[])?;
+    write_date(&mut fits_file, reproducible)?;
+    // NAXISn are written from the shape again, over the cards CFITSIO wrote
+    // from the image description, and checked once the data is written
+    let dimensions = fits_cube.shape().to_vec();
+    for (fits_idx, length) in dimensions.iter().rev().enumerate() {
+        update_i64_key(&mut fits_file, &format!("NAXIS{}", fits_idx + 1), *length as i64)?;
+    }
+    if mmap_write && raw_writable::<T>(filename) {
+        // The header is complete, so the data unit can be written once CFITSIO has closed the file
+        let extent = data_extent(&mut fits_file, 0)?;
+        check_naxis(&mut fits_file, filename, &dimensions)?;
+        drop(fits_file);
+        write_data_unit(filename, extent, fits_cube.view())?;
+        copy_extensions(old_file, FitsFile::edit(filename)?, filename)?;
+        return Ok(());
     }
-    hdu.write_image(&mut fits_file, &fits_cube.into_raw_vec())
+    // The raw data is only in FITS order if the cube is in standard layout, e.g. not a permuted view
+    let fits_cube = if fits_cube.is_standard_layout() {
+        fits_cube
+    } else {
+        fits_cube.as_standard_layout().into_owned()
+    };
+    hdu.write_image(&mut fits_file, &fits_cube.into_raw_vec_and_offset().0)?;
+    check_naxis(&mut fits_file, filename, &dimensions)?;
+    copy_extensions(old_file, fits_file, filename)?;
+    Ok(())
 }
 
+/// Check that the NAXISn cards of a written image match the shape of its data
+///
+/// # Arguments
+///
+/// * `fits_file` - The written FITS file, positioned at the image HDU
+/// * `filename` - The name of the FITS file, for error messages
+/// * `shape` - The shape of the data, in array order
+///
+fn check_naxis(fits_file: &mut FitsFile, filename: &str, shape: &[usize]) -> Result<(), FitsRotateError> {
+    let hdu = fits_file.primary_hdu()?;
+    let naxis = hdu.read_key::<i64>(fits_file, "NAXIS")? as usize;
+    let mut lengths: Vec<usize> = Vec::with_capacity(naxis);
+    for fits_idx in 1..naxis + 1 {
+        lengths.push(hdu.read_key::<i64>(fits_file, &format!("NAXIS{}", fits_idx))? as usize);
+    }
+    // The NAXISn cards are in FITS order, the reverse of the array order
+    let expected: Vec<usize> = shape.iter().rev().copied().collect();
+    if lengths != expected {
+        return Err(FitsRotateError::Message(format!(
+            "The NAXISn cards of {} ({:?}) do not match the shape of the data written ({:?})",
+            filename, lengths, expected
+        )));
+    }
+    Ok(())
+}
 
-/// Parse the mode string
-/// 
+/// Check if an extension of a FITS file is copied to its rotated output
+///
+/// The distortion lookup tables (WCSDVARR and D2IMARR extensions), which
+/// the rotated distortion cards refer to, and binary tables, which do not
+/// depend on the axes of the image, are copied. A header backup from an
+/// earlier rotation is not, since the output gets its own, and neither are
+/// other image extensions, whose axes would no longer match the image.
+///
 /// # Arguments
-/// 
-/// * `mode` - The mode string
-/// * `cube` - The FITS cube
-/// 
+///
+/// * `fits_file` - The original FITS file
+/// * `hdu_idx` - The (0-based) HDU of the extension
+///
 /// # Returns
-/// 
-/// * `Result<Vec<usize>, Error>` - The mode as a vector of integers
-/// 
-fn parse_mode(mode: &str, cube: &ArrayD<f32>) -> Result<Vec<usize>,Error> {
-    // Check that the mode is valid
-    // First check that length of mode is equal to the number of axes in the cube
-    if mode.len() != cube.ndim() {
-        return Err(Error::Message(format!("Mode length {} does not match number of axes in cube ({})", mode.len(), cube.ndim())));
-    }
-    // Now check that all elements can be converted to integers
-    let mut mode_int: Vec<usize> = Vec::new();
-    let mode_split: Vec<&str> = mode.split("").collect();
-    for m in mode_split {
-        // Catch empty strings
-        if m.is_empty() {
-            continue;
-        }
-        match m.parse::<usize>() {
-            Ok(m_int) => {
-                mode_int.push(m_int);
-            }
-            Err(e) => {
-                return Err(Error::Message(format!("Could not convert mode element {} to integer: {}", m, e)));
-            }
+///
+/// * `Result<bool, Error>` - Whether the extension is copied
+///
+fn is_copied_extension(fits_file: &mut FitsFile, hdu_idx: usize) -> Result<bool, Error> {
+    let hdu = fits_file.hdu(hdu_idx)?;
+    let extname: String = hdu.read_key(fits_file, "EXTNAME").unwrap_or_default();
+    let xtension: String = hdu.read_key(fits_file, "XTENSION").unwrap_or_default();
+    let extname = extname.trim();
+    Ok(DISTORTION_EXTNAMES.contains(&extname) || (xtension.trim() == "BINTABLE" && extname != BACKUP_EXTNAME))
+}
+
+/// Copy the extensions of a FITS file that are kept in its rotated output
+///
+/// The extensions picked by `is_copied_extension` are copied unchanged,
+/// in order: as raw blocks if the original is an uncompressed file, and
+/// through CFITSIO otherwise. The distortion lookup tables are indexed by
+/// their EXTVER, which the distortion cards refer to, so this keeps them
+/// valid. The new file is closed either way.
+///
+/// # Arguments
+///
+/// * `old_file` - The original FITS file
+/// * `fits_file` - The FITS file to append the extensions to
+/// * `filename` - The name of the FITS file to append the extensions to
+///
+fn copy_extensions(old_file: &mut FitsFile, mut fits_file: FitsFile, filename: &str) -> Result<(), Error> {
+    let mut hdus: Vec<usize> = Vec::new();
+    for hdu_idx in 1..old_file.num_hdus()? {
+        if is_copied_extension(old_file, hdu_idx)? {
+            hdus.push(hdu_idx);
+        }
+    }
+    if is_plain_fits(old_file.file_path()) {
+        drop(fits_file);
+        append_hdu_blocks(old_file, Path::new(filename), &hdus)?;
+    } else {
+        for hdu_idx in hdus {
+            copy_hdu(old_file, &mut fits_file, hdu_idx)?;
         }
     }
+    old_file.primary_hdu()?;
+    Ok(())
+}
 
-    Ok(mode_int)
+/// The value written for a missing numeric WCS keyword by `--fill-missing-wcs`
+///
+/// # Arguments
+///
+/// * `card_stub` - The keyword without its axis number, e.g. `CDELT`
+///
+/// # Returns
+///
+/// * `Option<f64>` - The default value, or `None` for a string keyword (`CUNIT`), which defaults to ''
+///
+fn missing_wcs_default(card_stub: &str) -> Option<f64> {
+    match card_stub {
+        "CDELT" | "CRPIX" => Some(1.0),
+        "CRVAL" => Some(0.0),
+        _ => None,
+    }
 }
 
-/// Simple program rotating the axes of a FITS cube
-#[derive(Parser, Debug)]
-#[command(author, version, about, long_about = None)]
-struct Args {
-    /// The FITS file
-    filename: String,
-    /// Mode of rotation - a sequence of integers specifying the order of the axes
-    /// (e.g. 321 for a 3D cube)
-    mode: String,
-    /// Overwrite the FITS file if it already exists
-    #[arg(short='o', long="overwrite")]
-    overwrite: bool,
+/// A new scratch FITS file in the temporary directory, removed when it is dropped
+fn scratch_file() -> Result<NamedTempFile, FitsRotateError> {
+    Ok(tempfile::Builder::new().prefix("fitsrotate_rs.").suffix(".fits").tempfile()?)
 }
 
-fn main() -> Result<(), Error> {
-    let args = Args::parse();
+/// Read a FITS cube from a reader, e.g. a socket, rather than a named file
+///
+/// CFITSIO can only read files, so everything is first copied from the
+/// reader to a scratch file in the temporary directory, which is read and
+/// then removed. The data is therefore copied twice, and needs room in the
+/// temporary directory. Reading straight from memory would need CFITSIO's
+/// `mem://` extended filename syntax.
+///
+/// # Arguments
+///
+/// * `reader` - Where to read the FITS file from
+///
+/// # Returns
+///
+/// * `Result<(ArrayD<T>, FitsHeader), FitsRotateError>` - The FITS cube and its primary header
+///
+fn read_fits_cube_from_reader<T, R: Read>(mut reader: R) -> Result<(ArrayD<T>, FitsHeader), FitsRotateError>
+where
+    ArrayD<T>: ReadImage,
+{
+    let mut scratch = scratch_file()?;
+    std::io::copy(&mut reader, &mut scratch)?;
+    scratch.flush()?;
+    let mut fits_file = FitsFile::open(scratch.path())?;
+    let hdu = fits_file.primary_hdu()?;
+    let header = FitsHeader::read(&mut fits_file)?;
+    let data = hdu.read_image(&mut fits_file)?;
+    Ok((data, header))
+}
 
-    let filename = args.filename;
-    let out_filename = filename.replace(".fits", ".rot.fits");
-    let check = check_file_exists(&out_filename, args.overwrite)?;
-    let (fits_cube, mut fits_file) = read_fits_cube(&filename);
-
-    let mode_vec = parse_mode(&args.mode, &fits_cube)?;
-
-    println!("Original FITS cube shape: {:?}", fits_cube.shape());
-    let rotated_fits_cube = rotate_fits_cube_axes(fits_cube, &mut fits_file, &mode_vec);
-    println!("Rotated FITS cube shape: {:?}", rotated_fits_cube.shape());
-    let _ = write_fits_cube(
-        &out_filename,
-        rotated_fits_cube,
-        &mode_vec,
-        fits_file,
-        args.overwrite,
-    )?;
-    println!("Wrote rotated FITS cube to {}", out_filename);
-    println!("Done!");
-    Ok(())
+/// Write a FITS cube to a writer, e.g. a socket, rather than a named file
+///
+/// CFITSIO can only write to files, so the cube is written to a scratch
+/// file in the temporary directory, which is streamed to the writer and
+/// then removed. Writing through a CFITSIO memory buffer would avoid the
+/// disk altogether.
+///
+/// # Arguments
+///
+/// * `writer` - Where to write the FITS file
+/// * `fits_cube` - The FITS cube, written as 32-bit (f32) or 64-bit (f64) floats
+/// * `header` - The header to write, besides the structural cards
+///
+fn write_fits_cube_to_writer<T: FitsPixel, W: Write>(
+    mut writer: W,
+    fits_cube: ArrayD<T>,
+    header: &FitsHeader,
+) -> Result<(), FitsRotateError> {
+    let scratch = scratch_file()?;
+    let description = ImageDescription {
+        data_type: T::IMAGE_TYPE,
+        dimensions: fits_cube.shape(),
+    };
+    // CFITSIO creates the file again under the name the scratch file holds
+    let mut fits_file = FitsFile::create(scratch.path())
+        .with_custom_primary(&description)
+        .overwrite()
+        .open()?;
+    let hdu = fits_file.primary_hdu()?;
+    // The cards CFITSIO has already written (e.g. EXTNAME) are not written twice
+    let written: Vec<String> = FitsHeader::read(&mut fits_file)?
+        .cards
+        .iter()
+        .filter(|card| !card.is_commentary())
+        .map(|card| card.keyword())
+        .collect();
+    header.write(&mut fits_file, &written)?;
+    hdu.write_image(&mut fits_file, &fits_cube.as_standard_layout().into_owned().into_raw_vec_and_offset().0)?;
+    // Close the file so that everything is flushed before it is read back
+    drop(fits_file);
+    std::io::copy(&mut std::fs::File::open(scratch.path())?, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// The header of a streamed rotation: the input header with its WCS rotated and the rotation in HISTORY
+///
+/// # Arguments
+///
+/// * `header` - The primary header of the input
+/// * `filename` - The input, for the HISTORY card
+/// * `mode` - The mode string
+/// * `mode_vec` - The new ordering of the axes
+/// * `lengths` - The length of each output axis, in FITS order
+///
+/// # Returns
+///
+/// * `Result<FitsHeader, FitsRotateError>` - The header to write with the rotated cube
+///
+fn stream_header(
+    header: &FitsHeader,
+    filename: &str,
+    mode: &str,
+    mode_vec: &[usize],
+    lengths: &[usize],
+) -> Result<FitsHeader, FitsRotateError> {
+    let naxis = mode_vec.len();
+    let permutation = AxisPermutation::new(mode_vec).expect("parse_mode only returns permutations");
+    let mut streamed = header.clone();
+    for keyword in rotated_keywords(naxis) {
+        streamed.remove_all_cards(&keyword);
+    }
+    streamed.cards.retain(|card| distortion_axis(&card.keyword()).is_none());
+    streamed.remove_all_cards("END");
+    let flips = parse_flips(mode);
+    // The input header is kept, so its AIPS beam records follow a reversed spectral axis
+    let ctype = |axis: usize| {
+        let keyword = format!("CTYPE{}", axis);
+        header.cards.iter().find(|card| card.keyword() == keyword).and_then(|card| card.value())
+    };
+    let spectral_axis =
+        (1..naxis + 1).find(|axis| ctype(*axis).and_then(|ctype| axis_role(&ctype)) == Some(AxisRole::Spectral));
+    if let Some(spectral_axis) = spectral_axis {
+        let n_channels = lengths[output_axis(spectral_axis, mode_vec) - 1];
+        if let Some(channels) = channel_order(n_channels, None, flips.contains(&spectral_axis)) {
+            renumber_beam_channels(&mut streamed, &channels);
+        }
+    }
+    let wcs = rotated_wcs_cards(header, permutation, &flips, lengths);
+    streamed.cards.extend(wcs.cards);
+    streamed.cards.extend(permute_distortion_cards(header, mode_vec)?.cards);
+    for history in [
+        format!("fitsrotate_rs: reordered the axes of {} with mode {}", filename, mode),
+        version_history(false),
+    ] {
+        // HISTORY text is wrapped over as many cards as it needs, as CFITSIO does
+        let chars: Vec<char> = history.chars().collect();
+        for chunk in chars.chunks(CARD_LENGTH - 8) {
+            let record = format!("HISTORY {}", chunk.iter().collect::<String>());
+            streamed.cards.push(HeaderCard::new(&format!("{:<width$}", record, width = CARD_LENGTH)));
+        }
+    }
+    Ok(streamed)
+}
+
+/// Rotate a FITS file and write the result to standard output, for the `stream` subcommand
+///
+/// Nothing else is printed to standard output, so that the rotated file
+/// can be piped to another program. Only the primary HDU is written. A
+/// filename of `-` reads the FITS file from standard input, through a
+/// scratch file (see `read_fits_cube_from_reader`).
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file, or `-` for standard input
+/// * `mode` - The mode string
+///
+fn stream_file(filename: &str, mode: &str) -> Result<(), FitsRotateError> {
+    if filename == "-" {
+        // The type of the data is only known once it has been read, so it is read at full precision
+        let (fits_cube, header) = read_fits_cube_from_reader::<f64, _>(std::io::stdin().lock())?;
+        let bitpix = header
+            .cards
+            .iter()
+            .find(|card| card.keyword() == "BITPIX")
+            .and_then(|card| card.value());
+        return match bitpix.as_deref() {
+            Some("-64") => stream_cube(fits_cube, &header, "standard input", mode),
+            _ => stream_cube(fits_cube.mapv(|v| v as f32), &header, "standard input", mode),
+        };
+    }
+    let mut fits_file = FitsFile::open(filename)?;
+    let hdu = fits_file
+        .primary_hdu()
+        .map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    match hdu.info {
+        HduInfo::ImageInfo {
+            image_type: ImageType::Double,
+            ..
+        } => stream_file_as::<f64>(filename, mode),
+        _ => stream_file_as::<f32>(filename, mode),
+    }
+}
+
+/// Rotate a FITS file to standard output, with the data read as `T`
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `mode` - The mode string
+///
+fn stream_file_as<T: FitsPixel>(filename: &str, mode: &str) -> Result<(), FitsRotateError>
+where
+    ArrayD<T>: ReadImage,
+{
+    let (fits_cube, mut fits_file) = read_fits_cube::<T>(filename)?;
+    let header = FitsHeader::read(&mut fits_file)?;
+    stream_cube(fits_cube, &header, filename, mode)
+}
+
+/// Rotate a cube that has been read and write it to standard output
+///
+/// # Arguments
+///
+/// * `fits_cube` - The FITS cube
+/// * `header` - Its primary header
+/// * `source` - Where it was read from, for the HISTORY card
+/// * `mode` - The mode string
+///
+fn stream_cube<T: FitsPixel>(
+    fits_cube: ArrayD<T>,
+    header: &FitsHeader,
+    source: &str,
+    mode: &str,
+) -> Result<(), FitsRotateError> {
+    let mode_vec = parse_mode(mode, fits_cube.ndim())?;
+    let flipped_fits_cube = flip_fits_cube_axes(fits_cube, &parse_flips(mode));
+    let rotated_fits_cube = FitsCube::permute_inplace(flipped_fits_cube, &array_mode(&mode_vec));
+    let lengths: Vec<usize> = rotated_fits_cube.shape().iter().rev().copied().collect();
+    let header = stream_header(header, source, mode, &mode_vec, &lengths)?;
+    write_fits_cube_to_writer(std::io::stdout().lock(), rotated_fits_cube, &header)
+}
+
+/// Split a mode string into its axes and flip markers
+///
+/// Modes of cubes with up to 9 axes can be given as digits (e.g. `3-12`).
+/// Larger cubes need the axes separated by commas (e.g. `10,1,-2,...`),
+/// which works for any number of axes.
+///
+/// # Arguments
+///
+/// * `mode` - The mode string
+///
+/// # Returns
+///
+/// * `Result<Vec<(usize, bool)>, FitsRotateError>` - Each axis, and whether it is flipped
+///
+fn mode_tokens(mode: &str) -> Result<Vec<(usize, bool)>, FitsRotateError> {
+    let tokens: Vec<String> = if mode.contains(',') {
+        mode.split(',').map(|token| token.trim().to_string()).collect()
+    } else {
+        // Each digit is an axis, with an optional minus sign in front
+        let mut tokens: Vec<String> = Vec::new();
+        let mut flip_next = false;
+        for c in mode.chars() {
+            if c == '-' {
+                flip_next = true;
+                continue;
+            }
+            tokens.push(if flip_next { format!("-{}", c) } else { c.to_string() });
+            flip_next = false;
+        }
+        tokens
+    };
+    tokens
+        .iter()
+        .map(|token| {
+            let (axis, flip) = match token.strip_prefix('-') {
+                Some(axis) => (axis, true),
+                None => (token.as_str(), false),
+            };
+            axis.parse::<usize>().map(|axis| (axis, flip)).map_err(|e| {
+                FitsRotateError::Message(format!("Could not convert mode element {} to integer: {}", token, e))
+            })
+        })
+        .collect()
+}
+
+/// Format a mode as a string that `parse_mode` accepts
+///
+/// Modes of up to 9 axes are written as digits, longer ones separated by
+/// commas.
+///
+/// # Arguments
+///
+/// * `mode` - The mode
+///
+/// # Returns
+///
+/// * `String` - The mode string
+///
+fn format_mode(mode: &[usize]) -> String {
+    let axes: Vec<String> = mode.iter().map(|axis| axis.to_string()).collect();
+    if mode.len() > 9 {
+        axes.join(",")
+    } else {
+        axes.concat()
+    }
+}
+
+/// Parse the mode string
+/// 
+/// # Arguments
+/// 
+/// * `mode` - The mode string
+/// * `naxis` - The number of axes in the FITS cube (NAXIS)
+/// 
+/// # Returns
+/// 
+/// * `Result<Vec<usize>, FitsRotateError>` - The mode as a vector of integers
+/// 
+fn parse_mode(mode: &str, naxis: usize) -> Result<Vec<usize>, FitsRotateError> {
+    let mode_int: Vec<usize> = mode_tokens(mode)?.into_iter().map(|(axis, _)| axis).collect();
+    // Check that the mode has one entry per axis of the cube
+    if mode_int.len() != naxis {
+        return Err(FitsRotateError::ModeLength {
+            mode: mode.to_string(),
+            length: mode_int.len(),
+            naxis,
+            ctypes: Vec::new(),
+        });
+    }
+    // Then check that every axis appears exactly once
+    if AxisPermutation::new(&mode_int).is_none() {
+        let (axis, count) = (1..naxis + 1)
+            .map(|axis| (axis, mode_int.iter().filter(|m| **m == axis).count()))
+            .find(|(_, count)| *count != 1)
+            .unwrap();
+        return Err(FitsRotateError::Message(format!(
+            "Mode {} must name each axis from 1 to {} exactly once, but axis {} appears {} times",
+            mode, naxis, axis, count
+        )));
+    }
+
+    Ok(mode_int)
+}
+
+/// Parse the flipped axes from the mode string
+///
+/// As in Miriad's `reorder`, a minus sign in front of an axis number means
+/// that (input) axis is reversed, e.g. `3-12` reverses axis 1.
+///
+/// # Arguments
+///
+/// * `mode` - The mode string
+///
+/// # Returns
+///
+/// * `Vec<usize>` - The (FITS, 1-based) input axes to flip
+///
+fn parse_flips(mode: &str) -> Vec<usize> {
+    mode_tokens(mode)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(_, flip)| *flip)
+        .map(|(axis, _)| axis)
+        .collect()
+}
+
+/// Print the HDUs of a FITS file: their index, type, EXTNAME and shape
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+///
+fn list_hdus(filename: &str) -> Result<(), FitsRotateError> {
+    let mut fits_file = FitsFile::open(filename)?;
+    for hdu_idx in 0..fits_file.num_hdus()? {
+        let hdu = fits_file.hdu(hdu_idx)?;
+        let xtension: String = hdu
+            .read_key(&mut fits_file, "XTENSION")
+            .unwrap_or_else(|_| "PRIMARY".to_string());
+        let extname: String = hdu.read_key(&mut fits_file, "EXTNAME").unwrap_or_default();
+        let description = match &hdu.info {
+            HduInfo::ImageInfo { shape, .. } if shape.is_empty() => "no data".to_string(),
+            // The NAXISn are in FITS order, the reverse of the array order
+            HduInfo::ImageInfo { shape, image_type } => {
+                let naxes: Vec<String> = shape.iter().rev().map(|length| length.to_string()).collect();
+                format!("{} {:?}", naxes.join(" x "), image_type)
+            }
+            HduInfo::TableInfo {
+                column_descriptions,
+                num_rows,
+            } => format!("{} rows x {} columns", num_rows, column_descriptions.len()),
+            HduInfo::AnyInfo => String::new(),
+        };
+        println!(
+            "{} {:<8} {:<10} {}",
+            label(&format!("HDU {}:", hdu_idx)),
+            xtension.trim(),
+            extname.trim(),
+            description
+        );
+    }
+    Ok(())
+}
+
+/// Print the CTYPE of each axis of a FITS file
+///
+/// Only the header is read. For a file on an HTTP(S) server, only the
+/// header blocks are downloaded.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file, or its URL
+///
+fn list_ctypes(filename: &str) -> Result<(), FitsRotateError> {
+    let ctypes = if is_url(filename) {
+        header_ctypes(&read_fits_header_from_url(filename)?)?
+    } else {
+        let mut fits_file = FitsFile::open(filename)?;
+        read_ctypes(&mut fits_file).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?
+    };
+    for (idx, ctype) in ctypes.iter().enumerate() {
+        println!("{} {}", label(&format!("AXIS{}:", idx + 1)), ctype);
+    }
+    Ok(())
+}
+
+/// Print the possible modes of a FITS file, with the order of the axes each gives
+///
+/// Only the header is read.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `json` - Print the modes as JSON rather than a table
+///
+fn print_modes(filename: &str, json: bool) -> Result<(), FitsRotateError> {
+    let mut fits_file = FitsFile::open(filename)?;
+    let ctypes = read_ctypes(&mut fits_file).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let entries = list_modes(&ctypes);
+    if json {
+        println!("{}", modes_json(&entries));
+        return Ok(());
+    }
+    for line in modes_table(&entries) {
+        println!("{}", line);
+    }
+    if ctypes.len() > modes::MAX_FULL_NAXIS {
+        println!(
+            "{}",
+            warning(&format!(
+                "{} axes have too many modes to list; only swaps of two axes and rolls are shown",
+                ctypes.len()
+            ))
+        );
+    }
+    Ok(())
+}
+
+/// Print a mode as a matrix, with rows and columns labelled by CTYPE
+///
+/// The matrix takes the pixel coordinates of the input (columns) to those
+/// of the output (rows), as a `PCi_j` or `CDi_j` matrix takes pixel to
+/// intermediate world coordinates. Only the header is read.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `mode` - The mode string
+/// * `signed` - Print the rotation matrix, with -1 for reversed axes, rather than the permutation matrix (see
+///   `matrix_table`)
+///
+fn print_mode_matrix(filename: &str, mode: &str, signed: bool) -> Result<(), FitsRotateError> {
+    let mut fits_file = FitsFile::open(filename)?;
+    let ctypes = read_ctypes(&mut fits_file).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let mode_vec = parse_mode(mode, ctypes.len()).map_err(|e| e.with_ctypes(ctypes.clone()))?;
+    if signed {
+        println!("{} p' = M p, mode {}", label("Rotation matrix:"), mode);
+    }
+    for line in matrix_table(&ctypes, &mode_vec, &parse_flips(mode), signed) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Print the axes of a FITS file in FITS and/or array order
+///
+/// FITS numbers the axes from 1 with the fastest (x) axis first, while the
+/// arrays in memory number them from 0 with the fastest axis last. Only the
+/// header is read.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `order` - The order to print, or `None` for both
+///
+fn print_axis_order(filename: &str, order: Option<AxisOrder>) -> Result<(), FitsRotateError> {
+    let mut fits_file = FitsFile::open(filename)?;
+    let hdu = fits_file
+        .primary_hdu()
+        .map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let shape = match &hdu.info {
+        HduInfo::ImageInfo { shape, .. } => shape.clone(),
+        _ => {
+            return Err(FitsRotateError::Message(format!(
+                "The primary HDU of {} is not an image",
+                filename
+            )))
+        }
+    };
+    let ctypes = read_ctypes(&mut fits_file)?;
+    let orders = match order {
+        Some(order) => vec![order],
+        None => vec![AxisOrder::Fits, AxisOrder::Array],
+    };
+    for order in orders {
+        match order {
+            AxisOrder::Fits => println!("{}", label("FITS order (1-based, x first):")),
+            AxisOrder::Array => println!("{}", label("Array order (0-based, x last):")),
+        }
+        for line in axis_order_lines(&ctypes, &shape, order) {
+            println!("  {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Work out the mode that puts a FITS file in the axis order of a reference
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `reference` - The reference FITS file
+///
+/// # Returns
+///
+/// * `Result<String, FitsRotateError>` - The mode
+///
+fn reference_mode(filename: &str, reference: &str) -> Result<String, FitsRotateError> {
+    let mut fits_file = FitsFile::open(filename)?;
+    let ctypes = read_ctypes(&mut fits_file)
+        .map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let mut reference_file = FitsFile::open(reference)?;
+    let reference_ctypes = read_ctypes(&mut reference_file)
+        .map_err(|e| FitsRotateError::unreadable_hdu(reference, e))?;
+    let mode = format_mode(&mode_from_reference(&ctypes, &reference_ctypes)?);
+    println!("Mode from the axes of {}: {}", reference, mode);
+    Ok(mode)
+}
+
+/// Work out the mode that puts a FITS file in a target axis order
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `target` - The target order, as roles or CTYPEs
+///
+/// # Returns
+///
+/// * `Result<String, FitsRotateError>` - The mode
+///
+fn target_mode(filename: &str, target: &[String]) -> Result<String, FitsRotateError> {
+    let mut fits_file = FitsFile::open(filename)?;
+    let ctypes = read_ctypes(&mut fits_file)
+        .map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let mode = format_mode(&mode_from_target(&ctypes, target)?);
+    println!("Mode for the order {}: {}", target.join(","), mode);
+    Ok(mode)
+}
+
+/// Choose the mode of a FITS file interactively
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+///
+/// # Returns
+///
+/// * `Result<String, FitsRotateError>` - The chosen mode
+///
+fn interactive_mode(filename: &str) -> Result<String, FitsRotateError> {
+    use std::io::IsTerminal;
+    if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+        return Err(FitsRotateError::Message("--interactive needs a terminal".to_string()));
+    }
+    let mut fits_file = FitsFile::open(filename)?;
+    let ctypes = read_ctypes(&mut fits_file)
+        .map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let hdu = fits_file.primary_hdu()?;
+    let mut entries: Vec<AxisEntry> = Vec::with_capacity(ctypes.len());
+    for (idx, ctype) in ctypes.into_iter().enumerate() {
+        let length: i64 = hdu.read_key(&mut fits_file, &format!("NAXIS{}", idx + 1))?;
+        entries.push(AxisEntry {
+            axis: idx + 1,
+            ctype,
+            length: length as usize,
+            flip: false,
+        });
+    }
+    let mode = choose_mode(entries)?;
+    println!("{} {}", label("Mode:"), mode);
+    Ok(mode)
+}
+
+/// Print which version of the FITS standard a file appears to conform to
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+///
+fn print_fits_version(filename: &str) -> Result<(), FitsRotateError> {
+    let mut fits_file = FitsFile::open(filename)?;
+    fits_file.primary_hdu()?;
+    let header = FitsHeader::read(&mut fits_file)?;
+    for line in fits_version_report(&header) {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+/// Print the mode that puts a file's axes in (RA, DEC, FREQ, STOKES) order
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+///
+fn print_suggested_mode(filename: &str) -> Result<(), FitsRotateError> {
+    let mut fits_file = FitsFile::open(filename)?;
+    let ctypes = read_ctypes(&mut fits_file)
+        .map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let (mode, reasons) = suggest_mode(&ctypes);
+    for reason in reasons {
+        println!("{}", reason);
+    }
+    println!("{} {}", label("Suggested mode:"), format_mode(&mode));
+    Ok(())
+}
+
+/// Print the build and platform details asked for in bug reports
+///
+/// # Arguments
+///
+/// * `json` - Print the report as JSON rather than text
+///
+fn print_env_report(json: bool) {
+    let report = EnvReport::collect();
+    if json {
+        println!("{}", report.to_json());
+    } else {
+        for line in report.lines() {
+            println!("{}", line);
+        }
+    }
+}
+
+/// Simple program rotating the axes of a FITS cube
+#[derive(Parser, Debug, Clone)]
+#[command(author, version, about, long_about = None, args_conflicts_with_subcommands = true)]
+struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// The FITS file (with --triplet, give only the mode)
+    filename: Option<String>,
+    /// Mode of rotation - a sequence of FITS axes, where the n-th is the input axis that
+    /// becomes output axis n (e.g. 321 for a 3D cube); a `-` before an axis flips it. Separate the axes
+    /// with commas for cubes with more than 9 axes (e.g. 10,1,2,3,4,5,6,7,8,9)
+    #[arg(allow_hyphen_values = true)]
+    mode: Option<String>,
+    /// Overwrite the FITS file if it already exists
+    #[arg(short='o', long="overwrite")]
+    overwrite: bool,
+    /// Repair common header problems (keyword case, card padding, missing
+    /// SIMPLE/END, duplicate keywords) and copy the repaired header to the output
+    #[arg(long="fix-header")]
+    fix_header: bool,
+    /// Convert a legacy CROTA rotation to the equivalent PC matrix
+    #[arg(long="modernize-wcs")]
+    modernize_wcs: bool,
+    /// Rotate a set of Stokes I, Q, U (and optionally V) cubes together,
+    /// after checking that their axes agree
+    #[arg(long="triplet", num_args=3..=4, value_name="FILE")]
+    triplet: Option<Vec<String>>,
+    /// Relative tolerance when comparing floating point header values
+    #[arg(long="tolerance", default_value_t=1e-9)]
+    tolerance: f64,
+    /// Print the CTYPE of each axis and exit without rotating
+    #[arg(long="list-ctypes")]
+    list_ctypes: bool,
+    /// Print the axes in FITS order (1-based, x first) and array order (0-based, x last), or only one of them,
+    /// and exit without rotating
+    #[arg(long="print-axis-order", value_name="ORDER", value_enum, num_args=0..=1, ignore_case=true)]
+    print_axis_order: Option<Option<AxisOrder>>,
+    /// Store the original header verbatim in a HDRBAK extension of the output
+    #[arg(long="backup-header")]
+    backup_header: bool,
+    /// Produce byte-identical outputs for identical inputs (DATE is taken
+    /// from SOURCE_DATE_EPOCH, or omitted)
+    #[arg(long="reproducible")]
+    reproducible: bool,
+    /// Write one output file per slice along this (FITS, 1-based) axis of the
+    /// rotated cube
+    #[arg(long="split-axis", value_name="AXIS")]
+    split_axis: Option<usize>,
+    /// Write a PNG preview of a plane of the rotated cube
+    #[arg(long="preview", value_name="PNG")]
+    preview: Option<String>,
+    /// Plane along the slowest axis to preview (default: the central plane)
+    #[arg(long="preview-plane", value_name="INDEX", requires="preview")]
+    preview_plane: Option<usize>,
+    /// The output FITS file (default: the input with .fits replaced by .rot.fits)
+    #[arg(long="output", value_name="OUT", conflicts_with="triplet")]
+    output: Option<String>,
+    /// Stack the files matching this glob pattern into a single cube
+    /// (the inverse of --split-axis)
+    #[arg(long="merge-files", value_name="FILE_GLOB", requires_all=["along", "output"])]
+    merge_files: Option<String>,
+    /// The (FITS, 1-based) axis to stack along with --merge-files
+    #[arg(long="along", value_name="AXIS", requires="merge_files")]
+    along: Option<usize>,
+    /// Report which version of the FITS standard the file conforms to and exit
+    #[arg(long="fits-version")]
+    fits_version: bool,
+    /// Choose the mode interactively (needs the `interactive` feature)
+    #[arg(long="interactive", conflicts_with_all=["mode", "triplet"])]
+    interactive: bool,
+    /// Pad the rotated cube to this shape, given as comma-separated
+    /// (FITS order) axis lengths, e.g. 512,512,288
+    #[arg(long="pad-to-shape", value_name="SHAPE")]
+    pad_to_shape: Option<String>,
+    /// How to fill the pixels added by --pad-to-shape
+    #[arg(long="pad-mode", value_enum, default_value_t=PadMode::Zero, requires="pad_to_shape")]
+    pad_mode: PadMode,
+    /// Take the mode from the axis order of this reference FITS file
+    #[arg(long="axes-from", value_name="REFERENCE", conflicts_with_all=["mode", "triplet", "interactive"])]
+    axes_from: Option<String>,
+    /// Do not lock the input file (for filesystems where locking hangs)
+    #[arg(long="no-lock")]
+    no_lock: bool,
+    /// Hold an exclusive lock on a .lock file next to each output while it is written, so that jobs writing the
+    /// same output take turns
+    #[arg(long="lock-output", conflicts_with="no_lock")]
+    lock_output: bool,
+    /// Give up if a file is still locked by another process after this many seconds
+    /// (default: wait forever)
+    #[arg(long="lock-timeout", value_name="SECONDS", conflicts_with="no_lock")]
+    lock_timeout: Option<f64>,
+    /// Create the directory of the output if it does not exist
+    #[arg(long="mkdirs")]
+    mkdirs: bool,
+    /// Work out the mode from the CTYPEs, to put the axes in the --target-order
+    #[arg(long="auto-mode", conflicts_with_all=["mode", "triplet", "interactive", "axes_from", "ops"])]
+    auto_mode: bool,
+    /// The axis order for --auto-mode, as roles (RA, DEC, FREQ, STOKES) or CTYPEs
+    #[arg(
+        long="target-order",
+        value_name="AXES",
+        value_delimiter=',',
+        default_values_t=["RA", "DEC", "FREQ", "STOKES"].map(String::from),
+        requires="auto_mode"
+    )]
+    target_order: Vec<String>,
+    /// Do several operations in one pass, e.g. 'rotate=4123,flip=freq,pad=512,512,288,4'
+    #[arg(long="ops", value_name="OPS", conflicts_with_all=["mode", "triplet", "interactive", "axes_from", "pad_to_shape"])]
+    ops: Option<String>,
+    /// Write the outputs under this directory, mirroring the directories of the inputs
+    #[arg(long="outdir", value_name="DIR", conflicts_with="output")]
+    outdir: Option<String>,
+    /// Rotate every FITS file under the input directory and its subdirectories; a glob pattern as the input has its
+    /// file name matched in every directory below its own
+    #[arg(long="recursive", conflicts_with="triplet")]
+    recursive: bool,
+    /// Leave out the files whose outputs all exist already, rather than failing or overwriting them
+    #[arg(long="skip-existing", conflicts_with="overwrite")]
+    skip_existing: bool,
+    /// Retry a file this many times after transient I/O errors (e.g. ESTALE on NFS)
+    #[arg(long="retries", visible_alias="max-retries", value_name="N", default_value_t=0)]
+    retries: usize,
+    /// How long to wait between retries (e.g. 5s or 500ms)
+    #[arg(long="retry-delay", value_name="DELAY", default_value="5s", value_parser=parse_duration)]
+    retry_delay: Duration,
+    /// The errno values treated as transient by --retries
+    #[arg(long="retry-errno", value_name="ERRNO", value_delimiter=',', default_values_t=TRANSIENT_ERRNOS)]
+    retry_errno: Vec<i32>,
+    /// The CFITSIO status codes treated as transient by --retries
+    #[arg(long="retry-status", value_name="STATUS", value_delimiter=',', default_values_t=TRANSIENT_FITS_STATUSES)]
+    retry_status: Vec<i32>,
+    /// Print the mode as a permutation matrix and exit without rotating
+    #[arg(long="print-permutation-matrix")]
+    print_permutation_matrix: bool,
+    /// Tell the kernel that the input is read sequentially, and drop it from the
+    /// page cache once read (Linux only)
+    #[arg(long="io-hints", value_name="BOOL", default_value_t=true, action=clap::ArgAction::Set)]
+    io_hints: bool,
+    /// Check crates.io for a newer release and exit (needs the `version-check` feature)
+    #[arg(long="version-check")]
+    version_check: bool,
+    /// Blank (set to NaN) the input pixels where this FITS mask is zero or NaN
+    #[arg(long="mask-file", value_name="PATH")]
+    mask_file: Option<String>,
+    /// Blank (set to NaN) the pixels inside the regions of this DS9 region file
+    #[arg(long="blank-region", value_name="REGION_FILE")]
+    blank_region: Option<String>,
+    /// Blank the pixels outside the regions of --blank-region instead
+    #[arg(long="blank-outside", requires="blank_region")]
+    blank_outside: bool,
+    /// Blank (set to NaN) the pixels below this value
+    #[arg(long="threshold-min", value_name="MIN", allow_hyphen_values=true)]
+    threshold_min: Option<f64>,
+    /// Blank (set to NaN) the pixels above this value
+    #[arg(long="threshold-max", value_name="MAX", allow_hyphen_values=true)]
+    threshold_max: Option<f64>,
+    /// Write a JSON description of the WCS of the output to this file
+    #[arg(long="wcs-sidecar", value_name="PATH", conflicts_with_all=["triplet", "split_axis"])]
+    wcs_sidecar: Option<String>,
+    /// Write the --wcs-sidecar as YAML instead of JSON
+    #[arg(long="yaml", requires="wcs_sidecar")]
+    yaml: bool,
+    /// Colour the output even if it is not a terminal or NO_COLOR is set
+    #[arg(long="color", conflicts_with="no_color")]
+    color: bool,
+    /// Do not colour the output
+    #[arg(long="no-color")]
+    no_color: bool,
+    /// Time N runs of reading, rotating and writing the file, writing to the temporary directory
+    #[arg(long="benchmark", value_name="N", conflicts_with_all=["triplet", "interactive", "axes_from"])]
+    benchmark: Option<usize>,
+    /// Copy the data through tiles of this shape (FITS order, e.g. 256,256) when rotating in --benchmark
+    #[arg(long="rechunk", value_name="SHAPE", requires="benchmark")]
+    rechunk: Option<String>,
+    /// Abort unless the CTYPE of an axis matches, e.g. 1=RA---TAN or 3=FREQ* (case-insensitive, may be repeated)
+    #[arg(long="require-ctype", value_name="AXIS=CTYPE", value_parser=parse_ctype_requirement)]
+    require_ctype: Vec<CtypeRequirement>,
+    /// Read each output back and check its WCS against the input, printing "WCS OK" or the inconsistencies
+    #[arg(long="report-wcs")]
+    report_wcs: bool,
+    /// Rotate only the files of task I of a job array (0-based); FILENAME may be a glob pattern
+    #[arg(long="task-index", value_name="I", requires="task_count")]
+    task_index: Option<usize>,
+    /// The number of tasks the files are shared between
+    #[arg(long="task-count", value_name="N", requires="task_index")]
+    task_count: Option<usize>,
+    /// Take the task from SLURM_ARRAY_TASK_ID and SLURM_ARRAY_TASK_COUNT if --task-index and --task-count are not given
+    #[arg(long="slurm")]
+    slurm: bool,
+    /// Write the image into an IMAGE extension, after a primary HDU with no data holding the global keywords
+    #[arg(long="mef-layout")]
+    mef_layout: bool,
+    /// The EXTNAME of the image extension with --mef-layout
+    #[arg(long="extname", value_name="NAME", default_value="SCI", requires="mef_layout")]
+    extname: String,
+    /// The EXTVER of the image extension with --mef-layout
+    #[arg(long="extver", value_name="N", requires="mef_layout")]
+    extver: Option<i64>,
+    /// Write INHERIT = T in the image extension with --mef-layout, so readers merge in the primary header
+    #[arg(long="inherit", requires="mef_layout")]
+    inherit: bool,
+    /// Write defaults (CDELT = 1.0, CRPIX = 1.0, CRVAL = 0.0, CUNIT = '') for WCS keywords missing from the input
+    #[arg(long="fill-missing-wcs")]
+    fill_missing_wcs: bool,
+    /// Keep only the plane of one Stokes parameter (I, Q, U, V, RR, LL, RL, LR, XX, YY, XY or YX), dropping the STOKES axis before the mode is applied
+    #[arg(long="stokes", value_name="LABEL", conflicts_with="benchmark")]
+    stokes: Option<String>,
+    /// Keep the STOKES axis, with length 1, with --stokes
+    #[arg(long="keep-stokes-axis", requires="stokes")]
+    keep_stokes_axis: bool,
+    /// Print the possible modes of the file, with the order of the axes each gives, and exit without rotating
+    #[arg(long="list-modes")]
+    list_modes: bool,
+    /// Print --list-modes as JSON
+    #[arg(long="json", requires="list_modes")]
+    json: bool,
+    /// Another mode to rotate the file with, from the same read of the data (may be repeated)
+    #[arg(
+        long="mode",
+        value_name="MODE",
+        allow_hyphen_values=true,
+        conflicts_with_all=["triplet", "ops", "auto_mode", "axes_from", "interactive", "benchmark"]
+    )]
+    modes: Vec<String>,
+    /// Replace the WCS of the output with that of a reference file, whose axis lengths must match the output
+    #[arg(long="copy-wcs-from", value_name="FILE", conflicts_with="modernize_wcs")]
+    copy_wcs_from: Option<String>,
+    /// The type to hold double precision (BITPIX = -64) data in while rotating it; float32 halves the memory
+    /// needed, and the output is written as float32 too
+    #[arg(long="working-dtype", value_name="DTYPE", value_enum, default_value_t=WorkingDtype::Float64)]
+    working_dtype: WorkingDtype,
+    /// Read and rotate the cube as usual, but discard the output rather than writing it, to time the read and
+    /// rotation alone
+    #[arg(
+        long="null-device",
+        conflicts_with_all=["split_axis", "report_wcs", "mef_layout", "wcs_sidecar", "backup_header"]
+    )]
+    null_device: bool,
+    /// Linearly interpolate the spectral axis onto a new grid, e.g. 'start=1.29GHz,width=1MHz,n=256'; channels
+    /// outside the input are blank
+    #[arg(long="spectral-regrid", value_name="GRID", conflicts_with="benchmark")]
+    spectral_regrid: Option<String>,
+    /// Smooth each spectrum with a kernel, e.g. 'boxcar=5' or 'gaussian=3chan', with the width in channels or in
+    /// spectral units (e.g. 'gaussian=2MHz'); blank channels are left out
+    #[arg(long="smooth-spectral", value_name="KERNEL", conflicts_with="benchmark")]
+    smooth_spectral: Option<String>,
+    /// Lay out the output header like the input header, card for card, editing the renumbered and changed
+    /// cards in place rather than appending them
+    #[arg(long="preserve-layout", conflicts_with_all=["split_axis", "mef_layout", "copy_wcs_from"])]
+    preserve_layout: bool,
+    /// Rewrite the WCS of an existing output in place to match the mode, without writing the data again, e.g.
+    /// after rotating with the wrong header
+    #[arg(
+        long="overwrite-header-only",
+        conflicts_with_all=["stokes", "spectral_regrid", "pad_to_shape", "split_axis", "null_device"]
+    )]
+    overwrite_header_only: bool,
+    /// Count the memory allocated, and report the peak of each phase and of the whole run (with the peak
+    /// resident size on Linux)
+    #[arg(long="mem-profile")]
+    mem_profile: bool,
+    /// Print the permutation matrix of the mode, with rows and columns labelled by CTYPE, and exit without
+    /// rotating
+    #[arg(long="print-rotation-matrix", conflicts_with="list_modes")]
+    print_rotation_matrix: bool,
+    /// Write a JSON-lines log of the run (one object per event) to this file, or to stderr with `-`, alongside
+    /// the usual messages
+    #[arg(long="json-logs")]
+    json_logs: Option<String>,
+    /// Smooth along this (FITS, 1-based) input axis with a NaN-aware rolling mean, before rotating
+    #[arg(long="smooth-axis", value_name="AXIS", requires="smooth_window")]
+    smooth_axis: Option<usize>,
+    /// The number of pixels in the rolling mean of --smooth-axis
+    #[arg(long="smooth-window", value_name="N", requires="smooth_axis")]
+    smooth_window: Option<usize>,
+    /// Check this many randomly chosen voxels of each output against the input, reading only those voxels
+    #[arg(
+        long="spot-check",
+        value_name="N",
+        conflicts_with_all=[
+            "mask_file", "blank_region", "threshold_min", "threshold_max", "spectral_regrid", "smooth_spectral",
+            "smooth_axis", "split_axis", "mef_layout", "null_device", "overwrite_header_only"
+        ]
+    )]
+    spot_check: Option<usize>,
+    /// The seed of the voxels chosen by --spot-check (default: from the clock, and printed)
+    #[arg(long="spot-seed", requires="spot_check")]
+    spot_seed: Option<u64>,
+    /// Cut the rotated cube into chunks along this axis, named by CTYPE (e.g. FREQ) or (FITS, 1-based) number
+    #[arg(
+        long="chunk-axis",
+        value_name="AXIS",
+        requires="chunk_size",
+        conflicts_with_all=[
+            "split_axis", "wcs_sidecar", "null_device", "preserve_layout", "overwrite_header_only", "spot_check"
+        ]
+    )]
+    chunk_axis: Option<String>,
+    /// The number of pixels in each chunk of --chunk-axis (the last chunk may be shorter)
+    #[arg(long="chunk-size", value_name="N", requires="chunk_axis")]
+    chunk_size: Option<usize>,
+    /// The name of each chunk, with the first and last pixels of the chunk as {start} and {end}, e.g.
+    /// 'part_{start:05}_{end:05}.fits' (default: <output>.<start>-<end>.fits)
+    #[arg(long="chunk-output", value_name="TEMPLATE", requires="chunk_axis")]
+    chunk_output: Option<String>,
+    /// Print the changelog of this version and exit
+    #[arg(long="changelog")]
+    changelog: bool,
+    /// Write the data of uncompressed outputs through a memory map rather than CFITSIO (Linux only;
+    /// other outputs are written by CFITSIO as usual)
+    #[arg(long="mmap-write")]
+    mmap_write: bool,
+    /// Read the data of uncompressed, unscaled float inputs through a memory map rather than CFITSIO (Linux only;
+    /// other inputs, and inputs narrowed by --working-dtype, are read by CFITSIO as usual)
+    #[arg(long="mmap-read")]
+    mmap_read: bool,
+    /// Apply the set, delete, rename and history operations of a YAML or JSON patch to the header of each output,
+    /// after the rotation has rewritten it
+    #[arg(long="header-patch", value_name="PATH", conflicts_with_all=["null_device", "overwrite_header_only"])]
+    header_patch: Option<String>,
+    /// Print the index, type, EXTNAME and shape of each HDU and exit without rotating
+    #[arg(long="list-hdus")]
+    list_hdus: bool,
+    /// Abort unless the BITPIX of the input is this value (8, 16, 32, 64, -32 or -64)
+    #[arg(long="require-bitpix", value_name="N", allow_hyphen_values=true, value_parser=parse_bitpix)]
+    require_bitpix: Option<i64>,
+    /// Abort unless the axes of the input are in this order, in FITS axis order, e.g. FREQ,STOKES,RA,DEC (`*` matches any axis)
+    #[arg(long="expect-order", value_name="AXES", value_delimiter=',')]
+    expect_order: Vec<String>,
+    /// Abort unless the axis lengths of the input match, in FITS axis order, e.g. '*,4,*,*' (`*` matches any length)
+    #[arg(long="expect-shape", value_name="LENGTHS", value_delimiter=',', value_parser=parse_expected_length)]
+    expect_shape: Vec<Option<usize>>,
+    /// Sort the planes along this axis, named by CTYPE (e.g. FREQ) or (FITS, 1-based) number, into ascending order of
+    /// their --sort-key
+    #[arg(
+        long="sort-axis",
+        value_name="AXIS",
+        requires="sort_key",
+        conflicts_with_all=["spectral_regrid", "smooth_spectral", "benchmark"]
+    )]
+    sort_axis: Option<String>,
+    /// The key of each plane of --sort-axis: a text file with one value per plane, or a table column as FILE[HDU]:COLUMN
+    #[arg(long="sort-key", value_name="KEYS", requires="sort_axis")]
+    sort_key: Option<String>,
+    /// Tile-compress each output with this algorithm, putting the image in a compressed extension as fpack does;
+    /// floating point pixels are compressed losslessly unless --quantize is given. The outputs of a tile-compressed
+    /// input are compressed like it (algorithm, tiles and dither) unless this is given; 'none' writes them
+    /// uncompressed
+    #[arg(
+        long="compress",
+        value_name="ALGORITHM",
+        value_enum,
+        conflicts_with_all=["mef_layout", "null_device", "overwrite_header_only"]
+    )]
+    compress: Option<Compression>,
+    /// Quantize floating point pixels in steps of the noise of each tile divided by q, e.g. 'q=4', before
+    /// compressing them (lossy), with --compress or like a tile-compressed input
+    #[arg(long="quantize", value_name="q=N", value_parser=parse_quantize)]
+    quantize: Option<f32>,
+    /// How to dither the pixels quantized by --quantize
+    #[arg(long="dither", value_name="METHOD", value_enum, default_value_t=Dither::Subtractive, requires="quantize")]
+    dither: Dither,
+    /// The seed (1 to 10000) of the dither of --quantize, for reproducible outputs (default: from the clock, or
+    /// from the data with --reproducible); it is recorded as ZDITHER0
+    #[arg(long="dither-seed", value_name="SEED", value_parser=parse_dither_seed, requires="quantize")]
+    dither_seed: Option<i32>,
+    /// The tiles of --compress, as N,M pixels of the last two spatial axes (e.g. 256,256; spectral and Stokes
+    /// axes always have tiles of 1 pixel); larger tiles compress better but take more memory to write and read
+    #[arg(long="tile-size", value_name="N,M", value_parser=parse_tile_size, requires="compress")]
+    tile_size: Option<[usize; 2]>,
+    /// Compare the number of pixels, blank pixels, minimum, maximum and mean of each output with the input,
+    /// allowing for the error of --quantize
+    #[arg(
+        long="stats",
+        conflicts_with_all=[
+            "mask_file", "blank_region", "threshold_min", "threshold_max", "spectral_regrid", "smooth_spectral",
+            "smooth_axis", "split_axis", "chunk_axis", "stokes", "pad_to_shape", "null_device",
+            "overwrite_header_only"
+        ]
+    )]
+    stats: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+enum Command {
+    /// Suggest the mode that puts the axes in (RA, DEC, FREQ, STOKES) order
+    SuggestMode {
+        /// The FITS file
+        filename: String,
+    },
+    /// Check that the headers of many files agree, e.g. before mosaicking
+    CheckConsistency {
+        /// The FITS files
+        #[arg(required = true)]
+        filenames: Vec<String>,
+        /// The keywords to compare, with `*` and `?` wildcards
+        #[arg(long="keys", value_delimiter=',', default_values_t=DEFAULT_CONSISTENCY_KEYS.map(String::from))]
+        keys: Vec<String>,
+        /// Relative tolerance for comparing numeric values
+        #[arg(long="tolerance", default_value_t=1e-9)]
+        tolerance: f64,
+    },
+    /// Repair the WCS of a file rotated by an older version that swapped the keywords the wrong way
+    FixHeader {
+        /// The rotated FITS file, which is edited in place
+        filename: String,
+        /// The FITS file it was rotated from
+        #[arg(long="original", value_name="FILE")]
+        original: String,
+        /// The mode of the rotation (default: the FROTMODE card of the rotated file)
+        #[arg(long="mode", allow_hyphen_values=true)]
+        mode: Option<String>,
+        /// Print the changes to the header without making them
+        #[arg(long="dry-run")]
+        dry_run: bool,
+    },
+    /// Print the version, cfitsio build and platform, for bug reports
+    Env {
+        /// Print the report as JSON
+        #[arg(long="json")]
+        json: bool,
+    },
+    /// Print the value of a cube at a world coordinate or pixel, e.g. to check a rotation against its input
+    Probe {
+        /// The FITS file
+        filename: String,
+        /// The world coordinates, converted to a pixel with the linear WCS, e.g. 'ra=150.21deg,dec=2.18deg,freq=1.4GHz'
+        #[arg(long="world", required_unless_present="pixel", conflicts_with="pixel")]
+        world: Option<String>,
+        /// The (1-based) pixel, in FITS axis order, e.g. 512,488,300
+        #[arg(long="pixel")]
+        pixel: Option<String>,
+        /// Print the spectrum through the position along this axis (e.g. freq) as CSV
+        #[arg(long="spectrum", value_name="AXIS")]
+        spectrum: Option<String>,
+    },
+    /// Summarize the rotations recorded in the HISTORY cards of a file
+    History {
+        /// The FITS file
+        filename: String,
+        /// Print the summary as JSON
+        #[arg(long="json")]
+        json: bool,
+    },
+    /// Rotate a FITS file and write the result to standard output, e.g. to pipe it to another program
+    Stream {
+        /// The FITS file, or - to read it from standard input
+        filename: String,
+        /// The new ordering of the axes, e.g. 312
+        #[arg(allow_hyphen_values=true)]
+        mode: String,
+    },
+}
+
+/// Rotate a single FITS file, retrying after transient I/O errors
+///
+/// A failed attempt restarts the whole file; the outputs left behind by the
+/// failed attempt are removed first, unless they existed beforehand.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `modes` - The mode strings, one per output
+/// * `args` - The command line arguments
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, FitsRotateError>` - The name of the rotated FITS file of each mode
+///
+fn rotate_file(filename: &str, modes: &[String], args: &Args) -> Result<Vec<String>, FitsRotateError> {
+    let policy = RetryPolicy {
+        retries: args.retries,
+        delay: args.retry_delay,
+        errnos: args.retry_errno.clone(),
+        statuses: args.retry_status.clone(),
+    };
+    let out_filenames: Vec<(String, bool)> = modes
+        .iter()
+        .map(|mode| output_filename(filename, mode, modes.len() > 1, args))
+        .map(|out_filename| {
+            out_filename.map(|out_filename| {
+                let existed = Path::new(&out_filename).exists();
+                (out_filename, existed)
+            })
+        })
+        .collect::<Result<_, _>>()?;
+    events::file_started(filename, modes);
+    let start = Instant::now();
+    let result = policy.run(
+        &format!("rotating {}", filename),
+        || rotate_file_once(filename, modes, args),
+        || {
+            for (out_filename, existed) in &out_filenames {
+                if !existed {
+                    let _ = std::fs::remove_file(out_filename);
+                }
+            }
+        },
+    );
+    match &result {
+        Ok(written) => events::file_finished(filename, Ok(written), start.elapsed()),
+        Err(e) => events::file_finished(filename, Err(&e.to_string()), start.elapsed()),
+    }
+    result
+}
+
+/// The name of the output file of a rotation
+///
+/// `{mode}` in `--output` is replaced by the mode. Without `--output`, the
+/// outputs of several modes are told apart by the mode, e.g.
+/// `cube.rot.4123.fits`.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `mode` - The mode string
+/// * `several` - Whether the file is rotated with several modes at once
+/// * `args` - The command line arguments
+///
+/// # Returns
+///
+/// * `Result<String, FitsRotateError>` - The output file name
+///
+fn output_filename(filename: &str, mode: &str, several: bool, args: &Args) -> Result<String, FitsRotateError> {
+    if let Some(output) = &args.output {
+        return Ok(output.replace("{mode}", mode));
+    }
+    let rotated = if several {
+        filename.replace(".fits", &format!(".rot.{}.fits", mode))
+    } else {
+        filename.replace(".fits", ".rot.fits")
+    };
+    match &args.outdir {
+        Some(outdir) => mirrored_filename(&rotated, outdir),
+        None => Ok(rotated),
+    }
+}
+
+/// The path of a file under an output directory, mirroring its directories
+///
+/// A relative path is kept as it is under the output directory (e.g.
+/// `obs1/cube.fits` becomes `<outdir>/obs1/cube.fits`), and an absolute path
+/// is mirrored from the root (`/data/cube.fits` becomes
+/// `<outdir>/data/cube.fits`). `.` components are dropped and `..` removes
+/// the directory before it, so `a/../cube.fits` is mirrored as `cube.fits`.
+/// A relative path that climbs above where it starts would land outside the
+/// output directory, so it is refused.
+///
+/// # Arguments
+///
+/// * `filename` - The file
+/// * `outdir` - The output directory
+///
+/// # Returns
+///
+/// * `Result<String, FitsRotateError>` - The mirrored path
+///
+fn mirrored_filename(filename: &str, outdir: &str) -> Result<String, FitsRotateError> {
+    let path = Path::new(filename);
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part),
+            // `..` at the root is the root itself
+            Component::ParentDir if parts.pop().is_none() && !path.has_root() => {
+                return Err(FitsRotateError::Message(format!(
+                    "{} is above the current directory, so it cannot be mirrored under --outdir (give its absolute path)",
+                    filename
+                )));
+            }
+            _ => {}
+        }
+    }
+    let mut mirrored = PathBuf::from(outdir);
+    mirrored.extend(parts);
+    Ok(mirrored.to_string_lossy().to_string())
+}
+
+/// An output of a rotation: one of the modes a file is rotated with
+struct ModeOutput {
+    /// The mode string
+    mode: String,
+    /// The parsed mode
+    mode_vec: Vec<usize>,
+    /// The shape (in FITS order) to pad the rotated cube to
+    pad_shape: Option<Vec<usize>>,
+    /// The (FITS, 1-based) axis of the rotated cube to cut into chunks, if any
+    chunk_axis: Option<usize>,
+    /// The output FITS file
+    out_filename: String,
+    /// The tile compression of the output, if any
+    compression: Option<CompressionSettings>,
+}
+
+/// Rotate a single FITS file with one or more modes
+///
+/// Double precision (BITPIX = -64) data is rotated and written as f64, and
+/// everything else as f32. The data is read once, whatever the number of
+/// modes.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `modes` - The mode strings, one per output
+/// * `args` - The command line arguments
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, FitsRotateError>` - The name of the rotated FITS file of each mode, or none if they were discarded or only their headers were rewritten
+///
+fn rotate_file_once(filename: &str, modes: &[String], args: &Args) -> Result<Vec<String>, FitsRotateError> {
+    // The outputs already hold the rotated data, so only their headers are rewritten, in place
+    if args.overwrite_header_only {
+        for mode in modes {
+            let out_filename = output_filename(filename, mode, modes.len() > 1, args)?;
+            if !Path::new(&out_filename).exists() {
+                return Err(FitsRotateError::Message(format!(
+                    "--overwrite-header-only needs an existing output, but {} does not exist",
+                    out_filename
+                )));
+            }
+            repair_header(&out_filename, filename, Some(mode), false)?;
+        }
+        return Ok(Vec::new());
+    }
+    // Everything that can be checked from the header is checked before the
+    // data is read, so that a typo does not cost a read of the whole cube
+    check_file_size(filename)?;
+    // A tile-compressed input is unpacked, and read from the unpacked file
+    // from here on; it is still named by its own name
+    let unpacked = unpack_input(filename).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let source = unpacked.as_ref().map_or(filename, |unpacked| unpacked.path.as_str());
+    if unpacked.is_some() {
+        println!("Unpacked the tile-compressed image of {}", filename);
+    }
+    let mut fits_file = FitsFile::open(source)?;
+    let hdu = fits_file
+        .primary_hdu()
+        .map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let (shape, image_type) = match &hdu.info {
+        HduInfo::ImageInfo { shape, image_type } => (shape.clone(), *image_type),
+        _ => {
+            return Err(FitsRotateError::Message(format!(
+                "The primary HDU of {} is not an image",
+                filename
+            )))
+        }
+    };
+    // Only the primary image is rotated, so say what becomes of any extensions
+    let extend: bool = hdu.read_key(&mut fits_file, "EXTEND").unwrap_or(false);
+    let n_hdus = fits_file.num_hdus()?;
+    if extend && n_hdus > 1 {
+        let mut n_copied = 0;
+        for hdu_idx in 1..n_hdus {
+            if is_copied_extension(&mut fits_file, hdu_idx)? {
+                n_copied += 1;
+            }
+        }
+        fits_file.primary_hdu()?;
+        let message = format!(
+            "{} has {} extensions after the primary image, which is the only HDU rotated: {} (binary tables and \
+             distortion lookup tables) are copied to the output and the rest are dropped (see --list-hdus)",
+            filename,
+            n_hdus - 1,
+            n_copied
+        );
+        warn(&message);
+    }
+    if let Some(required) = args.require_bitpix {
+        let bitpix: i64 = hdu.read_key(&mut fits_file, "BITPIX")?;
+        check_bitpix(filename, bitpix, required)?;
+    }
+    // The assertions are about the input, so they are checked before anything else is done with it
+    if !args.expect_order.is_empty() {
+        let ctypes = read_ctypes(&mut fits_file).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+        check_expected_order(filename, &ctypes, &args.expect_order)?;
+    }
+    if !args.expect_shape.is_empty() {
+        let naxes: Vec<usize> = shape.iter().rev().copied().collect();
+        check_expected_shape(filename, &naxes, &args.expect_shape)?;
+    }
+    if !args.require_ctype.is_empty() {
+        let ctypes = read_ctypes(&mut fits_file).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+        check_ctype_requirements(filename, &ctypes, &args.require_ctype)?;
+    }
+    if let Some(axis) = args.smooth_axis {
+        if axis < 1 || axis > shape.len() {
+            return Err(FitsRotateError::Message(format!(
+                "Cannot smooth along axis {} of a cube with {} axes",
+                axis,
+                shape.len()
+            )));
+        }
+        if args.smooth_window == Some(0) {
+            return Err(FitsRotateError::Message("--smooth-window must be at least 1".to_string()));
+        }
+    }
+    if args.chunk_size == Some(0) {
+        return Err(FitsRotateError::Message("--chunk-size must be at least 1".to_string()));
+    }
+    // --compress overrides the compression of the input
+    let compression = match (compression_settings(args), &unpacked) {
+        (Some(settings), _) => {
+            settings.check()?;
+            Some(settings).filter(|settings| settings.compression != Compression::None)
+        }
+        (None, Some(unpacked)) => {
+            let settings = unpacked.settings.carried(args.quantize, args.dither_seed);
+            settings.check()?;
+            Some(settings)
+        }
+        (None, None) if args.quantize.is_some() => {
+            return Err(FitsRotateError::Message(format!(
+                "--quantize needs --compress, as {} is not tile-compressed",
+                filename
+            )));
+        }
+        (None, None) => None,
+    };
+    // The planes are sorted along an axis of the input, before anything else is done to that axis
+    let sort = match (&args.sort_axis, &args.sort_key) {
+        (Some(axis), Some(key)) => Some(PlaneSort::find(&mut fits_file, filename, axis, key)?),
+        _ => None,
+    };
+    // The mode and padding apply to the cube left after selecting the Stokes plane
+    let stokes = match &args.stokes {
+        Some(stokes_label) => Some(StokesPlane::find(&mut fits_file, filename, stokes_label, args.keep_stokes_axis)?),
+        None => None,
+    };
+    if let (Some(sort), Some(stokes)) = (&sort, &stokes) {
+        if sort.axis == stokes.axis {
+            return Err(FitsRotateError::Message(format!(
+                "Cannot sort axis {} of {}, which is the Stokes axis of --stokes",
+                sort.axis, filename
+            )));
+        }
+    }
+    let shape = match &stokes {
+        Some(stokes) => stokes.shape(&shape),
+        None => shape,
+    };
+    // The spectral axis is regridded after the Stokes plane is selected
+    let regrid = match &args.spectral_regrid {
+        Some(spec) => Some(SpectralRegrid::find(&mut fits_file, filename, spec)?),
+        None => None,
+    };
+    let shape = match &regrid {
+        Some(regrid) => regrid.shape(&shape, cube_axis(regrid.axis, stokes.as_ref(), shape.len())),
+        None => shape,
+    };
+    // The spectra are smoothed on the new grid, if there is one
+    let smoothing = match &args.smooth_spectral {
+        Some(spec) => Some(SpectralSmoothing::find(&mut fits_file, filename, spec, regrid.as_ref())?),
+        None => None,
+    };
+    let naxis = shape.len();
+
+    let mut outputs: Vec<ModeOutput> = Vec::with_capacity(modes.len());
+    for mode in modes {
+        let mode_vec = parse_mode(mode, naxis)
+            .map_err(|e| e.with_ctypes(read_ctypes(&mut fits_file).unwrap_or_default()))?;
+
+        let pad_shape = match &args.pad_to_shape {
+            Some(pad_to_shape) => {
+                let pad_shape = parse_shape(pad_to_shape, naxis)?;
+                for (fits_idx, length) in pad_shape.iter().enumerate() {
+                    let rotated_length = shape[fits_index_to_array_index(mode_vec[fits_idx], naxis)];
+                    if *length < rotated_length {
+                        return Err(FitsRotateError::Message(format!(
+                            "Cannot pad axis {} of length {} to the shorter length {}",
+                            fits_idx + 1,
+                            rotated_length,
+                            length
+                        )));
+                    }
+                }
+                Some(pad_shape)
+            }
+            None => None,
+        };
+        if let Some(reference) = &args.copy_wcs_from {
+            let (_, reference_shape) = read_wcs_cards(reference)?;
+            let out_shape: Vec<usize> = match &pad_shape {
+                Some(pad_shape) => pad_shape.clone(),
+                None => mode_vec
+                    .iter()
+                    .map(|axis| shape[fits_index_to_array_index(*axis, naxis)])
+                    .collect(),
+            };
+            if reference_shape != out_shape {
+                return Err(FitsRotateError::Message(format!(
+                    "Cannot copy the WCS of {} (shape {:?}) to the output of mode {} (shape {:?})",
+                    reference, reference_shape, mode, out_shape
+                )));
+            }
+        }
+
+        // The chunk axis is named by its CTYPE in the rotated cube, or given by number
+        let chunk_axis = match &args.chunk_axis {
+            Some(name) => {
+                let ctypes = read_ctypes(&mut fits_file).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+                let input_axis = |axis: usize| stokes.as_ref().map_or(axis, |stokes| stokes.input_axis(axis));
+                let rotated: Vec<String> = mode_vec.iter().map(|axis| ctypes[input_axis(*axis) - 1].clone()).collect();
+                let axis = match name.trim().parse::<usize>() {
+                    Ok(axis) if (1..naxis + 1).contains(&axis) => Some(axis),
+                    Ok(_) => None,
+                    Err(_) => find_axis(name, &rotated).map(|idx| idx + 1),
+                };
+                Some(axis.ok_or_else(|| {
+                    FitsRotateError::Message(format!(
+                        "No axis of the output of mode {} matches --chunk-axis {} (CTYPEs: {})",
+                        mode,
+                        name,
+                        rotated.join(", ")
+                    ))
+                })?)
+            }
+            None => None,
+        };
+
+        let out_filename = output_filename(filename, mode, modes.len() > 1, args)?;
+        if let Some(other) = outputs.iter().find(|output| output.out_filename == out_filename) {
+            return Err(FitsRotateError::Message(format!(
+                "Modes {} and {} would both be written to {} (put {{mode}} in --output)",
+                other.mode, mode, out_filename
+            )));
+        }
+        // Split and chunked outputs are checked as they are written, and discarded outputs are never written
+        if args.split_axis.is_none() && args.chunk_axis.is_none() && !args.null_device {
+            check_file_exists(&out_filename, args.overwrite)?;
+        }
+        // The mirrored directories are created as needed
+        if !args.null_device {
+            check_output_writable(&out_filename, args.mkdirs || args.outdir.is_some())?;
+        }
+        // The tiles of the input are carried to the same axes of the output
+        let compression = match &compression {
+            Some(settings) if args.compress.is_none() => {
+                let input_mode: Vec<usize> = mode_vec
+                    .iter()
+                    .map(|axis| stokes.as_ref().map_or(*axis, |stokes| stokes.input_axis(*axis)))
+                    .collect();
+                Some(settings.permuted(&input_mode))
+            }
+            settings => settings.clone(),
+        };
+        outputs.push(ModeOutput {
+            mode: mode.clone(),
+            mode_vec,
+            pad_shape,
+            chunk_axis,
+            out_filename,
+            compression,
+        });
+    }
+
+    // Keep other processes from writing the input until the rotation is
+    // done, and with --lock-output from touching the outputs
+    let lock_outputs = args.lock_output && !args.null_device;
+    let _locks = if args.no_lock {
+        None
+    } else {
+        let timeout = args.lock_timeout.map(Duration::from_secs_f64);
+        let input_lock = lock_input(filename, timeout)?;
+        let output_locks = outputs
+            .iter()
+            .filter(|_| lock_outputs)
+            .map(|output| lock_output(&output.out_filename, timeout))
+            .collect::<Result<Vec<_>, _>>()?;
+        Some((input_lock, output_locks))
+    };
+    // Another process may have written an output while this one waited for its lock
+    if lock_outputs && args.split_axis.is_none() && args.chunk_axis.is_none() {
+        for output in &outputs {
+            check_file_exists(&output.out_filename, args.overwrite)?;
+        }
+    }
+
+    // Keep double precision data in double precision, unless asked not to
+    let (sort, stokes, regrid, smoothing) = (sort.as_ref(), stokes.as_ref(), regrid.as_ref(), smoothing.as_ref());
+    match (image_type, args.working_dtype) {
+        (ImageType::Double, WorkingDtype::Float64) => {
+            rotate_file_as::<f64>(filename, source, &outputs, sort, stokes, regrid, smoothing, false, args)?
+        }
+        (ImageType::Double, WorkingDtype::Float32) => {
+            rotate_file_as::<f32>(filename, source, &outputs, sort, stokes, regrid, smoothing, true, args)?
+        }
+        _ => rotate_file_as::<f32>(filename, source, &outputs, sort, stokes, regrid, smoothing, false, args)?,
+    }
+    // Nothing was written if the outputs were discarded
+    if args.null_device {
+        return Ok(Vec::new());
+    }
+    Ok(outputs.into_iter().map(|output| output.out_filename).collect())
+}
+
+/// The axis of the cube left after selecting a Stokes plane that an input axis became
+///
+/// # Arguments
+///
+/// * `input_axis` - The (FITS, 1-based) axis of the input
+/// * `stokes` - The Stokes plane that was selected, if any
+/// * `naxis` - The number of axes of the cube
+///
+/// # Returns
+///
+/// * `usize` - The (FITS, 1-based) axis of the cube
+///
+fn cube_axis(input_axis: usize, stokes: Option<&StokesPlane>, naxis: usize) -> usize {
+    (1..naxis + 1)
+        .find(|axis| stokes.map_or(*axis, |stokes| stokes.input_axis(*axis)) == input_axis)
+        .unwrap_or(input_axis)
+}
+
+/// Rotate a single FITS file, with the data read as `T`
+///
+/// The data is read, masked and thresholded once, and then rotated and
+/// written for each mode in turn. Every mode but the last rotates a copy of
+/// the data, and the last rotates the data itself.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `source` - The FITS file to read the data from: `filename`, or the unpacked file of a tile-compressed one
+/// * `outputs` - The output of each mode
+/// * `sort` - The sort of the planes along an axis, if any
+/// * `stokes` - The Stokes plane to keep, if any
+/// * `regrid` - The regridding of the spectral axis, if any
+/// * `smoothing` - The smoothing of the spectral axis, if any
+/// * `narrow` - Convert double precision data to float32 as it is read, reporting the precision lost
+/// * `args` - The command line arguments
+///
+#[allow(clippy::too_many_arguments)]
+fn rotate_file_as<T: FitsPixel>(
+    filename: &str,
+    source: &str,
+    outputs: &[ModeOutput],
+    sort: Option<&PlaneSort>,
+    stokes: Option<&StokesPlane>,
+    regrid: Option<&SpectralRegrid>,
+    smoothing: Option<&SpectralSmoothing>,
+    narrow: bool,
+    args: &Args,
+) -> Result<(), FitsRotateError>
+where
+    ArrayD<T>: ReadImage,
+{
+    // The mask is not rotated: it is in the axis order of the input
+    let mask = match &args.mask_file {
+        Some(mask_filename) => {
+            let (mask, _) = read_fits_cube::<f32>(mask_filename)?;
+            Some(mask.mapv(|v| v.is_finite() && v != 0.0))
+        }
+        None => None,
+    };
+    let hints = if args.io_hints {
+        SequentialRead::start(source)
+    } else {
+        None
+    };
+    let (mut fits_cube, mut fits_file) = if narrow {
+        let (fits_cube, fits_file, narrowing) = read_fits_cube_narrowed::<T>(source)?;
+        for line in narrowing.warnings() {
+            events::warning(&format!("Precision loss: {}", line));
+            println!("{} {}", warning("Precision loss:"), line);
+        }
+        (fits_cube, fits_file)
+    } else if args.mmap_read {
+        read_fits_cube_mapped::<T>(source)?
+    } else {
+        read_fits_cube::<T>(source)?
+    };
+    // The data is in memory now, so the kernel can drop it from the page cache
+    drop(hints);
+    let read_peak = args.mem_profile.then(phase_peak);
+    if let Some(read_peak) = read_peak {
+        println!("{} {}", label("Peak memory (read):"), format_bytes(read_peak));
+    }
+    events::phase_progress(filename, "read", 1, 1, read_peak);
+    if let Some(mask) = &mask {
+        let mut cube = FitsCube::new(fits_cube);
+        let n_masked = cube.apply_mask(mask)?;
+        println!("Masked {} of {} pixels", n_masked, cube.data.len());
+        fits_cube = cube.into_array();
+    }
+    if let Some(region_filename) = &args.blank_region {
+        let shapes = parse_regions(&std::fs::read_to_string(region_filename)?)?;
+        let region = region_mask(&shapes, &mut fits_file, fits_cube.shape(), args.blank_outside)?;
+        let mut cube = FitsCube::new(fits_cube);
+        let n_blanked = cube.apply_mask(&region)?;
+        println!(
+            "Blanked {} of {} pixels {} the regions of {}",
+            n_blanked,
+            cube.data.len(),
+            if args.blank_outside { "outside" } else { "inside" },
+            region_filename
+        );
+        fits_cube = cube.into_array();
+    }
+    // The mask and regions are in the pixels of the input, so the planes are sorted after them
+    if let Some(sort) = sort {
+        fits_cube = sort.sort(fits_cube);
+        println!("Sorted the planes of axis {} by the keys in {}", sort.axis, sort.source);
+    }
+    let threshold_history = if args.threshold_min.is_some() || args.threshold_max.is_some() {
+        let mut cube = FitsCube::new(fits_cube);
+        let (n_clipped, range) = threshold_cube(&mut cube, &mut fits_file, args.threshold_min, args.threshold_max)?;
+        fits_cube = cube.into_array();
+        Some(format!("fitsrotate_rs: blanked {} pixels outside {}", n_clipped, range))
+    } else {
+        None
+    };
+    let rolling_mean_history = match (args.smooth_axis, args.smooth_window) {
+        (Some(axis), Some(window)) => {
+            let array_idx = fits_index_to_array_index(axis, fits_cube.ndim());
+            fits_cube = FitsCube::new(fits_cube).rolling_mean(array_idx, window).into_array();
+            println!("Smoothed axis {} with a rolling mean of {} pixels", axis, window);
+            Some(rolling_mean_history(axis, window))
+        }
+        _ => None,
+    };
+    if let Some(stokes) = stokes {
+        fits_cube = stokes.select(fits_cube)?;
+        println!(
+            "Selected Stokes {} (plane {} of axis {})",
+            stokes_label(stokes.code),
+            stokes.plane,
+            stokes.axis
+        );
+    }
+    // The input axis of each axis of the cube, which differ if the Stokes axis was dropped
+    let input_axis = |axis: usize| stokes.map_or(axis, |stokes| stokes.input_axis(axis));
+    if let Some(regrid) = regrid {
+        let axis = cube_axis(regrid.axis, stokes, fits_cube.ndim());
+        fits_cube = regrid.regrid(fits_cube, axis);
+        println!("Regridded axis {} onto {}", regrid.axis, regrid.describe());
+    }
+    if let Some(smoothing) = smoothing {
+        let axis = cube_axis(smoothing.axis, stokes, fits_cube.ndim());
+        fits_cube = smoothing.smooth(fits_cube, axis);
+        println!("Smoothed axis {} with a {}", smoothing.axis, smoothing.describe());
+    }
+
+    // Keep the header exactly as it was read, before any repairs
+    let original_header = if args.backup_header {
+        fits_file.primary_hdu()?;
+        Some(FitsHeader::read(&mut fits_file)?)
+    } else {
+        None
+    };
+
+    let header = if args.fix_header {
+        fits_file.primary_hdu()?;
+        let mut header = FitsHeader::read(&mut fits_file)?;
+        for repair in header.fix() {
+            events::warning(&format!("Fixed header: {}", repair));
+            println!("{} {}", warning("Fixed header:"), repair);
+        }
+        Some(header)
+    } else {
+        None
+    };
+
+    println!("{} {:?}", label("Original FITS cube shape:"), fits_cube.shape());
+    // Rotate and write the cube for one mode; each mode starts from the header as it was read
+    let mut rotate_output = |fits_cube: ArrayD<T>, output: &ModeOutput, preview: bool| -> Result<(), FitsRotateError> {
+        let (mode, mode_vec, pad_shape, out_filename, compression) = (
+            output.mode.as_str(),
+            output.mode_vec.as_slice(),
+            output.pad_shape.as_deref(),
+            output.out_filename.as_str(),
+            output.compression.as_ref(),
+        );
+        if outputs.len() > 1 {
+            println!("{} {}", label("Mode:"), mode);
+        }
+        let mut header = header.clone();
+        let flips = parse_flips(mode);
+        let flipped_fits_cube = flip_fits_cube_axes(fits_cube, &flips);
+        // The (output axis, length) of each flipped axis
+        let flipped_axes: Vec<(usize, usize)> = flips
+            .iter()
+            .map(|axis| {
+                let array_idx = fits_index_to_array_index(*axis, flipped_fits_cube.ndim());
+                let length = flipped_fits_cube.shape()[array_idx];
+                (output_axis(*axis, mode_vec), length)
+            })
+            .collect();
+        // AIPS records the beam of each channel in HISTORY, which has to follow the planes of the spectral axis
+        let ctypes = read_ctypes(&mut fits_file)?;
+        let beam_channels = (1..flipped_fits_cube.ndim() + 1)
+            .find(|axis| axis_role(&ctypes[input_axis(*axis) - 1]) == Some(AxisRole::Spectral))
+            .and_then(|spectral_axis| {
+                let array_idx = fits_index_to_array_index(spectral_axis, flipped_fits_cube.ndim());
+                let sorted = sort
+                    .filter(|sort| sort.axis == input_axis(spectral_axis))
+                    .map(|sort| sort.planes.as_slice());
+                channel_order(flipped_fits_cube.shape()[array_idx], sorted, flips.contains(&spectral_axis))
+            });
+        if let (Some(header), Some(channels)) = (header.as_mut(), &beam_channels) {
+            report_beam_channels(renumber_beam_channels(header, channels));
+        }
+        let mut rotated_fits_cube = rotate_fits_cube_axes(flipped_fits_cube, mode_vec);
+        println!("{} {:?}", label("Rotated FITS cube shape:"), rotated_fits_cube.shape());
+        if let Some(pad_shape) = pad_shape {
+            rotated_fits_cube = pad_cube(rotated_fits_cube, pad_shape, args.pad_mode)?;
+            println!("{} {:?}", label("Padded FITS cube shape:"), rotated_fits_cube.shape());
+        }
+
+        // The preview uses the cube already in memory rather than reading again
+        if let Some(preview_filename) = args.preview.as_ref().filter(|_| preview) {
+            let plane = preview_plane(&rotated_fits_cube, args.preview_plane)?.mapv(|v| v.into() as f32);
+            let input_ctypes = read_ctypes(&mut fits_file)?;
+            let ctypes: Vec<String> = (1..rotated_fits_cube.ndim() + 1)
+                .map(|axis| input_ctypes[input_axis(axis) - 1].clone())
+                .collect();
+            let ctypes = rotated_ctypes(&ctypes, mode_vec);
+            write_preview(preview_filename, &plane, &ctypes[0], &ctypes[1])?;
+            println!("Wrote preview to {}", preview_filename);
+        }
+
+        let mut history = vec![
+            format!("fitsrotate_rs: reordered the axes of {} with mode {}", filename, mode),
+            version_history(args.reproducible),
+        ];
+        if let Some(reference) = &args.axes_from {
+            history.push(format!("fitsrotate_rs: mode taken from the axes of {}", reference));
+        }
+        if args.auto_mode {
+            history.push(format!("fitsrotate_rs: mode taken from the target order {}", args.target_order.join(",")));
+        }
+        if let Some(ops) = &args.ops {
+            for (idx, op) in parse_ops(ops)?.iter().enumerate() {
+                history.push(format!("fitsrotate_rs: --ops step {}: {}", idx + 1, op));
+            }
+        }
+        if narrow {
+            history.push("fitsrotate_rs: converted the data from double precision to float32".to_string());
+        }
+        if let Some(sort) = sort {
+            history.push(format!(
+                "fitsrotate_rs: sorted the planes of axis {} by the keys in {} onto {}",
+                sort.axis,
+                sort.source,
+                sort.describe()
+            ));
+        }
+        history.extend(threshold_history.clone());
+        history.extend(rolling_mean_history.clone());
+        if let Some(stokes) = stokes {
+            history.push(format!(
+                "fitsrotate_rs: selected Stokes {} (plane {} of axis {})",
+                stokes_label(stokes.code),
+                stokes.plane,
+                stokes.axis
+            ));
+        }
+        if let Some(regrid) = regrid {
+            history.push(format!(
+                "fitsrotate_rs: regridded axis {} onto {}",
+                regrid.axis,
+                regrid.describe()
+            ));
+        }
+        if let Some(smoothing) = smoothing {
+            history.push(format!(
+                "fitsrotate_rs: smoothed axis {} with a {}",
+                smoothing.axis,
+                smoothing.describe()
+            ));
+        }
+        let rotation = Rotation {
+            mode: mode_vec.iter().map(|axis| input_axis(*axis)).collect(),
+            header,
+            original_header: original_header.clone(),
+            flipped_axes,
+            sort: sort.cloned(),
+            stokes: stokes.copied(),
+            regrid: regrid.copied(),
+            beam_channels,
+            history,
+        };
+        match args.split_axis {
+            Some(split_axis) => {
+                if split_axis < 1 || split_axis > rotated_fits_cube.ndim() {
+                    return Err(FitsRotateError::Message(format!(
+                        "Cannot split along axis {} of a cube with {} axes",
+                        split_axis,
+                        rotated_fits_cube.ndim()
+                    )));
+                }
+                let axis = Axis(fits_index_to_array_index(split_axis, rotated_fits_cube.ndim()));
+                let n_slices = rotated_fits_cube.len_of(axis);
+                for index in 0..n_slices {
+                    let slice_filename = split_filename(out_filename, index, n_slices);
+                    let slice = rotated_fits_cube
+                        .slice_axis(axis, Slice::from(index..index + 1))
+                        .to_owned();
+                    write_output(&slice_filename, slice, &mut fits_file, &rotation, args)?;
+                    shift_split_crpix(&slice_filename, split_axis, index)?;
+                    finish_output(filename, source, &slice_filename, &rotation, compression, args, &[(split_axis, index as f64)])?;
+                }
+                println!("Split rotated FITS cube into {} files along axis {}", n_slices, split_axis);
+            }
+            None if output.chunk_axis.is_some() => {
+                let chunk_axis = output.chunk_axis.unwrap_or(1);
+                let chunk_size = args.chunk_size.unwrap_or(1);
+                let axis = Axis(fits_index_to_array_index(chunk_axis, rotated_fits_cube.ndim()));
+                let length = rotated_fits_cube.len_of(axis);
+                let n_chunks = length.div_ceil(chunk_size);
+                for chunk in 0..n_chunks {
+                    // The last chunk may be shorter
+                    let (start, end) = (chunk * chunk_size, ((chunk + 1) * chunk_size).min(length));
+                    let chunk_filename = chunk_filename(args.chunk_output.as_deref(), out_filename, start + 1, end, length);
+                    check_file_exists(&chunk_filename, args.overwrite)?;
+                    let sub_cube = rotated_fits_cube.slice_axis(axis, Slice::from(start..end)).to_owned();
+                    write_output(&chunk_filename, sub_cube, &mut fits_file, &rotation, args)?;
+                    shift_split_crpix(&chunk_filename, chunk_axis, start)?;
+                    write_chunk_keys(&chunk_filename, filename, chunk_axis, start + 1, end)?;
+                    finish_output(filename, source, &chunk_filename, &rotation, compression, args, &[(chunk_axis, start as f64)])?;
+                }
+                println!(
+                    "Cut rotated FITS cube into {} chunks of up to {} pixels along axis {}",
+                    n_chunks, chunk_size, chunk_axis
+                );
+            }
+            None => {
+                write_output(out_filename, rotated_fits_cube, &mut fits_file, &rotation, args)?;
+                finish_output(filename, source, out_filename, &rotation, compression, args, &[])?;
+            }
+        }
+        Ok(())
+    };
+    let (last, rest) = outputs
+        .split_last()
+        .ok_or_else(|| FitsRotateError::Message("Expected at least one mode".to_string()))?;
+    // The peak of each mode includes the data read, which is held until the last mode
+    let finish_phase = |output: &ModeOutput, done: usize| {
+        let peak = args.mem_profile.then(phase_peak);
+        if let Some(peak) = peak {
+            println!(
+                "{} {}",
+                label(&format!("Peak memory (rotate and write {}):", output.mode)),
+                format_bytes(peak)
+            );
+        }
+        events::phase_progress(filename, "rotate and write", done, outputs.len(), peak);
+    };
+    for (idx, output) in rest.iter().enumerate() {
+        rotate_output(fits_cube.clone(), output, idx == 0)?;
+        finish_phase(output, idx + 1);
+    }
+    rotate_output(fits_cube, last, rest.is_empty())?;
+    finish_phase(last, outputs.len());
+    Ok(())
+}
+
+/// Blank the pixels of a cube outside a range of values
+///
+/// The number of blanked pixels is reported for each channel of the
+/// spectral axis, or each plane of the last axis if there is no spectral
+/// axis, so that channels with RFI stand out.
+///
+/// # Arguments
+///
+/// * `cube` - The FITS cube
+/// * `fits_file` - The FITS file
+/// * `min` - The smallest value to keep, if any
+/// * `max` - The largest value to keep, if any
+///
+/// # Returns
+///
+/// * `Result<(usize, String), FitsRotateError>` - The number of blanked pixels, and the range kept
+///
+fn threshold_cube<T: FitsPixel>(
+    cube: &mut FitsCube<T>,
+    fits_file: &mut FitsFile,
+    min: Option<f64>,
+    max: Option<f64>,
+) -> Result<(usize, String), FitsRotateError> {
+    let naxis = cube.data.ndim();
+    let ctypes = read_ctypes(fits_file)?;
+    let fits_axis = ctypes
+        .iter()
+        .position(|ctype| axis_role(ctype) == Some(AxisRole::Spectral))
+        .map_or(naxis, |idx| idx + 1);
+    let range = format!(
+        "[{}, {}]",
+        min.map_or("-inf".to_string(), |min| min.to_string()),
+        max.map_or("inf".to_string(), |max| max.to_string())
+    );
+
+    let counts = cube.apply_threshold(min, max, fits_index_to_array_index(fits_axis, naxis));
+    let ctype = ctypes.get(fits_axis - 1).map_or("", |ctype| ctype.as_str());
+    for (plane, n_clipped) in counts.iter().enumerate().filter(|(_, n_clipped)| **n_clipped > 0) {
+        println!(
+            "Blanked {} pixels outside {} in plane {} of axis {} ({})",
+            n_clipped,
+            range,
+            plane + 1,
+            fits_axis,
+            ctype
+        );
+    }
+    let n_clipped: usize = counts.iter().sum();
+    println!("Blanked {} of {} pixels outside {}", n_clipped, cube.data.len(), range);
+    Ok((n_clipped, range))
+}
+
+/// Everything needed to write the outputs of a rotation, besides the data
+struct Rotation {
+    /// The input axis of each output axis
+    mode: Vec<usize>,
+    /// A (repaired) header to copy to the output
+    header: Option<FitsHeader>,
+    /// The original header to store as a backup
+    original_header: Option<FitsHeader>,
+    /// The (output axis, length) of each reversed axis
+    flipped_axes: Vec<(usize, usize)>,
+    /// The sort of the planes along an axis, if any
+    sort: Option<PlaneSort>,
+    /// The Stokes plane that was kept, if any
+    stokes: Option<StokesPlane>,
+    /// The regridding of the spectral axis, if any
+    regrid: Option<SpectralRegrid>,
+    /// The new channel of each channel of the spectral axis, if its planes were reordered
+    beam_channels: Option<Vec<usize>>,
+    /// HISTORY cards recording the rotation
+    history: Vec<String>,
+}
+
+/// Write a rotated cube, along with any requested extras
+///
+/// # Arguments
+///
+/// * `out_filename` - The output FITS file
+/// * `fits_cube` - The rotated FITS cube
+/// * `fits_file` - The original FITS file
+/// * `rotation` - The rotation applied to the cube
+/// * `args` - The command line arguments
+///
+fn write_output<T: FitsPixel>(
+    out_filename: &str,
+    fits_cube: ArrayD<T>,
+    fits_file: &mut FitsFile,
+    rotation: &Rotation,
+    args: &Args,
+) -> Result<(), FitsRotateError> {
+    // The rotated data is laid out as it would be written, and then dropped
+    if args.null_device {
+        let n_pixels = fits_cube.as_standard_layout().len();
+        warn(&format!("Discarded {} rotated pixels rather than writing {}", n_pixels, out_filename));
+        return Ok(());
+    }
+    let options = WriteOptions {
+        overwrite: args.overwrite,
+        header: rotation.header.as_ref(),
+        reproducible: args.reproducible,
+        fill_missing_wcs: args.fill_missing_wcs,
+        mmap_write: args.mmap_write,
+    };
+    write_fits_cube(out_filename, fits_cube, &rotation.mode, fits_file, options)?;
+    let mut out_file = FitsFile::edit(out_filename)?;
+    // The rotation is recorded first, so that the cards below belong to its record in `history`
+    for history in &rotation.history {
+        write_history(&mut out_file, history)?;
+    }
+    // The reference already describes the output, so nothing is done to its WCS
+    if let Some(reference) = &args.copy_wcs_from {
+        let (wcs_cards, _) = read_wcs_cards(reference)?;
+        replace_wcs(&mut out_file, &wcs_cards)?;
+        write_history(&mut out_file, &format!("fitsrotate_rs: replaced the WCS with that of {}", reference))?;
+        println!("Copied {} WCS cards from {}", wcs_cards.cards.len(), reference);
+    } else {
+        // The CROTA cards were rotated with their axes, so converting them here
+        // is the same as converting the input before the rotation
+        if args.modernize_wcs && modernize_wcs(&mut out_file)? {
+            write_history(&mut out_file, "fitsrotate_rs: converted CROTA to a PC matrix")?;
+            println!("Converted CROTA to a PC matrix in {}", out_filename);
+        }
+        // A sorted axis is described by its sorted keys
+        if let Some(sort) = &rotation.sort {
+            sort.write_wcs(&mut out_file, output_axis(sort.axis, &rotation.mode))?;
+        }
+        // A regridded spectral axis starts at the first channel of the new grid
+        if let Some(regrid) = &rotation.regrid {
+            let axis = output_axis(regrid.axis, &rotation.mode);
+            replace_key(&mut out_file, &format!("CRVAL{}", axis), regrid.grid.start)?;
+            replace_key(&mut out_file, &format!("CDELT{}", axis), regrid.grid.width)?;
+            replace_key(&mut out_file, &format!("CRPIX{}", axis), 1.0)?;
+        }
+        // A kept Stokes axis starts at the selected plane
+        if let Some(stokes) = rotation.stokes.filter(|stokes| stokes.keep_axis && stokes.plane > 1) {
+            let crpix_card = format!("CRPIX{}", output_axis(stokes.axis, &rotation.mode));
+            let crpix: f64 = out_file.primary_hdu()?.read_key(&mut out_file, &crpix_card).unwrap_or(1.0);
+            replace_key(&mut out_file, &crpix_card, crpix - (stokes.plane - 1) as f64)?;
+        }
+        for (fits_axis, length) in &rotation.flipped_axes {
+            flip_wcs_axis(&mut out_file, *fits_axis, *length)?;
+        }
+    }
+    if let Some(sort) = &rotation.sort {
+        sort.write_permutation(&mut out_file, output_axis(sort.axis, &rotation.mode))?;
+    }
+    // The patch was checked before any file was read, so it only fails here on the header itself
+    if let Some(patch_filename) = &args.header_patch {
+        let n_applied = HeaderPatch::load(patch_filename)?.apply(&mut out_file)?;
+        println!("Applied {} header patch operations from {}", n_applied, patch_filename);
+    }
+    if let Some(original_header) = &rotation.original_header {
+        let mut out_file = FitsFile::edit(out_filename)?;
+        write_header_backup(&mut out_file, original_header)?;
+        println!("Stored original header in {}", out_filename);
+    }
+    // Everything else has been written to the header by now
+    if args.preserve_layout {
+        fits_file.primary_hdu()?;
+        let mut input_header = FitsHeader::read(fits_file)?;
+        // The HISTORY cards of the input come back with the layout, so its beam records follow the channels too
+        if let Some(channels) = &rotation.beam_channels {
+            report_beam_channels(renumber_beam_channels(&mut input_header, channels));
+        }
+        let mut out_file = FitsFile::edit(out_filename)?;
+        let n_cards = preserve_layout(&mut out_file, &input_header, &rotation.mode)?;
+        println!("Laid out the {} cards of {} like the input header", n_cards, out_filename);
+    }
+    // The sidecar is read back from the output, so it describes the final header
+    if let Some(sidecar) = &args.wcs_sidecar {
+        write_sidecar(out_filename, sidecar, args.yaml)?;
+        println!("Wrote WCS sidecar to {}", sidecar);
+    }
+    println!("{}", success(&format!("Wrote rotated FITS cube to {}", out_filename)));
+    Ok(())
+}
+
+/// Print the number of AIPS beam HISTORY records renumbered for reordered channels, if there were any
+fn report_beam_channels(n_records: usize) {
+    if n_records > 0 {
+        println!("Renumbered {} AIPS beam HISTORY records for the reordered channels", n_records);
+    }
+}
+
+/// Check if a file name is a glob pattern
+fn is_glob(filename: &str) -> bool {
+    filename.contains(['*', '?'])
+}
+
+/// Rotate a batch of files, or the share of it that belongs to a task
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file, a glob pattern matching the FITS files, or a directory with --recursive
+/// * `modes` - The mode strings, one per output of each file
+/// * `task` - The task of a job array, if the files are shared between tasks
+/// * `args` - The command line arguments
+///
+fn rotate_batch(filename: &str, modes: &[String], task: Option<Task>, args: &Args) -> Result<(), FitsRotateError> {
+    let files = if args.recursive {
+        walk_files(filename)?
+    } else if is_glob(filename) {
+        glob_files(filename)?
+    } else {
+        vec![filename.to_string()]
+    };
+    if files.is_empty() {
+        return Err(FitsRotateError::Message(format!("No files match {}", filename)));
+    }
+    let task = task.unwrap_or(Task { index: 0, count: 1 });
+    // Every task checks the whole batch, so that a clash fails them all before anything is written
+    check_batch_outputs(&task_files(&files, Task { index: 0, count: 1 }), modes, args)?;
+    let owned = task_files(&files, task);
+    if args.output.is_some() && owned.len() > 1 {
+        return Err(FitsRotateError::Message(
+            "--output names a single file, but there are several files to rotate (use --outdir instead)".to_string(),
+        ));
+    }
+    println!(
+        "{} {} of {} files for task {} of {}",
+        label("Batch:"),
+        owned.len(),
+        files.len(),
+        task.index,
+        task.count
+    );
+    for file in &owned {
+        println!("  {}", file);
+    }
+    let mut skipped = 0;
+    for file in &owned {
+        if args.skip_existing {
+            let out_filenames = modes
+                .iter()
+                .map(|mode| output_filename(file, mode, modes.len() > 1, args))
+                .collect::<Result<Vec<String>, _>>()?;
+            if out_filenames.iter().all(|out_filename| Path::new(out_filename).exists()) {
+                println!("{} {} ({} exists)", label("Skipping:"), file, out_filenames.join(", "));
+                skipped += 1;
+                continue;
+            }
+        }
+        rotate_file(file, modes, args)?;
+    }
+    println!(
+        "{} {} rotated, {} skipped as their outputs exist",
+        label("Batch done:"),
+        owned.len() - skipped,
+        skipped
+    );
+    Ok(())
+}
+
+/// Check that the outputs of a batch neither clash with each other nor overwrite its inputs
+///
+/// Different inputs can map to the same output, e.g. `/data/cube.fits` and
+/// `data/cube.fits`, which are both mirrored to `data/cube.rot.fits` under
+/// `--outdir`, and the output of one file can be another file of the batch,
+/// e.g. `cube.rot.fits` for `cube.fits` when a glob matches both.
+///
+/// # Arguments
+///
+/// * `files` - The files of the batch
+/// * `modes` - The mode strings, one per output of each file
+/// * `args` - The command line arguments
+///
+fn check_batch_outputs(files: &[String], modes: &[String], args: &Args) -> Result<(), FitsRotateError> {
+    let mut outputs: Vec<(String, &String)> = Vec::new();
+    for file in files {
+        for mode in modes {
+            let out_filename = output_filename(file, mode, modes.len() > 1, args)?;
+            // Two modes of the same file are checked by `rotate_file_once`, with a hint about --output
+            if let Some((_, other)) = outputs.iter().find(|(output, other)| *output == out_filename && *other != file) {
+                return Err(FitsRotateError::Message(format!(
+                    "{} and {} would both be written to {}",
+                    other, file, out_filename
+                )));
+            }
+            if files.contains(&out_filename) {
+                return Err(FitsRotateError::Message(format!(
+                    "{} would be written to {}, which is an input of the batch",
+                    file, out_filename
+                )));
+            }
+            outputs.push((out_filename, file));
+        }
+    }
+    Ok(())
+}
+
+/// Check a written output and rearrange its HDUs, if requested
+///
+/// These steps come after everything else that edits the output, which
+/// expects the image in the primary HDU.
+///
+/// # Arguments
+///
+/// * `filename` - The input FITS file
+/// * `source` - The FITS file the data was read from: `filename`, or the unpacked file of a tile-compressed one
+/// * `out_filename` - The output FITS file
+/// * `rotation` - The rotation applied to the cube
+/// * `compression` - The tile compression of the output, if any
+/// * `args` - The command line arguments
+/// * `crpix_shifts` - The (output axis, shift) of the reference pixel of each split axis
+///
+fn finish_output(
+    filename: &str,
+    source: &str,
+    out_filename: &str,
+    rotation: &Rotation,
+    compression: Option<&CompressionSettings>,
+    args: &Args,
+    crpix_shifts: &[(usize, f64)],
+) -> Result<(), FitsRotateError> {
+    if args.report_wcs {
+        report_wcs(source, out_filename, rotation, crpix_shifts)?;
+    }
+    #[cfg(debug_assertions)]
+    corrupt_for_test(out_filename)?;
+    if let Some(n_samples) = args.spot_check {
+        let seed = args.spot_seed.unwrap_or_else(default_seed);
+        let flipped: Vec<usize> = rotation.flipped_axes.iter().map(|(axis, _)| *axis).collect();
+        spot_check(source, out_filename, &rotation.mode, &flipped, rotation.stokes.as_ref(), n_samples, seed)?;
+        println!("{} {} voxels of {} (seed {})", success("Spot check OK:"), n_samples, out_filename, seed);
+    }
+    if args.mef_layout {
+        write_mef_layout(out_filename, &args.extname, args.extver, args.inherit)?;
+        println!("Moved the image of {} to extension {}", out_filename, args.extname);
+    }
+    if let Some(settings) = compression {
+        compress_file(out_filename, settings)?;
+        println!("Tile-compressed {} with {:?}", out_filename, settings.compression);
+    }
+    // The statistics are read from the final output, so a quantized output is checked as it was written
+    if args.stats {
+        let (error, differences) = compare_stats(source, out_filename, args.tolerance)?;
+        if !differences.is_empty() {
+            return Err(FitsRotateError::Message(format!(
+                "The statistics of {} differ from those of {}: {}",
+                out_filename,
+                filename,
+                differences.join("; ")
+            )));
+        }
+        println!("{} {} (to within {} per pixel)", success("Stats OK:"), out_filename, error);
+    }
+    Ok(())
+}
+
+/// The tile compression of the outputs, if any
+fn compression_settings(args: &Args) -> Option<CompressionSettings> {
+    args.compress.map(|compression| CompressionSettings {
+        compression,
+        quantize: args.quantize,
+        dither: args.dither,
+        dither_seed: args.dither_seed,
+        reproducible: args.reproducible,
+        tiles: args.tile_size.map_or(Tiles::Rows, Tiles::Spatial),
+    })
+}
+
+/// Check the WCS of a written output against its input, and print the result
+///
+/// # Arguments
+///
+/// * `filename` - The input FITS file
+/// * `out_filename` - The output FITS file
+/// * `rotation` - The rotation applied to the cube
+/// * `crpix_shifts` - The (output axis, shift) of the reference pixel of each split axis
+///
+fn report_wcs(
+    filename: &str,
+    out_filename: &str,
+    rotation: &Rotation,
+    crpix_shifts: &[(usize, f64)],
+) -> Result<(), FitsRotateError> {
+    let mut input = CubeInfo::read(filename)?;
+    // A kept Stokes axis is a single plane of the input axis
+    if let Some(stokes) = rotation.stokes.filter(|stokes| stokes.keep_axis) {
+        let axis = &mut input.axes[stokes.axis - 1];
+        axis.naxis = 1;
+        axis.crpix = Some(axis.crpix.unwrap_or(1.0) - (stokes.plane - 1) as f64);
+    }
+    if let Some(sort) = &rotation.sort {
+        let axis = &mut input.axes[sort.axis - 1];
+        let (crval, cdelt) = sort.linear().unwrap_or((1.0, 1.0));
+        axis.ctype = Some(sort.output_ctype());
+        axis.crval = Some(crval);
+        axis.cdelt = Some(cdelt);
+        axis.crpix = Some(1.0);
+    }
+    if let Some(regrid) = &rotation.regrid {
+        let axis = &mut input.axes[regrid.axis - 1];
+        axis.naxis = regrid.grid.n;
+        axis.crval = Some(regrid.grid.start);
+        axis.cdelt = Some(regrid.grid.width);
+        axis.crpix = Some(1.0);
+    }
+    let output = CubeInfo::read(out_filename)?;
+    let mut out_file = FitsFile::open(out_filename)?;
+    out_file.primary_hdu()?;
+    let output_header = FitsHeader::read(&mut out_file)?;
+    let problems = check_output_wcs(
+        &input,
+        &output,
+        &output_header,
+        &rotation.mode,
+        &rotation.flipped_axes,
+        crpix_shifts,
+    );
+    if problems.is_empty() {
+        println!("{} {}", success("WCS OK:"), out_filename);
+    } else {
+        events::warning(&format!("WCS inconsistent: {}: {}", out_filename, problems.join("; ")));
+        println!("{} {}", warning("WCS inconsistent:"), out_filename);
+        for problem in problems {
+            println!("  {}", problem);
+        }
+    }
+    Ok(())
+}
+
+/// Time repeated rotations of a FITS file
+///
+/// Each run reads the file from disk, rotates it and writes it to a
+/// scratch file in the temporary directory, and the min, median and max
+/// time of each phase is reported. Nothing is written next to the input.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `mode` - The mode string
+/// * `n_runs` - The number of runs
+/// * `mmap_read` - Read the data through a memory map rather than CFITSIO
+/// * `mmap_write` - Write the data through a memory map rather than CFITSIO
+/// * `rechunk` - The shape (in FITS order) of the tiles to rotate the data through, if any
+///
+fn benchmark_file(
+    filename: &str,
+    mode: &str,
+    n_runs: usize,
+    mmap_read: bool,
+    mmap_write: bool,
+    rechunk: Option<&str>,
+) -> Result<(), FitsRotateError> {
+    if n_runs == 0 {
+        return Err(FitsRotateError::Message("--benchmark needs at least 1 run".to_string()));
+    }
+    let mut fits_file = FitsFile::open(filename)?;
+    let hdu = fits_file
+        .primary_hdu()
+        .map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+    let (naxis, image_type) = match &hdu.info {
+        HduInfo::ImageInfo { shape, image_type } => (shape.len(), *image_type),
+        _ => {
+            return Err(FitsRotateError::Message(format!(
+                "The primary HDU of {} is not an image",
+                filename
+            )))
+        }
+    };
+    let mode_vec = parse_mode(mode, naxis)
+        .map_err(|e| e.with_ctypes(read_ctypes(&mut fits_file).unwrap_or_default()))?;
+    // The tiles are in array order, like the data
+    let tile_shape = match rechunk {
+        Some(rechunk) => Some(parse_shape(rechunk, naxis)?.into_iter().rev().collect::<Vec<usize>>()),
+        None => None,
+    };
+    if tile_shape.as_ref().is_some_and(|tile_shape| tile_shape.contains(&0)) {
+        return Err(FitsRotateError::Message(format!("--rechunk {} has a tile length of 0", rechunk.unwrap_or(""))));
+    }
+    let scratch = benchmark_filename();
+    let scratch = scratch.to_string_lossy();
+    println!("Benchmarking {} runs of {} with mode {}, writing to {}", n_runs, filename, mode, scratch);
+    let tile_shape = tile_shape.as_deref();
+    let timings = match image_type {
+        ImageType::Double => {
+            benchmark_file_as::<f64>(filename, mode, &mode_vec, n_runs, &scratch, mmap_read, mmap_write, tile_shape)
+        }
+        _ => benchmark_file_as::<f32>(filename, mode, &mode_vec, n_runs, &scratch, mmap_read, mmap_write, tile_shape),
+    };
+    let _ = std::fs::remove_file(scratch.as_ref());
+    timings?.print();
+    Ok(())
+}
+
+/// Time repeated rotations of a FITS file, with the data read as `T`
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `mode` - The mode string
+/// * `mode_vec` - The parsed mode
+/// * `n_runs` - The number of runs
+/// * `scratch` - The file to write the outputs to
+/// * `mmap_read` - Read the data through a memory map rather than CFITSIO
+/// * `mmap_write` - Write the data through a memory map rather than CFITSIO
+/// * `tile_shape` - The shape (in array order) of the tiles to rotate the data through, if any
+///
+/// # Returns
+///
+/// * `Result<PhaseTimings, FitsRotateError>` - The time of each phase of each run
+///
+#[allow(clippy::too_many_arguments)]
+fn benchmark_file_as<T: FitsPixel>(
+    filename: &str,
+    mode: &str,
+    mode_vec: &[usize],
+    n_runs: usize,
+    scratch: &str,
+    mmap_read: bool,
+    mmap_write: bool,
+    tile_shape: Option<&[usize]>,
+) -> Result<PhaseTimings, FitsRotateError>
+where
+    ArrayD<T>: ReadImage,
+{
+    let flips = parse_flips(mode);
+    let mut timings = PhaseTimings::default();
+    for run in 0..n_runs {
+        // Drop the input from the page cache, so that each run reads from disk
+        // rather than from the copy left in memory by the run before
+        advise(&std::fs::File::open(filename)?, 0, 0, Advice::DontNeed);
+
+        phase_peak();
+        let start = Instant::now();
+        let (fits_cube, mut fits_file) = if mmap_read {
+            read_fits_cube_mapped::<T>(filename)?
+        } else {
+            read_fits_cube::<T>(filename)?
+        };
+        let read_time = start.elapsed();
+        let read_peak = phase_peak();
+
+        // The copy into the new order is part of the rotation
+        let start = Instant::now();
+        let flipped_fits_cube = flip_fits_cube_axes(fits_cube, &flips);
+        let rotated_fits_cube = match tile_shape {
+            Some(tile_shape) => FitsCube::permute_tiled(flipped_fits_cube, &array_mode(mode_vec), tile_shape),
+            None => rotate_fits_cube_axes(flipped_fits_cube, mode_vec),
+        };
+        let rotate_time = start.elapsed();
+        let rotate_peak = phase_peak();
+
+        let start = Instant::now();
+        let options = WriteOptions {
+            overwrite: true,
+            reproducible: true,
+            mmap_write,
+            ..WriteOptions::default()
+        };
+        write_fits_cube(scratch, rotated_fits_cube, mode_vec, &mut fits_file, options)?;
+        let write_time = start.elapsed();
+        let write_peak = phase_peak();
+        std::fs::remove_file(scratch)?;
+
+        println!(
+            "Run {} of {}: read {:.3?}, rotate {:.3?}, write {:.3?}",
+            run + 1,
+            n_runs,
+            read_time,
+            rotate_time,
+            write_time
+        );
+        timings.record([read_time, rotate_time, write_time]);
+        timings.record_peaks([read_peak, rotate_peak, write_peak]);
+    }
+    Ok(timings)
+}
+
+/// Rotate a Stokes triplet, failing the whole set if any file fails
+///
+/// The axes of all files are checked against each other before anything is
+/// read or written. If a rotation fails, the outputs already written are
+/// removed.
+///
+/// # Arguments
+///
+/// * `filenames` - The FITS files
+/// * `mode` - The mode string
+/// * `args` - The command line arguments
+///
+fn rotate_triplet(filenames: &[String], mode: &str, args: &Args) -> Result<(), FitsRotateError> {
+    check_triplet(filenames, args.tolerance)?;
+    let mut out_filenames: Vec<String> = Vec::new();
+    for filename in filenames {
+        let out_filename = output_filename(filename, mode, false, args)?;
+        if !args.null_device {
+            check_file_exists(&out_filename, args.overwrite)?;
+        }
+        // Different inputs can map to the same output, e.g. with --outdir
+        if out_filenames.contains(&out_filename) {
+            return Err(FitsRotateError::Message(format!(
+                "{} and another input would both be written to {}",
+                filename, out_filename
+            )));
+        }
+        out_filenames.push(out_filename);
+    }
+
+    let mut written: Vec<String> = Vec::new();
+    for filename in filenames {
+        match rotate_file(filename, &[mode.to_string()], args) {
+            Ok(out_filenames) => written.extend(out_filenames),
+            Err(e) => {
+                for out_filename in &written {
+                    let _ = std::fs::remove_file(out_filename);
+                    println!("Removed {} after failure", out_filename);
+                }
+                // Errors with their own exit code are passed on as they are
+                if e.exit_code() != 1 {
+                    return Err(e);
+                }
+                return Err(FitsRotateError::Message(format!("Failed to rotate {}: {}", filename, e)));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> Result<(), FitsRotateError> {
+    if let Some(command) = &args.command {
+        return match command {
+            Command::SuggestMode { filename } => print_suggested_mode(filename),
+            Command::CheckConsistency { filenames, keys, tolerance } => {
+                check_consistency(filenames, keys, *tolerance)
+            }
+            Command::FixHeader {
+                filename,
+                original,
+                mode,
+                dry_run,
+            } => repair_header(filename, original, mode.as_deref(), *dry_run),
+            Command::Env { json } => {
+                print_env_report(*json);
+                Ok(())
+            }
+            Command::Probe {
+                filename,
+                world,
+                pixel,
+                spectrum,
+            } => probe(filename, world.as_deref(), pixel.as_deref(), spectrum.as_deref()),
+            Command::History { filename, json } => print_provenance(filename, *json),
+            Command::Stream { filename, mode } => stream_file(filename, mode),
+        };
+    }
+
+    if args.version_check {
+        return check_latest_version();
+    }
+
+    if args.changelog {
+        print!("{}", CHANGELOG);
+        return Ok(());
+    }
+
+    // The whole patch is checked before any FITS file is opened
+    if let Some(patch_filename) = &args.header_patch {
+        HeaderPatch::load(patch_filename)?;
+    }
+
+    if let (Some(pattern), Some(along), Some(output)) = (&args.merge_files, args.along, &args.output) {
+        check_output_writable(output, args.mkdirs)?;
+        return merge_files(pattern, along, output, args.overwrite);
+    }
+
+    if args.list_modes {
+        let filename = args
+            .filename
+            .as_ref()
+            .ok_or_else(|| FitsRotateError::Message("--list-modes expects a FITS file".to_string()))?;
+        return print_modes(filename, args.json);
+    }
+
+    if args.print_rotation_matrix {
+        return match (&args.filename, &args.mode) {
+            (Some(filename), Some(mode)) => print_mode_matrix(filename, mode, true),
+            _ => Err(FitsRotateError::Message(
+                "--print-rotation-matrix expects a FITS file and a mode".to_string(),
+            )),
+        };
+    }
+
+    if args.list_hdus {
+        let filename = args
+            .filename
+            .as_ref()
+            .ok_or_else(|| FitsRotateError::Message("--list-hdus expects a FITS file".to_string()))?;
+        return list_hdus(filename);
+    }
+
+    if args.list_ctypes {
+        let filename = args
+            .filename
+            .as_ref()
+            .ok_or_else(|| FitsRotateError::Message("--list-ctypes expects a FITS file".to_string()))?;
+        return list_ctypes(filename);
+    }
+
+    if let Some(order) = args.print_axis_order {
+        let filename = args
+            .filename
+            .as_ref()
+            .ok_or_else(|| FitsRotateError::Message("--print-axis-order expects a FITS file".to_string()))?;
+        return print_axis_order(filename, order);
+    }
+
+    if args.fits_version {
+        let filename = args
+            .filename
+            .as_ref()
+            .ok_or_else(|| FitsRotateError::Message("--fits-version expects a FITS file".to_string()))?;
+        return print_fits_version(filename);
+    }
+
+    if args.print_permutation_matrix {
+        return match (&args.filename, &args.mode) {
+            (Some(filename), Some(mode)) => print_mode_matrix(filename, mode, false),
+            _ => Err(FitsRotateError::Message(
+                "--print-permutation-matrix expects a FITS file and a mode".to_string(),
+            )),
+        };
+    }
+
+    if let Some(n_runs) = args.benchmark {
+        return match (&args.filename, &args.mode) {
+            (Some(filename), Some(mode)) => {
+                benchmark_file(filename, mode, n_runs, args.mmap_read, args.mmap_write, args.rechunk.as_deref())
+            }
+            _ => Err(FitsRotateError::Message("--benchmark expects a FITS file and a mode".to_string())),
+        };
+    }
+
+    if let Some(ops) = &args.ops {
+        let filename = args
+            .filename
+            .as_ref()
+            .ok_or_else(|| FitsRotateError::Message("--ops expects a FITS file".to_string()))?;
+        // The whole pipeline is checked against the header before any data is read
+        let steps = parse_ops(ops)?;
+        let mut fits_file = FitsFile::open(filename)?;
+        let ctypes = read_ctypes(&mut fits_file).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+        let plan = build_plan(&steps, &ctypes)?;
+        println!("{} {}", label("Mode:"), plan.mode);
+        let mut args = args.clone();
+        args.pad_to_shape = plan.pad_to_shape;
+        rotate_file(filename, &[plan.mode], &args)?;
+        println!("{}", success("Done!"));
+        return Ok(());
+    }
+
+    if args.auto_mode {
+        let filename = args
+            .filename
+            .as_ref()
+            .ok_or_else(|| FitsRotateError::Message("--auto-mode expects a FITS file".to_string()))?;
+        let mode = target_mode(filename, &args.target_order)?;
+        rotate_file(filename, &[mode], &args)?;
+        println!("{}", success("Done!"));
+        return Ok(());
+    }
+
+    if let Some(reference) = &args.axes_from {
+        let filename = args
+            .filename
+            .as_ref()
+            .ok_or_else(|| FitsRotateError::Message("--axes-from expects a FITS file".to_string()))?;
+        let mode = reference_mode(filename, reference)?;
+        rotate_file(filename, &[mode], &args)?;
+        println!("{}", success("Done!"));
+        return Ok(());
+    }
+
+    if args.interactive {
+        let filename = args
+            .filename
+            .as_ref()
+            .ok_or_else(|| FitsRotateError::Message("--interactive expects a FITS file".to_string()))?;
+        let mode = interactive_mode(filename)?;
+        rotate_file(filename, &[mode], &args)?;
+        println!("{}", success("Done!"));
+        return Ok(());
+    }
+
+    match (&args.triplet, &args.filename, &args.mode) {
+        // The only positional argument given is the mode
+        (Some(filenames), Some(mode), None) => rotate_triplet(filenames, mode, &args)?,
+        (None, Some(filename), mode) if mode.is_some() || !args.modes.is_empty() => {
+            // The positional mode comes first, then each --mode
+            let modes: Vec<String> = mode.iter().chain(args.modes.iter()).cloned().collect();
+            let task = resolve_task(args.task_index, args.task_count, args.slurm)?;
+            if task.is_some() || is_glob(filename) || args.recursive || args.skip_existing {
+                rotate_batch(filename, &modes, task, &args)?;
+            } else {
+                rotate_file(filename, &modes, &args)?;
+            }
+        }
+        (Some(_), _, _) => {
+            return Err(FitsRotateError::Message("--triplet expects only the mode as a positional argument".to_string()));
+        }
+        _ => {
+            return Err(FitsRotateError::Message("Expected a FITS file and a mode".to_string()));
+        }
+    }
+    println!("{}", success("Done!"));
+    Ok(())
+}
+
+fn main() {
+    let args = Args::parse();
+    set_color_choice(args.color, args.no_color);
+    let mem_profile = args.mem_profile;
+    if mem_profile {
+        memory::enable();
+    }
+    if let Some(path) = &args.json_logs {
+        if let Err(e) = events::open(path) {
+            eprintln!("{} Cannot write the event log to {}: {}", color::error("Error:"), path, e);
+            std::process::exit(1);
+        }
+        events::run_started(&std::env::args().collect::<Vec<String>>());
+    }
+    let result = run(args);
+    if mem_profile {
+        println!(
+            "{} {} allocated, {} resident",
+            label("Peak memory:"),
+            format_bytes(total_peak()),
+            peak_resident().map_or("unknown".to_string(), format_bytes)
+        );
+    }
+    events::run_finished(
+        result.as_ref().err().map(|e| e.to_string()).as_deref(),
+        mem_profile.then(total_peak),
+    );
+    if let Err(e) = result {
+        eprintln!("{} {}", color::error("Error:"), e);
+        std::process::exit(e.exit_code());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::IxDyn;
+
+    /// A 2x3x4 (array order) cube where each pixel holds its own FITS pixel coordinates, as `xyz`
+    fn coordinate_cube() -> ArrayD<f32> {
+        ArrayD::from_shape_fn(IxDyn(&[2, 3, 4]), |idx| (100 * (idx[2] + 1) + 10 * (idx[1] + 1) + idx[0] + 1) as f32)
+    }
+
+    #[test]
+    fn moving_fits_axis_3_first_moves_ctype3() {
+        let rotated = rotate_fits_cube_axes(coordinate_cube(), &[3, 1, 2]);
+
+        // FITS axes 3, 1, 2 (lengths 2, 4, 3) become axes 1, 2, 3
+        assert_eq!(rotated.shape(), &[3, 4, 2]);
+        assert!(rotated.is_standard_layout());
+        // Output pixel (x, y, z) is input pixel (y, z, x)
+        for ((z, y, x), value) in rotated.clone().into_dimensionality::<ndarray::Ix3>().unwrap().indexed_iter() {
+            let expected = 100 * (y + 1) + 10 * (z + 1) + x + 1;
+            assert_eq!(*value, expected as f32);
+        }
+        // The raw data is in the new order too, so it is written that way
+        assert_eq!(&rotated.into_raw_vec_and_offset().0[..4], &[111.0, 112.0, 211.0, 212.0]);
+
+        let ctypes: Vec<String> = ["CTYPE1", "CTYPE2", "CTYPE3"].iter().map(|c| c.to_string()).collect();
+        assert_eq!(rotated_ctypes(&ctypes, &[3, 1, 2]), ["CTYPE3", "CTYPE1", "CTYPE2"]);
+    }
+
+    #[test]
+    fn cubes_round_trip_through_a_writer_and_a_reader() {
+        let header = FitsHeader {
+            cards: vec![HeaderCard::new(&format!("{:<80}", "OBJECT  = 'M31     '"))],
+        };
+        let mut bytes: Vec<u8> = Vec::new();
+        write_fits_cube_to_writer(&mut bytes, coordinate_cube(), &header).unwrap();
+        assert_eq!(bytes.len() % 2880, 0);
+
+        let (cube, read_header) = read_fits_cube_from_reader::<f32, _>(bytes.as_slice()).unwrap();
+        assert_eq!(cube, coordinate_cube());
+        let cards: Vec<String> = read_header
+            .cards
+            .iter()
+            .map(|card| card.record.trim_end().to_string())
+            .collect();
+        assert_eq!(
+            cards,
+            [
+                "SIMPLE  =                    T / file does conform to FITS standard",
+                "BITPIX  =                  -32 / number of bits per data pixel",
+                "NAXIS   =                    3 / number of data axes",
+                "NAXIS1  =                    4 / length of data axis 1",
+                "NAXIS2  =                    3 / length of data axis 2",
+                "NAXIS3  =                    2 / length of data axis 3",
+                "EXTEND  =                    T / FITS dataset may contain extensions",
+                "COMMENT   FITS (Flexible Image Transport System) format is defined in 'Astronomy",
+                "COMMENT   and Astrophysics', volume 376, page 359; bibcode: 2001A&A...376..359H",
+                "EXTNAME = '_PRIMARY'",
+                "OBJECT  = 'M31     '",
+                "END",
+            ]
+        );
+
+        // Writing what was read gives the same bytes again
+        let mut again: Vec<u8> = Vec::new();
+        let header = FitsHeader {
+            cards: read_header.cards.into_iter().filter(|card| !card.is_commentary()).collect(),
+        };
+        write_fits_cube_to_writer(&mut again, cube, &header).unwrap();
+        assert_eq!(again, bytes);
+    }
+
+    #[test]
+    fn modes_are_parsed_as_digits_or_with_commas() {
+        assert_eq!(parse_mode("312", 3).unwrap(), [3, 1, 2]);
+        assert_eq!(parse_mode("3,1,2", 3).unwrap(), [3, 1, 2]);
+        assert_eq!(parse_mode("3-12", 3).unwrap(), [3, 1, 2]);
+        assert_eq!(parse_flips("3-12"), [1]);
+        assert_eq!(parse_flips("-3,1,-2"), [3, 2]);
+        let long = "10,1,2,3,4,5,6,7,8,-9";
+        assert_eq!(parse_mode(long, 10).unwrap(), [10, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        assert_eq!(parse_flips(long), [9]);
+
+        assert!(matches!(
+            parse_mode("12", 3),
+            Err(FitsRotateError::ModeLength { length: 2, naxis: 3, .. })
+        ));
+        let repeated = parse_mode("112", 3).unwrap_err().to_string();
+        assert!(repeated.contains("axis 1 appears 2 times"), "{}", repeated);
+        assert!(parse_mode("1x2", 3).is_err());
+        assert!(parse_mode("1,2,", 3).is_err());
+    }
+
+    #[test]
+    fn formatted_modes_parse_back() {
+        assert_eq!(format_mode(&[3, 1, 2]), "312");
+        assert_eq!(format_mode(&[10, 1, 2, 3, 4, 5, 6, 7, 8, 9]), "10,1,2,3,4,5,6,7,8,9");
+        for naxis in 1..13 {
+            let mode: Vec<usize> = (1..naxis + 1).rev().collect();
+            assert_eq!(parse_mode(&format_mode(&mode), naxis).unwrap(), mode);
+        }
+    }
 }
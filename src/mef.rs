@@ -0,0 +1,105 @@
+use crate::distortion::distortion_axis;
+use crate::header::{append_hdu_blocks, copy_hdu, delete_key, FitsHeader, HeaderCard, CARD_LENGTH};
+use crate::wcs::is_wcs_card;
+use fitsio::errors::Error;
+use fitsio::FitsFile;
+use std::path::Path;
+
+/// Keywords that describe the image, which stay with the image along with its WCS
+const IMAGE_KEYWORDS: [&str; 13] = [
+    "EXTNAME", "EXTVER", "EXTLEVEL", "INHERIT", "BUNIT", "BLANK", "BSCALE", "BZERO", "DATAMIN", "DATAMAX", "BMAJ",
+    "BMIN", "BPA",
+];
+
+/// Check if a card is global metadata rather than a description of the image
+///
+/// Structural cards, the WCS (including distortions, with or without an
+/// alternate letter) and keywords describing the pixel values or beam
+/// belong to the image. Everything else, such as the telescope, observer
+/// and HISTORY, is global.
+///
+/// # Arguments
+///
+/// * `card` - The card
+///
+/// # Returns
+///
+/// * `bool` - Whether the card belongs in the primary header of a MEF
+///
+pub fn is_global_card(card: &HeaderCard) -> bool {
+    if card.is_structural() {
+        return false;
+    }
+    let keyword = card.keyword();
+    !IMAGE_KEYWORDS.contains(&keyword.as_str()) && distortion_axis(&keyword).is_none() && !is_wcs_card(card)
+}
+
+/// Rewrite a single-image FITS file as a MEF with a dataless primary
+///
+/// The global cards of the image header (see `is_global_card`) are copied
+/// to an empty primary HDU, and the image, with its full header, becomes
+/// the first extension, named `extname`. Any extensions of the file follow
+/// it in their original order. The MEF is written next to the file and
+/// then renamed over it.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file, with the image in its primary HDU
+/// * `extname` - The EXTNAME of the image extension
+/// * `extver` - The EXTVER of the image extension, if any
+/// * `inherit` - Write `INHERIT = T` in the image extension, so that readers merge in the primary header
+///
+pub fn write_mef_layout(filename: &str, extname: &str, extver: Option<i64>, inherit: bool) -> Result<(), Error> {
+    let mut single = FitsFile::open(filename)?;
+    single.primary_hdu()?;
+    let header = FitsHeader::read(&mut single)?;
+    let global = FitsHeader {
+        cards: header
+            .cards
+            .iter()
+            .filter(|card| is_global_card(card))
+            .cloned()
+            .collect(),
+    };
+
+    let partial = format!("{}.{}.partial", filename, std::process::id());
+    let _ = std::fs::remove_file(&partial);
+    let result = (|| -> Result<(), Error> {
+        let mut mef = FitsFile::create(&partial).open()?;
+        mef.primary_hdu()?;
+        global.write(&mut mef, &[])?;
+
+        // CFITSIO turns a copied primary array into an IMAGE extension
+        copy_hdu(&mut single, &mut mef, 0)?;
+        let extension = mef.hdu(1)?;
+        for keyword in ["EXTNAME", "EXTVER", "INHERIT"] {
+            if extension.read_key::<String>(&mut mef, keyword).is_ok() {
+                delete_key(&mut mef, keyword)?;
+            }
+        }
+        extension.write_key(&mut mef, "EXTNAME", extname)?;
+        if let Some(extver) = extver {
+            extension.write_key(&mut mef, "EXTVER", extver)?;
+        }
+        if inherit {
+            let record = format!("{:<8}= {:>20} / {}", "INHERIT", "T", "Inherit the primary header");
+            FitsHeader {
+                cards: vec![HeaderCard::new(&format!("{:<width$}", record, width = CARD_LENGTH))],
+            }
+            .write(&mut mef, &[])?;
+        }
+
+        // The other HDUs are copied unchanged, as raw blocks after the file is closed
+        drop(mef);
+        let hdus: Vec<usize> = (1..single.num_hdus()?).collect();
+        append_hdu_blocks(&mut single, Path::new(&partial), &hdus)?;
+        Ok(())
+    })();
+    drop(single);
+    if let Err(e) = result {
+        let _ = std::fs::remove_file(&partial);
+        return Err(e);
+    }
+    std::fs::rename(&partial, filename)?;
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Whether allocations are being counted
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// The bytes allocated since counting started, and not yet freed
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+
+/// The most bytes allocated at once since the last call to `phase_peak`
+static PHASE_PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// The most bytes allocated at once since counting started
+static TOTAL_PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/// The system allocator, counting the bytes allocated once `enable` is called
+///
+/// Until then each allocation only checks a flag, so the cost is
+/// negligible unless `--mem-profile` is given.
+pub struct CountingAllocator;
+
+/// Count an allocation
+fn grow(size: usize) {
+    let current = CURRENT.fetch_add(size, Ordering::Relaxed) + size;
+    PHASE_PEAK.fetch_max(current, Ordering::Relaxed);
+    TOTAL_PEAK.fetch_max(current, Ordering::Relaxed);
+}
+
+/// Count a deallocation, which may free memory allocated before counting started
+fn shrink(size: usize) {
+    let _ = CURRENT.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |current| {
+        Some(current.saturating_sub(size))
+    });
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() && ENABLED.load(Ordering::Relaxed) {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc_zeroed(layout);
+        if !ptr.is_null() && ENABLED.load(Ordering::Relaxed) {
+            grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        if ENABLED.load(Ordering::Relaxed) {
+            shrink(layout.size());
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = System.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() && ENABLED.load(Ordering::Relaxed) {
+            if new_size > layout.size() {
+                grow(new_size - layout.size());
+            } else {
+                shrink(layout.size() - new_size);
+            }
+        }
+        new_ptr
+    }
+}
+
+/// Start counting allocations
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// The most bytes allocated at once since the last call, which starts a new phase
+///
+/// # Returns
+///
+/// * `usize` - The peak of the phase that just ended
+///
+pub fn phase_peak() -> usize {
+    PHASE_PEAK.swap(CURRENT.load(Ordering::Relaxed), Ordering::Relaxed)
+}
+
+/// The most bytes allocated at once since counting started
+pub fn total_peak() -> usize {
+    TOTAL_PEAK.load(Ordering::Relaxed)
+}
+
+/// The peak resident set size of the process (`VmHWM`), on Linux
+///
+/// This includes memory allocated outside Rust, e.g. the buffers of CFITSIO.
+///
+/// # Returns
+///
+/// * `Option<usize>` - The peak resident bytes, or `None` where `/proc/self/status` is not available
+///
+pub fn peak_resident() -> Option<usize> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: usize = line["VmHWM:".len()..]
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kib * 1024)
+}
+
+/// A number of bytes in binary units, e.g. `1.50 GiB`
+pub fn format_bytes(bytes: usize) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.2} {}", value, UNITS[unit])
+    }
+}
@@ -0,0 +1,225 @@
+use crate::color::{label, success};
+use crate::error::FitsRotateError;
+use crate::header::replace_key;
+use crate::{fits_index_to_array_index, read_fits_cube, write_fits_cube, WriteOptions};
+use fitsio::FitsFile;
+use ndarray::{concatenate, ArrayD, ArrayViewD, Axis};
+use std::path::Path;
+
+/// Relative tolerance when checking that merged planes are evenly spaced
+const SPACING_TOLERANCE: f64 = 1e-6;
+
+/// Match a name against a glob pattern supporting `*` and `?`
+pub fn glob_match(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => {
+            glob_match(&pattern[1..], name) || (!name.is_empty() && glob_match(pattern, &name[1..]))
+        }
+        (Some('?'), Some(_)) => glob_match(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_match(&pattern[1..], &name[1..]),
+        _ => false,
+    }
+}
+
+/// Find the files matching a glob pattern
+///
+/// Wildcards are only supported in the file name, not in the directories.
+///
+/// # Arguments
+///
+/// * `pattern` - The glob pattern (e.g. `cube.rot.*.fits`)
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, FitsRotateError>` - The matching files, sorted by name
+///
+pub fn glob_files(pattern: &str) -> Result<Vec<String>, FitsRotateError> {
+    let path = Path::new(pattern);
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let file_pattern: Vec<char> = path
+        .file_name()
+        .map(|name| name.to_string_lossy().chars().collect())
+        .unwrap_or_default();
+
+    let mut matches: Vec<String> = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name: Vec<char> = entry.file_name().to_string_lossy().chars().collect();
+        if entry.path().is_file() && glob_match(&file_pattern, &name) {
+            matches.push(entry.path().to_string_lossy().to_string());
+        }
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+/// Find the files matching a glob pattern in a directory and all of its subdirectories
+///
+/// A directory on its own stands for all the FITS files under it (`<dir>/*.fits`).
+/// Symbolic links to directories are not followed, so a link back up the tree
+/// is not walked forever.
+///
+/// # Arguments
+///
+/// * `pattern` - The directory, or a glob pattern whose file name is matched in every directory below its own
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, FitsRotateError>` - The matching files, sorted by path
+///
+pub fn walk_files(pattern: &str) -> Result<Vec<String>, FitsRotateError> {
+    let path = Path::new(pattern);
+    let (root, file_pattern) = if path.is_dir() {
+        (path, "*.fits".chars().collect::<Vec<char>>())
+    } else {
+        let root = match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let file_pattern = path
+            .file_name()
+            .map(|name| name.to_string_lossy().chars().collect())
+            .unwrap_or_default();
+        (root, file_pattern)
+    };
+
+    let mut matches: Vec<String> = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)? {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+            let name: Vec<char> = entry.file_name().to_string_lossy().chars().collect();
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if entry.path().is_file() && glob_match(&file_pattern, &name) {
+                matches.push(entry.path().to_string_lossy().to_string());
+            }
+        }
+    }
+    matches.sort();
+    Ok(matches)
+}
+
+/// Read a floating point keyword, falling back to a default if it is missing
+fn read_key_or(fits_file: &mut FitsFile, keyword: &str, default: f64) -> f64 {
+    fits_file
+        .primary_hdu()
+        .and_then(|hdu| hdu.read_key::<f64>(fits_file, keyword))
+        .unwrap_or(default)
+}
+
+/// Stack separately written planes (or sub-cubes) back into a single cube
+///
+/// The files are stacked in order of their names. The merged axis takes its
+/// CRVAL and CRPIX from the first file, and its CDELT from the spacing of the
+/// world coordinates of the first pixel of successive files.
+///
+/// # Arguments
+///
+/// * `pattern` - Glob pattern matching the files to merge
+/// * `along` - The (FITS, 1-based) axis to stack along
+/// * `output` - The merged FITS file
+/// * `overwrite` - Overwrite the merged file if it already exists
+///
+pub fn merge_files(
+    pattern: &str,
+    along: usize,
+    output: &str,
+    overwrite: bool,
+) -> Result<(), FitsRotateError> {
+    let filenames = glob_files(pattern)?;
+    if filenames.is_empty() {
+        return Err(FitsRotateError::Message(format!("No files match {}", pattern)));
+    }
+    println!("Merging {} files along axis {}", filenames.len(), along);
+
+    let mut cubes: Vec<ArrayD<f32>> = Vec::with_capacity(filenames.len());
+    let mut first_pixels: Vec<f64> = Vec::with_capacity(filenames.len());
+    let mut first_file: Option<FitsFile> = None;
+    let (crval_card, cdelt_card, crpix_card) = (
+        format!("CRVAL{}", along),
+        format!("CDELT{}", along),
+        format!("CRPIX{}", along),
+    );
+    for filename in &filenames {
+        let (cube, mut fits_file) = read_fits_cube(filename)?;
+        if along < 1 || along > cube.ndim() {
+            return Err(FitsRotateError::Message(format!(
+                "Cannot merge along axis {} of {}, which has {} axes",
+                along,
+                filename,
+                cube.ndim()
+            )));
+        }
+        if let Some(first) = cubes.first() {
+            let axis = fits_index_to_array_index(along, cube.ndim());
+            let compatible = first.ndim() == cube.ndim()
+                && (0..cube.ndim()).all(|idx| idx == axis || first.shape()[idx] == cube.shape()[idx]);
+            if !compatible {
+                return Err(FitsRotateError::Message(format!(
+                    "Shape of {} ({:?}) is not compatible with {} ({:?}) for merging along axis {}",
+                    filename,
+                    cube.shape(),
+                    filenames[0],
+                    first.shape(),
+                    along
+                )));
+            }
+        }
+        let crval = read_key_or(&mut fits_file, &crval_card, 0.0);
+        let cdelt = read_key_or(&mut fits_file, &cdelt_card, 1.0);
+        let crpix = read_key_or(&mut fits_file, &crpix_card, 1.0);
+        first_pixels.push(crval + (1.0 - crpix) * cdelt);
+        cubes.push(cube);
+        if first_file.is_none() {
+            first_file = Some(fits_file);
+        }
+    }
+
+    let ndim = cubes[0].ndim();
+    let axis = Axis(fits_index_to_array_index(along, ndim));
+    let lengths: Vec<usize> = cubes.iter().map(|cube| cube.len_of(axis)).collect();
+
+    // Work out the spacing of the merged axis from the first pixels
+    let mut first_file = first_file.unwrap();
+    let mut cdelt = read_key_or(&mut first_file, &cdelt_card, 1.0);
+    if first_pixels.len() > 1 {
+        cdelt = (first_pixels[1] - first_pixels[0]) / lengths[0] as f64;
+        let mut offset = 0;
+        for (idx, first_pixel) in first_pixels.iter().enumerate() {
+            let expected = first_pixels[0] + offset as f64 * cdelt;
+            if (first_pixel - expected).abs() > SPACING_TOLERANCE * cdelt.abs().max(f64::MIN_POSITIVE) {
+                println!(
+                    "Warning: {} is not evenly spaced along axis {} (expected {}, found {})",
+                    filenames[idx], along, expected, first_pixel
+                );
+            }
+            offset += lengths[idx];
+        }
+    }
+    let crpix = read_key_or(&mut first_file, &crpix_card, 1.0);
+
+    let views: Vec<ArrayViewD<f32>> = cubes.iter().map(|cube| cube.view()).collect();
+    let merged = concatenate(axis, &views).map_err(|e| {
+        FitsRotateError::Message(format!("Could not merge cubes: {}", e))
+    })?;
+    println!("{} {:?}", label("Merged FITS cube shape:"), merged.shape());
+
+    let identity: Vec<usize> = (1..ndim + 1).collect();
+    let options = WriteOptions {
+        overwrite,
+        ..WriteOptions::default()
+    };
+    write_fits_cube(output, merged, &identity, &mut first_file, options)?;
+
+    let mut out_file = FitsFile::edit(output)?;
+    replace_key(&mut out_file, &cdelt_card, cdelt)?;
+    replace_key(&mut out_file, &crpix_card, crpix)?;
+    println!("{}", success(&format!("Wrote merged FITS cube to {}", output)));
+    Ok(())
+}
@@ -0,0 +1,260 @@
+use crate::axes::{axis_role, suggest_mode, AxisRole};
+use crate::buildinfo::json_string;
+use crate::permutation::rotation_matrix_entry;
+use crate::{format_mode, rotated_ctypes};
+
+/// The most axes for which every permutation is listed (5! = 120 modes)
+pub const MAX_FULL_NAXIS: usize = 5;
+
+/// A mode of a file, with the order of the axes it gives
+#[derive(Clone, Debug, PartialEq)]
+pub struct ModeEntry {
+    /// The mode
+    pub mode: Vec<usize>,
+    /// The CTYPE of each output axis
+    pub ctypes: Vec<String>,
+    /// Notes on common modes, e.g. `identity`
+    pub notes: Vec<&'static str>,
+}
+
+/// Step to the next permutation in lexicographic order, returning false after the last
+fn next_permutation(mode: &mut [usize]) -> bool {
+    let pivot = match (1..mode.len()).rev().find(|idx| mode[idx - 1] < mode[*idx]) {
+        Some(idx) => idx - 1,
+        None => return false,
+    };
+    let successor = (pivot + 1..mode.len())
+        .rev()
+        .find(|idx| mode[*idx] > mode[pivot])
+        .unwrap_or(pivot);
+    mode.swap(pivot, successor);
+    mode[pivot + 1..].reverse();
+    true
+}
+
+/// The modes to list for a number of axes
+///
+/// Every permutation is listed for up to `MAX_FULL_NAXIS` axes. Beyond
+/// that, only the modes one step from the identity are: each swap of two
+/// axes and each roll of all axes.
+fn candidate_modes(naxis: usize) -> Vec<Vec<usize>> {
+    let identity: Vec<usize> = (1..naxis + 1).collect();
+    if naxis <= MAX_FULL_NAXIS {
+        let mut modes = vec![identity.clone()];
+        let mut mode = identity;
+        while next_permutation(&mut mode) {
+            modes.push(mode.clone());
+        }
+        return modes;
+    }
+    let mut modes = vec![identity.clone(), identity.iter().rev().copied().collect()];
+    for i in 0..naxis {
+        for j in i + 1..naxis {
+            let mut mode = identity.clone();
+            mode.swap(i, j);
+            modes.push(mode);
+        }
+    }
+    for shift in 1..naxis {
+        let mut mode = identity.clone();
+        mode.rotate_left(shift);
+        modes.push(mode);
+    }
+    modes
+}
+
+/// List the modes of a file, with the order of the axes each gives
+///
+/// The identity, the reversal, the standard (RA, DEC, FREQ, STOKES) order
+/// of `suggest_mode`, and modes that put the spectral axis first or the
+/// STOKES axis last are noted. The standard order is always listed, even
+/// when there are too many axes to list every permutation.
+///
+/// # Arguments
+///
+/// * `ctypes` - The CTYPEs of the file, in FITS axis order
+///
+/// # Returns
+///
+/// * `Vec<ModeEntry>` - The modes
+///
+pub fn list_modes(ctypes: &[String]) -> Vec<ModeEntry> {
+    let naxis = ctypes.len();
+    let (standard, _) = suggest_mode(ctypes);
+    let mut modes = candidate_modes(naxis);
+    if !modes.contains(&standard) {
+        modes.push(standard.clone());
+    }
+    modes
+        .into_iter()
+        .map(|mode| {
+            let rotated: Vec<String> = mode.iter().map(|axis| ctypes[axis - 1].clone()).collect();
+            let role = |ctype: Option<&String>| ctype.and_then(|ctype| axis_role(ctype));
+            let mut notes: Vec<&'static str> = Vec::new();
+            if mode.iter().enumerate().all(|(idx, axis)| *axis == idx + 1) {
+                notes.push("identity");
+            }
+            if naxis > 1 && mode.iter().enumerate().all(|(idx, axis)| *axis == naxis - idx) {
+                notes.push("reverse");
+            }
+            if mode == standard {
+                notes.push("standard");
+            }
+            if role(rotated.first()) == Some(AxisRole::Spectral) {
+                notes.push("freq-first");
+            }
+            if naxis > 1 && role(rotated.last()) == Some(AxisRole::Stokes) {
+                notes.push("stokes-last");
+            }
+            ModeEntry {
+                mode,
+                ctypes: rotated,
+                notes,
+            }
+        })
+        .collect()
+}
+
+/// The rows of a table of modes
+///
+/// # Arguments
+///
+/// * `entries` - The modes
+///
+/// # Returns
+///
+/// * `Vec<String>` - The header and a row for each mode
+///
+pub fn modes_table(entries: &[ModeEntry]) -> Vec<String> {
+    let rows: Vec<(String, String, String)> = entries
+        .iter()
+        .map(|entry| {
+            (
+                format_mode(&entry.mode),
+                entry.ctypes.join(", "),
+                entry.notes.join(", "),
+            )
+        })
+        .collect();
+    let mode_width = rows.iter().map(|(mode, _, _)| mode.len()).max().unwrap_or(0).max(4);
+    let order_width = rows.iter().map(|(_, order, _)| order.len()).max().unwrap_or(0).max(5);
+    let row = |mode: &str, order: &str, notes: &str| {
+        format!("{:<mode_width$}  {:<order_width$}  {}", mode, order, notes)
+            .trim_end()
+            .to_string()
+    };
+    let mut lines = vec![row("MODE", "ORDER", "NOTES")];
+    lines.extend(rows.iter().map(|(mode, order, notes)| row(mode, order, notes)));
+    lines
+}
+
+/// The rows of a mode written as a matrix, with a row for each output axis and a column for each input axis
+///
+/// Entry (i, j) is 1 where output axis i is taken from input axis j, as in
+/// a `PCi_j` matrix, and 0 elsewhere. The flag chooses between the two
+/// ways the matrix is printed: `--print-rotation-matrix` gives a reversed
+/// axis -1 and labels the axes by CTYPE, while `--print-permutation-matrix`
+/// ignores reversals and labels them `AXISn CTYPE`.
+///
+/// # Arguments
+///
+/// * `ctypes` - The CTYPEs of the input, in FITS axis order
+/// * `mode` - The input axis of each output axis
+/// * `flips` - The (FITS, 1-based) input axes that are reversed
+/// * `signed` - Give reversed axes -1 and label the axes by CTYPE alone
+///
+/// # Returns
+///
+/// * `Vec<String>` - The header and a row for each output axis
+///
+pub fn matrix_table(ctypes: &[String], mode: &[usize], flips: &[usize], signed: bool) -> Vec<String> {
+    let label = |idx: usize, ctype: &str| {
+        if signed {
+            ctype.to_string()
+        } else {
+            format!("AXIS{} {}", idx + 1, ctype).trim_end().to_string()
+        }
+    };
+    let row_labels: Vec<String> = rotated_ctypes(ctypes, mode)
+        .iter()
+        .enumerate()
+        .map(|(idx, ctype)| label(idx, ctype))
+        .collect();
+    let col_labels: Vec<String> = ctypes.iter().enumerate().map(|(idx, ctype)| label(idx, ctype)).collect();
+    let flips = if signed { flips } else { &[] };
+    let corner = "out \\ in";
+    let row_width = row_labels.iter().map(|l| l.len()).max().unwrap_or(0).max(corner.len());
+    let col_width = col_labels.iter().map(|l| l.len()).max().unwrap_or(0).max(2);
+
+    let mut header = format!("{:<row_width$}", corner);
+    for col_label in &col_labels {
+        header += &format!("  {:>col_width$}", col_label);
+    }
+    let mut lines = vec![header];
+    for (out_idx, row_label) in row_labels.iter().enumerate() {
+        let mut row = format!("{:<row_width$}", row_label);
+        for in_idx in 0..ctypes.len() {
+            row += &format!("  {:>col_width$}", rotation_matrix_entry(mode, flips, out_idx + 1, in_idx + 1));
+        }
+        lines.push(row);
+    }
+    lines
+}
+
+/// The modes as a JSON array of objects with `mode`, `ctypes` and `notes`
+pub fn modes_json(entries: &[ModeEntry]) -> String {
+    let strings = |values: &[String]| {
+        let values: Vec<String> = values.iter().map(|value| json_string(value)).collect();
+        format!("[{}]", values.join(", "))
+    };
+    let objects: Vec<String> = entries
+        .iter()
+        .map(|entry| {
+            let notes: Vec<String> = entry.notes.iter().map(|note| note.to_string()).collect();
+            format!(
+                "  {{\"mode\": {}, \"ctypes\": {}, \"notes\": {}}}",
+                json_string(&format_mode(&entry.mode)),
+                strings(&entry.ctypes),
+                strings(&notes)
+            )
+        })
+        .collect();
+    format!("[\n{}\n]", objects.join(",\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctypes() -> Vec<String> {
+        ["RA---SIN", "DEC--SIN", "FREQ"].iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn the_permutation_matrix_labels_the_axes_by_number_and_ignores_reversals() {
+        let lines = matrix_table(&ctypes(), &[3, 1, 2], &[1], false);
+        assert_eq!(
+            lines,
+            vec![
+                "out \\ in        AXIS1 RA---SIN  AXIS2 DEC--SIN      AXIS3 FREQ",
+                "AXIS1 FREQ                   0               0               1",
+                "AXIS2 RA---SIN               1               0               0",
+                "AXIS3 DEC--SIN               0               1               0",
+            ]
+        );
+    }
+
+    #[test]
+    fn the_rotation_matrix_gives_reversed_axes_minus_one() {
+        let lines = matrix_table(&ctypes(), &[3, 2, 1], &[3], true);
+        assert_eq!(
+            lines,
+            vec![
+                "out \\ in  RA---SIN  DEC--SIN      FREQ",
+                "FREQ             0         0        -1",
+                "DEC--SIN         0         1         0",
+                "RA---SIN         1         0         0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,186 @@
+use crate::axes::axis_role;
+use crate::error::FitsRotateError;
+use crate::pad::parse_shape;
+use crate::{mode_tokens, parse_mode};
+use crate::permutation::compose_modes;
+use std::fmt;
+
+/// Operations that `--ops` knows of but fitsrotate_rs cannot do yet
+const UNSUPPORTED_OPERATIONS: [&str; 2] = ["crop", "squeeze"];
+
+/// A step of an `--ops` pipeline
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operation {
+    /// Reorder the axes with a mode, as in the positional mode argument
+    Rotate(String),
+    /// Reverse an axis, given by number (in the current axis order) or by role (e.g. `freq`)
+    Flip(String),
+    /// Pad the axes to a shape, as in `--pad-to-shape`
+    Pad(String),
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::Rotate(mode) => write!(f, "rotate={}", mode),
+            Operation::Flip(axis) => write!(f, "flip={}", axis),
+            Operation::Pad(shape) => write!(f, "pad={}", shape),
+        }
+    }
+}
+
+/// Parse an `--ops` pipeline, e.g. `rotate=4123,flip=freq,pad=512,512,288,4`
+///
+/// Steps are separated by commas. Since modes and shapes can contain
+/// commas themselves, anything after a comma that does not start a new
+/// step continues the value of the step before.
+///
+/// # Arguments
+///
+/// * `ops` - The pipeline
+///
+/// # Returns
+///
+/// * `Result<Vec<Operation>, FitsRotateError>` - The steps, in order
+///
+pub fn parse_ops(ops: &str) -> Result<Vec<Operation>, FitsRotateError> {
+    let is_step = |token: &str| {
+        let name = token.split('=').next().unwrap_or("").trim();
+        ["rotate", "flip", "pad"].contains(&name) || UNSUPPORTED_OPERATIONS.contains(&name)
+    };
+    let mut steps: Vec<String> = Vec::new();
+    for token in ops.split(',') {
+        match steps.last_mut() {
+            Some(step) if !is_step(token) => {
+                step.push(',');
+                step.push_str(token.trim());
+            }
+            _ => steps.push(token.trim().to_string()),
+        }
+    }
+    steps
+        .iter()
+        .map(|step| {
+            let (name, value) = match step.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim().to_string()),
+                None => (step.as_str(), String::new()),
+            };
+            if UNSUPPORTED_OPERATIONS.contains(&name) {
+                return Err(FitsRotateError::Message(format!(
+                    "--ops step `{}` is not supported yet (supported: rotate, flip, pad)",
+                    step
+                )));
+            }
+            if value.is_empty() {
+                return Err(FitsRotateError::Message(format!(
+                    "--ops step `{}` needs a value, e.g. rotate=312, flip=freq or pad=512,512,288",
+                    step
+                )));
+            }
+            match name {
+                "rotate" => Ok(Operation::Rotate(value)),
+                "flip" => Ok(Operation::Flip(value)),
+                "pad" => Ok(Operation::Pad(value)),
+                _ => Err(FitsRotateError::Message(format!(
+                    "Unknown --ops step `{}` (supported: rotate, flip, pad)",
+                    step
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// A pipeline reduced to a single rotation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Plan {
+    /// The mode doing every rotate and flip step at once
+    pub mode: String,
+    /// The shape to pad the output to, if any
+    pub pad_to_shape: Option<String>,
+}
+
+/// Reduce a pipeline to a single mode, checking it against the axes of a cube
+///
+/// Flipping an axis and then reordering the axes is the same as reordering
+/// them and flipping wherever that axis ends up, so the rotate and flip
+/// steps can all be done at once: the rotations compose into one mode, and
+/// each flip is moved back to the input axis it applies to. Padding has to
+/// be the last step, since it changes what a later flip would do. Only the
+/// header is needed, so every step is checked before any data is read.
+///
+/// # Arguments
+///
+/// * `ops` - The steps
+/// * `ctypes` - The CTYPEs of the input, in FITS axis order
+///
+/// # Returns
+///
+/// * `Result<Plan, FitsRotateError>` - The mode and padding of the whole pipeline
+///
+pub fn build_plan(ops: &[Operation], ctypes: &[String]) -> Result<Plan, FitsRotateError> {
+    let naxis = ctypes.len();
+    // The input axis of each current axis, and which input axes are reversed
+    let mut mode: Vec<usize> = (1..naxis + 1).collect();
+    let mut flipped = vec![false; naxis];
+    let mut pad_to_shape: Option<String> = None;
+    for (idx, op) in ops.iter().enumerate() {
+        let step_error =
+            |message: String| FitsRotateError::Message(format!("--ops step {} ({}): {}", idx + 1, op, message));
+        if pad_to_shape.is_some() {
+            return Err(step_error("pad must be the last step".to_string()));
+        }
+        match op {
+            Operation::Rotate(step_mode) => {
+                // Flips within a rotate step are applied before it reorders
+                for (axis, flip) in mode_tokens(step_mode)? {
+                    if flip && axis >= 1 && axis <= naxis {
+                        flipped[mode[axis - 1] - 1] ^= true;
+                    }
+                }
+                let step_mode = parse_mode(step_mode, naxis).map_err(|e| step_error(e.to_string()))?;
+                let mut composed = vec![0; naxis];
+                compose_modes(&mode, &step_mode, &mut composed);
+                mode = composed;
+            }
+            Operation::Flip(axis) => {
+                let input_axis = match axis.parse::<usize>() {
+                    Ok(axis) if axis >= 1 && axis <= naxis => mode[axis - 1],
+                    Ok(axis) => {
+                        return Err(step_error(format!(
+                            "there is no axis {} in a cube with {} axes",
+                            axis, naxis
+                        )))
+                    }
+                    Err(_) => {
+                        let role = axis_role(axis)
+                            .ok_or_else(|| step_error(format!("{} is neither an axis number nor a role", axis)))?;
+                        let input_idx = ctypes
+                            .iter()
+                            .position(|ctype| axis_role(ctype) == Some(role))
+                            .ok_or_else(|| {
+                                step_error(format!("there is no {} axis (CTYPEs: {})", role, ctypes.join(", ")))
+                            })?;
+                        input_idx + 1
+                    }
+                };
+                flipped[input_axis - 1] ^= true;
+            }
+            Operation::Pad(shape) => {
+                parse_shape(shape, naxis).map_err(|e| step_error(e.to_string()))?;
+                pad_to_shape = Some(shape.clone());
+            }
+        }
+    }
+    let tokens: Vec<String> = mode
+        .iter()
+        .map(|axis| {
+            if flipped[axis - 1] {
+                format!("-{}", axis)
+            } else {
+                axis.to_string()
+            }
+        })
+        .collect();
+    let mode = if naxis > 9 { tokens.join(",") } else { tokens.concat() };
+    Ok(Plan { mode, pad_to_shape })
+}
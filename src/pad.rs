@@ -0,0 +1,215 @@
+use crate::error::FitsRotateError;
+use crate::fits_index_to_array_index;
+use clap::ValueEnum;
+use ndarray::{ArrayD, Axis, Slice};
+
+/// How to fill the pixels added by `--pad-to-shape`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum PadMode {
+    /// Fill with 0
+    Zero,
+    /// Fill with NaN
+    Nan,
+    /// Repeat the edge value
+    Edge,
+    /// Mirror the axis about its edge pixel
+    Reflect,
+    /// Continue from the start of the axis (circular)
+    Wrap,
+}
+
+/// Parse a shape given as comma-separated axis lengths in FITS order
+///
+/// # Arguments
+///
+/// * `shape` - The shape string (e.g. `512,512,288`)
+/// * `naxis` - The number of axes in the FITS cube
+///
+/// # Returns
+///
+/// * `Result<Vec<usize>, FitsRotateError>` - The axis lengths, in FITS order
+///
+pub fn parse_shape(shape: &str, naxis: usize) -> Result<Vec<usize>, FitsRotateError> {
+    let lengths = shape
+        .split(',')
+        .map(|length| {
+            length.trim().parse::<usize>().map_err(|e| {
+                FitsRotateError::Message(format!("Could not convert axis length {} to integer: {}", length, e))
+            })
+        })
+        .collect::<Result<Vec<usize>, FitsRotateError>>()?;
+    if lengths.len() != naxis {
+        return Err(FitsRotateError::Message(format!(
+            "Shape {} has {} axes, but the cube has {}",
+            shape,
+            lengths.len(),
+            naxis
+        )));
+    }
+    Ok(lengths)
+}
+
+/// The index of the original pixel that a padded pixel is copied from
+///
+/// # Arguments
+///
+/// * `index` - The index along the padded axis
+/// * `len` - The length of the original axis
+/// * `mode` - The padding mode (one of edge, reflect or wrap)
+///
+/// # Returns
+///
+/// * `usize` - The index along the original axis
+///
+fn source_index(index: usize, len: usize, mode: PadMode) -> usize {
+    if index < len {
+        return index;
+    }
+    match mode {
+        PadMode::Reflect if len > 1 => {
+            // Reflections repeat with a period of 2 * (len - 1)
+            let period = 2 * (len - 1);
+            let phase = index % period;
+            if phase < len {
+                phase
+            } else {
+                period - phase
+            }
+        }
+        PadMode::Wrap => index % len,
+        _ => len - 1,
+    }
+}
+
+/// Pad a FITS cube to a given shape
+///
+/// Pixels are added after the end of each axis, so the reference pixels
+/// (CRPIX) of the WCS are unchanged.
+///
+/// # Arguments
+///
+/// * `fits_cube` - The FITS cube
+/// * `shape` - The padded axis lengths, in FITS order
+/// * `mode` - How to fill the added pixels
+///
+/// # Returns
+///
+/// * `Result<ArrayD<T>, FitsRotateError>` - The padded FITS cube
+///
+pub fn pad_cube<T: Copy + From<f32>>(
+    mut fits_cube: ArrayD<T>,
+    shape: &[usize],
+    mode: PadMode,
+) -> Result<ArrayD<T>, FitsRotateError> {
+    let naxis = fits_cube.ndim();
+    for (fits_idx, new_len) in shape.iter().enumerate() {
+        let axis = Axis(fits_index_to_array_index(fits_idx + 1, naxis));
+        let len = fits_cube.len_of(axis);
+        if *new_len < len {
+            return Err(FitsRotateError::Message(format!(
+                "Cannot pad axis {} of length {} to the shorter length {}",
+                fits_idx + 1,
+                len,
+                new_len
+            )));
+        }
+        if *new_len == len {
+            continue;
+        }
+        if len == 0 && !matches!(mode, PadMode::Zero | PadMode::Nan) {
+            return Err(FitsRotateError::Message(format!(
+                "Cannot pad the empty axis {} with {:?} padding",
+                fits_idx + 1,
+                mode
+            )));
+        }
+        fits_cube = match mode {
+            PadMode::Zero | PadMode::Nan => {
+                let fill = if mode == PadMode::Zero { T::from(0.0) } else { T::from(f32::NAN) };
+                let mut padded_shape = fits_cube.shape().to_vec();
+                padded_shape[axis.index()] = *new_len;
+                let mut padded = ArrayD::from_elem(padded_shape, fill);
+                padded
+                    .slice_axis_mut(axis, Slice::from(0..len))
+                    .assign(&fits_cube);
+                padded
+            }
+            _ => {
+                let indices: Vec<usize> = (0..*new_len)
+                    .map(|index| source_index(index, len, mode))
+                    .collect();
+                fits_cube.select(axis, &indices)
+            }
+        };
+    }
+    Ok(fits_cube)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::{arr1, arr2, IxDyn};
+
+    /// Pad the 1-D array `1, 2, 3` to 8 pixels
+    fn pad_row(mode: PadMode) -> Vec<f32> {
+        let row = arr1(&[1.0f32, 2.0, 3.0]).into_dyn();
+        pad_cube(row, &[8], mode).unwrap().into_raw_vec_and_offset().0
+    }
+
+    #[test]
+    fn zero() {
+        assert_eq!(pad_row(PadMode::Zero), [1.0, 2.0, 3.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn nan() {
+        let padded = pad_row(PadMode::Nan);
+        assert_eq!(&padded[..3], &[1.0, 2.0, 3.0]);
+        assert!(padded[3..].iter().all(|value| value.is_nan()));
+    }
+
+    #[test]
+    fn edge() {
+        assert_eq!(pad_row(PadMode::Edge), [1.0, 2.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0]);
+    }
+
+    #[test]
+    fn reflect() {
+        assert_eq!(pad_row(PadMode::Reflect), [1.0, 2.0, 3.0, 2.0, 1.0, 2.0, 3.0, 2.0]);
+        // A single pixel has nothing to mirror, so it is repeated
+        let pixel = arr1(&[5.0f32]).into_dyn();
+        assert_eq!(pad_cube(pixel, &[3], PadMode::Reflect).unwrap().into_raw_vec_and_offset().0, [5.0, 5.0, 5.0]);
+    }
+
+    #[test]
+    fn wrap() {
+        assert_eq!(pad_row(PadMode::Wrap), [1.0, 2.0, 3.0, 1.0, 2.0, 3.0, 1.0, 2.0]);
+    }
+
+    #[test]
+    fn shape_is_in_fits_order() {
+        // FITS axis 1 (length 3) is padded to 4 and FITS axis 2 (length 2) to 3
+        let plane = arr2(&[[1.0f32, 2.0, 3.0], [4.0, 5.0, 6.0]]).into_dyn();
+        let padded = pad_cube(plane, &[4, 3], PadMode::Edge).unwrap();
+        assert_eq!(
+            padded,
+            arr2(&[[1.0f32, 2.0, 3.0, 3.0], [4.0, 5.0, 6.0, 6.0], [4.0, 5.0, 6.0, 6.0]]).into_dyn()
+        );
+    }
+
+    #[test]
+    fn shrinking_and_empty_axes_are_errors() {
+        let plane = ArrayD::<f32>::zeros(IxDyn(&[2, 3]));
+        assert!(pad_cube(plane, &[2, 2], PadMode::Zero).is_err());
+        let empty = ArrayD::<f32>::zeros(IxDyn(&[0]));
+        assert!(pad_cube(empty.clone(), &[2], PadMode::Edge).is_err());
+        assert_eq!(pad_cube(empty, &[2], PadMode::Zero).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn shapes_are_parsed() {
+        assert_eq!(parse_shape("512, 512,288", 3).unwrap(), [512, 512, 288]);
+        assert!(parse_shape("512,512", 3).is_err());
+        assert!(parse_shape("512,x,288", 3).is_err());
+    }
+}
@@ -0,0 +1,162 @@
+use crate::error::FitsRotateError;
+
+/// The share of a batch taken by one task of a job array
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Task {
+    /// The (0-based) index of this task
+    pub index: usize,
+    /// The number of tasks
+    pub count: usize,
+}
+
+/// Read a non-negative integer from an environment variable
+fn env_usize(name: &str) -> Result<Option<usize>, FitsRotateError> {
+    match std::env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| FitsRotateError::Message(format!("{}={} is not a non-negative integer", name, value))),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Work out the task of this process from the command line or Slurm
+///
+/// `--task-index` and `--task-count` take precedence. Otherwise, with
+/// `--slurm`, the task is taken from `SLURM_ARRAY_TASK_ID` and
+/// `SLURM_ARRAY_TASK_COUNT`. Array indices need not start at 0 (e.g.
+/// `--array=1-10`), so `SLURM_ARRAY_TASK_MIN` is subtracted from the ID.
+///
+/// # Arguments
+///
+/// * `task_index` - The `--task-index`
+/// * `task_count` - The `--task-count`
+/// * `slurm` - Read the task from the Slurm environment if it is not given
+///
+/// # Returns
+///
+/// * `Result<Option<Task>, FitsRotateError>` - The task, or `None` if the whole batch is this process's
+///
+pub fn resolve_task(
+    task_index: Option<usize>,
+    task_count: Option<usize>,
+    slurm: bool,
+) -> Result<Option<Task>, FitsRotateError> {
+    let (index, count) = match (task_index, task_count) {
+        (Some(index), Some(count)) => (index, count),
+        (None, None) if slurm => {
+            let id = env_usize("SLURM_ARRAY_TASK_ID")?;
+            let count = env_usize("SLURM_ARRAY_TASK_COUNT")?;
+            let min = env_usize("SLURM_ARRAY_TASK_MIN")?.unwrap_or(0);
+            match (id, count) {
+                (Some(id), Some(count)) if id >= min => (id - min, count),
+                (Some(id), Some(_)) => {
+                    return Err(FitsRotateError::Message(format!(
+                        "SLURM_ARRAY_TASK_ID ({}) is below SLURM_ARRAY_TASK_MIN ({})",
+                        id, min
+                    )))
+                }
+                _ => {
+                    return Err(FitsRotateError::Message(
+                        "--slurm expects SLURM_ARRAY_TASK_ID and SLURM_ARRAY_TASK_COUNT to be set (is this a job array?)"
+                            .to_string(),
+                    ))
+                }
+            }
+        }
+        (None, None) => return Ok(None),
+        _ => {
+            return Err(FitsRotateError::Message(
+                "--task-index and --task-count must be given together".to_string(),
+            ))
+        }
+    };
+    if count == 0 {
+        return Err(FitsRotateError::Message("The task count must be at least 1".to_string()));
+    }
+    if index >= count {
+        return Err(FitsRotateError::Message(format!(
+            "The task index ({}) must be less than the task count ({})",
+            index, count
+        )));
+    }
+    Ok(Some(Task { index, count }))
+}
+
+/// Take the share of a batch of files that belongs to a task
+///
+/// The files are sorted and duplicates removed, so every task sees the
+/// same list whatever order it was resolved in. Task `I` of `N` takes the
+/// files at the positions congruent to `I` modulo `N`, so the tasks share
+/// the batch between them with no file left out or taken twice.
+///
+/// # Arguments
+///
+/// * `files` - The files of the batch
+/// * `task` - The task
+///
+/// # Returns
+///
+/// * `Vec<String>` - The files of this task, sorted
+///
+pub fn task_files(files: &[String], task: Task) -> Vec<String> {
+    let mut files = files.to_vec();
+    files.sort();
+    files.dedup();
+    files
+        .into_iter()
+        .enumerate()
+        .filter(|(idx, _)| idx % task.count == task.index)
+        .map(|(_, file)| file)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tasks_share_a_batch_without_gaps_or_overlaps() {
+        for n_files in 0..20 {
+            // Unsorted, with a duplicate, as a glob or a shell might give them
+            let mut files: Vec<String> = (0..n_files).rev().map(|idx| format!("cube{:02}.fits", idx)).collect();
+            if n_files > 0 {
+                files.push(files[0].clone());
+            }
+            let expected: Vec<String> = (0..n_files).map(|idx| format!("cube{:02}.fits", idx)).collect();
+            for count in 1..8 {
+                let shares: Vec<Vec<String>> = (0..count)
+                    .map(|index| task_files(&files, Task { index, count }))
+                    .collect();
+                let mut taken: Vec<String> = shares.concat();
+                taken.sort();
+                assert_eq!(taken, expected, "{} files over {} tasks", n_files, count);
+                // The shares differ in size by at most one file
+                let sizes: Vec<usize> = shares.iter().map(|share| share.len()).collect();
+                assert!(
+                    sizes.iter().max().unwrap() - sizes.iter().min().unwrap() <= 1,
+                    "{:?}",
+                    sizes
+                );
+                for share in &shares {
+                    assert!(share.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", share);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn task_shares_do_not_depend_on_the_order_of_the_files() {
+        let files: Vec<String> = ["c.fits", "a.fits", "d.fits", "b.fits", "e.fits"]
+            .map(String::from)
+            .to_vec();
+        let mut reversed = files.clone();
+        reversed.reverse();
+        for index in 0..2 {
+            let task = Task { index, count: 2 };
+            assert_eq!(task_files(&files, task), task_files(&reversed, task));
+        }
+        assert_eq!(task_files(&files, Task { index: 1, count: 2 }), ["b.fits", "d.fits"]);
+    }
+}
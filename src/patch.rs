@@ -0,0 +1,730 @@
+//! Declarative header patches, applied to each output with `--header-patch`
+//!
+//! A patch is a list of operations, applied in order, as YAML:
+//!
+//! ```yaml
+//! - op: set
+//!   key: OBJECT
+//!   value: M31
+//!   comment: Target name
+//! - op: delete
+//!   key: OBSOLETE
+//! - op: rename
+//!   key: EPOCH
+//!   to: EQUINOX
+//! - op: history
+//!   text: Header fixed for project X
+//! ```
+//!
+//! or as the same JSON array of objects. Set values keep their type:
+//! `true`/`false` are logical, numbers with no decimal point or exponent
+//! are integers, other numbers are floats, and anything else (or anything
+//! quoted) is a string. Only this flat subset of YAML is read.
+//!
+//! The whole patch is checked when it is parsed, before any file is
+//! touched: structural keywords (`SIMPLE`, `BITPIX`, `NAXISn`, ...) and
+//! commentary keywords cannot be patched, and no two operations may change
+//! the same keyword in different ways.
+
+use crate::error::FitsRotateError;
+use crate::header::{delete_key, is_structural_keyword};
+use crate::provenance::write_history;
+use fitsio::errors::{check_status, Error};
+use fitsio::FitsFile;
+use std::ffi::CString;
+
+/// The longest string value that fits on a single card
+const MAX_STRING_LENGTH: usize = 68;
+
+/// A scalar value of a patch document
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Logical(bool),
+}
+
+/// The fields of one entry of a patch document, in order
+type Fields = Vec<(String, PatchValue)>;
+
+/// An operation of a header patch
+#[derive(Clone, Debug, PartialEq)]
+pub enum PatchOp {
+    /// Set a keyword, adding it if it is missing, and keeping its comment unless one is given
+    Set {
+        keyword: String,
+        value: PatchValue,
+        comment: Option<String>,
+    },
+    /// Delete a keyword, if it is present
+    Delete { keyword: String },
+    /// Rename a keyword, if it is present, keeping its value and comment
+    Rename { keyword: String, to: String },
+    /// Append a HISTORY card
+    History { text: String },
+}
+
+impl PatchOp {
+    /// The keywords the operation changes
+    fn keywords(&self) -> Vec<&str> {
+        match self {
+            PatchOp::Set { keyword, .. } | PatchOp::Delete { keyword } => vec![keyword],
+            PatchOp::Rename { keyword, to } => vec![keyword, to],
+            PatchOp::History { .. } => Vec::new(),
+        }
+    }
+}
+
+/// A validated list of header operations
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeaderPatch {
+    pub ops: Vec<PatchOp>,
+}
+
+impl HeaderPatch {
+    /// Read and check a patch file
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The patch, as YAML or JSON
+    ///
+    /// # Returns
+    ///
+    /// * `Result<HeaderPatch, FitsRotateError>` - The patch
+    ///
+    pub fn load(filename: &str) -> Result<HeaderPatch, FitsRotateError> {
+        let text = std::fs::read_to_string(filename)
+            .map_err(|e| FitsRotateError::Message(format!("Cannot read header patch {}: {}", filename, e)))?;
+        HeaderPatch::parse(&text)
+            .map_err(|e| FitsRotateError::Message(format!("Invalid header patch {}: {}", filename, e)))
+    }
+
+    /// Parse and check a patch
+    ///
+    /// A document starting with `[` is read as JSON, and anything else as YAML.
+    ///
+    /// # Arguments
+    ///
+    /// * `text` - The patch document
+    ///
+    /// # Returns
+    ///
+    /// * `Result<HeaderPatch, FitsRotateError>` - The patch
+    ///
+    pub fn parse(text: &str) -> Result<HeaderPatch, FitsRotateError> {
+        let entries = if text.trim_start().starts_with('[') {
+            parse_json(text)?
+        } else {
+            parse_yaml(text)?
+        };
+        let ops = entries
+            .into_iter()
+            .enumerate()
+            .map(|(idx, fields)| operation(idx + 1, fields))
+            .collect::<Result<Vec<PatchOp>, FitsRotateError>>()?;
+        // Repeating an operation is harmless, but two different ones on a keyword conflict
+        for (idx, op) in ops.iter().enumerate() {
+            for (earlier_idx, earlier) in ops[..idx].iter().enumerate() {
+                let shared = earlier
+                    .keywords()
+                    .into_iter()
+                    .find(|keyword| op.keywords().contains(keyword));
+                if let Some(keyword) = shared.filter(|_| earlier != op) {
+                    return Err(FitsRotateError::Message(format!(
+                        "operations {} and {} both change {}",
+                        earlier_idx + 1,
+                        idx + 1,
+                        keyword
+                    )));
+                }
+            }
+        }
+        Ok(HeaderPatch { ops })
+    }
+
+    /// Apply the patch to the primary header of a FITS file
+    ///
+    /// # Arguments
+    ///
+    /// * `fits_file` - The FITS file, opened for editing
+    ///
+    /// # Returns
+    ///
+    /// * `Result<usize, FitsRotateError>` - The number of operations that changed the header
+    ///
+    pub fn apply(&self, fits_file: &mut FitsFile) -> Result<usize, FitsRotateError> {
+        let mut n_applied = 0;
+        for op in &self.ops {
+            let hdu = fits_file.primary_hdu()?;
+            let present = |fits_file: &mut FitsFile, keyword: &str| hdu.read_key::<String>(fits_file, keyword).is_ok();
+            match op {
+                PatchOp::Set {
+                    keyword,
+                    value,
+                    comment,
+                } => {
+                    // An empty comment is written for a new keyword, and "&" keeps the comment of an old one
+                    let comment = match comment {
+                        Some(comment) => comment.as_str(),
+                        None if present(fits_file, keyword) => "&",
+                        None => "",
+                    };
+                    update_key(fits_file, keyword, value, comment)?;
+                }
+                PatchOp::Delete { keyword } => {
+                    if !present(fits_file, keyword) {
+                        continue;
+                    }
+                    delete_key(fits_file, keyword)?;
+                }
+                PatchOp::Rename { keyword, to } => {
+                    if !present(fits_file, keyword) {
+                        continue;
+                    }
+                    if present(fits_file, to) {
+                        return Err(FitsRotateError::Message(format!(
+                            "Cannot rename {} to {}: the header already has {}",
+                            keyword, to, to
+                        )));
+                    }
+                    rename_key(fits_file, keyword, to)?;
+                }
+                PatchOp::History { text } => write_history(fits_file, text)?,
+            }
+            n_applied += 1;
+        }
+        Ok(n_applied)
+    }
+}
+
+/// Set a keyword of the current HDU to a typed value, adding it if it is missing
+///
+/// # Arguments
+///
+/// * `fits_file` - The FITS file
+/// * `keyword` - The keyword
+/// * `value` - The value
+/// * `comment` - The comment, or `&` to keep the comment of an existing card
+///
+fn update_key(fits_file: &mut FitsFile, keyword: &str, value: &PatchValue, comment: &str) -> Result<(), Error> {
+    let c_keyword = CString::new(keyword)?;
+    let c_comment = CString::new(comment)?;
+    let mut status = 0;
+    unsafe {
+        let (fptr, key, comm) = (fits_file.as_raw(), c_keyword.as_ptr(), c_comment.as_ptr());
+        match value {
+            PatchValue::String(value) => {
+                let c_value = CString::new(value.as_str())?;
+                fitsio::sys::ffukys(fptr, key, c_value.as_ptr(), comm, &mut status);
+            }
+            PatchValue::Integer(value) => {
+                fitsio::sys::ffukyj(fptr, key, *value, comm, &mut status);
+            }
+            // A negative number of decimals asks for that many significant digits
+            PatchValue::Float(value) => {
+                fitsio::sys::ffukyd(fptr, key, *value, -15, comm, &mut status);
+            }
+            PatchValue::Logical(value) => {
+                fitsio::sys::ffukyl(fptr, key, *value as i32, comm, &mut status);
+            }
+        }
+    }
+    check_status(status)
+}
+
+/// Rename a keyword of the current HDU, keeping its value and comment
+///
+/// # Arguments
+///
+/// * `fits_file` - The FITS file
+/// * `keyword` - The keyword to rename
+/// * `to` - The new keyword
+///
+fn rename_key(fits_file: &mut FitsFile, keyword: &str, to: &str) -> Result<(), Error> {
+    let c_keyword = CString::new(keyword)?;
+    let c_to = CString::new(to)?;
+    let mut status = 0;
+    unsafe {
+        fitsio::sys::ffmnam(fits_file.as_raw(), c_keyword.as_ptr(), c_to.as_ptr(), &mut status);
+    }
+    check_status(status)
+}
+
+/// Check a keyword to be patched, returning it in upper case
+fn check_keyword(idx: usize, keyword: &str) -> Result<String, FitsRotateError> {
+    let keyword = keyword.trim().to_uppercase();
+    if keyword.is_empty()
+        || keyword.len() > 8
+        || !keyword
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '-' || c == '_')
+    {
+        return Err(FitsRotateError::Message(format!(
+            "operation {}: {:?} is not a FITS keyword (up to 8 of A-Z, 0-9, - and _)",
+            idx, keyword
+        )));
+    }
+    if is_structural_keyword(&keyword) {
+        return Err(FitsRotateError::Message(format!(
+            "operation {}: {} describes the data layout and cannot be patched",
+            idx, keyword
+        )));
+    }
+    if ["HISTORY", "COMMENT"].contains(&keyword.as_str()) {
+        return Err(FitsRotateError::Message(format!(
+            "operation {}: {} cards cannot be set, deleted or renamed (use op: history to add one)",
+            idx, keyword
+        )));
+    }
+    Ok(keyword)
+}
+
+/// Build an operation from the fields of an entry of a patch document
+fn operation(idx: usize, fields: Fields) -> Result<PatchOp, FitsRotateError> {
+    let field = |name: &str| fields.iter().find(|(key, _)| key == name).map(|(_, value)| value);
+    let string = |name: &str| match field(name) {
+        Some(PatchValue::String(value)) => Ok(value.clone()),
+        Some(_) => Err(FitsRotateError::Message(format!(
+            "operation {}: {} must be a string",
+            idx, name
+        ))),
+        None => Err(FitsRotateError::Message(format!(
+            "operation {}: {} is missing",
+            idx, name
+        ))),
+    };
+    let op = string("op")?;
+    let allowed: &[&str] = match op.as_str() {
+        "set" => &["op", "key", "value", "comment"],
+        "delete" => &["op", "key"],
+        "rename" => &["op", "key", "to"],
+        "history" => &["op", "text"],
+        _ => {
+            return Err(FitsRotateError::Message(format!(
+                "operation {}: unknown op {:?} (expected set, delete, rename or history)",
+                idx, op
+            )))
+        }
+    };
+    if let Some((key, _)) = fields.iter().find(|(key, _)| !allowed.contains(&key.as_str())) {
+        return Err(FitsRotateError::Message(format!(
+            "operation {}: unknown field {:?} for op {}",
+            idx, key, op
+        )));
+    }
+    match op.as_str() {
+        "set" => {
+            let value = field("value")
+                .cloned()
+                .ok_or_else(|| FitsRotateError::Message(format!("operation {}: value is missing", idx)))?;
+            if matches!(&value, PatchValue::String(value) if value.len() > MAX_STRING_LENGTH) {
+                return Err(FitsRotateError::Message(format!(
+                    "operation {}: string values are limited to {} characters",
+                    idx, MAX_STRING_LENGTH
+                )));
+            }
+            if matches!(value, PatchValue::Float(value) if !value.is_finite()) {
+                return Err(FitsRotateError::Message(format!(
+                    "operation {}: FITS cannot store a non-finite value",
+                    idx
+                )));
+            }
+            let comment = match field("comment") {
+                Some(_) => Some(string("comment")?),
+                None => None,
+            };
+            Ok(PatchOp::Set {
+                keyword: check_keyword(idx, &string("key")?)?,
+                value,
+                comment,
+            })
+        }
+        "delete" => Ok(PatchOp::Delete {
+            keyword: check_keyword(idx, &string("key")?)?,
+        }),
+        "rename" => Ok(PatchOp::Rename {
+            keyword: check_keyword(idx, &string("key")?)?,
+            to: check_keyword(idx, &string("to")?)?,
+        }),
+        _ => Ok(PatchOp::History { text: string("text")? }),
+    }
+}
+
+/// Parse an unquoted scalar, by the YAML core schema
+fn plain_scalar(text: &str) -> PatchValue {
+    match text {
+        "true" | "True" | "TRUE" => return PatchValue::Logical(true),
+        "false" | "False" | "FALSE" => return PatchValue::Logical(false),
+        _ => {}
+    }
+    // Words such as "inf" or "nan" parse as floats, but are strings here
+    let numeric = text.starts_with(|c: char| c.is_ascii_digit() || c == '-' || c == '+' || c == '.');
+    if numeric {
+        if let Ok(value) = text.parse::<i64>() {
+            return PatchValue::Integer(value);
+        }
+        if let Ok(value) = text.parse::<f64>() {
+            return PatchValue::Float(value);
+        }
+    }
+    PatchValue::String(text.to_string())
+}
+
+/// Remove a YAML comment (`#` at the start of a line or after a space) outside quotes
+fn strip_yaml_comment(line: &str) -> &str {
+    let mut quote: Option<char> = None;
+    let mut previous = ' ';
+    for (idx, c) in line.char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None if c == '#' && previous.is_whitespace() => return &line[..idx],
+            None => {}
+        }
+        previous = c;
+    }
+    line
+}
+
+/// Parse a YAML scalar, quoted or not
+fn yaml_scalar(line_number: usize, text: &str) -> Result<PatchValue, FitsRotateError> {
+    let invalid = || FitsRotateError::Message(format!("line {}: unterminated string {}", line_number, text));
+    if let Some(rest) = text.strip_prefix('\'') {
+        let inner = rest.strip_suffix('\'').ok_or_else(invalid)?;
+        return Ok(PatchValue::String(inner.replace("''", "'")));
+    }
+    if let Some(rest) = text.strip_prefix('"') {
+        let inner = rest.strip_suffix('"').ok_or_else(invalid)?;
+        let mut value = String::new();
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            value.push(match (c, c == '\\') {
+                (_, true) => match chars.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some(other) => other,
+                    None => return Err(invalid()),
+                },
+                (c, false) => c,
+            });
+        }
+        return Ok(PatchValue::String(value));
+    }
+    if text.is_empty() || text == "~" || text == "null" {
+        return Err(FitsRotateError::Message(format!("line {}: missing value", line_number)));
+    }
+    Ok(plain_scalar(text))
+}
+
+/// Parse a YAML patch: a block sequence of flat mappings
+fn parse_yaml(text: &str) -> Result<Vec<Fields>, FitsRotateError> {
+    let mut entries: Vec<Fields> = Vec::new();
+    for (idx, line) in text.lines().enumerate() {
+        let line_number = idx + 1;
+        let line = strip_yaml_comment(line).trim_end();
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed == "---" {
+            continue;
+        }
+        let field = if trimmed == "-" || trimmed.starts_with("- ") {
+            entries.push(Vec::new());
+            trimmed[1..].trim_start()
+        } else if entries.is_empty() || trimmed.len() == line.len() {
+            return Err(FitsRotateError::Message(format!(
+                "line {}: expected an operation starting with '- '",
+                line_number
+            )));
+        } else {
+            trimmed
+        };
+        if field.is_empty() {
+            continue;
+        }
+        let (key, value) = field
+            .split_once(':')
+            .ok_or_else(|| FitsRotateError::Message(format!("line {}: expected 'field: value'", line_number)))?;
+        let value = yaml_scalar(line_number, value.trim())?;
+        if let Some(entry) = entries.last_mut() {
+            entry.push((key.trim().to_string(), value));
+        }
+    }
+    Ok(entries)
+}
+
+/// A reader of the JSON subset used by patches: an array of flat objects
+struct JsonReader<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl JsonReader<'_> {
+    /// An error at the current position
+    fn error(&self, message: &str) -> FitsRotateError {
+        FitsRotateError::Message(format!("{} at byte {}", message, self.pos))
+    }
+
+    /// The next character after any whitespace, without consuming it
+    fn peek(&mut self) -> Option<char> {
+        let rest = &self.text[self.pos..];
+        self.pos += rest.len() - rest.trim_start().len();
+        self.text[self.pos..].chars().next()
+    }
+
+    /// Consume an expected character
+    fn expect(&mut self, c: char) -> Result<(), FitsRotateError> {
+        if self.peek() != Some(c) {
+            return Err(self.error(&format!("expected '{}'", c)));
+        }
+        self.pos += c.len_utf8();
+        Ok(())
+    }
+
+    /// Read a string
+    fn string(&mut self) -> Result<String, FitsRotateError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        let mut chars = self.text[self.pos..].char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.pos += offset + 1;
+                    return Ok(value);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('r') => value.push('\r'),
+                    Some('b') => value.push('\u{8}'),
+                    Some('f') => value.push('\u{c}'),
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| chars.next().map(|(_, c)| c)).collect();
+                        let c = u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32);
+                        value.push(c.ok_or_else(|| self.error("invalid \\u escape"))?);
+                    }
+                    Some(other) => value.push(other),
+                    None => break,
+                },
+                c => value.push(c),
+            }
+        }
+        Err(self.error("unterminated string"))
+    }
+
+    /// Read a scalar value
+    fn scalar(&mut self) -> Result<PatchValue, FitsRotateError> {
+        match self.peek() {
+            Some('"') => Ok(PatchValue::String(self.string()?)),
+            Some('{') | Some('[') => Err(self.error("nested values are not supported")),
+            _ => {
+                let rest = &self.text[self.pos..];
+                let end = rest.find([',', '}', ']', ' ', '\t', '\r', '\n']).unwrap_or(rest.len());
+                let token = &rest[..end];
+                let value = match token {
+                    "true" => PatchValue::Logical(true),
+                    "false" => PatchValue::Logical(false),
+                    _ => match plain_scalar(token) {
+                        PatchValue::String(_) => return Err(self.error(&format!("invalid value {:?}", token))),
+                        value => value,
+                    },
+                };
+                self.pos += end;
+                Ok(value)
+            }
+        }
+    }
+
+    /// Read an object of scalars
+    fn object(&mut self) -> Result<Fields, FitsRotateError> {
+        self.expect('{')?;
+        let mut fields = Fields::new();
+        if self.peek() == Some('}') {
+            self.pos += 1;
+            return Ok(fields);
+        }
+        loop {
+            let key = self.string()?;
+            self.expect(':')?;
+            fields.push((key, self.scalar()?));
+            match self.peek() {
+                Some(',') => self.pos += 1,
+                Some('}') => {
+                    self.pos += 1;
+                    return Ok(fields);
+                }
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+}
+
+/// Parse a JSON patch: an array of flat objects
+fn parse_json(text: &str) -> Result<Vec<Fields>, FitsRotateError> {
+    let mut reader = JsonReader { text, pos: 0 };
+    let mut entries: Vec<Fields> = Vec::new();
+    reader.expect('[')?;
+    if reader.peek() == Some(']') {
+        reader.pos += 1;
+    } else {
+        loop {
+            entries.push(reader.object()?);
+            match reader.peek() {
+                Some(',') => reader.pos += 1,
+                Some(']') => {
+                    reader.pos += 1;
+                    break;
+                }
+                _ => return Err(reader.error("expected ',' or ']'")),
+            }
+        }
+    }
+    if reader.peek().is_some() {
+        return Err(reader.error("unexpected text after the array"));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The error message of a patch that is refused
+    fn refusal(text: &str) -> String {
+        HeaderPatch::parse(text).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn yaml_and_json_patches_parse_to_the_same_typed_operations() {
+        let yaml = "\
+- op: set
+  key: object      # keywords are upper-cased
+  value: M31
+  comment: 'Target''s name'
+- op: set
+  key: NCHAN
+  value: -42
+- op: set
+  key: RESTFRQ
+  value: 1.4e9
+- op: set
+  key: CALIB
+  value: true
+- op: set
+  key: VERSION
+  value: \"3\"
+- op: delete
+  key: OBSOLETE
+- op: rename
+  key: EPOCH
+  to: EQUINOX
+- op: history
+  text: 'Fixed # not a comment'
+";
+        let json = r#"[
+            {"op": "set", "key": "object", "value": "M31", "comment": "Target's name"},
+            {"op": "set", "key": "NCHAN", "value": -42},
+            {"op": "set", "key": "RESTFRQ", "value": 1.4e9},
+            {"op": "set", "key": "CALIB", "value": true},
+            {"op": "set", "key": "VERSION", "value": "3"},
+            {"op": "delete", "key": "OBSOLETE"},
+            {"op": "rename", "key": "EPOCH", "to": "EQUINOX"},
+            {"op": "history", "text": "Fixed # not a comment"}
+        ]"#;
+        let set = |keyword: &str, value: PatchValue, comment: Option<&str>| PatchOp::Set {
+            keyword: keyword.to_string(),
+            value,
+            comment: comment.map(str::to_string),
+        };
+        let expected = vec![
+            set("OBJECT", PatchValue::String("M31".into()), Some("Target's name")),
+            set("NCHAN", PatchValue::Integer(-42), None),
+            set("RESTFRQ", PatchValue::Float(1.4e9), None),
+            set("CALIB", PatchValue::Logical(true), None),
+            set("VERSION", PatchValue::String("3".into()), None),
+            PatchOp::Delete {
+                keyword: "OBSOLETE".into(),
+            },
+            PatchOp::Rename {
+                keyword: "EPOCH".into(),
+                to: "EQUINOX".into(),
+            },
+            PatchOp::History {
+                text: "Fixed # not a comment".into(),
+            },
+        ];
+        assert_eq!(HeaderPatch::parse(yaml).unwrap().ops, expected);
+        assert_eq!(HeaderPatch::parse(json).unwrap().ops, expected);
+        assert_eq!(HeaderPatch::parse("[]").unwrap().ops, []);
+    }
+
+    #[test]
+    fn plain_scalars_are_typed_by_the_yaml_core_schema() {
+        assert_eq!(plain_scalar("12"), PatchValue::Integer(12));
+        assert_eq!(plain_scalar("+12"), PatchValue::Integer(12));
+        assert_eq!(plain_scalar("12.0"), PatchValue::Float(12.0));
+        assert_eq!(plain_scalar("-.5e-3"), PatchValue::Float(-0.5e-3));
+        assert_eq!(plain_scalar("False"), PatchValue::Logical(false));
+        for text in ["inf", "nan", "12 h", "J2000", "yes"] {
+            assert_eq!(plain_scalar(text), PatchValue::String(text.into()), "{}", text);
+        }
+    }
+
+    #[test]
+    fn patches_that_would_break_the_header_are_refused() {
+        for (text, message) in [
+            ("- op: set\n  key: NAXIS2\n  value: 3\n", "describes the data layout"),
+            ("- op: delete\n  key: BITPIX\n", "describes the data layout"),
+            (
+                "- op: rename\n  key: OBJECT\n  to: SIMPLE\n",
+                "describes the data layout",
+            ),
+            ("- op: set\n  key: COMMENT\n  value: x\n", "cards cannot be set"),
+            ("- op: set\n  key: TOOLONGKEY\n  value: 1\n", "is not a FITS keyword"),
+            ("- op: set\n  key: BAD KEY\n  value: 1\n", "is not a FITS keyword"),
+            ("- op: set\n  key: OBJECT\n", "value is missing"),
+            (
+                "- op: delete\n  key: OBJECT\n  value: 1\n",
+                "unknown field \"value\" for op delete",
+            ),
+            ("- op: move\n  key: OBJECT\n", "unknown op \"move\""),
+            ("- op: set\n  key: RESTFRQ\n  value: 1e999\n", "non-finite"),
+            ("key: OBJECT\n", "expected an operation starting with '- '"),
+            ("- op: history\n  text: 'unterminated\n", "unterminated string"),
+            (
+                "[{\"op\": \"delete\", \"key\": {\"nested\": 1}}]",
+                "nested values are not supported",
+            ),
+            (
+                "[{\"op\": \"delete\", \"key\": \"A\"}] extra",
+                "unexpected text after the array",
+            ),
+        ] {
+            let error = refusal(text);
+            assert!(error.contains(message), "{:?}: {}", text, error);
+        }
+        let long = format!(
+            "- op: set\n  key: OBJECT\n  value: {}\n",
+            "x".repeat(MAX_STRING_LENGTH + 1)
+        );
+        assert!(refusal(&long).contains("limited to 68 characters"));
+    }
+
+    #[test]
+    fn only_different_operations_on_a_keyword_conflict() {
+        let twice = "- op: delete\n  key: OBSOLETE\n- op: delete\n  key: OBSOLETE\n";
+        assert_eq!(HeaderPatch::parse(twice).unwrap().ops.len(), 2);
+        let conflicts = [
+            "- op: set\n  key: OBJECT\n  value: a\n- op: set\n  key: OBJECT\n  value: b\n",
+            "- op: set\n  key: OBJECT\n  value: a\n- op: delete\n  key: OBJECT\n",
+            // The target of a rename counts as changed too
+            "- op: rename\n  key: EPOCH\n  to: EQUINOX\n- op: set\n  key: EQUINOX\n  value: 2000.0\n",
+        ];
+        for text in conflicts {
+            assert!(refusal(text).contains("operations 1 and 2 both change"), "{}", text);
+        }
+        // HISTORY cards are only ever appended, so any number of them can be added
+        let history = "- op: history\n  text: a\n- op: history\n  text: b\n";
+        assert_eq!(HeaderPatch::parse(history).unwrap().ops.len(), 2);
+    }
+}
@@ -0,0 +1,230 @@
+//! Pure axis permutation logic
+//!
+//! Nothing in this module allocates or does any I/O: it only uses `core`
+//! and works on slices, so it can be lifted into `no_std` or WASM code as
+//! it is.
+//!
+//! Modes follow Miriad's `reorder` convention: entry n (0-based) of a mode
+//! is the (FITS, 1-based) input axis that becomes output axis n + 1.
+
+use core::fmt;
+
+/// Convert a FITS index to an array index
+///
+/// FITS indices are 1-based, while array indices are 0-based.
+/// FITS indices are in the order x, y, z, ..., while array indices are in the order z, y, x, ...
+///
+/// # Arguments
+///
+/// * `fits_index` - The FITS index
+/// * `naxis` - The number of axes in the FITS cube
+///
+/// # Returns
+///
+/// * `usize` - The array index
+///
+/// # Examples
+///
+/// ```
+/// use fitsrotate_rs::fits_index_to_array_index;
+/// let fits_index = 3;
+/// let naxis = 3;
+/// let array_index = fits_index_to_array_index(fits_index, naxis);
+/// assert_eq!(array_index, 0);
+/// ```
+///
+/// ```
+/// use fitsrotate_rs::fits_index_to_array_index;
+/// let fits_index = 1;
+/// let naxis = 3;
+/// let array_index = fits_index_to_array_index(fits_index, naxis);
+/// assert_eq!(array_index, 2);
+/// ```
+///
+/// ```
+/// use fitsrotate_rs::fits_index_to_array_index;
+/// let fits_index = 2;
+/// let naxis = 3;
+/// let array_index = fits_index_to_array_index(fits_index, naxis);
+/// assert_eq!(array_index, 1);
+/// ```
+pub fn fits_index_to_array_index(fits_index: usize, naxis: usize) -> usize {
+    naxis - fits_index
+}
+
+/// Check that a mode names each axis from 1 to its length exactly once
+pub fn is_permutation(mode: &[usize]) -> bool {
+    (1..mode.len() + 1).all(|axis| mode.iter().filter(|m| **m == axis).count() == 1)
+}
+
+/// The (FITS, 1-based) output axis that an input axis ends up on
+///
+/// This is the inverse of the mode, which lists the input axis of each
+/// output axis.
+///
+/// # Arguments
+///
+/// * `input_axis` - The (FITS, 1-based) input axis
+/// * `mode` - The new ordering of the axes
+///
+/// # Returns
+///
+/// * `usize` - The output axis
+///
+pub fn output_axis(input_axis: usize, mode: &[usize]) -> usize {
+    mode.iter().position(|axis| *axis == input_axis).unwrap() + 1
+}
+
+/// An entry of the matrix that takes input pixel coordinates to output pixel coordinates
+///
+/// The matrix of a mode is a permutation matrix, like a `PCi_j` matrix: row
+/// `i` has a 1 in column `mode[i - 1]`, or a -1 if that input axis is
+/// reversed.
+///
+/// # Arguments
+///
+/// * `mode` - The new ordering of the axes
+/// * `flips` - The (FITS, 1-based) input axes that are reversed
+/// * `row` - The (FITS, 1-based) output axis
+/// * `column` - The (FITS, 1-based) input axis
+///
+/// # Returns
+///
+/// * `i8` - 1, -1 or 0
+///
+pub fn rotation_matrix_entry(mode: &[usize], flips: &[usize], row: usize, column: usize) -> i8 {
+    match mode.get(row - 1) {
+        Some(axis) if *axis == column && flips.contains(&column) => -1,
+        Some(axis) if *axis == column => 1,
+        _ => 0,
+    }
+}
+
+/// Work out the single mode equivalent to applying one mode after another
+///
+/// Output axis n of `second` is axis `second[n]` of the result of `first`,
+/// which is input axis `first[second[n] - 1]`.
+///
+/// # Arguments
+///
+/// * `first` - The mode applied first
+/// * `second` - The mode applied second
+/// * `composed` - Filled with the composed mode (same length as the modes)
+///
+pub fn compose_modes(first: &[usize], second: &[usize], composed: &mut [usize]) {
+    for (out, axis) in composed.iter_mut().zip(second) {
+        *out = first[axis - 1];
+    }
+}
+
+/// A validated permutation of FITS axes, borrowing its mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AxisPermutation<'a> {
+    mode: &'a [usize],
+}
+
+impl<'a> AxisPermutation<'a> {
+    /// Wrap a mode, returning `None` if it is not a permutation
+    pub fn new(mode: &'a [usize]) -> Option<Self> {
+        if is_permutation(mode) {
+            Some(AxisPermutation { mode })
+        } else {
+            None
+        }
+    }
+
+    /// The number of axes
+    pub fn naxis(&self) -> usize {
+        self.mode.len()
+    }
+
+    /// The (FITS, 1-based) input axis that becomes a given output axis
+    pub fn input_axis(&self, output_axis: usize) -> usize {
+        self.mode[output_axis - 1]
+    }
+
+    /// The (FITS, 1-based) output axis that a given input axis becomes
+    pub fn output_axis(&self, input_axis: usize) -> usize {
+        output_axis(input_axis, self.mode)
+    }
+}
+
+impl fmt::Display for AxisPermutation<'_> {
+    /// Format as a mode string: digits, or comma-separated for more than 9 axes
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (idx, axis) in self.mode.iter().enumerate() {
+            if idx > 0 && self.mode.len() > 9 {
+                write!(f, ",")?;
+            }
+            write!(f, "{}", axis)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fits_and_array_indices_run_in_opposite_orders() {
+        assert_eq!(fits_index_to_array_index(1, 3), 2);
+        assert_eq!(fits_index_to_array_index(3, 3), 0);
+    }
+
+    #[test]
+    fn only_permutations_are_accepted() {
+        assert!(AxisPermutation::new(&[3, 1, 2]).is_some());
+        assert!(AxisPermutation::new(&[]).is_some());
+        assert!(AxisPermutation::new(&[1, 1, 2]).is_none());
+        assert!(AxisPermutation::new(&[1, 2, 4]).is_none());
+        assert!(AxisPermutation::new(&[0, 1]).is_none());
+    }
+
+    #[test]
+    fn axes_map_both_ways() {
+        let permutation = AxisPermutation::new(&[3, 1, 2]).unwrap();
+        assert_eq!(permutation.naxis(), 3);
+        assert_eq!(permutation.input_axis(1), 3);
+        assert_eq!(permutation.output_axis(3), 1);
+        assert_eq!(permutation.output_axis(1), 2);
+    }
+
+    #[test]
+    fn the_inverse_undoes_the_permutation() {
+        let mode = [3, 1, 4, 2];
+        let permutation = AxisPermutation::new(&mode).unwrap();
+        // The inverse takes each input axis back from the output axis it became
+        let mut inverse = [0; 4];
+        for (idx, axis) in inverse.iter_mut().enumerate() {
+            *axis = permutation.output_axis(idx + 1);
+        }
+        assert_eq!(inverse, [2, 4, 1, 3]);
+        let mut composed = [0; 4];
+        compose_modes(&mode, &inverse, &mut composed);
+        assert_eq!(composed, [1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn composing_applies_the_first_mode_first() {
+        // 231 then 213: output axis 1 is axis 2 of the first result, which is input axis 3
+        let mut composed = [0; 3];
+        compose_modes(&[2, 3, 1], &[2, 1, 3], &mut composed);
+        assert_eq!(composed, [3, 2, 1]);
+    }
+
+    #[test]
+    fn rotation_matrix_entries_carry_flips() {
+        let (mode, flips) = ([2, 1], [1]);
+        assert_eq!(rotation_matrix_entry(&mode, &flips, 1, 2), 1);
+        assert_eq!(rotation_matrix_entry(&mode, &flips, 2, 1), -1);
+        assert_eq!(rotation_matrix_entry(&mode, &flips, 1, 1), 0);
+    }
+
+    #[test]
+    fn display_matches_the_mode_syntax() {
+        let long: [usize; 10] = [10, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        assert_eq!(AxisPermutation::new(&[3, 1, 2]).unwrap().to_string(), "312");
+        assert_eq!(AxisPermutation::new(&long).unwrap().to_string(), "10,1,2,3,4,5,6,7,8,9");
+    }
+}
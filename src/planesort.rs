@@ -0,0 +1,358 @@
+use crate::axes::{find_axis, read_ctypes};
+use crate::error::FitsRotateError;
+use crate::header::{delete_key, replace_key};
+use crate::permutation::fits_index_to_array_index;
+use crate::regrid::axis_quantity;
+use fitsio::errors::Error;
+use fitsio::tables::{ColumnDataType, ColumnDescription};
+use fitsio::FitsFile;
+use ndarray::{ArrayD, Axis};
+
+/// The extension holding the coordinate array of a `-TAB` axis
+pub const TAB_EXTNAME: &str = "WCS-TAB";
+
+/// The extension recording the input plane of each sorted plane
+pub const SORT_EXTNAME: &str = "SORTPERM";
+
+/// Sorted keys are described by a linear WCS if every step is within this fraction of the mean step
+const UNIFORM_TOLERANCE: f64 = 1e-6;
+
+/// A sort of the planes along one axis, chosen with `--sort-axis` and `--sort-key`
+#[derive(Clone, Debug, PartialEq)]
+pub struct PlaneSort {
+    /// The (FITS, 1-based) axis of the input
+    pub axis: usize,
+    /// The CTYPE of the axis
+    pub ctype: String,
+    /// Where the keys were read from
+    pub source: String,
+    /// The (0-based) input plane of each sorted plane
+    pub planes: Vec<usize>,
+    /// The key of each sorted plane, in ascending order
+    pub keys: Vec<f64>,
+}
+
+impl PlaneSort {
+    /// Work out the sort of the planes along an axis of a FITS file
+    ///
+    /// The keys are either a text file with one value per plane (blank
+    /// lines and `#` comments are skipped), or a column of a table given as
+    /// `FILE[HDU]:COLUMN`, e.g. `freqs.fits[CHANNELS]:FREQ`. Values are in
+    /// the units of the axis, and the text file may give them with a
+    /// spectral unit instead (e.g. `1.4GHz`). Planes with equal keys keep
+    /// their order.
+    ///
+    /// # Arguments
+    ///
+    /// * `fits_file` - The FITS file
+    /// * `filename` - The name of the FITS file, for error messages
+    /// * `axis_name` - The axis, named by CTYPE (e.g. `freq`) or (FITS, 1-based) number
+    /// * `key_spec` - Where to read the keys from
+    ///
+    /// # Returns
+    ///
+    /// * `Result<PlaneSort, FitsRotateError>` - The sort
+    ///
+    pub fn find(
+        fits_file: &mut FitsFile,
+        filename: &str,
+        axis_name: &str,
+        key_spec: &str,
+    ) -> Result<PlaneSort, FitsRotateError> {
+        let ctypes = read_ctypes(fits_file).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+        let naxis = ctypes.len();
+        let axis = match axis_name.trim().parse::<usize>() {
+            Ok(axis) if (1..naxis + 1).contains(&axis) => Some(axis),
+            Ok(_) => None,
+            Err(_) => find_axis(axis_name, &ctypes).map(|idx| idx + 1),
+        }
+        .ok_or_else(|| {
+            FitsRotateError::Message(format!(
+                "No axis of {} matches --sort-axis {} (CTYPEs: {})",
+                filename,
+                axis_name,
+                ctypes.join(", ")
+            ))
+        })?;
+
+        // The new WCS of the axis is written with CDELT and CRPIX alone
+        let hdu = fits_file.primary_hdu()?;
+        let has_cd = (1..naxis + 1).any(|other| {
+            hdu.read_key::<f64>(fits_file, &format!("CD{}_{}", axis, other)).is_ok()
+                || hdu.read_key::<f64>(fits_file, &format!("CD{}_{}", other, axis)).is_ok()
+        });
+        let pc = hdu
+            .read_key::<f64>(fits_file, &format!("PC{}_{}", axis, axis))
+            .unwrap_or(1.0);
+        if has_cd || pc != 1.0 {
+            return Err(FitsRotateError::Message(format!(
+                "--sort-axis needs axis {} of {} to be described by CDELT{} alone, without a CD or PC scale",
+                axis, filename, axis
+            )));
+        }
+        let length = hdu.read_key::<i64>(fits_file, &format!("NAXIS{}", axis))? as usize;
+        let cunit: String = hdu.read_key(fits_file, &format!("CUNIT{}", axis)).unwrap_or_default();
+
+        let keys = read_keys(key_spec, &cunit, axis)?;
+        if keys.len() != length {
+            return Err(FitsRotateError::Message(format!(
+                "{} has {} keys, but axis {} of {} has {} planes",
+                key_spec,
+                keys.len(),
+                axis,
+                filename,
+                length
+            )));
+        }
+        if let Some(idx) = keys.iter().position(|key| !key.is_finite()) {
+            return Err(FitsRotateError::Message(format!(
+                "Key {} of {} is not a finite number",
+                idx + 1,
+                key_spec
+            )));
+        }
+        let mut planes: Vec<usize> = (0..length).collect();
+        planes.sort_by(|a, b| keys[*a].total_cmp(&keys[*b]));
+        Ok(PlaneSort {
+            axis,
+            ctype: ctypes[axis - 1].clone(),
+            source: key_spec.to_string(),
+            keys: planes.iter().map(|plane| keys[*plane]).collect(),
+            planes,
+        })
+    }
+
+    /// The start and step of the sorted keys, if they are evenly spaced
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(f64, f64)>` - The first key and the mean step, or `None` if the keys need a `-TAB` lookup
+    ///
+    pub fn linear(&self) -> Option<(f64, f64)> {
+        let start = *self.keys.first()?;
+        if self.keys.len() < 2 {
+            return None;
+        }
+        let step = (self.keys[self.keys.len() - 1] - start) / (self.keys.len() - 1) as f64;
+        let uniform = step > 0.0
+            && self
+                .keys
+                .windows(2)
+                .all(|pair| ((pair[1] - pair[0]) - step).abs() <= UNIFORM_TOLERANCE * step);
+        uniform.then_some((start, step))
+    }
+
+    /// Check if the planes are already in order, so that sorting them changes nothing
+    pub fn is_identity(&self) -> bool {
+        self.planes.iter().enumerate().all(|(idx, plane)| idx == *plane)
+    }
+
+    /// Put the planes of a cube in the sorted order
+    ///
+    /// # Arguments
+    ///
+    /// * `cube` - The cube, with all the axes of the input
+    ///
+    /// # Returns
+    ///
+    /// * `ArrayD<T>` - The cube with its planes sorted
+    ///
+    pub fn sort<T: Clone>(&self, cube: ArrayD<T>) -> ArrayD<T> {
+        if self.is_identity() {
+            return cube;
+        }
+        let array_idx = fits_index_to_array_index(self.axis, cube.ndim());
+        cube.select(Axis(array_idx), &self.planes)
+    }
+
+    /// The new WCS of the axis, for HISTORY cards and messages
+    pub fn describe(&self) -> String {
+        match self.linear() {
+            Some((start, step)) => format!("a linear axis from {} in steps of {}", start, step),
+            None => format!("a -TAB lookup of {} values", self.keys.len()),
+        }
+    }
+
+    /// The CTYPE of the sorted axis, which uses the `-TAB` algorithm unless the keys are evenly spaced
+    pub fn output_ctype(&self) -> String {
+        if self.linear().is_some() {
+            return self.ctype.clone();
+        }
+        let name = self.ctype.split('-').next().unwrap_or("");
+        format!("{:-<4}-TAB", &name[..name.len().min(4)])
+    }
+
+    /// Write the WCS of the sorted axis to the primary header of an output
+    ///
+    /// Evenly spaced keys are written as `CRVAL`, `CDELT` and `CRPIX`.
+    /// Other keys are written to the coordinate array of a `WCS-TAB`
+    /// extension (WCS Paper III), with `CRVAL`, `CDELT` and `CRPIX` of 1 so
+    /// that the pixel is the index into the array.
+    ///
+    /// # Arguments
+    ///
+    /// * `fits_file` - The output FITS file
+    /// * `output_axis` - The (FITS, 1-based) axis of the output that the sorted axis became
+    ///
+    pub fn write_wcs(&self, fits_file: &mut FitsFile, output_axis: usize) -> Result<(), Error> {
+        let (crval, cdelt) = match self.linear() {
+            Some((start, step)) => (start, step),
+            None => {
+                let column = ColumnDescription::new("COORDS")
+                    .with_type(ColumnDataType::Double)
+                    .that_repeats(self.keys.len())
+                    .create()?;
+                let hdu = fits_file.create_table(TAB_EXTNAME, &[column])?;
+                hdu.write_col(fits_file, "COORDS", &self.keys)?;
+                hdu.write_key(fits_file, "TDIM1", format!("(1,{})", self.keys.len()))?;
+
+                replace_string_key(fits_file, &format!("CTYPE{}", output_axis), &self.output_ctype())?;
+                replace_string_key(fits_file, &format!("PS{}_0", output_axis), TAB_EXTNAME)?;
+                replace_string_key(fits_file, &format!("PS{}_1", output_axis), "COORDS")?;
+                (1.0, 1.0)
+            }
+        };
+        replace_key(fits_file, &format!("CRVAL{}", output_axis), crval)?;
+        replace_key(fits_file, &format!("CDELT{}", output_axis), cdelt)?;
+        replace_key(fits_file, &format!("CRPIX{}", output_axis), 1.0)
+    }
+
+    /// Record the input plane and key of each sorted plane in a `SORTPERM` extension
+    ///
+    /// Row n of the `PLANE` column is the (1-based) plane of the input that
+    /// became plane n, so that the sort can be undone.
+    ///
+    /// # Arguments
+    ///
+    /// * `fits_file` - The output FITS file
+    /// * `output_axis` - The (FITS, 1-based) axis of the output that the sorted axis became
+    ///
+    pub fn write_permutation(&self, fits_file: &mut FitsFile, output_axis: usize) -> Result<(), Error> {
+        let columns = [
+            ColumnDescription::new("PLANE").with_type(ColumnDataType::Long).create()?,
+            ColumnDescription::new("KEY").with_type(ColumnDataType::Double).create()?,
+        ];
+        let planes: Vec<i64> = self.planes.iter().map(|plane| *plane as i64 + 1).collect();
+        let hdu = fits_file.create_table(SORT_EXTNAME, &columns)?;
+        hdu.write_col(fits_file, "PLANE", &planes)?;
+        hdu.write_col(fits_file, "KEY", &self.keys)?;
+        hdu.write_key(fits_file, "SORTAXIS", (output_axis as i64, "Axis of the primary HDU that was sorted"))?;
+        hdu.write_key(fits_file, "SORTSRC", (self.source.clone(), "Where the keys were read from"))?;
+        fits_file.primary_hdu()?;
+        Ok(())
+    }
+}
+
+/// Replace the value of a string keyword in the primary HDU
+fn replace_string_key(fits_file: &mut FitsFile, keyword: &str, value: &str) -> Result<(), Error> {
+    let hdu = fits_file.primary_hdu()?;
+    if hdu.read_key::<String>(fits_file, keyword).is_ok() {
+        delete_key(fits_file, keyword)?;
+    }
+    hdu.write_key(fits_file, keyword, value.to_string())
+}
+
+/// Read the keys of `--sort-key`
+///
+/// # Arguments
+///
+/// * `spec` - A text file, or a table column as `FILE[HDU]:COLUMN`
+/// * `cunit` - The CUNIT of the sorted axis
+/// * `axis` - The (FITS, 1-based) sorted axis, for error messages
+///
+/// # Returns
+///
+/// * `Result<Vec<f64>, FitsRotateError>` - The key of each plane
+///
+fn read_keys(spec: &str, cunit: &str, axis: usize) -> Result<Vec<f64>, FitsRotateError> {
+    if let Some((table, column)) = spec.rsplit_once("]:") {
+        let (path, hdu) = table.split_once('[').ok_or_else(|| {
+            FitsRotateError::Message(format!("{} is not of the form FILE[HDU]:COLUMN", spec))
+        })?;
+        let mut fits_file = FitsFile::open(path)?;
+        let hdu = match hdu.trim().parse::<usize>() {
+            Ok(idx) => fits_file.hdu(idx)?,
+            Err(_) => fits_file.hdu(hdu.trim())?,
+        };
+        return Ok(hdu.read_col::<f64>(&mut fits_file, column.trim())?);
+    }
+    let text = std::fs::read_to_string(spec)?;
+    text.lines()
+        .map(|line| line.split('#').next().unwrap_or("").trim())
+        .filter(|line| !line.is_empty())
+        .map(|line| axis_quantity(line, cunit, axis))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sort of the given keys, in the order of the input planes
+    fn sort_of(ctype: &str, keys: &[f64]) -> PlaneSort {
+        let mut planes: Vec<usize> = (0..keys.len()).collect();
+        planes.sort_by(|a, b| keys[*a].total_cmp(&keys[*b]));
+        PlaneSort {
+            axis: 1,
+            ctype: ctype.to_string(),
+            source: "keys.txt".to_string(),
+            keys: planes.iter().map(|plane| keys[*plane]).collect(),
+            planes,
+        }
+    }
+
+    #[test]
+    fn evenly_spaced_keys_give_a_linear_axis() {
+        let sort = sort_of("FREQ", &[1.3e9, 1.0e9, 1.2e9, 1.1e9]);
+        assert_eq!(sort.planes, [1, 3, 2, 0]);
+        let (start, step) = sort.linear().unwrap();
+        assert_eq!(start, 1.0e9);
+        assert!((step - 1e8).abs() < 1e-3, "{}", step);
+        assert_eq!(sort.output_ctype(), "FREQ");
+
+        let uneven = sort_of("VRAD", &[3.0, 1.0, 2.5]);
+        assert_eq!(uneven.linear(), None);
+        assert_eq!(uneven.output_ctype(), "VRAD-TAB");
+        assert_eq!(sort_of("FREQ-LSR", &[2.0, 0.0, 5.0]).output_ctype(), "FREQ-TAB");
+        // A single plane or repeated keys have no step
+        assert_eq!(sort_of("FREQ", &[1.0]).linear(), None);
+        assert_eq!(sort_of("FREQ", &[1.0, 1.0]).linear(), None);
+    }
+
+    #[test]
+    fn sort_moves_whole_planes_and_keeps_ties_in_order() {
+        let sort = PlaneSort {
+            axis: 3,
+            ..sort_of("FREQ", &[2.0, 0.0, 2.0, 1.0])
+        };
+        assert_eq!(sort.planes, [1, 3, 0, 2]);
+        assert!(!sort.is_identity());
+        // FITS axis 3 is the first array axis
+        let cube = ArrayD::from_shape_fn(ndarray::IxDyn(&[4, 2, 2]), |idx| {
+            (idx[0] * 10 + idx[1] * 2 + idx[2]) as i32
+        });
+        let sorted = sort.sort(cube.clone());
+        for (plane, input_plane) in sort.planes.iter().enumerate() {
+            assert_eq!(
+                sorted.index_axis(Axis(0), plane),
+                cube.index_axis(Axis(0), *input_plane)
+            );
+        }
+        assert!(sort_of("FREQ", &[0.0, 1.0, 2.0]).is_identity());
+    }
+
+    #[test]
+    fn text_keys_skip_comments_and_take_spectral_units() {
+        let path = std::env::temp_dir().join(format!("fitsrotate_rs.planesort.{}.keys.txt", std::process::id()));
+        std::fs::write(
+            &path,
+            "# channel frequencies\n1.4GHz\n\n1400.5 MHz  # second\n1.401e9\n",
+        )
+        .unwrap();
+        let keys = read_keys(path.to_str().unwrap(), "Hz", 3).unwrap();
+        assert_eq!(keys, [1.4e9, 1.4005e9, 1.401e9]);
+        std::fs::remove_file(&path).unwrap();
+        let error = read_keys("freqs.fits]:FREQ", "Hz", 3).unwrap_err().to_string();
+        assert!(error.contains("is not of the form FILE[HDU]:COLUMN"), "{}", error);
+    }
+}
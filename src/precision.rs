@@ -0,0 +1,83 @@
+use clap::ValueEnum;
+
+/// The largest integer up to which every integer is exactly a float32 (2^24)
+pub const F32_EXACT_INTEGER_LIMIT: f64 = 16_777_216.0;
+
+/// The type double precision data is held in while it is rotated
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum WorkingDtype {
+    /// Convert to 32-bit floats as the data is read, halving the memory needed
+    Float32,
+    /// Keep 64-bit floats
+    Float64,
+}
+
+/// The precision lost converting double precision values to float32
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Narrowing {
+    /// The number of values converted
+    pub n_values: usize,
+    /// The largest relative rounding error of a finite, non-zero value
+    pub max_relative_error: f64,
+    /// The number of integer values beyond the exact-integer range of float32 that were rounded
+    pub n_rounded_integers: usize,
+}
+
+impl Narrowing {
+    /// Convert a value to float32, keeping track of the error
+    pub fn narrow(&mut self, value: f64) -> f32 {
+        let narrowed = value as f32;
+        self.n_values += 1;
+        if value.is_finite() && value != 0.0 {
+            let error = (narrowed as f64 - value).abs() / value.abs();
+            self.max_relative_error = self.max_relative_error.max(error);
+            if value.fract() == 0.0 && value.abs() > F32_EXACT_INTEGER_LIMIT && narrowed as f64 != value {
+                self.n_rounded_integers += 1;
+            }
+        }
+        narrowed
+    }
+
+    /// Describe the precision that was lost
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<String>` - A line for the rounding error, and one for any rounded integers
+    ///
+    pub fn warnings(&self) -> Vec<String> {
+        let mut warnings = vec![format!(
+            "converted {} double precision values to float32; the largest relative rounding error is {:.3e}",
+            self.n_values, self.max_relative_error
+        )];
+        if self.n_rounded_integers > 0 {
+            warnings.push(format!(
+                "{} integer values beyond {} (2^24) could not be held exactly and were rounded",
+                self.n_rounded_integers, F32_EXACT_INTEGER_LIMIT
+            ));
+        }
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn narrowing_tracks_the_largest_error_and_the_rounded_integers() {
+        let mut narrowing = Narrowing::default();
+        for value in [0.0, 1.5, -3.0, 16_777_216.0, 16_777_217.0, f64::NAN, f64::INFINITY] {
+            narrowing.narrow(value);
+        }
+        assert_eq!(narrowing.n_values, 7);
+        assert_eq!(narrowing.n_rounded_integers, 1);
+        assert_eq!(narrowing.max_relative_error, 1.0 / 16_777_217.0);
+        assert_eq!(narrowing.warnings().len(), 2);
+
+        // Values that float32 holds exactly lose nothing
+        let mut exact = Narrowing::default();
+        assert_eq!(exact.narrow(0.25), 0.25);
+        assert_eq!(exact.max_relative_error, 0.0);
+        assert_eq!(exact.warnings().len(), 1);
+    }
+}
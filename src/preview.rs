@@ -0,0 +1,285 @@
+use crate::error::FitsRotateError;
+use ndarray::{Array2, ArrayD, ArrayView2, Axis, Ix2};
+use std::fs::File;
+use std::io::Write;
+
+/// Colour used for NaN (and other non-finite) pixels in a preview
+const NAN_COLOUR: [u8; 3] = [255, 0, 255];
+
+/// Lower and upper percentiles of the finite pixels mapped to black and white
+const STRETCH_PERCENTILES: (f64, f64) = (0.5, 99.5);
+
+/// Softening of the asinh stretch applied between the percentiles
+const ASINH_SOFTENING: f32 = 10.0;
+
+/// Extract the plane of a cube to preview
+///
+/// The plane is spanned by the two fastest (FITS axes 1 and 2) axes. Along
+/// the slowest axis the plane at `plane` is taken, defaulting to the central
+/// one, and along any other axes the central plane is taken.
+///
+/// # Arguments
+///
+/// * `cube` - The (rotated) FITS cube
+/// * `plane` - The index along the slowest axis
+///
+/// # Returns
+///
+/// * `Result<Array2<T>, FitsRotateError>` - The plane
+///
+pub fn preview_plane<T: Clone>(cube: &ArrayD<T>, plane: Option<usize>) -> Result<Array2<T>, FitsRotateError> {
+    if cube.ndim() < 2 {
+        return Err(FitsRotateError::Message(format!(
+            "Cannot preview a cube with {} axes",
+            cube.ndim()
+        )));
+    }
+    let mut view = cube.view();
+    let n_outer = cube.ndim() - 2;
+    for outer in 0..n_outer {
+        // Always index the first axis; the remaining ones shift down
+        let len = view.len_of(Axis(0));
+        let index = match (outer, plane) {
+            (0, Some(plane)) if plane >= len => {
+                return Err(FitsRotateError::Message(format!(
+                    "Preview plane {} is out of range for an axis of length {}",
+                    plane, len
+                )));
+            }
+            (0, Some(plane)) => plane,
+            _ => len / 2,
+        };
+        view = view.index_axis_move(Axis(0), index);
+    }
+    let view: ArrayView2<T> = view.into_dimensionality::<Ix2>().map_err(|e| {
+        FitsRotateError::Message(format!("Could not extract preview plane: {}", e))
+    })?;
+    Ok(view.to_owned())
+}
+
+/// Map a plane to 8-bit grey levels with a percentile and asinh stretch
+///
+/// # Arguments
+///
+/// * `plane` - The plane
+///
+/// # Returns
+///
+/// * `Vec<[u8; 3]>` - RGB pixels, top row first (FITS row 1 is the bottom)
+///
+fn stretch_plane(plane: &Array2<f32>) -> Vec<[u8; 3]> {
+    let mut finite: Vec<f32> = plane.iter().cloned().filter(|v| v.is_finite()).collect();
+    finite.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let percentile = |p: f64| -> f32 {
+        if finite.is_empty() {
+            return 0.0;
+        }
+        let idx = ((p / 100.0) * (finite.len() - 1) as f64).round() as usize;
+        finite[idx]
+    };
+    let (lo, hi) = (percentile(STRETCH_PERCENTILES.0), percentile(STRETCH_PERCENTILES.1));
+    let range = if hi > lo { hi - lo } else { 1.0 };
+
+    let (n_rows, n_cols) = plane.dim();
+    let mut pixels: Vec<[u8; 3]> = Vec::with_capacity(n_rows * n_cols);
+    for row in (0..n_rows).rev() {
+        for col in 0..n_cols {
+            let value = plane[[row, col]];
+            if !value.is_finite() {
+                pixels.push(NAN_COLOUR);
+                continue;
+            }
+            let scaled = ((value - lo) / range).clamp(0.0, 1.0);
+            let stretched = (ASINH_SOFTENING * scaled).asinh() / ASINH_SOFTENING.asinh();
+            let grey = (stretched * 255.0).round() as u8;
+            pixels.push([grey, grey, grey]);
+        }
+    }
+    pixels
+}
+
+/// CRC-32 (as used by PNG) of a byte slice
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Adler-32 checksum (as used by zlib) of a byte slice
+fn adler32(bytes: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in bytes {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+/// Wrap bytes in a zlib stream of uncompressed (stored) deflate blocks
+fn zlib_stored(bytes: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    let mut chunks = bytes.chunks(65535).peekable();
+    if chunks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(chunk) = chunks.next() {
+        let last = chunks.peek().is_none();
+        let len = chunk.len() as u16;
+        out.push(u8::from(last));
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(chunk);
+    }
+    out.extend_from_slice(&adler32(bytes).to_be_bytes());
+    out
+}
+
+/// Append a PNG chunk to a buffer
+fn push_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// Write a PNG preview of a plane
+///
+/// The plane is stretched between the 0.5 and 99.5 percentiles of its
+/// finite values with an asinh stretch and written as grey levels, with
+/// NaNs shown in magenta. The names of the displayed axes are recorded in
+/// `tEXt` chunks of the PNG.
+///
+/// # Arguments
+///
+/// * `filename` - The PNG file to write
+/// * `plane` - The plane, as returned by `preview_plane`
+/// * `x_label` - The name of the horizontal (FITS axis 1) axis
+/// * `y_label` - The name of the vertical (FITS axis 2) axis
+///
+pub fn write_preview(
+    filename: &str,
+    plane: &Array2<f32>,
+    x_label: &str,
+    y_label: &str,
+) -> Result<(), FitsRotateError> {
+    let (height, width) = plane.dim();
+    let pixels = stretch_plane(plane);
+
+    // Each row is prefixed with filter type 0 (none)
+    let mut raw: Vec<u8> = Vec::with_capacity(height * (width * 3 + 1));
+    for row in pixels.chunks(width.max(1)) {
+        raw.push(0);
+        for pixel in row {
+            raw.extend_from_slice(pixel);
+        }
+    }
+
+    let mut png: Vec<u8> = vec![0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+    let mut ihdr: Vec<u8> = Vec::new();
+    ihdr.extend_from_slice(&(width as u32).to_be_bytes());
+    ihdr.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8-bit RGB, default compression/filter, no interlacing
+    ihdr.extend_from_slice(&[8, 2, 0, 0, 0]);
+    push_chunk(&mut png, b"IHDR", &ihdr);
+    push_chunk(&mut png, b"tEXt", format!("X-Axis\0{}", x_label).as_bytes());
+    push_chunk(&mut png, b"tEXt", format!("Y-Axis\0{}", y_label).as_bytes());
+    push_chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    push_chunk(&mut png, b"IEND", &[]);
+
+    let mut file = File::create(filename)?;
+    file.write_all(&png)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::IxDyn;
+
+    /// The width, height and RGB pixels (top row first) of a PNG written by `write_preview`
+    fn decode_png(png: &[u8]) -> (usize, usize, Vec<[u8; 3]>) {
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a]);
+        let (mut width, mut height, mut idat) = (0, 0, Vec::new());
+        let mut offset = 8;
+        while offset < png.len() {
+            let length = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+            let kind = &png[offset + 4..offset + 8];
+            let data = &png[offset + 8..offset + 8 + length];
+            let crc = u32::from_be_bytes(png[offset + 8 + length..offset + 12 + length].try_into().unwrap());
+            assert_eq!(crc, crc32(&png[offset + 4..offset + 8 + length]));
+            match kind {
+                b"IHDR" => {
+                    width = u32::from_be_bytes(data[..4].try_into().unwrap()) as usize;
+                    height = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+                }
+                b"IDAT" => idat.extend_from_slice(data),
+                _ => {}
+            }
+            offset += length + 12;
+        }
+        // Stored deflate blocks: a final flag, the length and its complement, then the bytes
+        let mut raw = Vec::new();
+        let mut block = 2;
+        loop {
+            let last = idat[block] == 1;
+            let length = u16::from_le_bytes([idat[block + 1], idat[block + 2]]) as usize;
+            raw.extend_from_slice(&idat[block + 5..block + 5 + length]);
+            block += 5 + length;
+            if last {
+                break;
+            }
+        }
+        assert_eq!(&idat[block..], &adler32(&raw).to_be_bytes());
+        let pixels = raw
+            .chunks(width * 3 + 1)
+            .flat_map(|row| {
+                assert_eq!(row[0], 0);
+                row[1..].chunks(3).map(|pixel| [pixel[0], pixel[1], pixel[2]]).collect::<Vec<_>>()
+            })
+            .collect();
+        (width, height, pixels)
+    }
+
+    #[test]
+    fn png_checksums() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+    }
+
+    #[test]
+    fn central_plane_is_previewed() {
+        let cube = ArrayD::from_shape_fn(IxDyn(&[5, 3, 4]), |idx| (idx[0] * 100 + idx[1] * 10 + idx[2]) as f32);
+        let plane = preview_plane(&cube, None).unwrap();
+        assert_eq!(plane.dim(), (3, 4));
+        assert_eq!(plane[[2, 3]], 223.0);
+        assert_eq!(preview_plane(&cube, Some(4)).unwrap()[[0, 0]], 400.0);
+        assert!(preview_plane(&cube, Some(5)).is_err());
+    }
+
+    #[test]
+    fn bright_source_lands_on_its_pixel() {
+        // 7 pixels along FITS axis 1 and 5 along FITS axis 2
+        let mut plane = Array2::<f32>::zeros((5, 7));
+        plane[[1, 5]] = 1000.0;
+        plane[[4, 0]] = f32::NAN;
+        let filename = std::env::temp_dir().join(format!("fitsrotate_rs.preview.{}.png", std::process::id()));
+        write_preview(filename.to_str().unwrap(), &plane, "RA---SIN", "DEC--SIN").unwrap();
+        let png = std::fs::read(&filename).unwrap();
+        let _ = std::fs::remove_file(&filename);
+
+        let (width, height, pixels) = decode_png(&png);
+        assert_eq!((width, height), (7, 5));
+        // The PNG is drawn top down, so FITS row 2 of 5 is PNG row 3
+        let brightest = pixels.iter().position(|pixel| *pixel == [255, 255, 255]);
+        assert_eq!(brightest, Some(3 * width + 5));
+        assert_eq!(pixels[0], NAN_COLOUR);
+        assert_eq!(pixels.iter().filter(|pixel| **pixel == [0, 0, 0]).count(), 33);
+    }
+}
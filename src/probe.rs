@@ -0,0 +1,418 @@
+use crate::axes::{axis_role, find_axis, AxisRole};
+use crate::color::label;
+use crate::error::FitsRotateError;
+use crate::regrid::axis_quantity;
+use crate::sidecar::CubeInfo;
+use crate::stokes::stokes_code;
+use fitsio::FitsFile;
+use std::ops::Range;
+
+/// The angular units accepted by `probe --world`, with their size in degrees
+const ANGLE_UNITS: [(&str, f64); 4] = [
+    ("deg", 1.0),
+    ("arcmin", 1.0 / 60.0),
+    ("arcsec", 1.0 / 3600.0),
+    ("rad", 57.295_779_513_082_32),
+];
+
+/// The linear part of the WCS of a cube: `world = CRVAL + M (pixel - CRPIX)`
+///
+/// The celestial projection is not applied, so RA and DEC are only exact
+/// near the reference pixel. A rotation permutes (and reverses) the axes
+/// of `M`, so the same world coordinate gives the same voxel of the input
+/// and of its rotated output.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LinearWcs {
+    /// The CTYPE of each axis, in FITS order
+    pub ctypes: Vec<String>,
+    /// The CUNIT of each axis
+    pub cunits: Vec<String>,
+    /// The length of each axis
+    pub shape: Vec<usize>,
+    pub crval: Vec<f64>,
+    pub crpix: Vec<f64>,
+    /// The CD matrix, or PCi_j scaled by CDELTi (row i is world axis i)
+    pub matrix: Vec<Vec<f64>>,
+}
+
+impl LinearWcs {
+    /// The linear WCS of a cube, with the defaults of the FITS standard for missing cards
+    pub fn new(info: &CubeInfo) -> LinearWcs {
+        let naxis = info.axes.len();
+        let matrix = match (&info.cd, &info.pc) {
+            (Some(cd), _) => cd.clone(),
+            (None, pc) => (0..naxis)
+                .map(|i| {
+                    let cdelt = info.axes[i].cdelt.unwrap_or(1.0);
+                    (0..naxis)
+                        .map(|j| {
+                            let pc = pc.as_ref().map_or(if i == j { 1.0 } else { 0.0 }, |pc| pc[i][j]);
+                            cdelt * pc
+                        })
+                        .collect()
+                })
+                .collect(),
+        };
+        LinearWcs {
+            ctypes: info
+                .axes
+                .iter()
+                .map(|axis| axis.ctype.clone().unwrap_or_default())
+                .collect(),
+            cunits: info
+                .axes
+                .iter()
+                .map(|axis| axis.cunit.clone().unwrap_or_default())
+                .collect(),
+            shape: info.axes.iter().map(|axis| axis.naxis).collect(),
+            crval: info.axes.iter().map(|axis| axis.crval.unwrap_or(0.0)).collect(),
+            crpix: info.axes.iter().map(|axis| axis.crpix.unwrap_or(0.0)).collect(),
+            matrix,
+        }
+    }
+
+    /// The world coordinates of a (1-based) pixel
+    pub fn pixel_to_world(&self, pixel: &[f64]) -> Vec<f64> {
+        (0..self.crval.len())
+            .map(|i| {
+                self.crval[i]
+                    + (0..pixel.len())
+                        .map(|j| self.matrix[i][j] * (pixel[j] - self.crpix[j]))
+                        .sum::<f64>()
+            })
+            .collect()
+    }
+
+    /// The (1-based) pixel of some world coordinates
+    ///
+    /// An axis without a world coordinate must have length 1, and is at
+    /// pixel 1.
+    ///
+    /// # Arguments
+    ///
+    /// * `world` - The world coordinate of each axis, if given, in the units of the axis
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Vec<f64>, FitsRotateError>` - The pixel, which may be fractional or outside the cube
+    ///
+    pub fn world_to_pixel(&self, world: &[Option<f64>]) -> Result<Vec<f64>, FitsRotateError> {
+        let mut pixel: Vec<f64> = vec![1.0; self.shape.len()];
+        for (idx, value) in world.iter().enumerate() {
+            if value.is_none() && self.shape[idx] > 1 {
+                return Err(FitsRotateError::Message(format!(
+                    "No world coordinate for axis {} ({}), which has {} pixels",
+                    idx + 1,
+                    self.ctypes[idx],
+                    self.shape[idx]
+                )));
+            }
+        }
+        // Each given coordinate is an equation for the pixel of its own axis
+        let given: Vec<usize> = (0..world.len()).filter(|idx| world[*idx].is_some()).collect();
+        let mut system: Vec<Vec<f64>> = given
+            .iter()
+            .map(|i| {
+                let mut rhs = world[*i].unwrap_or(0.0) - self.crval[*i];
+                for j in (0..pixel.len()).filter(|j| !given.contains(j)) {
+                    rhs -= self.matrix[*i][j] * (pixel[j] - self.crpix[j]);
+                }
+                let mut row: Vec<f64> = given.iter().map(|j| self.matrix[*i][*j]).collect();
+                row.push(rhs);
+                row
+            })
+            .collect();
+        let offsets = solve(&mut system).ok_or_else(|| {
+            FitsRotateError::Message("The WCS matrix is singular, so world coordinates have no pixel".to_string())
+        })?;
+        for (j, offset) in given.iter().zip(offsets) {
+            pixel[*j] = self.crpix[*j] + offset;
+        }
+        Ok(pixel)
+    }
+}
+
+/// Solve a linear system by Gaussian elimination with partial pivoting
+///
+/// # Arguments
+///
+/// * `system` - The augmented matrix, one row per equation with the right-hand side last
+///
+/// # Returns
+///
+/// * `Option<Vec<f64>>` - The solution, or `None` if the matrix is singular
+///
+fn solve(system: &mut [Vec<f64>]) -> Option<Vec<f64>> {
+    let n = system.len();
+    for column in 0..n {
+        let pivot = (column..n).max_by(|a, b| system[*a][column].abs().total_cmp(&system[*b][column].abs()))?;
+        if system[pivot][column] == 0.0 {
+            return None;
+        }
+        system.swap(column, pivot);
+        for row in column + 1..n {
+            let factor = system[row][column] / system[column][column];
+            let (upper, lower) = system.split_at_mut(row);
+            for (value, pivot_value) in lower[0][column..].iter_mut().zip(&upper[column][column..]) {
+                *value -= factor * pivot_value;
+            }
+        }
+    }
+    let mut solution = vec![0.0; n];
+    for row in (0..n).rev() {
+        let known: f64 = (row + 1..n).map(|idx| system[row][idx] * solution[idx]).sum();
+        solution[row] = (system[row][n] - known) / system[row][row];
+    }
+    Some(solution)
+}
+
+/// Convert a world coordinate such as `150.21deg` or `1.4GHz` to the units of an axis
+///
+/// Angles may be given in deg, arcmin, arcsec or rad, spectral
+/// coordinates in any unit of `--spectral-regrid`, and Stokes parameters
+/// by name (e.g. `I`). A number without a unit is in the units of the axis.
+fn world_value(text: &str, wcs: &LinearWcs, idx: usize) -> Result<f64, FitsRotateError> {
+    let text = text.trim();
+    match axis_role(&wcs.ctypes[idx]) {
+        Some(AxisRole::Spectral) => axis_quantity(text, &wcs.cunits[idx], idx + 1),
+        Some(AxisRole::Stokes) => stokes_code(text)
+            .map(|code| code as f64)
+            .or_else(|| text.parse::<f64>().ok())
+            .ok_or_else(|| FitsRotateError::Message(format!("Unknown Stokes parameter {}", text))),
+        Some(AxisRole::Ra) | Some(AxisRole::Dec) => {
+            let to_degrees = |unit: &str| {
+                ANGLE_UNITS
+                    .iter()
+                    .find(|(name, _)| *name == unit)
+                    .map(|(_, scale)| *scale)
+            };
+            let unit_start = text.find(|c: char| c.is_ascii_alphabetic()).unwrap_or(text.len());
+            let (number, unit) = text.split_at(unit_start);
+            let value = number
+                .trim()
+                .parse::<f64>()
+                .map_err(|_| FitsRotateError::Message(format!("Cannot read {} as an angle", text)))?;
+            if unit.is_empty() {
+                return Ok(value);
+            }
+            let scale = to_degrees(unit).ok_or_else(|| {
+                FitsRotateError::Message(format!("Unknown unit {} (expected deg, arcmin, arcsec or rad)", unit))
+            })?;
+            let axis_scale = match wcs.cunits[idx].trim() {
+                "" => 1.0,
+                cunit => to_degrees(cunit).ok_or_else(|| {
+                    FitsRotateError::Message(format!(
+                        "Cannot convert {} to the units of axis {} ({})",
+                        unit,
+                        idx + 1,
+                        cunit
+                    ))
+                })?,
+            };
+            Ok(value * scale / axis_scale)
+        }
+        None => text
+            .parse::<f64>()
+            .map_err(|_| FitsRotateError::Message(format!("Cannot read {} as a number", text))),
+    }
+}
+
+/// Parse world coordinates such as `ra=150.21deg,dec=2.18deg,freq=1.4GHz`
+///
+/// # Arguments
+///
+/// * `spec` - The coordinates, as `<axis>=<value>` separated by commas
+/// * `wcs` - The WCS of the cube
+///
+/// # Returns
+///
+/// * `Result<Vec<Option<f64>>, FitsRotateError>` - The coordinate of each axis, if given, in the units of the axis
+///
+pub fn parse_world(spec: &str, wcs: &LinearWcs) -> Result<Vec<Option<f64>>, FitsRotateError> {
+    let mut world: Vec<Option<f64>> = vec![None; wcs.ctypes.len()];
+    for item in spec.split(',').filter(|item| !item.trim().is_empty()) {
+        let (name, value) = item
+            .split_once('=')
+            .ok_or_else(|| FitsRotateError::Message(format!("Expected <axis>=<value>, got {}", item)))?;
+        let idx = find_axis(name, &wcs.ctypes).ok_or_else(|| {
+            FitsRotateError::Message(format!(
+                "No axis matches {} (CTYPEs: {})",
+                name.trim(),
+                wcs.ctypes.join(", ")
+            ))
+        })?;
+        if world[idx].is_some() {
+            return Err(FitsRotateError::Message(format!(
+                "Axis {} ({}) is given twice",
+                idx + 1,
+                wcs.ctypes[idx]
+            )));
+        }
+        world[idx] = Some(world_value(value, wcs, idx)?);
+    }
+    Ok(world)
+}
+
+/// Parse a (1-based) pixel such as `512,488,300`, in FITS axis order
+pub fn parse_pixel(spec: &str, naxis: usize) -> Result<Vec<f64>, FitsRotateError> {
+    let pixel: Vec<f64> = spec
+        .split(',')
+        .map(|value| value.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .map_err(|_| FitsRotateError::Message(format!("Expected a pixel such as 512,488,300, got {}", spec)))?;
+    if pixel.len() != naxis {
+        return Err(FitsRotateError::Message(format!(
+            "Expected {} pixel coordinates, got {}",
+            naxis,
+            pixel.len()
+        )));
+    }
+    Ok(pixel)
+}
+
+/// Print the value of the voxel at a position in a cube, or the spectrum through it as CSV
+///
+/// The position is rounded to the nearest voxel. Only that voxel (or
+/// spectrum) is read. The values are printed in full precision, so that
+/// the input and its rotated output can be compared exactly.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `world` - The world coordinates, e.g. `ra=150.21deg,dec=2.18deg,freq=1.4GHz`
+/// * `pixel` - The (1-based) pixel, e.g. `512,488,300`, if no world coordinates are given
+/// * `spectrum` - The axis to print the spectrum along, e.g. `freq`
+///
+pub fn probe(
+    filename: &str,
+    world: Option<&str>,
+    pixel: Option<&str>,
+    spectrum: Option<&str>,
+) -> Result<(), FitsRotateError> {
+    let wcs = LinearWcs::new(&CubeInfo::read(filename)?);
+    let naxis = wcs.shape.len();
+    let position = match (world, pixel) {
+        (Some(world), _) => wcs.world_to_pixel(&parse_world(world, &wcs)?)?,
+        (None, Some(pixel)) => parse_pixel(pixel, naxis)?,
+        (None, None) => return Err(FitsRotateError::Message("probe expects --world or --pixel".to_string())),
+    };
+    let voxel: Vec<usize> = position.iter().map(|p| p.round().max(0.0) as usize).collect();
+    for (idx, (p, length)) in voxel.iter().zip(&wcs.shape).enumerate() {
+        if *p < 1 || p > length {
+            return Err(FitsRotateError::Message(format!(
+                "Pixel {} of axis {} ({}) is outside the cube (1 to {})",
+                position[idx],
+                idx + 1,
+                wcs.ctypes[idx],
+                length
+            )));
+        }
+    }
+    let spectral_axis = match spectrum {
+        Some(name) => Some(find_axis(name, &wcs.ctypes).ok_or_else(|| {
+            FitsRotateError::Message(format!(
+                "No axis matches {} (CTYPEs: {})",
+                name.trim(),
+                wcs.ctypes.join(", ")
+            ))
+        })?),
+        None => None,
+    };
+
+    // The ranges are 0-based and in FITS order
+    let ranges: Vec<Range<usize>> = voxel
+        .iter()
+        .enumerate()
+        .map(|(idx, p)| match spectral_axis {
+            Some(axis) if axis == idx => 0..wcs.shape[idx],
+            _ => p - 1..*p,
+        })
+        .collect();
+    let mut fits_file = FitsFile::open(filename)?;
+    let hdu = fits_file.primary_hdu()?;
+    let values: Vec<f64> = hdu.read_region(&mut fits_file, &ranges.iter().collect::<Vec<&Range<usize>>>())?;
+
+    match spectral_axis {
+        Some(axis) => {
+            println!("channel,{},value", wcs.ctypes[axis].trim());
+            for (channel, value) in values.iter().enumerate() {
+                let mut channel_pixel: Vec<f64> = voxel.iter().map(|p| *p as f64).collect();
+                channel_pixel[axis] = (channel + 1) as f64;
+                println!("{},{},{}", channel + 1, wcs.pixel_to_world(&channel_pixel)[axis], value);
+            }
+        }
+        None => {
+            let voxel_pixel: Vec<f64> = voxel.iter().map(|p| *p as f64).collect();
+            let world = wcs.pixel_to_world(&voxel_pixel);
+            let pixels: Vec<String> = voxel.iter().map(|p| p.to_string()).collect();
+            let coords: Vec<String> = (0..naxis)
+                .map(|idx| {
+                    format!("{}={} {}", wcs.ctypes[idx].trim(), world[idx], wcs.cunits[idx].trim())
+                        .trim_end()
+                        .to_string()
+                })
+                .collect();
+            println!("{} {}", label("Pixel:"), pixels.join(", "));
+            println!("{} {}", label("World:"), coords.join(", "));
+            println!("{} {}", label("Value:"), values[0]);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2-D WCS with a rotated matrix, so that each world axis depends on both pixel axes
+    fn wcs() -> LinearWcs {
+        LinearWcs {
+            ctypes: vec!["RA---SIN".to_string(), "DEC--SIN".to_string()],
+            cunits: vec!["deg".to_string(), "deg".to_string()],
+            shape: vec![10, 20],
+            crval: vec![150.0, 2.0],
+            crpix: vec![5.0, 10.0],
+            matrix: vec![vec![-0.5, 0.25], vec![0.25, 0.5]],
+        }
+    }
+
+    #[test]
+    fn world_to_pixel_inverts_pixel_to_world() {
+        let wcs = wcs();
+        for pixel in [[5.0, 10.0], [1.0, 1.0], [7.5, 18.25]] {
+            let world = wcs.pixel_to_world(&pixel);
+            let back = wcs.world_to_pixel(&[Some(world[0]), Some(world[1])]).unwrap();
+            for (a, b) in back.iter().zip(pixel) {
+                assert!((a - b).abs() < 1e-12, "{:?} != {:?}", back, pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn singular_matrices_and_missing_coordinates_have_no_pixel() {
+        let mut singular = wcs();
+        singular.matrix = vec![vec![1.0, 2.0], vec![2.0, 4.0]];
+        assert!(singular.world_to_pixel(&[Some(1.0), Some(2.0)]).is_err());
+        assert!(wcs().world_to_pixel(&[Some(150.0), None]).is_err());
+    }
+
+    #[test]
+    fn world_coordinates_are_converted_to_the_units_of_the_axis() {
+        let wcs = wcs();
+        assert_eq!(parse_world("ra=9000arcmin", &wcs).unwrap(), vec![Some(150.0), None]);
+        assert_eq!(
+            parse_world("dec=7200arcsec,ra=150", &wcs).unwrap(),
+            vec![Some(150.0), Some(2.0)]
+        );
+        assert!(parse_world("ra=1deg,ra=2deg", &wcs).is_err());
+        assert!(parse_world("ra=1parsec", &wcs).is_err());
+        assert!(parse_world("freq=1GHz", &wcs).is_err());
+    }
+
+    #[test]
+    fn pixels_need_a_number_for_each_axis() {
+        assert_eq!(parse_pixel("512, 488,300", 3).unwrap(), vec![512.0, 488.0, 300.0]);
+        assert!(parse_pixel("512,488", 3).is_err());
+        assert!(parse_pixel("512,x,300", 3).is_err());
+    }
+}
@@ -0,0 +1,466 @@
+use crate::buildinfo::json_string;
+use crate::color::label;
+use crate::error::FitsRotateError;
+use crate::header::FitsHeader;
+use fitsio::errors::{check_status, Error};
+use fitsio::FitsFile;
+use std::ffi::CString;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The start of every HISTORY card written by this tool
+const HISTORY_PREFIX: &str = "fitsrotate_rs: ";
+
+/// The width of the text of a HISTORY card, after the keyword
+const HISTORY_WIDTH: usize = 72;
+
+/// Format seconds since the Unix epoch as a FITS date (`YYYY-MM-DDThh:mm:ss`)
+///
+/// # Arguments
+///
+/// * `secs` - Seconds since 1970-01-01T00:00:00 UTC
+///
+/// # Returns
+///
+/// * `String` - The FITS date string
+///
+pub fn format_fits_date(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+    let (hour, minute, second) = (rem / 3600, (rem % 3600) / 60, rem % 60);
+
+    // Convert days since the epoch to a civil date (proleptic Gregorian)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        year, month, day, hour, minute, second
+    )
+}
+
+/// The date to stamp on an output file
+///
+/// In reproducible mode the date is taken from `SOURCE_DATE_EPOCH`, or
+/// omitted entirely if that is not set, so that repeated runs produce
+/// identical files.
+///
+/// # Arguments
+///
+/// * `reproducible` - Avoid depending on the current time
+///
+/// # Returns
+///
+/// * `Option<String>` - The FITS date string, if any
+///
+pub fn output_date(reproducible: bool) -> Option<String> {
+    if reproducible {
+        let epoch = std::env::var("SOURCE_DATE_EPOCH").ok()?;
+        return epoch.trim().parse::<u64>().ok().map(format_fits_date);
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+    Some(format_fits_date(now.as_secs()))
+}
+
+/// Write the DATE keyword to the primary header
+///
+/// # Arguments
+///
+/// * `fits_file` - The FITS file
+/// * `reproducible` - Avoid depending on the current time
+///
+pub fn write_date(fits_file: &mut FitsFile, reproducible: bool) -> Result<(), Error> {
+    if let Some(date) = output_date(reproducible) {
+        let hdu = fits_file.primary_hdu()?;
+        hdu.write_key(fits_file, "DATE", (date, "UTC date the file was written"))?;
+    }
+    Ok(())
+}
+
+/// Append a HISTORY card to the primary header
+///
+/// # Arguments
+///
+/// * `fits_file` - The FITS file
+/// * `history` - The text of the card
+///
+pub fn write_history(fits_file: &mut FitsFile, history: &str) -> Result<(), Error> {
+    fits_file.primary_hdu()?;
+    let c_history = CString::new(history)?;
+    let mut status = 0;
+    unsafe {
+        fitsio::sys::ffphis(fits_file.as_raw(), c_history.as_ptr(), &mut status);
+    }
+    check_status(status)
+}
+
+/// The HISTORY card recording the version that wrote a file, and when
+///
+/// # Arguments
+///
+/// * `reproducible` - Avoid depending on the current time
+///
+/// # Returns
+///
+/// * `String` - The text of the card
+///
+pub fn version_history(reproducible: bool) -> String {
+    let version = env!("CARGO_PKG_VERSION");
+    match output_date(reproducible) {
+        Some(date) => format!("{}written by version {} on {}", HISTORY_PREFIX, version, date),
+        None => format!("{}written by version {}", HISTORY_PREFIX, version),
+    }
+}
+
+/// One run of this tool over a file, as recorded in its HISTORY cards
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProvenanceRecord {
+    /// What the run did: `rotated`, `repaired` or `modified`
+    pub action: String,
+    /// The file the run read
+    pub input: Option<String>,
+    /// The mode of the rotation
+    pub mode: Option<String>,
+    /// The version of this tool
+    pub version: Option<String>,
+    /// The date of the run (older versions did not record one)
+    pub date: Option<String>,
+    /// The other changes made by the run, e.g. a Stokes plane selection
+    pub steps: Vec<String>,
+}
+
+/// The provenance trail of a file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Provenance {
+    /// The DATE card, i.e. when the file was last written
+    pub date: Option<String>,
+    /// The runs of this tool, oldest first
+    pub records: Vec<ProvenanceRecord>,
+    /// The HISTORY cards written by other software
+    pub other_history: Vec<String>,
+}
+
+impl Provenance {
+    /// Collect the provenance trail from the cards of a header
+    ///
+    /// Each rotation (or `fix-header` repair) starts a new record, and the
+    /// HISTORY cards written after it by the same run are added to it. A
+    /// rotated file keeps the HISTORY cards of its input, so the records
+    /// come out in the order the runs were made.
+    ///
+    /// # Arguments
+    ///
+    /// * `header` - The primary header
+    ///
+    /// # Returns
+    ///
+    /// * `Provenance` - The provenance trail
+    ///
+    pub fn from_header(header: &FitsHeader) -> Provenance {
+        let mut provenance = Provenance {
+            date: header
+                .cards
+                .iter()
+                .find(|card| card.keyword() == "DATE")
+                .and_then(|card| card.value()),
+            ..Default::default()
+        };
+        for history in history_texts(header) {
+            let text = match history.strip_prefix(HISTORY_PREFIX) {
+                Some(text) => text,
+                None => {
+                    provenance.other_history.push(history);
+                    continue;
+                }
+            };
+            let started = |action: &str, rest: &str| {
+                let (input, mode) = match rest.rsplit_once(" with mode ") {
+                    Some((input, mode)) => (input, Some(mode.to_string())),
+                    None => (rest, None),
+                };
+                ProvenanceRecord {
+                    action: action.to_string(),
+                    input: Some(input.to_string()),
+                    mode,
+                    ..Default::default()
+                }
+            };
+            if let Some(rest) = text.strip_prefix("reordered the axes of ") {
+                provenance.records.push(started("rotated", rest));
+                continue;
+            }
+            if let Some(rest) = text.strip_prefix("repaired the WCS from ") {
+                provenance.records.push(started("repaired", rest));
+                continue;
+            }
+            if provenance.records.is_empty() {
+                provenance.records.push(ProvenanceRecord {
+                    action: "modified".to_string(),
+                    ..Default::default()
+                });
+            }
+            let record = provenance.records.last_mut().unwrap();
+            match text.strip_prefix("written by version ") {
+                Some(rest) => match rest.split_once(" on ") {
+                    Some((version, date)) => {
+                        record.version = Some(version.to_string());
+                        record.date = Some(date.to_string());
+                    }
+                    None => record.version = Some(rest.to_string()),
+                },
+                None => record.steps.push(text.to_string()),
+            }
+        }
+        provenance
+    }
+
+    /// Read the provenance trail from the primary header of a file
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The FITS file
+    ///
+    /// # Returns
+    ///
+    /// * `Result<Provenance, FitsRotateError>` - The provenance trail
+    ///
+    pub fn read(filename: &str) -> Result<Provenance, FitsRotateError> {
+        let mut fits_file = FitsFile::open(filename)?;
+        let header = FitsHeader::read(&mut fits_file).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+        Ok(Provenance::from_header(&header))
+    }
+
+    /// The summary of the trail, for reading in a terminal
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if self.records.is_empty() {
+            lines.push("No fitsrotate_rs provenance found".to_string());
+        }
+        for (idx, record) in self.records.iter().enumerate() {
+            let mut line = format!("{}. {}", idx + 1, capitalize(&record.action));
+            if let Some(input) = &record.input {
+                line.push_str(&format!(" from {}", input));
+            }
+            if let Some(mode) = &record.mode {
+                line.push_str(&format!(" with mode {}", mode));
+            }
+            let when: Vec<String> = [
+                record.version.as_ref().map(|version| format!("version {}", version)),
+                record.date.clone(),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            if !when.is_empty() {
+                line.push_str(&format!(" ({})", when.join(", ")));
+            }
+            lines.push(line);
+            for step in &record.steps {
+                lines.push(format!("   - {}", step));
+            }
+        }
+        lines.push(format!(
+            "Last written: {}",
+            self.date.as_deref().unwrap_or("unknown (no DATE card)")
+        ));
+        lines.push(format!("Other HISTORY cards: {}", self.other_history.len()));
+        lines
+    }
+
+    /// The trail as a JSON object, for catalogs
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The FITS file, recorded in the object
+    ///
+    pub fn to_json(&self, filename: &str) -> String {
+        let optional = |value: &Option<String>| value.as_deref().map_or("null".to_string(), json_string);
+        let list = |values: &[String]| {
+            let values: Vec<String> = values.iter().map(|value| json_string(value)).collect();
+            format!("[{}]", values.join(", "))
+        };
+        let records: Vec<String> = self
+            .records
+            .iter()
+            .map(|record| {
+                format!(
+                    "    {{\"action\": {}, \"input\": {}, \"mode\": {}, \"version\": {}, \"date\": {}, \"steps\": {}}}",
+                    json_string(&record.action),
+                    optional(&record.input),
+                    optional(&record.mode),
+                    optional(&record.version),
+                    optional(&record.date),
+                    list(&record.steps)
+                )
+            })
+            .collect();
+        let records = if records.is_empty() {
+            "[]".to_string()
+        } else {
+            format!("[\n{}\n  ]", records.join(",\n"))
+        };
+        let fields = [
+            ("file", json_string(filename)),
+            ("date", optional(&self.date)),
+            ("records", records),
+            ("other_history", list(&self.other_history)),
+        ];
+        let fields: Vec<String> = fields
+            .iter()
+            .map(|(key, value)| format!("  {}: {}", json_string(key), value))
+            .collect();
+        format!("{{\n{}\n}}", fields.join(",\n"))
+    }
+}
+
+/// Print the provenance trail of a file
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `json` - Print the trail as JSON rather than text
+///
+pub fn print_provenance(filename: &str, json: bool) -> Result<(), FitsRotateError> {
+    let provenance = Provenance::read(filename)?;
+    if json {
+        println!("{}", provenance.to_json(filename));
+    } else {
+        println!("{} {}", label("History of"), filename);
+        for line in provenance.lines() {
+            println!("{}", line);
+        }
+    }
+    Ok(())
+}
+
+/// The text of each HISTORY record of a header
+///
+/// CFITSIO splits a long HISTORY record over several cards, so a card that
+/// follows a full-width card of this tool, and is not itself one of its
+/// records, is taken to continue it.
+///
+/// # Arguments
+///
+/// * `header` - The header
+///
+/// # Returns
+///
+/// * `Vec<String>` - The text of each record
+///
+fn history_texts(header: &FitsHeader) -> Vec<String> {
+    let mut texts: Vec<String> = Vec::new();
+    let mut continued = false;
+    for card in header.cards.iter().filter(|card| card.keyword() == "HISTORY") {
+        let text = card.record.get(8..).unwrap_or("");
+        // Padding keeps a space that fell at the end of a full card
+        let padded = format!("{:<width$}", text, width = HISTORY_WIDTH);
+        match texts.last_mut() {
+            Some(last) if continued && !text.starts_with(HISTORY_PREFIX) => last.push_str(&padded),
+            _ => texts.push(padded),
+        }
+        continued = texts.last().is_some_and(|last| last.starts_with(HISTORY_PREFIX))
+            && text.trim_end().len() >= HISTORY_WIDTH - 1;
+    }
+    texts.iter().map(|text| text.trim_end().to_string()).collect()
+}
+
+/// Capitalize the first letter of a word
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::HeaderCard;
+
+    #[test]
+    fn fits_dates() {
+        assert_eq!(format_fits_date(0), "1970-01-01T00:00:00");
+        assert_eq!(format_fits_date(951782400), "2000-02-29T00:00:00");
+        assert_eq!(format_fits_date(1709251199), "2024-02-29T23:59:59");
+    }
+
+    #[test]
+    fn reproducible_date_comes_from_source_date_epoch() {
+        std::env::set_var("SOURCE_DATE_EPOCH", "951782400");
+        assert_eq!(output_date(true).as_deref(), Some("2000-02-29T00:00:00"));
+        std::env::remove_var("SOURCE_DATE_EPOCH");
+        assert_eq!(output_date(true), None);
+        assert!(output_date(false).is_some());
+    }
+
+    /// A header of HISTORY cards, split by CFITSIO at 72 characters as write_history does
+    fn history_header(records: &[&str]) -> FitsHeader {
+        let mut cards = vec![HeaderCard::new("DATE    = '2024-02-29T23:59:59'")];
+        for record in records {
+            let chars: Vec<char> = record.chars().collect();
+            for chunk in chars.chunks(HISTORY_WIDTH) {
+                cards.push(HeaderCard::new(&format!(
+                    "HISTORY {}",
+                    chunk.iter().collect::<String>()
+                )));
+            }
+        }
+        FitsHeader { cards }
+    }
+
+    #[test]
+    fn records_are_rebuilt_from_the_history_cards() {
+        let long_input = format!("/data/{}/cube.fits", "deep".repeat(20));
+        let header = history_header(&[
+            "Imaged by WSClean",
+            &format!("fitsrotate_rs: reordered the axes of {} with mode 312", long_input),
+            "fitsrotate_rs: written by version 0.1.0 on 2024-02-29T00:00:00",
+            "fitsrotate_rs: converted CROTA to a PC matrix",
+            "fitsrotate_rs: repaired the WCS from cube.rot.fits with mode -213",
+            "fitsrotate_rs: written by version 0.1.1",
+        ]);
+        let provenance = Provenance::from_header(&header);
+        assert_eq!(provenance.date.as_deref(), Some("2024-02-29T23:59:59"));
+        assert_eq!(provenance.other_history, ["Imaged by WSClean"]);
+        assert_eq!(
+            provenance.records,
+            [
+                ProvenanceRecord {
+                    action: "rotated".into(),
+                    input: Some(long_input),
+                    mode: Some("312".into()),
+                    version: Some("0.1.0".into()),
+                    date: Some("2024-02-29T00:00:00".into()),
+                    steps: vec!["converted CROTA to a PC matrix".into()],
+                },
+                ProvenanceRecord {
+                    action: "repaired".into(),
+                    input: Some("cube.rot.fits".into()),
+                    mode: Some("-213".into()),
+                    version: Some("0.1.1".into()),
+                    date: None,
+                    steps: Vec::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn cards_before_a_rotation_start_a_modified_record() {
+        let provenance = Provenance::from_header(&history_header(&["fitsrotate_rs: blanked 3 pixels outside 0,1"]));
+        assert_eq!(provenance.records.len(), 1);
+        assert_eq!(provenance.records[0].action, "modified");
+        assert_eq!(provenance.records[0].steps, ["blanked 3 pixels outside 0,1"]);
+
+        let empty = Provenance::from_header(&FitsHeader::default());
+        assert_eq!(empty.lines()[0], "No fitsrotate_rs provenance found");
+        assert!(empty.to_json("cube.fits").contains("\"records\": []"));
+    }
+}
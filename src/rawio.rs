@@ -0,0 +1,589 @@
+use crate::error::FitsRotateError;
+use crate::header::is_plain_fits;
+use crate::FitsPixel;
+use fitsio::images::ImageType;
+use ndarray::{ArrayD, ArrayViewD, Axis, IxDyn};
+use std::ops::Range;
+use std::path::Path;
+
+/// Suffixes of output names that CFITSIO compresses as it writes
+const COMPRESSED_SUFFIXES: [&str; 4] = [".gz", ".Z", ".bz2", ".fz"];
+
+/// Check if the data unit of an input can be read through a memory map
+///
+/// Only uncompressed files of floating point pixels on Linux qualify.
+/// Scaled data (`BSCALE`/`BZERO`) and compressed files are left to CFITSIO,
+/// which knows how to decode them.
+///
+/// # Arguments
+///
+/// * `filename` - The input FITS file
+/// * `image_type` - The type of the pixels in the file
+/// * `scaled` - Whether the header scales the pixels with `BSCALE` or `BZERO`
+///
+/// # Returns
+///
+/// * `bool` - Whether `read_data_unit` can read the input
+///
+pub fn raw_readable(filename: &str, image_type: ImageType, scaled: bool) -> bool {
+    cfg!(target_os = "linux")
+        && matches!(image_type, ImageType::Float | ImageType::Double)
+        && !scaled
+        && is_plain_fits(Path::new(filename))
+}
+
+/// Check if the data unit of an output can be written through a memory map
+///
+/// Only plain files of floating point pixels on Linux qualify. Names with
+/// CFITSIO's extended syntax (e.g. `!out.fits` or `out.fits[compress]`),
+/// outputs to stdout or memory, and compressed suffixes are left to
+/// CFITSIO, which knows how to encode them.
+///
+/// # Arguments
+///
+/// * `filename` - The output FITS file
+///
+/// # Returns
+///
+/// * `bool` - Whether `write_data_unit` can write the output
+///
+pub fn raw_writable<T: FitsPixel>(filename: &str) -> bool {
+    cfg!(target_os = "linux")
+        && matches!(T::IMAGE_TYPE, ImageType::Float | ImageType::Double)
+        && filename != "-"
+        && !filename.starts_with('!')
+        && !filename.contains('[')
+        && !filename.contains("://")
+        && !COMPRESSED_SUFFIXES.iter().any(|suffix| filename.ends_with(suffix))
+}
+
+/// A shared memory map of part of a file, unmapped when dropped
+#[cfg(target_os = "linux")]
+struct Mapping {
+    ptr: *mut libc::c_void,
+    len: usize,
+    /// The bytes between the (page-aligned) start of the map and the requested offset
+    skip: usize,
+}
+
+#[cfg(target_os = "linux")]
+impl Mapping {
+    /// Map a range of a file
+    ///
+    /// The offset of a map must be a multiple of the page size, which the
+    /// 2880-byte blocks of a FITS file need not be, so the map starts at
+    /// the page before the range.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - The file, opened for writing too if `writable`
+    /// * `range` - The byte range to map, which must be within the file
+    /// * `writable` - Map the range for writing rather than only reading
+    ///
+    /// # Returns
+    ///
+    /// * `std::io::Result<Mapping>` - The map
+    ///
+    fn new(file: &std::fs::File, range: Range<u64>, writable: bool) -> std::io::Result<Mapping> {
+        use std::os::unix::io::AsRawFd;
+        let page = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(1) as u64;
+        let start = range.start / page * page;
+        let skip = (range.start - start) as usize;
+        let len = (range.end - start) as usize;
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                if writable {
+                    libc::PROT_READ | libc::PROT_WRITE
+                } else {
+                    libc::PROT_READ
+                },
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                start as libc::off_t,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(std::io::Error::last_os_error());
+        }
+        // Like the read hints, this is only advice
+        unsafe {
+            libc::madvise(ptr, len, libc::MADV_SEQUENTIAL);
+        }
+        Ok(Mapping { ptr, len, skip })
+    }
+
+    /// The mapped bytes of the requested range
+    fn bytes(&self) -> &[u8] {
+        let bytes = unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) };
+        &bytes[self.skip..]
+    }
+
+    /// The mapped bytes of the requested range, for writing (the map must be writable)
+    fn bytes_mut(&mut self) -> &mut [u8] {
+        let bytes = unsafe { std::slice::from_raw_parts_mut(self.ptr as *mut u8, self.len) };
+        &mut bytes[self.skip..]
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe {
+            libc::munmap(self.ptr, self.len);
+        }
+    }
+}
+
+/// Encode values as big-endian bytes, in the (logical) order of the array
+fn encode<T: FitsPixel>(values: ArrayViewD<T>, bytes: &mut [u8]) {
+    for (value, out) in values.iter().zip(bytes.chunks_exact_mut(std::mem::size_of::<T>())) {
+        value.write_be(out);
+    }
+}
+
+/// Write the data unit of a FITS image through a memory map, bypassing CFITSIO
+///
+/// The header must already be written and the file closed by CFITSIO. The
+/// file is extended to the end of the data unit, whose blocks are reserved
+/// on disk first so that a full disk is reported as an error rather than
+/// a crash. The cube is then written as big-endian values, one thread per
+/// slab of its first (slowest) array axis. The array is read in its logical
+/// order, so a permuted view is written as it would be by CFITSIO, and the
+/// padding of the last block is left as zeros, as the standard requires for
+/// images.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `extent` - The byte range of the data unit, including its padding (see `data_extent`)
+/// * `fits_cube` - The data
+///
+/// # Returns
+///
+/// * `Result<(), FitsRotateError>` - An error if the file cannot be extended or mapped
+///
+#[cfg(target_os = "linux")]
+pub fn write_data_unit<T: FitsPixel>(
+    filename: &str,
+    extent: Range<u64>,
+    fits_cube: ArrayViewD<T>,
+) -> Result<(), FitsRotateError> {
+    use std::os::unix::io::AsRawFd;
+    let n_bytes = fits_cube.len() * std::mem::size_of::<T>();
+    if n_bytes as u64 > extent.end - extent.start {
+        return Err(FitsRotateError::Message(format!(
+            "The data unit of {} has {} bytes, too few for {} bytes of data",
+            filename,
+            extent.end - extent.start,
+            n_bytes
+        )));
+    }
+    if n_bytes == 0 {
+        return Ok(());
+    }
+    let file = std::fs::OpenOptions::new().read(true).write(true).open(filename)?;
+    let status = unsafe {
+        libc::posix_fallocate(
+            file.as_raw_fd(),
+            extent.start as libc::off_t,
+            (extent.end - extent.start) as libc::off_t,
+        )
+    };
+    if status != 0 {
+        return Err(std::io::Error::from_raw_os_error(status).into());
+    }
+    let mut mapping = Mapping::new(&file, extent, true)?;
+    let bytes = &mut mapping.bytes_mut()[..n_bytes];
+    if fits_cube.ndim() == 0 {
+        encode(fits_cube, bytes);
+        return Ok(());
+    }
+    let n_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let slab_length = fits_cube.len_of(Axis(0)).div_ceil(n_threads).max(1);
+    std::thread::scope(|scope| {
+        let mut rest = bytes;
+        for slab in fits_cube.axis_chunks_iter(Axis(0), slab_length) {
+            let (head, tail) = std::mem::take(&mut rest).split_at_mut(slab.len() * std::mem::size_of::<T>());
+            rest = tail;
+            scope.spawn(move || encode(slab, head));
+        }
+    });
+    Ok(())
+}
+
+/// Write the data unit of a FITS image through a memory map, bypassing CFITSIO
+///
+/// Memory-mapped writes are only supported on Linux, so this always fails
+/// elsewhere; `raw_writable` is false there, so it is never called.
+#[cfg(not(target_os = "linux"))]
+pub fn write_data_unit<T: FitsPixel>(
+    filename: &str,
+    _extent: Range<u64>,
+    _fits_cube: ArrayViewD<T>,
+) -> Result<(), FitsRotateError> {
+    Err(FitsRotateError::Message(format!(
+        "Cannot write {} through a memory map on this platform",
+        filename
+    )))
+}
+
+/// Decode big-endian pixels of type `S` into a slice of values
+fn decode<T: FitsPixel, S: Copy>(bytes: &[u8], values: &mut [T], from_be: fn(&[u8]) -> S, convert: fn(S) -> T) {
+    for (value, raw) in values.iter_mut().zip(bytes.chunks_exact(std::mem::size_of::<S>())) {
+        *value = convert(from_be(raw));
+    }
+}
+
+/// Read the data unit of a FITS image through a memory map, bypassing CFITSIO
+///
+/// The data unit is mapped read-only and decoded from big-endian straight
+/// into the cube, one thread per slab of the slowest axis, without passing
+/// through the buffers of CFITSIO. Each value is decoded from its bytes, so
+/// it does not matter that the data unit need not start on a page, or be
+/// aligned for its pixel type in memory.
+///
+/// A file that is truncated while it is mapped cannot be read safely, so
+/// its length is checked first, and the read fails if the length or
+/// modification time of the file changed while it was read, rather than
+/// return a mix of old and new data. The inputs are locked while they are
+/// rotated (unless `--no-lock` is given), which keeps well-behaved writers
+/// away in the first place.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+/// * `data_start` - The byte offset of the data unit (see `data_extent`)
+/// * `shape` - The shape of the image, in array order
+/// * `image_type` - The type of the pixels in the file, float32 or float64
+///
+/// # Returns
+///
+/// * `Result<ArrayD<T>, FitsRotateError>` - The image, converted to `T`
+///
+#[cfg(target_os = "linux")]
+pub fn read_data_unit<T: FitsPixel>(
+    filename: &str,
+    data_start: u64,
+    shape: &[usize],
+    image_type: ImageType,
+) -> Result<ArrayD<T>, FitsRotateError> {
+    let pixel_size = match image_type {
+        ImageType::Float => 4,
+        ImageType::Double => 8,
+        _ => {
+            return Err(FitsRotateError::Message(format!(
+                "Cannot read the {:?} pixels of {} through a memory map",
+                image_type, filename
+            )))
+        }
+    };
+    let n_pixels: usize = shape.iter().product();
+    let n_bytes = (n_pixels * pixel_size) as u64;
+    let mut data: Vec<T> = vec![T::from(0.0); n_pixels];
+    if n_pixels > 0 {
+        let file = std::fs::File::open(filename)?;
+        let before = file.metadata()?;
+        if before.len() < data_start + n_bytes {
+            return Err(FitsRotateError::Message(format!(
+                "{} is truncated: its data unit needs {} bytes but the file has {}",
+                filename,
+                data_start + n_bytes,
+                before.len()
+            )));
+        }
+        let mapping = Mapping::new(&file, data_start..data_start + n_bytes, false)?;
+        let bytes = &mapping.bytes()[..n_bytes as usize];
+        let n_threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let chunk_length = n_pixels.div_ceil(n_threads);
+        std::thread::scope(|scope| {
+            for (values, raw) in data
+                .chunks_mut(chunk_length)
+                .zip(bytes.chunks(chunk_length * pixel_size))
+            {
+                scope.spawn(move || match image_type {
+                    ImageType::Float => decode(raw, values, |b| f32::from_be_bytes([b[0], b[1], b[2], b[3]]), T::from),
+                    _ => decode(
+                        raw,
+                        values,
+                        |b| f64::from_be_bytes([b[0], b[1], b[2], b[3], b[4], b[5], b[6], b[7]]),
+                        T::from_f64,
+                    ),
+                });
+            }
+        });
+        drop(mapping);
+        let after = file.metadata()?;
+        if after.len() != before.len() || after.modified().ok() != before.modified().ok() {
+            return Err(FitsRotateError::Message(format!(
+                "{} was modified while it was read, so its data may be inconsistent",
+                filename
+            )));
+        }
+    }
+    ArrayD::from_shape_vec(IxDyn(shape), data).map_err(|e| FitsRotateError::Message(e.to_string()))
+}
+
+/// Read the data unit of a FITS image through a memory map, bypassing CFITSIO
+///
+/// Memory-mapped reads are only supported on Linux, so this always fails
+/// elsewhere; `raw_readable` is false there, so it is never called.
+#[cfg(not(target_os = "linux"))]
+pub fn read_data_unit<T: FitsPixel>(
+    filename: &str,
+    _data_start: u64,
+    _shape: &[usize],
+    _image_type: ImageType,
+) -> Result<ArrayD<T>, FitsRotateError> {
+    Err(FitsRotateError::Message(format!(
+        "Cannot read {} through a memory map on this platform",
+        filename
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::header::data_extent;
+    use fitsio::images::ImageDescription;
+    use fitsio::FitsFile;
+    use std::path::PathBuf;
+
+    /// A FITS file with an empty image of the shape and pixel type of the cube, and the byte range of its data unit
+    fn empty_image<T: FitsPixel>(name: &str, shape: &[usize]) -> (PathBuf, Range<u64>) {
+        let path = std::env::temp_dir().join(format!("fitsrotate_rs.rawio.{}.{}.fits", std::process::id(), name));
+        let _ = std::fs::remove_file(&path);
+        let description = ImageDescription {
+            data_type: T::IMAGE_TYPE,
+            dimensions: shape,
+        };
+        let mut fits_file = FitsFile::create(&path)
+            .with_custom_primary(&description)
+            .open()
+            .unwrap();
+        let extent = data_extent(&mut fits_file, 0).unwrap();
+        (path, extent)
+    }
+
+    /// Write a cube through CFITSIO and through a memory map, and return the two files
+    fn written_both_ways<T: FitsPixel>(name: &str, cube: ArrayViewD<T>) -> (Vec<u8>, Vec<u8>) {
+        let (by_cfitsio, _) = empty_image::<T>(&format!("{}.cfitsio", name), cube.shape());
+        let mut fits_file = FitsFile::edit(&by_cfitsio).unwrap();
+        let hdu = fits_file.primary_hdu().unwrap();
+        let values: Vec<T> = cube.iter().copied().collect();
+        hdu.write_image(&mut fits_file, &values).unwrap();
+        drop(fits_file);
+
+        let (by_mmap, extent) = empty_image::<T>(&format!("{}.mmap", name), cube.shape());
+        write_data_unit(by_mmap.to_str().unwrap(), extent, cube).unwrap();
+        let bytes = (std::fs::read(&by_cfitsio).unwrap(), std::fs::read(&by_mmap).unwrap());
+        for path in [by_cfitsio, by_mmap] {
+            std::fs::remove_file(path).unwrap();
+        }
+        bytes
+    }
+
+    #[test]
+    fn only_plain_float_outputs_are_written_through_a_memory_map() {
+        assert_eq!(raw_writable::<f32>("cube.rot.fits"), cfg!(target_os = "linux"));
+        assert_eq!(raw_writable::<f64>("/data/cube.rot.fits"), cfg!(target_os = "linux"));
+        for name in [
+            "-",
+            "!cube.rot.fits",
+            "cube.rot.fits[compress]",
+            "mem://cube.fits",
+            "cube.rot.fits.gz",
+            "cube.rot.fits.fz",
+        ] {
+            assert!(!raw_writable::<f32>(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn a_permuted_view_is_written_as_cfitsio_writes_it() {
+        // 3 x 5 x 7 float32 values leave most of the only data block as padding
+        let cube = ArrayD::from_shape_fn(IxDyn(&[3, 5, 7]), |idx| {
+            (idx[0] * 100 + idx[1] * 10 + idx[2]) as f32 - 0.5
+        });
+        let permuted = cube.view().permuted_axes(IxDyn(&[2, 0, 1]));
+        let (by_cfitsio, by_mmap) = written_both_ways("permuted_f32", permuted.view());
+        assert_eq!(by_mmap.len() % 2880, 0);
+        assert!(by_mmap == by_cfitsio);
+
+        // Enough float64 values for several slabs and blocks, reversed along one axis
+        let cube = ArrayD::from_shape_fn(IxDyn(&[64, 9, 11]), |idx| {
+            (idx[0] * 99 + idx[1] * 11 + idx[2]) as f64 / 7.0
+        });
+        let mut flipped = cube.view().permuted_axes(IxDyn(&[1, 0, 2]));
+        flipped.invert_axis(Axis(1));
+        let (by_cfitsio, by_mmap) = written_both_ways("flipped_f64", flipped);
+        assert!(by_mmap == by_cfitsio);
+    }
+
+    #[test]
+    fn a_data_unit_too_small_for_the_cube_is_refused() {
+        let (path, extent) = empty_image::<f32>("too_small", &[2, 2]);
+        let cube = ArrayD::<f32>::zeros(IxDyn(&[40, 40]));
+        let error = write_data_unit(path.to_str().unwrap(), extent.start..extent.start + 16, cube.view());
+        assert!(error.is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Time writing a 256 MB float32 cube, rotated, through CFITSIO and through a memory map
+    ///
+    /// Run with `cargo test --release -- --ignored --nocapture write_256mb`.
+    #[test]
+    #[ignore]
+    fn write_256mb_cube_through_cfitsio_and_a_memory_map() {
+        let shape = [64, 1024, 1024];
+        let cube = ArrayD::from_shape_fn(IxDyn(&shape), |idx| (idx[0] + idx[1] + idx[2]) as f32);
+        let rotated = cube.view().permuted_axes(IxDyn(&[2, 0, 1]));
+
+        let (by_cfitsio, _) = empty_image::<f32>("timing_cfitsio", rotated.shape());
+        let start = std::time::Instant::now();
+        let mut fits_file = FitsFile::edit(&by_cfitsio).unwrap();
+        let hdu = fits_file.primary_hdu().unwrap();
+        let values = rotated.as_standard_layout().into_owned().into_raw_vec_and_offset().0;
+        hdu.write_image(&mut fits_file, &values).unwrap();
+        drop(fits_file);
+        let cfitsio_time = start.elapsed();
+
+        let (by_mmap, extent) = empty_image::<f32>("timing_mmap", rotated.shape());
+        let start = std::time::Instant::now();
+        write_data_unit(by_mmap.to_str().unwrap(), extent, rotated.view()).unwrap();
+        let mmap_time = start.elapsed();
+
+        assert!(std::fs::read(&by_cfitsio).unwrap() == std::fs::read(&by_mmap).unwrap());
+        println!(
+            "256 MB rotated cube: {:.3?} through CFITSIO, {:.3?} through a memory map ({:.1}x)",
+            cfitsio_time,
+            mmap_time,
+            cfitsio_time.as_secs_f64() / mmap_time.as_secs_f64()
+        );
+        for path in [by_cfitsio, by_mmap] {
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    /// A file of `offset` bytes of junk followed by the big-endian bytes of the values
+    fn raw_file(name: &str, offset: usize, values: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("fitsrotate_rs.rawio.{}.{}.raw", std::process::id(), name));
+        let mut bytes = vec![0xA5u8; offset];
+        bytes.extend_from_slice(values);
+        std::fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    #[test]
+    fn float32_and_float64_data_units_are_read_at_any_offset_as_either_type() {
+        let shape = [3, 5, 7];
+        let values: Vec<f64> = (0..105).map(|v| v as f64 / 8.0 - 3.0).collect();
+        let be32: Vec<u8> = values.iter().flat_map(|v| (*v as f32).to_be_bytes()).collect();
+        let be64: Vec<u8> = values.iter().flat_map(|v| v.to_be_bytes()).collect();
+        // Offsets that are, and are not, aligned for the pixel type or on a page
+        for offset in [0, 1, 2880, 4099] {
+            let path = raw_file(&format!("f32_{}", offset), offset, &be32);
+            let name = path.to_str().unwrap();
+            let as_f32 = read_data_unit::<f32>(name, offset as u64, &shape, ImageType::Float).unwrap();
+            let as_f64 = read_data_unit::<f64>(name, offset as u64, &shape, ImageType::Float).unwrap();
+            assert_eq!(as_f32.shape(), shape);
+            assert_eq!(as_f32.iter().map(|v| *v as f64).collect::<Vec<_>>(), values);
+            assert_eq!(as_f64.iter().copied().collect::<Vec<_>>(), values);
+            std::fs::remove_file(path).unwrap();
+
+            let path = raw_file(&format!("f64_{}", offset), offset, &be64);
+            let name = path.to_str().unwrap();
+            let as_f64 = read_data_unit::<f64>(name, offset as u64, &shape, ImageType::Double).unwrap();
+            let as_f32 = read_data_unit::<f32>(name, offset as u64, &shape, ImageType::Double).unwrap();
+            assert_eq!(as_f64.iter().copied().collect::<Vec<_>>(), values);
+            assert_eq!(as_f32.iter().map(|v| *v as f64).collect::<Vec<_>>(), values);
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn truncated_and_integer_data_units_are_refused() {
+        let be32: Vec<u8> = (0..20).flat_map(|v| (v as f32).to_be_bytes()).collect();
+        let path = raw_file("truncated", 16, &be32[..be32.len() - 1]);
+        let name = path.to_str().unwrap();
+        let error = read_data_unit::<f32>(name, 16, &[4, 5], ImageType::Float).unwrap_err();
+        assert!(error.to_string().contains("is truncated"), "{}", error);
+        assert!(read_data_unit::<f32>(name, 16, &[4, 4], ImageType::Short).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn only_plain_unscaled_float_inputs_are_read_through_a_memory_map() {
+        let path = std::env::temp_dir().join(format!("fitsrotate_rs.rawio.{}.readable.fits", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let description = ImageDescription {
+            data_type: ImageType::Float,
+            dimensions: &[2, 2],
+        };
+        FitsFile::create(&path)
+            .with_custom_primary(&description)
+            .open()
+            .unwrap();
+        let name = path.to_str().unwrap();
+        let linux = cfg!(target_os = "linux");
+        assert_eq!(raw_readable(name, ImageType::Float, false), linux);
+        assert_eq!(raw_readable(name, ImageType::Double, false), linux);
+        assert!(!raw_readable(name, ImageType::Float, true));
+        assert!(!raw_readable(name, ImageType::Short, false));
+        assert!(!raw_readable(name, ImageType::Long, false));
+        std::fs::remove_file(&path).unwrap();
+        // A file that is not plain FITS (e.g. gzip) cannot be mapped
+        std::fs::write(&path, [0x1f, 0x8b, 0x08, 0, 0, 0, 0, 0, 0, 0]).unwrap();
+        assert!(!raw_readable(name, ImageType::Float, false));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    /// Time reading a 2 GB float32 cube through CFITSIO and through a memory map
+    ///
+    /// Run with `cargo test --release -- --ignored --nocapture read_2gb`.
+    #[test]
+    #[ignore]
+    fn read_2gb_cube_through_cfitsio_and_a_memory_map() {
+        let shape = [128, 2048, 2048];
+        let path = std::env::temp_dir().join(format!("fitsrotate_rs.rawio.{}.timing_read.fits", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let description = ImageDescription {
+            data_type: ImageType::Float,
+            dimensions: &shape,
+        };
+        let mut fits_file = FitsFile::create(&path)
+            .with_custom_primary(&description)
+            .open()
+            .unwrap();
+        let extent = data_extent(&mut fits_file, 0).unwrap();
+        drop(fits_file);
+        let plane = ArrayD::from_shape_fn(IxDyn(&shape[1..]), |idx| (idx[0] + idx[1]) as f32);
+        let cube = ndarray::stack(Axis(0), &vec![plane.view(); shape[0]]).unwrap();
+        write_data_unit(path.to_str().unwrap(), extent.clone(), cube.view()).unwrap();
+        drop(cube);
+
+        let start = std::time::Instant::now();
+        let mut fits_file = FitsFile::open(&path).unwrap();
+        let hdu = fits_file.primary_hdu().unwrap();
+        let by_cfitsio: ArrayD<f32> = hdu.read_image(&mut fits_file).unwrap();
+        let cfitsio_time = start.elapsed();
+        drop(fits_file);
+        // Only one copy of the cube is held at a time
+        let cfitsio_sum: f64 = by_cfitsio.iter().map(|v| *v as f64).sum();
+        drop(by_cfitsio);
+
+        let start = std::time::Instant::now();
+        let by_mmap = read_data_unit::<f32>(path.to_str().unwrap(), extent.start, &shape, ImageType::Float).unwrap();
+        let mmap_time = start.elapsed();
+
+        assert_eq!(by_mmap.iter().map(|v| *v as f64).sum::<f64>(), cfitsio_sum);
+        println!(
+            "2 GB cube: {:.3?} through CFITSIO, {:.3?} through a memory map ({:.1}x)",
+            cfitsio_time,
+            mmap_time,
+            cfitsio_time.as_secs_f64() / mmap_time.as_secs_f64()
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
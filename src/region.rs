@@ -0,0 +1,509 @@
+use crate::axes::{axis_role, read_ctypes, AxisRole};
+use crate::error::FitsRotateError;
+use fitsio::FitsFile;
+use ndarray::{ArrayD, IxDyn};
+
+/// The coordinate system of a region
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RegionSystem {
+    /// (1-based) FITS pixels
+    Image,
+    /// Equatorial (RA, DEC) in degrees
+    Fk5,
+}
+
+/// A region shape, in the coordinates of its system
+///
+/// Positions are (x, y) in pixels or (RA, DEC) in degrees, and sizes are
+/// in pixels or degrees.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Shape {
+    Circle { centre: (f64, f64), radius: f64 },
+    Box { centre: (f64, f64), size: (f64, f64), angle: f64 },
+    Polygon { vertices: Vec<(f64, f64)> },
+    /// An axis-aligned ellipse, for sky circles on grids with rectangular pixels
+    Ellipse { centre: (f64, f64), radii: (f64, f64) },
+}
+
+/// A shape of a region file
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionShape {
+    pub shape: Shape,
+    pub system: RegionSystem,
+    /// Excluded (`-` prefixed) shapes cut holes out of the region
+    pub exclude: bool,
+    /// The line of the region file the shape is on
+    pub line: usize,
+}
+
+/// Parse a DS9-style region file
+///
+/// Circles, boxes and polygons are supported, in `image` or `fk5` (also
+/// `icrs` and `j2000`) coordinates. Sky positions can be given in degrees
+/// or sexagesimal (`hh:mm:ss`, `dd:mm:ss`), and sky sizes in degrees or
+/// with a `"`, `'`, `d` or `r` unit.
+///
+/// # Arguments
+///
+/// * `text` - The contents of the region file
+///
+/// # Returns
+///
+/// * `Result<Vec<RegionShape>, FitsRotateError>` - The shapes
+///
+pub fn parse_regions(text: &str) -> Result<Vec<RegionShape>, FitsRotateError> {
+    let mut shapes: Vec<RegionShape> = Vec::new();
+    let mut system: Option<RegionSystem> = None;
+    for (line_idx, line) in text.lines().enumerate() {
+        let line_number = line_idx + 1;
+        // Anything after a # is a comment or shape properties
+        let line = line.split('#').next().unwrap_or("");
+        for item in line.split(';') {
+            let item = item.trim();
+            if item.is_empty() || item.starts_with("global") {
+                continue;
+            }
+            match item.to_lowercase().as_str() {
+                "image" => {
+                    system = Some(RegionSystem::Image);
+                    continue;
+                }
+                "fk5" | "icrs" | "j2000" => {
+                    system = Some(RegionSystem::Fk5);
+                    continue;
+                }
+                _ => {}
+            }
+            let error = |message: String| {
+                FitsRotateError::Message(format!("Region file line {}: {}", line_number, message))
+            };
+            let (exclude, item) = match item.strip_prefix('-') {
+                Some(item) => (true, item.trim()),
+                None => (false, item.strip_prefix('+').unwrap_or(item).trim()),
+            };
+            let (name, args) = match (item.find('('), item.rfind(')')) {
+                (Some(open), Some(close)) if open < close => (item[..open].trim(), &item[open + 1..close]),
+                _ => return Err(error(format!("cannot parse `{}`", item))),
+            };
+            let system = system.ok_or_else(|| {
+                error("no coordinate system (image or fk5) is given before the first shape".to_string())
+            })?;
+            let args: Vec<&str> = args
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|arg| !arg.is_empty())
+                .collect();
+            let position = |idx: usize| -> Result<(f64, f64), FitsRotateError> {
+                match (args.get(idx), args.get(idx + 1)) {
+                    (Some(x), Some(y)) => Ok((
+                        parse_coordinate(x, system, true).map_err(&error)?,
+                        parse_coordinate(y, system, false).map_err(&error)?,
+                    )),
+                    _ => Err(error(format!("{} needs more arguments", name))),
+                }
+            };
+            let size = |idx: usize| -> Result<f64, FitsRotateError> {
+                let arg = args
+                    .get(idx)
+                    .ok_or_else(|| error(format!("{} needs more arguments", name)))?;
+                parse_size(arg, system).map_err(&error)
+            };
+            let shape = match name.to_lowercase().as_str() {
+                "circle" => Shape::Circle { centre: position(0)?, radius: size(2)? },
+                "box" => Shape::Box {
+                    centre: position(0)?,
+                    size: (size(2)?, size(3)?),
+                    angle: match args.get(4) {
+                        Some(angle) => angle
+                            .parse()
+                            .map_err(|_| error(format!("{} is not an angle", angle)))?,
+                        None => 0.0,
+                    },
+                },
+                "polygon" => {
+                    if args.len() < 6 || !args.len().is_multiple_of(2) {
+                        return Err(error("polygon needs at least 3 vertices".to_string()));
+                    }
+                    let vertices: Result<Vec<(f64, f64)>, FitsRotateError> =
+                        (0..args.len()).step_by(2).map(position).collect();
+                    Shape::Polygon { vertices: vertices? }
+                }
+                _ => {
+                    return Err(error(format!(
+                        "unsupported region type `{}` (supported: circle, box, polygon)",
+                        name
+                    )))
+                }
+            };
+            shapes.push(RegionShape { shape, system, exclude, line: line_number });
+        }
+    }
+    Ok(shapes)
+}
+
+/// Parse a position coordinate
+///
+/// # Arguments
+///
+/// * `value` - The coordinate
+/// * `system` - The coordinate system
+/// * `is_ra` - The coordinate is the first (x or RA) of a position
+///
+/// # Returns
+///
+/// * `Result<f64, String>` - The coordinate in pixels or degrees
+///
+fn parse_coordinate(value: &str, system: RegionSystem, is_ra: bool) -> Result<f64, String> {
+    let invalid = || format!("{} is not a valid coordinate", value);
+    if system == RegionSystem::Fk5 && value.contains(':') {
+        let negative = value.starts_with('-');
+        let parts: Result<Vec<f64>, _> = value
+            .trim_start_matches(['-', '+'])
+            .split(':')
+            .map(|part| part.parse::<f64>())
+            .collect();
+        let parts = parts.map_err(|_| invalid())?;
+        let mut degrees = 0.0;
+        for (part, scale) in parts.iter().zip([1.0, 60.0, 3600.0]) {
+            degrees += part / scale;
+        }
+        // Right ascensions are in hours
+        if is_ra {
+            degrees *= 15.0;
+        }
+        return Ok(if negative { -degrees } else { degrees });
+    }
+    value.trim_end_matches('d').parse().map_err(|_| invalid())
+}
+
+/// Parse a size (radius or box side)
+///
+/// # Arguments
+///
+/// * `value` - The size, with an optional unit
+/// * `system` - The coordinate system
+///
+/// # Returns
+///
+/// * `Result<f64, String>` - The size in pixels or degrees
+///
+fn parse_size(value: &str, system: RegionSystem) -> Result<f64, String> {
+    let (number, scale) = match value.chars().last() {
+        Some('"') => (&value[..value.len() - 1], 1.0 / 3600.0),
+        Some('\'') => (&value[..value.len() - 1], 1.0 / 60.0),
+        Some('d') => (&value[..value.len() - 1], 1.0),
+        Some('r') => (&value[..value.len() - 1], 180.0 / std::f64::consts::PI),
+        _ => (value, 1.0),
+    };
+    if system == RegionSystem::Image && number.len() != value.len() {
+        return Err(format!("{} has an angular unit, but the region is in image coordinates", value));
+    }
+    number
+        .parse::<f64>()
+        .map(|size| size * scale)
+        .map_err(|_| format!("{} is not a valid size", value))
+}
+
+/// The celestial projection of the spatial axes of a cube
+///
+/// Only the CRVAL, CRPIX and CDELT (or the diagonal of CD) of each axis
+/// are used: rotation terms are ignored. TAN and SIN projections are
+/// supported, and anything else is treated as a plate carrée (CAR).
+#[derive(Clone, Debug)]
+pub struct SkyProjection {
+    crval: (f64, f64),
+    crpix: (f64, f64),
+    cdelt: (f64, f64),
+    projection: String,
+}
+
+impl SkyProjection {
+    /// Convert a sky position to (1-based) pixel coordinates
+    ///
+    /// # Arguments
+    ///
+    /// * `ra` - The RA in degrees
+    /// * `dec` - The DEC in degrees
+    ///
+    /// # Returns
+    ///
+    /// * `Option<(f64, f64)>` - The pixel position, or `None` if it is not on the projection
+    ///
+    pub fn sky_to_pixel(&self, ra: f64, dec: f64) -> Option<(f64, f64)> {
+        let (ra0, dec0) = (self.crval.0.to_radians(), self.crval.1.to_radians());
+        let (ra, dec) = (ra.to_radians(), dec.to_radians());
+        let dra = ra - ra0;
+        let cos_c = dec0.sin() * dec.sin() + dec0.cos() * dec.cos() * dra.cos();
+        let xi = dec.cos() * dra.sin();
+        let eta = dec0.cos() * dec.sin() - dec0.sin() * dec.cos() * dra.cos();
+        let (x, y) = match self.projection.as_str() {
+            "TAN" if cos_c > 0.0 => (xi / cos_c, eta / cos_c),
+            "SIN" if cos_c >= 0.0 => (xi, eta),
+            "TAN" | "SIN" => return None,
+            _ => {
+                let dra = (dra + std::f64::consts::PI).rem_euclid(2.0 * std::f64::consts::PI) - std::f64::consts::PI;
+                (dra, dec - dec0)
+            }
+        };
+        Some((
+            self.crpix.0 + x.to_degrees() / self.cdelt.0,
+            self.crpix.1 + y.to_degrees() / self.cdelt.1,
+        ))
+    }
+
+    /// Convert a shape in sky coordinates to pixel coordinates
+    ///
+    /// Sizes are scaled by the pixel size of each axis, so a circle on a
+    /// grid with rectangular pixels becomes an ellipse.
+    fn shape_to_pixels(&self, shape: &Shape, line: usize) -> Result<Shape, FitsRotateError> {
+        let to_pixel = |(ra, dec): (f64, f64)| {
+            self.sky_to_pixel(ra, dec).ok_or_else(|| {
+                FitsRotateError::Message(format!(
+                    "Region file line {}: ({}, {}) is not on the projection of the image",
+                    line, ra, dec
+                ))
+            })
+        };
+        let (sx, sy) = (self.cdelt.0.abs(), self.cdelt.1.abs());
+        Ok(match shape {
+            Shape::Circle { centre, radius } if sx == sy => {
+                Shape::Circle { centre: to_pixel(*centre)?, radius: radius / sx }
+            }
+            Shape::Circle { centre, radius } => Shape::Ellipse {
+                centre: to_pixel(*centre)?,
+                radii: (radius / sx, radius / sy),
+            },
+            Shape::Box { centre, size, angle } => Shape::Box {
+                centre: to_pixel(*centre)?,
+                size: (size.0 / sx, size.1 / sy),
+                angle: *angle,
+            },
+            Shape::Polygon { vertices } => Shape::Polygon {
+                vertices: vertices.iter().map(|vertex| to_pixel(*vertex)).collect::<Result<_, _>>()?,
+            },
+            Shape::Ellipse { .. } => shape.clone(),
+        })
+    }
+}
+
+/// Check if a (1-based) pixel position is inside a shape in pixel coordinates
+fn contains(shape: &Shape, x: f64, y: f64) -> bool {
+    match shape {
+        Shape::Circle { centre, radius } => (x - centre.0).powi(2) + (y - centre.1).powi(2) <= radius.powi(2),
+        Shape::Ellipse { centre, radii } => ((x - centre.0) / radii.0).powi(2) + ((y - centre.1) / radii.1).powi(2) <= 1.0,
+        Shape::Box { centre, size, angle } => {
+            let (sin, cos) = angle.to_radians().sin_cos();
+            let (dx, dy) = (x - centre.0, y - centre.1);
+            let (u, v) = (dx * cos + dy * sin, -dx * sin + dy * cos);
+            u.abs() <= size.0 / 2.0 && v.abs() <= size.1 / 2.0
+        }
+        Shape::Polygon { vertices } => {
+            // Even-odd rule: count the edges crossed by a ray towards +x
+            let mut inside = false;
+            let mut previous = vertices[vertices.len() - 1];
+            for vertex in vertices {
+                if (vertex.1 > y) != (previous.1 > y)
+                    && x < (previous.0 - vertex.0) * (y - vertex.1) / (previous.1 - vertex.1) + vertex.0
+                {
+                    inside = !inside;
+                }
+                previous = *vertex;
+            }
+            inside
+        }
+    }
+}
+
+/// Read the projection of the spatial axes of a cube
+///
+/// # Arguments
+///
+/// * `fits_file` - The FITS file
+/// * `axes` - The (FITS, 1-based) RA and DEC axes
+///
+/// # Returns
+///
+/// * `Result<SkyProjection, FitsRotateError>` - The projection
+///
+fn read_projection(fits_file: &mut FitsFile, axes: (usize, usize)) -> Result<SkyProjection, FitsRotateError> {
+    let hdu = fits_file.primary_hdu()?;
+    let mut read_cdelt = |axis: usize| -> Result<f64, FitsRotateError> {
+        let cdelt: f64 = hdu
+            .read_key(fits_file, &format!("CDELT{}", axis))
+            .or_else(|_| hdu.read_key(fits_file, &format!("CD{}_{}", axis, axis)))?;
+        if cdelt == 0.0 {
+            return Err(FitsRotateError::Message(format!("The pixel size of axis {} is zero", axis)));
+        }
+        Ok(cdelt)
+    };
+    let cdelt = (read_cdelt(axes.0)?, read_cdelt(axes.1)?);
+    let crval = (
+        hdu.read_key::<f64>(fits_file, &format!("CRVAL{}", axes.0))?,
+        hdu.read_key::<f64>(fits_file, &format!("CRVAL{}", axes.1))?,
+    );
+    let crpix = (
+        hdu.read_key::<f64>(fits_file, &format!("CRPIX{}", axes.0))?,
+        hdu.read_key::<f64>(fits_file, &format!("CRPIX{}", axes.1))?,
+    );
+    let ctype: String = hdu.read_key(fits_file, &format!("CTYPE{}", axes.0))?;
+    let projection = ctype.trim().rsplit('-').next().unwrap_or("").to_uppercase();
+    Ok(SkyProjection { crval, crpix, cdelt, projection })
+}
+
+/// Rasterise a region against the spatial axes of a cube
+///
+/// The spatial axes are the RA and DEC axes, or the first two axes if
+/// there are none. The mask has length 1 along every other axis, so that
+/// it applies to every plane of the cube.
+///
+/// # Arguments
+///
+/// * `shapes` - The region
+/// * `fits_file` - The FITS file, for its WCS
+/// * `shape` - The shape of the cube, in array order
+/// * `outside` - Select the pixels outside the region rather than inside
+///
+/// # Returns
+///
+/// * `Result<ArrayD<bool>, FitsRotateError>` - The mask, `false` for pixels to blank
+///
+pub fn region_mask(
+    shapes: &[RegionShape],
+    fits_file: &mut FitsFile,
+    shape: &[usize],
+    outside: bool,
+) -> Result<ArrayD<bool>, FitsRotateError> {
+    let naxis = shape.len();
+    if naxis < 2 {
+        return Err(FitsRotateError::Message("Regions need a cube with at least 2 axes".to_string()));
+    }
+    let roles: Vec<Option<AxisRole>> = read_ctypes(fits_file)?.iter().map(|c| axis_role(c)).collect();
+    let sky_axes = (
+        roles.iter().position(|role| *role == Some(AxisRole::Ra)),
+        roles.iter().position(|role| *role == Some(AxisRole::Dec)),
+    );
+    let axes = match sky_axes {
+        (Some(ra), Some(dec)) => (ra + 1, dec + 1),
+        _ => (1, 2),
+    };
+    let projection = if shapes.iter().any(|shape| shape.system == RegionSystem::Fk5) {
+        match sky_axes {
+            (Some(_), Some(_)) => Some(read_projection(fits_file, axes)?),
+            _ => {
+                return Err(FitsRotateError::Message(
+                    "The region is in sky coordinates, but the cube has no RA and DEC axes".to_string(),
+                ))
+            }
+        }
+    } else {
+        None
+    };
+    let mut pixel_shapes: Vec<(Shape, bool)> = Vec::with_capacity(shapes.len());
+    for region_shape in shapes {
+        let shape = match (&projection, region_shape.system) {
+            (Some(projection), RegionSystem::Fk5) => projection.shape_to_pixels(&region_shape.shape, region_shape.line)?,
+            _ => region_shape.shape.clone(),
+        };
+        pixel_shapes.push((shape, region_shape.exclude));
+    }
+
+    let (x_idx, y_idx) = (naxis - axes.0, naxis - axes.1);
+    let mut mask_shape = vec![1; naxis];
+    mask_shape[x_idx] = shape[x_idx];
+    mask_shape[y_idx] = shape[y_idx];
+    let any_include = pixel_shapes.iter().any(|(_, exclude)| !exclude);
+    let mask = ArrayD::from_shape_fn(IxDyn(&mask_shape), |idx| {
+        let (x, y) = (idx[x_idx] as f64 + 1.0, idx[y_idx] as f64 + 1.0);
+        // A region of only excluded shapes covers everything else
+        let included = !any_include || pixel_shapes.iter().any(|(shape, exclude)| !exclude && contains(shape, x, y));
+        let excluded = pixel_shapes.iter().any(|(shape, exclude)| *exclude && contains(shape, x, y));
+        let in_region = included && !excluded;
+        // Keep the pixels that are not selected for blanking
+        in_region == outside
+    });
+    Ok(mask)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The number of pixels of an n x n grid inside a shape in pixel coordinates
+    fn count_inside(shape: &Shape, n: usize) -> usize {
+        (1..n + 1)
+            .flat_map(|x| (1..n + 1).map(move |y| (x as f64, y as f64)))
+            .filter(|(x, y)| contains(shape, *x, *y))
+            .count()
+    }
+
+    #[test]
+    fn circles_rasterise_to_the_lattice_points_within_the_radius() {
+        // Gauss's circle problem: 29 lattice points within a radius of 3, 13 within 2
+        let circle = Shape::Circle { centre: (6.0, 6.0), radius: 3.0 };
+        assert_eq!(count_inside(&circle, 11), 29);
+        let circle = Shape::Circle { centre: (6.0, 6.0), radius: 2.0 };
+        assert_eq!(count_inside(&circle, 11), 13);
+        // Cut by the edge of the grid: the points with x >= 1 of a circle centred on x = 1
+        let circle = Shape::Circle { centre: (1.0, 6.0), radius: 3.0 };
+        assert_eq!(count_inside(&circle, 11), 18);
+    }
+
+    #[test]
+    fn boxes_and_polygons_rasterise() {
+        let square = Shape::Box { centre: (6.0, 6.0), size: (4.0, 2.0), angle: 0.0 };
+        assert_eq!(count_inside(&square, 11), 5 * 3);
+        // Turned by 90 degrees, the long side runs along y
+        let turned = Shape::Box { centre: (6.0, 6.0), size: (4.0, 2.0), angle: 90.0 };
+        assert!(contains(&turned, 6.0, 8.0));
+        assert!(!contains(&turned, 8.0, 6.0));
+        let triangle = Shape::Polygon { vertices: vec![(0.5, 0.5), (4.5, 0.5), (0.5, 4.5)] };
+        // x + y < 5 for x, y >= 1
+        assert_eq!(count_inside(&triangle, 11), 6);
+    }
+
+    #[test]
+    fn region_files_are_parsed() {
+        let text = "# Region file format: DS9\nglobal color=green\nimage\ncircle(6,6,3)\n-box(6,6,2,2,0) # hole\n";
+        let shapes = parse_regions(text).unwrap();
+        assert_eq!(shapes.len(), 2);
+        assert_eq!(shapes[0].shape, Shape::Circle { centre: (6.0, 6.0), radius: 3.0 });
+        assert_eq!((shapes[0].system, shapes[0].exclude, shapes[0].line), (RegionSystem::Image, false, 4));
+        assert!(shapes[1].exclude);
+
+        let shapes = parse_regions("fk5; circle(12:00:00, -30:30:00, 36\")").unwrap();
+        match shapes[0].shape {
+            Shape::Circle { centre, radius } => {
+                assert!((centre.0 - 180.0).abs() < 1e-9 && (centre.1 + 30.5).abs() < 1e-9);
+                assert!((radius - 0.01).abs() < 1e-12);
+            }
+            ref other => panic!("expected a circle, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unsupported_regions_give_their_line() {
+        let error = parse_regions("image\ncircle(1,1,1)\nannulus(5,5,1,2)\n").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Region file line 3: unsupported region type `annulus` (supported: circle, box, polygon)"
+        );
+        let error = parse_regions("circle(1,1,1)").unwrap_err();
+        assert!(error.to_string().starts_with("Region file line 1: no coordinate system"));
+    }
+
+    #[test]
+    fn sky_circles_are_scaled_by_the_pixel_size() {
+        let projection = SkyProjection {
+            crval: (180.0, -30.0),
+            crpix: (6.0, 6.0),
+            cdelt: (-0.001, 0.002),
+            projection: "SIN".to_string(),
+        };
+        let (x, y) = projection.sky_to_pixel(180.0, -30.0).unwrap();
+        assert!((x - 6.0).abs() < 1e-9 && (y - 6.0).abs() < 1e-9);
+        let circle = Shape::Circle { centre: (180.0, -30.0), radius: 0.004 };
+        match projection.shape_to_pixels(&circle, 1).unwrap() {
+            Shape::Ellipse { radii, .. } => assert_eq!(radii, (4.0, 2.0)),
+            other => panic!("expected an ellipse, got {:?}", other),
+        }
+    }
+}
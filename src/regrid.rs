@@ -0,0 +1,446 @@
+use crate::axes::{axis_role, read_ctypes, AxisRole};
+use crate::error::FitsRotateError;
+use crate::permutation::fits_index_to_array_index;
+use crate::FitsPixel;
+use fitsio::FitsFile;
+use ndarray::{ArrayD, Axis};
+
+/// The units accepted by `--spectral-regrid`, with their size in, and the name of, the SI unit
+pub const SPECTRAL_UNITS: [(&str, f64, &str); 6] = [
+    ("Hz", 1.0, "Hz"),
+    ("kHz", 1e3, "Hz"),
+    ("MHz", 1e6, "Hz"),
+    ("GHz", 1e9, "Hz"),
+    ("m/s", 1.0, "m/s"),
+    ("km/s", 1e3, "m/s"),
+];
+
+/// Positions within this many pixels of the first or last channel are not extrapolations
+const EDGE_TOLERANCE: f64 = 1e-9;
+
+/// The size of a unit in, and the name of, its SI unit
+fn unit_scale(unit: &str) -> Option<(f64, &'static str)> {
+    SPECTRAL_UNITS
+        .iter()
+        .find(|(name, _, _)| *name == unit.trim())
+        .map(|(_, scale, si)| (*scale, *si))
+}
+
+/// Split a quantity such as `1.29GHz` into its value and unit, if it has one
+fn parse_quantity(text: &str) -> Result<(f64, Option<&str>), FitsRotateError> {
+    let text = text.trim();
+    let unit_start = text
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| c.is_ascii_alphabetic() || *c == '/')
+        .last()
+        .map_or(text.len(), |(idx, _)| idx);
+    let (number, unit) = text.split_at(unit_start);
+    let value = number
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| FitsRotateError::Message(format!("Cannot read {} as a number with a unit", text)))?;
+    Ok((value, Some(unit).filter(|unit| !unit.is_empty())))
+}
+
+/// Convert a quantity such as `1.29GHz` to the units of a spectral axis
+///
+/// # Arguments
+///
+/// * `text` - The quantity; a number without a unit is already in the units of the axis
+/// * `cunit` - The CUNIT of the axis; an axis without one is in SI units
+/// * `axis` - The (FITS, 1-based) axis, for error messages
+///
+/// # Returns
+///
+/// * `Result<f64, FitsRotateError>` - The quantity in the units of the axis
+///
+pub fn axis_quantity(text: &str, cunit: &str, axis: usize) -> Result<f64, FitsRotateError> {
+    let (value, unit) = parse_quantity(text)?;
+    let unit = match unit {
+        Some(unit) => unit,
+        None => return Ok(value),
+    };
+    let (scale, si) = unit_scale(unit).ok_or_else(|| {
+        let known: Vec<&str> = SPECTRAL_UNITS.iter().map(|(name, _, _)| *name).collect();
+        FitsRotateError::Message(format!("Unknown unit {} (expected one of {})", unit, known.join(", ")))
+    })?;
+    let (axis_scale, axis_si) = match cunit.trim() {
+        "" => (1.0, si),
+        cunit => unit_scale(cunit).unwrap_or((f64::NAN, "")),
+    };
+    if axis_si != si {
+        return Err(FitsRotateError::Message(format!(
+            "Cannot convert {} to the units of axis {} ({})",
+            unit,
+            axis,
+            cunit.trim()
+        )));
+    }
+    Ok(value * scale / axis_scale)
+}
+
+/// Apply a function to each spectrum of a cube
+///
+/// The cube is laid out with the spectral axis last, so that each
+/// spectrum is contiguous while the function runs, and then put back in
+/// its original axis order.
+///
+/// # Arguments
+///
+/// * `fits_cube` - The cube
+/// * `axis` - The (FITS, 1-based) spectral axis of the cube
+/// * `n_channels` - The number of channels of each new spectrum
+/// * `f` - Fills a new spectrum from an old one
+///
+/// # Returns
+///
+/// * `ArrayD<T>` - The cube of new spectra, in standard layout
+///
+pub(crate) fn map_spectra<T: FitsPixel>(
+    fits_cube: ArrayD<T>,
+    axis: usize,
+    n_channels: usize,
+    mut f: impl FnMut(&[f64], &mut [f64]),
+) -> ArrayD<T> {
+    let naxis = fits_cube.ndim();
+    let array_idx = fits_index_to_array_index(axis, naxis);
+    let mut order: Vec<usize> = (0..naxis).filter(|idx| *idx != array_idx).collect();
+    order.push(array_idx);
+    let spectra = fits_cube.permuted_axes(order.clone()).as_standard_layout().into_owned();
+
+    let mut shape = spectra.shape().to_vec();
+    shape[naxis - 1] = n_channels;
+    let mut mapped = ArrayD::from_elem(shape, T::from_f64(f64::NAN));
+    let mut spectrum: Vec<f64> = Vec::with_capacity(spectra.len_of(Axis(naxis - 1)));
+    let mut new_spectrum = vec![f64::NAN; n_channels];
+    for (lane, mut out) in spectra
+        .lanes(Axis(naxis - 1))
+        .into_iter()
+        .zip(mapped.lanes_mut(Axis(naxis - 1)))
+    {
+        spectrum.clear();
+        spectrum.extend(lane.iter().map(|value| (*value).into()));
+        f(&spectrum, &mut new_spectrum);
+        for (out_value, value) in out.iter_mut().zip(&new_spectrum) {
+            *out_value = T::from_f64(*value);
+        }
+    }
+
+    // Array axis order[j] of the input is axis j of the new cube
+    let mut inverse = vec![0; naxis];
+    for (idx, old_idx) in order.iter().enumerate() {
+        inverse[*old_idx] = idx;
+    }
+    mapped.permuted_axes(inverse).as_standard_layout().into_owned()
+}
+
+/// A linear grid of channels
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpectralGrid {
+    /// The value of the first channel
+    pub start: f64,
+    /// The step between channels
+    pub width: f64,
+    /// The number of channels
+    pub n: usize,
+}
+
+/// A regridding of the spectral axis, chosen with `--spectral-regrid`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpectralRegrid {
+    /// The (FITS, 1-based) spectral axis of the input
+    pub axis: usize,
+    /// The CRVAL of the spectral axis of the input
+    pub crval: f64,
+    /// The CDELT of the spectral axis of the input
+    pub cdelt: f64,
+    /// The CRPIX of the spectral axis of the input
+    pub crpix: f64,
+    /// The new grid, in the units of the spectral axis
+    pub grid: SpectralGrid,
+}
+
+impl SpectralRegrid {
+    /// Work out the regridding of the spectral axis of a FITS file
+    ///
+    /// The grid is given as `start=<value>,width=<value>,n=<channels>`,
+    /// e.g. `start=1.29GHz,width=1MHz,n=256`. Values without a unit are in
+    /// the units of the spectral axis (its `CUNIT`).
+    ///
+    /// # Arguments
+    ///
+    /// * `fits_file` - The FITS file
+    /// * `filename` - The name of the FITS file, for error messages
+    /// * `spec` - The new grid
+    ///
+    /// # Returns
+    ///
+    /// * `Result<SpectralRegrid, FitsRotateError>` - The regridding
+    ///
+    pub fn find(fits_file: &mut FitsFile, filename: &str, spec: &str) -> Result<SpectralRegrid, FitsRotateError> {
+        let ctypes = read_ctypes(fits_file).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+        let axis = ctypes
+            .iter()
+            .position(|ctype| axis_role(ctype) == Some(AxisRole::Spectral))
+            .map(|idx| idx + 1)
+            .ok_or_else(|| {
+                FitsRotateError::Message(format!(
+                    "{} has no spectral axis (CTYPEs: {})",
+                    filename,
+                    ctypes.join(", ")
+                ))
+            })?;
+        let hdu = fits_file.primary_hdu()?;
+        let naxis = ctypes.len();
+        let has_cd = (1..naxis + 1).any(|other| {
+            hdu.read_key::<f64>(fits_file, &format!("CD{}_{}", axis, other)).is_ok()
+                || hdu.read_key::<f64>(fits_file, &format!("CD{}_{}", other, axis)).is_ok()
+        });
+        let pc = hdu
+            .read_key::<f64>(fits_file, &format!("PC{}_{}", axis, axis))
+            .unwrap_or(1.0);
+        if has_cd || pc != 1.0 {
+            return Err(FitsRotateError::Message(format!(
+                "--spectral-regrid needs the spectral axis of {} to be described by CDELT{} alone, without a CD or PC scale",
+                filename, axis
+            )));
+        }
+        let number = |fits_file: &mut FitsFile, stub: &str, default: f64| {
+            hdu.read_key::<f64>(fits_file, &format!("{}{}", stub, axis))
+                .unwrap_or(default)
+        };
+        let crval = number(fits_file, "CRVAL", 0.0);
+        let cdelt = number(fits_file, "CDELT", 1.0);
+        let crpix = number(fits_file, "CRPIX", 1.0);
+        let cunit: String = hdu.read_key(fits_file, &format!("CUNIT{}", axis)).unwrap_or_default();
+
+        let mut start: Option<f64> = None;
+        let mut width: Option<f64> = None;
+        let mut n: Option<usize> = None;
+        for item in spec.split(',') {
+            let (key, value) = item.split_once('=').ok_or_else(|| {
+                FitsRotateError::Message(format!("Expected key=value in --spectral-regrid, got {}", item))
+            })?;
+            let quantity = |text: &str| axis_quantity(text, &cunit, axis);
+            match key.trim() {
+                "start" => start = Some(quantity(value)?),
+                "width" => width = Some(quantity(value)?),
+                "n" => {
+                    n = Some(value.trim().parse().map_err(|_| {
+                        FitsRotateError::Message(format!("Expected a number of channels, got {}", value))
+                    })?)
+                }
+                other => {
+                    return Err(FitsRotateError::Message(format!(
+                        "Unknown key {} in --spectral-regrid (expected start, width and n)",
+                        other
+                    )))
+                }
+            }
+        }
+        let grid = match (start, width, n) {
+            (Some(start), Some(width), Some(n)) if width != 0.0 && n > 0 => SpectralGrid { start, width, n },
+            (Some(_), Some(_), Some(_)) => {
+                return Err(FitsRotateError::Message(
+                    "--spectral-regrid needs a non-zero width and at least one channel".to_string(),
+                ))
+            }
+            _ => {
+                return Err(FitsRotateError::Message(
+                    "--spectral-regrid needs start, width and n, e.g. start=1.29GHz,width=1MHz,n=256".to_string(),
+                ))
+            }
+        };
+        Ok(SpectralRegrid {
+            axis,
+            crval,
+            cdelt,
+            crpix,
+            grid,
+        })
+    }
+
+    /// The (0-based, fractional) input channel of each channel of the new grid
+    ///
+    /// The value of input channel `p` (1-based) is `CRVAL + (p - CRPIX) * CDELT`.
+    pub fn positions(&self) -> Vec<f64> {
+        (0..self.grid.n)
+            .map(|channel| {
+                let value = self.grid.start + channel as f64 * self.grid.width;
+                self.crpix + (value - self.crval) / self.cdelt - 1.0
+            })
+            .collect()
+    }
+
+    /// Regrid the spectral axis of a cube
+    ///
+    /// # Arguments
+    ///
+    /// * `fits_cube` - The cube
+    /// * `axis` - The (FITS, 1-based) spectral axis of the cube
+    ///
+    /// # Returns
+    ///
+    /// * `ArrayD<T>` - The regridded cube, in standard layout
+    ///
+    pub(crate) fn regrid<T: FitsPixel>(&self, fits_cube: ArrayD<T>, axis: usize) -> ArrayD<T> {
+        let positions = self.positions();
+        map_spectra(fits_cube, axis, self.grid.n, |spectrum, regridded| {
+            for (value, position) in regridded.iter_mut().zip(&positions) {
+                *value = interpolate(spectrum, *position);
+            }
+        })
+    }
+
+    /// The shape of a cube after regridding
+    ///
+    /// # Arguments
+    ///
+    /// * `shape` - The shape of the cube, in array order
+    /// * `axis` - The (FITS, 1-based) spectral axis of the cube
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<usize>` - The shape after `regrid`, in array order
+    ///
+    pub fn shape(&self, shape: &[usize], axis: usize) -> Vec<usize> {
+        let mut shape = shape.to_vec();
+        let array_idx = fits_index_to_array_index(axis, shape.len());
+        shape[array_idx] = self.grid.n;
+        shape
+    }
+
+    /// The new grid, for HISTORY cards and messages
+    pub fn describe(&self) -> String {
+        format!(
+            "start={}, width={}, n={}",
+            self.grid.start, self.grid.width, self.grid.n
+        )
+    }
+}
+
+/// Linearly interpolate a spectrum at a (0-based, fractional) channel
+///
+/// Positions outside the spectrum, which would need extrapolation, are
+/// NaN, as are positions next to a NaN channel unless they fall exactly on
+/// a channel.
+///
+/// # Arguments
+///
+/// * `spectrum` - The values of the channels
+/// * `position` - The channel to interpolate at
+///
+/// # Returns
+///
+/// * `f64` - The interpolated value
+///
+pub fn interpolate(spectrum: &[f64], position: f64) -> f64 {
+    let last = match spectrum.len() {
+        0 => return f64::NAN,
+        len => (len - 1) as f64,
+    };
+    if !position.is_finite() || position < -EDGE_TOLERANCE || position > last + EDGE_TOLERANCE {
+        return f64::NAN;
+    }
+    let position = position.clamp(0.0, last);
+    let below = position.floor();
+    let fraction = position - below;
+    let below = below as usize;
+    if fraction < EDGE_TOLERANCE {
+        return spectrum[below];
+    }
+    if 1.0 - fraction < EDGE_TOLERANCE {
+        return spectrum[below + 1];
+    }
+    spectrum[below] * (1.0 - fraction) + spectrum[below + 1] * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::IxDyn;
+
+    /// A regridding of a 16-channel axis from 1 GHz in steps of 1 MHz (FITS axis 3 of a 2x2x16 cube)
+    fn regrid(start: f64, width: f64, n: usize) -> SpectralRegrid {
+        SpectralRegrid {
+            axis: 3,
+            crval: 1.0e9,
+            cdelt: 1.0e6,
+            crpix: 1.0,
+            grid: SpectralGrid { start, width, n },
+        }
+    }
+
+    /// A 2x2x16 (FITS order) cube whose spectrum at pixel (x, y) is `f(x + 2y, channel)`
+    fn cube(f: impl Fn(usize, f64) -> f64) -> ArrayD<f64> {
+        ArrayD::from_shape_fn(IxDyn(&[16, 2, 2]), |idx| f(idx[2] + 2 * idx[1], idx[0] as f64))
+    }
+
+    #[test]
+    fn a_linear_spectrum_is_regridded_exactly() {
+        // The value at frequency nu is a + b * nu, in MHz, with a different a and b for each spectrum
+        let line = |spectrum: usize, mhz: f64| spectrum as f64 - 0.5 * (spectrum + 1) as f64 * mhz;
+        let input = cube(|spectrum, channel| line(spectrum, 1000.0 + channel));
+        let regrid = regrid(1.00025e9, 2.5e6, 6);
+        let regridded = regrid.regrid(input, 3);
+        assert_eq!(regridded.shape(), [6, 2, 2]);
+        for ((channel, y, x), value) in regridded.into_dimensionality::<ndarray::Ix3>().unwrap().indexed_iter() {
+            let expected = line(x + 2 * y, 1000.25 + 2.5 * channel as f64);
+            assert!((value - expected).abs() < 1e-9, "{} != {}", value, expected);
+        }
+    }
+
+    #[test]
+    fn a_quadratic_spectrum_is_interpolated_between_its_channels() {
+        // Between channels k and k + 1, the linear interpolation of k^2 is k^2 + t * (2k + 1)
+        let input = cube(|_, channel| channel * channel);
+        let regrid = regrid(1.0003e9, 1.0e6, 15);
+        let regridded = regrid.regrid(input, 3);
+        for (channel, value) in regridded
+            .index_axis(Axis(1), 0)
+            .index_axis(Axis(1), 0)
+            .iter()
+            .enumerate()
+        {
+            let k = channel as f64;
+            let expected = k * k + 0.3 * (2.0 * k + 1.0);
+            assert!(
+                (value - expected).abs() < 1e-6,
+                "{}: {} != {}",
+                channel,
+                value,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn channels_beyond_the_spectrum_or_next_to_a_nan_are_nan() {
+        let spectrum: Vec<f64> = (0..16).map(|channel| channel as f64).collect();
+        // From half a channel below the first to half a channel above the last
+        let positions = regrid(0.9995e9, 1.0e6, 17).positions();
+        assert_eq!(positions[0], -0.5);
+        assert_eq!(positions[16], 15.5);
+        let values: Vec<f64> = positions
+            .iter()
+            .map(|position| interpolate(&spectrum, *position))
+            .collect();
+        assert!(values[0].is_nan() && values[16].is_nan());
+        assert_eq!(
+            &values[1..16],
+            (0..15).map(|channel| channel as f64 + 0.5).collect::<Vec<_>>()
+        );
+
+        // The first and last channels are not extrapolations
+        assert_eq!(interpolate(&spectrum, 0.0), 0.0);
+        assert_eq!(interpolate(&spectrum, 15.0), 15.0);
+
+        let mut holed = spectrum.clone();
+        holed[5] = f64::NAN;
+        assert!(interpolate(&holed, 4.5).is_nan());
+        assert!(interpolate(&holed, 5.5).is_nan());
+        assert_eq!(interpolate(&holed, 4.0), 4.0);
+        assert_eq!(interpolate(&holed, 6.25), 6.25);
+    }
+}
@@ -0,0 +1,311 @@
+use crate::error::FitsRotateError;
+use crate::header::FitsHeader;
+#[cfg(any(test, feature = "http"))]
+use crate::header::{HeaderCard, CARD_LENGTH};
+
+/// Length of a FITS block; headers are a whole number of blocks
+#[cfg(any(test, feature = "http"))]
+pub const BLOCK_LENGTH: usize = 2880;
+
+/// The most header blocks to download before giving up on finding END
+#[cfg(feature = "http")]
+const MAX_HEADER_BLOCKS: usize = 1000;
+
+/// Check if a file name is an HTTP(S) URL
+pub fn is_url(filename: &str) -> bool {
+    filename.starts_with("http://") || filename.starts_with("https://")
+}
+
+/// Parse the cards of a header block, stopping at END
+///
+/// # Arguments
+///
+/// * `block` - The block, `BLOCK_LENGTH` bytes long
+/// * `cards` - The cards read so far, which the cards of the block are appended to
+///
+/// # Returns
+///
+/// * `Result<bool, FitsRotateError>` - True if the block holds the END card
+///
+#[cfg(any(test, feature = "http"))]
+pub fn parse_header_block(block: &[u8], cards: &mut Vec<HeaderCard>) -> Result<bool, FitsRotateError> {
+    if block.len() != BLOCK_LENGTH {
+        return Err(FitsRotateError::Message(format!(
+            "Expected a header block of {} bytes, got {}",
+            BLOCK_LENGTH,
+            block.len()
+        )));
+    }
+    for chunk in block.chunks(CARD_LENGTH) {
+        let record = std::str::from_utf8(chunk)
+            .ok()
+            .filter(|record| record.is_ascii())
+            .ok_or_else(|| FitsRotateError::Message("The header has a card that is not ASCII text".to_string()))?;
+        let card = HeaderCard::new(record);
+        if cards.is_empty() && card.keyword() != "SIMPLE" {
+            return Err(FitsRotateError::Message(
+                "Not a FITS file: the header does not start with SIMPLE".to_string(),
+            ));
+        }
+        let end = card.keyword() == "END";
+        cards.push(card);
+        if end {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The CTYPE of each axis of a header
+///
+/// # Arguments
+///
+/// * `header` - The header
+///
+/// # Returns
+///
+/// * `Result<Vec<String>, FitsRotateError>` - The CTYPEs, in FITS axis order
+///
+pub fn header_ctypes(header: &FitsHeader) -> Result<Vec<String>, FitsRotateError> {
+    let value = |keyword: &str| {
+        header
+            .cards
+            .iter()
+            .find(|card| card.keyword() == keyword)
+            .and_then(|card| card.value())
+    };
+    let naxis: usize = value("NAXIS")
+        .and_then(|naxis| naxis.parse().ok())
+        .ok_or_else(|| FitsRotateError::Message("The header has no NAXIS card".to_string()))?;
+    Ok((1..naxis + 1)
+        .map(|axis| value(&format!("CTYPE{}", axis)).unwrap_or_default().trim().to_string())
+        .collect())
+}
+
+/// Read the primary header of a FITS file on an HTTP(S) server
+///
+/// The header is fetched one 2880-byte block at a time with `Range`
+/// requests (`bytes=0-2879`, then `bytes=2880-5759`, ...) until the END
+/// card is found, so none of the data is downloaded. A server that ignores
+/// the range sends the whole file instead, which is read only as far as
+/// the block needed.
+///
+/// # Arguments
+///
+/// * `url` - The URL of the FITS file
+///
+/// # Returns
+///
+/// * `Result<FitsHeader, FitsRotateError>` - The header, ending with the END card
+///
+#[cfg(feature = "http")]
+pub fn read_fits_header_from_url(url: &str) -> Result<FitsHeader, FitsRotateError> {
+    use std::io::Read;
+
+    let http_error = |e: reqwest::Error| FitsRotateError::Message(format!("Could not read {}: {}", url, e));
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(concat!("fitsrotate_rs/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(http_error)?;
+    let mut cards: Vec<HeaderCard> = Vec::new();
+    for block_idx in 0..MAX_HEADER_BLOCKS {
+        let start = block_idx * BLOCK_LENGTH;
+        let response = client
+            .get(url)
+            .header(reqwest::header::RANGE, format!("bytes={}-{}", start, start + BLOCK_LENGTH - 1))
+            .send()
+            .and_then(|response| response.error_for_status())
+            .map_err(http_error)?;
+        // Without a partial response the body starts at the beginning of the file
+        let skip = if response.status() == reqwest::StatusCode::PARTIAL_CONTENT {
+            0
+        } else {
+            start
+        };
+        let mut block: Vec<u8> = Vec::with_capacity(skip + BLOCK_LENGTH);
+        response.take((skip + BLOCK_LENGTH) as u64).read_to_end(&mut block)?;
+        if block.len() < skip + BLOCK_LENGTH {
+            return Err(FitsRotateError::Message(format!(
+                "{} ends before the END card of its header",
+                url
+            )));
+        }
+        if parse_header_block(&block[skip..], &mut cards)? {
+            return Ok(FitsHeader { cards });
+        }
+    }
+    Err(FitsRotateError::Message(format!(
+        "Found no END card in the first {} header blocks of {}",
+        MAX_HEADER_BLOCKS, url
+    )))
+}
+
+/// Read the primary header of a FITS file on an HTTP(S) server
+///
+/// This build does not include the http feature, so this always fails.
+#[cfg(not(feature = "http"))]
+pub fn read_fits_header_from_url(url: &str) -> Result<FitsHeader, FitsRotateError> {
+    Err(FitsRotateError::Message(format!(
+        "Reading {} needs fitsrotate_rs to be built with the `http` feature",
+        url
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A header of the given records, padded to whole blocks
+    fn header_bytes(records: &[&str]) -> Vec<u8> {
+        let mut bytes: Vec<u8> = records
+            .iter()
+            .flat_map(|record| format!("{:<width$}", record, width = CARD_LENGTH).into_bytes())
+            .collect();
+        bytes.resize(bytes.len().div_ceil(BLOCK_LENGTH) * BLOCK_LENGTH, b' ');
+        bytes
+    }
+
+    /// The records of a three-axis image header
+    fn cube_records() -> Vec<String> {
+        let mut records: Vec<String> = vec![
+            "SIMPLE  =                    T".into(),
+            "BITPIX  =                  -32".into(),
+            "NAXIS   =                    3".into(),
+        ];
+        for (axis, ctype) in ["RA---SIN", "DEC--SIN", "FREQ"].iter().enumerate() {
+            records.push(format!("NAXIS{}  =                    4", axis + 1));
+            records.push(format!("CTYPE{}  = '{:<8}'", axis + 1, ctype));
+        }
+        records
+    }
+
+    #[test]
+    fn urls_are_http_or_https() {
+        assert!(is_url("http://example.org/cube.fits"));
+        assert!(is_url("https://example.org/cube.fits"));
+        for name in [
+            "cube.fits",
+            "ftp://example.org/cube.fits",
+            "/data/http://cube.fits",
+            "HTTP://example.org",
+        ] {
+            assert!(!is_url(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn header_blocks_are_parsed_up_to_end() {
+        // Enough COMMENT cards that END falls in the second block
+        let mut records = cube_records();
+        records.extend((0..40).map(|idx| format!("COMMENT card {}", idx)));
+        records.push("END".into());
+        let records: Vec<&str> = records.iter().map(String::as_str).collect();
+        let bytes = header_bytes(&records);
+        assert_eq!(bytes.len(), 2 * BLOCK_LENGTH);
+
+        let mut cards = Vec::new();
+        assert!(!parse_header_block(&bytes[..BLOCK_LENGTH], &mut cards).unwrap());
+        assert_eq!(cards.len(), BLOCK_LENGTH / CARD_LENGTH);
+        assert!(parse_header_block(&bytes[BLOCK_LENGTH..], &mut cards).unwrap());
+        assert_eq!(cards.len(), records.len());
+        assert_eq!(cards.last().unwrap().keyword(), "END");
+
+        let header = FitsHeader { cards };
+        assert_eq!(header_ctypes(&header).unwrap(), ["RA---SIN", "DEC--SIN", "FREQ"]);
+    }
+
+    #[test]
+    fn blocks_that_are_not_a_fits_header_are_refused() {
+        let refusal = |block: &[u8]| parse_header_block(block, &mut Vec::new()).unwrap_err().to_string();
+        assert!(refusal(&header_bytes(&["SIMPLE  =                    T"])[..100]).contains("of 2880 bytes, got 100"));
+        assert!(refusal(&header_bytes(&["XTENSION= 'IMAGE   '"])).contains("does not start with SIMPLE"));
+        let mut binary = header_bytes(&["SIMPLE  =                    T", "COMMENT", "END"]);
+        binary[CARD_LENGTH + 10] = 0xc3;
+        assert!(refusal(&binary).contains("not ASCII text"));
+        // The rest of the block after END is not looked at
+        let mut after_end = header_bytes(&["SIMPLE  =                    T", "END"]);
+        after_end[2 * CARD_LENGTH] = 0xff;
+        assert!(parse_header_block(&after_end, &mut Vec::new()).unwrap());
+        assert!(header_ctypes(&FitsHeader::default())
+            .unwrap_err()
+            .to_string()
+            .contains("no NAXIS card"));
+    }
+
+    #[cfg(not(feature = "http"))]
+    #[test]
+    fn urls_need_the_http_feature() {
+        let error = read_fits_header_from_url("https://example.org/cube.fits").unwrap_err();
+        assert!(error.to_string().contains("built with the `http` feature"), "{}", error);
+    }
+
+    /// Serve a file over HTTP on a local port, answering `Range` requests unless `ranges` is false
+    ///
+    /// # Returns
+    ///
+    /// * `(String, std::thread::JoinHandle<Vec<String>>)` - The URL, and a thread giving the `Range` of each request
+    ///
+    #[cfg(feature = "http")]
+    fn serve(body: Vec<u8>, ranges: bool, n_requests: usize) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/cube.fits", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut requested = Vec::new();
+            for stream in listener.incoming().take(n_requests) {
+                let mut stream = stream.unwrap();
+                let mut range = None;
+                for line in BufReader::new(stream.try_clone().unwrap()).lines() {
+                    let line = line.unwrap();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some(value) = line.to_ascii_lowercase().strip_prefix("range: bytes=") {
+                        let (start, end) = value.trim().split_once('-').unwrap();
+                        range = Some((start.parse::<usize>().unwrap(), end.parse::<usize>().unwrap()));
+                    }
+                }
+                let (status, part) = match range {
+                    Some((start, end)) if ranges => {
+                        requested.push(format!("{}-{}", start, end));
+                        ("206 Partial Content", &body[start..(end + 1).min(body.len())])
+                    }
+                    _ => ("200 OK", &body[..]),
+                };
+                let head = format!(
+                    "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    part.len()
+                );
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(part).unwrap();
+            }
+            requested
+        });
+        (url, server)
+    }
+
+    #[cfg(feature = "http")]
+    #[test]
+    fn headers_are_read_block_by_block_without_the_data() {
+        let mut records = cube_records();
+        records.extend((0..40).map(|idx| format!("COMMENT card {}", idx)));
+        records.push("END".into());
+        let records: Vec<&str> = records.iter().map(String::as_str).collect();
+        let mut file = header_bytes(&records);
+        // The data unit, which is never asked for
+        file.extend(vec![0u8; 4 * BLOCK_LENGTH]);
+
+        for ranges in [true, false] {
+            let (url, server) = serve(file.clone(), ranges, 2);
+            let header = read_fits_header_from_url(&url).unwrap();
+            assert_eq!(header.cards.len(), records.len());
+            assert_eq!(header_ctypes(&header).unwrap(), ["RA---SIN", "DEC--SIN", "FREQ"]);
+            let requested = server.join().unwrap();
+            if ranges {
+                assert_eq!(requested, ["0-2879", "2880-5759"]);
+            }
+        }
+    }
+}
@@ -0,0 +1,247 @@
+use crate::color::{label, success, warning};
+use crate::error::FitsRotateError;
+use crate::header::{delete_key, FitsHeader, HeaderCard, CARD_LENGTH};
+use crate::permutation::AxisPermutation;
+use crate::provenance::{version_history, write_history};
+use crate::{parse_flips, parse_mode, OPTIONAL_WCS_CARD_STUBS, WCS_CARD_STUBS, WCS_MATRIX_STUBS};
+use fitsio::errors::check_status;
+use fitsio::FitsFile;
+
+/// Keyword recording the mode of a rotation in the output header
+pub const MODE_KEYWORD: &str = "FROTMODE";
+
+/// Find the first card with a keyword
+fn find_card<'a>(header: &'a FitsHeader, keyword: &str) -> Option<&'a HeaderCard> {
+    header.cards.iter().find(|card| card.keyword() == keyword)
+}
+
+/// The value of a numeric card, if it is present
+fn number_value(header: &FitsHeader, keyword: &str) -> Option<f64> {
+    find_card(header, keyword)
+        .and_then(|card| card.value())
+        .and_then(|value| value.replace('D', "E").parse().ok())
+}
+
+/// A copy of a card under a new keyword, keeping its value and comment
+pub fn renumbered_card(card: &HeaderCard, keyword: &str) -> HeaderCard {
+    let record = format!("{:<8}{}", keyword, &card.record[8.min(card.record.len())..]);
+    HeaderCard::new(&format!("{:<width$}", record, width = CARD_LENGTH))
+}
+
+/// Set the value of a numeric card, adding the card if it is missing
+fn set_number(header: &mut FitsHeader, keyword: &str, value: f64) {
+    // FITS exponents are upper case, e.g. 1.5E-05
+    let record = format!("{:<8}= {:>20}", keyword, format!("{:?}", value).to_uppercase());
+    let card = HeaderCard::new(&format!("{:<width$}", record, width = CARD_LENGTH));
+    match header.cards.iter_mut().find(|old| old.keyword() == keyword) {
+        Some(old) => *old = card,
+        None => header.cards.push(card),
+    }
+}
+
+/// The keywords of the WCS cards that a rotation rewrites
+pub fn rotated_keywords(naxis: usize) -> Vec<String> {
+    let mut keywords: Vec<String> = WCS_CARD_STUBS
+        .iter()
+        .chain(OPTIONAL_WCS_CARD_STUBS.iter())
+        .flat_map(|stub| (1..naxis + 1).map(move |axis| format!("{}{}", stub, axis)))
+        .collect();
+    keywords.extend(WCS_MATRIX_STUBS.iter().flat_map(|stub| {
+        (1..naxis + 1).flat_map(move |i| (1..naxis + 1).map(move |j| format!("{}{}_{}", stub, i, j)))
+    }));
+    keywords
+}
+
+/// Work out the WCS cards of a rotated file from the header of its input
+///
+/// Output axis `n` takes the cards of input axis `mode[n - 1]`, and matrix
+/// entry `(i, j)` takes entry `(mode[i - 1], mode[j - 1])`. The WCS of each
+/// reversed axis is then updated as `flip_wcs_axis` does for a new output.
+///
+/// # Arguments
+///
+/// * `original` - The header of the input
+/// * `permutation` - The new ordering of the axes
+/// * `flips` - The (FITS, 1-based) input axes that were reversed
+/// * `lengths` - The length of each output axis
+///
+/// # Returns
+///
+/// * `FitsHeader` - The WCS cards the rotated file should have
+///
+pub fn rotated_wcs_cards(
+    original: &FitsHeader,
+    permutation: AxisPermutation,
+    flips: &[usize],
+    lengths: &[usize],
+) -> FitsHeader {
+    let naxis = permutation.naxis();
+    let mut cards = FitsHeader::default();
+    for stub in WCS_CARD_STUBS.iter().chain(OPTIONAL_WCS_CARD_STUBS.iter()) {
+        for axis in 1..naxis + 1 {
+            if let Some(card) = find_card(original, &format!("{}{}", stub, permutation.input_axis(axis))) {
+                cards.cards.push(renumbered_card(card, &format!("{}{}", stub, axis)));
+            }
+        }
+    }
+    for stub in WCS_MATRIX_STUBS {
+        for i in 1..naxis + 1 {
+            for j in 1..naxis + 1 {
+                let old_keyword = format!("{}{}_{}", stub, permutation.input_axis(i), permutation.input_axis(j));
+                if let Some(card) = find_card(original, &old_keyword) {
+                    cards.cards.push(renumbered_card(card, &format!("{}{}_{}", stub, i, j)));
+                }
+            }
+        }
+    }
+
+    let has_cd = cards
+        .cards
+        .iter()
+        .any(|card| card.keyword().starts_with("CD") && !card.keyword().starts_with("CDELT"));
+    for axis in flips.iter().map(|axis| permutation.output_axis(*axis)) {
+        let crpix_card = format!("CRPIX{}", axis);
+        let crpix = number_value(&cards, &crpix_card).unwrap_or(1.0);
+        set_number(&mut cards, &crpix_card, lengths[axis - 1] as f64 + 1.0 - crpix);
+        if has_cd {
+            for i in 1..naxis + 1 {
+                let cd_card = format!("CD{}_{}", i, axis);
+                if let Some(cd) = number_value(&cards, &cd_card) {
+                    set_number(&mut cards, &cd_card, -cd);
+                }
+            }
+            continue;
+        }
+        for k in (1..naxis + 1).filter(|k| *k != axis) {
+            for pc_card in [format!("PC{}_{}", axis, k), format!("PC{}_{}", k, axis)] {
+                if let Some(pc) = number_value(&cards, &pc_card) {
+                    set_number(&mut cards, &pc_card, -pc);
+                }
+            }
+        }
+        let cdelt_card = format!("CDELT{}", axis);
+        let cdelt = number_value(&cards, &cdelt_card).unwrap_or(1.0);
+        set_number(&mut cards, &cdelt_card, -cdelt);
+    }
+    cards
+}
+
+/// Repair the WCS cards of a file rotated by a version with the keyword-swap bug
+///
+/// Those versions permuted the data correctly but gave output axis
+/// `mode[n - 1]` the cards of input axis `n`, rather than the other way
+/// round. The WCS cards are worked out again from the header of the
+/// original file, and only the cards that differ are rewritten, in place;
+/// the data is not touched. A CHECKSUM, if the file has one, is updated.
+///
+/// # Arguments
+///
+/// * `filename` - The rotated FITS file
+/// * `original` - The FITS file it was rotated from
+/// * `mode` - The mode of the rotation, or `None` to read it from the `FROTMODE` card
+/// * `dry_run` - Print the changes without making them
+///
+pub fn repair_header(filename: &str, original: &str, mode: Option<&str>, dry_run: bool) -> Result<(), FitsRotateError> {
+    let mut fits_file = if dry_run {
+        FitsFile::open(filename)?
+    } else {
+        FitsFile::edit(filename)?
+    };
+    fits_file.primary_hdu()?;
+    let header = FitsHeader::read(&mut fits_file)?;
+    let mode = match mode {
+        Some(mode) => mode.to_string(),
+        None => find_card(&header, MODE_KEYWORD)
+            .and_then(|card| card.value())
+            .ok_or_else(|| {
+                FitsRotateError::Message(format!(
+                    "{} has no {} card, so the mode must be given with --mode",
+                    filename, MODE_KEYWORD
+                ))
+            })?,
+    };
+
+    let mut original_file = FitsFile::open(original)?;
+    original_file.primary_hdu()?;
+    let original_header = FitsHeader::read(&mut original_file)?;
+    let length = |header: &FitsHeader, keyword: &str| number_value(header, keyword).map(|length| length as usize);
+    let naxis = length(&header, "NAXIS").unwrap_or(0);
+    if length(&original_header, "NAXIS") != Some(naxis) {
+        return Err(FitsRotateError::Message(format!(
+            "{} and {} do not have the same number of axes",
+            filename, original
+        )));
+    }
+    let mode_vec = parse_mode(&mode, naxis)?;
+    let mut lengths: Vec<usize> = Vec::with_capacity(naxis);
+    for (idx, in_axis) in mode_vec.iter().enumerate() {
+        let rotated_length = length(&header, &format!("NAXIS{}", idx + 1));
+        let original_length = length(&original_header, &format!("NAXIS{}", in_axis));
+        if rotated_length != original_length {
+            return Err(FitsRotateError::Message(format!(
+                "NAXIS{} of {} does not match NAXIS{} of {}: was {} rotated with mode {}?",
+                idx + 1,
+                filename,
+                in_axis,
+                original,
+                filename,
+                mode
+            )));
+        }
+        lengths.push(rotated_length.unwrap_or(0));
+    }
+
+    let permutation = AxisPermutation::new(&mode_vec).expect("parse_mode only returns permutations");
+    let expected = rotated_wcs_cards(&original_header, permutation, &parse_flips(&mode), &lengths);
+    let changes: Vec<(Option<&HeaderCard>, Option<&HeaderCard>)> = rotated_keywords(naxis)
+        .iter()
+        .map(|keyword| (find_card(&header, keyword), find_card(&expected, keyword)))
+        .filter(|(old, new)| old.map(|card| card.record.trim_end()) != new.map(|card| card.record.trim_end()))
+        .collect();
+    if changes.is_empty() {
+        println!("{} {}", success("Header OK:"), filename);
+        return Ok(());
+    }
+    println!("{} {}", label("Header changes:"), filename);
+    for (old, new) in &changes {
+        if let Some(old) = old {
+            println!("- {}", old.record.trim_end());
+        }
+        if let Some(new) = new {
+            println!("+ {}", new.record.trim_end());
+        }
+    }
+    if dry_run {
+        println!("{}", warning(&format!("Dry run: {} was not changed", filename)));
+        return Ok(());
+    }
+
+    for (old, new) in &changes {
+        if let Some(old) = old {
+            delete_key(&mut fits_file, &old.keyword())?;
+        }
+        if let Some(new) = new {
+            FitsHeader {
+                cards: vec![(*new).clone()],
+            }
+            .write(&mut fits_file, &[])?;
+        }
+    }
+    write_history(
+        &mut fits_file,
+        &format!("fitsrotate_rs: repaired the WCS from {} with mode {}", original, mode),
+    )?;
+    write_history(&mut fits_file, &version_history(false))?;
+    if find_card(&header, "CHECKSUM").is_some() {
+        let mut status = 0;
+        unsafe {
+            fitsio::sys::ffpcks(fits_file.as_raw(), &mut status);
+        }
+        check_status(status)?;
+    }
+    println!(
+        "{}",
+        success(&format!("Repaired {} cards of {}", changes.len(), filename))
+    );
+    Ok(())
+}
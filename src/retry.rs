@@ -0,0 +1,226 @@
+use crate::error::FitsRotateError;
+use crate::warn;
+use std::time::Duration;
+
+/// errno values that are usually transient on network filesystems
+/// (EINTR, EIO, EAGAIN, ETIMEDOUT, ESTALE)
+pub const TRANSIENT_ERRNOS: [i32; 5] = [4, 5, 11, 110, 116];
+
+/// CFITSIO status codes that are usually transient on network filesystems
+/// (FILE_NOT_OPENED, WRITE_ERROR, READ_ERROR)
+pub const TRANSIENT_FITS_STATUSES: [i32; 3] = [104, 106, 108];
+
+/// When and how often to retry a failed operation
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    /// How many times to retry after the first attempt
+    pub retries: usize,
+    /// How long to wait between attempts
+    pub delay: Duration,
+    /// errno values to retry on
+    pub errnos: Vec<i32>,
+    /// CFITSIO status codes to retry on
+    pub statuses: Vec<i32>,
+}
+
+impl RetryPolicy {
+    /// Check if an error is one of the transient errors to retry on
+    pub fn is_transient(&self, error: &FitsRotateError) -> bool {
+        match error {
+            FitsRotateError::Fits(fitsio::errors::Error::Io(e)) => {
+                e.raw_os_error().is_some_and(|errno| self.errnos.contains(&errno))
+            }
+            FitsRotateError::Fits(fitsio::errors::Error::Fits(e)) => self.statuses.contains(&e.status),
+            _ => false,
+        }
+    }
+
+    /// Run an operation, retrying it after transient errors
+    ///
+    /// Each failed attempt is logged. Errors that are not transient are
+    /// returned straight away.
+    ///
+    /// # Arguments
+    ///
+    /// * `what` - A description of the operation, for the log
+    /// * `operation` - The operation, restarted from scratch on each attempt
+    /// * `cleanup` - Called after each failed attempt that will be retried
+    ///
+    /// # Returns
+    ///
+    /// * `Result<T, FitsRotateError>` - The result of the last attempt
+    ///
+    pub fn run<T>(
+        &self,
+        what: &str,
+        mut operation: impl FnMut() -> Result<T, FitsRotateError>,
+        mut cleanup: impl FnMut(),
+    ) -> Result<T, FitsRotateError> {
+        let mut attempt = 0;
+        loop {
+            match operation() {
+                Ok(result) => return Ok(result),
+                Err(e) if attempt < self.retries && self.is_transient(&e) => {
+                    attempt += 1;
+                    let message = format!(
+                        "Transient error while {}: {}; retrying in {:?} (attempt {} of {})",
+                        what, e, self.delay, attempt, self.retries
+                    );
+                    warn(&message);
+                    cleanup();
+                    std::thread::sleep(self.delay);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Parse a duration such as `5s`, `500ms` or `1.5` (seconds)
+///
+/// # Arguments
+///
+/// * `duration` - The duration string
+///
+/// # Returns
+///
+/// * `Result<Duration, String>` - The duration
+///
+pub fn parse_duration(duration: &str) -> Result<Duration, String> {
+    let duration = duration.trim();
+    let (number, scale) = if let Some(ms) = duration.strip_suffix("ms") {
+        (ms, 1e-3)
+    } else if let Some(s) = duration.strip_suffix('s') {
+        (s, 1.0)
+    } else if let Some(m) = duration.strip_suffix('m') {
+        (m, 60.0)
+    } else {
+        (duration, 1.0)
+    };
+    let value: f64 = number
+        .trim()
+        .parse()
+        .map_err(|_| format!("{} is not a duration (e.g. 5s or 500ms)", duration))?;
+    Duration::try_from_secs_f64(value * scale).map_err(|e| format!("{} is not a valid duration: {}", duration, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use std::io::Read;
+
+    const ESTALE: i32 = 116;
+    const ENOENT: i32 = 2;
+
+    /// A reader that fails with an errno on its first read, then reads its data
+    struct FaultyReader {
+        fault: Option<i32>,
+        data: &'static [u8],
+    }
+
+    impl Read for FaultyReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if let Some(errno) = self.fault.take() {
+                return Err(std::io::Error::from_raw_os_error(errno));
+            }
+            self.data.read(buf)
+        }
+    }
+
+    fn policy(retries: usize) -> RetryPolicy {
+        RetryPolicy {
+            retries,
+            delay: Duration::ZERO,
+            errnos: TRANSIENT_ERRNOS.to_vec(),
+            statuses: TRANSIENT_FITS_STATUSES.to_vec(),
+        }
+    }
+
+    /// Read through a reader that is reopened on each attempt, with the given faults
+    ///
+    /// Returns the result, the number of attempts and the number of cleanups.
+    fn read_with_faults(policy: &RetryPolicy, faults: &[i32]) -> (Result<Vec<u8>, FitsRotateError>, usize, usize) {
+        let (attempts, cleanups) = (Cell::new(0), Cell::new(0));
+        let result = policy.run(
+            "reading",
+            || {
+                let fault = faults.get(attempts.get()).copied();
+                attempts.set(attempts.get() + 1);
+                let mut reader = FaultyReader { fault, data: b"cube" };
+                let mut bytes = Vec::new();
+                reader.read_to_end(&mut bytes)?;
+                Ok(bytes)
+            },
+            || cleanups.set(cleanups.get() + 1),
+        );
+        (result, attempts.get(), cleanups.get())
+    }
+
+    #[test]
+    fn transient_faults_are_retried_until_a_read_succeeds() {
+        let (result, attempts, cleanups) = read_with_faults(&policy(3), &[ESTALE, 5]);
+        assert_eq!(result.unwrap(), b"cube");
+        assert_eq!((attempts, cleanups), (3, 2));
+    }
+
+    #[test]
+    fn retries_give_up_with_the_last_error() {
+        let (result, attempts, cleanups) = read_with_faults(&policy(2), &[ESTALE, ESTALE, ESTALE, ESTALE]);
+        match result {
+            Err(FitsRotateError::Fits(fitsio::errors::Error::Io(e))) => assert_eq!(e.raw_os_error(), Some(ESTALE)),
+            other => panic!("expected an ESTALE error, got {:?}", other),
+        }
+        assert_eq!((attempts, cleanups), (3, 2));
+    }
+
+    #[test]
+    fn permanent_faults_are_not_retried() {
+        let (result, attempts, cleanups) = read_with_faults(&policy(3), &[ENOENT]);
+        assert!(result.is_err());
+        assert_eq!((attempts, cleanups), (1, 0));
+    }
+
+    #[test]
+    fn no_retries_by_default() {
+        let (result, attempts, _) = read_with_faults(&policy(0), &[ESTALE]);
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn transient_classes_follow_the_policy() {
+        let fits_error = |status| {
+            FitsRotateError::Fits(fitsio::errors::Error::Fits(fitsio::errors::FitsError {
+                status,
+                message: String::new(),
+            }))
+        };
+        let io_error = |errno| FitsRotateError::from(std::io::Error::from_raw_os_error(errno));
+        let default = policy(1);
+        assert!(default.is_transient(&fits_error(108)));
+        assert!(!default.is_transient(&fits_error(202)));
+        assert!(default.is_transient(&io_error(ESTALE)));
+        assert!(!default.is_transient(&FitsRotateError::Message("gone".to_string())));
+
+        // --retry-errno and --retry-status replace the defaults
+        let custom = RetryPolicy {
+            errnos: vec![ENOENT],
+            statuses: vec![202],
+            ..policy(1)
+        };
+        assert!(custom.is_transient(&io_error(ENOENT)));
+        assert!(!custom.is_transient(&io_error(ESTALE)));
+        assert!(custom.is_transient(&fits_error(202)));
+    }
+
+    #[test]
+    fn durations_take_units() {
+        assert_eq!(parse_duration("5s"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("2m"), Ok(Duration::from_secs(120)));
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert!(parse_duration("soon").is_err());
+        assert!(parse_duration("-1s").is_err());
+    }
+}
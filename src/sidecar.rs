@@ -0,0 +1,319 @@
+//! WCS sidecar files describing the axes of a cube
+//!
+//! The sidecar is a JSON (or YAML) object with the schema
+//! `fitsrotate_rs-wcs-sidecar/1`:
+//!
+//! * `schema` - The schema name and version
+//! * `file` - The FITS file described
+//! * `naxis` - The number of axes
+//! * `axes` - One object per axis, in FITS order, with `axis` (1-based),
+//!   `naxis`, `ctype`, `cunit`, `crval`, `cdelt`, `crpix` and `role` (RA, DEC,
+//!   FREQ, STOKES or null)
+//! * `pc` - The PC matrix as a list of rows, or null if there are no PC cards
+//! * `cd` - The CD matrix as a list of rows, or null if there are no CD cards
+//!
+//! Cards missing from the header are null.
+
+use crate::axes::axis_role;
+use crate::error::FitsRotateError;
+use fitsio::FitsFile;
+
+/// The name and version of the sidecar schema
+pub const SIDECAR_SCHEMA: &str = "fitsrotate_rs-wcs-sidecar/1";
+
+/// The WCS of a single axis
+#[derive(Clone, Debug, PartialEq)]
+pub struct AxisInfo {
+    /// The (FITS, 1-based) axis
+    pub axis: usize,
+    /// The length of the axis
+    pub naxis: usize,
+    pub ctype: Option<String>,
+    pub cunit: Option<String>,
+    pub crval: Option<f64>,
+    pub cdelt: Option<f64>,
+    pub crpix: Option<f64>,
+}
+
+/// The WCS of a cube
+#[derive(Clone, Debug, PartialEq)]
+pub struct CubeInfo {
+    /// The FITS file
+    pub file: String,
+    /// The axes, in FITS order
+    pub axes: Vec<AxisInfo>,
+    /// The PC matrix (row i is PCi_j), if the header has PC cards
+    pub pc: Option<Vec<Vec<f64>>>,
+    /// The CD matrix (row i is CDi_j), if the header has CD cards
+    pub cd: Option<Vec<Vec<f64>>>,
+}
+
+impl CubeInfo {
+    /// Read the WCS of the primary HDU of a FITS file
+    ///
+    /// # Arguments
+    ///
+    /// * `filename` - The FITS file
+    ///
+    /// # Returns
+    ///
+    /// * `Result<CubeInfo, FitsRotateError>` - The WCS
+    ///
+    pub fn read(filename: &str) -> Result<CubeInfo, FitsRotateError> {
+        let mut fits_file = FitsFile::open(filename)?;
+        let hdu = fits_file.primary_hdu()?;
+        let naxis = hdu.read_key::<i64>(&mut fits_file, "NAXIS")? as usize;
+        let mut axes: Vec<AxisInfo> = Vec::with_capacity(naxis);
+        for axis in 1..naxis + 1 {
+            let string = |fits_file: &mut FitsFile, stub: &str| {
+                hdu.read_key::<String>(fits_file, &format!("{}{}", stub, axis))
+                    .ok()
+                    .map(|value| value.trim().to_string())
+            };
+            let number =
+                |fits_file: &mut FitsFile, stub: &str| hdu.read_key::<f64>(fits_file, &format!("{}{}", stub, axis)).ok();
+            axes.push(AxisInfo {
+                axis,
+                naxis: hdu.read_key::<i64>(&mut fits_file, &format!("NAXIS{}", axis))? as usize,
+                ctype: string(&mut fits_file, "CTYPE"),
+                cunit: string(&mut fits_file, "CUNIT"),
+                crval: number(&mut fits_file, "CRVAL"),
+                cdelt: number(&mut fits_file, "CDELT"),
+                crpix: number(&mut fits_file, "CRPIX"),
+            });
+        }
+        // Missing PC cards default to the identity, and missing CD cards to zero
+        let mut matrix = |stub: &str, diagonal: f64| -> Option<Vec<Vec<f64>>> {
+            let mut found = false;
+            let rows = (1..naxis + 1)
+                .map(|i| {
+                    (1..naxis + 1)
+                        .map(|j| match hdu.read_key::<f64>(&mut fits_file, &format!("{}{}_{}", stub, i, j)) {
+                            Ok(value) => {
+                                found = true;
+                                value
+                            }
+                            Err(_) if i == j => diagonal,
+                            Err(_) => 0.0,
+                        })
+                        .collect()
+                })
+                .collect();
+            if found {
+                Some(rows)
+            } else {
+                None
+            }
+        };
+        let pc = matrix("PC", 1.0);
+        let cd = matrix("CD", 0.0);
+        Ok(CubeInfo {
+            file: filename.to_string(),
+            axes,
+            pc,
+            cd,
+        })
+    }
+
+    /// The sidecar as a tree of values
+    fn to_value(&self) -> Value {
+        let string = |value: &Option<String>| value.clone().map_or(Value::Null, Value::String);
+        let number = |value: Option<f64>| value.map_or(Value::Null, Value::Number);
+        let matrix = |matrix: &Option<Vec<Vec<f64>>>| {
+            matrix.as_ref().map_or(Value::Null, |rows| {
+                Value::List(
+                    rows.iter()
+                        .map(|row| Value::List(row.iter().map(|value| Value::Number(*value)).collect()))
+                        .collect(),
+                )
+            })
+        };
+        let axes = self
+            .axes
+            .iter()
+            .map(|axis| {
+                let role = axis.ctype.as_deref().and_then(axis_role);
+                Value::Object(vec![
+                    ("axis", Value::Integer(axis.axis as i64)),
+                    ("naxis", Value::Integer(axis.naxis as i64)),
+                    ("ctype", string(&axis.ctype)),
+                    ("cunit", string(&axis.cunit)),
+                    ("crval", number(axis.crval)),
+                    ("cdelt", number(axis.cdelt)),
+                    ("crpix", number(axis.crpix)),
+                    ("role", role.map_or(Value::Null, |role| Value::String(role.to_string()))),
+                ])
+            })
+            .collect();
+        Value::Object(vec![
+            ("schema", Value::String(SIDECAR_SCHEMA.to_string())),
+            ("file", Value::String(self.file.clone())),
+            ("naxis", Value::Integer(self.axes.len() as i64)),
+            ("axes", Value::List(axes)),
+            ("pc", matrix(&self.pc)),
+            ("cd", matrix(&self.cd)),
+        ])
+    }
+
+    /// The sidecar as JSON
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        self.to_value().write_json(&mut json, 0);
+        json.push('\n');
+        json
+    }
+
+    /// The sidecar as YAML
+    pub fn to_yaml(&self) -> String {
+        let mut yaml = String::new();
+        if let Value::Object(fields) = self.to_value() {
+            for (key, value) in fields {
+                value.write_yaml_field(&mut yaml, key, 0);
+            }
+        }
+        yaml
+    }
+}
+
+/// A value of the sidecar, for writing as JSON or YAML
+enum Value {
+    Null,
+    Integer(i64),
+    Number(f64),
+    String(String),
+    List(Vec<Value>),
+    Object(Vec<(&'static str, Value)>),
+}
+
+impl Value {
+    /// Write a scalar or inline list, which is valid in both JSON and YAML
+    fn inline(&self) -> Option<String> {
+        match self {
+            Value::Null => Some("null".to_string()),
+            Value::Integer(value) => Some(value.to_string()),
+            // JSON has no NaN or infinity
+            Value::Number(value) if !value.is_finite() => Some("null".to_string()),
+            Value::Number(value) => {
+                // YAML 1.1 parsers need a decimal point before an exponent
+                let number = format!("{:?}", value);
+                match number.split_once('e') {
+                    Some((mantissa, exponent)) if !mantissa.contains('.') => {
+                        Some(format!("{}.0e{}", mantissa, exponent))
+                    }
+                    _ => Some(number),
+                }
+            }
+            Value::String(value) => Some(quote(value)),
+            Value::List(values) if values.iter().all(|value| !matches!(value, Value::Object(_))) => {
+                let values: Option<Vec<String>> = values.iter().map(|value| value.inline()).collect();
+                Some(format!("[{}]", values?.join(", ")))
+            }
+            _ => None,
+        }
+    }
+
+    /// Write the value as indented JSON
+    fn write_json(&self, out: &mut String, indent: usize) {
+        if let Some(inline) = self.inline() {
+            out.push_str(&inline);
+            return;
+        }
+        let pad = "  ".repeat(indent + 1);
+        match self {
+            Value::List(values) => {
+                out.push_str("[\n");
+                for (idx, value) in values.iter().enumerate() {
+                    out.push_str(&pad);
+                    value.write_json(out, indent + 1);
+                    out.push_str(if idx + 1 < values.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&"  ".repeat(indent));
+                out.push(']');
+            }
+            Value::Object(fields) => {
+                out.push_str("{\n");
+                for (idx, (key, value)) in fields.iter().enumerate() {
+                    out.push_str(&format!("{}{}: ", pad, quote(key)));
+                    value.write_json(out, indent + 1);
+                    out.push_str(if idx + 1 < fields.len() { ",\n" } else { "\n" });
+                }
+                out.push_str(&"  ".repeat(indent));
+                out.push('}');
+            }
+            _ => {}
+        }
+    }
+
+    /// Write the value as a YAML mapping entry
+    fn write_yaml_field(&self, out: &mut String, key: &str, indent: usize) {
+        let pad = "  ".repeat(indent);
+        if let Some(inline) = self.inline() {
+            out.push_str(&format!("{}{}: {}\n", pad, key, inline));
+            return;
+        }
+        out.push_str(&format!("{}{}:\n", pad, key));
+        match self {
+            Value::List(values) => {
+                for value in values {
+                    match value {
+                        Value::Object(fields) => {
+                            for (idx, (key, value)) in fields.iter().enumerate() {
+                                let mut field = String::new();
+                                value.write_yaml_field(&mut field, key, indent + 1);
+                                // The first field of each item starts the item
+                                if idx == 0 {
+                                    field.replace_range(pad.len()..pad.len() + 2, "- ");
+                                }
+                                out.push_str(&field);
+                            }
+                        }
+                        value => out.push_str(&format!("{}- {}\n", pad, value.inline().unwrap_or_default())),
+                    }
+                }
+            }
+            Value::Object(fields) => {
+                for (key, value) in fields {
+                    value.write_yaml_field(out, key, indent + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Quote and escape a string, as valid JSON and YAML
+fn quote(value: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => quoted.push_str(&format!("\\u{:04x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Write a sidecar describing a FITS file
+///
+/// The sidecar is written to a temporary file next to it and then renamed,
+/// so that readers never see a partial sidecar.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file to describe
+/// * `sidecar` - The sidecar file
+/// * `yaml` - Write YAML rather than JSON
+///
+pub fn write_sidecar(filename: &str, sidecar: &str, yaml: bool) -> Result<(), FitsRotateError> {
+    let info = CubeInfo::read(filename)?;
+    let contents = if yaml { info.to_yaml() } else { info.to_json() };
+    let partial = format!("{}.{}.partial", sidecar, std::process::id());
+    std::fs::write(&partial, contents)?;
+    std::fs::rename(&partial, sidecar).inspect_err(|_| {
+        let _ = std::fs::remove_file(&partial);
+    })?;
+    Ok(())
+}
@@ -0,0 +1,325 @@
+use crate::axes::{axis_role, read_ctypes, AxisRole};
+use crate::error::FitsRotateError;
+use crate::permutation::fits_index_to_array_index;
+use crate::regrid::{axis_quantity, map_spectra, SpectralRegrid};
+use crate::FitsPixel;
+use fitsio::FitsFile;
+use ndarray::ArrayD;
+
+/// The ratio of the FWHM of a Gaussian to its standard deviation, 2 sqrt(2 ln 2)
+const FWHM_PER_SIGMA: f64 = 2.354_820_045_030_949;
+
+/// Gaussian kernels are cut off this many standard deviations from their centre
+const GAUSSIAN_EXTENT: f64 = 4.0;
+
+/// The shape of a smoothing kernel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SmoothingKernel {
+    /// Equal weights over the width
+    Boxcar,
+    /// A Gaussian with the width as its FWHM
+    Gaussian,
+}
+
+/// A smoothing of the spectral axis, chosen with `--smooth-spectral`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpectralSmoothing {
+    /// The (FITS, 1-based) spectral axis of the input
+    pub axis: usize,
+    /// The shape of the kernel
+    pub kernel: SmoothingKernel,
+    /// The width of the kernel in channels (the FWHM of a Gaussian)
+    pub width: f64,
+    /// The width of a channel, in the units of the spectral axis
+    pub channel_width: f64,
+    /// The CUNIT of the spectral axis
+    pub cunit: String,
+}
+
+impl SpectralSmoothing {
+    /// Work out the smoothing of the spectral axis of a FITS file
+    ///
+    /// The kernel is given as `boxcar=<width>` or `gaussian=<FWHM>`, with
+    /// the width in channels (e.g. `5` or `5chan`) or in the units of the
+    /// spectral axis (e.g. `2MHz` or `10km/s`), which are converted to
+    /// channels with the width of a channel.
+    ///
+    /// # Arguments
+    ///
+    /// * `fits_file` - The FITS file
+    /// * `filename` - The name of the FITS file, for error messages
+    /// * `spec` - The kernel
+    /// * `regrid` - The regridding of the spectral axis, which sets the width of a channel, if any
+    ///
+    /// # Returns
+    ///
+    /// * `Result<SpectralSmoothing, FitsRotateError>` - The smoothing
+    ///
+    pub fn find(
+        fits_file: &mut FitsFile,
+        filename: &str,
+        spec: &str,
+        regrid: Option<&SpectralRegrid>,
+    ) -> Result<SpectralSmoothing, FitsRotateError> {
+        let (kernel, width) = spec.split_once('=').ok_or_else(|| {
+            FitsRotateError::Message(format!(
+                "Expected boxcar=<width> or gaussian=<FWHM> for --smooth-spectral, got {}",
+                spec
+            ))
+        })?;
+        let kernel = match kernel.trim().to_lowercase().as_str() {
+            "boxcar" => SmoothingKernel::Boxcar,
+            "gaussian" => SmoothingKernel::Gaussian,
+            other => {
+                return Err(FitsRotateError::Message(format!(
+                    "Unknown kernel {} for --smooth-spectral (expected boxcar or gaussian)",
+                    other
+                )))
+            }
+        };
+        let ctypes = read_ctypes(fits_file).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+        let axis = ctypes
+            .iter()
+            .position(|ctype| axis_role(ctype) == Some(AxisRole::Spectral))
+            .map(|idx| idx + 1)
+            .ok_or_else(|| {
+                FitsRotateError::Message(format!(
+                    "{} has no spectral axis (CTYPEs: {})",
+                    filename,
+                    ctypes.join(", ")
+                ))
+            })?;
+        let hdu = fits_file.primary_hdu()?;
+        let cunit: String = hdu.read_key(fits_file, &format!("CUNIT{}", axis)).unwrap_or_default();
+        let channel_width = match regrid {
+            Some(regrid) => regrid.grid.width,
+            None => hdu.read_key::<f64>(fits_file, &format!("CDELT{}", axis)).unwrap_or(1.0),
+        }
+        .abs();
+
+        let width = width.trim();
+        let width = match width.strip_suffix("chan") {
+            Some(channels) => channels.trim().parse::<f64>().ok(),
+            None => match width.parse::<f64>() {
+                Ok(channels) => Some(channels),
+                Err(_) => Some(axis_quantity(width, &cunit, axis)? / channel_width),
+            },
+        }
+        .filter(|width| width.is_finite() && *width > 0.0)
+        .ok_or_else(|| FitsRotateError::Message(format!("Expected a positive kernel width, got {}", width)))?;
+        Ok(SpectralSmoothing {
+            axis,
+            kernel,
+            width,
+            channel_width,
+            cunit: cunit.trim().to_string(),
+        })
+    }
+
+    /// The weights of the kernel, centred on the middle weight
+    ///
+    /// A boxcar whose width is not an odd number of channels has partial
+    /// weights at its ends, so that it covers exactly its width.
+    pub fn weights(&self) -> Vec<f64> {
+        let weights: Vec<f64> = match self.kernel {
+            SmoothingKernel::Boxcar => {
+                let half_width = self.width / 2.0;
+                let reach = (half_width - 0.5).ceil().max(0.0) as i64;
+                (-reach..reach + 1)
+                    .map(|offset| {
+                        let offset = offset.abs() as f64;
+                        (half_width - (offset - 0.5)).clamp(0.0, 1.0)
+                    })
+                    .collect()
+            }
+            SmoothingKernel::Gaussian => {
+                let sigma = self.width / FWHM_PER_SIGMA;
+                let reach = (GAUSSIAN_EXTENT * sigma).ceil() as i64;
+                (-reach..reach + 1)
+                    .map(|offset| (-0.5 * (offset as f64 / sigma).powi(2)).exp())
+                    .collect()
+            }
+        };
+        let total: f64 = weights.iter().sum();
+        weights.iter().map(|weight| weight / total).collect()
+    }
+
+    /// Smooth the spectral axis of a cube
+    ///
+    /// # Arguments
+    ///
+    /// * `fits_cube` - The cube
+    /// * `axis` - The (FITS, 1-based) spectral axis of the cube
+    ///
+    /// # Returns
+    ///
+    /// * `ArrayD<T>` - The smoothed cube, in standard layout
+    ///
+    pub(crate) fn smooth<T: FitsPixel>(&self, fits_cube: ArrayD<T>, axis: usize) -> ArrayD<T> {
+        let weights = self.weights();
+        let n_channels = fits_cube.shape()[fits_index_to_array_index(axis, fits_cube.ndim())];
+        map_spectra(fits_cube, axis, n_channels, |spectrum, smoothed| {
+            convolve(spectrum, &weights, smoothed)
+        })
+    }
+
+    /// The spectral resolution after smoothing, in the units of the spectral axis
+    ///
+    /// The width of the kernel is added in quadrature to the width of a
+    /// channel, the resolution of the unsmoothed spectrum.
+    pub fn resolution(&self) -> f64 {
+        (self.width.powi(2) + 1.0).sqrt() * self.channel_width
+    }
+
+    /// The kernel and the resolution it gives, for HISTORY cards and messages
+    pub fn describe(&self) -> String {
+        let kind = match self.kernel {
+            SmoothingKernel::Boxcar => "boxcar of width",
+            SmoothingKernel::Gaussian => "Gaussian of FWHM",
+        };
+        format!(
+            "{} {} channels, an effective resolution of {} {}",
+            kind,
+            self.width,
+            self.resolution(),
+            self.cunit
+        )
+        .trim_end()
+        .to_string()
+    }
+}
+
+/// Convolve a spectrum with a kernel, ignoring blank (NaN) channels
+///
+/// Each channel is the weighted sum of the finite channels under the
+/// kernel, divided by the sum of their weights, so blank channels and the
+/// ends of the spectrum do not pull the result towards zero. A channel with
+/// no finite channels under the kernel is blank.
+///
+/// # Arguments
+///
+/// * `spectrum` - The values of the channels
+/// * `weights` - The weights of the kernel, centred on the middle weight
+/// * `smoothed` - Filled with the smoothed spectrum
+///
+pub fn convolve(spectrum: &[f64], weights: &[f64], smoothed: &mut [f64]) {
+    let reach = (weights.len() / 2) as i64;
+    for (channel, value) in smoothed.iter_mut().enumerate() {
+        let mut sum = 0.0;
+        let mut total_weight = 0.0;
+        for (tap, weight) in weights.iter().enumerate() {
+            let source = channel as i64 + tap as i64 - reach;
+            if let Some(sample) = usize::try_from(source).ok().and_then(|source| spectrum.get(source)) {
+                if sample.is_finite() {
+                    sum += weight * sample;
+                    total_weight += weight;
+                }
+            }
+        }
+        *value = if total_weight > 0.0 {
+            sum / total_weight
+        } else {
+            f64::NAN
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ndarray::IxDyn;
+
+    fn smoothing(kernel: SmoothingKernel, width: f64) -> SpectralSmoothing {
+        SpectralSmoothing {
+            axis: 1,
+            kernel,
+            width,
+            channel_width: 1.0e6,
+            cunit: "Hz".to_string(),
+        }
+    }
+
+    /// The smoothed spectrum of a unit delta at channel 20 of 41, far enough from the ends not to be renormalised
+    fn smoothed_delta(smoothing: &SpectralSmoothing) -> Vec<f64> {
+        let mut delta = vec![0.0; 41];
+        delta[20] = 1.0;
+        let cube = ArrayD::from_shape_vec(IxDyn(&[41]), delta).unwrap();
+        smoothing.smooth(cube, 1).into_raw_vec_and_offset().0
+    }
+
+    #[test]
+    fn a_delta_is_smoothed_into_a_boxcar() {
+        let mut expected = vec![0.0; 41];
+        expected[18..23].fill(0.2);
+        assert_eq!(smoothed_delta(&smoothing(SmoothingKernel::Boxcar, 5.0)), expected);
+
+        // An even width has half weights at its ends
+        let mut expected = vec![0.0; 41];
+        expected[18..23].copy_from_slice(&[0.125, 0.25, 0.25, 0.25, 0.125]);
+        assert_eq!(smoothed_delta(&smoothing(SmoothingKernel::Boxcar, 4.0)), expected);
+    }
+
+    #[test]
+    fn a_delta_is_smoothed_into_a_gaussian() {
+        let fwhm = 3.0;
+        let sigma = fwhm / (2.0 * (2.0 * 2.0_f64.ln()).sqrt());
+        // The kernel reaches ceil(4 sigma) = 6 channels either side
+        let gaussian = |offset: f64| (-0.5 * (offset / sigma).powi(2)).exp();
+        let total: f64 = (-6..7).map(|offset| gaussian(offset as f64)).sum();
+        let smoothed = smoothed_delta(&smoothing(SmoothingKernel::Gaussian, fwhm));
+        for (channel, value) in smoothed.iter().enumerate() {
+            let offset = channel as f64 - 20.0;
+            let expected = if offset.abs() <= 6.0 {
+                gaussian(offset) / total
+            } else {
+                0.0
+            };
+            assert!(
+                (value - expected).abs() < 1e-15,
+                "{}: {} != {}",
+                channel,
+                value,
+                expected
+            );
+        }
+        // The half maximum is half the FWHM from the peak
+        assert!((gaussian(fwhm / 2.0) - 0.5).abs() < 1e-12);
+        assert!((smoothed.iter().sum::<f64>() - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn blanks_and_ends_do_not_pull_a_flat_spectrum_down() {
+        let mut spectrum = vec![3.0; 12];
+        spectrum[4] = f64::NAN;
+        spectrum[5] = f64::NAN;
+        let mut smoothed = vec![0.0; 12];
+        convolve(
+            &spectrum,
+            &smoothing(SmoothingKernel::Gaussian, 2.0).weights(),
+            &mut smoothed,
+        );
+        assert!(
+            smoothed.iter().all(|value| (value - 3.0).abs() < 1e-12),
+            "{:?}",
+            smoothed
+        );
+
+        // With only blanks under the kernel, the channel is blank
+        let mut smoothed = vec![0.0; 4];
+        convolve(&[f64::NAN; 4], &[1.0 / 3.0; 3], &mut smoothed);
+        assert!(smoothed.iter().all(|value| value.is_nan()));
+    }
+
+    #[test]
+    fn the_resolution_adds_the_kernel_to_a_channel_in_quadrature() {
+        let boxcar = smoothing(SmoothingKernel::Boxcar, 3.0);
+        assert_eq!(boxcar.resolution(), 10.0_f64.sqrt() * 1.0e6);
+        assert_eq!(
+            boxcar.describe(),
+            format!(
+                "boxcar of width 3 channels, an effective resolution of {} Hz",
+                10.0_f64.sqrt() * 1.0e6
+            )
+        );
+    }
+}
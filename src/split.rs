@@ -0,0 +1,166 @@
+use crate::header::replace_key;
+use fitsio::errors::Error;
+use fitsio::FitsFile;
+
+/// Keyword recording the file a chunk was cut from
+pub const CHUNK_PARENT_KEYWORD: &str = "FRPARENT";
+
+/// Keywords recording the axis, and the first and last (1-based) pixels of the parent, of a chunk
+pub const CHUNK_RANGE_KEYWORDS: [&str; 3] = ["FRCHAXIS", "FRCHBEG", "FRCHEND"];
+
+/// Name of the output file holding one slice of a split cube
+///
+/// The slice index is zero-padded to at least three digits, e.g.
+/// `cube.rot.fits` becomes `cube.rot.000.fits`, `cube.rot.001.fits`, ...
+///
+/// # Arguments
+///
+/// * `out_filename` - The name of the unsplit output file
+/// * `index` - The slice index
+/// * `n_slices` - The total number of slices
+///
+/// # Returns
+///
+/// * `String` - The name of the slice file
+///
+pub fn split_filename(out_filename: &str, index: usize, n_slices: usize) -> String {
+    let width = n_slices.saturating_sub(1).to_string().len().max(3);
+    let stem = out_filename.strip_suffix(".fits").unwrap_or(out_filename);
+    format!("{}.{:0width$}.fits", stem, index, width = width)
+}
+
+/// Shift the reference pixel of a split axis to match the slice position
+///
+/// A slice taken at (0-based) `index` along an axis starts `index` pixels
+/// into the original axis, so its CRPIX is `index` smaller.
+///
+/// # Arguments
+///
+/// * `filename` - The slice file
+/// * `fits_axis` - The (1-based) FITS axis the cube was split along
+/// * `index` - The slice index
+///
+pub fn shift_split_crpix(filename: &str, fits_axis: usize, index: usize) -> Result<(), Error> {
+    let mut fits_file = FitsFile::edit(filename)?;
+    let hdu = fits_file.primary_hdu()?;
+    let card = format!("CRPIX{}", fits_axis);
+    let crpix: f64 = match hdu.read_key(&mut fits_file, &card) {
+        Ok(crpix) => crpix,
+        // Nothing to shift
+        Err(_) => return Ok(()),
+    };
+    replace_key(&mut fits_file, &card, crpix - index as f64)
+}
+
+/// Name of the output file holding one chunk of a cube
+///
+/// The template may contain `{start}` and `{end}`, the first and last
+/// (1-based) pixels of the chunk along the parent axis, optionally
+/// zero-padded as in `{start:05}`. Without a template, the range is added
+/// before the extension, padded to the width of the axis length, e.g.
+/// `cube.rot.0001-0128.fits`.
+///
+/// # Arguments
+///
+/// * `template` - The file name template, if any
+/// * `out_filename` - The name of the unchunked output file
+/// * `start` - The first pixel of the chunk
+/// * `end` - The last pixel of the chunk
+/// * `length` - The length of the chunked axis
+///
+/// # Returns
+///
+/// * `String` - The name of the chunk file
+///
+pub fn chunk_filename(template: Option<&str>, out_filename: &str, start: usize, end: usize, length: usize) -> String {
+    let template = match template {
+        Some(template) => template.to_string(),
+        None => {
+            let width = length.to_string().len();
+            let stem = out_filename.strip_suffix(".fits").unwrap_or(out_filename);
+            format!("{}.{{start:0{width}}}-{{end:0{width}}}.fits", stem, width = width)
+        }
+    };
+    let mut name = String::with_capacity(template.len());
+    let mut rest = template.as_str();
+    while let Some(open) = rest.find('{') {
+        name.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('}').map(|close| open + close) else {
+            break;
+        };
+        let field = &rest[open + 1..close];
+        let (key, width) = match field.split_once(':') {
+            Some((key, spec)) => (key, spec.trim_start_matches('0').parse::<usize>().ok()),
+            None => (field, None),
+        };
+        let value = match key {
+            "start" => Some(start),
+            "end" => Some(end),
+            _ => None,
+        };
+        match value {
+            Some(value) => name.push_str(&format!("{:0width$}", value, width = width.unwrap_or(0))),
+            // Anything else is kept as it is
+            None => name.push_str(&rest[open..close + 1]),
+        }
+        rest = &rest[close + 1..];
+    }
+    name.push_str(rest);
+    name
+}
+
+/// Record where a chunk was cut from in its header
+///
+/// # Arguments
+///
+/// * `filename` - The chunk file
+/// * `parent` - The file the chunk was cut from
+/// * `fits_axis` - The (1-based) FITS axis of the chunk that was cut
+/// * `start` - The first (1-based) pixel of the parent axis in the chunk
+/// * `end` - The last (1-based) pixel of the parent axis in the chunk
+///
+pub fn write_chunk_keys(filename: &str, parent: &str, fits_axis: usize, start: usize, end: usize) -> Result<(), Error> {
+    let mut fits_file = FitsFile::edit(filename)?;
+    let hdu = fits_file.primary_hdu()?;
+    hdu.write_key(&mut fits_file, CHUNK_PARENT_KEYWORD, parent)?;
+    for (keyword, value) in CHUNK_RANGE_KEYWORDS.iter().zip([fits_axis, start, end]) {
+        hdu.write_key(&mut fits_file, keyword, value as i64)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slice_indices_are_padded_to_three_digits_or_more() {
+        assert_eq!(split_filename("cube.rot.fits", 0, 4), "cube.rot.000.fits");
+        assert_eq!(split_filename("cube.rot.fits", 1234, 2000), "cube.rot.1234.fits");
+        assert_eq!(split_filename("cube", 7, 10), "cube.007.fits");
+    }
+
+    #[test]
+    fn chunk_names_fill_in_the_start_and_end_of_the_template() {
+        assert_eq!(
+            chunk_filename(Some("part_{start:05}_{end:05}.fits"), "cube.rot.fits", 129, 256, 300),
+            "part_00129_00256.fits"
+        );
+        assert_eq!(
+            chunk_filename(Some("{start}-{end}.fits"), "cube.rot.fits", 1, 8, 10),
+            "1-8.fits"
+        );
+        // Unknown fields and unclosed braces are kept
+        assert_eq!(
+            chunk_filename(Some("{mode}_{start}.fits"), "x", 3, 4, 4),
+            "{mode}_3.fits"
+        );
+        assert_eq!(chunk_filename(Some("c{start}{end"), "x", 3, 4, 4), "c3{end");
+        // Without a template, the range is padded to the width of the axis length
+        assert_eq!(
+            chunk_filename(None, "cube.rot.fits", 1, 128, 1000),
+            "cube.rot.0001-0128.fits"
+        );
+        assert_eq!(chunk_filename(None, "cube.rot.fits", 9, 10, 10), "cube.rot.09-10.fits");
+    }
+}
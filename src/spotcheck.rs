@@ -0,0 +1,202 @@
+use crate::error::FitsRotateError;
+use crate::stokes::StokesPlane;
+use fitsio::hdu::HduInfo;
+use fitsio::images::ImageType;
+use fitsio::FitsFile;
+
+/// A small, seedable pseudo-random number generator (SplitMix64)
+///
+/// The samples only need to be spread evenly and reproducible from a
+/// seed, not to be cryptographically random.
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    /// Start a generator from a seed
+    pub fn new(seed: u64) -> Self {
+        SplitMix64 { state: seed }
+    }
+
+    /// The next pseudo-random number
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A pseudo-random (1-based) pixel of an axis of `length` pixels
+    pub fn pixel(&mut self, length: usize) -> usize {
+        (self.next_u64() % length as u64) as usize + 1
+    }
+}
+
+/// The environment variable that makes a debug build corrupt every nth voxel of its outputs, to test `--spot-check`
+#[cfg(debug_assertions)]
+const CORRUPT_EVERY_ENV: &str = "FITSROTATE_RS_TEST_CORRUPT_EVERY";
+
+/// A seed for `--spot-check` when none is given, from the clock
+pub fn default_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |time| time.as_nanos() as u64)
+}
+
+/// The shape (in FITS order) and type of the primary image of an open FITS file
+fn image_shape(fits_file: &mut FitsFile, filename: &str) -> Result<(Vec<usize>, ImageType), FitsRotateError> {
+    let hdu = fits_file.primary_hdu()?;
+    match &hdu.info {
+        HduInfo::ImageInfo { shape, image_type } => Ok((shape.iter().rev().copied().collect(), *image_type)),
+        _ => Err(FitsRotateError::Message(format!(
+            "The primary HDU of {} is not an image",
+            filename
+        ))),
+    }
+}
+
+/// Read a single voxel of the primary image of a FITS file
+///
+/// # Arguments
+///
+/// * `fits_file` - The FITS file
+/// * `shape` - The shape of the image, in FITS order
+/// * `pixel` - The (1-based) pixel, in FITS order
+///
+/// # Returns
+///
+/// * `Result<f64, FitsRotateError>` - The value of the voxel
+///
+fn read_voxel(fits_file: &mut FitsFile, shape: &[usize], pixel: &[usize]) -> Result<f64, FitsRotateError> {
+    // FITS axis 1 varies fastest
+    let mut offset = 0;
+    for (length, p) in shape.iter().zip(pixel).rev() {
+        offset = offset * length + (p - 1);
+    }
+    let hdu = fits_file.primary_hdu()?;
+    let values: Vec<f64> = hdu.read_section(fits_file, offset, offset + 1)?;
+    Ok(values[0])
+}
+
+/// Corrupt every nth voxel of a written output, if `FITSROTATE_RS_TEST_CORRUPT_EVERY` is set to n
+///
+/// This is a hook for the tests of `--spot-check`, and only exists in
+/// debug builds. One is added to each corrupted voxel.
+///
+/// # Arguments
+///
+/// * `out_filename` - The rotated FITS file
+///
+#[cfg(debug_assertions)]
+pub fn corrupt_for_test(out_filename: &str) -> Result<(), FitsRotateError> {
+    let every = match std::env::var(CORRUPT_EVERY_ENV).ok().and_then(|every| every.parse::<usize>().ok()) {
+        Some(every) if every > 0 => every,
+        _ => return Ok(()),
+    };
+    let mut fits_file = FitsFile::edit(out_filename)?;
+    let (shape, _) = image_shape(&mut fits_file, out_filename)?;
+    let hdu = fits_file.primary_hdu()?;
+    for offset in (0..shape.iter().product()).step_by(every) {
+        let value: Vec<f64> = hdu.read_section(&mut fits_file, offset, offset + 1)?;
+        hdu.write_section(&mut fits_file, offset, offset + 1, &[value[0] + 1.0])?;
+    }
+    Ok(())
+}
+
+/// Check a sample of the voxels of a rotated file against its input
+///
+/// Each sampled voxel of the input is mapped to the output through the
+/// mode, the reversed axes and the selected Stokes plane, and the two are
+/// read on their own, so the cost depends on the number of samples rather
+/// than the size of the cube. Padding only adds pixels after the end of
+/// each axis, so it does not move any voxel. A float32 output is compared
+/// with the input rounded to float32, as it was written.
+///
+/// # Arguments
+///
+/// * `filename` - The input FITS file
+/// * `out_filename` - The rotated FITS file
+/// * `mode` - The input axis of each output axis
+/// * `flipped` - The (FITS, 1-based) output axes that were reversed
+/// * `stokes` - The Stokes plane that was kept, if any
+/// * `n_samples` - The number of voxels to check
+/// * `seed` - The seed of the sample
+///
+/// # Returns
+///
+/// * `Result<(), FitsRotateError>` - An error describing the first voxel that differs
+///
+pub fn spot_check(
+    filename: &str,
+    out_filename: &str,
+    mode: &[usize],
+    flipped: &[usize],
+    stokes: Option<&StokesPlane>,
+    n_samples: usize,
+    seed: u64,
+) -> Result<(), FitsRotateError> {
+    let mut in_file = FitsFile::open(filename)?;
+    let (in_shape, _) = image_shape(&mut in_file, filename)?;
+    let mut out_file = FitsFile::open(out_filename)?;
+    let (out_shape, out_type) = image_shape(&mut out_file, out_filename)?;
+    let float32 = matches!(out_type, ImageType::Float);
+
+    let mut rng = SplitMix64::new(seed);
+    let mut in_pixel: Vec<usize> = vec![1; in_shape.len()];
+    let mut out_pixel: Vec<usize> = vec![1; out_shape.len()];
+    if let Some(stokes) = stokes {
+        in_pixel[stokes.axis - 1] = stokes.plane;
+    }
+    for _ in 0..n_samples {
+        for (out_idx, in_axis) in mode.iter().enumerate() {
+            let on_plane = stokes.is_some_and(|stokes| stokes.axis == *in_axis);
+            let (p, length) = if on_plane {
+                (1, 1)
+            } else {
+                let length = in_shape[in_axis - 1];
+                let p = rng.pixel(length);
+                in_pixel[in_axis - 1] = p;
+                (p, length)
+            };
+            out_pixel[out_idx] = if flipped.contains(&(out_idx + 1)) {
+                length + 1 - p
+            } else {
+                p
+            };
+        }
+        let expected = read_voxel(&mut in_file, &in_shape, &in_pixel)?;
+        let expected = if float32 { expected as f32 as f64 } else { expected };
+        let actual = read_voxel(&mut out_file, &out_shape, &out_pixel)?;
+        let same = expected == actual || (expected.is_nan() && actual.is_nan());
+        if !same {
+            return Err(FitsRotateError::Message(format!(
+                "Spot check of {} failed: input voxel {:?} is {} but output voxel {:?} is {} (seed {})",
+                out_filename, in_pixel, expected, out_pixel, actual, seed
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn samples_are_reproducible_from_the_seed_and_cover_the_axis() {
+        let sample = |seed| {
+            let mut rng = SplitMix64::new(seed);
+            (0..1000).map(|_| rng.pixel(7)).collect::<Vec<usize>>()
+        };
+        assert_eq!(sample(42), sample(42));
+        assert_ne!(sample(42), sample(43));
+        let pixels = sample(42);
+        assert!(pixels.iter().all(|p| (1..=7).contains(p)));
+        // Each pixel is drawn about 1000 / 7 = 143 times
+        for p in 1..=7 {
+            let count = pixels.iter().filter(|pixel| **pixel == p).count();
+            assert!((100..190).contains(&count), "pixel {} drawn {} times", p, count);
+        }
+    }
+}
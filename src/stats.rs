@@ -0,0 +1,266 @@
+use crate::error::FitsRotateError;
+use fitsio::errors::{check_status, Error};
+use fitsio::hdu::HduInfo;
+use fitsio::images::ImageType;
+use fitsio::FitsFile;
+use std::ffi::CString;
+
+/// The number of pixels read at a time
+const STATS_CHUNK: usize = 1 << 20;
+
+/// Summary statistics of the pixels of an image
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PixelStats {
+    /// The number of pixels
+    pub n_pixels: usize,
+    /// The number of blank (NaN) pixels
+    pub n_blank: usize,
+    /// The smallest value, or NaN if every pixel is blank
+    pub min: f64,
+    /// The largest value, or NaN if every pixel is blank
+    pub max: f64,
+    /// The mean value, or NaN if every pixel is blank
+    pub mean: f64,
+}
+
+/// Running totals of the pixels seen so far
+#[derive(Default)]
+struct Totals {
+    n_pixels: usize,
+    n_blank: usize,
+    sum: f64,
+    min: Option<f64>,
+    max: Option<f64>,
+}
+
+impl Totals {
+    /// Add a pixel, counting it as blank if it is NaN
+    fn add(&mut self, value: f64) {
+        self.n_pixels += 1;
+        if value.is_nan() {
+            self.n_blank += 1;
+            return;
+        }
+        self.sum += value;
+        self.min = Some(self.min.map_or(value, |min| min.min(value)));
+        self.max = Some(self.max.map_or(value, |max| max.max(value)));
+    }
+
+    /// The statistics of the pixels seen
+    fn finish(self) -> PixelStats {
+        PixelStats {
+            n_pixels: self.n_pixels,
+            n_blank: self.n_blank,
+            min: self.min.unwrap_or(f64::NAN),
+            max: self.max.unwrap_or(f64::NAN),
+            mean: self.sum / (self.n_pixels - self.n_blank) as f64,
+        }
+    }
+}
+
+impl PixelStats {
+    /// Read the statistics of an image HDU, a chunk of pixels at a time
+    ///
+    /// # Arguments
+    ///
+    /// * `fits_file` - The FITS file
+    /// * `hdu_idx` - The image HDU, which may be tile-compressed
+    ///
+    pub fn read(fits_file: &mut FitsFile, hdu_idx: usize) -> Result<Self, Error> {
+        let hdu = fits_file.hdu(hdu_idx)?;
+        let n_pixels: usize = match &hdu.info {
+            HduInfo::ImageInfo { shape, .. } => shape.iter().product(),
+            _ => 0,
+        };
+        let mut totals = Totals::default();
+        for start in (0..n_pixels).step_by(STATS_CHUNK) {
+            let end = (start + STATS_CHUNK).min(n_pixels);
+            let chunk: Vec<f64> = hdu.read_section(fits_file, start, end)?;
+            chunk.into_iter().for_each(|value| totals.add(value));
+        }
+        Ok(totals.finish())
+    }
+
+    /// The differences from the statistics of another image
+    ///
+    /// The number of pixels and of blank pixels must match exactly, and the
+    /// minimum, maximum and mean to within `error`, the largest difference
+    /// allowed between any two pixels.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - The statistics of the other image
+    /// * `error` - The largest difference allowed between a pixel and its counterpart
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<String>` - A description of each difference, empty if they agree
+    ///
+    pub fn differences(&self, other: &PixelStats, error: f64) -> Vec<String> {
+        let mut differences = Vec::new();
+        for (name, this, that) in [
+            ("pixels", self.n_pixels, other.n_pixels),
+            ("blank pixels", self.n_blank, other.n_blank),
+        ] {
+            if this != that {
+                differences.push(format!("{} {} != {}", name, this, that));
+            }
+        }
+        for (name, this, that) in [
+            ("minimum", self.min, other.min),
+            ("maximum", self.max, other.max),
+            ("mean", self.mean, other.mean),
+        ] {
+            let same = (this.is_nan() && that.is_nan()) || (this - that).abs() <= error;
+            if !same {
+                differences.push(format!("{} {} != {} (allowing {})", name, this, that, error));
+            }
+        }
+        differences
+    }
+}
+
+/// The first HDU of a file with an image, which is an extension in a tile-compressed file
+pub fn image_hdu(fits_file: &mut FitsFile) -> Result<usize, Error> {
+    for hdu_idx in 0..fits_file.num_hdus()? {
+        if matches!(fits_file.hdu(hdu_idx)?.info, HduInfo::ImageInfo { ref shape, .. } if !shape.is_empty()) {
+            return Ok(hdu_idx);
+        }
+    }
+    Ok(0)
+}
+
+/// The largest error in a pixel of a quantized, tile-compressed image
+///
+/// Each tile of floating point pixels is quantized in steps of its ZSCALE,
+/// and a dithered or rounded value is at most half a step away from the
+/// original. Images that are not quantized have no error.
+///
+/// # Arguments
+///
+/// * `fits_file` - The FITS file
+/// * `hdu_idx` - The image HDU
+///
+/// # Returns
+///
+/// * `Result<f64, Error>` - Half the largest ZSCALE of the tiles, or 0
+///
+pub fn quantization_error(fits_file: &mut FitsFile, hdu_idx: usize) -> Result<f64, Error> {
+    let hdu = fits_file.hdu(hdu_idx)?;
+    let quantized = hdu
+        .read_key::<String>(fits_file, "ZQUANTIZ")
+        .is_ok_and(|method| method.trim() != "NONE");
+    if !quantized {
+        return Ok(0.0);
+    }
+    // The tiles of a compressed image are the rows of its binary table
+    let column = CString::new("ZSCALE").unwrap();
+    let (mut colnum, mut n_rows, mut anynul, mut status) = (0, 0, 0, 0);
+    unsafe {
+        fitsio::sys::ffgcno(
+            fits_file.as_raw(),
+            0,
+            column.as_ptr() as *mut _,
+            &mut colnum,
+            &mut status,
+        );
+        fitsio::sys::ffgnrwll(fits_file.as_raw(), &mut n_rows, &mut status);
+    }
+    check_status(status)?;
+    let mut scales = vec![0.0_f64; n_rows as usize];
+    unsafe {
+        fitsio::sys::ffgcvd(
+            fits_file.as_raw(),
+            colnum,
+            1,
+            1,
+            n_rows,
+            0.0,
+            scales.as_mut_ptr(),
+            &mut anynul,
+            &mut status,
+        );
+    }
+    check_status(status)?;
+    Ok(scales.iter().fold(0.0, |max: f64, scale| max.max(scale.abs())) / 2.0)
+}
+
+/// Compare the statistics of the pixels of a rotated file with those of its input
+///
+/// A rotation or reversal of the axes only moves the pixels, so the
+/// statistics agree, up to the rounding of the sums (`tolerance`, relative
+/// to the largest value), the rounding of double precision input to a
+/// float32 output, and the error of a quantized output.
+///
+/// # Arguments
+///
+/// * `filename` - The input FITS file
+/// * `out_filename` - The rotated FITS file
+/// * `tolerance` - The relative tolerance of the comparison
+///
+/// # Returns
+///
+/// * `Result<(f64, Vec<String>), FitsRotateError>` - The error allowed in a pixel, and the differences
+///
+pub fn compare_stats(
+    filename: &str,
+    out_filename: &str,
+    tolerance: f64,
+) -> Result<(f64, Vec<String>), FitsRotateError> {
+    let mut in_file = FitsFile::open(filename)?;
+    let in_hdu = image_hdu(&mut in_file)?;
+    let input = PixelStats::read(&mut in_file, in_hdu)?;
+    let mut out_file = FitsFile::open(out_filename)?;
+    let out_hdu = image_hdu(&mut out_file)?;
+    let output = PixelStats::read(&mut out_file, out_hdu)?;
+
+    let largest = input.min.abs().max(input.max.abs());
+    let largest = if largest.is_nan() { 0.0 } else { largest };
+    let mut relative = tolerance;
+    let narrowed = |fits_file: &mut FitsFile, hdu_idx| -> Result<bool, Error> {
+        Ok(matches!(fits_file.hdu(hdu_idx)?.info, HduInfo::ImageInfo { image_type: ImageType::Float, .. }))
+    };
+    if narrowed(&mut out_file, out_hdu)? && !narrowed(&mut in_file, in_hdu)? {
+        relative += f32::EPSILON as f64;
+    }
+    let error = quantization_error(&mut out_file, out_hdu)? + relative * largest;
+    Ok((error, input.differences(&output, error)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The statistics of a sequence of pixel values, leaving out the blank ones
+    fn stats_of(values: impl IntoIterator<Item = f64>) -> PixelStats {
+        let mut totals = Totals::default();
+        values.into_iter().for_each(|value| totals.add(value));
+        totals.finish()
+    }
+
+    #[test]
+    fn blank_pixels_are_counted_but_left_out_of_the_statistics() {
+        let stats = stats_of([3.0, f64::NAN, -1.0, 4.0, f64::NAN]);
+        assert_eq!(stats.n_pixels, 5);
+        assert_eq!(stats.n_blank, 2);
+        assert_eq!((stats.min, stats.max, stats.mean), (-1.0, 4.0, 2.0));
+
+        let blank = stats_of([f64::NAN, f64::NAN]);
+        assert_eq!((blank.n_pixels, blank.n_blank), (2, 2));
+        assert!(blank.min.is_nan() && blank.max.is_nan() && blank.mean.is_nan());
+        assert!(blank.differences(&blank, 0.0).is_empty());
+    }
+
+    #[test]
+    fn statistics_differ_beyond_the_error_allowed() {
+        let stats = stats_of([1.0, 2.0, 3.0]);
+        let shifted = stats_of([1.1, 2.1, 3.1]);
+        assert!(stats.differences(&stats, 0.0).is_empty());
+        assert!(stats.differences(&shifted, 0.11).is_empty());
+        assert_eq!(stats.differences(&shifted, 0.05).len(), 3);
+
+        let fewer = stats_of([1.0, 3.0]);
+        let differences = stats.differences(&fewer, 0.0);
+        assert_eq!(differences, vec!["pixels 3 != 2".to_string()]);
+    }
+}
@@ -0,0 +1,190 @@
+use crate::axes::{axis_role, read_ctypes, AxisRole};
+use crate::cube::FitsCube;
+use crate::error::FitsRotateError;
+use crate::permutation::fits_index_to_array_index;
+use fitsio::FitsFile;
+use ndarray::{ArrayD, Axis, Slice};
+
+/// The Stokes parameters and their codes in the FITS WCS convention
+/// (Greisen & Calabretta 2002): positive codes are the Stokes parameters,
+/// negative codes the circular (RR, LL, ...) and linear (XX, YY, ...)
+/// correlations
+pub const STOKES_CODES: [(&str, i64); 12] = [
+    ("I", 1),
+    ("Q", 2),
+    ("U", 3),
+    ("V", 4),
+    ("RR", -1),
+    ("LL", -2),
+    ("RL", -3),
+    ("LR", -4),
+    ("XX", -5),
+    ("YY", -6),
+    ("XY", -7),
+    ("YX", -8),
+];
+
+/// The code of a Stokes parameter, e.g. 1 for I or -5 for XX (case-insensitive)
+pub fn stokes_code(label: &str) -> Option<i64> {
+    STOKES_CODES
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(label.trim()))
+        .map(|(_, code)| *code)
+}
+
+/// The name of a Stokes code, or the code itself if it is not a known parameter
+pub fn stokes_label(code: i64) -> String {
+    STOKES_CODES
+        .iter()
+        .find(|(_, known)| *known == code)
+        .map_or(code.to_string(), |(name, _)| name.to_string())
+}
+
+/// A single plane of the Stokes axis, chosen with `--stokes`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StokesPlane {
+    /// The (FITS, 1-based) Stokes axis of the input
+    pub axis: usize,
+    /// The (1-based) pixel of the plane along the Stokes axis
+    pub plane: usize,
+    /// The Stokes code of the plane
+    pub code: i64,
+    /// Keep the Stokes axis, with length 1, rather than dropping it
+    pub keep_axis: bool,
+}
+
+impl StokesPlane {
+    /// Find the plane of a Stokes parameter in a FITS file
+    ///
+    /// The code of pixel `p` of the Stokes axis is `CRVAL + (p - CRPIX) * CDELT`.
+    ///
+    /// # Arguments
+    ///
+    /// * `fits_file` - The FITS file
+    /// * `filename` - The name of the FITS file, for error messages
+    /// * `label` - The Stokes parameter, e.g. `I` or `XX`
+    /// * `keep_axis` - Keep the Stokes axis, with length 1, rather than dropping it
+    ///
+    /// # Returns
+    ///
+    /// * `Result<StokesPlane, FitsRotateError>` - The plane
+    ///
+    pub fn find(
+        fits_file: &mut FitsFile,
+        filename: &str,
+        label: &str,
+        keep_axis: bool,
+    ) -> Result<StokesPlane, FitsRotateError> {
+        let code = stokes_code(label).ok_or_else(|| {
+            let known: Vec<&str> = STOKES_CODES.iter().map(|(name, _)| *name).collect();
+            FitsRotateError::Message(format!(
+                "{} is not a Stokes parameter (expected one of {})",
+                label,
+                known.join(", ")
+            ))
+        })?;
+        let ctypes = read_ctypes(fits_file).map_err(|e| FitsRotateError::unreadable_hdu(filename, e))?;
+        let axis = ctypes
+            .iter()
+            .position(|ctype| axis_role(ctype) == Some(AxisRole::Stokes))
+            .map(|idx| idx + 1)
+            .ok_or_else(|| {
+                FitsRotateError::Message(format!(
+                    "{} has no STOKES axis (CTYPEs: {})",
+                    filename,
+                    ctypes.join(", ")
+                ))
+            })?;
+        let hdu = fits_file.primary_hdu()?;
+        let length = hdu.read_key::<i64>(fits_file, &format!("NAXIS{}", axis))? as usize;
+        let number = |fits_file: &mut FitsFile, stub: &str, default: f64| {
+            hdu.read_key::<f64>(fits_file, &format!("{}{}", stub, axis))
+                .unwrap_or(default)
+        };
+        let crval = number(fits_file, "CRVAL", 1.0);
+        let cdelt = number(fits_file, "CDELT", 1.0);
+        let crpix = number(fits_file, "CRPIX", 1.0);
+        let codes: Vec<i64> = (1..length + 1)
+            .map(|pixel| (crval + (pixel as f64 - crpix) * cdelt).round() as i64)
+            .collect();
+        match codes.iter().position(|plane_code| *plane_code == code) {
+            Some(idx) => Ok(StokesPlane {
+                axis,
+                plane: idx + 1,
+                code,
+                keep_axis,
+            }),
+            None => {
+                let available: Vec<String> = codes.iter().map(|code| stokes_label(*code)).collect();
+                Err(FitsRotateError::Message(format!(
+                    "Stokes {} is not in {}, which has {}",
+                    stokes_label(code),
+                    filename,
+                    available.join(", ")
+                )))
+            }
+        }
+    }
+
+    /// Take the plane out of a cube
+    ///
+    /// # Arguments
+    ///
+    /// * `fits_cube` - The cube, with the axes of the input
+    ///
+    /// # Returns
+    ///
+    /// * `Result<ArrayD<T>, FitsRotateError>` - The plane, with the Stokes axis of length 1 or dropped
+    ///
+    pub fn select<T: Copy + From<f32>>(&self, fits_cube: ArrayD<T>) -> Result<ArrayD<T>, FitsRotateError> {
+        let array_idx = fits_index_to_array_index(self.axis, fits_cube.ndim());
+        if self.keep_axis {
+            Ok(fits_cube
+                .slice_axis(Axis(array_idx), Slice::from(self.plane - 1..self.plane))
+                .to_owned())
+        } else {
+            Ok(FitsCube::new(fits_cube).slice_at(array_idx, self.plane - 1)?.into_array())
+        }
+    }
+
+    /// The shape of the selected plane
+    ///
+    /// # Arguments
+    ///
+    /// * `shape` - The shape of the input, in array order
+    ///
+    /// # Returns
+    ///
+    /// * `Vec<usize>` - The shape after `select`, in array order
+    ///
+    pub fn shape(&self, shape: &[usize]) -> Vec<usize> {
+        let array_idx = fits_index_to_array_index(self.axis, shape.len());
+        let mut shape = shape.to_vec();
+        if self.keep_axis {
+            shape[array_idx] = 1;
+        } else {
+            shape.remove(array_idx);
+        }
+        shape
+    }
+
+    /// The input axis of an axis of the selected plane
+    ///
+    /// Dropping the Stokes axis moves the axes after it down by one.
+    ///
+    /// # Arguments
+    ///
+    /// * `axis` - The (FITS, 1-based) axis of the selected plane
+    ///
+    /// # Returns
+    ///
+    /// * `usize` - The (FITS, 1-based) axis of the input
+    ///
+    pub fn input_axis(&self, axis: usize) -> usize {
+        if !self.keep_axis && axis >= self.axis {
+            axis + 1
+        } else {
+            axis
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use fitsio::errors::Error;
+use fitsio::FitsFile;
+
+/// Keywords compared as floating point values between the files of a triplet
+const NUMERIC_CARD_STUBS: [&str; 3] = ["CRVAL", "CDELT", "CRPIX"];
+
+/// Columns of the BEAMS table compared between the files of a triplet
+const BEAM_COLUMNS: [&str; 3] = ["BMAJ", "BMIN", "BPA"];
+
+/// The header values of one file that must agree across a triplet
+#[derive(Debug)]
+struct TripletSummary {
+    filename: String,
+    naxis: Vec<i64>,
+    ctypes: Vec<Option<String>>,
+    numeric: Vec<(String, Option<f64>)>,
+    beams: Option<Vec<(String, Vec<f64>)>>,
+}
+
+/// Read the header values of a file needed for the triplet checks
+///
+/// Only the headers (and the BEAMS table, if any) are read; the image data
+/// is left on disk.
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+///
+/// # Returns
+///
+/// * `Result<TripletSummary, Error>` - The header values
+///
+fn read_summary(filename: &str) -> Result<TripletSummary, Error> {
+    let mut fits_file = FitsFile::open(filename)?;
+    let hdu = fits_file.primary_hdu()?;
+    let naxis: i64 = hdu.read_key(&mut fits_file, "NAXIS")?;
+
+    let mut naxes: Vec<i64> = Vec::new();
+    let mut ctypes: Vec<Option<String>> = Vec::new();
+    let mut numeric: Vec<(String, Option<f64>)> = Vec::new();
+    for fits_idx in 1..naxis + 1 {
+        naxes.push(hdu.read_key(&mut fits_file, &format!("NAXIS{}", fits_idx))?);
+        // Trailing spaces pad string values and are not significant
+        let ctype: Option<String> = hdu.read_key(&mut fits_file, &format!("CTYPE{}", fits_idx)).ok();
+        ctypes.push(ctype.map(|ctype| ctype.trim_end().to_string()));
+        for card_stub in NUMERIC_CARD_STUBS {
+            let card = format!("{}{}", card_stub, fits_idx);
+            let value: Option<f64> = hdu.read_key(&mut fits_file, &card).ok();
+            numeric.push((card, value));
+        }
+    }
+
+    let beams = match fits_file.hdu("BEAMS") {
+        Ok(beams_hdu) => {
+            let mut columns = Vec::new();
+            for column in BEAM_COLUMNS {
+                let values: Vec<f64> = beams_hdu.read_col(&mut fits_file, column)?;
+                columns.push((column.to_string(), values));
+            }
+            Some(columns)
+        }
+        Err(_) => None,
+    };
+
+    Ok(TripletSummary {
+        filename: filename.to_string(),
+        naxis: naxes,
+        ctypes,
+        numeric,
+        beams,
+    })
+}
+
+/// Check if two floating point values agree within a relative tolerance
+pub fn within_tolerance(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() <= tolerance * a.abs().max(b.abs()).max(1.0)
+}
+
+/// Describe every difference between two triplet summaries
+fn compare_summaries(a: &TripletSummary, b: &TripletSummary, tolerance: f64) -> Vec<String> {
+    let mut mismatches: Vec<String> = Vec::new();
+    let pair = format!("{} and {}", a.filename, b.filename);
+
+    if a.naxis.len() != b.naxis.len() {
+        mismatches.push(format!(
+            "NAXIS differs between {}: {} vs {}",
+            pair,
+            a.naxis.len(),
+            b.naxis.len()
+        ));
+        // Nothing else lines up if the number of axes differs
+        return mismatches;
+    }
+
+    for (idx, (na, nb)) in a.naxis.iter().zip(&b.naxis).enumerate() {
+        if na != nb {
+            mismatches.push(format!("NAXIS{} differs between {}: {} vs {}", idx + 1, pair, na, nb));
+        }
+    }
+    for (idx, (ca, cb)) in a.ctypes.iter().zip(&b.ctypes).enumerate() {
+        if ca != cb {
+            mismatches.push(format!(
+                "CTYPE{} differs between {}: {:?} vs {:?}",
+                idx + 1,
+                pair,
+                ca,
+                cb
+            ));
+        }
+    }
+    for ((card, va), (_, vb)) in a.numeric.iter().zip(&b.numeric) {
+        let agree = match (va, vb) {
+            (Some(va), Some(vb)) => within_tolerance(*va, *vb, tolerance),
+            (None, None) => true,
+            _ => false,
+        };
+        if !agree {
+            mismatches.push(format!("{} differs between {}: {:?} vs {:?}", card, pair, va, vb));
+        }
+    }
+
+    match (&a.beams, &b.beams) {
+        (Some(beams_a), Some(beams_b)) => {
+            for ((column, va), (_, vb)) in beams_a.iter().zip(beams_b) {
+                if va.len() != vb.len() {
+                    mismatches.push(format!(
+                        "BEAMS {} differs between {}: {} vs {} rows",
+                        column,
+                        pair,
+                        va.len(),
+                        vb.len()
+                    ));
+                    continue;
+                }
+                for (row, (xa, xb)) in va.iter().zip(vb).enumerate() {
+                    if !within_tolerance(*xa, *xb, tolerance) {
+                        mismatches.push(format!(
+                            "BEAMS {} (row {}) differs between {}: {} vs {}",
+                            column, row, pair, xa, xb
+                        ));
+                    }
+                }
+            }
+        }
+        (None, None) => {}
+        _ => mismatches.push(format!("BEAMS table present in only one of {}", pair)),
+    }
+
+    mismatches
+}
+
+/// Check that all files of a Stokes triplet share the same axes
+///
+/// The NAXISn, CTYPEn, CRVALn, CDELTn and CRPIXn keywords of every file
+/// are compared against the first file, as is the BEAMS table if present.
+/// Floating point values are compared with a relative tolerance.
+///
+/// # Arguments
+///
+/// * `filenames` - The FITS files (e.g. I, Q, U and optionally V)
+/// * `tolerance` - Relative tolerance for floating point values
+///
+/// # Returns
+///
+/// * `Result<(), Error>` - An error listing every mismatch found
+///
+pub fn check_triplet(filenames: &[String], tolerance: f64) -> Result<(), Error> {
+    let summaries = filenames
+        .iter()
+        .map(|filename| read_summary(filename))
+        .collect::<Result<Vec<TripletSummary>, Error>>()?;
+
+    let mut mismatches: Vec<String> = Vec::new();
+    for other in &summaries[1..] {
+        mismatches.extend(compare_summaries(&summaries[0], other, tolerance));
+    }
+    if !mismatches.is_empty() {
+        return Err(Error::Message(format!(
+            "Input files are not consistent:\n  {}",
+            mismatches.join("\n  ")
+        )));
+    }
+    Ok(())
+}
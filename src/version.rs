@@ -0,0 +1,92 @@
+use crate::error::FitsRotateError;
+
+/// The crates.io API endpoint describing this crate
+#[cfg(feature = "version-check")]
+pub const CRATES_IO_URL: &str = "https://crates.io/api/v1/crates/fitsrotate_rs";
+
+/// The changelog of this crate, embedded at build time for `--changelog`
+pub const CHANGELOG: &str = include_str!("../CHANGELOG.md");
+
+/// Extract the `max_version` field from a crates.io API response
+///
+/// # Arguments
+///
+/// * `body` - The JSON response
+///
+/// # Returns
+///
+/// * `Option<String>` - The latest version, if it could be found
+///
+#[cfg(feature = "version-check")]
+pub fn parse_max_version(body: &str) -> Option<String> {
+    let key = "\"max_version\"";
+    let rest = &body[body.find(key)? + key.len()..];
+    let rest = rest.trim_start().strip_prefix(':')?.trim_start().strip_prefix('"')?;
+    Some(rest[..rest.find('"')?].to_string())
+}
+
+/// Check if a version is newer than another
+///
+/// Versions are compared by their numeric `major.minor.patch` parts; any
+/// pre-release or build suffix is ignored.
+///
+/// # Arguments
+///
+/// * `latest` - The latest version
+/// * `current` - The running version
+///
+/// # Returns
+///
+/// * `bool` - True if `latest` is newer than `current`
+///
+#[cfg(feature = "version-check")]
+pub fn is_newer(latest: &str, current: &str) -> bool {
+    let parts = |version: &str| -> Vec<u64> {
+        version
+            .split(['-', '+'])
+            .next()
+            .unwrap_or("")
+            .split('.')
+            .map(|part| part.parse::<u64>().unwrap_or(0))
+            .collect()
+    };
+    parts(latest) > parts(current)
+}
+
+/// Compare the running version with the latest release on crates.io
+#[cfg(feature = "version-check")]
+pub fn check_latest_version() -> Result<(), FitsRotateError> {
+    let current = env!("CARGO_PKG_VERSION");
+    let client = reqwest::blocking::Client::builder()
+        .user_agent(concat!("fitsrotate_rs/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .map_err(|e| FitsRotateError::Message(format!("Could not query crates.io: {}", e)))?;
+    let body = client
+        .get(CRATES_IO_URL)
+        .send()
+        .and_then(|response| response.error_for_status())
+        .and_then(|response| response.text())
+        .map_err(|e| FitsRotateError::Message(format!("Could not query crates.io: {}", e)))?;
+    let latest = parse_max_version(&body).ok_or_else(|| {
+        FitsRotateError::Message("Could not find the latest version in the crates.io response".to_string())
+    })?;
+    if is_newer(&latest, current) {
+        println!(
+            "fitsrotate_rs {} is available (running {}); update with `cargo install fitsrotate_rs`",
+            latest, current
+        );
+    } else {
+        println!("fitsrotate_rs {} is up to date", current);
+    }
+    Ok(())
+}
+
+/// Compare the running version with the latest release on crates.io
+///
+/// This build does not include the version-check feature, so this always fails.
+#[cfg(not(feature = "version-check"))]
+pub fn check_latest_version() -> Result<(), FitsRotateError> {
+    Err(FitsRotateError::Message(
+        "--version-check needs fitsrotate_rs to be built with the `version-check` feature".to_string(),
+    ))
+}
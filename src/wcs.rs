@@ -0,0 +1,345 @@
+use crate::axes::{axis_role, read_ctypes, AxisRole};
+use crate::header::{delete_key, replace_key, FitsHeader, HeaderCard};
+use crate::sidecar::CubeInfo;
+use crate::triplet::within_tolerance;
+use fitsio::errors::Error;
+use fitsio::FitsFile;
+
+/// WCS keywords that are not indexed by axis (each may also have an alternate letter, e.g. `WCSNAMEA`)
+const WCS_KEYWORDS: [&str; 13] = [
+    "WCSAXES", "WCSNAME", "LONPOLE", "LATPOLE", "RADESYS", "EQUINOX", "EPOCH", "RESTFRQ", "RESTFREQ", "RESTWAV",
+    "SPECSYS", "SSYSOBS", "VELOSYS",
+];
+
+/// Stubs of WCS keywords followed by an axis number (e.g. `CTYPE3`)
+const AXIS_STUBS: [&str; 9] = [
+    "CTYPE", "CRVAL", "CDELT", "CRPIX", "CUNIT", "CROTA", "CRDER", "CSYER", "CNAME",
+];
+
+/// Stubs of WCS keywords followed by a pair of numbers (e.g. `PC1_2`, `PV2_1`)
+const PAIR_STUBS: [&str; 4] = ["PC", "CD", "PV", "PS"];
+
+/// Check if a card is part of the WCS of an image
+///
+/// The WCS is made up of the axis-indexed keywords (`CTYPEn`, `CRVALn`,
+/// ...), the `PCi_j`, `CDi_j`, `PVi_m` and `PSi_m` matrices and parameters,
+/// and the keywords in `WCS_KEYWORDS`, all with or without the letter of an
+/// alternate description. The distortion cards of WCS Paper IV are not
+/// included, as they refer to lookup tables in other HDUs.
+///
+/// # Arguments
+///
+/// * `card` - The card
+///
+/// # Returns
+///
+/// * `bool` - Whether the card is part of the WCS
+///
+pub fn is_wcs_card(card: &HeaderCard) -> bool {
+    let keyword = card.keyword();
+    if WCS_KEYWORDS
+        .iter()
+        .any(|wcs_keyword| keyword == *wcs_keyword || is_alternate_of(&keyword, wcs_keyword))
+    {
+        return true;
+    }
+    // Alternate WCS descriptions end in a letter after the axis number
+    let base = match keyword.char_indices().last() {
+        Some((idx, c)) if c.is_ascii_uppercase() && keyword[..idx].ends_with(|c: char| c.is_ascii_digit()) => {
+            &keyword[..idx]
+        }
+        _ => keyword.as_str(),
+    };
+    let numbered = |rest: &str| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_digit());
+    let axis_card = AXIS_STUBS
+        .iter()
+        .any(|stub| base.strip_prefix(stub).is_some_and(numbered));
+    let pair_card = PAIR_STUBS.iter().any(|stub| {
+        base.strip_prefix(stub)
+            .and_then(|rest| rest.split_once('_'))
+            .is_some_and(|(i, j)| numbered(i) && numbered(j))
+    });
+    axis_card || pair_card
+}
+
+/// Check if a keyword is another keyword followed by the letter of an alternate description
+fn is_alternate_of(keyword: &str, base: &str) -> bool {
+    keyword
+        .strip_prefix(base)
+        .is_some_and(|rest| rest.len() == 1 && rest.chars().all(|c| c.is_ascii_uppercase()))
+}
+
+/// Read the WCS cards and axis lengths of the primary HDU of a FITS file
+///
+/// # Arguments
+///
+/// * `filename` - The FITS file
+///
+/// # Returns
+///
+/// * `Result<(FitsHeader, Vec<usize>), Error>` - The WCS cards (see `is_wcs_card`), and the `NAXISn` in FITS order
+///
+pub fn read_wcs_cards(filename: &str) -> Result<(FitsHeader, Vec<usize>), Error> {
+    let mut fits_file = FitsFile::open(filename)?;
+    let hdu = fits_file.primary_hdu()?;
+    let naxis = hdu.read_key::<i64>(&mut fits_file, "NAXIS")? as usize;
+    let mut shape: Vec<usize> = Vec::with_capacity(naxis);
+    for axis in 1..naxis + 1 {
+        shape.push(hdu.read_key::<i64>(&mut fits_file, &format!("NAXIS{}", axis))? as usize);
+    }
+    let header = FitsHeader::read(&mut fits_file)?;
+    let cards = header.cards.into_iter().filter(is_wcs_card).collect();
+    Ok((FitsHeader { cards }, shape))
+}
+
+/// Replace the WCS of the primary HDU of a FITS file
+///
+/// Every WCS card of the file (see `is_wcs_card`) is deleted, and the
+/// given cards are written in their place.
+///
+/// # Arguments
+///
+/// * `fits_file` - The FITS file
+/// * `wcs_cards` - The WCS cards to write
+///
+pub fn replace_wcs(fits_file: &mut FitsFile, wcs_cards: &FitsHeader) -> Result<(), Error> {
+    fits_file.primary_hdu()?;
+    let header = FitsHeader::read(fits_file)?;
+    for card in header.cards.iter().filter(|card| is_wcs_card(card)) {
+        delete_key(fits_file, &card.keyword())?;
+    }
+    wcs_cards.write(fits_file, &[])
+}
+
+/// Read a floating point keyword of the primary HDU, if it is present
+fn read_f64(fits_file: &mut FitsFile, keyword: &str) -> Result<Option<f64>, Error> {
+    let hdu = fits_file.primary_hdu()?;
+    Ok(hdu.read_key::<f64>(fits_file, keyword).ok())
+}
+
+/// Check if the primary HDU has any cards of a matrix (e.g. `PC` or `CD`)
+fn has_matrix(fits_file: &mut FitsFile, stub: &str, naxis: usize) -> Result<bool, Error> {
+    for i in 1..naxis + 1 {
+        for j in 1..naxis + 1 {
+            if read_f64(fits_file, &format!("{}{}_{}", stub, i, j))?.is_some() {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Update the WCS of an axis whose pixels have been reversed
+///
+/// Pixel `p` of the reversed axis was pixel `length + 1 - p` of the
+/// original, so the reference pixel moves to `length + 1 - CRPIX` and the
+/// increment changes sign. Reversing pixel axis `j` negates column `j` of
+/// the linear transformation: with a CD matrix that column is negated
+/// directly, otherwise `CDELTj` is negated along with the off-diagonal
+/// entries of row and column `j` of the PC matrix.
+///
+/// # Arguments
+///
+/// * `fits_file` - The FITS file
+/// * `fits_axis` - The (FITS, 1-based) axis that was reversed
+/// * `length` - The length of the axis
+///
+pub fn flip_wcs_axis(fits_file: &mut FitsFile, fits_axis: usize, length: usize) -> Result<(), Error> {
+    let hdu = fits_file.primary_hdu()?;
+    let naxis = hdu.read_key::<i64>(fits_file, "NAXIS")? as usize;
+    let crpix_card = format!("CRPIX{}", fits_axis);
+    let crpix: f64 = hdu.read_key(fits_file, &crpix_card).unwrap_or(1.0);
+    replace_key(fits_file, &crpix_card, length as f64 + 1.0 - crpix)?;
+    if has_matrix(fits_file, "CD", naxis)? {
+        for i in 1..naxis + 1 {
+            let cd_card = format!("CD{}_{}", i, fits_axis);
+            if let Some(cd) = read_f64(fits_file, &cd_card)? {
+                replace_key(fits_file, &cd_card, -cd)?;
+            }
+        }
+        return Ok(());
+    }
+    for k in (1..naxis + 1).filter(|k| *k != fits_axis) {
+        for pc_card in [format!("PC{}_{}", fits_axis, k), format!("PC{}_{}", k, fits_axis)] {
+            if let Some(pc) = read_f64(fits_file, &pc_card)? {
+                replace_key(fits_file, &pc_card, -pc)?;
+            }
+        }
+    }
+    let cdelt_card = format!("CDELT{}", fits_axis);
+    let cdelt: f64 = hdu.read_key(fits_file, &cdelt_card).unwrap_or(1.0);
+    replace_key(fits_file, &cdelt_card, -cdelt)
+}
+
+/// Convert a legacy `CROTAn` rotation to the equivalent PC matrix
+///
+/// Following WCS Paper II (Calabretta & Greisen 2002), a rotation `ρ` of
+/// the celestial axes (longitude `i`, latitude `j`) is
+///
+/// * `PCi_i = cos ρ`
+/// * `PCi_j = -sin ρ CDELTj / CDELTi`
+/// * `PCj_i = sin ρ CDELTi / CDELTj`
+/// * `PCj_j = cos ρ`
+///
+/// `ρ` is the `CROTA` of the latitude axis, or of any other axis if that is
+/// missing, and the CDELTs are kept. All `CROTAn` cards are removed.
+/// Headers that already have a PC or CD matrix, or no `CROTAn` cards, are
+/// left alone. If there are no celestial axes, axes 1 and 2 are taken to be
+/// the rotated pair.
+///
+/// # Arguments
+///
+/// * `fits_file` - The FITS file
+///
+/// # Returns
+///
+/// * `Result<bool, Error>` - Whether the header was changed
+///
+pub fn modernize_wcs(fits_file: &mut FitsFile) -> Result<bool, Error> {
+    let ctypes = read_ctypes(fits_file)?;
+    let naxis = ctypes.len();
+    if has_matrix(fits_file, "PC", naxis)? || has_matrix(fits_file, "CD", naxis)? {
+        return Ok(false);
+    }
+    let mut crotas: Vec<(usize, f64)> = Vec::new();
+    for axis in 1..naxis + 1 {
+        if let Some(crota) = read_f64(fits_file, &format!("CROTA{}", axis))? {
+            crotas.push((axis, crota));
+        }
+    }
+    if crotas.is_empty() {
+        return Ok(false);
+    }
+
+    let find_role = |role: AxisRole| ctypes.iter().position(|ctype| axis_role(ctype) == Some(role)).map(|idx| idx + 1);
+    let (lon, lat) = match (find_role(AxisRole::Ra), find_role(AxisRole::Dec)) {
+        (Some(lon), Some(lat)) => (lon, lat),
+        _ => (1, 2),
+    };
+    let rho = crotas
+        .iter()
+        .find(|(axis, _)| *axis == lat)
+        .or_else(|| crotas.iter().find(|(_, crota)| *crota != 0.0))
+        .map_or(0.0, |(_, crota)| *crota);
+    if rho != 0.0 && lat <= naxis {
+        let cdelt_lon = read_f64(fits_file, &format!("CDELT{}", lon))?.unwrap_or(1.0);
+        let cdelt_lat = read_f64(fits_file, &format!("CDELT{}", lat))?.unwrap_or(1.0);
+        let (sin, cos) = rho.to_radians().sin_cos();
+        replace_key(fits_file, &format!("PC{}_{}", lon, lon), cos)?;
+        replace_key(fits_file, &format!("PC{}_{}", lon, lat), -sin * cdelt_lat / cdelt_lon)?;
+        replace_key(fits_file, &format!("PC{}_{}", lat, lon), sin * cdelt_lon / cdelt_lat)?;
+        replace_key(fits_file, &format!("PC{}_{}", lat, lat), cos)?;
+    }
+    for (axis, _) in crotas {
+        delete_key(fits_file, &format!("CROTA{}", axis))?;
+    }
+    Ok(true)
+}
+
+/// Relative tolerance when comparing the WCS of the output with the input
+const WCS_TOLERANCE: f64 = 1e-9;
+
+/// Describe a value of the output that does not match the expected value
+fn compare_value(problems: &mut Vec<String>, card: String, expected: Option<f64>, found: Option<f64>) {
+    match (expected, found) {
+        (Some(expected), Some(found)) if within_tolerance(expected, found, WCS_TOLERANCE) => {}
+        (None, None) => {}
+        (expected, found) => problems.push(format!(
+            "{} is {}, expected {}",
+            card,
+            found.map_or("missing".to_string(), |value| value.to_string()),
+            expected.map_or("missing".to_string(), |value| value.to_string())
+        )),
+    }
+}
+
+/// Check the WCS of a written output against the WCS of its input
+///
+/// For each output axis `n`, which is input axis `a = mode[n - 1]`:
+///
+/// 1. `CTYPEn` must be the `CTYPEa` of the input
+/// 2. `CRVALn` must be the `CRVALa` of the input
+/// 3. `CDELTn` must be the `CDELTa` of the input, negated if the axis was
+///    reversed (unless the increments are in a CD matrix)
+/// 4. `CRPIXn` must be the `CRPIXa` of the input, mirrored about the axis
+///    length if the axis was reversed and shifted by any split
+///
+/// and a CD matrix must not have cards beyond the number of axes.
+///
+/// # Arguments
+///
+/// * `input` - The WCS of the input
+/// * `output` - The WCS of the output
+/// * `output_header` - The header of the output, to look for stray CD cards
+/// * `mode` - The new ordering of the axes
+/// * `flipped_axes` - The (output axis, length) of each reversed axis
+/// * `crpix_shifts` - The (output axis, shift) of the reference pixel of each split axis
+///
+/// # Returns
+///
+/// * `Vec<String>` - A description of each inconsistency, empty if the WCS is consistent
+///
+pub fn check_output_wcs(
+    input: &CubeInfo,
+    output: &CubeInfo,
+    output_header: &FitsHeader,
+    mode: &[usize],
+    flipped_axes: &[(usize, usize)],
+    crpix_shifts: &[(usize, f64)],
+) -> Vec<String> {
+    let mut problems: Vec<String> = Vec::new();
+    if output.axes.len() != mode.len() {
+        problems.push(format!(
+            "the output has {} axes, but the mode has {}",
+            output.axes.len(),
+            mode.len()
+        ));
+        return problems;
+    }
+    for (out_axis, in_axis) in mode.iter().enumerate().map(|(idx, axis)| (idx + 1, *axis)) {
+        let (old, new) = (&input.axes[in_axis - 1], &output.axes[out_axis - 1]);
+        if old.ctype != new.ctype {
+            problems.push(format!(
+                "CTYPE{} is {}, expected {} (CTYPE{} of the input)",
+                out_axis,
+                new.ctype.as_deref().unwrap_or("missing"),
+                old.ctype.as_deref().unwrap_or("missing"),
+                in_axis
+            ));
+        }
+        compare_value(&mut problems, format!("CRVAL{}", out_axis), old.crval, new.crval);
+        let flipped = flipped_axes.iter().any(|(axis, _)| *axis == out_axis);
+        let cdelt = match old.cdelt {
+            Some(cdelt) if flipped && output.cd.is_none() => Some(-cdelt),
+            // Flipping writes the CDELT even if the input had none
+            None if flipped && output.cd.is_none() => Some(-1.0),
+            cdelt => cdelt,
+        };
+        compare_value(&mut problems, format!("CDELT{}", out_axis), cdelt, new.cdelt);
+        let mut crpix = match old.crpix {
+            Some(crpix) if flipped => Some(old.naxis as f64 + 1.0 - crpix),
+            None if flipped => Some(old.naxis as f64),
+            crpix => crpix,
+        };
+        for (axis, shift) in crpix_shifts {
+            if *axis == out_axis {
+                crpix = crpix.map(|crpix| crpix - shift);
+            }
+        }
+        compare_value(&mut problems, format!("CRPIX{}", out_axis), crpix, new.crpix);
+    }
+    if output.cd.is_some() {
+        let naxis = output.axes.len();
+        for card in &output_header.cards {
+            let keyword = card.keyword();
+            let indices = keyword
+                .strip_prefix("CD")
+                .and_then(|rest| rest.split_once('_'))
+                .and_then(|(i, j)| Some((i.parse::<usize>().ok()?, j.parse::<usize>().ok()?)));
+            if indices.is_some_and(|(i, j)| i > naxis || j > naxis) {
+                problems.push(format!("{} is beyond the {} axes of the CD matrix", keyword, naxis));
+            }
+        }
+    }
+    problems
+}
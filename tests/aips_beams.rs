@@ -0,0 +1,167 @@
+mod common;
+
+use common::{
+    arg, fitsrotate, fitsrotate_command, header_records, output_text, ramp, read_cube, scratch_dir, wcs_cards,
+    write_cube,
+};
+use fitsio::FitsFile;
+use std::ffi::CString;
+use std::path::Path;
+
+/// An AIPS beam record for a channel
+fn beam_record(bmaj: f64, field: &str, channel: usize) -> String {
+    let record = format!(
+        "HISTORY AIPS   CLEAN BMAJ= {:.4E} BMIN= 1.1111E-03 BPA= 12.50 {}{:>5}",
+        bmaj, field, channel
+    );
+    assert!(record.len() <= 80, "{}", record);
+    record
+}
+
+/// Append raw records to the primary header of a file
+fn append_records(path: &Path, records: &[String]) {
+    let mut fits_file = FitsFile::edit(path).unwrap();
+    for record in records {
+        let c_record = CString::new(record.as_str()).unwrap();
+        let mut status = 0;
+        unsafe {
+            fitsio::sys::ffprec(fits_file.as_raw(), c_record.as_ptr(), &mut status);
+        }
+        assert_eq!(status, 0, "{}", record);
+    }
+}
+
+/// The AIPS HISTORY records of a file
+fn foreign_history(path: &Path) -> Vec<String> {
+    header_records(path, 0)
+        .into_iter()
+        .filter(|record| record.starts_with("HISTORY AIPS"))
+        .map(|record| record.trim_end().to_string())
+        .collect()
+}
+
+/// A cube of four channels with a block of AIPS beam records, one per channel and one for a channel it does not have
+fn beam_cube(dir: &Path) -> std::path::PathBuf {
+    let input = dir.join("cube.fits");
+    // FITS lengths 2, 3, 4: four channels
+    write_cube(&input, &ramp(&[4, 3, 2]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    append_records(
+        &input,
+        &[
+            "HISTORY AIPS   IMAGR  NITER= 1000".to_string(),
+            beam_record(1.0e-3, "CHAN=", 1),
+            beam_record(2.0e-3, "CHAN=", 2),
+            "HISTORY AIPS   CLEAN  note between the records".to_string(),
+            beam_record(3.0e-3, "CHANNEL=", 3),
+            beam_record(4.0e-3, "PLANE=", 4),
+            // A channel that the cube does not have is left alone
+            beam_record(9.0e-3, "CHAN=", 7),
+        ],
+    );
+    input
+}
+
+/// The beam records of `beam_cube` after its spectral axis is reversed
+///
+/// Channel c becomes 5 - c, and the records take the places of the old block in channel order.
+fn reversed_beam_records() -> Vec<String> {
+    vec![
+        "HISTORY AIPS   IMAGR  NITER= 1000".to_string(),
+        beam_record(4.0e-3, "PLANE=", 1),
+        beam_record(3.0e-3, "CHANNEL=", 2),
+        "HISTORY AIPS   CLEAN  note between the records".to_string(),
+        beam_record(2.0e-3, "CHAN=", 3),
+        beam_record(1.0e-3, "CHAN=", 4),
+        beam_record(9.0e-3, "CHAN=", 7),
+    ]
+}
+
+#[test]
+fn reversing_the_spectral_axis_renumbers_the_beam_block() {
+    let dir = scratch_dir("aips_beams");
+    let input = beam_cube(&dir);
+
+    let output = fitsrotate(&[arg(&input), "-312", "--fix-header"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Renumbered 4 AIPS beam HISTORY records"));
+    let rotated = dir.join("cube.rot.fits");
+    assert_eq!(foreign_history(&rotated), reversed_beam_records());
+    // The beam of each plane is still the one recorded for it
+    let input_cube = read_cube::<f32>(&input);
+    let rotated_cube = read_cube::<f32>(&rotated);
+    for channel in 0..4 {
+        assert_eq!(rotated_cube[[0, 0, 3 - channel]], input_cube[[channel, 0, 0]]);
+    }
+}
+
+#[test]
+fn the_beam_block_is_left_alone_without_a_spectral_flip() {
+    let dir = scratch_dir("aips_beams_unflipped");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[4, 3, 2]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    let records: Vec<String> = (1..5)
+        .map(|channel| beam_record(channel as f64 * 1e-3, "CHAN=", channel))
+        .collect();
+    append_records(&input, &records);
+
+    // Reversing RA rather than FREQ leaves the channels as they are
+    let output = fitsrotate(&[arg(&input), "3-12", "--fix-header"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert_eq!(foreign_history(&dir.join("cube.rot.fits")), records);
+}
+
+#[test]
+fn the_beam_block_is_renumbered_in_a_preserved_layout() {
+    let dir = scratch_dir("aips_beams_layout");
+    let input = beam_cube(&dir);
+
+    // Without --fix-header the records only reach the output with the layout of the input header
+    let output = fitsrotate(&[arg(&input), "-312", "--preserve-layout"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Renumbered 4 AIPS beam HISTORY records"));
+    assert_eq!(foreign_history(&dir.join("cube.rot.fits")), reversed_beam_records());
+}
+
+#[test]
+fn the_streamed_beam_block_is_renumbered() {
+    let dir = scratch_dir("aips_beams_stream");
+    let input = beam_cube(&dir);
+
+    let output = fitsrotate_command(&["stream", arg(&input), "-312"]).output().unwrap();
+    assert!(output.status.success(), "{}", output_text(&output));
+    let streamed = dir.join("streamed.fits");
+    std::fs::write(&streamed, &output.stdout).unwrap();
+    assert_eq!(foreign_history(&streamed), reversed_beam_records());
+}
+
+#[test]
+fn sorting_the_channels_renumbers_the_beam_block() {
+    let dir = scratch_dir("aips_beams_sort");
+    let input = beam_cube(&dir);
+    // Channels 1, 2, 3, 4 sort into places 3, 1, 4, 2
+    let key_file = dir.join("freqs.txt");
+    std::fs::write(&key_file, "1.3e9\n1.1e9\n1.4e9\n1.2e9\n").unwrap();
+
+    let output = fitsrotate(&[
+        arg(&input),
+        "312",
+        "--fix-header",
+        "--sort-axis",
+        "freq",
+        "--sort-key",
+        arg(&key_file),
+    ]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert_eq!(
+        foreign_history(&dir.join("cube.rot.fits")),
+        [
+            "HISTORY AIPS   IMAGR  NITER= 1000".to_string(),
+            beam_record(2.0e-3, "CHAN=", 1),
+            beam_record(4.0e-3, "PLANE=", 2),
+            "HISTORY AIPS   CLEAN  note between the records".to_string(),
+            beam_record(1.0e-3, "CHAN=", 3),
+            beam_record(3.0e-3, "CHANNEL=", 4),
+            beam_record(9.0e-3, "CHAN=", 7),
+        ]
+    );
+}
@@ -0,0 +1,50 @@
+mod common;
+
+use common::{arg, ctypes, fitsrotate, output_text, ramp, read_f64, scratch_dir, wcs_cards, write_cube};
+
+#[test]
+fn input_is_put_in_the_order_of_the_reference() {
+    let dir = scratch_dir("axes_from");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[3, 4, 2]), &wcs_cards(&["FREQ", "RA---SIN", "DEC--SIN"]));
+    // The same axes in another order, and with another projection
+    let reference = dir.join("reference.fits");
+    write_cube(
+        &reference,
+        &ramp(&[2, 3, 4]),
+        &wcs_cards(&["RA---TAN", "DEC--TAN", "FREQ"]),
+    );
+
+    let output = fitsrotate(&[arg(&input), "--axes-from", arg(&reference)]);
+    let text = output_text(&output);
+    assert!(output.status.success(), "{}", text);
+    assert!(
+        text.contains(&format!("Mode from the axes of {}: 231", arg(&reference))),
+        "{}",
+        text
+    );
+    let rotated = dir.join("cube.rot.fits");
+    assert_eq!(ctypes(&rotated), ["RA---SIN", "DEC--SIN", "FREQ"]);
+    // The WCS of each axis moves with it
+    assert_eq!(read_f64(&rotated, "CRVAL3"), Some(10.0));
+    assert_eq!(read_f64(&rotated, "NAXIS3"), Some(2.0));
+}
+
+#[test]
+fn unmatched_axis_is_an_error() {
+    let dir = scratch_dir("axes_from_unmatched");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[3, 4, 2]), &wcs_cards(&["FREQ", "RA---SIN", "DEC--SIN"]));
+    let reference = dir.join("reference.fits");
+    write_cube(
+        &reference,
+        &ramp(&[1, 3, 4]),
+        &wcs_cards(&["RA---SIN", "DEC--SIN", "STOKES"]),
+    );
+
+    let output = fitsrotate(&[arg(&input), "--axes-from", arg(&reference)]);
+    let text = output_text(&output);
+    assert!(!output.status.success(), "{}", text);
+    assert!(text.contains("Unknown axis STOKES"), "{}", text);
+    assert!(!dir.join("cube.rot.fits").exists());
+}
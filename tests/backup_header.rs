@@ -0,0 +1,25 @@
+mod common;
+
+use common::{
+    arg, card, data_bytes, fitsrotate, header_records, output_text, ramp, read_str, scratch_dir, wcs_cards, write_cube,
+};
+
+#[test]
+fn backup_matches_the_input_header_byte_for_byte() {
+    let dir = scratch_dir("backup_header");
+    let input = dir.join("cube.fits");
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    cards.push(card("OBJECT", "A long object name padded with spaces   "));
+    cards.push(card("BMAJ", 1.25e-3));
+    write_cube(&input, &ramp(&[2, 3, 4]), &cards);
+
+    let output = fitsrotate(&[arg(&input), "312", "--backup-header"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    let rotated = dir.join("cube.rot.fits");
+    assert_eq!(read_str(&rotated, "HDRBAKEX").as_deref(), Some("HDRBAK"));
+    assert_eq!(read_str(&rotated, "CTYPE1").as_deref(), Some("FREQ"));
+    // The backup extension follows the primary HDU, one 80-byte row per card
+    let backup = data_bytes(&rotated, 1);
+    assert_eq!(backup, header_records(&input, 0).concat().into_bytes());
+}
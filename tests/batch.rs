@@ -0,0 +1,70 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, ramp, scratch_dir, wcs_cards, write_cube};
+
+#[test]
+fn an_output_that_is_another_input_fails_the_batch_up_front() {
+    let dir = scratch_dir("batch_clash");
+    for name in ["a.fits", "a.rot.fits", "b.fits"] {
+        write_cube(
+            &dir.join(name),
+            &ramp(&[2, 3, 4]),
+            &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]),
+        );
+    }
+    let before = std::fs::read(dir.join("a.rot.fits")).unwrap();
+
+    let pattern = dir.join("*.fits");
+    let output = fitsrotate(&[arg(&pattern), "312", "--overwrite"]);
+    assert!(!output.status.success());
+    let text = output_text(&output);
+    let expected = format!(
+        "{} would be written to {}, which is an input of the batch",
+        arg(&dir.join("a.fits")),
+        arg(&dir.join("a.rot.fits"))
+    );
+    assert!(text.contains(&expected), "{}", text);
+
+    // Nothing was written, not even the outputs that do not clash
+    assert_eq!(std::fs::read(dir.join("a.rot.fits")).unwrap(), before);
+    assert!(!dir.join("b.rot.fits").exists());
+    assert!(!dir.join("a.rot.rot.fits").exists());
+}
+
+#[test]
+fn every_task_of_an_array_sees_the_clash() {
+    let dir = scratch_dir("batch_clash_tasks");
+    for name in ["a.fits", "a.rot.fits", "b.fits", "c.fits"] {
+        write_cube(
+            &dir.join(name),
+            &ramp(&[2, 3, 4]),
+            &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]),
+        );
+    }
+    let pattern = dir.join("*.fits");
+    for index in ["0", "1"] {
+        // Task 1 owns neither a.fits nor a.rot.fits, but fails all the same
+        let output = fitsrotate(&[arg(&pattern), "312", "--task-index", index, "--task-count", "2"]);
+        assert!(!output.status.success(), "task {}: {}", index, output_text(&output));
+    }
+    assert!(!dir.join("b.rot.fits").exists());
+    assert!(!dir.join("c.rot.fits").exists());
+}
+
+#[test]
+fn a_batch_without_clashes_is_rotated() {
+    let dir = scratch_dir("batch_ok");
+    for name in ["a.fits", "b.fits", "c.fits"] {
+        write_cube(
+            &dir.join(name),
+            &ramp(&[2, 3, 4]),
+            &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]),
+        );
+    }
+    let pattern = dir.join("*.fits");
+    let output = fitsrotate(&[arg(&pattern), "312"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    for name in ["a.rot.fits", "b.rot.fits", "c.rot.fits"] {
+        assert!(dir.join(name).exists(), "{}", name);
+    }
+}
@@ -0,0 +1,52 @@
+mod common;
+
+use common::{arg, fitsrotate, fitsrotate_command, output_text, ramp, scratch_dir, wcs_cards, write_cube};
+
+#[test]
+fn benchmark_reports_each_phase_and_writes_to_the_temporary_directory() {
+    let dir = scratch_dir("benchmark");
+    let tmpdir = scratch_dir("benchmark_tmpdir");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[2, 3, 4]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+
+    let output = fitsrotate_command(&[arg(&input), "312", "--benchmark", "3"])
+        .env("TMPDIR", &tmpdir)
+        .output()
+        .unwrap();
+    let text = output_text(&output);
+    assert!(output.status.success(), "{}", text);
+
+    let header = text.lines().find(|line| line.starts_with("phase")).expect(&text);
+    assert_eq!(header.split_whitespace().collect::<Vec<_>>(), ["phase", "min", "median", "max"]);
+    for phase in ["read", "rotate", "write"] {
+        let row = text.lines().find(|line| line.starts_with(phase)).expect(&text);
+        let columns: Vec<&str> = row.split_whitespace().collect();
+        assert_eq!(columns.len(), 4, "{}", row);
+        assert!(columns[1..].iter().all(|time| time.ends_with('s')), "{}", row);
+    }
+
+    // Nothing is written next to the input, and the scratch output is cleaned up
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+    assert_eq!(std::fs::read_dir(&tmpdir).unwrap().count(), 0);
+}
+
+#[test]
+fn benchmark_can_rotate_through_tiles() {
+    let dir = scratch_dir("benchmark_rechunk");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[2, 3, 4]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+
+    let output = fitsrotate(&[arg(&input), "312", "--benchmark", "2", "--rechunk", "3,2,1"]);
+    let text = output_text(&output);
+    assert!(output.status.success(), "{}", text);
+    assert!(text.lines().any(|line| line.starts_with("rotate")), "{}", text);
+
+    // Tiles need one non-zero length per axis, and a benchmark to time
+    for (rechunk, message) in [("3,2", "has 2 axes"), ("3,0,1", "tile length of 0")] {
+        let output = fitsrotate(&[arg(&input), "312", "--benchmark", "1", "--rechunk", rechunk]);
+        assert!(!output.status.success());
+        assert!(output_text(&output).contains(message), "{}", output_text(&output));
+    }
+    let output = fitsrotate(&[arg(&input), "312", "--rechunk", "3,2,1"]);
+    assert!(!output.status.success());
+}
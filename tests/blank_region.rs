@@ -0,0 +1,42 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, read_cube, scratch_dir, wcs_cards, write_cube};
+use ndarray::{ArrayD, Axis, IxDyn};
+
+/// Blank a circle of radius 3 on an 11 x 11 grid with 2 channels, returning the NaN count of each plane
+fn blanked_per_plane(name: &str, extra: &[&str]) -> Vec<usize> {
+    let dir = scratch_dir(name);
+    let input = dir.join("cube.fits");
+    let region = dir.join("circle.reg");
+    write_cube(
+        &input,
+        &ArrayD::from_elem(IxDyn(&[2, 11, 11]), 1.0),
+        &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]),
+    );
+    std::fs::write(&region, "image\ncircle(6,6,3)\n").unwrap();
+
+    let mut args = vec![arg(&input), "312", "--blank-region", arg(&region)];
+    args.extend_from_slice(extra);
+    let output = fitsrotate(&args);
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    // FREQ is output axis 1, the last array axis
+    let rotated = read_cube::<f32>(&dir.join("cube.rot.fits"));
+    rotated
+        .axis_iter(Axis(2))
+        .map(|plane| plane.iter().filter(|value| value.is_nan()).count())
+        .collect()
+}
+
+#[test]
+fn a_circle_blanks_the_same_pixels_in_every_plane() {
+    assert_eq!(blanked_per_plane("blank_inside", &[]), [29, 29]);
+}
+
+#[test]
+fn blank_outside_blanks_the_rest() {
+    assert_eq!(
+        blanked_per_plane("blank_outside", &["--blank-outside"]),
+        [121 - 29, 121 - 29]
+    );
+}
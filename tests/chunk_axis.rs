@@ -0,0 +1,69 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, ramp, read_cube, read_f64, read_str, scratch_dir, wcs_cards, write_cube};
+use ndarray::{concatenate, ArrayD, Axis};
+use std::path::Path;
+
+/// The world coordinate of each pixel of axis 1
+fn axis_1_world(path: &Path) -> Vec<f64> {
+    let (crval, cdelt, crpix) = (
+        read_f64(path, "CRVAL1").unwrap(),
+        read_f64(path, "CDELT1").unwrap(),
+        read_f64(path, "CRPIX1").unwrap(),
+    );
+    let naxis1 = read_f64(path, "NAXIS1").unwrap() as usize;
+    (1..=naxis1)
+        .map(|pixel| crval + (pixel as f64 - crpix) * cdelt)
+        .collect()
+}
+
+#[test]
+fn the_chunks_of_the_spectral_axis_tile_the_parent_exactly() {
+    let dir = scratch_dir("chunk_axis");
+    let input = dir.join("cube.fits");
+    write_cube(
+        &input,
+        &ramp(&[10, 3, 4]),
+        &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]),
+    );
+    let whole = dir.join("cube.whole.fits");
+    let run = fitsrotate(&[arg(&input), "312", "--output", arg(&whole)]);
+    assert!(run.status.success(), "{}", output_text(&run));
+
+    let template = dir.join("part_{start:05}_{end:05}.fits");
+    let args = [
+        arg(&input),
+        "312",
+        "--chunk-axis",
+        "FREQ",
+        "--chunk-size",
+        "4",
+        "--chunk-output",
+        arg(&template),
+    ];
+    let run = fitsrotate(&args);
+    assert!(run.status.success(), "{}", output_text(&run));
+
+    // ceil(10 / 4) chunks, the last holding the remaining 2 channels
+    let ranges = [(1, 4), (5, 8), (9, 10)];
+    let mut world = Vec::new();
+    let mut chunks: Vec<ArrayD<f32>> = Vec::new();
+    for (start, end) in ranges {
+        let chunk = dir.join(format!("part_{:05}_{:05}.fits", start, end));
+        assert!(chunk.exists(), "{:?}", chunk);
+        assert_eq!(read_f64(&chunk, "NAXIS1"), Some((end - start + 1) as f64));
+        assert_eq!(read_str(&chunk, "FRPARENT").as_deref(), Some(arg(&input)));
+        assert_eq!(read_f64(&chunk, "FRCHAXIS"), Some(1.0));
+        assert_eq!(read_f64(&chunk, "FRCHBEG"), Some(start as f64));
+        assert_eq!(read_f64(&chunk, "FRCHEND"), Some(end as f64));
+        world.extend(axis_1_world(&chunk));
+        chunks.push(read_cube(&chunk));
+    }
+    assert!(!dir.join("part_00011_00012.fits").exists());
+
+    // In order, with no overlap or gap, the chunks have the channels of the parent
+    assert_eq!(world, axis_1_world(&whole));
+    let views: Vec<_> = chunks.iter().map(|chunk| chunk.view()).collect();
+    let reassembled = concatenate(Axis(2), &views).unwrap();
+    assert_eq!(reassembled, read_cube::<f32>(&whole));
+}
@@ -0,0 +1,228 @@
+//! Fixtures shared by the integration tests
+#![allow(dead_code)]
+
+pub mod json;
+
+use fitsio::images::{ImageDescription, ImageType, ReadImage, WriteImage};
+use fitsio::FitsFile;
+use ndarray::{ArrayD, IxDyn};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+/// The value of a header card of a fixture
+#[derive(Clone, Debug)]
+pub enum Value {
+    Str(String),
+    Float(f64),
+    Int(i64),
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Value::Str(value.to_string())
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Float(value)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(value: i64) -> Self {
+        Value::Int(value)
+    }
+}
+
+/// A header card of a fixture
+pub fn card(keyword: &str, value: impl Into<Value>) -> (String, Value) {
+    (keyword.to_string(), value.into())
+}
+
+/// The WCS cards of a fixture, one axis per CTYPE (in FITS order)
+///
+/// Axis n has CRVAL n * 10, CDELT n and CRPIX 1, so that every axis can be
+/// told apart after a rotation.
+pub fn wcs_cards(ctypes: &[&str]) -> Vec<(String, Value)> {
+    let mut cards = Vec::new();
+    for (idx, ctype) in ctypes.iter().enumerate() {
+        let axis = idx + 1;
+        let cunit = match ctype.split('-').next().unwrap_or("") {
+            "FREQ" => "Hz",
+            "STOKES" => "",
+            _ => "deg",
+        };
+        cards.push(card(&format!("CTYPE{}", axis), *ctype));
+        cards.push(card(&format!("CRVAL{}", axis), axis as f64 * 10.0));
+        cards.push(card(&format!("CDELT{}", axis), axis as f64));
+        cards.push(card(&format!("CRPIX{}", axis), 1.0));
+        cards.push(card(&format!("CUNIT{}", axis), cunit));
+    }
+    cards
+}
+
+/// Replace the value of a card of a fixture, or add it if it is missing
+pub fn set_card(cards: &mut Vec<(String, Value)>, keyword: &str, value: impl Into<Value>) {
+    let value = value.into();
+    match cards.iter_mut().find(|(old, _)| old == keyword) {
+        Some((_, old)) => *old = value,
+        None => cards.push((keyword.to_string(), value)),
+    }
+}
+
+/// An empty scratch directory for one test
+pub fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("fitsrotate_rs.test.{}.{}", std::process::id(), name));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// A ramp of `0, 1, 2, ...` with the given (array order) shape
+pub fn ramp(shape: &[usize]) -> ArrayD<f32> {
+    let n: usize = shape.iter().product();
+    ArrayD::from_shape_vec(IxDyn(shape), (0..n).map(|v| v as f32).collect()).unwrap()
+}
+
+/// Write a fixture with the given data and header cards
+pub fn write_image<T: WriteImage + Clone>(
+    path: &Path,
+    image_type: ImageType,
+    data: &ArrayD<T>,
+    cards: &[(String, Value)],
+) {
+    let _ = std::fs::remove_file(path);
+    let description = ImageDescription {
+        data_type: image_type,
+        dimensions: data.shape(),
+    };
+    let mut fits_file = FitsFile::create(path).with_custom_primary(&description).open().unwrap();
+    let hdu = fits_file.primary_hdu().unwrap();
+    for (keyword, value) in cards {
+        match value {
+            Value::Str(value) => hdu.write_key(&mut fits_file, keyword, value.as_str()),
+            Value::Float(value) => hdu.write_key(&mut fits_file, keyword, *value),
+            Value::Int(value) => hdu.write_key(&mut fits_file, keyword, *value),
+        }
+        .unwrap();
+    }
+    let data: Vec<T> = data.as_standard_layout().iter().cloned().collect();
+    hdu.write_image(&mut fits_file, &data).unwrap();
+}
+
+/// Write a 32-bit float fixture
+pub fn write_cube(path: &Path, data: &ArrayD<f32>, cards: &[(String, Value)]) {
+    write_image(path, ImageType::Float, data, cards);
+}
+
+/// Read the primary image of a FITS file
+pub fn read_cube<T>(path: &Path) -> ArrayD<T>
+where
+    ArrayD<T>: ReadImage,
+{
+    let mut fits_file = FitsFile::open(path).unwrap();
+    let hdu = fits_file.primary_hdu().unwrap();
+    hdu.read_image(&mut fits_file).unwrap()
+}
+
+/// Read a string keyword of the primary header, without trailing spaces
+pub fn read_str(path: &Path, keyword: &str) -> Option<String> {
+    let mut fits_file = FitsFile::open(path).unwrap();
+    let hdu = fits_file.primary_hdu().unwrap();
+    hdu.read_key::<String>(&mut fits_file, keyword)
+        .ok()
+        .map(|value| value.trim_end().to_string())
+}
+
+/// Read a numeric keyword of the primary header
+pub fn read_f64(path: &Path, keyword: &str) -> Option<f64> {
+    let mut fits_file = FitsFile::open(path).unwrap();
+    let hdu = fits_file.primary_hdu().unwrap();
+    hdu.read_key::<f64>(&mut fits_file, keyword).ok()
+}
+
+/// The CTYPE of each axis of the primary header, in FITS order
+pub fn ctypes(path: &Path) -> Vec<String> {
+    let naxis = read_f64(path, "NAXIS").unwrap() as usize;
+    (1..naxis + 1)
+        .map(|axis| read_str(path, &format!("CTYPE{}", axis)).unwrap_or_default())
+        .collect()
+}
+
+/// The 80-character header records of an HDU, up to and including END
+pub fn header_records(path: &Path, hdu: usize) -> Vec<String> {
+    let bytes = std::fs::read(path).unwrap();
+    let (header, _) = hdu_extent(&bytes, hdu);
+    bytes[header]
+        .chunks(80)
+        .map(|record| String::from_utf8_lossy(record).to_string())
+        .collect()
+}
+
+/// The bytes of the data unit of an HDU, without the padding of its last block
+pub fn data_bytes(path: &Path, hdu: usize) -> Vec<u8> {
+    let bytes = std::fs::read(path).unwrap();
+    let (_, data) = hdu_extent(&bytes, hdu);
+    bytes[data].to_vec()
+}
+
+/// The header records (up to END) and the data unit (without padding) of an HDU
+fn hdu_extent(bytes: &[u8], hdu: usize) -> (Range<usize>, Range<usize>) {
+    let mut offset = 0;
+    for idx in 0.. {
+        let mut header_end = offset;
+        let mut bitpix = 0usize;
+        let mut dims: Vec<usize> = Vec::new();
+        let (mut pcount, mut gcount) = (0usize, 1usize);
+        for record in bytes[offset..].chunks(80) {
+            header_end += 80;
+            let record = String::from_utf8_lossy(record);
+            let keyword = record[..8].trim_end();
+            let value = record.get(10..30).unwrap_or("").trim();
+            match keyword {
+                "BITPIX" => bitpix = value.parse::<i64>().unwrap().unsigned_abs() as usize,
+                "PCOUNT" => pcount = value.parse().unwrap(),
+                "GCOUNT" => gcount = value.parse().unwrap(),
+                "END" => break,
+                _ if keyword.starts_with("NAXIS") && keyword.len() > 5 => dims.push(value.parse().unwrap()),
+                _ => {}
+            }
+        }
+        let data_start = header_end.div_ceil(2880) * 2880;
+        let n_pixels: usize = if dims.is_empty() { 0 } else { dims.iter().product() };
+        let data_end = data_start + bitpix / 8 * gcount * (pcount + n_pixels);
+        if idx == hdu {
+            return (offset..header_end, data_start..data_end);
+        }
+        offset = data_end.div_ceil(2880) * 2880;
+    }
+    unreachable!()
+}
+
+/// Run the binary with the given arguments
+pub fn fitsrotate(args: &[&str]) -> Output {
+    fitsrotate_command(args).output().unwrap()
+}
+
+/// A command running the binary, for tests that need to set up its environment or stdin
+pub fn fitsrotate_command(args: &[&str]) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_fitsrotate_rs"));
+    command.args(args).env("NO_COLOR", "1").env_remove("SOURCE_DATE_EPOCH");
+    command
+}
+
+/// The standard output and error of a run, for assertion messages
+pub fn output_text(output: &Output) -> String {
+    format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+}
+
+/// A path as a `&str`, for the command line
+pub fn arg(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
@@ -0,0 +1,152 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, ramp, read_cube, scratch_dir, wcs_cards, write_cube};
+use fitsio::FitsFile;
+use ndarray::{ArrayD, IxDyn};
+use std::path::{Path, PathBuf};
+
+/// A card of the compressed extension of a file
+fn compressed_card(path: &Path, keyword: &str) -> Option<String> {
+    let mut fits_file = FitsFile::open(path).unwrap();
+    let hdu = fits_file.hdu(1).unwrap();
+    hdu.read_key::<String>(&mut fits_file, keyword)
+        .ok()
+        .map(|value| value.trim_end().to_string())
+}
+
+/// The decompressed image of a tile-compressed file
+fn read_compressed(path: &Path) -> ArrayD<f32> {
+    let mut fits_file = FitsFile::open(path).unwrap();
+    let hdu = fits_file.hdu(1).unwrap();
+    hdu.read_image(&mut fits_file).unwrap()
+}
+
+/// A 6 x 8 x 16 ramp, packed as fpack would with the extra arguments to `<stem>.fits.fz`
+fn packed_cube(dir: &Path, stem: &str, extra: &[&str]) -> (ArrayD<f32>, PathBuf) {
+    let plain = dir.join(format!("{}.fits", stem));
+    let data = ramp(&[6, 8, 16]);
+    write_cube(&plain, &data, &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    let packed = dir.join(format!("{}.fits.fz", stem));
+    let mut args = vec![arg(&plain), "123", "--output", arg(&packed)];
+    args.extend_from_slice(extra);
+    let run = fitsrotate(&args);
+    assert!(run.status.success(), "{}", output_text(&run));
+    (data, packed)
+}
+
+#[test]
+fn a_compressed_input_gives_outputs_compressed_the_same_way() {
+    let dir = scratch_dir("compressed_input_gzip2");
+    let (data, packed) = packed_cube(&dir, "gzip2_cube", &["--compress", "gzip2", "--tile-size", "8,2"]);
+    let tiles = |path: &Path| -> Vec<Option<String>> {
+        (1..=3)
+            .map(|axis| compressed_card(path, &format!("ZTILE{}", axis)))
+            .collect()
+    };
+    assert_eq!(tiles(&packed), [Some("8".into()), Some("2".into()), Some("1".into())]);
+
+    let output = dir.join("cube.231.fits");
+    let run = fitsrotate(&[arg(&packed), "231", "--output", arg(&output), "--stats"]);
+    assert!(run.status.success(), "{}", output_text(&run));
+    assert!(
+        output_text(&run).contains("Unpacked the tile-compressed image"),
+        "{}",
+        output_text(&run)
+    );
+
+    assert_eq!(compressed_card(&output, "ZCMPTYPE"), Some("GZIP_2".to_string()));
+    // Each tile follows its axis, rather than being laid out again from the CTYPEs as --tile-size would
+    assert_eq!(tiles(&output), [Some("2".into()), Some("1".into()), Some("8".into())]);
+    // gzip2 of float32 pixels is lossless
+    assert_eq!(read_compressed(&output), data.permuted_axes(IxDyn(&[2, 0, 1])));
+}
+
+#[test]
+fn a_quantized_input_is_only_quantized_again_with_a_level() {
+    let dir = scratch_dir("compressed_input_rice");
+    let (_, packed) = packed_cube(
+        &dir,
+        "rice_cube",
+        &[
+            "--compress",
+            "rice",
+            "--quantize",
+            "q=4",
+            "--dither",
+            "subtractive2",
+            "--dither-seed",
+            "42",
+        ],
+    );
+    let quantized = read_compressed(&packed);
+
+    // Without a level the outputs are lossless, so Rice becomes gzip2
+    let output = dir.join("cube.312.fits");
+    let run = fitsrotate(&[arg(&packed), "312", "--output", arg(&output)]);
+    assert!(run.status.success(), "{}", output_text(&run));
+    assert_eq!(compressed_card(&output, "ZCMPTYPE"), Some("GZIP_2".to_string()));
+    assert_eq!(compressed_card(&output, "ZQUANTIZ"), Some("NONE".to_string()));
+    assert_eq!(read_compressed(&output), quantized.permuted_axes(IxDyn(&[1, 2, 0])));
+
+    // With one, the algorithm, dither and seed of the input are carried
+    let run = fitsrotate(&[
+        arg(&packed),
+        "312",
+        "--output",
+        arg(&output),
+        "--overwrite",
+        "--quantize",
+        "q=4",
+    ]);
+    assert!(run.status.success(), "{}", output_text(&run));
+    // CFITSIO names Rice RICE_ONE for quantized floating point pixels
+    assert_eq!(compressed_card(&output, "ZCMPTYPE"), Some("RICE_ONE".to_string()));
+    assert_eq!(
+        compressed_card(&output, "ZCMPTYPE"),
+        compressed_card(&packed, "ZCMPTYPE")
+    );
+    assert_eq!(
+        compressed_card(&output, "ZQUANTIZ"),
+        Some("SUBTRACTIVE_DITHER_2".to_string())
+    );
+    assert_eq!(compressed_card(&output, "ZDITHER0"), Some("42".to_string()));
+    // The rows of the input stay whole along the axis they were on, now axis 2
+    assert_eq!(compressed_card(&output, "ZTILE1"), Some("1".to_string()));
+    assert_eq!(compressed_card(&output, "ZTILE2"), Some("16".to_string()));
+    assert_eq!(compressed_card(&output, "ZTILE3"), Some("1".to_string()));
+}
+
+#[test]
+fn compress_none_writes_a_compressed_input_uncompressed() {
+    let dir = scratch_dir("compressed_input_none");
+    let (data, packed) = packed_cube(&dir, "unpacked_cube", &["--compress", "gzip"]);
+    let output = dir.join("cube.312.fits");
+    let run = fitsrotate(&[arg(&packed), "312", "--output", arg(&output), "--compress", "none"]);
+    assert!(run.status.success(), "{}", output_text(&run));
+
+    assert_eq!(read_cube::<f32>(&output), data.permuted_axes(IxDyn(&[1, 2, 0])));
+    assert_eq!(FitsFile::open(&output).unwrap().num_hdus().unwrap(), 1);
+    // The unpacked copy of the input is gone
+    let leftovers: Vec<_> = std::fs::read_dir(std::env::temp_dir())
+        .unwrap()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with("unpacked_cube.fits.fz.")
+        })
+        .collect();
+    assert!(leftovers.is_empty(), "{:?}", leftovers);
+
+    let run = fitsrotate(&[
+        arg(&packed),
+        "312",
+        "--overwrite",
+        "--compress",
+        "none",
+        "--tile-size",
+        "4,4",
+    ]);
+    assert!(!run.status.success(), "{}", output_text(&run));
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, ramp, scratch_dir, set_card, wcs_cards, write_cube};
+use std::path::PathBuf;
+
+/// Four matching cubes, the last with its CRVAL1 moved by a relative `nudge`
+fn nudged_files(name: &str, nudge: f64) -> Vec<PathBuf> {
+    let dir = scratch_dir(name);
+    (0..4)
+        .map(|idx| {
+            let path = dir.join(format!("cube{}.fits", idx));
+            let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+            if idx == 3 {
+                set_card(&mut cards, "CRVAL1", 10.0 * (1.0 + nudge));
+            }
+            write_cube(&path, &ramp(&[2, 3, 4]), &cards);
+            path
+        })
+        .collect()
+}
+
+fn check_consistency(files: &[PathBuf], extra: &[&str]) -> std::process::Output {
+    let mut args = vec!["check-consistency"];
+    args.extend(files.iter().map(|path| arg(path)));
+    args.extend(extra);
+    fitsrotate(&args)
+}
+
+#[test]
+fn a_nudged_crval_is_reported_as_its_own_group() {
+    let files = nudged_files("consistency_nudged", 1e-6);
+    let output = check_consistency(&files, &[]);
+    let text = output_text(&output);
+    assert_eq!(output.status.code(), Some(1), "{}", text);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "The files fall into 2 groups");
+    let majority: Vec<&str> = files[..3].iter().map(|path| arg(path)).collect();
+    assert_eq!(lines[1], format!("Group 1 (3 files): {}", majority.join(", ")));
+    assert_eq!(lines[2], format!("Group 2 (1 files): {}", arg(&files[3])));
+    // CRVAL1 is the only keyword listed for the odd file out
+    assert_eq!(lines.len(), 4, "{}", stdout);
+    assert_eq!(lines[3], "  CRVAL1: 10.00001 (group 1: 10.)");
+    assert!(
+        text.contains("1 of 4 files are not consistent with the rest"),
+        "{}",
+        text
+    );
+}
+
+#[test]
+fn a_nudge_within_the_tolerance_is_consistent() {
+    let files = nudged_files("consistency_tolerance", 1e-6);
+    let output = check_consistency(&files, &["--tolerance", "1e-5"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("All 4 files are consistent on "));
+
+    let files = nudged_files("consistency_default_tolerance", 1e-12);
+    let output = check_consistency(&files, &[]);
+    assert!(output.status.success(), "{}", output_text(&output));
+}
+
+#[test]
+fn keywords_left_out_of_keys_are_not_compared() {
+    let files = nudged_files("consistency_keys", 1e-6);
+    let output = check_consistency(&files, &["--keys", "CTYPE*,CDELT?"]);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(output.status.success(), "{}", output_text(&output));
+    // Three CTYPEs and three CDELTs
+    assert_eq!(stdout.trim(), "All 4 files are consistent on 6 keywords");
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use common::{
+    arg, card, data_bytes, fitsrotate, header_records, output_text, ramp, scratch_dir, wcs_cards, write_cube, Value,
+};
+use std::path::Path;
+
+/// The WCS of the reference: a different description of the rotated axes, with a PC matrix and an alternate WCS
+fn reference_cards() -> Vec<(String, Value)> {
+    vec![
+        card("WCSAXES", 3),
+        card("CTYPE1", "VRAD"),
+        card("CTYPE2", "GLON-CAR"),
+        card("CTYPE3", "GLAT-CAR"),
+        card("CRVAL1", 1500.0),
+        card("CRVAL2", 120.5),
+        card("CRVAL3", -2.25),
+        card("CDELT1", -250.0),
+        card("CDELT2", 0.01),
+        card("CDELT3", 0.01),
+        card("CRPIX1", 2.0),
+        card("CRPIX2", 1.5),
+        card("CRPIX3", 1.5),
+        card("PC2_3", 0.125),
+        card("PC3_2", -0.125),
+        card("CTYPE1A", "FREQ"),
+        card("CRVAL1A", 1.4e9),
+        card("WCSNAME", "Regridded"),
+        card("SPECSYS", "LSRK"),
+    ]
+}
+
+/// The WCS records of a header: every record but the structural, DATE, EXTNAME, HISTORY and COMMENT ones
+fn wcs_records(path: &Path) -> Vec<String> {
+    let not_wcs = [
+        "SIMPLE", "BITPIX", "NAXIS", "EXTEND", "END", "DATE", "EXTNAME", "HISTORY", "COMMENT",
+    ];
+    let mut records: Vec<String> = header_records(path, 0)
+        .into_iter()
+        .filter(|record| {
+            let keyword = record[..8].trim_end();
+            !keyword.is_empty() && !not_wcs.iter().any(|other| keyword.starts_with(other))
+        })
+        .map(|record| record.trim_end().to_string())
+        .collect();
+    records.sort();
+    records
+}
+
+#[test]
+fn the_output_takes_the_wcs_of_the_reference() {
+    let dir = scratch_dir("copy_wcs");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[4, 3, 2]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    // The output of mode 312 has FITS axis lengths 4, 2, 3
+    let reference = dir.join("reference.fits");
+    write_cube(&reference, &ramp(&[3, 2, 4]), &reference_cards());
+
+    let rotated = dir.join("rotated.fits");
+    let output = fitsrotate(&[arg(&input), "312", "--output", arg(&rotated)]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let copied = dir.join("copied.fits");
+    let args = ["312", "--copy-wcs-from", arg(&reference), "--output", arg(&copied)];
+    let output = fitsrotate(&[&[arg(&input)], &args[..]].concat());
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    // The WCS is that of the reference alone, with none of the permuted cards of the input (e.g. CUNITn) left
+    assert_eq!(wcs_records(&copied), wcs_records(&reference));
+    assert_eq!(data_bytes(&copied, 0), data_bytes(&rotated, 0));
+    let history = format!("fitsrotate_rs: replaced the WCS with that of {}", arg(&reference));
+    let records: String = header_records(&copied, 0)
+        .iter()
+        .filter(|record| record.starts_with("HISTORY "))
+        .map(|record| record[8..].trim_end().to_string())
+        .collect();
+    assert!(records.contains(&history), "{}", records);
+}
+
+#[test]
+fn a_reference_of_another_shape_is_refused_before_writing() {
+    let dir = scratch_dir("copy_wcs_shape");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[4, 3, 2]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    // The shape of the input, not of the output of mode 312
+    let reference = dir.join("reference.fits");
+    write_cube(&reference, &ramp(&[4, 3, 2]), &reference_cards());
+
+    let copied = dir.join("copied.fits");
+    let args = ["312", "--copy-wcs-from", arg(&reference), "--output", arg(&copied)];
+    let output = fitsrotate(&[&[arg(&input)], &args[..]].concat());
+    assert!(!output.status.success());
+    assert!(
+        output_text(&output).contains("Cannot copy the WCS of"),
+        "{}",
+        output_text(&output)
+    );
+    assert!(!copied.exists());
+}
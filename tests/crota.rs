@@ -0,0 +1,124 @@
+mod common;
+
+use common::{arg, card, fitsrotate, output_text, ramp, read_f64, scratch_dir, set_card, wcs_cards, write_cube};
+use std::path::Path;
+
+#[test]
+fn crota_follows_its_axis() {
+    let dir = scratch_dir("crota");
+    let input = dir.join("cube.fits");
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    cards.push(card("CROTA2", 45.0));
+    write_cube(&input, &ramp(&[2, 3, 4]), &cards);
+
+    // DEC (input axis 2) becomes output axis 3
+    let output = fitsrotate(&[arg(&input), "312"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    let rotated = dir.join("cube.rot.fits");
+    assert_eq!(read_f64(&rotated, "CROTA3"), Some(45.0));
+    assert_eq!(read_f64(&rotated, "CROTA2"), None);
+    assert_eq!(read_f64(&rotated, "CROTA1"), None);
+    assert_eq!(read_f64(&rotated, "CRVAL3"), Some(20.0));
+}
+
+/// The world coordinates of a pixel under a PC matrix, in FITS axis order
+fn pc_world(path: &Path, pixel: &[f64]) -> Vec<f64> {
+    let naxis = pixel.len();
+    (1..naxis + 1)
+        .map(|i| {
+            let offset: f64 = (1..naxis + 1)
+                .map(|j| {
+                    let pc = read_f64(path, &format!("PC{}_{}", i, j)).unwrap_or(if i == j { 1.0 } else { 0.0 });
+                    pc * (pixel[j - 1] - read_f64(path, &format!("CRPIX{}", j)).unwrap())
+                })
+                .sum();
+            read_f64(path, &format!("CRVAL{}", i)).unwrap() + read_f64(path, &format!("CDELT{}", i)).unwrap() * offset
+        })
+        .collect()
+}
+
+/// The world coordinates of a pixel of the input, with the AIPS convention for CROTA2 of axes 1 and 2
+fn crota_world(pixel: &[f64], cdelt: &[f64], crpix: &[f64], crval: &[f64], crota: f64) -> Vec<f64> {
+    let (sin, cos) = crota.to_radians().sin_cos();
+    let offset: Vec<f64> = (0..3).map(|idx| pixel[idx] - crpix[idx]).collect();
+    vec![
+        crval[0] + cdelt[0] * offset[0] * cos - cdelt[1] * offset[1] * sin,
+        crval[1] + cdelt[0] * offset[0] * sin + cdelt[1] * offset[1] * cos,
+        crval[2] + cdelt[2] * offset[2],
+    ]
+}
+
+#[test]
+fn modernized_pc_matrix_maps_pixels_to_the_same_world_coordinates() {
+    let dir = scratch_dir("crota_modernize");
+    let input = dir.join("cube.fits");
+    let (cdelt, crpix, crval) = ([-0.001, 0.002, 1e6], [2.0, 3.0, 1.0], [150.0, 2.0, 1.4e9]);
+    let crota = 30.0;
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    for axis in 1..4 {
+        set_card(&mut cards, &format!("CDELT{}", axis), cdelt[axis - 1]);
+        set_card(&mut cards, &format!("CRPIX{}", axis), crpix[axis - 1]);
+        set_card(&mut cards, &format!("CRVAL{}", axis), crval[axis - 1]);
+    }
+    cards.push(card("CROTA2", crota));
+    // FITS lengths 4, 3, 2
+    write_cube(&input, &ramp(&[2, 3, 4]), &cards);
+    let lengths = [4.0, 3.0, 2.0];
+
+    for mode in ["312", "3-12", "2-1-3"] {
+        let rotated = dir.join(format!("cube.{}.fits", mode));
+        let output = fitsrotate(&[arg(&input), mode, "--modernize-wcs", "--output", arg(&rotated)]);
+        assert!(output.status.success(), "{}", output_text(&output));
+        assert_eq!(read_f64(&rotated, "CROTA2"), None);
+        assert_eq!(read_f64(&rotated, "CROTA3"), None);
+
+        // Each output axis is an input axis, which may have been reversed
+        let axes: Vec<(usize, bool)> = mode
+            .replace('-', " -")
+            .split_whitespace()
+            .flat_map(|token| {
+                let flipped = token.starts_with('-');
+                let digits: Vec<usize> = token
+                    .trim_start_matches('-')
+                    .chars()
+                    .map(|c| c as usize - '0' as usize)
+                    .collect();
+                digits
+                    .into_iter()
+                    .enumerate()
+                    .map(move |(idx, axis)| (axis, flipped && idx == 0))
+            })
+            .collect();
+        let out_lengths: [usize; 3] = [0, 1, 2].map(|n| lengths[axes[n].0 - 1] as usize);
+        let out_pixels = (1..out_lengths[0] + 1).flat_map(|q1| {
+            (1..out_lengths[1] + 1).flat_map(move |q2| (1..out_lengths[2] + 1).map(move |q3| [q1, q2, q3]))
+        });
+        for q in out_pixels {
+            let out_pixel: Vec<f64> = q.iter().map(|v| *v as f64).collect();
+            let mut in_pixel = [0.0; 3];
+            for (n, (axis, flipped)) in axes.iter().enumerate() {
+                let length = lengths[axis - 1];
+                in_pixel[axis - 1] = if *flipped {
+                    length + 1.0 - out_pixel[n]
+                } else {
+                    out_pixel[n]
+                };
+            }
+            let expected = crota_world(&in_pixel, &cdelt, &crpix, &crval, crota);
+            let found = pc_world(&rotated, &out_pixel);
+            for (n, (axis, _)) in axes.iter().enumerate() {
+                let tolerance = 1e-9 * expected[axis - 1].abs().max(1.0);
+                assert!(
+                    (found[n] - expected[axis - 1]).abs() <= tolerance,
+                    "mode {} output pixel {:?}, axis {}: {} != {}",
+                    mode,
+                    out_pixel,
+                    n + 1,
+                    found[n],
+                    expected[axis - 1]
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use common::{arg, card, fitsrotate, header_records, output_text, ramp, read_cube, scratch_dir, wcs_cards, write_cube};
+use fitsio::images::{ImageDescription, ImageType};
+use fitsio::FitsFile;
+
+/// The distortion cards of an HST image: a detector to image correction and a prior distortion per axis
+fn hst_cards() -> Vec<(String, common::Value)> {
+    let mut cards = wcs_cards(&["RA---TAN-SIP", "DEC--TAN-SIP"]);
+    cards.extend([
+        card("D2IMDIS1", "Lookup"),
+        card("D2IMERR1", 0.01),
+        card("D2IM1", "EXTVER: 1"),
+        card("D2IM1", "NAXES: 2"),
+        card("D2IM1", "AXIS.1: 1"),
+        card("D2IM1", "AXIS.2: 2"),
+        card("CPDIS1", "Lookup"),
+        card("CPERR1", 0.02),
+        card("DP1", "EXTVER: 1"),
+        card("DP1", "NAXES: 2"),
+        card("DP1", "AXIS.1: 1"),
+        card("DP1", "AXIS.2: 2"),
+        card("CPDIS2", "Lookup"),
+        card("CPERR2", 0.03),
+        card("DP2", "EXTVER: 2"),
+        card("DP2", "NAXES: 2"),
+        card("DP2", "AXIS.1: 1.0"),
+        card("DP2", "AXIS.2: 2.0"),
+    ]);
+    cards
+}
+
+/// The records of the distortion cards, trimmed
+fn distortion_records(path: &std::path::Path) -> Vec<String> {
+    header_records(path, 0)
+        .into_iter()
+        .filter(|record| {
+            ["D2IM", "CPDIS", "CPERR", "DP"]
+                .iter()
+                .any(|stub| record.starts_with(stub))
+        })
+        .map(|record| record.trim_end().to_string())
+        .collect()
+}
+
+#[test]
+fn hst_distortion_cards_follow_their_axes() {
+    let dir = scratch_dir("distortion");
+    let input = dir.join("image.fits");
+    write_cube(&input, &ramp(&[3, 4]), &hst_cards());
+    // The lookup tables, which are copied as they are
+    let mut fits_file = FitsFile::edit(&input).unwrap();
+    for (extname, extver) in [("D2IMARR", 1), ("WCSDVARR", 1), ("WCSDVARR", 2)] {
+        let description = ImageDescription {
+            data_type: ImageType::Float,
+            dimensions: &[2, 2],
+        };
+        let hdu = fits_file.create_image(extname, &description).unwrap();
+        hdu.write_key(&mut fits_file, "EXTVER", extver as i64).unwrap();
+        hdu.write_image(&mut fits_file, &[extver as f32; 4]).unwrap();
+    }
+    drop(fits_file);
+
+    let output = fitsrotate(&[arg(&input), "21"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let rotated = dir.join("image.rot.fits");
+
+    // Each card moves to the new number of its axis, and each AXIS.k field names the new number of its axis
+    assert_eq!(
+        distortion_records(&rotated),
+        [
+            "D2IMDIS2= 'Lookup  '",
+            "D2IMERR2=                 0.01",
+            "D2IM2   = 'EXTVER: 1'",
+            "D2IM2   = 'NAXES: 2'",
+            "D2IM2   = 'AXIS.1: 2'",
+            "D2IM2   = 'AXIS.2: 1'",
+            "CPDIS2  = 'Lookup  '",
+            "CPERR2  =                 0.02",
+            "DP2     = 'EXTVER: 1'",
+            "DP2     = 'NAXES: 2'",
+            "DP2     = 'AXIS.1: 2'",
+            "DP2     = 'AXIS.2: 1'",
+            "CPDIS1  = 'Lookup  '",
+            "CPERR1  =                 0.03",
+            "DP1     = 'EXTVER: 2'",
+            "DP1     = 'NAXES: 2'",
+            "DP1     = 'AXIS.1: 2.0'",
+            "DP1     = 'AXIS.2: 1.0'",
+        ]
+    );
+    assert_eq!(read_cube::<f32>(&rotated), ramp(&[3, 4]).reversed_axes());
+
+    let mut fits_file = FitsFile::open(&rotated).unwrap();
+    let extensions: Vec<(String, i64)> = (1..fits_file.num_hdus().unwrap())
+        .map(|idx| {
+            let hdu = fits_file.hdu(idx).unwrap();
+            let extname: String = hdu.read_key(&mut fits_file, "EXTNAME").unwrap();
+            (extname, hdu.read_key(&mut fits_file, "EXTVER").unwrap())
+        })
+        .collect();
+    assert_eq!(
+        extensions,
+        [
+            ("D2IMARR".to_string(), 1),
+            ("WCSDVARR".to_string(), 1),
+            ("WCSDVARR".to_string(), 2)
+        ]
+    );
+}
@@ -0,0 +1,47 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, read_cube, read_f64, scratch_dir, wcs_cards, write_image};
+use fitsio::images::ImageType;
+use ndarray::{ArrayD, Ix3, IxDyn};
+
+#[test]
+fn f64_cubes_are_rotated_at_full_precision() {
+    let dir = scratch_dir("double_precision");
+    let input = dir.join("cube.fits");
+    // Steps of 1e-12 are lost in f32
+    let cube = ArrayD::from_shape_fn(IxDyn(&[2, 3, 4]), |idx| {
+        1.0 + 1e-12 * (100 * idx[2] + 10 * idx[1] + idx[0]) as f64
+    });
+    write_image(
+        &input,
+        ImageType::Double,
+        &cube,
+        &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]),
+    );
+
+    let output = fitsrotate(&[arg(&input), "321"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    let rotated_path = dir.join("cube.rot.fits");
+    assert_eq!(read_f64(&rotated_path, "BITPIX"), Some(-64.0));
+    let rotated = read_cube::<f64>(&rotated_path).into_dimensionality::<Ix3>().unwrap();
+    assert_eq!(rotated.dim(), (4, 3, 2));
+    let cube = cube.into_dimensionality::<Ix3>().unwrap();
+    for ((x, y, z), value) in rotated.indexed_iter() {
+        assert_eq!(*value, cube[[z, y, x]]);
+    }
+}
+
+#[test]
+fn f32_cubes_stay_f32() {
+    let dir = scratch_dir("single_precision");
+    let input = dir.join("cube.fits");
+    let cube = ArrayD::from_shape_fn(IxDyn(&[2, 3]), |idx| (10 * idx[0] + idx[1]) as f32);
+    write_image(&input, ImageType::Float, &cube, &wcs_cards(&["RA---SIN", "DEC--SIN"]));
+
+    let output = fitsrotate(&[arg(&input), "21"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let rotated_path = dir.join("cube.rot.fits");
+    assert_eq!(read_f64(&rotated_path, "BITPIX"), Some(-32.0));
+    assert_eq!(read_cube::<f32>(&rotated_path), cube.t());
+}
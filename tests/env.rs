@@ -0,0 +1,85 @@
+mod common;
+
+use common::json::{self, Json};
+use common::{fitsrotate_command, output_text, scratch_dir};
+
+#[test]
+fn env_prints_a_line_per_item() {
+    let output = fitsrotate_command(&["env"]).output().unwrap();
+    let text = output_text(&output);
+    assert!(output.status.success(), "{}", text);
+    for prefix in [
+        "fitsrotate_rs: ",
+        "git commit: ",
+        "cfitsio: ",
+        "cfitsio gzip: ",
+        "cfitsio bzip2: ",
+        "cfitsio reentrant: ",
+        "features: ",
+        "platform: ",
+        "cpus: ",
+        "memory: ",
+        "TMPDIR: ",
+        "RAYON_NUM_THREADS: ",
+    ] {
+        assert!(
+            text.lines().any(|line| line.starts_with(prefix)),
+            "no {:?} in {}",
+            prefix,
+            text
+        );
+    }
+}
+
+#[test]
+fn env_json_parses_with_the_expected_keys() {
+    let tmpdir = scratch_dir("env \"quoted\"");
+    let output = fitsrotate_command(&["env", "--json"])
+        .env("TMPDIR", &tmpdir)
+        .env_remove("RAYON_NUM_THREADS")
+        .output()
+        .unwrap();
+    let text = String::from_utf8(output.stdout.clone()).unwrap();
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    let report = json::parse(&text).unwrap_or_else(|e| panic!("{}: {}", e, text));
+    assert_eq!(
+        report.keys(),
+        [
+            "arch",
+            "bzip2",
+            "cfitsio_version",
+            "cpus",
+            "env",
+            "features",
+            "git_hash",
+            "gzip",
+            "memory_bytes",
+            "os",
+            "reentrant",
+            "version"
+        ]
+    );
+    assert_eq!(
+        report.get("version").and_then(Json::as_str),
+        Some(env!("CARGO_PKG_VERSION"))
+    );
+    assert_eq!(report.get("os").and_then(Json::as_str), Some(std::env::consts::OS));
+    assert_eq!(report.get("arch").and_then(Json::as_str), Some(std::env::consts::ARCH));
+    for key in ["gzip", "bzip2", "reentrant"] {
+        assert!(matches!(report.get(key), Some(Json::Bool(_))), "{}", key);
+    }
+    assert!(report
+        .get("cpus")
+        .and_then(Json::as_f64)
+        .is_some_and(|cpus| cpus >= 1.0));
+    match report.get("features") {
+        Some(Json::Array(features)) => assert!(features.contains(&Json::String("std".to_string()))),
+        other => panic!("features is {:?}", other),
+    }
+
+    // The environment variables are quoted, or null if unset
+    let env = report.get("env").unwrap();
+    assert_eq!(env.get("TMPDIR").and_then(Json::as_str), tmpdir.to_str());
+    assert_eq!(env.get("RAYON_NUM_THREADS"), Some(&Json::Null));
+}
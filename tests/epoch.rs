@@ -0,0 +1,66 @@
+mod common;
+
+use common::{arg, card, fitsrotate, header_records, output_text, ramp, read_f64, scratch_dir, wcs_cards, write_cube};
+use std::path::PathBuf;
+
+/// Write a B1950 fixture with the given extra cards
+fn b1950_fixture(name: &str, extra: &[(String, common::Value)]) -> PathBuf {
+    let dir = scratch_dir(name);
+    let input = dir.join("cube.fits");
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    cards.push(card("EPOCH", 1950.0));
+    cards.extend_from_slice(extra);
+    write_cube(&input, &ramp(&[2, 3, 4]), &cards);
+    input
+}
+
+/// Check if the primary header of a file has a HISTORY card containing some text
+fn has_history(path: &std::path::Path, text: &str) -> bool {
+    header_records(path, 0)
+        .iter()
+        .any(|record| record.starts_with("HISTORY") && record.contains(text))
+}
+
+#[test]
+fn epoch_is_kept_without_fix_header() {
+    let input = b1950_fixture("epoch_kept", &[]);
+
+    let output = fitsrotate(&[arg(&input), "321"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    let rotated = input.with_extension("rot.fits");
+    assert_eq!(read_f64(&rotated, "EPOCH"), Some(1950.0));
+    assert_eq!(read_f64(&rotated, "EQUINOX"), None);
+    assert!(!has_history(&rotated, "EPOCH renamed"));
+}
+
+#[test]
+fn fix_header_renames_epoch_to_equinox() {
+    let input = b1950_fixture("epoch_renamed", &[]);
+
+    let output = fitsrotate(&[arg(&input), "321", "--fix-header"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert!(output_text(&output).contains("Renamed EPOCH to EQUINOX"));
+
+    let rotated = input.with_extension("rot.fits");
+    assert_eq!(read_f64(&rotated, "EQUINOX"), Some(1950.0));
+    assert_eq!(read_f64(&rotated, "EPOCH"), None);
+    // The card is renamed rather than copied, so no EPOCH record is left behind
+    let records = header_records(&rotated, 0);
+    assert!(!records.iter().any(|record| record.starts_with("EPOCH ")), "{:#?}", records);
+    assert_eq!(records.iter().filter(|record| record.starts_with("EQUINOX ")).count(), 1);
+    assert!(has_history(&rotated, "EPOCH renamed to EQUINOX"));
+}
+
+#[test]
+fn fix_header_keeps_epoch_next_to_an_equinox() {
+    let input = b1950_fixture("epoch_and_equinox", &[card("EQUINOX", 2000.0)]);
+
+    let output = fitsrotate(&[arg(&input), "321", "--fix-header"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    let rotated = input.with_extension("rot.fits");
+    assert_eq!(read_f64(&rotated, "EQUINOX"), Some(2000.0));
+    assert_eq!(read_f64(&rotated, "EPOCH"), Some(1950.0));
+    assert!(!has_history(&rotated, "EPOCH renamed"));
+}
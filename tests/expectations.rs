@@ -0,0 +1,95 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, ramp, scratch_dir, wcs_cards, write_cube};
+
+/// The exit code of a run that fails an expectation (EX_DATAERR)
+const EXIT_EXPECTATION: i32 = 65;
+
+#[test]
+fn met_expectations_do_not_change_the_output() {
+    let dir = scratch_dir("expectations_met");
+    let input = dir.join("cube.fits");
+    write_cube(
+        &input,
+        &ramp(&[2, 4, 3, 5]),
+        &wcs_cards(&["FREQ", "STOKES", "RA---SIN", "DEC--SIN"]),
+    );
+    let plain = dir.join("cube.plain.fits");
+    let run = fitsrotate(&[arg(&input), "3412", "--output", arg(&plain), "--reproducible"]);
+    assert!(run.status.success(), "{}", output_text(&run));
+
+    for (order, shape) in [
+        ("FREQ,STOKES,RA,DEC", "5,3,4,2"),
+        ("freq,stokes,ra,dec", "*,3,*,*"),
+        ("*,STOKES,RA---*,DEC--SI?", "*,*,*,*"),
+    ] {
+        let output = dir.join("cube.checked.fits");
+        let run = fitsrotate(&[
+            arg(&input),
+            "3412",
+            "--output",
+            arg(&output),
+            "--reproducible",
+            "--overwrite",
+            "--expect-order",
+            order,
+            "--expect-shape",
+            shape,
+        ]);
+        assert!(run.status.success(), "{} {}: {}", order, shape, output_text(&run));
+        assert_eq!(std::fs::read(&output).unwrap(), std::fs::read(&plain).unwrap());
+    }
+}
+
+#[test]
+fn failed_expectations_abort_with_their_own_exit_code() {
+    let dir = scratch_dir("expectations_failed");
+    let input = dir.join("cube.fits");
+    write_cube(
+        &input,
+        &ramp(&[2, 4, 3, 5]),
+        &wcs_cards(&["FREQ", "STOKES", "RA---SIN", "DEC--SIN"]),
+    );
+    for (option, value, expected, found) in [
+        (
+            "--expect-order",
+            "STOKES,FREQ,RA,DEC",
+            "STOKES,FREQ,RA,DEC",
+            "FREQ,STOKES,RA---SIN,DEC--SIN",
+        ),
+        (
+            "--expect-order",
+            "FREQ,STOKES,RA",
+            "FREQ,STOKES,RA",
+            "FREQ,STOKES,RA---SIN,DEC--SIN",
+        ),
+        (
+            "--expect-order",
+            "*,*,*-TAN,*",
+            "*,*,*-TAN,*",
+            "FREQ,STOKES,RA---SIN,DEC--SIN",
+        ),
+        ("--expect-shape", "*,4,*,*", "*,4,*,*", "5,3,4,2"),
+        ("--expect-shape", "5,3,4", "5,3,4", "5,3,4,2"),
+    ] {
+        let run = fitsrotate(&[arg(&input), "3412", option, value]);
+        assert_eq!(
+            run.status.code(),
+            Some(EXIT_EXPECTATION),
+            "{} {}: {}",
+            option,
+            value,
+            output_text(&run)
+        );
+        let text = output_text(&run);
+        assert!(text.contains(&format!("does not meet {}", option)), "{}", text);
+        assert!(text.contains(&format!("expected: {}\n", expected)), "{}", text);
+        assert!(text.contains(&format!("found:    {}", found)), "{}", text);
+        assert!(!dir.join("cube.rot.fits").exists());
+    }
+
+    // A malformed length is a usage error rather than a failed expectation
+    let run = fitsrotate(&[arg(&input), "3412", "--expect-shape", "*,0,*,*"]);
+    assert!(!run.status.success());
+    assert_ne!(run.status.code(), Some(EXIT_EXPECTATION), "{}", output_text(&run));
+}
@@ -0,0 +1,59 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, scratch_dir};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Write the header of a 1 GiB cube, with its data unit left as a hole in a sparse file
+fn write_large_cube(dir: &Path) -> PathBuf {
+    let path = dir.join("large.fits");
+    let cards = [
+        "SIMPLE  =                    T",
+        "BITPIX  =                  -32",
+        "NAXIS   =                    3",
+        "NAXIS1  =                 1024",
+        "NAXIS2  =                 1024",
+        "NAXIS3  =                  256",
+        "CTYPE1  = 'RA---SIN'",
+        "CTYPE2  = 'DEC--SIN'",
+        "CTYPE3  = 'FREQ    '",
+        "END",
+    ];
+    let mut header: String = cards.iter().map(|card| format!("{:<80}", card)).collect();
+    header = format!("{:<2880}", header);
+    let mut file = std::fs::File::create(&path).unwrap();
+    file.write_all(header.as_bytes()).unwrap();
+    let data_bytes: u64 = 4 * 1024 * 1024 * 256;
+    file.set_len(2880 + data_bytes.div_ceil(2880) * 2880).unwrap();
+    path
+}
+
+/// Check that a run failed with a message, without the cube having been read into memory
+fn assert_failed_before_reading(args: &[&str], message: &str) {
+    let mut args = args.to_vec();
+    args.push("--mem-profile");
+    let output = fitsrotate(&args);
+    let text = output_text(&output);
+    assert!(!output.status.success(), "{}", text);
+    assert!(text.contains(message), "{}", text);
+    // The cube would take 1 GiB of the memory allocated by the tool
+    let peak = text.lines().find(|line| line.starts_with("Peak memory:")).unwrap();
+    let allocated = peak.split(" allocated").next().unwrap();
+    assert!(!allocated.contains("MiB") && !allocated.contains("GiB"), "{}", peak);
+}
+
+#[test]
+fn invalid_mode_fails_before_reading_data() {
+    let dir = scratch_dir("fail_fast_mode");
+    let input = write_large_cube(&dir);
+    assert_failed_before_reading(&[arg(&input), "4123"], "Mode length 4 does not match");
+    assert_failed_before_reading(&[arg(&input), "112"], "axis 1 appears 2 times");
+}
+
+#[test]
+fn existing_output_fails_before_reading_data() {
+    let dir = scratch_dir("fail_fast_output");
+    let input = write_large_cube(&dir);
+    std::fs::write(dir.join("large.rot.fits"), b"").unwrap();
+    assert_failed_before_reading(&[arg(&input), "321"], "already exists");
+}
@@ -0,0 +1,177 @@
+mod common;
+
+use common::{
+    arg, card, fitsrotate, fitsrotate_command, header_records, output_text, ramp, scratch_dir, wcs_cards, write_cube,
+};
+use std::path::Path;
+
+/// Every kind of operation: typed sets, a set keeping the old comment, deletes, a rename and HISTORY
+const PATCH_YAML: &str = "\
+# Fixes for the test project
+- op: set
+  key: OBJECT
+  value: M31
+  comment: Target name
+- op: set
+  key: TELESCOP
+  value: 'ASKAP'
+- op: set
+  key: NCHANAVG
+  value: 42
+- op: set
+  key: RESTFRQ
+  value: 1.420405752e9
+- op: set
+  key: CALIBRAT
+  value: true
+- op: delete
+  key: OBSOLETE
+- op: delete
+  key: MISSING   # not in the header, so nothing is done
+- op: rename
+  key: ORIGIN
+  to: INSTITUT
+- op: history
+  text: \"Header fixed for project X\"
+";
+
+/// The same patch as JSON
+const PATCH_JSON: &str = r#"[
+  {"op": "set", "key": "OBJECT", "value": "M31", "comment": "Target name"},
+  {"op": "set", "key": "TELESCOP", "value": "ASKAP"},
+  {"op": "set", "key": "NCHANAVG", "value": 42},
+  {"op": "set", "key": "RESTFRQ", "value": 1.420405752e9},
+  {"op": "set", "key": "CALIBRAT", "value": true},
+  {"op": "delete", "key": "OBSOLETE"},
+  {"op": "delete", "key": "MISSING"},
+  {"op": "rename", "key": "ORIGIN", "to": "INSTITUT"},
+  {"op": "history", "text": "Header fixed for project X"}
+]"#;
+
+/// A cube with the keywords the patch changes
+fn patch_fixture(path: &Path) {
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    cards.extend([
+        card("OBJECT", "m31"),
+        card("TELESCOP", "ATCA"),
+        card("OBSOLETE", "yes"),
+        card("ORIGIN", "ATNF"),
+    ]);
+    write_cube(path, &ramp(&[2, 3, 4]), &cards);
+}
+
+/// The header records of a file, trimmed
+fn records(path: &Path) -> Vec<String> {
+    header_records(path, 0)
+        .into_iter()
+        .map(|record| record.trim_end().to_string())
+        .collect()
+}
+
+#[test]
+fn a_patch_of_every_operation_gives_the_expected_header() {
+    let dir = scratch_dir("header_patch");
+    let input = dir.join("cube.fits");
+    patch_fixture(&input);
+    let mut outputs = Vec::new();
+    for (name, patch) in [("yaml", PATCH_YAML), ("json", PATCH_JSON)] {
+        std::fs::write(dir.join(format!("fixes.{}", name)), patch).unwrap();
+        let output = format!("cube.{}.fits", name);
+        let patch_file = format!("fixes.{}", name);
+        // Relative names, so that the HISTORY of the output does not depend on the scratch directory
+        let args = [
+            "cube.fits",
+            "312",
+            "--output",
+            &output,
+            "--header-patch",
+            &patch_file,
+            "--fix-header",
+            "--reproducible",
+        ];
+        let run = fitsrotate_command(&args).current_dir(&dir).output().unwrap();
+        assert!(run.status.success(), "{}", output_text(&run));
+        assert!(
+            output_text(&run).contains("Applied 8 header patch operations"),
+            "{}",
+            output_text(&run)
+        );
+        outputs.push(dir.join(output));
+    }
+
+    let patched = records(&outputs[0]);
+    let snapshot: Vec<String> = SNAPSHOT
+        .iter()
+        .map(|record| record.replace("{version}", env!("CARGO_PKG_VERSION")))
+        .collect();
+    assert_eq!(patched, snapshot, "{:#?}", patched);
+    // JSON and YAML are the same patch
+    assert_eq!(records(&outputs[1]), patched);
+}
+
+#[test]
+fn an_invalid_patch_is_refused_before_any_output_is_written() {
+    let dir = scratch_dir("header_patch_invalid");
+    let input = dir.join("cube.fits");
+    patch_fixture(&input);
+    for (patch, message) in [
+        ("- op: set\n  key: NAXIS1\n  value: 3\n", "describes the data layout"),
+        ("- op: delete\n  key: HISTORY\n", "cards cannot be set"),
+        (
+            "- op: set\n  key: OBJECT\n  value: M31\n- op: delete\n  key: OBJECT\n",
+            "operations 1 and 2 both change OBJECT",
+        ),
+        ("- op: move\n  key: OBJECT\n", "unknown op"),
+    ] {
+        let patch_file = dir.join("fixes.yaml");
+        std::fs::write(&patch_file, patch).unwrap();
+        let run = fitsrotate(&[arg(&input), "312", "--header-patch", arg(&patch_file)]);
+        assert!(!run.status.success(), "{}", patch);
+        assert!(output_text(&run).contains(message), "{}: {}", patch, output_text(&run));
+        assert!(!dir.join("cube.rot.fits").exists());
+    }
+}
+
+/// The header of the patched output, with `{version}` for the version of fitsrotate_rs
+///
+/// The comment blocks and OBJECT to INSTITUT come from the input, as written by --fix-header. TELESCOP keeps
+/// its (empty) comment, ORIGIN is renamed, OBSOLETE is gone, and the new keywords and HISTORY are appended.
+const SNAPSHOT: &[&str] = &[
+    "SIMPLE  =                    T / file does conform to FITS standard",
+    "BITPIX  =                  -32 / number of bits per data pixel",
+    "NAXIS   =                    3 / number of data axes",
+    "NAXIS1  =                    2 / length of data axis 1",
+    "NAXIS2  =                    4 / length of data axis 2",
+    "NAXIS3  =                    3 / length of data axis 3",
+    "EXTEND  =                    T / FITS dataset may contain extensions",
+    "COMMENT   FITS (Flexible Image Transport System) format is defined in 'Astronomy",
+    "COMMENT   and Astrophysics', volume 376, page 359; bibcode: 2001A&A...376..359H",
+    "EXTNAME = '_PRIMARY'",
+    "COMMENT   FITS (Flexible Image Transport System) format is defined in 'Astronomy",
+    "COMMENT   and Astrophysics', volume 376, page 359; bibcode: 2001A&A...376..359H",
+    "OBJECT  = 'M31     '           / Target name",
+    "TELESCOP= 'ASKAP   '",
+    "INSTITUT= 'ATNF    '",
+    "CTYPE1  = 'FREQ    '",
+    "CTYPE2  = 'RA---SIN'",
+    "CTYPE3  = 'DEC--SIN'",
+    "CRVAL1  =                  30.",
+    "CRVAL2  =                  10.",
+    "CRVAL3  =                  20.",
+    "CDELT1  =                   3.",
+    "CDELT2  =                   1.",
+    "CDELT3  =                   2.",
+    "CRPIX1  =                   1.",
+    "CRPIX2  =                   1.",
+    "CRPIX3  =                   1.",
+    "CUNIT1  = 'Hz      '",
+    "CUNIT2  = 'deg     '",
+    "CUNIT3  = 'deg     '",
+    "HISTORY fitsrotate_rs: reordered the axes of cube.fits with mode 312",
+    "HISTORY fitsrotate_rs: written by version {version}",
+    "NCHANAVG=                   42",
+    "RESTFRQ =          1420405752.",
+    "CALIBRAT=                    T",
+    "HISTORY Header fixed for project X",
+    "END",
+];
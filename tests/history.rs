@@ -0,0 +1,106 @@
+mod common;
+
+use common::json::{self, Json};
+use common::{arg, fitsrotate, fitsrotate_command, output_text, ramp, scratch_dir, wcs_cards, write_cube};
+
+/// A string field of a JSON record
+fn field<'a>(record: &'a Json, key: &str) -> Option<&'a str> {
+    record.get(key).and_then(Json::as_str)
+}
+
+#[test]
+fn the_history_of_two_rotations_gives_back_their_parameters() {
+    let dir = scratch_dir("history_chain");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[2, 3, 4]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    let once = dir.join("cube.once.fits");
+    let twice = dir.join("cube.twice.fits");
+    let args = [
+        arg(&input),
+        "--ops",
+        "rotate=312,flip=freq",
+        "--output",
+        arg(&once),
+        "--reproducible",
+    ];
+    let run = fitsrotate_command(&args)
+        .env("SOURCE_DATE_EPOCH", "951782400")
+        .output()
+        .unwrap();
+    assert!(run.status.success(), "{}", output_text(&run));
+    // --fix-header keeps the HISTORY cards of the first run
+    let run = fitsrotate(&[arg(&once), "-213", "--output", arg(&twice), "--fix-header"]);
+    assert!(run.status.success(), "{}", output_text(&run));
+
+    let run = fitsrotate(&["history", arg(&twice), "--json"]);
+    assert!(run.status.success(), "{}", output_text(&run));
+    let text = String::from_utf8_lossy(&run.stdout);
+    let history = json::parse(&text).unwrap_or_else(|e| panic!("{}: {}", e, text));
+    assert_eq!(field(&history, "file"), Some(arg(&twice)));
+    let records = match history.get("records") {
+        Some(Json::Array(records)) => records,
+        other => panic!("{:?}", other),
+    };
+    assert_eq!(records.len(), 2, "{}", text);
+
+    // The --ops chain is recorded as the single mode it comes to, with its steps
+    let first = &records[0];
+    assert_eq!(field(first, "action"), Some("rotated"));
+    assert_eq!(field(first, "input"), Some(arg(&input)));
+    assert_eq!(field(first, "mode"), Some("-312"));
+    assert_eq!(field(first, "version"), Some(env!("CARGO_PKG_VERSION")));
+    assert_eq!(field(first, "date"), Some("2000-02-29T00:00:00"));
+    assert_eq!(
+        first.get("steps"),
+        Some(&Json::Array(vec![
+            Json::String("--ops step 1: rotate=312".into()),
+            Json::String("--ops step 2: flip=freq".into()),
+        ]))
+    );
+
+    let second = &records[1];
+    assert_eq!(field(second, "action"), Some("rotated"));
+    assert_eq!(field(second, "input"), Some(arg(&once)));
+    assert_eq!(field(second, "mode"), Some("-213"));
+    assert_eq!(field(second, "version"), Some(env!("CARGO_PKG_VERSION")));
+    // Not reproducible, so dated by the clock
+    let date = field(second, "date").unwrap();
+    assert!(
+        date.len() == 19 && date.as_bytes()[10] == b'T' && date > "2000",
+        "{}",
+        date
+    );
+    assert_eq!(history.get("other_history"), Some(&Json::Array(Vec::new())));
+
+    let run = fitsrotate(&["history", arg(&twice)]);
+    assert!(run.status.success(), "{}", output_text(&run));
+    let summary = String::from_utf8_lossy(&run.stdout);
+    let expected = format!(
+        "1. Rotated from {} with mode -312 (version {}, 2000-02-29T00:00:00)\n   - --ops step 1: rotate=312",
+        arg(&input),
+        env!("CARGO_PKG_VERSION")
+    );
+    assert!(summary.contains(&expected), "{}", summary);
+    assert!(
+        summary.contains(&format!("2. Rotated from {} with mode -213", arg(&once))),
+        "{}",
+        summary
+    );
+}
+
+#[test]
+fn a_file_without_provenance_says_so() {
+    let dir = scratch_dir("history_none");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[2, 3, 4]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    let run = fitsrotate(&["history", arg(&input)]);
+    assert!(run.status.success(), "{}", output_text(&run));
+    assert!(
+        String::from_utf8_lossy(&run.stdout).contains("No fitsrotate_rs provenance found"),
+        "{}",
+        output_text(&run)
+    );
+    let run = fitsrotate(&["history", arg(&input), "--json"]);
+    let history = json::parse(&String::from_utf8_lossy(&run.stdout)).unwrap();
+    assert_eq!(history.get("records"), Some(&Json::Array(Vec::new())));
+}
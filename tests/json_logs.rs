@@ -0,0 +1,76 @@
+mod common;
+
+use common::json::{self, Json};
+use common::{arg, fitsrotate, output_text, ramp, scratch_dir, wcs_cards, write_cube};
+
+/// The value of a string field of an event
+fn field<'a>(event: &'a Json, key: &str) -> &'a str {
+    event
+        .get(key)
+        .and_then(Json::as_str)
+        .unwrap_or_else(|| panic!("no {} in {:?}", key, event))
+}
+
+#[test]
+fn a_batch_with_one_failure_logs_each_file_and_the_failed_run() {
+    let dir = scratch_dir("json_logs_batch");
+    let good = dir.join("a.fits");
+    write_cube(&good, &ramp(&[2, 3, 4]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    // Two axes cannot be rotated with a mode of three
+    let bad = dir.join("b.fits");
+    write_cube(&bad, &ramp(&[3, 4]), &wcs_cards(&["RA---SIN", "DEC--SIN"]));
+
+    let log = dir.join("events.jsonl");
+    let output = fitsrotate(&[arg(&dir.join("*.fits")), "312", "--json-logs", arg(&log)]);
+    assert!(!output.status.success(), "{}", output_text(&output));
+    let log = std::fs::read_to_string(&log).unwrap();
+    let events: Vec<Json> = log.lines().map(|line| json::parse(line).unwrap()).collect();
+
+    // Progress is throttled, so only the order of the other events is fixed
+    let sequence: Vec<(&str, Option<&str>)> = events
+        .iter()
+        .filter(|event| field(event, "event") != "phase_progress")
+        .map(|event| (field(event, "event"), event.get("file").and_then(Json::as_str)))
+        .collect();
+    let (good, bad) = (arg(&good), arg(&bad));
+    assert_eq!(
+        sequence,
+        vec![
+            ("run_started", None),
+            ("file_started", Some(good)),
+            ("file_finished", Some(good)),
+            ("file_started", Some(bad)),
+            ("file_finished", Some(bad)),
+            ("run_finished", None),
+        ],
+        "{}",
+        log
+    );
+    for event in &events {
+        if field(event, "event") == "phase_progress" {
+            assert_eq!(event.get("file").and_then(Json::as_str), Some(good), "{}", log);
+        }
+    }
+
+    let finished: Vec<&Json> = events
+        .iter()
+        .filter(|event| field(event, "event") == "file_finished")
+        .collect();
+    assert_eq!(field(finished[0], "status"), "ok");
+    assert_eq!(
+        finished[0].get("outputs"),
+        Some(&Json::Array(vec![Json::String(
+            arg(&dir.join("a.rot.fits")).to_string()
+        )]))
+    );
+    assert!(finished[0].get("error").is_none());
+    assert_eq!(field(finished[1], "status"), "failed");
+    assert!(finished[1].get("outputs").is_none());
+    assert!(!field(finished[1], "error").is_empty());
+
+    let run = events.last().unwrap();
+    assert_eq!(field(run, "status"), "failed");
+    assert_eq!(run.get("files").and_then(Json::as_f64), Some(2.0));
+    assert_eq!(run.get("failed").and_then(Json::as_f64), Some(1.0));
+    assert_eq!(field(run, "error"), field(finished[1], "error"));
+}
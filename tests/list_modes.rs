@@ -0,0 +1,114 @@
+mod common;
+
+use common::json::{self, Json};
+use common::{arg, fitsrotate, output_text, ramp, scratch_dir, wcs_cards, write_cube};
+use std::path::{Path, PathBuf};
+
+/// Write a fixture with the given CTYPEs, two pixels along each axis
+fn write_fixture(name: &str, ctypes: &[&str]) -> PathBuf {
+    let dir = scratch_dir(name);
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&vec![2; ctypes.len()]), &wcs_cards(ctypes));
+    input
+}
+
+/// The standard output of `--list-modes`, with any extra arguments
+fn list_modes(input: &Path, extra: &[&str]) -> String {
+    let output = fitsrotate(&[&[arg(input), "--list-modes"], extra].concat());
+    assert!(output.status.success(), "{}", output_text(&output));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn the_modes_of_a_3d_cube_are_listed() {
+    let input = write_fixture("list_modes_3d", &["RA---SIN", "DEC--SIN", "FREQ"]);
+    let expected = "\
+MODE  ORDER                     NOTES
+123   RA---SIN, DEC--SIN, FREQ  identity, standard
+132   RA---SIN, FREQ, DEC--SIN
+213   DEC--SIN, RA---SIN, FREQ
+231   DEC--SIN, FREQ, RA---SIN
+312   FREQ, RA---SIN, DEC--SIN  freq-first
+321   FREQ, DEC--SIN, RA---SIN  reverse, freq-first
+";
+    assert_eq!(list_modes(&input, &[]), expected);
+}
+
+#[test]
+fn the_modes_of_a_4d_cube_are_listed() {
+    let input = write_fixture("list_modes_4d", &["FREQ", "RA---SIN", "STOKES", "DEC--SIN"]);
+    let expected = "\
+MODE  ORDER                             NOTES
+1234  FREQ, RA---SIN, STOKES, DEC--SIN  identity, freq-first
+1243  FREQ, RA---SIN, DEC--SIN, STOKES  freq-first, stokes-last
+1324  FREQ, STOKES, RA---SIN, DEC--SIN  freq-first
+1342  FREQ, STOKES, DEC--SIN, RA---SIN  freq-first
+1423  FREQ, DEC--SIN, RA---SIN, STOKES  freq-first, stokes-last
+1432  FREQ, DEC--SIN, STOKES, RA---SIN  freq-first
+2134  RA---SIN, FREQ, STOKES, DEC--SIN
+2143  RA---SIN, FREQ, DEC--SIN, STOKES  stokes-last
+2314  RA---SIN, STOKES, FREQ, DEC--SIN
+2341  RA---SIN, STOKES, DEC--SIN, FREQ
+2413  RA---SIN, DEC--SIN, FREQ, STOKES  standard, stokes-last
+2431  RA---SIN, DEC--SIN, STOKES, FREQ
+3124  STOKES, FREQ, RA---SIN, DEC--SIN
+3142  STOKES, FREQ, DEC--SIN, RA---SIN
+3214  STOKES, RA---SIN, FREQ, DEC--SIN
+3241  STOKES, RA---SIN, DEC--SIN, FREQ
+3412  STOKES, DEC--SIN, FREQ, RA---SIN
+3421  STOKES, DEC--SIN, RA---SIN, FREQ
+4123  DEC--SIN, FREQ, RA---SIN, STOKES  stokes-last
+4132  DEC--SIN, FREQ, STOKES, RA---SIN
+4213  DEC--SIN, RA---SIN, FREQ, STOKES  stokes-last
+4231  DEC--SIN, RA---SIN, STOKES, FREQ
+4312  DEC--SIN, STOKES, FREQ, RA---SIN
+4321  DEC--SIN, STOKES, RA---SIN, FREQ  reverse
+";
+    assert_eq!(list_modes(&input, &[]), expected);
+}
+
+#[test]
+fn the_modes_are_listed_as_json() {
+    let input = write_fixture("list_modes_json", &["RA---SIN", "DEC--SIN", "FREQ"]);
+    let text = list_modes(&input, &["--json"]);
+    let modes = match json::parse(&text).unwrap_or_else(|e| panic!("{}: {}", e, text)) {
+        Json::Array(modes) => modes,
+        other => panic!("not an array: {:?}", other),
+    };
+    let strings = |value: &Json| match value {
+        Json::Array(items) => items
+            .iter()
+            .map(|item| item.as_str().unwrap().to_string())
+            .collect::<Vec<_>>(),
+        other => panic!("not an array: {:?}", other),
+    };
+    let rows: Vec<(String, Vec<String>, Vec<String>)> = modes
+        .iter()
+        .map(|entry| {
+            (
+                entry.get("mode").and_then(Json::as_str).unwrap().to_string(),
+                strings(entry.get("ctypes").unwrap()),
+                strings(entry.get("notes").unwrap()),
+            )
+        })
+        .collect();
+    assert_eq!(rows.len(), 6);
+    assert_eq!(
+        rows[0],
+        (
+            "123".into(),
+            vec!["RA---SIN".into(), "DEC--SIN".into(), "FREQ".into()],
+            vec!["identity".into(), "standard".into()]
+        )
+    );
+    assert_eq!(
+        rows[5],
+        (
+            "321".into(),
+            vec!["FREQ".into(), "DEC--SIN".into(), "RA---SIN".into()],
+            vec!["reverse".into(), "freq-first".into()]
+        )
+    );
+    let listed: Vec<&str> = rows.iter().map(|(mode, _, _)| mode.as_str()).collect();
+    assert_eq!(listed, ["123", "132", "213", "231", "312", "321"]);
+}
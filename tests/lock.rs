@@ -0,0 +1,119 @@
+mod common;
+
+use common::{arg, fitsrotate, fitsrotate_command, output_text, ramp, scratch_dir, wcs_cards, write_cube};
+use fs4::FileExt;
+use std::fs::{File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+
+/// Write the input of a test, returning it and the lock file of its output
+fn fixture(name: &str) -> (PathBuf, PathBuf) {
+    let dir = scratch_dir(name);
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[2, 3, 4]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    (input, dir.join("cube.rot.fits.lock"))
+}
+
+/// Hold an exclusive lock on a file, as another process would
+fn hold_lock(path: &Path) -> File {
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .unwrap();
+    FileExt::lock(&file).unwrap();
+    file
+}
+
+#[test]
+fn the_lock_file_is_removed_after_a_rotation() {
+    let (input, lock_path) = fixture("lock_removed");
+
+    let output = fitsrotate(&[arg(&input), "321", "--lock-output"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert!(input.with_extension("rot.fits").exists());
+    assert!(!lock_path.exists());
+
+    // A failed rotation leaves no lock file either
+    let output = fitsrotate(&[arg(&input), "321", "--lock-output"]);
+    assert!(!output.status.success());
+    assert!(!lock_path.exists());
+}
+
+#[test]
+fn a_held_output_lock_times_out_with_code_75() {
+    let (input, lock_path) = fixture("lock_timeout");
+    let _lock = hold_lock(&lock_path);
+
+    let output = fitsrotate(&[arg(&input), "321", "--lock-output", "--lock-timeout", "0.2"]);
+    assert_eq!(output.status.code(), Some(75), "{}", output_text(&output));
+    assert!(output_text(&output).contains("is locked by another process"));
+    assert!(!input.with_extension("rot.fits").exists());
+}
+
+#[test]
+fn a_held_input_lock_times_out_with_code_75() {
+    let (input, _) = fixture("lock_input");
+    let _lock = hold_lock(&input);
+
+    let output = fitsrotate(&[arg(&input), "321", "--lock-timeout", "0.2"]);
+    assert_eq!(output.status.code(), Some(75), "{}", output_text(&output));
+}
+
+#[test]
+fn no_lock_ignores_held_locks() {
+    let (input, _) = fixture("no_lock");
+    let _lock = hold_lock(&input);
+
+    let output = fitsrotate(&[arg(&input), "321", "--no-lock"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    let output = fitsrotate(&[arg(&input), "321", "--no-lock", "--lock-output"]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn outputs_are_only_locked_with_lock_output() {
+    let (input, lock_path) = fixture("lock_output_off");
+    let _lock = hold_lock(&lock_path);
+
+    let output = fitsrotate(&[arg(&input), "321", "--lock-timeout", "0.2"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert!(input.with_extension("rot.fits").exists());
+}
+
+#[test]
+fn two_processes_writing_one_output_take_turns() {
+    let (input, lock_path) = fixture("lock_two_processes");
+    let lock = hold_lock(&lock_path);
+
+    let mut children: Vec<_> = (0..2)
+        .map(|_| {
+            fitsrotate_command(&[arg(&input), "321", "--lock-output"])
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                .spawn()
+                .unwrap()
+        })
+        .collect();
+    // Both wait for the lock rather than failing or writing
+    std::thread::sleep(Duration::from_millis(500));
+    for child in children.iter_mut() {
+        assert!(child.try_wait().unwrap().is_none());
+    }
+    assert!(!input.with_extension("rot.fits").exists());
+
+    // One writes the output, and the other then finds it there
+    drop(lock);
+    let outputs: Vec<_> = children
+        .into_iter()
+        .map(|child| child.wait_with_output().unwrap())
+        .collect();
+    let succeeded = outputs.iter().filter(|output| output.status.success()).count();
+    assert_eq!(succeeded, 1, "{}", outputs.iter().map(output_text).collect::<String>());
+    let failed = outputs.iter().find(|output| !output.status.success()).unwrap();
+    assert!(output_text(failed).contains("exists"), "{}", output_text(failed));
+    assert!(!lock_path.exists());
+}
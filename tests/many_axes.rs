@@ -0,0 +1,88 @@
+mod common;
+
+use common::{
+    arg, ctypes, fitsrotate, header_records, output_text, ramp, read_cube, read_f64, scratch_dir, wcs_cards, write_cube,
+};
+
+const CTYPES: [&str; 10] = [
+    "RA---SIN", "DEC--SIN", "FREQ", "STOKES", "TIME", "BEAM", "ANT1", "ANT2", "BASELINE", "CHANNEL",
+];
+
+/// Rotate a fixture with one axis per FITS shape entry, and check its data and WCS
+///
+/// # Arguments
+///
+/// * `name` - The name of the test
+/// * `fits_shape` - The length of each axis, in FITS order
+/// * `mode` - The mode, as given on the command line
+/// * `mode_vec` - The mode, as the input axis of each output axis
+///
+fn check_rotation(name: &str, fits_shape: &[usize], mode: &str, mode_vec: &[usize]) {
+    let naxis = fits_shape.len();
+    let dir = scratch_dir(name);
+    let input = dir.join("cube.fits");
+    let array_shape: Vec<usize> = fits_shape.iter().rev().copied().collect();
+    let cube = ramp(&array_shape);
+    write_cube(&input, &cube, &wcs_cards(&CTYPES[..naxis]));
+
+    let output = fitsrotate(&[arg(&input), mode]);
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    let rotated = dir.join("cube.rot.fits");
+    assert_eq!(read_f64(&rotated, "NAXIS"), Some(naxis as f64));
+    let expected_ctypes: Vec<String> = mode_vec.iter().map(|axis| CTYPES[axis - 1].to_string()).collect();
+    assert_eq!(ctypes(&rotated), expected_ctypes);
+    // NAXISn are written over the cards CFITSIO wrote, so each is there once
+    let records = header_records(&rotated, 0);
+    for axis in 1..naxis + 1 {
+        let keyword = format!("NAXIS{}", axis);
+        let count = records
+            .iter()
+            .filter(|record| record[..8].trim_end() == keyword)
+            .count();
+        assert_eq!(count, 1, "{}", keyword);
+    }
+    for (idx, in_axis) in mode_vec.iter().enumerate() {
+        let axis = idx + 1;
+        assert_eq!(
+            read_f64(&rotated, &format!("NAXIS{}", axis)),
+            Some(fits_shape[in_axis - 1] as f64)
+        );
+        assert_eq!(
+            read_f64(&rotated, &format!("CRVAL{}", axis)),
+            Some(*in_axis as f64 * 10.0)
+        );
+        assert_eq!(read_f64(&rotated, &format!("CDELT{}", axis)), Some(*in_axis as f64));
+    }
+
+    // Array axis a of the output is FITS axis naxis - a, which is input FITS axis mode[naxis - a - 1]
+    let order: Vec<usize> = (0..naxis).map(|a| naxis - mode_vec[naxis - a - 1]).collect();
+    let expected = cube.permuted_axes(order);
+    assert_eq!(read_cube::<f32>(&rotated), expected);
+}
+
+#[test]
+fn five_axes() {
+    check_rotation("five_axes", &[3, 2, 4, 1, 2], "53142", &[5, 3, 1, 4, 2]);
+}
+
+#[test]
+fn six_axes() {
+    check_rotation("six_axes", &[2, 3, 1, 2, 2, 3], "632514", &[6, 3, 2, 5, 1, 4]);
+}
+
+#[test]
+fn seven_axes() {
+    check_rotation("seven_axes", &[2, 1, 3, 1, 2, 1, 2], "7654321", &[7, 6, 5, 4, 3, 2, 1]);
+}
+
+#[test]
+fn two_digit_axes_do_not_collide_with_one_digit_axes() {
+    // CTYPE10 must not be taken for CTYPE1 followed by a 0
+    check_rotation(
+        "ten_axes",
+        &[2, 1, 1, 1, 1, 1, 1, 1, 1, 3],
+        "10,2,3,4,5,6,7,8,9,1",
+        &[10, 2, 3, 4, 5, 6, 7, 8, 9, 1],
+    );
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, ramp, read_cube, scratch_dir, wcs_cards, write_cube};
+use ndarray::{ArrayD, IxDyn};
+
+#[test]
+fn mask_is_applied_before_the_rotation() {
+    let dir = scratch_dir("mask_file");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[2, 3, 4]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    // A single-channel mask that excludes the first column, in the axis order of the input
+    let mask_path = dir.join("mask.fits");
+    let mask = ArrayD::from_shape_fn(IxDyn(&[1, 3, 4]), |idx| if idx[2] == 0 { 0.0f32 } else { 1.0 });
+    write_cube(&mask_path, &mask, &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+
+    let output = fitsrotate(&[arg(&input), "312", "--mask-file", arg(&mask_path)]);
+    let text = output_text(&output);
+    assert!(output.status.success(), "{}", text);
+    assert!(text.contains("Masked 6 of 24 pixels"), "{}", text);
+
+    // FITS axis 1 of the input is FITS axis 2 of the output
+    let rotated = read_cube::<f32>(&dir.join("cube.rot.fits"));
+    assert_eq!(rotated.shape(), &[3, 4, 2]);
+    for (idx, value) in rotated.indexed_iter() {
+        assert_eq!(value.is_nan(), idx[1] == 0, "{:?}", idx);
+    }
+}
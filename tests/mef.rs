@@ -0,0 +1,202 @@
+mod common;
+
+use common::{
+    arg, card, data_bytes, fitsrotate, header_records, output_text, ramp, scratch_dir, wcs_cards, write_cube,
+};
+use fitsio::hdu::HduInfo;
+use fitsio::tables::{ColumnDataType, ColumnDescription};
+use fitsio::FitsFile;
+use ndarray::ArrayD;
+
+/// The value of a keyword in the records of a header, without its comment
+fn value_of(records: &[String], keyword: &str) -> Option<String> {
+    let record = records
+        .iter()
+        .find(|record| record[..8].trim_end() == keyword && &record[8..10] == "= ")?;
+    let value = record[10..].trim_start();
+    let value = match value.strip_prefix('\'') {
+        Some(rest) => &value[..rest.find('\'').unwrap() + 2],
+        None => value.split('/').next().unwrap().trim_end(),
+    };
+    Some(value.to_string())
+}
+
+/// The index of the first HDU with an image, as astropy and DS9 look for the data
+fn first_image_hdu(fits_file: &mut FitsFile) -> Option<usize> {
+    (0..fits_file.num_hdus().unwrap()).find(
+        |idx| matches!(fits_file.hdu(*idx).unwrap().info, HduInfo::ImageInfo { ref shape, .. } if !shape.is_empty()),
+    )
+}
+
+#[test]
+fn mef_layout_puts_the_image_in_an_extension_after_a_dataless_primary() {
+    let dir = scratch_dir("mef");
+    let input = dir.join("cube.fits");
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    cards.extend([
+        card("TELESCOP", "ASKAP"),
+        card("OBSERVER", "Nobody"),
+        card("BUNIT", "Jy/beam"),
+    ]);
+    write_cube(&input, &ramp(&[4, 3, 2]), &cards);
+    // A table, which is copied after the image
+    let mut fits_file = FitsFile::edit(&input).unwrap();
+    let column = ColumnDescription::new("FLAG")
+        .with_type(ColumnDataType::Int)
+        .create()
+        .unwrap();
+    let hdu = fits_file.create_table("FLAGS", &[column]).unwrap();
+    hdu.write_col(&mut fits_file, "FLAG", &[1, 0, 1]).unwrap();
+    drop(fits_file);
+
+    let single = dir.join("single.fits");
+    let output = fitsrotate(&[arg(&input), "312", "--fix-header", "--output", arg(&single)]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let mef = dir.join("mef.fits");
+    let args = [
+        "312",
+        "--fix-header",
+        "--output",
+        arg(&mef),
+        "--mef-layout",
+        "--extname",
+        "SCI",
+        "--extver",
+        "2",
+        "--inherit",
+    ];
+    let output = fitsrotate(&[&[arg(&input)], &args[..]].concat());
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    let mut fits_file = FitsFile::open(&mef).unwrap();
+    assert_eq!(fits_file.num_hdus().unwrap(), 3);
+
+    // The primary has no data, and holds the global keywords alone
+    let primary = header_records(&mef, 0);
+    assert_eq!(value_of(&primary, "NAXIS").as_deref(), Some("0"));
+    assert_eq!(value_of(&primary, "EXTEND").as_deref(), Some("T"));
+    assert_eq!(value_of(&primary, "TELESCOP").as_deref(), Some("'ASKAP   '"));
+    assert_eq!(value_of(&primary, "OBSERVER").as_deref(), Some("'Nobody  '"));
+    for keyword in ["CTYPE1", "CRVAL1", "CDELT3", "BUNIT", "EXTNAME"] {
+        assert_eq!(value_of(&primary, keyword), None, "{} is in the primary", keyword);
+    }
+    assert!(data_bytes(&mef, 0).is_empty());
+
+    // The image extension has the full, rotated header and the rotated data
+    let image = header_records(&mef, 1);
+    assert_eq!(value_of(&image, "XTENSION").as_deref(), Some("'IMAGE   '"));
+    assert_eq!(value_of(&image, "EXTNAME").as_deref(), Some("'SCI     '"));
+    assert_eq!(value_of(&image, "EXTVER").as_deref(), Some("2"));
+    assert_eq!(value_of(&image, "INHERIT").as_deref(), Some("T"));
+    assert_eq!(value_of(&image, "BUNIT").as_deref(), Some("'Jy/beam '"));
+    let single_records = header_records(&single, 0);
+    for keyword in [
+        "NAXIS1", "NAXIS2", "NAXIS3", "CTYPE1", "CTYPE2", "CTYPE3", "CRVAL1", "CDELT3", "TELESCOP",
+    ] {
+        assert_eq!(
+            value_of(&image, keyword),
+            value_of(&single_records, keyword),
+            "{}",
+            keyword
+        );
+    }
+    assert_eq!(value_of(&image, "CTYPE1").as_deref(), Some("'FREQ    '"));
+    assert_eq!(data_bytes(&mef, 1), data_bytes(&single, 0));
+
+    // The table follows, byte for byte
+    assert_eq!(
+        value_of(&header_records(&mef, 2), "EXTNAME").as_deref(),
+        Some("'FLAGS   '")
+    );
+    assert_eq!(header_records(&mef, 2), header_records(&input, 1));
+    assert_eq!(data_bytes(&mef, 2), data_bytes(&input, 1));
+
+    // A reader taking the first HDU with an image finds the rotated cube
+    assert_eq!(first_image_hdu(&mut fits_file), Some(1));
+    let data: ArrayD<f32> = fits_file.hdu("SCI").unwrap().read_image(&mut fits_file).unwrap();
+    assert_eq!(data, common::read_cube::<f32>(&single));
+    assert_eq!(data.shape(), [3, 2, 4]);
+}
+
+/// The byte offset of the header of an HDU in its file
+fn hdu_start(fits_file: &mut FitsFile, hdu: usize) -> usize {
+    fits_file.hdu(hdu).unwrap();
+    let (mut header_start, mut data_start, mut data_end, mut status) = (0, 0, 0, 0);
+    unsafe {
+        fitsio::sys::ffghadll(
+            fits_file.as_raw(),
+            &mut header_start,
+            &mut data_start,
+            &mut data_end,
+            &mut status,
+        );
+    }
+    assert_eq!(status, 0);
+    header_start as usize
+}
+
+/// Check the CHECKSUM and DATASUM of an HDU, which CFITSIO reports as 1 when they are valid
+fn checksums_valid(fits_file: &mut FitsFile, hdu: usize) -> bool {
+    fits_file.hdu(hdu).unwrap();
+    let (mut data_ok, mut hdu_ok, mut status) = (0, 0, 0);
+    unsafe {
+        fitsio::sys::ffvcks(fits_file.as_raw(), &mut data_ok, &mut hdu_ok, &mut status);
+    }
+    assert_eq!(status, 0);
+    data_ok == 1 && hdu_ok == 1
+}
+
+#[test]
+fn passed_through_extensions_are_copied_byte_for_byte() {
+    let dir = scratch_dir("mef_blocks");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[4, 3, 2]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    // A large table of random weights, and a small table after it, both with checksums
+    let mut state: u64 = 2024;
+    let weights: Vec<f64> = (0..1_000_003)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as f64 / u64::MAX as f64
+        })
+        .collect();
+    let mut fits_file = FitsFile::edit(&input).unwrap();
+    for (name, column, values) in [("WEIGHTS", "WEIGHT", &weights[..]), ("SCALE", "SCALE", &[0.5, 2.0][..])] {
+        let description = ColumnDescription::new(column)
+            .with_type(ColumnDataType::Double)
+            .create()
+            .unwrap();
+        let hdu = fits_file.create_table(name, &[description]).unwrap();
+        hdu.write_col(&mut fits_file, column, values).unwrap();
+        let mut status = 0;
+        unsafe {
+            fitsio::sys::ffpcks(fits_file.as_raw(), &mut status);
+        }
+        assert_eq!(status, 0);
+    }
+    let tail_start = hdu_start(&mut fits_file, 1);
+    drop(fits_file);
+
+    let output = dir.join("mef.fits");
+    let run = fitsrotate(&[arg(&input), "312", "--output", arg(&output), "--mef-layout"]);
+    assert!(run.status.success(), "{}", output_text(&run));
+
+    // Everything after the image, headers, data and padding, is the same bytes in the same order
+    let mut fits_file = FitsFile::open(&output).unwrap();
+    assert_eq!(fits_file.num_hdus().unwrap(), 4);
+    let input_bytes = std::fs::read(&input).unwrap();
+    let output_bytes = std::fs::read(&output).unwrap();
+    let output_tail = &output_bytes[hdu_start(&mut fits_file, 2)..];
+    assert_eq!(output_tail.len(), input_bytes.len() - tail_start);
+    assert!(output_tail == &input_bytes[tail_start..]);
+    for hdu in [2, 3] {
+        assert!(checksums_valid(&mut fits_file, hdu), "HDU {}", hdu);
+    }
+    let copied: Vec<f64> = fits_file
+        .hdu("WEIGHTS")
+        .unwrap()
+        .read_col(&mut fits_file, "WEIGHT")
+        .unwrap();
+    assert!(copied == weights);
+}
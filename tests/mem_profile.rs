@@ -0,0 +1,85 @@
+mod common;
+
+use common::json::{self, Json};
+use common::{arg, fitsrotate, output_text, ramp, scratch_dir, wcs_cards, write_cube};
+
+/// The JSON-lines events of a run of type `event`
+fn events(log: &str, event: &str) -> Vec<Json> {
+    log.lines()
+        .map(|line| json::parse(line).unwrap())
+        .filter(|object| object.get("event").and_then(Json::as_str) == Some(event))
+        .collect()
+}
+
+/// The peak of the phase of a run, in bytes, from its `phase_progress` event
+fn phase_peak(log: &str, phase: &str) -> Option<f64> {
+    events(log, "phase_progress")
+        .into_iter()
+        .find(|object| object.get("phase").and_then(Json::as_str) == Some(phase))
+        .unwrap_or_else(|| panic!("no {} phase in {}", phase, log))
+        .get("peak_bytes")
+        .and_then(Json::as_f64)
+}
+
+#[test]
+fn the_peaks_of_a_small_rotation_are_a_sane_multiple_of_the_cube() {
+    let dir = scratch_dir("mem_profile");
+    let input = dir.join("cube.fits");
+    write_cube(
+        &input,
+        &ramp(&[64, 64, 64]),
+        &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]),
+    );
+    let cube_bytes = (64 * 64 * 64 * std::mem::size_of::<f32>()) as f64;
+
+    let log = dir.join("events.jsonl");
+    let output = fitsrotate(&[arg(&input), "312", "--mem-profile", "--json-logs", arg(&log)]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let text = output_text(&output);
+    for line in [
+        "Peak memory (read):",
+        "Peak memory (rotate and write 312):",
+        "Peak memory:",
+    ] {
+        assert!(text.contains(line), "{}", text);
+    }
+    let log = std::fs::read_to_string(&log).unwrap();
+
+    // Reading holds the cube, and rotating holds the input and the rotated copy, with little else besides
+    let read = phase_peak(&log, "read").unwrap();
+    assert!(
+        read >= cube_bytes && read <= 1.5 * cube_bytes,
+        "read peak {} for a cube of {}",
+        read,
+        cube_bytes
+    );
+    let rotate = phase_peak(&log, "rotate and write").unwrap();
+    assert!(
+        rotate >= 2.0 * cube_bytes && rotate <= 3.0 * cube_bytes,
+        "rotate peak {} for a cube of {}",
+        rotate,
+        cube_bytes
+    );
+    let finished = events(&log, "run_finished");
+    let total = finished[0].get("peak_bytes").and_then(Json::as_f64).unwrap();
+    assert_eq!(total, read.max(rotate));
+}
+
+#[test]
+fn the_peaks_are_not_counted_without_mem_profile() {
+    let dir = scratch_dir("mem_profile_off");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[8, 8, 8]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+
+    let log = dir.join("events.jsonl");
+    let output = fitsrotate(&[arg(&input), "312", "--json-logs", arg(&log)]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert!(
+        !output_text(&output).contains("Peak memory"),
+        "{}",
+        output_text(&output)
+    );
+    let log = std::fs::read_to_string(&log).unwrap();
+    assert_eq!(phase_peak(&log, "read"), None);
+    assert_eq!(phase_peak(&log, "rotate and write"), None);
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, ramp, scratch_dir, set_card, wcs_cards, write_image};
+use fitsio::images::ImageType;
+use ndarray::ArrayD;
+use std::path::Path;
+
+/// Rotate a file with and without --mmap-read, and check that the outputs are the same file
+fn check_readers_agree(dir: &Path, input: &Path, name: &str, extra: &[&str]) {
+    let written = |reader: &str, mmap: bool| {
+        let output = dir.join(format!("{}.{}.fits", name, reader));
+        let mut args = vec![arg(input), "-312", "--output", arg(&output), "--reproducible"];
+        args.extend_from_slice(extra);
+        if mmap {
+            args.push("--mmap-read");
+        }
+        let run = fitsrotate(&args);
+        assert!(run.status.success(), "{}: {}", name, output_text(&run));
+        std::fs::read(&output).unwrap()
+    };
+    assert!(
+        written("mmap", true) == written("cfitsio", false),
+        "{}: the outputs differ",
+        name
+    );
+}
+
+#[test]
+fn inputs_of_every_pixel_type_read_the_same_through_a_memory_map() {
+    let dir = scratch_dir("mmap_read_types");
+    let cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    // An odd number of pixels, so the data unit ends partway through a block
+    let data = ramp(&[5, 7, 9]).mapv(|v| v - 100.0);
+
+    let float32 = dir.join("float32.fits");
+    let mut with_blank = data.clone();
+    with_blank[[2, 3, 4]] = f32::NAN;
+    write_image(&float32, ImageType::Float, &with_blank, &cards);
+    check_readers_agree(&dir, &float32, "float32", &[]);
+
+    let float64 = dir.join("float64.fits");
+    let data64: ArrayD<f64> = data.mapv(|v| v as f64 / 3.0);
+    write_image(&float64, ImageType::Double, &data64, &cards);
+    check_readers_agree(&dir, &float64, "float64", &[]);
+    check_readers_agree(&dir, &float64, "float64_narrowed", &["--working-dtype", "float32"]);
+
+    // Integers are left to CFITSIO
+    let int16 = dir.join("int16.fits");
+    let data16: ArrayD<i16> = data.mapv(|v| v as i16);
+    write_image(&int16, ImageType::Short, &data16, &cards);
+    check_readers_agree(&dir, &int16, "int16", &[]);
+    let int32 = dir.join("int32.fits");
+    let data32: ArrayD<i32> = data.mapv(|v| v as i32 * 1000);
+    write_image(&int32, ImageType::Long, &data32, &cards);
+    check_readers_agree(&dir, &int32, "int32", &[]);
+}
+
+#[test]
+fn scaled_inputs_fall_back_to_cfitsio() {
+    let dir = scratch_dir("mmap_read_scaled");
+    let data = ramp(&[3, 4, 5]);
+    // The raw values are not the pixel values, so reading them raw would be wrong
+    let scaled = dir.join("scaled.fits");
+    let mut scaled_cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    set_card(&mut scaled_cards, "BSCALE", 2.0);
+    set_card(&mut scaled_cards, "BZERO", 10.0);
+    write_image(&scaled, ImageType::Float, &data, &scaled_cards);
+    check_readers_agree(&dir, &scaled, "scaled", &[]);
+}
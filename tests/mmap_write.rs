@@ -0,0 +1,81 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, ramp, scratch_dir, wcs_cards, write_cube, write_image};
+use fitsio::images::ImageType;
+use ndarray::ArrayD;
+use std::path::Path;
+
+/// Rotate a file with and without --mmap-write, and check that the outputs are the same file
+fn check_writers_agree(dir: &Path, input: &Path, mode: &str, extra: &[&str]) {
+    let written = |name: &str, mmap: bool| {
+        let output = dir.join(name);
+        let mut args = vec![arg(input), mode, "--output", arg(&output), "--reproducible"];
+        args.extend_from_slice(extra);
+        if mmap {
+            args.push("--mmap-write");
+        }
+        let run = fitsrotate(&args);
+        assert!(run.status.success(), "{}", output_text(&run));
+        std::fs::read(&output).unwrap()
+    };
+    let by_cfitsio = written(&format!("cfitsio.{}.fits", mode), false);
+    let by_mmap = written(&format!("mmap.{}.fits", mode), true);
+    // The padding of the last block is zero in both
+    assert_eq!(by_mmap.len() % 2880, 0, "mode {}", mode);
+    assert!(by_mmap == by_cfitsio, "mode {}: the outputs differ", mode);
+}
+
+#[test]
+fn float32_outputs_are_the_same_through_a_memory_map() {
+    let dir = scratch_dir("mmap_write_f32");
+    let input = dir.join("cube.fits");
+    // Not a whole number of blocks, so the last block is padded
+    let mut data = ramp(&[7, 11, 13]);
+    data[[3, 5, 7]] = f32::NAN;
+    data[[0, 0, 1]] = -0.0;
+    write_cube(&input, &data, &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    for mode in ["123", "312", "-213", "3,-2,1"] {
+        check_writers_agree(&dir, &input, mode, &[]);
+    }
+    // Padded data goes through the same writer
+    check_writers_agree(&dir, &input, "231", &["--pad-to-shape", "16,8,16"]);
+}
+
+#[test]
+fn float64_outputs_are_the_same_through_a_memory_map() {
+    let dir = scratch_dir("mmap_write_f64");
+    let input = dir.join("cube.fits");
+    let data: ArrayD<f64> = ramp(&[2, 5, 3, 4]).mapv(|v| v as f64 / 3.0);
+    write_image(
+        &input,
+        ImageType::Double,
+        &data,
+        &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ", "STOKES"]),
+    );
+    for mode in ["4321", "-3,1,4,2"] {
+        check_writers_agree(&dir, &input, mode, &[]);
+    }
+}
+
+#[test]
+fn compressed_names_fall_back_to_cfitsio() {
+    let dir = scratch_dir("mmap_write_fallback");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[3, 4, 5]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    let plain = dir.join("cube.plain.fits");
+    let run = fitsrotate(&[arg(&input), "312", "--output", arg(&plain), "--reproducible"]);
+    assert!(run.status.success(), "{}", output_text(&run));
+
+    // CFITSIO writes a .fz name, so the output is whatever it makes of it
+    let packed = dir.join("cube.rot.fits.fz");
+    let run = fitsrotate(&[
+        arg(&input),
+        "312",
+        "--output",
+        arg(&packed),
+        "--reproducible",
+        "--mmap-write",
+    ]);
+    assert!(run.status.success(), "{}", output_text(&run));
+    assert_eq!(std::fs::read(&packed).unwrap(), std::fs::read(&plain).unwrap());
+}
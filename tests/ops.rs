@@ -0,0 +1,71 @@
+mod common;
+
+use common::{
+    arg, card, fitsrotate, output_text, ramp, read_cube, read_f64, read_str, scratch_dir, wcs_cards, write_cube,
+};
+use std::path::Path;
+
+fn run(args: &[&str]) {
+    let output = fitsrotate(args);
+    assert!(output.status.success(), "{}", output_text(&output));
+}
+
+#[test]
+fn chained_ops_match_separate_runs() {
+    let dir = scratch_dir("ops_chain");
+    let input = dir.join("cube.fits");
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    cards.push(card("PC1_2", 0.5));
+    cards.push(card("PC2_1", -0.25));
+    write_cube(&input, &ramp(&[2, 3, 4]), &cards);
+
+    // FITS lengths 4, 3, 2 become 2, 4, 3 and are then padded
+    let chained = dir.join("chained.fits");
+    run(&[
+        arg(&input),
+        "--ops",
+        "rotate=312,flip=freq,pad=4,6,5",
+        "--output",
+        arg(&chained),
+    ]);
+
+    let (rotated, flipped, padded) = (dir.join("step1.fits"), dir.join("step2.fits"), dir.join("step3.fits"));
+    run(&[arg(&input), "312", "--output", arg(&rotated)]);
+    run(&[arg(&rotated), "-123", "--output", arg(&flipped)]);
+    run(&[
+        arg(&flipped),
+        "123",
+        "--pad-to-shape",
+        "4,6,5",
+        "--output",
+        arg(&padded),
+    ]);
+
+    assert_same_cube(&chained, &padded);
+    let single = dir.join("single.fits");
+    run(&[arg(&input), "-312", "--pad-to-shape", "4,6,5", "--output", arg(&single)]);
+    assert_same_cube(&chained, &single);
+    assert_eq!(read_cube::<f32>(&chained).shape(), &[5, 6, 4]);
+    assert_eq!(read_str(&chained, "CTYPE1").as_deref(), Some("FREQ"));
+    assert_eq!(read_f64(&chained, "CDELT1"), Some(-3.0));
+    assert_eq!(read_f64(&chained, "PC2_3"), Some(0.5));
+}
+
+/// Check that two files hold the same data and linear WCS
+fn assert_same_cube(a: &Path, b: &Path) {
+    assert_eq!(read_cube::<f32>(a), read_cube::<f32>(b));
+    for axis in 1..4 {
+        for stub in ["NAXIS", "CRVAL", "CDELT", "CRPIX"] {
+            let keyword = format!("{}{}", stub, axis);
+            assert_eq!(read_f64(a, &keyword), read_f64(b, &keyword), "{}", keyword);
+        }
+        for stub in ["CTYPE", "CUNIT"] {
+            let keyword = format!("{}{}", stub, axis);
+            assert_eq!(read_str(a, &keyword), read_str(b, &keyword), "{}", keyword);
+        }
+        for j in 1..4 {
+            let keyword = format!("PC{}_{}", axis, j);
+            assert_eq!(read_f64(a, &keyword), read_f64(b, &keyword), "{}", keyword);
+        }
+    }
+}
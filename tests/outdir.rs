@@ -0,0 +1,222 @@
+mod common;
+
+use common::{arg, fitsrotate_command, output_text, ramp, read_cube, scratch_dir, wcs_cards, write_cube};
+use ndarray::ArrayD;
+use std::path::{Path, PathBuf};
+
+/// Write a fixture cube, creating its directory
+fn write_fixture(path: &Path) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    write_cube(path, &ramp(&[2, 3, 4]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+}
+
+/// Run the binary in a directory
+fn run_in(dir: &Path, args: &[&str]) {
+    let output = fitsrotate_command(args).current_dir(dir).output().unwrap();
+    assert!(output.status.success(), "{}", output_text(&output));
+}
+
+/// Every file under a directory, relative to it and sorted
+fn tree(dir: &Path) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(next) = dirs.pop() {
+        for entry in std::fs::read_dir(next).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                files.push(path.strip_prefix(dir).unwrap().to_path_buf());
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/// The fixture rotated with mode 312
+fn rotated_ramp() -> ArrayD<f32> {
+    ramp(&[2, 3, 4])
+        .permuted_axes(vec![1, 2, 0])
+        .as_standard_layout()
+        .into_owned()
+}
+
+#[test]
+fn relative_inputs_are_mirrored_under_the_output_directory() {
+    let dir = scratch_dir("outdir_relative");
+    for name in [
+        "obs1/I.fits",
+        "obs1/Q.fits",
+        "obs1/U.fits",
+        "obs2/a.fits",
+        "obs2/b.fits",
+    ] {
+        write_fixture(&dir.join(name));
+    }
+    // The mode comes first, since --triplet takes a fourth file (Stokes V) too
+    run_in(
+        &dir,
+        &[
+            "312",
+            "--outdir",
+            "processed",
+            "--triplet",
+            "obs1/I.fits",
+            "obs1/Q.fits",
+            "obs1/U.fits",
+        ],
+    );
+    run_in(&dir, &["obs2/*.fits", "312", "--outdir", "processed"]);
+    // A . in the input path is dropped rather than mirrored
+    run_in(&dir, &["obs2/./a.fits", "312", "--outdir", "dotted"]);
+
+    let expected: Vec<PathBuf> = [
+        "dotted/obs2/a.rot.fits",
+        "obs1/I.fits",
+        "obs1/Q.fits",
+        "obs1/U.fits",
+        "obs2/a.fits",
+        "obs2/b.fits",
+        "processed/obs1/I.rot.fits",
+        "processed/obs1/Q.rot.fits",
+        "processed/obs1/U.rot.fits",
+        "processed/obs2/a.rot.fits",
+        "processed/obs2/b.rot.fits",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .collect();
+    assert_eq!(tree(&dir), expected);
+    for output in expected.iter().filter(|path| path.to_string_lossy().contains(".rot.")) {
+        assert_eq!(
+            read_cube::<f32>(&dir.join(output)),
+            rotated_ramp(),
+            "{}",
+            output.display()
+        );
+    }
+}
+
+#[test]
+fn absolute_inputs_are_mirrored_from_the_root() {
+    let dir = scratch_dir("outdir_absolute");
+    let input = dir.join("data").join("cube.fits");
+    write_fixture(&input);
+    let outdir = dir.join("processed");
+    run_in(&dir, &[arg(&input), "312", "--outdir", arg(&outdir)]);
+
+    let relative = input.with_file_name("cube.rot.fits");
+    let mirrored = outdir.join(relative.strip_prefix("/").unwrap());
+    assert_eq!(read_cube::<f32>(&mirrored), rotated_ramp());
+    assert!(!relative.exists());
+}
+
+#[test]
+fn parent_components_are_resolved_and_may_not_climb_out() {
+    let dir = scratch_dir("outdir_parent");
+    write_fixture(&dir.join("work/obs2/a.fits"));
+    write_fixture(&dir.join("outside.fits"));
+    let work = dir.join("work");
+    std::fs::create_dir_all(work.join("obs1")).unwrap();
+    run_in(&work, &["obs1/../obs2/a.fits", "312", "--outdir", "processed"]);
+    assert_eq!(
+        read_cube::<f32>(&work.join("processed/obs2/a.rot.fits")),
+        rotated_ramp()
+    );
+    assert!(!work.join("processed/obs1").exists());
+
+    // ../outside.fits would be mirrored outside the output directory
+    let output = fitsrotate_command(&["../outside.fits", "312", "--outdir", "processed"])
+        .current_dir(&work)
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    let text = output_text(&output);
+    assert!(text.contains("cannot be mirrored under --outdir"), "{}", text);
+    assert!(!dir.join("outside.rot.fits").exists());
+}
+
+#[test]
+fn a_recursive_batch_mirrors_the_tree() {
+    let dir = scratch_dir("outdir_recursive");
+    for name in [
+        "survey/a.fits",
+        "survey/field1/b.fits",
+        "survey/field1/deep/c.fits",
+        "survey/field2/d.fits",
+    ] {
+        write_fixture(&dir.join(name));
+    }
+    std::fs::write(dir.join("survey/field2/notes.txt"), "not a cube").unwrap();
+    let output = fitsrotate_command(&["survey", "312", "--recursive", "--outdir", "processed"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    let text = output_text(&output);
+    assert!(output.status.success(), "{}", text);
+    assert!(text.contains("Batch done: 4 rotated, 0 skipped"), "{}", text);
+
+    let expected: Vec<PathBuf> = [
+        "a.rot.fits",
+        "field1/b.rot.fits",
+        "field1/deep/c.rot.fits",
+        "field2/d.rot.fits",
+    ]
+    .iter()
+    .map(PathBuf::from)
+    .collect();
+    let processed = dir.join("processed/survey");
+    assert_eq!(tree(&processed), expected);
+    for output in &expected {
+        assert_eq!(
+            read_cube::<f32>(&processed.join(output)),
+            rotated_ramp(),
+            "{}",
+            output.display()
+        );
+    }
+    // The input tree is left alone
+    assert!(tree(&dir.join("survey"))
+        .iter()
+        .all(|path| !path.to_string_lossy().contains(".rot.")));
+}
+
+#[test]
+fn skip_existing_picks_up_an_interrupted_batch() {
+    let dir = scratch_dir("outdir_skip_existing");
+    for name in ["survey/a.fits", "survey/field1/b.fits", "survey/field1/c.fits"] {
+        write_fixture(&dir.join(name));
+    }
+    let args = ["survey", "312", "--recursive", "--outdir", "processed"];
+    run_in(&dir, &args);
+    // As if the batch had stopped before c.fits; a.fits's output is kept, to check it is not rewritten
+    std::fs::remove_file(dir.join("processed/survey/field1/c.rot.fits")).unwrap();
+    let kept = dir.join("processed/survey/a.rot.fits");
+    std::fs::write(&kept, "left over").unwrap();
+
+    // Without --skip-existing, the existing outputs fail the batch
+    let output = fitsrotate_command(&args).current_dir(&dir).output().unwrap();
+    assert!(!output.status.success());
+
+    let output = fitsrotate_command(&[&args[..], &["--skip-existing"]].concat())
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    let text = output_text(&output);
+    assert!(output.status.success(), "{}", text);
+    assert!(
+        text.contains(&format!(
+            "survey/a.fits ({} exists)",
+            Path::new("processed/survey/a.rot.fits").display()
+        )),
+        "{}",
+        text
+    );
+    assert!(text.contains("Batch done: 1 rotated, 2 skipped"), "{}", text);
+    assert_eq!(std::fs::read_to_string(&kept).unwrap(), "left over");
+    assert_eq!(
+        read_cube::<f32>(&dir.join("processed/survey/field1/c.rot.fits")),
+        rotated_ramp()
+    );
+}
@@ -0,0 +1,53 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, ramp, scratch_dir, wcs_cards, write_cube};
+use std::path::{Path, PathBuf};
+
+fn write_input(dir: &Path) -> PathBuf {
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[2, 3]), &wcs_cards(&["RA---SIN", "DEC--SIN"]));
+    input
+}
+
+#[test]
+fn missing_parent_needs_mkdirs() {
+    let dir = scratch_dir("output_dir_missing");
+    let input = write_input(&dir);
+    let output_path = dir.join("products").join("nested").join("cube.rot.fits");
+
+    let output = fitsrotate(&[arg(&input), "21", "--output", arg(&output_path)]);
+    let text = output_text(&output);
+    assert!(!output.status.success(), "{}", text);
+    assert!(text.contains("does not exist (pass --mkdirs to create it)"), "{}", text);
+    assert!(!dir.join("products").exists());
+
+    let output = fitsrotate(&[arg(&input), "21", "--output", arg(&output_path), "--mkdirs"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert!(output_path.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn read_only_directory_is_refused_up_front() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = scratch_dir("output_dir_read_only");
+    let input = write_input(&dir);
+    let read_only = dir.join("read_only");
+    std::fs::create_dir(&read_only).unwrap();
+    std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o555)).unwrap();
+    // Permissions do not stop root, which the check cannot be tested as
+    if std::fs::File::create(read_only.join("probe")).is_ok() {
+        eprintln!("skipping: the read-only directory is writable by this user");
+        return;
+    }
+
+    let output_path = read_only.join("cube.rot.fits");
+    let output = fitsrotate(&[arg(&input), "21", "--output", arg(&output_path)]);
+    let text = output_text(&output);
+    assert!(!output.status.success(), "{}", text);
+    assert!(text.contains("cannot create files in"), "{}", text);
+    // The check comes before the data is read
+    assert!(!text.contains("Original FITS cube shape"), "{}", text);
+    std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
@@ -0,0 +1,81 @@
+mod common;
+
+use common::{
+    arg, ctypes, fitsrotate, header_records, output_text, ramp, scratch_dir, set_card, wcs_cards, write_cube,
+};
+use std::path::{Path, PathBuf};
+
+/// A cube whose CTYPEs are padded with spaces well beyond the 8 characters of a short string
+fn write_padded(path: &Path, padded: bool) {
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    if padded {
+        set_card(&mut cards, "CTYPE1", "RA---SIN     ");
+        set_card(&mut cards, "CTYPE2", "DEC--SIN     ");
+        set_card(&mut cards, "CTYPE3", "FREQ         ");
+    }
+    write_cube(path, &ramp(&[2, 3, 4]), &cards);
+}
+
+#[test]
+fn padded_ctypes_are_written_with_their_padding() {
+    let dir = scratch_dir("padded_ctype_fixture");
+    let path = dir.join("cube.fits");
+    write_padded(&path, true);
+    // The padding is in the file, so the tool is what has to ignore it
+    assert!(header_records(&path, 0)
+        .iter()
+        .any(|record| record.starts_with("CTYPE3  = 'FREQ         '")));
+}
+
+#[test]
+fn padded_ctypes_match_require_ctype_and_auto_mode() {
+    let dir = scratch_dir("padded_ctype_checks");
+    let input = dir.join("cube.fits");
+    write_padded(&input, true);
+
+    let output = fitsrotate(&[
+        arg(&input),
+        "312",
+        "--require-ctype",
+        "3=FREQ",
+        "--require-ctype",
+        "1=ra---sin",
+    ]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert_eq!(ctypes(&dir.join("cube.rot.fits")), ["FREQ", "RA---SIN", "DEC--SIN"]);
+
+    let auto = dir.join("auto.fits");
+    let output = fitsrotate(&[
+        arg(&input),
+        "--auto-mode",
+        "--target-order",
+        "FREQ,RA---SIN,DEC--SIN",
+        "--output",
+        arg(&auto),
+    ]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert_eq!(ctypes(&auto), ["FREQ", "RA---SIN", "DEC--SIN"]);
+}
+
+#[test]
+fn padded_and_unpadded_ctypes_make_a_triplet() {
+    let dir = scratch_dir("padded_ctype_triplet");
+    let trio: Vec<PathBuf> = ["i", "q", "u"]
+        .iter()
+        .map(|stokes| dir.join(format!("{}.fits", stokes)))
+        .collect();
+    for (idx, path) in trio.iter().enumerate() {
+        write_padded(path, idx == 1);
+    }
+    let output = fitsrotate(&["312", "--triplet", arg(&trio[0]), arg(&trio[1]), arg(&trio[2])]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    for path in &trio {
+        assert_eq!(
+            ctypes(&path.with_extension("rot.fits")),
+            ["FREQ", "RA---SIN", "DEC--SIN"]
+        );
+    }
+
+    let output = fitsrotate(&["check-consistency", arg(&trio[0]), arg(&trio[1]), arg(&trio[2])]);
+    assert!(output.status.success(), "{}", output_text(&output));
+}
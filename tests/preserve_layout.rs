@@ -0,0 +1,148 @@
+mod common;
+
+use common::{arg, fitsrotate, header_records, output_text, read_cube, scratch_dir};
+use ndarray::IxDyn;
+use std::path::Path;
+
+/// A card with the value right-aligned in columns 11-30 and a comment from column 32, as CFITSIO writes them
+fn record(keyword: &str, value: &str, comment: &str) -> String {
+    let record = if value.starts_with('\'') {
+        format!("{:<8}= {:<20} / {}", keyword, value, comment)
+    } else {
+        format!("{:<8}= {:>20} / {}", keyword, value, comment)
+    };
+    format!("{:<80}", record)
+}
+
+/// A commentary card, or a blank one
+fn commentary(keyword: &str, text: &str) -> String {
+    format!("{:<80}", format!("{:<8}{}", keyword, text))
+}
+
+/// Write a FITS file by hand from its header records and its data, so that the layout is not CFITSIO's
+fn write_raw(path: &Path, records: &[String], data: &[f32]) {
+    let mut bytes: Vec<u8> = records.iter().flat_map(|record| record.bytes()).collect();
+    bytes.extend(format!("{:<80}", "END").bytes());
+    bytes.resize(bytes.len().div_ceil(2880) * 2880, b' ');
+    bytes.extend(data.iter().flat_map(|value| value.to_be_bytes()));
+    bytes.resize(bytes.len().div_ceil(2880) * 2880, 0);
+    std::fs::write(path, bytes).unwrap();
+}
+
+/// The WCS cards of an axis, with comments naming the axis so that they can be followed
+fn axis_records(axis: usize, ctype: &str, crval: &str, cdelt: &str, cunit: &str) -> Vec<String> {
+    vec![
+        record(
+            &format!("CTYPE{}", axis),
+            &format!("'{}'", ctype),
+            &format!("type of axis {}", axis),
+        ),
+        record(
+            &format!("CRVAL{}", axis),
+            crval,
+            &format!("[{}] at CRPIX{}", cunit, axis),
+        ),
+        record(
+            &format!("CDELT{}", axis),
+            cdelt,
+            &format!("[{}] step of axis {}", cunit, axis),
+        ),
+        record(
+            &format!("CRPIX{}", axis),
+            "1.0",
+            &format!("reference pixel of axis {}", axis),
+        ),
+        record(
+            &format!("CUNIT{}", axis),
+            &format!("'{}'", cunit),
+            &format!("unit of axis {}", axis),
+        ),
+    ]
+}
+
+#[test]
+fn only_the_rotated_cards_of_a_preserved_layout_differ() {
+    let dir = scratch_dir("preserve_layout");
+    let input = dir.join("cube.fits");
+    let (n_ra, n_dec, n_freq) = (4, 3, 2);
+    let structural = |naxis: [usize; 3]| {
+        let mut records = vec![
+            record("SIMPLE", "T", "conforms to FITS standard"),
+            record("BITPIX", "-32", "array data type"),
+            record("NAXIS", "3", "number of array dimensions"),
+        ];
+        for (idx, n) in naxis.iter().enumerate() {
+            records.push(record(
+                &format!("NAXIS{}", idx + 1),
+                &n.to_string(),
+                "length of an axis",
+            ));
+        }
+        records
+    };
+    let ra = axis_records(1, "RA---SIN", "180.0", "-0.001", "deg");
+    let dec = axis_records(2, "DEC--SIN", "-45.0", "0.001", "deg");
+    // Integers in a real valued card, which must not be rewritten as "1420000000."
+    let freq = axis_records(3, "FREQ", "1420000000", "1000000", "Hz");
+    let body = |wcs: &[&Vec<String>]| {
+        let mut records = vec![
+            commentary("", ""),
+            record("OBJECT", "'M31     '", "name of the object"),
+            record("TELESCOP", "'ASKAP   '", "telescope"),
+            commentary("", ""),
+            commentary("COMMENT", "--- world coordinates ---"),
+        ];
+        for axis in wcs {
+            records.extend(axis.iter().cloned());
+        }
+        records.push(commentary("", ""));
+        records.push(record("BUNIT", "'Jy/beam '", "unit of the data"));
+        records
+    };
+    let input_records = [structural([n_ra, n_dec, n_freq]), body(&[&ra, &dec, &freq])].concat();
+    let data = common::ramp(&[n_freq, n_dec, n_ra]);
+    write_raw(&input, &input_records, data.as_slice().unwrap());
+
+    let output = fitsrotate(&[arg(&input), "312", "--preserve-layout"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let rotated = dir.join("cube.rot.fits");
+
+    // Each card of axis 3 of the input is now a card of axis 1, with the text of its value and its comment
+    let renumbered = |records: &Vec<String>, from: usize, to: usize| -> Vec<String> {
+        let (from, to) = (from.to_string(), to.to_string());
+        records
+            .iter()
+            .map(|record| format!("{}{}", record[..8].replacen(&from, &to, 1), &record[8..]))
+            .collect()
+    };
+    let mut expected = [
+        structural([n_freq, n_ra, n_dec]),
+        body(&[
+            &renumbered(&freq, 3, 1),
+            &renumbered(&ra, 1, 2),
+            &renumbered(&dec, 2, 3),
+        ]),
+    ]
+    .concat();
+    // The FITS standard requires EXTEND after the NAXISn cards of a primary header
+    expected.insert(6, record("EXTEND", "T", "FITS dataset may contain extensions"));
+
+    let output_records = header_records(&rotated, 0);
+    assert_eq!(&output_records[..expected.len()], &expected[..]);
+    // Only the cards of the output that have nothing in the input follow, in the order they were written
+    let appended: Vec<&str> = output_records[expected.len()..]
+        .iter()
+        .map(|record| record[..8].trim_end())
+        .collect();
+    assert_eq!(appended.last(), Some(&"END"));
+    for keyword in &appended {
+        assert!(
+            ["COMMENT", "EXTNAME", "DATE", "HISTORY", "END"].contains(keyword),
+            "{:?}",
+            appended
+        );
+    }
+
+    let rotated_data = read_cube::<f32>(&rotated);
+    assert_eq!(rotated_data, data.permuted_axes(IxDyn(&[1, 2, 0])));
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, scratch_dir, wcs_cards, write_cube};
+use ndarray::{ArrayD, IxDyn};
+use std::path::Path;
+
+/// The value of the fixture at a world coordinate, which is exact in float32 on the grid of the fixture
+fn value_at(ra: f64, dec: f64, freq: f64) -> f64 {
+    ra + 100.0 * dec + 10000.0 * freq
+}
+
+/// A cube whose values are `value_at` its world coordinates, with the WCS of `wcs_cards`
+fn world_cube(path: &Path, (n_ra, n_dec, n_freq): (usize, usize, usize)) {
+    // Axis n has CRVAL n * 10, CDELT n and CRPIX 1
+    let data = ArrayD::from_shape_fn(IxDyn(&[n_freq, n_dec, n_ra]), |idx| {
+        let world = |axis: usize, pixel: usize| axis as f64 * 10.0 + axis as f64 * pixel as f64;
+        value_at(world(1, idx[2]), world(2, idx[1]), world(3, idx[0])) as f32
+    });
+    write_cube(path, &data, &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+}
+
+/// The output of a successful probe
+fn probe(args: &[&str]) -> String {
+    let mut args = args.to_vec();
+    args.insert(0, "probe");
+    let output = fitsrotate(&args);
+    assert!(output.status.success(), "{}", output_text(&output));
+    String::from_utf8(output.stdout).unwrap()
+}
+
+/// The (world coordinate, value) rows of a spectrum printed by a probe, in order of the world coordinate
+fn spectrum_rows(text: &str) -> Vec<(f64, f64)> {
+    let mut rows: Vec<(f64, f64)> = text
+        .lines()
+        .skip(1)
+        .map(|line| {
+            let fields: Vec<f64> = line.split(',').map(|field| field.parse().unwrap()).collect();
+            (fields[1], fields[2])
+        })
+        .collect();
+    rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+    rows
+}
+
+/// The value printed by a probe of a voxel
+fn probed_value(text: &str) -> f64 {
+    let line = text.lines().find(|line| line.starts_with("Value:")).unwrap();
+    line["Value:".len()..].trim().parse().unwrap()
+}
+
+#[test]
+fn the_input_and_its_rotations_give_the_same_value_at_a_world_coordinate() {
+    let dir = scratch_dir("probe_world");
+    let input = dir.join("cube.fits");
+    world_cube(&input, (5, 4, 6));
+    let world = "ra=12deg,dec=24deg,freq=36Hz";
+    let expected = value_at(12.0, 24.0, 36.0);
+
+    let text = probe(&[arg(&input), "--world", world]);
+    assert_eq!(probed_value(&text), expected, "{}", text);
+    assert!(text.contains("Pixel: 3, 3, 3"), "{}", text);
+    // Other units of the same coordinate are the same voxel
+    let text = probe(&[arg(&input), "--world", "ra=720arcmin,dec=24deg,freq=0.036kHz"]);
+    assert_eq!(probed_value(&text), expected, "{}", text);
+
+    let spectrum = probe(&[arg(&input), "--world", world, "--spectrum", "freq"]);
+    let lines: Vec<&str> = spectrum.lines().collect();
+    assert_eq!(lines[0], "channel,FREQ,value");
+    assert_eq!(lines.len(), 7);
+    for (channel, line) in lines[1..].iter().enumerate() {
+        let freq = 30.0 + 3.0 * channel as f64;
+        assert_eq!(
+            *line,
+            format!("{},{},{}", channel + 1, freq, value_at(12.0, 24.0, freq))
+        );
+    }
+
+    for mode in ["312", "-321"] {
+        let output = dir.join(format!("cube.{}.fits", mode));
+        let rotated = fitsrotate(&[arg(&input), mode, "--output", arg(&output)]);
+        assert!(rotated.status.success(), "{}", output_text(&rotated));
+
+        let text = probe(&[arg(&output), "--world", world]);
+        assert_eq!(probed_value(&text), expected, "mode {}: {}", mode, text);
+        // A reversed spectral axis lists the same spectrum from the other end
+        let rotated_spectrum = probe(&[arg(&output), "--world", world, "--spectrum", "freq"]);
+        if !mode.starts_with('-') {
+            assert_eq!(rotated_spectrum, spectrum, "mode {}", mode);
+        }
+        assert_eq!(rotated_spectrum.lines().next(), Some("channel,FREQ,value"));
+        assert_eq!(
+            spectrum_rows(&rotated_spectrum),
+            spectrum_rows(&spectrum),
+            "mode {}",
+            mode
+        );
+    }
+
+    // The same voxel by pixel, with the axes in the order of the output
+    let text = probe(&[arg(&dir.join("cube.312.fits")), "--pixel", "3,3,3"]);
+    assert_eq!(probed_value(&text), expected, "{}", text);
+}
+
+#[test]
+fn positions_outside_the_cube_are_refused() {
+    let dir = scratch_dir("probe_outside");
+    let input = dir.join("cube.fits");
+    world_cube(&input, (5, 4, 6));
+    let output = fitsrotate(&["probe", arg(&input), "--world", "ra=100deg,dec=24deg,freq=36Hz"]);
+    assert!(!output.status.success());
+    assert!(
+        output_text(&output).contains("is outside the cube"),
+        "{}",
+        output_text(&output)
+    );
+    let output = fitsrotate(&["probe", arg(&input), "--pixel", "1,1"]);
+    assert!(!output.status.success());
+    assert!(
+        output_text(&output).contains("Expected 3 pixel coordinates, got 2"),
+        "{}",
+        output_text(&output)
+    );
+}
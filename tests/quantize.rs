@@ -0,0 +1,212 @@
+mod common;
+
+use common::{arg, data_bytes, fitsrotate, output_text, read_str, scratch_dir, wcs_cards, write_cube};
+use fitsio::FitsFile;
+use ndarray::{ArrayD, Dimension, IxDyn};
+use std::ffi::CString;
+use std::path::Path;
+
+/// The standard deviation of the noise of the fixture
+const SIGMA: f64 = 1.0;
+
+/// A cube of Gaussian noise on a smooth ramp, from a fixed seed
+fn noise_cube(shape: &[usize]) -> ArrayD<f32> {
+    let mut state: u64 = 12345;
+    let mut uniform = move || {
+        // SplitMix64, mapped onto (0, 1]
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 11) as f64 / (1u64 << 53) as f64 + f64::EPSILON
+    };
+    ArrayD::from_shape_fn(IxDyn(shape), |idx| {
+        let gaussian = (-2.0 * uniform().ln()).sqrt() * (2.0 * std::f64::consts::PI * uniform()).cos();
+        (100.0 + idx.slice().iter().sum::<usize>() as f64 + SIGMA * gaussian) as f32
+    })
+}
+
+/// The image of the compressed extension of a file, decompressed
+fn read_compressed(path: &Path) -> ArrayD<f32> {
+    let mut fits_file = FitsFile::open(path).unwrap();
+    let hdu = fits_file.hdu(1).unwrap();
+    hdu.read_image(&mut fits_file).unwrap()
+}
+
+/// The quantization step (ZSCALE) of each tile of the compressed extension of a file
+fn tile_scales(path: &Path) -> Vec<f64> {
+    let mut fits_file = FitsFile::open(path).unwrap();
+    fits_file.hdu(1).unwrap();
+    let column = CString::new("ZSCALE").unwrap();
+    let (mut colnum, mut n_rows, mut anynul, mut status) = (0, 0, 0, 0);
+    unsafe {
+        fitsio::sys::ffgcno(
+            fits_file.as_raw(),
+            0,
+            column.as_ptr() as *mut _,
+            &mut colnum,
+            &mut status,
+        );
+        fitsio::sys::ffgnrwll(fits_file.as_raw(), &mut n_rows, &mut status);
+    }
+    let mut scales = vec![0.0; n_rows as usize];
+    unsafe {
+        fitsio::sys::ffgcvd(
+            fits_file.as_raw(),
+            colnum,
+            1,
+            1,
+            n_rows,
+            0.0,
+            scales.as_mut_ptr(),
+            &mut anynul,
+            &mut status,
+        );
+    }
+    assert_eq!(status, 0);
+    scales
+}
+
+/// A card of the compressed extension of a file
+fn compressed_card(path: &Path, keyword: &str) -> Option<String> {
+    let mut fits_file = FitsFile::open(path).unwrap();
+    let hdu = fits_file.hdu(1).unwrap();
+    hdu.read_key::<String>(&mut fits_file, keyword).ok()
+}
+
+#[test]
+fn quantized_output_is_within_the_q_scaled_noise_and_reproducible_from_its_seed() {
+    let dir = scratch_dir("quantize");
+    let input = dir.join("cube.fits");
+    let data = noise_cube(&[256, 8, 16]);
+    write_cube(&input, &data, &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    let q = 4.0;
+    let rotate = |output: &Path, seed: &str| {
+        let args = [
+            arg(&input),
+            "312",
+            "--output",
+            arg(output),
+            "--compress",
+            "rice",
+            "--quantize",
+            "q=4",
+            "--dither",
+            "subtractive",
+            "--dither-seed",
+            seed,
+            "--stats",
+        ];
+        let output = fitsrotate(&args);
+        assert!(output.status.success(), "{}", output_text(&output));
+        output_text(&output)
+    };
+    let first = dir.join("first.fits");
+    let text = rotate(&first, "42");
+    assert!(text.contains("Stats OK:"), "{}", text);
+
+    // The settings are recorded, so the file can be decompressed and reproduced
+    assert_eq!(compressed_card(&first, "ZCMPTYPE").as_deref(), Some("RICE_1"));
+    assert_eq!(
+        compressed_card(&first, "ZQUANTIZ").as_deref(),
+        Some("SUBTRACTIVE_DITHER_1")
+    );
+    assert_eq!(compressed_card(&first, "ZDITHER0").as_deref(), Some("42"));
+    assert_eq!(compressed_card(&first, "EXTNAME").as_deref(), Some("COMPRESSED_IMAGE"));
+    assert_eq!(compressed_card(&first, "CTYPE1").as_deref(), Some("FREQ"));
+
+    // Each tile (a row along axis 1) is quantized in steps of its noise over q, and its pixels move by at most
+    // half a step, by an amount spread evenly over the step
+    let step = SIGMA / q;
+    let expected = data.permuted_axes(IxDyn(&[1, 2, 0]));
+    let quantized = read_compressed(&first);
+    assert_eq!(quantized.shape(), expected.shape());
+    let scales = tile_scales(&first);
+    let n_channels = expected.shape()[2];
+    assert_eq!(scales.len(), expected.len() / n_channels);
+    // The pixels in FITS order, so that each tile is a run of them
+    let quantized: Vec<f32> = quantized.iter().copied().collect();
+    let expected: Vec<f32> = expected.iter().copied().collect();
+    let mut squares = 0.0;
+    for (tile, (quantized, expected)) in quantized
+        .chunks(n_channels)
+        .zip(expected.chunks(n_channels))
+        .enumerate()
+    {
+        // The noise of a tile is measured from its pixels, so it scatters about sigma
+        let scale = scales[tile];
+        assert!(
+            scale > 0.5 * step && scale < 2.0 * step,
+            "tile {} has a step of {}",
+            tile,
+            scale
+        );
+        for (a, b) in quantized.iter().zip(expected.iter()) {
+            let error = *a as f64 - *b as f64;
+            assert!(
+                error.abs() <= scale / 2.0 + 1e-5,
+                "tile {} moved a pixel by {}",
+                tile,
+                error
+            );
+            squares += error * error;
+        }
+    }
+    let rms_error = (squares / expected.len() as f64).sqrt();
+    let uniform_rms = step / 12.0_f64.sqrt();
+    assert!(
+        (rms_error - uniform_rms).abs() < 0.2 * uniform_rms,
+        "RMS error {} for a step of {}",
+        rms_error,
+        step
+    );
+
+    // The same seed dithers the same way, and another seed does not
+    let second = dir.join("second.fits");
+    rotate(&second, "42");
+    assert_eq!(data_bytes(&first, 1), data_bytes(&second, 1));
+    let other = dir.join("other.fits");
+    rotate(&other, "43");
+    assert_eq!(compressed_card(&other, "ZDITHER0").as_deref(), Some("43"));
+    assert_ne!(data_bytes(&first, 1), data_bytes(&other, 1));
+}
+
+#[test]
+fn floating_point_pixels_are_only_quantized_with_an_explicit_level() {
+    let dir = scratch_dir("quantize_refused");
+    let input = dir.join("cube.fits");
+    let data = noise_cube(&[4, 8, 8]);
+    write_cube(&input, &data, &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+
+    // Rice has to quantize floating point pixels, so it is refused without a level
+    let output = fitsrotate(&[arg(&input), "312", "--compress", "rice"]);
+    assert!(!output.status.success());
+    assert!(
+        output_text(&output).contains("needs a quantization level"),
+        "{}",
+        output_text(&output)
+    );
+    assert!(!dir.join("cube.rot.fits").exists());
+    let output = fitsrotate(&[arg(&input), "312", "--compress", "rice", "--quantize", "4"]);
+    assert!(!output.status.success());
+    assert!(output_text(&output).contains("q=N"), "{}", output_text(&output));
+    let output = fitsrotate(&[arg(&input), "312", "--quantize", "q=4"]);
+    assert!(!output.status.success());
+    assert!(
+        output_text(&output).contains("needs --compress"),
+        "{}",
+        output_text(&output)
+    );
+
+    // Gzip compresses them losslessly, and records no quantization
+    let output = fitsrotate(&[arg(&input), "312", "--compress", "gzip2", "--stats"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let rotated = dir.join("cube.rot.fits");
+    assert_eq!(read_compressed(&rotated), data.permuted_axes(IxDyn(&[1, 2, 0])));
+    assert_eq!(compressed_card(&rotated, "ZCMPTYPE").as_deref(), Some("GZIP_2"));
+    assert_ne!(
+        compressed_card(&rotated, "ZQUANTIZ").as_deref(),
+        Some("SUBTRACTIVE_DITHER_1")
+    );
+    assert_eq!(read_str(&rotated, "NAXIS").as_deref(), Some("0"));
+}
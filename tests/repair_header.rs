@@ -0,0 +1,123 @@
+mod common;
+
+use common::{
+    arg, card, data_bytes, fitsrotate, header_records, output_text, ramp, read_cube, scratch_dir, wcs_cards,
+    write_cube, Value,
+};
+use fitsio::FitsFile;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+const MODE: [usize; 3] = [3, 1, 2];
+
+/// The WCS cards of the original, with a PC matrix coupling its first two axes
+fn original_cards() -> Vec<(String, Value)> {
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    cards.extend([card("PC1_2", 0.25), card("PC2_1", -0.5)]);
+    cards
+}
+
+/// The WCS of a file: each CTYPE, CRVAL, CDELT, CRPIX, CUNIT and PC card, with its value parsed
+fn wcs_values(path: &Path) -> BTreeMap<String, String> {
+    header_records(path, 0)
+        .iter()
+        .filter(|record| {
+            ["CTYPE", "CRVAL", "CDELT", "CRPIX", "CUNIT", "PC"]
+                .iter()
+                .any(|stub| record.starts_with(stub))
+        })
+        .map(|record| {
+            let value = record[10..].split('/').next().unwrap().trim();
+            let value = match value.parse::<f64>() {
+                Ok(number) => number.to_string(),
+                Err(_) => value.trim_matches('\'').trim_end().to_string(),
+            };
+            (record[..8].trim_end().to_string(), value)
+        })
+        .collect()
+}
+
+/// The (CFITSIO) checksum status of the primary HDU and its data: 1 if correct, 0 if missing, -1 if wrong
+fn checksum_status(path: &Path) -> (i32, i32) {
+    let mut fits_file = FitsFile::open(path).unwrap();
+    fits_file.primary_hdu().unwrap();
+    let (mut data_ok, mut hdu_ok, mut status) = (0, 0, 0);
+    unsafe {
+        fitsio::sys::ffvcks(fits_file.as_raw(), &mut data_ok, &mut hdu_ok, &mut status);
+    }
+    assert_eq!(status, 0);
+    (hdu_ok, data_ok)
+}
+
+/// Write the output of an older version: the rotated data, with output axis `MODE[n - 1]` given the WCS of input axis n
+fn write_wrongly_rotated(path: &Path, correct: &Path) {
+    let original: BTreeMap<String, Value> = original_cards().into_iter().collect();
+    let output_axis = |axis: usize| MODE[axis - 1];
+    let mut cards = Vec::new();
+    for stub in ["CTYPE", "CRVAL", "CDELT", "CRPIX", "CUNIT"] {
+        for axis in 1..4 {
+            cards.push((
+                format!("{}{}", stub, output_axis(axis)),
+                original[&format!("{}{}", stub, axis)].clone(),
+            ));
+        }
+    }
+    for (i, j) in [(1, 2), (2, 1)] {
+        let keyword = format!("PC{}_{}", output_axis(i), output_axis(j));
+        cards.push((keyword, original[&format!("PC{}_{}", i, j)].clone()));
+    }
+    write_cube(path, &read_cube::<f32>(correct), &cards);
+    let mut fits_file = FitsFile::edit(path).unwrap();
+    fits_file.primary_hdu().unwrap();
+    let mut status = 0;
+    unsafe {
+        fitsio::sys::ffpcks(fits_file.as_raw(), &mut status);
+    }
+    assert_eq!(status, 0);
+}
+
+#[test]
+fn fix_header_gives_the_wcs_of_a_fresh_rotation() {
+    let dir = scratch_dir("repair_header");
+    let original = dir.join("original.fits");
+    write_cube(&original, &ramp(&[4, 3, 2]), &original_cards());
+    let correct = dir.join("correct.fits");
+    let output = fitsrotate(&[arg(&original), "312", "--output", arg(&correct)]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let wrong = dir.join("wrong.fits");
+    write_wrongly_rotated(&wrong, &correct);
+    assert_ne!(wcs_values(&wrong), wcs_values(&correct));
+    let data = data_bytes(&wrong, 0);
+
+    // A dry run prints the changes and leaves the file alone
+    let before = std::fs::read(&wrong).unwrap();
+    let args = [
+        "fix-header",
+        arg(&wrong),
+        "--original",
+        arg(&original),
+        "--mode",
+        "312",
+        "--dry-run",
+    ];
+    let output = fitsrotate(&args);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let text = output_text(&output);
+    assert!(text.contains("- CTYPE1  = 'DEC--SIN'"), "{}", text);
+    assert!(text.contains("+ CTYPE1  = 'FREQ    '"), "{}", text);
+    assert_eq!(std::fs::read(&wrong).unwrap(), before);
+
+    let output = fitsrotate(&args[..args.len() - 1]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert_eq!(wcs_values(&wrong), wcs_values(&correct));
+    assert_eq!(data_bytes(&wrong, 0), data);
+    assert_eq!(checksum_status(&wrong), (1, 1));
+    assert!(header_records(&wrong, 0)
+        .iter()
+        .any(|record| record.starts_with("HISTORY fitsrotate_rs: repaired the WCS from")));
+
+    // A second repair finds nothing to do
+    let output = fitsrotate(&args[..args.len() - 1]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert!(output_text(&output).contains("Header OK:"), "{}", output_text(&output));
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use common::{arg, fitsrotate_command, output_text, ramp, read_str, scratch_dir, wcs_cards, write_cube};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::time::Duration;
+
+/// Rotate a cube with --reproducible and hash the output
+fn rotate_and_hash(input: &Path, output: &Path, epoch: Option<&str>) -> u64 {
+    let mut command = fitsrotate_command(&[
+        arg(input),
+        "321",
+        "--reproducible",
+        "--overwrite",
+        "--output",
+        arg(output),
+    ]);
+    if let Some(epoch) = epoch {
+        command.env("SOURCE_DATE_EPOCH", epoch);
+    }
+    let run = command.output().unwrap();
+    assert!(run.status.success(), "{}", output_text(&run));
+    let mut hasher = DefaultHasher::new();
+    std::fs::read(output).unwrap().hash(&mut hasher);
+    hasher.finish()
+}
+
+#[test]
+fn two_runs_produce_identical_files() {
+    let dir = scratch_dir("reproducible");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[2, 3, 4]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    let output = dir.join("cube.rot.fits");
+
+    let first = rotate_and_hash(&input, &output, None);
+    // DATE has a resolution of a second, so the runs must be more than a second apart
+    std::thread::sleep(Duration::from_millis(1100));
+    let second = rotate_and_hash(&input, &output, None);
+    assert_eq!(first, second);
+    assert_eq!(read_str(&output, "DATE"), None);
+
+    let first = rotate_and_hash(&input, &output, Some("951782400"));
+    std::thread::sleep(Duration::from_millis(1100));
+    let second = rotate_and_hash(&input, &output, Some("951782400"));
+    assert_eq!(first, second);
+    assert_eq!(read_str(&output, "DATE").as_deref(), Some("2000-02-29T00:00:00"));
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::{arg, data_bytes, fitsrotate, header_records, output_text, ramp, scratch_dir, wcs_cards, write_cube};
+use std::path::Path;
+
+/// The header records of the primary HDU, without those that record when and how it was written
+fn header_without_history(path: &Path) -> Vec<String> {
+    header_records(path, 0)
+        .into_iter()
+        .filter(|record| !record.starts_with("DATE    ") && !record.starts_with("HISTORY "))
+        .collect()
+}
+
+#[test]
+fn two_modes_give_the_outputs_of_two_single_runs() {
+    let dir = scratch_dir("several_modes");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[4, 3, 2]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+
+    let output = fitsrotate(&[arg(&input), "312", "--mode", "-321"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    for mode in ["312", "-321"] {
+        let single = dir.join(format!("single.{}.fits", mode));
+        let output = fitsrotate(&[arg(&input), mode, "--output", arg(&single)]);
+        assert!(output.status.success(), "{}", output_text(&output));
+
+        let several = dir.join(format!("cube.rot.{}.fits", mode));
+        assert_eq!(
+            header_without_history(&several),
+            header_without_history(&single),
+            "{}",
+            mode
+        );
+        assert_eq!(data_bytes(&several, 0), data_bytes(&single, 0), "{}", mode);
+    }
+    assert_ne!(
+        data_bytes(&dir.join("cube.rot.312.fits"), 0),
+        data_bytes(&dir.join("cube.rot.-321.fits"), 0)
+    );
+}
+
+#[test]
+fn the_outputs_of_several_modes_are_named_by_the_template() {
+    let dir = scratch_dir("several_modes_template");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[4, 3, 2]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+
+    let template = dir.join("out.{mode}.fits");
+    let output = fitsrotate(&[arg(&input), "312", "--mode", "213", "--output", arg(&template)]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert!(dir.join("out.312.fits").exists());
+    assert!(dir.join("out.213.fits").exists());
+
+    // Without {mode}, both outputs would have the same name, and nothing is written
+    let fixed = dir.join("out.fits");
+    let output = fitsrotate(&[arg(&input), "312", "--mode", "213", "--output", arg(&fixed)]);
+    assert!(!output.status.success());
+    assert!(
+        output_text(&output).contains("Modes 312 and 213 would both be written to"),
+        "{}",
+        output_text(&output)
+    );
+    assert!(!fixed.exists());
+}
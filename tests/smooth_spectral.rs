@@ -0,0 +1,46 @@
+mod common;
+
+use common::{arg, fitsrotate, header_records, output_text, read_cube, scratch_dir, set_card, wcs_cards, write_cube};
+use ndarray::{ArrayD, IxDyn};
+
+#[test]
+fn a_kernel_in_frequency_units_smooths_a_delta_spectrum() {
+    let dir = scratch_dir("smooth_spectral");
+    let input = dir.join("cube.fits");
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    set_card(&mut cards, "CDELT3", 1.0e6);
+    // A delta at channel 4 of each spectrum, of height 1 + x + 2y
+    let data = ArrayD::from_shape_fn(IxDyn(&[9, 3, 2]), |idx| {
+        if idx[0] == 4 {
+            (1 + idx[2] + 2 * idx[1]) as f32
+        } else {
+            0.0
+        }
+    });
+    write_cube(&input, &data, &cards);
+
+    let output = fitsrotate(&[arg(&input), "312", "--smooth-spectral", "boxcar=3MHz"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let rotated = dir.join("cube.rot.fits");
+
+    // 3 MHz is 3 channels, so each delta is spread evenly over its channel and the two either side
+    let smoothed = read_cube::<f32>(&rotated);
+    assert_eq!(smoothed.shape(), [3, 2, 9]);
+    for ((y, x, channel), value) in smoothed.into_dimensionality::<ndarray::Ix3>().unwrap().indexed_iter() {
+        let expected = if (3..6).contains(&channel) {
+            (1 + x + 2 * y) as f32 / 3.0
+        } else {
+            0.0
+        };
+        assert!((value - expected).abs() < 1e-6, "{} != {}", value, expected);
+    }
+
+    let history: String = header_records(&rotated, 0)
+        .iter()
+        .filter(|record| record.starts_with("HISTORY "))
+        .map(|record| record[8..].trim_end().to_string())
+        .collect();
+    let resolution = format!("an effective resolution of {} Hz", 10.0_f64.sqrt() * 1.0e6);
+    assert!(history.contains("boxcar of width 3 channels"), "{}", history);
+    assert!(history.contains(&resolution), "{}", history);
+}
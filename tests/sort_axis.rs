@@ -0,0 +1,146 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, ramp, read_cube, read_f64, read_str, scratch_dir, wcs_cards, write_cube};
+use fitsio::FitsFile;
+use ndarray::{ArrayD, Axis, IxDyn};
+use std::path::{Path, PathBuf};
+
+/// The order the channels are written in, as a correlator interleaving two sidebands might
+const SCRAMBLE: [usize; 6] = [3, 0, 4, 1, 5, 2];
+
+/// A cube of 6 channels written in the order of `SCRAMBLE`, where every pixel of a channel holds the index of its
+/// true frequency, plus its position in the plane
+fn scrambled_cube(dir: &Path) -> (ArrayD<f32>, PathBuf) {
+    let mut data = ramp(&[6, 3, 4]).mapv(|v| (v % 12.0) / 100.0);
+    for (plane, channel) in SCRAMBLE.iter().enumerate() {
+        data.index_axis_mut(Axis(0), plane)
+            .mapv_inplace(|v| v + *channel as f32);
+    }
+    let input = dir.join("cube.fits");
+    write_cube(&input, &data, &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    (data, input)
+}
+
+/// A column of the SORTPERM extension of a file
+fn sort_column<T: fitsio::tables::ReadsCol>(path: &Path, column: &str) -> Vec<T> {
+    let mut fits_file = FitsFile::open(path).unwrap();
+    let hdu = fits_file.hdu("SORTPERM").unwrap();
+    hdu.read_col(&mut fits_file, column).unwrap()
+}
+
+/// The (0-based) input plane of each sorted plane
+fn sorted_planes() -> Vec<usize> {
+    let mut planes: Vec<usize> = (0..SCRAMBLE.len()).collect();
+    planes.sort_by_key(|plane| SCRAMBLE[*plane]);
+    planes
+}
+
+#[test]
+fn a_scrambled_ramp_comes_out_in_order_with_a_linear_axis() {
+    let dir = scratch_dir("sort_axis_linear");
+    let (data, input) = scrambled_cube(&dir);
+    // Evenly spaced frequencies, 1 MHz apart from 1.4 GHz
+    let keys: Vec<String> = SCRAMBLE
+        .iter()
+        .map(|channel| format!("{}MHz", 1400 + channel))
+        .collect();
+    let key_file = dir.join("freqs.txt");
+    std::fs::write(&key_file, format!("# true frequencies\n{}\n", keys.join("\n"))).unwrap();
+
+    let output = dir.join("cube.sorted.fits");
+    let args = [
+        arg(&input),
+        "312",
+        "--sort-axis",
+        "freq",
+        "--sort-key",
+        arg(&key_file),
+        "--output",
+        arg(&output),
+    ];
+    let run = fitsrotate(&args);
+    assert!(run.status.success(), "{}", output_text(&run));
+
+    // FREQ is axis 1 of the output, and its planes have the channels in ascending order
+    let sorted = read_cube::<f32>(&output);
+    let firsts: Vec<f32> = sorted
+        .index_axis(Axis(0), 0)
+        .index_axis(Axis(0), 0)
+        .iter()
+        .copied()
+        .collect();
+    assert!(firsts.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", firsts);
+    for (plane, first) in firsts.iter().enumerate() {
+        assert_eq!(first.floor(), plane as f32);
+    }
+    // Each plane is a whole input plane
+    let expected = data.select(Axis(0), &sorted_planes()).permuted_axes(IxDyn(&[1, 2, 0]));
+    assert_eq!(sorted, expected);
+
+    assert_eq!(read_str(&output, "CTYPE1").as_deref(), Some("FREQ"));
+    assert_eq!(read_f64(&output, "CRVAL1"), Some(1.4e9));
+    let cdelt = read_f64(&output, "CDELT1").unwrap();
+    assert!((cdelt - 1e6).abs() < 1e-3, "{}", cdelt);
+    assert_eq!(read_f64(&output, "CRPIX1"), Some(1.0));
+
+    // The permutation is recorded, so that the sort can be undone
+    let planes: Vec<i64> = sort_column(&output, "PLANE");
+    let expected_planes: Vec<i64> = sorted_planes().iter().map(|plane| *plane as i64 + 1).collect();
+    assert_eq!(planes, expected_planes);
+    let recorded: Vec<f64> = sort_column(&output, "KEY");
+    assert!(recorded.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", recorded);
+}
+
+#[test]
+fn uneven_keys_give_a_tab_lookup() {
+    let dir = scratch_dir("sort_axis_tab");
+    let (data, input) = scrambled_cube(&dir);
+    // The channels are unevenly spaced, so no linear WCS describes them
+    let frequencies = [1.0e9, 1.1e9, 1.3e9, 1.4e9, 1.8e9, 1.9e9];
+    let keys: Vec<String> = SCRAMBLE
+        .iter()
+        .map(|channel| frequencies[*channel].to_string())
+        .collect();
+    let key_file = dir.join("freqs.txt");
+    std::fs::write(&key_file, keys.join("\n")).unwrap();
+
+    let output = dir.join("cube.sorted.fits");
+    let args = [
+        arg(&input),
+        "123",
+        "--sort-axis",
+        "3",
+        "--sort-key",
+        arg(&key_file),
+        "--output",
+        arg(&output),
+    ];
+    let run = fitsrotate(&args);
+    assert!(run.status.success(), "{}", output_text(&run));
+    assert_eq!(read_cube::<f32>(&output), data.select(Axis(0), &sorted_planes()));
+
+    assert_eq!(read_str(&output, "CTYPE3").as_deref(), Some("FREQ-TAB"));
+    assert_eq!(read_str(&output, "PS3_0").as_deref(), Some("WCS-TAB"));
+    assert_eq!(read_str(&output, "PS3_1").as_deref(), Some("COORDS"));
+    let mut fits_file = FitsFile::open(&output).unwrap();
+    let hdu = fits_file.hdu("WCS-TAB").unwrap();
+    let coords: Vec<f64> = hdu.read_col(&mut fits_file, "COORDS").unwrap();
+    assert_eq!(coords, frequencies);
+
+    // A key file of the wrong length is refused before anything is written
+    std::fs::write(&key_file, keys[..5].join("\n")).unwrap();
+    let refused = dir.join("cube.refused.fits");
+    let run = fitsrotate(&[
+        arg(&input),
+        "123",
+        "--sort-axis",
+        "3",
+        "--sort-key",
+        arg(&key_file),
+        "--output",
+        arg(&refused),
+    ]);
+    assert!(!run.status.success());
+    assert!(output_text(&run).contains("has 5 keys"), "{}", output_text(&run));
+    assert!(!refused.exists());
+}
@@ -0,0 +1,55 @@
+mod common;
+
+use common::{
+    arg, fitsrotate, header_records, output_text, read_cube, read_f64, scratch_dir, set_card, wcs_cards, write_cube,
+};
+use ndarray::{ArrayD, IxDyn};
+
+#[test]
+fn a_linear_spectrum_is_regridded_and_its_wcs_rewritten() {
+    let dir = scratch_dir("spectral_regrid");
+    let input = dir.join("cube.fits");
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    set_card(&mut cards, "CRVAL3", 1.0e9);
+    set_card(&mut cards, "CDELT3", 1.0e6);
+    set_card(&mut cards, "CRPIX3", 3.0);
+    // The value at frequency nu (in MHz) of the spectrum at pixel (x, y) is 2 * nu - 100 * (x + 2y)
+    let line = |x: usize, y: usize, mhz: f64| 2.0 * mhz - 100.0 * (x + 2 * y) as f64;
+    // Channel c (0-based) is at 998 + c MHz
+    let data = ArrayD::from_shape_fn(IxDyn(&[8, 3, 2]), |idx| {
+        line(idx[2], idx[1], 998.0 + idx[0] as f64) as f32
+    });
+    write_cube(&input, &data, &cards);
+
+    let args = ["312", "--spectral-regrid", "start=998.5MHz,width=0.002GHz,n=5"];
+    let output = fitsrotate(&[&[arg(&input)], &args[..]].concat());
+    assert!(output.status.success(), "{}", output_text(&output));
+    let rotated = dir.join("cube.rot.fits");
+
+    // The spectral axis is first, with the new grid
+    assert_eq!(read_f64(&rotated, "NAXIS1"), Some(5.0));
+    assert_eq!(read_f64(&rotated, "CRVAL1"), Some(998.5e6));
+    assert_eq!(read_f64(&rotated, "CDELT1"), Some(2.0e6));
+    assert_eq!(read_f64(&rotated, "CRPIX1"), Some(1.0));
+    let history: String = header_records(&rotated, 0)
+        .iter()
+        .filter(|record| record.starts_with("HISTORY "))
+        .map(|record| record[8..].trim_end().to_string())
+        .collect();
+    assert!(
+        history.contains("regridded axis 3 onto start=998500000, width=2000000, n=5"),
+        "{}",
+        history
+    );
+
+    // Channel c of the new grid is at 998.5 + 2c MHz; the last one, at 1006.5 MHz, is beyond the input
+    let regridded = read_cube::<f32>(&rotated);
+    assert_eq!(regridded.shape(), [3, 2, 5]);
+    for ((y, x, channel), value) in regridded.into_dimensionality::<ndarray::Ix3>().unwrap().indexed_iter() {
+        if channel == 4 {
+            assert!(value.is_nan(), "{}", value);
+        } else {
+            assert_eq!(*value as f64, line(x, y, 998.5 + 2.0 * channel as f64));
+        }
+    }
+}
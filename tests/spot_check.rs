@@ -0,0 +1,101 @@
+mod common;
+
+use common::{arg, fitsrotate, fitsrotate_command, output_text, ramp, scratch_dir, wcs_cards, write_cube};
+use std::path::{Path, PathBuf};
+
+/// A 32 x 32 x 16 ramp, so that every voxel has its own value
+fn ramp_cube(dir: &Path) -> PathBuf {
+    let input = dir.join("cube.fits");
+    write_cube(
+        &input,
+        &ramp(&[16, 32, 32]),
+        &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]),
+    );
+    input
+}
+
+#[test]
+fn a_faithful_rotation_passes_the_spot_check() {
+    let dir = scratch_dir("spot_check_ok");
+    let input = ramp_cube(&dir);
+    for mode in ["312", "-231"] {
+        let output = dir.join(format!("cube.{}.fits", mode));
+        let args = [
+            arg(&input),
+            mode,
+            "--output",
+            arg(&output),
+            "--spot-check",
+            "1000",
+            "--spot-seed",
+            "7",
+        ];
+        let run = fitsrotate(&args);
+        assert!(run.status.success(), "{}", output_text(&run));
+        assert!(
+            output_text(&run).contains("Spot check OK: 1000 voxels"),
+            "{}",
+            output_text(&run)
+        );
+        assert!(output_text(&run).contains("(seed 7)"), "{}", output_text(&run));
+    }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+fn a_corrupted_output_fails_the_spot_check_whatever_the_seed() {
+    let dir = scratch_dir("spot_check_corrupt");
+    let input = ramp_cube(&dir);
+    let output = dir.join("cube.rot.fits");
+    // One voxel in a hundred is wrong, so 1000 samples all miss it with a probability of 0.99^1000 = 4e-5
+    for seed in 1..=20 {
+        let seed = seed.to_string();
+        let args = [
+            arg(&input),
+            "-312",
+            "--overwrite",
+            "--spot-check",
+            "1000",
+            "--spot-seed",
+            &seed,
+        ];
+        let run = fitsrotate_command(&args)
+            .env("FITSROTATE_RS_TEST_CORRUPT_EVERY", "100")
+            .output()
+            .unwrap();
+        let text = output_text(&run);
+        assert!(!run.status.success(), "seed {}: {}", seed, text);
+
+        // The first mismatch is reported with the voxel of the input and of the output, which differ by the corruption
+        let message = text.lines().find(|line| line.contains("Spot check of")).unwrap();
+        assert!(message.contains(arg(&output)), "{}", message);
+        assert!(message.ends_with(&format!("(seed {})", seed)), "{}", message);
+        let pixels: Vec<Vec<usize>> = message
+            .split('[')
+            .skip(1)
+            .map(|rest| {
+                let list = rest.split(']').next().unwrap();
+                list.split(", ").map(|p| p.parse().unwrap()).collect()
+            })
+            .collect();
+        let (in_pixel, out_pixel) = (&pixels[0], &pixels[1]);
+        // Mode -312 puts input axis 3 first, reversed
+        assert_eq!(
+            out_pixel,
+            &vec![17 - in_pixel[2], in_pixel[0], in_pixel[1]],
+            "{}",
+            message
+        );
+        let value = |pixel: &[usize]| ((pixel[0] - 1) + 32 * (pixel[1] - 1) + 32 * 32 * (pixel[2] - 1)) as f64;
+        assert!(
+            message.contains(&format!("is {} but output voxel", value(in_pixel))),
+            "{}",
+            message
+        );
+        assert!(
+            message.contains(&format!("is {} (seed", value(in_pixel) + 1.0)),
+            "{}",
+            message
+        );
+    }
+}
@@ -0,0 +1,82 @@
+mod common;
+
+use common::{
+    arg, ctypes, fitsrotate, output_text, ramp, read_cube, read_f64, scratch_dir, set_card, wcs_cards, write_cube,
+};
+use ndarray::{s, ArrayD};
+use std::path::Path;
+
+/// Write a (FITS order) 2 x 3 x N cube whose third axis is STOKES, with the given CRVAL and CDELT
+fn write_stokes_cube(path: &Path, planes: usize, crval: f64, cdelt: f64) -> ArrayD<f32> {
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "STOKES"]);
+    set_card(&mut cards, "CRVAL3", crval);
+    set_card(&mut cards, "CDELT3", cdelt);
+    let data = ramp(&[planes, 3, 2]);
+    write_cube(path, &data, &cards);
+    data
+}
+
+/// The Stokes code of the first pixel of an axis of a FITS file
+fn first_code(path: &Path, axis: usize) -> f64 {
+    let number = |stub: &str| read_f64(path, &format!("{}{}", stub, axis)).unwrap();
+    number("CRVAL") + (1.0 - number("CRPIX")) * number("CDELT")
+}
+
+#[test]
+fn stokes_u_is_taken_out_of_an_iquv_cube() {
+    let dir = scratch_dir("stokes_iquv");
+    let input = dir.join("iquv.fits");
+    let data = write_stokes_cube(&input, 4, 1.0, 1.0);
+
+    let output = fitsrotate(&[arg(&input), "21", "--stokes", "u"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let rotated = dir.join("iquv.rot.fits");
+    assert_eq!(read_f64(&rotated, "NAXIS"), Some(2.0));
+    assert_eq!(ctypes(&rotated), ["DEC--SIN", "RA---SIN"]);
+    assert_eq!(read_f64(&rotated, "CRVAL3"), None);
+    let plane = data.slice(s![2, .., ..]).reversed_axes().into_owned().into_dyn();
+    assert_eq!(read_cube::<f32>(&rotated), plane);
+
+    // Kept, the STOKES axis has one pixel that is still labelled U
+    let kept = dir.join("kept.fits");
+    let args = ["312", "--stokes", "U", "--keep-stokes-axis", "--output", arg(&kept)];
+    let output = fitsrotate(&[&[arg(&input)], &args[..]].concat());
+    assert!(output.status.success(), "{}", output_text(&output));
+    assert_eq!(ctypes(&kept), ["STOKES", "RA---SIN", "DEC--SIN"]);
+    assert_eq!(read_f64(&kept, "NAXIS1"), Some(1.0));
+    assert_eq!(first_code(&kept, 1), 3.0);
+    let plane = data
+        .slice(s![2..3, .., ..])
+        .permuted_axes([1, 2, 0])
+        .into_owned()
+        .into_dyn();
+    assert_eq!(read_cube::<f32>(&kept), plane);
+}
+
+#[test]
+fn linear_correlations_are_found_from_negative_codes() {
+    let dir = scratch_dir("stokes_xxyy");
+    let input = dir.join("xxyy.fits");
+    // XX is -5 and YY is -6
+    let data = write_stokes_cube(&input, 2, -5.0, -1.0);
+
+    let output = fitsrotate(&[arg(&input), "12", "--stokes", "YY"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let rotated = dir.join("xxyy.rot.fits");
+    assert_eq!(ctypes(&rotated), ["RA---SIN", "DEC--SIN"]);
+    assert_eq!(
+        read_cube::<f32>(&rotated),
+        data.slice(s![1, .., ..]).into_owned().into_dyn()
+    );
+
+    // A parameter that is not on the axis lists those that are
+    let missing = dir.join("missing.fits");
+    let output = fitsrotate(&[arg(&input), "12", "--stokes", "I", "--output", arg(&missing)]);
+    assert!(!output.status.success());
+    assert!(
+        output_text(&output).contains("Stokes I is not in") && output_text(&output).contains("which has XX, YY"),
+        "{}",
+        output_text(&output)
+    );
+    assert!(!missing.exists());
+}
@@ -0,0 +1,165 @@
+mod common;
+
+use common::{
+    arg, card, data_bytes, fitsrotate, fitsrotate_command, header_records, output_text, ramp, read_f64, read_str,
+    scratch_dir, wcs_cards, write_cube, write_image,
+};
+use fitsio::images::ImageType;
+use std::io::Write;
+use std::path::Path;
+use std::process::Stdio;
+
+/// Rotate a fixture both to a file and with `stream`, writing the streamed bytes next to it
+fn rotate_both_ways(name: &str, mode: &str) -> (std::path::PathBuf, std::path::PathBuf) {
+    let dir = scratch_dir(name);
+    let input = dir.join("cube.fits");
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    cards.push(card("PC1_2", 0.5));
+    cards.push(card("PC2_1", -0.25));
+    cards.push(card("OBJECT", "M31"));
+    write_cube(&input, &ramp(&[2, 3, 4]), &cards);
+
+    let output = fitsrotate(&[arg(&input), mode]);
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    let output = fitsrotate(&["stream", arg(&input), mode]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let streamed = dir.join("streamed.fits");
+    std::fs::write(&streamed, &output.stdout).unwrap();
+    (dir.join("cube.rot.fits"), streamed)
+}
+
+/// Check that two files have the same data and WCS
+fn assert_same_rotation(rotated: &Path, streamed: &Path) {
+    assert_eq!(data_bytes(streamed, 0), data_bytes(rotated, 0));
+    for axis in 1..4 {
+        for stub in ["NAXIS", "CRVAL", "CDELT", "CRPIX"] {
+            let keyword = format!("{}{}", stub, axis);
+            assert_eq!(read_f64(streamed, &keyword), read_f64(rotated, &keyword), "{}", keyword);
+        }
+        for stub in ["CTYPE", "CUNIT"] {
+            let keyword = format!("{}{}", stub, axis);
+            assert_eq!(read_str(streamed, &keyword), read_str(rotated, &keyword), "{}", keyword);
+        }
+        for j in 1..4 {
+            let keyword = format!("PC{}_{}", axis, j);
+            assert_eq!(read_f64(streamed, &keyword), read_f64(rotated, &keyword), "{}", keyword);
+        }
+    }
+}
+
+#[test]
+fn streaming_writes_the_same_rotation_as_a_file() {
+    let (rotated, streamed) = rotate_both_ways("stream", "312");
+    // Standard output holds the FITS file alone
+    let bytes = std::fs::read(&streamed).unwrap();
+    assert_eq!(bytes.len() % 2880, 0);
+    assert!(bytes.starts_with(b"SIMPLE  ="));
+
+    assert_same_rotation(&rotated, &streamed);
+    assert_eq!(read_str(&streamed, "CTYPE1").as_deref(), Some("FREQ"));
+    assert_eq!(read_f64(&streamed, "PC2_3"), Some(0.5));
+    assert_eq!(read_str(&streamed, "OBJECT").as_deref(), Some("M31"));
+    // The input cards of the old axes are all replaced, rather than kept alongside
+    let records = header_records(&streamed, 0);
+    let count = |keyword: &str| records.iter().filter(|record| record.starts_with(keyword)).count();
+    assert_eq!(count("CTYPE1  "), 1);
+    assert_eq!(count("PC1_2   "), 0);
+    assert_eq!(count("EXTNAME "), 1);
+    // A HISTORY text longer than a card carries on in the next one
+    let history: String = records
+        .iter()
+        .filter_map(|record| record.strip_prefix("HISTORY "))
+        .collect();
+    let input = rotated.with_file_name("cube.fits");
+    let expected = format!("fitsrotate_rs: reordered the axes of {} with mode 312", input.display());
+    assert!(history.contains(&expected), "{}", history);
+}
+
+#[test]
+fn streaming_a_flip_writes_the_same_rotation_as_a_file() {
+    let (rotated, streamed) = rotate_both_ways("stream_flip", "3-12");
+    assert_same_rotation(&rotated, &streamed);
+    assert_eq!(read_f64(&streamed, "CDELT2"), Some(-1.0));
+}
+
+#[test]
+fn streaming_a_bad_mode_writes_nothing() {
+    let dir = scratch_dir("stream_bad_mode");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[2, 3, 4]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    let output = fitsrotate(&["stream", arg(&input), "3121"]);
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty(), "{}", output_text(&output));
+}
+
+/// Run `stream - MODE` with a file on standard input
+fn stream_stdin(input: &Path, mode: &str) -> std::process::Output {
+    let mut child = fitsrotate_command(&["stream", "-", mode])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(&std::fs::read(input).unwrap())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn streaming_from_standard_input_matches_streaming_a_file() {
+    let dir = scratch_dir("stream_stdin");
+    let inputs = [
+        ("float.fits", ImageType::Float, -32.0),
+        ("double.fits", ImageType::Double, -64.0),
+    ];
+    for (name, image_type, bitpix) in inputs {
+        let input = dir.join(name);
+        let data = ramp(&[2, 3, 4]).mapv(|v| v as f64 + 0.125);
+        write_image(&input, image_type, &data, &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+
+        let from_file = fitsrotate(&["stream", arg(&input), "3-12"]);
+        assert!(from_file.status.success(), "{}", output_text(&from_file));
+        let from_stdin = stream_stdin(&input, "3-12");
+        assert!(from_stdin.status.success(), "{}", output_text(&from_stdin));
+
+        let (file_out, stdin_out) = (dir.join("from_file.fits"), dir.join("from_stdin.fits"));
+        std::fs::write(&file_out, &from_file.stdout).unwrap();
+        std::fs::write(&stdin_out, &from_stdin.stdout).unwrap();
+        // The data keeps its type, though it was read from standard input without knowing it
+        assert_eq!(read_f64(&stdin_out, "BITPIX"), Some(bitpix));
+        assert_same_rotation(&file_out, &stdin_out);
+
+        // Only the HISTORY cards naming the input differ
+        let other_records = |path: &Path| -> Vec<String> {
+            header_records(path, 0)
+                .into_iter()
+                .filter(|record| !record.starts_with("HISTORY "))
+                .collect()
+        };
+        assert_eq!(other_records(&stdin_out), other_records(&file_out));
+        let history: String = header_records(&stdin_out, 0)
+            .iter()
+            .filter_map(|record| record.strip_prefix("HISTORY "))
+            .collect();
+        assert!(
+            history.contains("reordered the axes of standard input with mode 3-12"),
+            "{}",
+            history
+        );
+    }
+}
+
+#[test]
+fn streaming_garbage_from_standard_input_fails() {
+    let dir = scratch_dir("stream_stdin_garbage");
+    let input = dir.join("garbage.fits");
+    std::fs::write(&input, b"not a FITS file").unwrap();
+    let output = stream_stdin(&input, "312");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty(), "{}", output_text(&output));
+}
@@ -0,0 +1,48 @@
+mod common;
+
+use common::{arg, fitsrotate, header_records, output_text, read_cube, scratch_dir, wcs_cards, write_cube};
+use ndarray::{ArrayD, Ix3, IxDyn};
+
+#[test]
+fn planted_outliers_are_blanked_and_counted_per_channel() {
+    let dir = scratch_dir("threshold");
+    let input = dir.join("cube.fits");
+    // (FREQ, DEC, RA) in array order, with RFI in channels 2 and 4
+    let mut cube = ArrayD::<f32>::from_elem(IxDyn(&[4, 3, 5]), 1.0);
+    cube[[1, 0, 0]] = 1e6;
+    cube[[1, 2, 4]] = -5e3;
+    cube[[1, 1, 1]] = f32::NAN;
+    cube[[3, 1, 2]] = 2e3;
+    write_cube(&input, &cube, &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+
+    let output = fitsrotate(&[arg(&input), "312", "--threshold-min", "-1e3", "--threshold-max", "1e3"]);
+    let text = output_text(&output);
+    assert!(output.status.success(), "{}", text);
+    let counts: Vec<&str> = text.lines().filter(|line| line.starts_with("Blanked")).collect();
+    assert_eq!(
+        counts,
+        [
+            "Blanked 2 pixels outside [-1000, 1000] in plane 2 of axis 3 (FREQ)",
+            "Blanked 1 pixels outside [-1000, 1000] in plane 4 of axis 3 (FREQ)",
+            "Blanked 3 of 60 pixels outside [-1000, 1000]",
+        ]
+    );
+
+    // FREQ is output axis 1, the last array axis
+    let rotated = read_cube::<f32>(&dir.join("cube.rot.fits"))
+        .into_dimensionality::<Ix3>()
+        .unwrap();
+    let blanked: Vec<(usize, usize, usize)> = rotated
+        .indexed_iter()
+        .filter(|(_, value)| value.is_nan())
+        .map(|(idx, _)| idx)
+        .collect();
+    // The planted outliers, and the pixel that was already blank
+    assert_eq!(blanked, [(0, 0, 1), (1, 1, 1), (1, 2, 3), (2, 4, 1)]);
+    assert!(rotated.iter().all(|value| value.is_nan() || *value == 1.0));
+
+    let history = header_records(&dir.join("cube.rot.fits"), 0);
+    assert!(history
+        .iter()
+        .any(|record| record.starts_with("HISTORY") && record.contains("blanked 3 pixels outside [-1000, 1000]")));
+}
@@ -0,0 +1,74 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, scratch_dir, wcs_cards, write_cube};
+use fitsio::FitsFile;
+use ndarray::{ArrayD, IxDyn};
+use std::path::Path;
+
+/// A card of the compressed extension of a file
+fn compressed_card(path: &Path, keyword: &str) -> Option<i64> {
+    let mut fits_file = FitsFile::open(path).unwrap();
+    let hdu = fits_file.hdu(1).unwrap();
+    hdu.read_key::<i64>(&mut fits_file, keyword).ok()
+}
+
+#[test]
+fn larger_tiles_give_smaller_files() {
+    let dir = scratch_dir("tile_size");
+    let input = dir.join("cube.fits");
+    // A smooth image in each of 4 channels, which compresses well in large tiles
+    let data = ArrayD::from_shape_fn(IxDyn(&[4, 64, 64]), |idx| {
+        (idx[0] as f32 * 1000.0) + ((idx[1] as f32 / 8.0).sin() * (idx[2] as f32 / 8.0).cos() * 100.0).round()
+    });
+    write_cube(&input, &data, &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+
+    let mut sizes = Vec::new();
+    for tile_size in ["4,4", "16,16", "64,64"] {
+        let output = dir.join(format!("cube.{}.fits", tile_size.replace(',', "x")));
+        let args = [
+            arg(&input),
+            "312",
+            "--output",
+            arg(&output),
+            "--compress",
+            "gzip2",
+            "--tile-size",
+            tile_size,
+            "--stats",
+        ];
+        let run = fitsrotate(&args);
+        assert!(run.status.success(), "{}", output_text(&run));
+
+        // The spectral axis, now axis 1, is tiled a channel at a time, and the spatial axes by the tile size
+        let length: i64 = tile_size.split(',').next().unwrap().parse().unwrap();
+        assert_eq!(compressed_card(&output, "ZTILE1"), Some(1));
+        assert_eq!(compressed_card(&output, "ZTILE2"), Some(length));
+        assert_eq!(compressed_card(&output, "ZTILE3"), Some(length));
+        assert_eq!(
+            compressed_card(&output, "NAXIS2"),
+            Some(4 * 64 * 64 / (length * length))
+        );
+        sizes.push(std::fs::metadata(&output).unwrap().len());
+    }
+    assert!(sizes[0] > sizes[1] && sizes[1] > sizes[2], "{:?}", sizes);
+}
+
+#[test]
+fn the_tile_size_needs_compression_and_two_lengths() {
+    let dir = scratch_dir("tile_size_refused");
+    let input = dir.join("cube.fits");
+    write_cube(
+        &input,
+        &common::ramp(&[2, 4, 4]),
+        &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]),
+    );
+    for args in [
+        vec![arg(&input), "312", "--tile-size", "4,4"],
+        vec![arg(&input), "312", "--compress", "gzip", "--tile-size", "4"],
+        vec![arg(&input), "312", "--compress", "gzip", "--tile-size", "0,4"],
+    ] {
+        let run = fitsrotate(&args);
+        assert!(!run.status.success(), "{:?}: {}", args, output_text(&run));
+    }
+    assert!(!dir.join("cube.rot.fits").exists());
+}
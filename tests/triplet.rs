@@ -0,0 +1,54 @@
+mod common;
+
+use common::{arg, ctypes, fitsrotate, output_text, ramp, scratch_dir, set_card, wcs_cards, write_cube};
+use std::path::PathBuf;
+
+/// Write an I, Q, U trio of 2x3x4 cubes, with CRVAL3 of U shifted by `shift`
+fn write_trio(name: &str, shift: f64) -> Vec<PathBuf> {
+    let dir = scratch_dir(name);
+    ["i", "q", "u"]
+        .iter()
+        .map(|stokes| {
+            let path = dir.join(format!("{}.fits", stokes));
+            let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+            if *stokes == "u" {
+                set_card(&mut cards, "CRVAL3", 30.0 + shift);
+            }
+            write_cube(&path, &ramp(&[2, 3, 4]), &cards);
+            path
+        })
+        .collect()
+}
+
+#[test]
+fn matching_trio_is_rotated() {
+    let trio = write_trio("triplet_matching", 0.0);
+    let output = fitsrotate(&["312", "--triplet", arg(&trio[0]), arg(&trio[1]), arg(&trio[2])]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    for path in &trio {
+        let rotated = path.with_extension("rot.fits");
+        assert_eq!(ctypes(&rotated), ["FREQ", "RA---SIN", "DEC--SIN"]);
+    }
+}
+
+#[test]
+fn shifted_crval3_fails_the_whole_trio() {
+    let trio = write_trio("triplet_shifted", 1e-3);
+    let output = fitsrotate(&["312", "--triplet", arg(&trio[0]), arg(&trio[1]), arg(&trio[2])]);
+    assert!(!output.status.success());
+    let text = output_text(&output);
+    assert!(
+        text.contains(&format!(
+            "CRVAL3 differs between {} and {}",
+            arg(&trio[0]),
+            arg(&trio[2])
+        )),
+        "{}",
+        text
+    );
+    // Only the shifted keyword of the shifted file is reported
+    assert!(!text.contains(&format!("and {}", arg(&trio[1]))), "{}", text);
+    for path in &trio {
+        assert!(!path.with_extension("rot.fits").exists());
+    }
+}
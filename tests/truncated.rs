@@ -0,0 +1,48 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, ramp, scratch_dir, wcs_cards, write_cube};
+use std::path::Path;
+
+/// Write a fixture whose data unit spans several 2880-byte blocks
+fn write_fixture(path: &Path) -> u64 {
+    write_cube(path, &ramp(&[2, 30, 40]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    std::fs::metadata(path).unwrap().len()
+}
+
+#[test]
+fn intact_files_pass_the_size_check() {
+    let dir = scratch_dir("truncated_intact");
+    let input = dir.join("cube.fits");
+    write_fixture(&input);
+
+    let output = fitsrotate(&[arg(&input), "321"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+}
+
+#[test]
+fn files_truncated_anywhere_are_rejected_before_rotating() {
+    let dir = scratch_dir("truncated");
+    let input = dir.join("cube.fits");
+    let length = write_fixture(&input);
+    // One header block and four data blocks
+    assert_eq!(length, 5 * 2880);
+
+    let cases = [
+        (1000, "no END card"),
+        (2880, "bytes short"),
+        (2880 + 5000, "bytes short"),
+        (length - 1, "1 bytes short"),
+    ];
+    for (offset, message) in cases {
+        write_fixture(&input);
+        let file = std::fs::OpenOptions::new().write(true).open(&input).unwrap();
+        file.set_len(offset).unwrap();
+        drop(file);
+
+        let output = fitsrotate(&[arg(&input), "321"]);
+        let text = output_text(&output);
+        assert!(!output.status.success(), "truncated at {}: {}", offset, text);
+        assert!(text.contains(message), "truncated at {}: {}", offset, text);
+        assert!(!dir.join("cube.rot.fits").exists(), "truncated at {}", offset);
+    }
+}
@@ -0,0 +1,69 @@
+mod common;
+
+use common::{arg, fitsrotate, header_records, output_text, ramp, read_f64, scratch_dir, wcs_cards, write_cube};
+use fitsio::FitsFile;
+use std::ffi::CString;
+use std::path::Path;
+
+/// Write numeric cards to the primary header with 17 significant digits, which `write_key` rounds to 15
+fn write_exact_keys(path: &Path, cards: &[(&str, f64)]) {
+    let mut fits_file = FitsFile::edit(path).unwrap();
+    fits_file.primary_hdu().unwrap();
+    for (keyword, value) in cards {
+        let keyword = CString::new(*keyword).unwrap();
+        let mut status = 0;
+        unsafe {
+            fitsio::sys::ffukyd(
+                fits_file.as_raw(),
+                keyword.as_ptr(),
+                *value,
+                -17,
+                std::ptr::null(),
+                &mut status,
+            );
+        }
+        assert_eq!(status, 0);
+    }
+}
+
+/// The value field of a card of the primary header
+fn value_field(path: &Path, keyword: &str) -> String {
+    let record = header_records(path, 0)
+        .into_iter()
+        .find(|record| record[..8].trim_end() == keyword)
+        .unwrap_or_else(|| panic!("no {}", keyword));
+    record[10..].split('/').next().unwrap().trim().to_string()
+}
+
+#[test]
+fn high_precision_wcs_values_survive_a_rotation() {
+    let dir = scratch_dir("wcs_precision");
+    let input = dir.join("cube.fits");
+    write_cube(&input, &ramp(&[4, 3, 2]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+    // The rest frequency of HI, a sub-arcsecond pixel and a PC term, each needing 16 or 17 digits
+    let crval = 1_420_405_751.766_775_3;
+    let cdelt = -1.0 / 7200.0;
+    let pc = 1.0 / 3.0;
+    write_exact_keys(
+        &input,
+        &[("CRVAL3", crval), ("CDELT1", cdelt), ("PC1_2", pc), ("PC2_1", -pc)],
+    );
+    assert_eq!(read_f64(&input, "CRVAL3"), Some(crval));
+
+    let output = fitsrotate(&[arg(&input), "312"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let rotated = dir.join("cube.rot.fits");
+
+    // The values are numbers, not strings, and are read back exactly
+    assert_eq!(read_f64(&rotated, "CRVAL1"), Some(crval));
+    assert_eq!(read_f64(&rotated, "CDELT2"), Some(cdelt));
+    assert_eq!(read_f64(&rotated, "PC2_3"), Some(pc));
+    assert_eq!(read_f64(&rotated, "PC3_2"), Some(-pc));
+    for keyword in ["CRVAL1", "CDELT2", "PC2_3", "CRPIX1"] {
+        let field = value_field(&rotated, keyword);
+        assert!(field.parse::<f64>().is_ok(), "{} = {}", keyword, field);
+    }
+    // Values that need no more than 15 digits are written as before
+    assert_eq!(value_field(&rotated, "CRVAL2"), "10.");
+    assert_eq!(value_field(&rotated, "CDELT1"), "3.");
+}
@@ -0,0 +1,107 @@
+mod common;
+
+use common::json::{self, Json};
+use common::{arg, card, fitsrotate, output_text, ramp, read_f64, read_str, scratch_dir, wcs_cards, write_cube};
+
+#[test]
+fn the_sidecar_describes_the_output_header() {
+    let dir = scratch_dir("wcs_sidecar");
+    let input = dir.join("cube.fits");
+    let sidecar = dir.join("cube.rot.json");
+    let mut cards = wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]);
+    cards.push(card("PC1_2", 0.5));
+    cards.push(card("PC2_1", -0.25));
+    write_cube(&input, &ramp(&[2, 3, 4]), &cards);
+
+    let output = fitsrotate(&[arg(&input), "312", "--wcs-sidecar", arg(&sidecar)]);
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    let rotated = dir.join("cube.rot.fits");
+    let text = std::fs::read_to_string(&sidecar).unwrap();
+    let info = json::parse(&text).unwrap_or_else(|e| panic!("{}: {}", e, text));
+    assert_eq!(
+        info.get("schema").and_then(Json::as_str),
+        Some("fitsrotate_rs-wcs-sidecar/1")
+    );
+    assert_eq!(info.get("file").and_then(Json::as_str), rotated.to_str());
+    assert_eq!(info.get("naxis").and_then(Json::as_f64), Some(3.0));
+
+    let axes = match info.get("axes") {
+        Some(Json::Array(axes)) => axes,
+        other => panic!("axes is {:?}", other),
+    };
+    assert_eq!(axes.len(), 3);
+    for (idx, axis_info) in axes.iter().enumerate() {
+        let axis = idx + 1;
+        assert_eq!(axis_info.get("axis").and_then(Json::as_f64), Some(axis as f64));
+        for stub in ["naxis", "crval", "cdelt", "crpix"] {
+            let keyword = format!("{}{}", stub.to_uppercase(), axis);
+            assert_eq!(
+                axis_info.get(stub).and_then(Json::as_f64),
+                read_f64(&rotated, &keyword),
+                "{}",
+                keyword
+            );
+        }
+        for stub in ["ctype", "cunit"] {
+            let keyword = format!("{}{}", stub.to_uppercase(), axis);
+            let value = read_str(&rotated, &keyword).map(|value| value.trim().to_string());
+            assert_eq!(
+                axis_info.get(stub).and_then(Json::as_str),
+                value.as_deref(),
+                "{}",
+                keyword
+            );
+        }
+    }
+    // Output axis 1 is FREQ, the input's axis 3
+    assert_eq!(axes[0].get("ctype").and_then(Json::as_str), Some("FREQ"));
+    assert_eq!(axes[0].get("role").and_then(Json::as_str), Some("FREQ"));
+
+    // The PC matrix follows the axes, with the identity where there is no card
+    let pc: Vec<Vec<f64>> = match info.get("pc") {
+        Some(Json::Array(rows)) => rows
+            .iter()
+            .map(|row| match row {
+                Json::Array(values) => values.iter().map(|value| value.as_f64().unwrap()).collect(),
+                other => panic!("PC row is {:?}", other),
+            })
+            .collect(),
+        other => panic!("pc is {:?}", other),
+    };
+    assert_eq!(pc, [[1.0, 0.0, 0.0], [0.0, 1.0, 0.5], [0.0, -0.25, 1.0]]);
+    for i in 1..4 {
+        for j in 1..4 {
+            if let Some(value) = read_f64(&rotated, &format!("PC{}_{}", i, j)) {
+                assert_eq!(pc[i - 1][j - 1], value, "PC{}_{}", i, j);
+            }
+        }
+    }
+    assert_eq!(info.get("cd"), Some(&Json::Null));
+
+    // Nothing but the input, the output and the sidecar is left behind
+    assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 3);
+}
+
+#[test]
+fn yaml_sidecars_have_the_same_fields() {
+    let dir = scratch_dir("wcs_sidecar_yaml");
+    let input = dir.join("cube.fits");
+    let sidecar = dir.join("cube.rot.yaml");
+    write_cube(&input, &ramp(&[2, 3, 4]), &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]));
+
+    let output = fitsrotate(&[arg(&input), "312", "--wcs-sidecar", arg(&sidecar), "--yaml"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+
+    let text = std::fs::read_to_string(&sidecar).unwrap();
+    assert!(text.starts_with("schema: "), "{}", text);
+    for line in ["naxis: 3", "pc: null", "cd: null"] {
+        assert!(text.lines().any(|l| l.trim() == line), "no {:?} in {}", line, text);
+    }
+    let ctypes: Vec<&str> = text
+        .lines()
+        .filter_map(|line| line.trim().trim_start_matches("- ").strip_prefix("ctype: "))
+        .collect();
+    assert_eq!(ctypes.len(), 3, "{}", text);
+    assert!(ctypes[0].contains("FREQ") && ctypes[1].contains("RA---SIN") && ctypes[2].contains("DEC--SIN"));
+}
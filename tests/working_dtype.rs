@@ -0,0 +1,112 @@
+mod common;
+
+use common::{arg, fitsrotate, output_text, read_cube, read_f64, scratch_dir, wcs_cards, write_image};
+use fitsio::images::ImageType;
+use ndarray::{ArrayD, IxDyn};
+use std::path::Path;
+
+/// Write a double precision cube of the given (array order) shape, with pixel n holding `offset + n`
+fn write_double_cube(path: &Path, shape: &[usize], offset: f64) -> ArrayD<f64> {
+    let n: usize = shape.iter().product();
+    let data = ArrayD::from_shape_vec(IxDyn(shape), (0..n).map(|v| offset + v as f64).collect()).unwrap();
+    write_image(
+        path,
+        ImageType::Double,
+        &data,
+        &wcs_cards(&["RA---SIN", "DEC--SIN", "FREQ"]),
+    );
+    data
+}
+
+/// The peak memory allocated over a whole run, in bytes, from the report of `--mem-profile`
+fn peak_bytes(text: &str) -> f64 {
+    let line = text
+        .lines()
+        .find_map(|line| line.strip_prefix("Peak memory: "))
+        .unwrap_or_else(|| panic!("no peak in {}", text));
+    let mut words = line.split_whitespace();
+    let value: f64 = words.next().unwrap().parse().unwrap();
+    let scale = match words.next().unwrap() {
+        "B" => 1.0,
+        "KiB" => 1024.0,
+        "MiB" => 1024.0 * 1024.0,
+        "GiB" => 1024.0 * 1024.0 * 1024.0,
+        unit => panic!("unknown unit {}", unit),
+    };
+    value * scale
+}
+
+#[test]
+fn float32_halves_the_memory_of_a_double_precision_rotation() {
+    let dir = scratch_dir("working_dtype_memory");
+    let input = dir.join("cube.fits");
+    // Twice the chunk the data is narrowed in, so that the chunk does not dominate
+    write_double_cube(&input, &[128, 128, 128], 0.0);
+
+    let double = dir.join("double.fits");
+    let output = fitsrotate(&[arg(&input), "312", "--mem-profile", "--output", arg(&double)]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let double_peak = peak_bytes(&output_text(&output));
+    assert!(
+        !output_text(&output).contains("Precision loss"),
+        "{}",
+        output_text(&output)
+    );
+    assert_eq!(read_f64(&double, "BITPIX"), Some(-64.0));
+
+    let single = dir.join("single.fits");
+    let args = [
+        "312",
+        "--mem-profile",
+        "--working-dtype",
+        "float32",
+        "--output",
+        arg(&single),
+    ];
+    let output = fitsrotate(&[&[arg(&input)], &args[..]].concat());
+    assert!(output.status.success(), "{}", output_text(&output));
+    let single_peak = peak_bytes(&output_text(&output));
+    assert_eq!(read_f64(&single, "BITPIX"), Some(-32.0));
+
+    // The cube is 16 MiB in double precision
+    assert!(double_peak >= 16.0 * 1024.0 * 1024.0, "{}", double_peak);
+    assert!(single_peak <= 0.55 * double_peak, "{} vs {}", single_peak, double_peak);
+}
+
+#[test]
+fn integers_beyond_the_float32_range_are_reported() {
+    let dir = scratch_dir("working_dtype_warning");
+    let input = dir.join("cube.fits");
+    let data = write_double_cube(&input, &[4, 3, 2], 16_777_216.0);
+
+    let output = fitsrotate(&[arg(&input), "312", "--working-dtype", "float32"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let text = output_text(&output);
+    assert!(
+        text.contains("converted 24 double precision values to float32"),
+        "{}",
+        text
+    );
+    // 2^24 + 1, 2^24 + 3, ... are halfway between two float32 values
+    assert!(
+        text.contains("12 integer values beyond 16777216 (2^24) could not be held exactly and were rounded"),
+        "{}",
+        text
+    );
+    let rotated = read_cube::<f64>(&dir.join("cube.rot.fits"));
+    let expected = data.permuted_axes(IxDyn(&[1, 2, 0])).mapv(|value| value as f32 as f64);
+    assert_eq!(rotated.as_standard_layout(), expected.as_standard_layout());
+
+    // Small integers are held exactly, so only the (zero) rounding error is reported
+    let small = dir.join("small.fits");
+    write_double_cube(&small, &[4, 3, 2], 1.0);
+    let output = fitsrotate(&[arg(&small), "312", "--working-dtype", "float32"]);
+    assert!(output.status.success(), "{}", output_text(&output));
+    let text = output_text(&output);
+    assert!(
+        text.contains("the largest relative rounding error is 0.000e0"),
+        "{}",
+        text
+    );
+    assert!(!text.contains("could not be held exactly"), "{}", text);
+}